@@ -1,5 +1,64 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
 fn main() {
     println!("cargo:rustc-link-arg-bins=--nmagic");
     println!("cargo:rustc-link-arg-bins=-Tlink.x");
     println!("cargo:rustc-link-arg-bins=-Tdefmt.x");
+
+    // each binary gets its own memory.x, on the nucleo the bootloader keeps flash sector 0 and the application is
+    // linked after it into its slot, see src/slots.rs, the flight board's application has all of flash
+    let out = PathBuf::from(env::var("OUT_DIR").unwrap());
+    let nucleo = env::var_os("CARGO_FEATURE_NUCLEO_F767").is_some();
+    let ram = if nucleo { "512K" } else { "128K" };
+    let app = if nucleo { (0x0800_8000, "480K") } else { (0x0800_0000, "1024K") };
+    memory_x(&out.join("app"), app, ram);
+    println!("cargo:rustc-link-arg-bin=avionics-sw-hapsis=-L{}", out.join("app").display());
+    if nucleo {
+        memory_x(&out.join("bootloader"), (0x0800_0000, "32K"), ram);
+        println!("cargo:rustc-link-arg-bin=bootloader=-L{}", out.join("bootloader").display());
+    }
+
+    // firmware identity, so every log and downlink says exactly what code flew
+    let git_hash = git(&["rev-parse", "--short=8", "HEAD"]).unwrap_or_else(|| "unknown".into());
+    let dirty = git(&["status", "--porcelain", "--untracked-files=no"]).is_none_or(|status| !status.is_empty());
+    let mut features: Vec<String> = env::vars()
+        .filter_map(|(key, _)| key.strip_prefix("CARGO_FEATURE_").map(|f| f.to_lowercase().replace('_', "-")))
+        .collect();
+    features.sort();
+
+    println!("cargo:rustc-env=FIRMWARE_GIT_HASH={git_hash}");
+    println!("cargo:rustc-env=FIRMWARE_DIRTY={dirty}");
+    println!("cargo:rustc-env=FIRMWARE_PROFILE={}", env::var("PROFILE").unwrap_or_default());
+    println!("cargo:rustc-env=FIRMWARE_FEATURES={}", features.join(","));
+    // a commit on a branch moves the branch's ref, not HEAD, and gc packs loose refs into packed-refs, so those are
+    // watched too, in a worktree HEAD and the index are its own and the refs shared
+    // a missing path would rerun this on every build, so only the ones there are watched
+    let git_dir = git(&["rev-parse", "--git-dir"]).unwrap_or_else(|| ".git".into());
+    let common_dir = git(&["rev-parse", "--git-common-dir"]).unwrap_or_else(|| git_dir.clone());
+    let head_ref = git(&["symbolic-ref", "-q", "HEAD"]);
+    let watched = [Some(format!("{git_dir}/HEAD")), Some(format!("{git_dir}/index")),
+        head_ref.map(|head_ref| format!("{common_dir}/{head_ref}")), Some(format!("{common_dir}/packed-refs"))];
+    for path in watched.into_iter().flatten().filter(|path| Path::new(path).exists()) {
+        println!("cargo:rerun-if-changed={path}");
+    }
+    println!("cargo:rerun-if-changed=src");
+}
+
+// write a memory.x for cortex-m-rt's link.x into dir
+fn memory_x(dir: &Path, flash: (u32, &str), ram: &str) {
+    fs::create_dir_all(dir).unwrap();
+    let memory = format!("MEMORY\n{{\n    FLASH : ORIGIN = {:#010x}, LENGTH = {}\n    RAM   : ORIGIN = 0x20000000, LENGTH = {}\n}}\n", flash.0, flash.1, ram);
+    fs::write(dir.join("memory.x"), memory).unwrap();
+}
+
+// trimmed stdout of a git command, None if git isn't available or it fails
+fn git(args: &[&str]) -> Option<String> {
+    let output = Command::new("git").args(args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(String::from_utf8_lossy(&output.stdout).trim().to_string())
 }
@@ -0,0 +1,252 @@
+// pyro and cutdown actuation channels, the continuity through each channel's load and which channels are armed
+// a channel arms only with continuity unless the arm overrides the check, a channel that loses continuity while armed
+// stays armed, the load may have burned through on firing and the channel is still wanted
+// only an armed channel fires, the current through the load while it fires tells whether it actually did
+
+/// sense voltage at or below which a channel has continuity, V
+/// each sense line is pulled up to 3.3 V and pulled down through the load, so an e-match or cutdown wire holds it low
+pub const CONTINUITY_MAX_VOLTS: f32 = 1.0;
+
+/// firing current above which the load is taken to be conducting, A
+pub const FIRE_CURRENT_MIN_AMPS: f32 = 0.5;
+
+/// time the current has to stay above FIRE_CURRENT_MIN_AMPS for a firing to count, us
+/// an e-match bridge wire burns open in a ms or two, the cutdown nichrome conducts for seconds
+pub const FIRE_CONFIRM_US: u32 = 1_000;
+
+/// Actuation channel, the balloon cutdown and two pyro outputs
+#[derive(Copy, Clone, PartialEq, Eq, Debug, defmt::Format)]
+pub enum Channel {
+    Cutdown,
+    Pyro1,
+    Pyro2,
+}
+
+impl Channel {
+    pub const ALL: [Channel; 3] = [Channel::Cutdown, Channel::Pyro1, Channel::Pyro2];
+
+    pub fn from_id(id: u8) -> Option<Self> {
+        Self::ALL.get(id as usize).copied()
+    }
+
+    pub fn id(self) -> u8 {
+        self as u8
+    }
+
+    /// name used by the debug console
+    pub fn name(self) -> &'static str {
+        match self {
+            Channel::Cutdown => "cutdown",
+            Channel::Pyro1 => "pyro1",
+            Channel::Pyro2 => "pyro2",
+        }
+    }
+
+    /// channel from its console name
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|channel| channel.name() == name)
+    }
+}
+
+/// Set of channels, one bit per channel id
+#[derive(Copy, Clone, PartialEq, Eq, Default, Debug, defmt::Format)]
+pub struct ChannelFlags(pub u8);
+
+impl ChannelFlags {
+    pub const NONE: ChannelFlags = ChannelFlags(0);
+
+    pub fn contains(self, channel: Channel) -> bool {
+        self.0 & 1 << channel.id() != 0
+    }
+
+    pub fn set(&mut self, channel: Channel, on: bool) {
+        if on {
+            self.0 |= 1 << channel.id();
+        } else {
+            self.0 &= !(1 << channel.id());
+        }
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    /// the channels in the set, in id order
+    pub fn iter(self) -> impl Iterator<Item = Channel> {
+        Channel::ALL.into_iter().filter(move |&channel| self.contains(channel))
+    }
+}
+
+/// Why a channel didn't arm
+#[derive(Copy, Clone, PartialEq, Eq, Debug, defmt::Format)]
+pub enum ArmError {
+    /// the sense line says there is no load on the channel
+    NoContinuity,
+    /// continuity hasn't been read since boot
+    NotMeasured,
+}
+
+/// Continuity and arming of every channel
+#[derive(Copy, Clone, Default)]
+pub struct Actuation {
+    continuity: ChannelFlags,
+    measured: ChannelFlags,
+    armed: ChannelFlags,
+    /// armed without continuity on the operator's say so
+    overridden: ChannelFlags,
+    /// fired since boot, a load that burned through has no continuity left to lose
+    fired: ChannelFlags,
+}
+
+impl Actuation {
+    pub const fn new() -> Self {
+        Self {
+            continuity: ChannelFlags::NONE,
+            measured: ChannelFlags::NONE,
+            armed: ChannelFlags::NONE,
+            overridden: ChannelFlags::NONE,
+            fired: ChannelFlags::NONE,
+        }
+    }
+
+    /// record a sense voltage reading (V), returns true when the channel's continuity changed or was first read
+    /// a nan reading counts as no continuity
+    pub fn sense(&mut self, channel: Channel, volts: f32) -> bool {
+        let continuity = volts <= CONTINUITY_MAX_VOLTS;
+        let changed = !self.measured.contains(channel) || self.continuity.contains(channel) != continuity;
+        self.measured.set(channel, true);
+        self.continuity.set(channel, continuity);
+        changed
+    }
+
+    /// arm a channel, override arms it whatever its continuity
+    pub fn arm(&mut self, channel: Channel, override_continuity: bool) -> Result<(), ArmError> {
+        if !override_continuity {
+            if !self.measured.contains(channel) {
+                return Err(ArmError::NotMeasured);
+            }
+            if !self.continuity.contains(channel) {
+                return Err(ArmError::NoContinuity);
+            }
+        }
+        self.armed.set(channel, true);
+        self.overridden.set(channel, override_continuity && !self.continuity.contains(channel));
+        Ok(())
+    }
+
+    /// arm the channels that were armed before a reset in flight, continuity is checked as usual once it is read
+    /// the ones fired before it stay fired, their burned through loads aren't a lost continuity
+    pub fn resume(&mut self, armed: ChannelFlags, fired: ChannelFlags) {
+        self.armed = armed;
+        self.fired = fired;
+    }
+
+    pub fn disarm(&mut self, channel: Channel) {
+        self.armed.set(channel, false);
+        self.overridden.set(channel, false);
+    }
+
+    /// channels with continuity at the last reading
+    pub fn continuity(&self) -> ChannelFlags {
+        self.continuity
+    }
+
+    pub fn armed(&self) -> ChannelFlags {
+        self.armed
+    }
+
+    /// armed channels that have lost continuity since they were armed, overridden and fired channels aren't counted
+    pub fn lost(&self) -> ChannelFlags {
+        ChannelFlags(self.armed.0 & self.measured.0 & !self.continuity.0 & !self.overridden.0 & !self.fired.0)
+    }
+
+    /// record that a channel was fired, whether or not current was seen
+    pub fn set_fired(&mut self, channel: Channel) {
+        self.fired.set(channel, true);
+    }
+
+    pub fn fired(&self) -> ChannelFlags {
+        self.fired
+    }
+
+    /// armed channels that were armed without continuity
+    pub fn overridden(&self) -> ChannelFlags {
+        self.overridden
+    }
+}
+
+/// What the current through a channel's load did while it fired
+#[derive(Copy, Clone, PartialEq, Debug, defmt::Format)]
+pub struct FireReport {
+    pub channel: Channel,
+    /// current above FIRE_CURRENT_MIN_AMPS for at least FIRE_CONFIRM_US, the load conducted
+    pub confirmed: bool,
+    /// the current dropped back below FIRE_CURRENT_MIN_AMPS before the output switched off, the load burned open
+    pub opened: bool,
+    /// A
+    pub peak_current: f32,
+    /// time the current spent above FIRE_CURRENT_MIN_AMPS, us
+    pub conducted: u32,
+    /// time the output was on, us
+    pub dwell: u32,
+    /// uptime when the output switched on, us
+    pub time_stamp: u32,
+}
+
+/// Watches the current through a channel while it fires, fed the current samples in order
+pub struct FireMonitor {
+    channel: Channel,
+    start: u32,
+    last: u32,
+    conducting: bool,
+    peak_current: f32,
+    conducted: u32,
+    /// current seen and then gone again
+    opened: bool,
+}
+
+impl FireMonitor {
+    /// start watching when the output switches on at time_stamp (us)
+    pub fn new(channel: Channel, time_stamp: u32) -> Self {
+        Self {
+            channel,
+            start: time_stamp,
+            last: time_stamp,
+            conducting: false,
+            peak_current: 0.0,
+            conducted: 0,
+            opened: false,
+        }
+    }
+
+    /// add a current sample (A) taken at time_stamp (us), a nan sample counts as no current
+    /// the time since the last sample is counted as conducting if the load was conducting at either end of it
+    pub fn update(&mut self, amps: f32, time_stamp: u32) {
+        let conducting = amps >= FIRE_CURRENT_MIN_AMPS;
+        if conducting || self.conducting {
+            self.conducted = self.conducted.saturating_add(time_stamp.wrapping_sub(self.last));
+        }
+        if self.conducting && !conducting {
+            self.opened = true;
+        }
+        if conducting {
+            self.opened = false;
+        }
+        self.conducting = conducting;
+        self.peak_current = self.peak_current.max(amps);
+        self.last = time_stamp;
+    }
+
+    /// the firing as seen, the output switched off at time_stamp (us)
+    pub fn finish(&self, time_stamp: u32) -> FireReport {
+        FireReport {
+            channel: self.channel,
+            confirmed: self.conducted >= FIRE_CONFIRM_US,
+            opened: self.opened,
+            peak_current: self.peak_current,
+            conducted: self.conducted,
+            dwell: time_stamp.wrapping_sub(self.start),
+            time_stamp: self.start,
+        }
+    }
+}
@@ -0,0 +1,292 @@
+use libm::sqrtf;
+
+use crate::estimator::{AttitudeEstimator, seconds_since};
+use crate::{AttitudeData, ImuData};
+
+/// Madgwick gradient descent orientation filter
+/// fuses gyro, accelerometer, and (if present) magnetometer into an attitude quaternion
+/// gyro is expected in rad/s, accel and mag only need consistent units since they are normalized
+pub struct Madgwick {
+    /// filter gain, higher trusts accel/mag more and converges faster but is noisier
+    pub beta: f32,
+    q: [f32; 4],
+    convergence: Convergence,
+    last_time_stamp: Option<u32>,
+}
+
+/// gravity direction error below which a sample counts towards convergence
+const CONVERGENCE_THRESHOLD: f32 = 0.05;
+
+/// consecutive samples below the threshold needed before the filter is considered converged
+const CONVERGENCE_SAMPLES: u16 = 20;
+
+/// tracks the gravity direction error over consecutive samples
+struct Convergence {
+    error: f32,
+    count: u16,
+}
+
+impl Convergence {
+    const fn new() -> Self {
+        Self { error: 0.0, count: 0 }
+    }
+
+    fn record(&mut self, error: f32) {
+        self.error = error;
+        if error < CONVERGENCE_THRESHOLD {
+            self.count = self.count.saturating_add(1);
+        } else {
+            self.count = 0;
+        }
+    }
+
+    fn converged(&self) -> bool {
+        self.count >= CONVERGENCE_SAMPLES
+    }
+
+    fn attitude(&self, quaternion: [f32; 4], time_stamp: u32) -> AttitudeData {
+        AttitudeData {
+            quaternion,
+            converged: self.converged(),
+            time_stamp,
+        }
+    }
+}
+
+impl Madgwick {
+    pub const fn new(beta: f32) -> Self {
+        Self {
+            beta,
+            q: [1.0, 0.0, 0.0, 0.0],
+            convergence: Convergence::new(),
+            last_time_stamp: None,
+        }
+    }
+
+    /// run one filter step, dt is the time since the previous sample in seconds
+    pub fn step(&mut self, data: &ImuData, dt: f32) {
+        let [q0, q1, q2, q3] = self.q;
+        let [gx, gy, gz] = data.gyro;
+
+        // rate of change of quaternion from gyroscope
+        let mut q_dot = [
+            0.5 * (-q1 * gx - q2 * gy - q3 * gz),
+            0.5 * (q0 * gx + q2 * gz - q3 * gy),
+            0.5 * (q0 * gy - q1 * gz + q3 * gx),
+            0.5 * (q0 * gz + q1 * gy - q2 * gx),
+        ];
+
+        // only apply the corrective step with a valid accelerometer reading
+        if let Some([ax, ay, az]) = normalize3(data.acceleration) {
+            // objective function and jacobian for the gravity direction
+            let f_g = [
+                2.0 * (q1 * q3 - q0 * q2) - ax,
+                2.0 * (q0 * q1 + q2 * q3) - ay,
+                2.0 * (0.5 - q1 * q1 - q2 * q2) - az,
+            ];
+            let j_g = [
+                [-2.0 * q2, 2.0 * q3, -2.0 * q0, 2.0 * q1],
+                [2.0 * q1, 2.0 * q0, 2.0 * q3, 2.0 * q2],
+                [0.0, -4.0 * q1, -4.0 * q2, 0.0],
+            ];
+
+            let mut step = [0.0; 4];
+            accumulate_gradient(&mut step, &j_g, &f_g);
+
+            self.convergence.record(sqrtf(f_g[0] * f_g[0] + f_g[1] * f_g[1] + f_g[2] * f_g[2]));
+
+            // add the magnetic field direction if the magnetometer reading is usable
+            if let Some(m) = normalize3(data.mag) {
+                let [mx, my, mz] = m;
+                let [bx, bz] = earth_mag_reference(self.q, m);
+
+                let f_b = [
+                    2.0 * bx * (0.5 - q2 * q2 - q3 * q3) + 2.0 * bz * (q1 * q3 - q0 * q2) - mx,
+                    2.0 * bx * (q1 * q2 - q0 * q3) + 2.0 * bz * (q0 * q1 + q2 * q3) - my,
+                    2.0 * bx * (q0 * q2 + q1 * q3) + 2.0 * bz * (0.5 - q1 * q1 - q2 * q2) - mz,
+                ];
+                let j_b = [
+                    [-2.0 * bz * q2, 2.0 * bz * q3, -4.0 * bx * q2 - 2.0 * bz * q0, -4.0 * bx * q3 + 2.0 * bz * q1],
+                    [-2.0 * bx * q3 + 2.0 * bz * q1, 2.0 * bx * q2 + 2.0 * bz * q0, 2.0 * bx * q1 + 2.0 * bz * q3, -2.0 * bx * q0 + 2.0 * bz * q2],
+                    [2.0 * bx * q2, 2.0 * bx * q3 - 4.0 * bz * q1, 2.0 * bx * q0 - 4.0 * bz * q2, 2.0 * bx * q1],
+                ];
+                accumulate_gradient(&mut step, &j_b, &f_b);
+            }
+
+            // feed the normalized gradient step back against the gyro rate
+            if let Some(step) = normalize4(step) {
+                for i in 0..4 {
+                    q_dot[i] -= self.beta * step[i];
+                }
+            }
+        }
+
+        self.q = integrate(self.q, q_dot, dt);
+    }
+}
+
+impl AttitudeEstimator for Madgwick {
+    fn update(&mut self, data: &ImuData) -> AttitudeData {
+        // first sample only seeds the timestamp, there is no interval to integrate over yet
+        if let Some(dt) = seconds_since(&mut self.last_time_stamp, data.time_stamp) {
+            self.step(data, dt);
+        }
+        self.convergence.attitude(self.q, data.time_stamp)
+    }
+
+    fn error(&self) -> f32 {
+        self.convergence.error
+    }
+
+    fn converged(&self) -> bool {
+        self.convergence.converged()
+    }
+}
+
+/// Mahony nonlinear complementary filter
+/// PI feedback of the accel/mag direction error onto the gyro rate, the integral term tracks gyro bias
+pub struct Mahony {
+    /// proportional gain on the direction error
+    pub kp: f32,
+    /// integral gain on the direction error, zero disables bias tracking
+    pub ki: f32,
+    q: [f32; 4],
+    integral: [f32; 3],
+    convergence: Convergence,
+    last_time_stamp: Option<u32>,
+}
+
+impl Mahony {
+    pub const fn new(kp: f32, ki: f32) -> Self {
+        Self {
+            kp,
+            ki,
+            q: [1.0, 0.0, 0.0, 0.0],
+            integral: [0.0; 3],
+            convergence: Convergence::new(),
+            last_time_stamp: None,
+        }
+    }
+
+    /// run one filter step, dt is the time since the previous sample in seconds
+    pub fn step(&mut self, data: &ImuData, dt: f32) {
+        let [q0, q1, q2, q3] = self.q;
+        let mut g = data.gyro;
+
+        if let Some(a) = normalize3(data.acceleration) {
+            // estimated gravity direction in body frame
+            let v = [
+                2.0 * (q1 * q3 - q0 * q2),
+                2.0 * (q0 * q1 + q2 * q3),
+                q0 * q0 - q1 * q1 - q2 * q2 + q3 * q3,
+            ];
+            let mut e = cross(a, v);
+
+            let diff = [a[0] - v[0], a[1] - v[1], a[2] - v[2]];
+            self.convergence.record(sqrtf(diff[0] * diff[0] + diff[1] * diff[1] + diff[2] * diff[2]));
+
+            if let Some(m) = normalize3(data.mag) {
+                let [bx, bz] = earth_mag_reference(self.q, m);
+
+                // estimated magnetic field direction in body frame
+                let w = [
+                    2.0 * bx * (0.5 - q2 * q2 - q3 * q3) + 2.0 * bz * (q1 * q3 - q0 * q2),
+                    2.0 * bx * (q1 * q2 - q0 * q3) + 2.0 * bz * (q0 * q1 + q2 * q3),
+                    2.0 * bx * (q0 * q2 + q1 * q3) + 2.0 * bz * (0.5 - q1 * q1 - q2 * q2),
+                ];
+                let e_m = cross(m, w);
+                for i in 0..3 {
+                    e[i] += e_m[i];
+                }
+            }
+
+            for i in 0..3 {
+                if self.ki > 0.0 {
+                    self.integral[i] += self.ki * e[i] * dt;
+                }
+                g[i] += self.kp * e[i] + self.integral[i];
+            }
+        }
+
+        let [gx, gy, gz] = g;
+        let q_dot = [
+            0.5 * (-q1 * gx - q2 * gy - q3 * gz),
+            0.5 * (q0 * gx + q2 * gz - q3 * gy),
+            0.5 * (q0 * gy - q1 * gz + q3 * gx),
+            0.5 * (q0 * gz + q1 * gy - q2 * gx),
+        ];
+
+        self.q = integrate(self.q, q_dot, dt);
+    }
+}
+
+impl AttitudeEstimator for Mahony {
+    fn update(&mut self, data: &ImuData) -> AttitudeData {
+        if let Some(dt) = seconds_since(&mut self.last_time_stamp, data.time_stamp) {
+            self.step(data, dt);
+        }
+        self.convergence.attitude(self.q, data.time_stamp)
+    }
+
+    fn error(&self) -> f32 {
+        self.convergence.error
+    }
+
+    fn converged(&self) -> bool {
+        self.convergence.converged()
+    }
+}
+
+/// rotate a normalized mag reading into earth frame and flatten it onto the x-z plane
+/// returns (bx, bz), the reference field direction used by both filters
+fn earth_mag_reference(q: [f32; 4], m: [f32; 3]) -> [f32; 2] {
+    let [q0, q1, q2, q3] = q;
+    let [mx, my, mz] = m;
+    let hx = mx * (1.0 - 2.0 * (q2 * q2 + q3 * q3)) + my * 2.0 * (q1 * q2 - q0 * q3) + mz * 2.0 * (q1 * q3 + q0 * q2);
+    let hy = mx * 2.0 * (q1 * q2 + q0 * q3) + my * (1.0 - 2.0 * (q1 * q1 + q3 * q3)) + mz * 2.0 * (q2 * q3 - q0 * q1);
+    let hz = mx * 2.0 * (q1 * q3 - q0 * q2) + my * 2.0 * (q2 * q3 + q0 * q1) + mz * (1.0 - 2.0 * (q1 * q1 + q2 * q2));
+    [sqrtf(hx * hx + hy * hy), hz]
+}
+
+/// integrate a quaternion rate over dt and renormalize
+fn integrate(mut q: [f32; 4], q_dot: [f32; 4], dt: f32) -> [f32; 4] {
+    for i in 0..4 {
+        q[i] += q_dot[i] * dt;
+    }
+    normalize4(q).unwrap_or([1.0, 0.0, 0.0, 0.0])
+}
+
+fn cross(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+/// step += J^T * f
+fn accumulate_gradient(step: &mut [f32; 4], j: &[[f32; 4]; 3], f: &[f32; 3]) {
+    for (row, f) in j.iter().zip(f) {
+        for i in 0..4 {
+            step[i] += row[i] * f;
+        }
+    }
+}
+
+fn normalize3(v: [f32; 3]) -> Option<[f32; 3]> {
+    let norm = sqrtf(v[0] * v[0] + v[1] * v[1] + v[2] * v[2]);
+    if norm > 0.0 && norm.is_finite() {
+        Some([v[0] / norm, v[1] / norm, v[2] / norm])
+    } else {
+        None
+    }
+}
+
+fn normalize4(v: [f32; 4]) -> Option<[f32; 4]> {
+    let norm = sqrtf(v[0] * v[0] + v[1] * v[1] + v[2] * v[2] + v[3] * v[3]);
+    if norm > 0.0 && norm.is_finite() {
+        Some([v[0] / norm, v[1] / norm, v[2] / norm, v[3] / norm])
+    } else {
+        None
+    }
+}
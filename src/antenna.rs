@@ -0,0 +1,66 @@
+// rf path switch between the payload's two antennas, the lower one hangs below the box and looks down at the ground
+// through the ascent, float, and descent, the upper one on the lid is the one left clear once the box is lying on the
+// ground after landing
+// the switch follows the flight state unless the config pins one antenna, the ground sees which one every frame
+// came through in its link statistics and pins the other over the uplink when it hears more through it
+
+use crate::mission::FlightState;
+
+/// Antenna behind the rf switch
+#[derive(Copy, Clone, PartialEq, Eq, Debug, defmt::Format)]
+pub enum Antenna {
+    /// below the box, the switch's default position
+    Lower,
+    /// on the lid
+    Upper,
+}
+
+impl Antenna {
+    pub const ALL: [Antenna; 2] = [Antenna::Lower, Antenna::Upper];
+
+    pub fn from_id(id: u8) -> Option<Self> {
+        Self::ALL.get(id as usize).copied()
+    }
+
+    pub fn id(self) -> u8 {
+        self as u8
+    }
+
+    /// name used by the debug console
+    pub fn name(self) -> &'static str {
+        match self {
+            Antenna::Lower => "lower",
+            Antenna::Upper => "upper",
+        }
+    }
+}
+
+/// How the antenna is picked, from config
+#[derive(Copy, Clone, PartialEq, Eq, Debug, defmt::Format)]
+pub enum AntennaMode {
+    /// follow the flight state
+    Auto,
+    /// always this antenna
+    Fixed(Antenna),
+}
+
+impl AntennaMode {
+    /// mode from its config value, 0 follows the flight state, 1 pins the lower and 2 the upper antenna, unknown values
+    /// follow the flight state
+    pub fn from_u32(value: u32) -> Self {
+        match value {
+            1 => AntennaMode::Fixed(Antenna::Lower),
+            2 => AntennaMode::Fixed(Antenna::Upper),
+            _ => AntennaMode::Auto,
+        }
+    }
+
+    /// antenna to transmit through in the flight state
+    pub fn select(self, state: FlightState) -> Antenna {
+        match (self, state) {
+            (AntennaMode::Fixed(antenna), _) => antenna,
+            (AntennaMode::Auto, FlightState::Landed) => Antenna::Upper,
+            (AntennaMode::Auto, _) => Antenna::Lower,
+        }
+    }
+}
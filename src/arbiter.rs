@@ -0,0 +1,92 @@
+// priority on a shared spi bus, short control critical transactions (radio acks, cutdown confirmation) go ahead of
+// bulk traffic (sd card log blocks, external flash dumps) instead of queueing behind it
+// a transaction in progress is never interrupted, chip select has to stay low through it, so bulk devices keep their
+// transactions short and release the bus between them, at every release a waiting high priority device goes first
+
+use core::cell::Cell;
+
+use embassy_futures::yield_now;
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_sync::blocking_mutex::raw::ThreadModeRawMutex;
+use embedded_hal_async::spi::{ErrorType, Operation, SpiDevice};
+
+#[derive(Copy, Clone, PartialEq, Eq, defmt::Format)]
+pub enum BusPriority {
+    // bulk transfers, wait while any high priority transaction is waiting or running
+    Low,
+    // short transactions that must not sit behind bulk transfers
+    High,
+}
+
+// high priority transactions waiting for or holding one bus
+pub struct Arbiter {
+    high: Mutex<ThreadModeRawMutex, Cell<u8>>,
+}
+
+impl Arbiter {
+    pub const fn new() -> Self {
+        Self { high: Mutex::new(Cell::new(0)) }
+    }
+
+    fn high_pending(&self) -> bool {
+        self.high.lock(|h| h.get()) > 0
+    }
+}
+
+// counts a high priority transaction from the moment it asks for the bus until it finishes or is cancelled
+struct HighGuard<'a>(&'a Arbiter);
+
+impl<'a> HighGuard<'a> {
+    fn new(arbiter: &'a Arbiter) -> Self {
+        arbiter.high.lock(|h| h.set(h.get() + 1));
+        Self(arbiter)
+    }
+}
+
+impl Drop for HighGuard<'_> {
+    fn drop(&mut self) {
+        self.0.high.lock(|h| h.set(h.get() - 1));
+    }
+}
+
+// a device on an arbitrated bus
+pub struct Arbitrated<D> {
+    device: D,
+    priority: BusPriority,
+    arbiter: &'static Arbiter,
+}
+
+impl<D> Arbitrated<D> {
+    pub fn new(device: D, priority: BusPriority, arbiter: &'static Arbiter) -> Self {
+        Self { device, priority, arbiter }
+    }
+
+    // the nucleo's card is on sdmmc, not the arbitrated spi bus
+    #[cfg_attr(feature = "nucleo-f767", allow(dead_code))]
+    pub fn device_mut(&mut self) -> &mut D {
+        &mut self.device
+    }
+}
+
+impl<D: ErrorType> ErrorType for Arbitrated<D> {
+    type Error = D::Error;
+}
+
+impl<D: SpiDevice> SpiDevice for Arbitrated<D> {
+    async fn transaction(&mut self, operations: &mut [Operation<'_, u8>]) -> Result<(), Self::Error> {
+        match self.priority {
+            BusPriority::High => {
+                let _guard = HighGuard::new(self.arbiter);
+                self.device.transaction(operations).await
+            }
+            BusPriority::Low => {
+                // high priority transactions are a few bytes, spinning the executor until they are done is cheaper
+                // than a wakeup registration
+                while self.arbiter.high_pending() {
+                    yield_now().await;
+                }
+                self.device.transaction(operations).await
+            }
+        }
+    }
+}
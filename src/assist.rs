@@ -0,0 +1,221 @@
+use crate::crc::crc32;
+
+/// assist bytes carried by one uplink or console chunk
+pub const CHUNK_LEN: usize = 64;
+
+/// longest ubx message relayed, the mga ephemeris, almanac, and AssistNow Offline messages are all well under it
+pub const MAX_MESSAGE_LEN: usize = 128;
+
+const SYNC: [u8; 2] = [0xB5, 0x62];
+// sync, class, id, and length in front of the payload, and the two checksum bytes after it
+const HEADER_LEN: usize = 6;
+const CHECKSUM_LEN: usize = 2;
+/// ubx class of the multiple gnss assistance messages, nothing else from an upload reaches the receiver
+const CLASS_MGA: u8 = 0x13;
+
+/// GNSS assistance upload steps from the uplink or console
+/// the data is a file of ubx mga messages as served by AssistNow Online or Offline, relayed to the receiver
+/// message by message so it starts with ephemeris, almanac, and time instead of decoding them from the sky
+#[derive(Copy, Clone, PartialEq, defmt::Format)]
+pub enum AssistCommand {
+    /// start an upload of size bytes, dropping any upload in progress
+    Begin { size: u32 },
+    /// assist bytes at offset, crc is the crc32 of data[..len], every chunk but the last must be full
+    Chunk {
+        offset: u32,
+        len: u8,
+        data: [u8; CHUNK_LEN],
+        crc: u32,
+    },
+    /// end the upload
+    Finish,
+}
+
+/// Why an upload step was rejected
+#[derive(Copy, Clone, PartialEq, Eq, Debug, defmt::Format)]
+pub enum AssistError {
+    /// no upload in progress
+    NotStarted,
+    /// empty upload
+    BadSize,
+    /// chunk data doesn't match its crc, resend it
+    ChunkCrc,
+    /// chunk is short without ending the upload, or runs past the end
+    BadChunk,
+    /// chunk past the next expected offset, resend from next_offset
+    OutOfOrder,
+    /// finish before every byte arrived
+    Incomplete,
+}
+
+/// Upload progress, sent back after every step so the ground knows where to resume
+#[derive(Copy, Clone, PartialEq, Eq, defmt::Format)]
+pub struct AssistStatus {
+    pub size: u32,
+    pub next_offset: u32,
+    /// messages handed to the receiver
+    pub messages: u16,
+    /// messages dropped for a bad checksum, a class other than mga, a length over MAX_MESSAGE_LEN, or a send failure
+    pub rejected: u16,
+    pub result: Result<(), AssistError>,
+    pub time_stamp: u32,
+}
+
+#[derive(Copy, Clone)]
+struct Upload {
+    size: u32,
+    next_offset: u32,
+}
+
+/// Tracks an assist upload and splits its bytes back into ubx messages
+/// chunks are taken in order, a repeated chunk is ignored, and a message may span chunks
+pub struct AssistReceiver {
+    upload: Option<Upload>,
+    message: [u8; MAX_MESSAGE_LEN],
+    len: usize,
+    messages: u16,
+    rejected: u16,
+}
+
+impl Default for AssistReceiver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AssistReceiver {
+    pub const fn new() -> Self {
+        Self {
+            upload: None,
+            message: [0; MAX_MESSAGE_LEN],
+            len: 0,
+            messages: 0,
+            rejected: 0,
+        }
+    }
+
+    /// upload size, next expected offset, and message counts, size and offset are zeros when idle
+    pub fn status(&self, result: Result<(), AssistError>, time_stamp: u32) -> AssistStatus {
+        let (size, next_offset) = self.upload.map_or((0, 0), |u| (u.size, u.next_offset));
+        AssistStatus {
+            size,
+            next_offset,
+            messages: self.messages,
+            rejected: self.rejected,
+            result,
+            time_stamp,
+        }
+    }
+
+    /// start a new upload, messages the receiver already has are simply sent again
+    pub fn begin(&mut self, size: u32) -> Result<(), AssistError> {
+        if size == 0 {
+            return Err(AssistError::BadSize);
+        }
+        *self = Self::new();
+        self.upload = Some(Upload { size, next_offset: 0 });
+        Ok(())
+    }
+
+    /// check a chunk, returns true if it is the next one and its bytes should be pushed
+    /// false for a repeat of a chunk already pushed, the ack was lost and the ground sent it again
+    pub fn accept(&self, offset: u32, data: &[u8], crc: u32) -> Result<bool, AssistError> {
+        let upload = self.upload.ok_or(AssistError::NotStarted)?;
+        if crc32(data) != crc {
+            return Err(AssistError::ChunkCrc);
+        }
+
+        let end = offset.checked_add(data.len() as u32).ok_or(AssistError::BadChunk)?;
+        if data.is_empty() || end > upload.size || (data.len() < CHUNK_LEN && end != upload.size) {
+            return Err(AssistError::BadChunk);
+        }
+
+        if offset < upload.next_offset {
+            return Ok(false);
+        }
+        if offset > upload.next_offset {
+            return Err(AssistError::OutOfOrder);
+        }
+        Ok(true)
+    }
+
+    /// add one byte of an accepted chunk, returns a whole mga message once its checksum checks out, to be counted
+    /// once the receiver has it, bytes outside a message are skipped until the next sync pattern
+    pub fn push(&mut self, byte: u8) -> Option<&[u8]> {
+        if self.len < SYNC.len() && byte != SYNC[self.len] {
+            // a stray sync byte may start the next message
+            self.len = 0;
+            if byte != SYNC[0] {
+                return None;
+            }
+        }
+        self.message[self.len] = byte;
+        self.len += 1;
+        if self.len < HEADER_LEN {
+            return None;
+        }
+
+        let total = HEADER_LEN + u16::from_le_bytes([self.message[4], self.message[5]]) as usize + CHECKSUM_LEN;
+        if total > MAX_MESSAGE_LEN {
+            self.discard();
+            return None;
+        }
+        if self.len < total {
+            return None;
+        }
+
+        self.len = 0;
+        let body = &self.message[SYNC.len()..total - CHECKSUM_LEN];
+        if checksum(body) != [self.message[total - 2], self.message[total - 1]] || body[0] != CLASS_MGA {
+            self.discard();
+            return None;
+        }
+        Some(&self.message[..total])
+    }
+
+    /// count a message from push as sent, or as rejected if the receiver didn't take it
+    pub fn count(&mut self, sent: bool) {
+        if sent {
+            self.messages = self.messages.saturating_add(1);
+        } else {
+            self.rejected = self.rejected.saturating_add(1);
+        }
+    }
+
+    // drop the message being collected
+    fn discard(&mut self) {
+        self.len = 0;
+        self.count(false);
+    }
+
+    /// count an accepted chunk once its bytes are pushed
+    pub fn advance(&mut self, len: usize) {
+        if let Some(upload) = self.upload.as_mut() {
+            upload.next_offset += len as u32;
+        }
+    }
+
+    /// end the upload, returns the messages sent and rejected, a message cut off at the end counts as rejected
+    pub fn finish(&mut self) -> Result<(u16, u16), AssistError> {
+        let upload = self.upload.ok_or(AssistError::NotStarted)?;
+        if upload.next_offset != upload.size {
+            return Err(AssistError::Incomplete);
+        }
+
+        if self.len > 0 {
+            self.discard();
+        }
+        self.upload = None;
+        Ok((self.messages, self.rejected))
+    }
+}
+
+// 8 bit fletcher checksum over class, id, length, and payload
+fn checksum(body: &[u8]) -> [u8; 2] {
+    let (mut a, mut b) = (0u8, 0u8);
+    for &byte in body {
+        a = a.wrapping_add(byte);
+        b = b.wrapping_add(a);
+    }
+    [a, b]
+}
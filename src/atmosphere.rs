@@ -0,0 +1,222 @@
+use libm::{expf, logf, powf};
+
+/// standard gravity, m/s^2
+const G0: f32 = 9.80665;
+
+/// specific gas constant of dry air, J/(kg K)
+const R_AIR: f32 = 287.053;
+
+/// earth radius used for the geopotential altitude conversion, m
+const EARTH_RADIUS: f32 = 6_356_766.0;
+
+/// ISA sea level pressure, hPa
+pub const SEA_LEVEL_PRESSURE: f32 = 1013.25;
+
+/// One layer of the standard atmosphere, the temperature changes linearly with geopotential altitude inside it
+struct Layer {
+    /// base altitude, m
+    altitude: f32,
+    /// base temperature, K
+    temperature: f32,
+    /// base pressure, hPa
+    pressure: f32,
+    /// temperature lapse rate, K/m (positive when warming with altitude)
+    lapse: f32,
+}
+
+/// ISA layers up to the mesosphere, base pressures from the 1976 standard
+const LAYERS: [Layer; 7] = [
+    Layer { altitude: 0.0, temperature: 288.15, pressure: 1013.25, lapse: -0.0065 },
+    Layer { altitude: 11_000.0, temperature: 216.65, pressure: 226.3206, lapse: 0.0 },
+    Layer { altitude: 20_000.0, temperature: 216.65, pressure: 54.74889, lapse: 0.001 },
+    Layer { altitude: 32_000.0, temperature: 228.65, pressure: 8.680187, lapse: 0.0028 },
+    Layer { altitude: 47_000.0, temperature: 270.65, pressure: 1.109063, lapse: 0.0 },
+    Layer { altitude: 51_000.0, temperature: 270.65, pressure: 0.6693887, lapse: -0.0028 },
+    Layer { altitude: 71_000.0, temperature: 214.65, pressure: 0.0395642, lapse: -0.002 },
+];
+
+/// ISA altitude (m above mean sea level) for a static pressure (hPa)
+/// the returned altitude is geometric so it compares directly with gps, at 30 km it is about 140 m above geopotential
+/// pressures above sea level pressure extrapolate the lowest layer downwards
+pub fn pressure_to_altitude(pressure: f32) -> f32 {
+    geometric(geopotential_altitude(pressure))
+}
+
+/// ISA static pressure (hPa) at a geometric altitude (m above mean sea level), the inverse of pressure_to_altitude
+pub fn altitude_to_pressure(altitude: f32) -> f32 {
+    geopotential_pressure(geopotential(altitude))
+}
+
+fn geopotential_altitude(pressure: f32) -> f32 {
+    // highest layer whose base pressure is at or above the reading
+    let layer = LAYERS.iter().rev().find(|l| pressure <= l.pressure).unwrap_or(&LAYERS[0]);
+    let ratio = pressure / layer.pressure;
+
+    if layer.lapse == 0.0 {
+        layer.altitude - R_AIR * layer.temperature / G0 * logf(ratio)
+    } else {
+        layer.altitude + layer.temperature / layer.lapse * (powf(ratio, -R_AIR * layer.lapse / G0) - 1.0)
+    }
+}
+
+fn geopotential_pressure(altitude: f32) -> f32 {
+    let layer = LAYERS.iter().rev().find(|l| altitude >= l.altitude).unwrap_or(&LAYERS[0]);
+    let height = altitude - layer.altitude;
+
+    if layer.lapse == 0.0 {
+        layer.pressure * expf(-G0 * height / (R_AIR * layer.temperature))
+    } else {
+        layer.pressure * powf(1.0 + layer.lapse * height / layer.temperature, -G0 / (R_AIR * layer.lapse))
+    }
+}
+
+fn geopotential(geometric: f32) -> f32 {
+    EARTH_RADIUS * geometric / (EARTH_RADIUS + geometric)
+}
+
+fn geometric(geopotential: f32) -> f32 {
+    EARTH_RADIUS * geopotential / (EARTH_RADIUS - geopotential)
+}
+
+/// measured temperatures outside this range (C) are treated as sensor faults and the ISA temperature is used
+const TEMPERATURE_RANGE: (f32, f32) = (-90.0, 60.0);
+
+/// offset from celsius to kelvin
+const CELSIUS_TO_KELVIN: f32 = 273.15;
+
+/// Altitude integrated from the hypsometric equation with the measured air temperature
+/// each pressure step adds R T / g * ln(p0 / p1), so real temperature profiles like inversions are followed
+/// instead of assuming the ISA lapse rate, it starts from the ISA altitude of the first sample
+pub struct HypsometricAltitude {
+    /// previous (pressure hPa, temperature K, geopotential altitude m)
+    previous: Option<(f32, f32, f32)>,
+}
+
+impl Default for HypsometricAltitude {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HypsometricAltitude {
+    pub const fn new() -> Self {
+        Self { previous: None }
+    }
+
+    /// add a pressure (hPa) and air temperature (C) sample and return the geometric altitude in m, a pressure that
+    /// isn't finite and positive is skipped, giving the last altitude, or NaN before the first good sample
+    pub fn update(&mut self, pressure: f32, temperature: f32) -> f32 {
+        // checked before the first sample too, a bad one would be the start every later altitude is integrated from
+        if !(pressure > 0.0 && pressure.is_finite()) {
+            return self.previous.map_or(f32::NAN, |(_, _, altitude)| geometric(altitude));
+        }
+
+        let Some((prev_pressure, prev_temperature, prev_altitude)) = self.previous else {
+            let altitude = geopotential_altitude(pressure);
+            self.previous = Some((pressure, air_temperature(temperature, altitude), altitude));
+            return geometric(altitude);
+        };
+
+        // the layer temperature is the mean of both ends, the guess for the new end uses the old altitude
+        // the equation assumes constant gravity so it integrates geopotential altitude
+        let temperature = air_temperature(temperature, prev_altitude);
+        let mean = 0.5 * (prev_temperature + temperature);
+        let altitude = prev_altitude + R_AIR * mean / G0 * logf(prev_pressure / pressure);
+
+        self.previous = Some((pressure, temperature, altitude));
+        geometric(altitude)
+    }
+}
+
+/// measured temperature in K, or the ISA temperature at the geopotential altitude if the measurement is implausible
+fn air_temperature(temperature: f32, altitude: f32) -> f32 {
+    if (TEMPERATURE_RANGE.0..=TEMPERATURE_RANGE.1).contains(&temperature) {
+        temperature + CELSIUS_TO_KELVIN
+    } else {
+        isa_temperature(geometric(altitude))
+    }
+}
+
+/// ISA temperature (K) at a geometric altitude (m)
+pub fn isa_temperature(altitude: f32) -> f32 {
+    let altitude = geopotential(altitude);
+    let layer = LAYERS.iter().rev().find(|l| altitude >= l.altitude).unwrap_or(&LAYERS[0]);
+    layer.temperature + layer.lapse * (altitude - layer.altitude)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // geometric altitude (m), pressure (hPa), and temperature (K) from the 1976 us standard atmosphere tables
+    const REFERENCE: [(f32, f32, f32); 7] = [
+        (0.0, 1013.25, 288.15),
+        (5_000.0, 540.48, 255.68),
+        (11_000.0, 226.99, 216.77),
+        (20_000.0, 55.293, 216.65),
+        (25_000.0, 25.492, 221.55),
+        (30_000.0, 11.970, 226.51),
+        (40_000.0, 2.8714, 250.35),
+    ];
+
+    #[test]
+    fn matches_the_standard_tables() {
+        for (altitude, pressure, temperature) in REFERENCE {
+            let computed = pressure_to_altitude(pressure);
+            assert!((computed - altitude).abs() < 5.0, "{pressure} hPa gave {computed} m, expected {altitude}");
+            let computed = altitude_to_pressure(altitude);
+            assert!((computed / pressure - 1.0).abs() < 1e-3, "{altitude} m gave {computed} hPa, expected {pressure}");
+            let computed = isa_temperature(altitude);
+            assert!((computed - temperature).abs() < 0.05, "{altitude} m gave {computed} K, expected {temperature}");
+        }
+    }
+
+    #[test]
+    fn round_trips_across_the_layers() {
+        for altitude in (-500..=45_000).step_by(250).map(|altitude| altitude as f32) {
+            let back = pressure_to_altitude(altitude_to_pressure(altitude));
+            assert!((back - altitude).abs() < 1.0 + altitude.abs() * 1e-4, "{altitude} m came back as {back}");
+        }
+        // above sea level pressure extrapolates below sea level
+        assert!(pressure_to_altitude(1030.0) < 0.0);
+    }
+
+    #[test]
+    fn hypsometric_follows_isa_in_a_standard_atmosphere() {
+        let mut hypsometric = HypsometricAltitude::new();
+        for altitude in (0..=30_000).step_by(100).map(|altitude| altitude as f32) {
+            let temperature = isa_temperature(altitude) - CELSIUS_TO_KELVIN;
+            let computed = hypsometric.update(altitude_to_pressure(altitude), temperature);
+            assert!((computed - altitude).abs() < 10.0, "{altitude} m came out as {computed}");
+        }
+    }
+
+    #[test]
+    fn hypsometric_follows_a_warm_layer() {
+        // 10 K warmer than isa, the same pressure drop covers more height
+        let mut hypsometric = HypsometricAltitude::new();
+        let start = hypsometric.update(altitude_to_pressure(1000.0), isa_temperature(1000.0) - CELSIUS_TO_KELVIN + 10.0);
+        let end = hypsometric.update(altitude_to_pressure(2000.0), isa_temperature(2000.0) - CELSIUS_TO_KELVIN + 10.0);
+        assert!(end - start > 1030.0, "{}", end - start);
+        // an implausible temperature falls back to isa
+        let mut hypsometric = HypsometricAltitude::new();
+        hypsometric.update(altitude_to_pressure(1000.0), -200.0);
+        for altitude in (1100..=2000).step_by(100).map(|altitude| altitude as f32) {
+            let computed = hypsometric.update(altitude_to_pressure(altitude), f32::NAN);
+            assert!((computed - altitude).abs() < 5.0, "{altitude} m came out as {computed}");
+        }
+    }
+
+    #[test]
+    fn hypsometric_skips_bad_first_samples() {
+        let mut hypsometric = HypsometricAltitude::new();
+        assert!(hypsometric.update(f32::NAN, 15.0).is_nan());
+        assert!(hypsometric.update(0.0, 15.0).is_nan());
+        assert!(hypsometric.update(-3.0, 15.0).is_nan());
+        let start = hypsometric.update(1013.25, 15.0);
+        assert!(start.abs() < 1.0, "{start}");
+        assert_eq!(hypsometric.update(f32::INFINITY, 15.0), start);
+        let next = hypsometric.update(altitude_to_pressure(100.0), isa_temperature(100.0) - CELSIUS_TO_KELVIN);
+        assert!((next - 100.0).abs() < 1.0, "{next}");
+    }
+}
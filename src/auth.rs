@@ -0,0 +1,303 @@
+// commands that take the payload out of the flight code carry a tag instead of a fixed key, an hmac-sha256 under a
+// key provisioned at build time over a nonce the board hands out, so a tag is good for one command and a recorded or
+// stray one is worthless
+// the board issues a challenge nonce, the ground tools work out the tag with the same key, and the board checks it
+// against the nonce it issued, which is used up by the check whether the tag was right or not
+
+/// bytes of the hmac a command carries, the leading half of it
+pub const TAG_LEN: usize = 16;
+
+/// key length, the sha-256 output size
+pub const KEY_LEN: usize = 32;
+
+/// what a tag authenticates, the purpose and the nonce
+const DOMAIN: &[u8] = b"hapsis bootloader";
+
+const BLOCK: usize = 64;
+
+const K: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+const H0: [u32; 8] = [0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19];
+
+/// Incremental sha-256
+pub struct Sha256 {
+    state: [u32; 8],
+    block: [u8; BLOCK],
+    /// bytes in block
+    filled: usize,
+    /// bytes hashed so far
+    len: u64,
+}
+
+impl Default for Sha256 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Sha256 {
+    pub const fn new() -> Self {
+        Self { state: H0, block: [0; BLOCK], filled: 0, len: 0 }
+    }
+
+    pub fn update(&mut self, mut data: &[u8]) {
+        self.len += data.len() as u64;
+        while !data.is_empty() {
+            let n = (BLOCK - self.filled).min(data.len());
+            self.block[self.filled..self.filled + n].copy_from_slice(&data[..n]);
+            self.filled += n;
+            data = &data[n..];
+            if self.filled == BLOCK {
+                compress(&mut self.state, &self.block);
+                self.filled = 0;
+            }
+        }
+    }
+
+    pub fn finish(mut self) -> [u8; 32] {
+        let bits = self.len * 8;
+        self.update(&[0x80]);
+        while self.filled != BLOCK - 8 {
+            self.update(&[0]);
+        }
+        self.update(&bits.to_be_bytes());
+
+        let mut digest = [0; 32];
+        for (bytes, word) in digest.chunks_exact_mut(4).zip(self.state) {
+            bytes.copy_from_slice(&word.to_be_bytes());
+        }
+        digest
+    }
+}
+
+fn compress(state: &mut [u32; 8], block: &[u8; BLOCK]) {
+    let mut w = [0u32; 64];
+    for (word, bytes) in w.iter_mut().zip(block.chunks_exact(4)) {
+        *word = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    }
+    for i in 16..64 {
+        let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+        let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+        w[i] = w[i - 16].wrapping_add(s0).wrapping_add(w[i - 7]).wrapping_add(s1);
+    }
+
+    let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = *state;
+    for (k, w) in K.iter().zip(w) {
+        let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+        let ch = (e & f) ^ (!e & g);
+        let t1 = h.wrapping_add(s1).wrapping_add(ch).wrapping_add(*k).wrapping_add(w);
+        let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+        let maj = (a & b) ^ (a & c) ^ (b & c);
+        let t2 = s0.wrapping_add(maj);
+        (h, g, f, e, d, c, b, a) = (g, f, e, d.wrapping_add(t1), c, b, a, t1.wrapping_add(t2));
+    }
+    for (word, add) in state.iter_mut().zip([a, b, c, d, e, f, g, h]) {
+        *word = word.wrapping_add(add);
+    }
+}
+
+/// hmac-sha256 of message under key, parts are hashed one after the other as if they were one message
+pub fn hmac_sha256(key: &[u8], parts: &[&[u8]]) -> [u8; 32] {
+    // a key longer than a block is hashed down first
+    let mut padded = [0u8; BLOCK];
+    if key.len() > BLOCK {
+        let mut hash = Sha256::new();
+        hash.update(key);
+        padded[..32].copy_from_slice(&hash.finish());
+    } else {
+        padded[..key.len()].copy_from_slice(key);
+    }
+
+    let mut inner = Sha256::new();
+    inner.update(&padded.map(|byte| byte ^ 0x36));
+    for part in parts {
+        inner.update(part);
+    }
+    let mut outer = Sha256::new();
+    outer.update(&padded.map(|byte| byte ^ 0x5c));
+    outer.update(&inner.finish());
+    outer.finish()
+}
+
+/// tag for the nonce the board issued
+pub fn tag(key: &[u8; KEY_LEN], nonce: u64) -> [u8; TAG_LEN] {
+    let mac = hmac_sha256(key, &[DOMAIN, &nonce.to_le_bytes()]);
+    let mut tag = [0; TAG_LEN];
+    tag.copy_from_slice(&mac[..TAG_LEN]);
+    tag
+}
+
+/// key from its 64 hex digits, None for anything else
+pub const fn parse_key(hex: &str) -> Option<[u8; KEY_LEN]> {
+    parse_hex(hex)
+}
+
+/// tag from its 32 hex digits, None for anything else
+pub const fn parse_tag(hex: &str) -> Option<[u8; TAG_LEN]> {
+    parse_hex(hex)
+}
+
+const fn parse_hex<const N: usize>(hex: &str) -> Option<[u8; N]> {
+    let digits = hex.as_bytes();
+    if digits.len() != 2 * N {
+        return None;
+    }
+    let mut bytes = [0; N];
+    let mut i = 0;
+    while i < N {
+        let (Some(high), Some(low)) = (hex_digit(digits[2 * i]), hex_digit(digits[2 * i + 1])) else {
+            return None;
+        };
+        bytes[i] = high << 4 | low;
+        i += 1;
+    }
+    Some(bytes)
+}
+
+const fn hex_digit(digit: u8) -> Option<u8> {
+    match digit {
+        b'0'..=b'9' => Some(digit - b'0'),
+        b'a'..=b'f' => Some(digit - b'a' + 10),
+        b'A'..=b'F' => Some(digit - b'A' + 10),
+        _ => None,
+    }
+}
+
+/// Why a tagged command was refused
+#[derive(Copy, Clone, PartialEq, Eq, Debug, defmt::Format)]
+pub enum AuthError {
+    /// the firmware was built without a key, the command can't be unlocked at all
+    NoKey,
+    /// no challenge issued, or it was used up by an earlier attempt
+    NoChallenge,
+    WrongTag,
+}
+
+/// The key and the challenge nonce handed out last
+pub struct Challenge {
+    key: Option<[u8; KEY_LEN]>,
+    /// nonces handed out this boot
+    issued: u32,
+    nonce: Option<u64>,
+}
+
+impl Challenge {
+    pub const fn new(key: Option<[u8; KEY_LEN]>) -> Self {
+        Self { key, issued: 0, nonce: None }
+    }
+
+    /// hand out a nonce, it replaces the one before
+    /// the nonce is the boot count over the number handed out this boot, so none repeats while the boot count counts up
+    pub fn issue(&mut self, boot_count: u32) -> Result<u64, AuthError> {
+        self.key.ok_or(AuthError::NoKey)?;
+        let nonce = (boot_count as u64) << 32 | self.issued as u64;
+        self.issued = self.issued.wrapping_add(1);
+        self.nonce = Some(nonce);
+        Ok(nonce)
+    }
+
+    /// check a tag against the nonce handed out, the nonce is gone afterwards either way so each gets one guess
+    pub fn verify(&mut self, tag: &[u8; TAG_LEN]) -> Result<(), AuthError> {
+        let key = self.key.as_ref().ok_or(AuthError::NoKey)?;
+        let nonce = self.nonce.take().ok_or(AuthError::NoChallenge)?;
+        // every byte is compared so the time taken doesn't say how much of a guess was right
+        let expected = self::tag(key, nonce);
+        let difference = expected.iter().zip(tag).fold(0, |difference, (a, b)| difference | (a ^ b));
+        if difference == 0 { Ok(()) } else { Err(AuthError::WrongTag) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sha256(data: &[u8]) -> [u8; 32] {
+        let mut hash = Sha256::new();
+        hash.update(data);
+        hash.finish()
+    }
+
+    fn hex<const N: usize>(hex: &str) -> [u8; N] {
+        parse_hex(hex).unwrap()
+    }
+
+    #[test]
+    fn sha256_matches_the_fips_examples() {
+        assert_eq!(sha256(b"abc"), hex("ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"));
+        assert_eq!(sha256(b""), hex("e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"));
+        assert_eq!(sha256(b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"),
+            hex("248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1"));
+    }
+
+    #[test]
+    fn sha256_is_the_same_in_pieces() {
+        let data: Vec<u8> = (0..200u32).map(|i| (i * 7) as u8).collect();
+        let mut hash = Sha256::new();
+        for piece in data.chunks(13) {
+            hash.update(piece);
+        }
+        assert_eq!(hash.finish(), sha256(&data));
+    }
+
+    // rfc 4231 test cases 1, 2, and 6 (a key longer than a block)
+    #[test]
+    fn hmac_matches_rfc_4231() {
+        assert_eq!(hmac_sha256(&[0x0b; 20], &[b"Hi There"]),
+            hex("b0344c61d8db38535ca8afceaf0bf12b881dc200c9833da726e9376c2e32cff7"));
+        assert_eq!(hmac_sha256(b"Jefe", &[b"what do ya want ", b"for nothing?"]),
+            hex("5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"));
+        assert_eq!(hmac_sha256(&[0xaa; 131], &[b"Test Using Larger Than Block-Size Key - Hash Key First"]),
+            hex("60e431591ee0b67f0d8a26aacbf5b77f8e0bc6213728c5140546040f0ee37f54"));
+    }
+
+    #[test]
+    fn right_tag_unlocks_once() {
+        let key = [7; KEY_LEN];
+        let mut challenge = Challenge::new(Some(key));
+        let nonce = challenge.issue(1).unwrap();
+        assert_eq!(challenge.verify(&tag(&key, nonce)), Ok(()));
+        assert_eq!(challenge.verify(&tag(&key, nonce)), Err(AuthError::NoChallenge));
+    }
+
+    #[test]
+    fn wrong_tag_uses_up_the_nonce() {
+        let key = [7; KEY_LEN];
+        let mut challenge = Challenge::new(Some(key));
+        let nonce = challenge.issue(1).unwrap();
+        assert_eq!(challenge.verify(&tag(&[8; KEY_LEN], nonce)), Err(AuthError::WrongTag));
+        assert_eq!(challenge.verify(&tag(&key, nonce)), Err(AuthError::NoChallenge));
+    }
+
+    #[test]
+    fn old_nonce_tag_is_refused() {
+        let key = [7; KEY_LEN];
+        let mut challenge = Challenge::new(Some(key));
+        let old = challenge.issue(1).unwrap();
+        assert_ne!(challenge.issue(1).unwrap(), old);
+        assert_eq!(challenge.verify(&tag(&key, old)), Err(AuthError::WrongTag));
+    }
+
+    #[test]
+    fn no_key_never_unlocks() {
+        let mut challenge = Challenge::new(None);
+        assert_eq!(challenge.issue(1), Err(AuthError::NoKey));
+        assert_eq!(challenge.verify(&[0; TAG_LEN]), Err(AuthError::NoKey));
+    }
+
+    #[test]
+    fn keys_parse_from_hex() {
+        let key = parse_key("000102030405060708090a0b0c0d0e0f101112131415161718191A1B1C1D1E1F").unwrap();
+        assert_eq!(key, core::array::from_fn(|i| i as u8));
+        assert_eq!(parse_key("0001"), None);
+        assert_eq!(parse_tag("zz0102030405060708090a0b0c0d0e0f"), None);
+    }
+}
@@ -0,0 +1,255 @@
+// barometer pipeline, what the baro task does with each pair of samples, here so it runs against mock parts on the host
+// each unit is compensated and validated on its own, the pair picks the unit to use, single sample glitches are
+// rejected and the altitude filter turns the pressure into altitude and vertical speed
+// the task keeps the timing, the shared state and the ground pressure capture between the steps
+
+use crate::atmosphere::HypsometricAltitude;
+use crate::estimator::AltitudeEstimator;
+use crate::filters::HampelFilter;
+use crate::redundancy::{PairLimits, PairMonitor, Unit};
+use crate::sensors::Barometer;
+use crate::validate::{Limits, SampleFlags, Validator};
+use crate::{BaroData, VerticalState};
+
+/// outlier rejection window length, the thresholds are in the config
+pub const BARO_OUTLIER_WINDOW: usize = 7;
+
+/// redundant barometers, the parts are good to +-1.5 hPa each so 3 hPa apart for 5 samples is a divergence, and 5
+/// failed reads in a row a failed part
+pub const BARO_PAIR_LIMITS: PairLimits = PairLimits { divergence: 3.0, divergence_samples: 5, failed_samples: 5 };
+
+/// what a working barometer can read, pressure in hPa and temperature in C, the temperature range is wider than the
+/// part's rating so a cold gondola still reads, a rocket near the ground moves the pressure ~40 hPa/s
+pub const BARO_LIMITS: [Limits; 2] = [
+    Limits { min: 1.0, max: 1200.0, max_rate: 100.0 },
+    Limits { min: -60.0, max: 100.0, max_rate: 5.0 },
+];
+
+/// at osr 4096 the noise is about a count, this many identical samples in a row is a frozen part
+pub const BARO_FROZEN_SAMPLES: u16 = 20;
+
+/// One unit's read
+#[derive(Copy, Clone)]
+pub enum BaroRead<E> {
+    Good(BaroData),
+    /// the sample failed the checks, it counts against the unit like a failed read, flags say which
+    Rejected(BaroData),
+    Failed(E),
+    /// not read yet
+    Missing,
+}
+
+impl<E> BaroRead<E> {
+    /// the sample if it can be used
+    pub fn sample(&self) -> Option<BaroData> {
+        match self {
+            BaroRead::Good(data) => Some(*data),
+            _ => None,
+        }
+    }
+}
+
+/// The redundant barometers and the filters between them and the vertical state
+pub struct BaroPipeline<E> {
+    pair: PairMonitor,
+    validators: [Validator<2>; 2],
+    outlier_filter: HampelFilter<BARO_OUTLIER_WINDOW>,
+    hypsometric: HypsometricAltitude,
+    estimator: E,
+}
+
+impl<E: AltitudeEstimator> BaroPipeline<E> {
+    /// outlier_k and outlier_min_deviation are the hampel thresholds from the config
+    pub fn new(estimator: E, outlier_k: f32, outlier_min_deviation: f32) -> Self {
+        Self {
+            pair: PairMonitor::new(BARO_PAIR_LIMITS),
+            validators: Unit::ALL.map(|_| Validator::new(BARO_LIMITS, BARO_FROZEN_SAMPLES)),
+            outlier_filter: HampelFilter::new(outlier_k, outlier_min_deviation),
+            hypsometric: HypsometricAltitude::new(),
+            estimator,
+        }
+    }
+
+    /// start the rate checks and filters over, after a sleep the pressure has moved on over what looks like one sample
+    /// period and they would take it for a glitch or a plunge, the pair's health and the temperature profile stay
+    pub fn restart(&mut self, estimator: E, outlier_k: f32, outlier_min_deviation: f32) {
+        self.validators = Unit::ALL.map(|_| Validator::new(BARO_LIMITS, BARO_FROZEN_SAMPLES));
+        self.outlier_filter = HampelFilter::new(outlier_k, outlier_min_deviation);
+        self.estimator = estimator;
+    }
+
+    /// read both units, compensate (pressure, temperature) -> pressure removes the temperature dependent offset
+    pub async fn read<B: Barometer>(&mut self, barometers: &mut [B; 2], time_stamp: u32, compensate: impl Fn(f32, f32) -> f32) -> [BaroRead<B::Error>; 2] {
+        let mut reads = [const { BaroRead::Missing }; 2];
+        for ((read, barometer), validator) in reads.iter_mut().zip(barometers.iter_mut()).zip(self.validators.iter_mut()) {
+            *read = match barometer.read(time_stamp).await {
+                Ok(mut data) => {
+                    data.pressure = compensate(data.pressure, data.temperature);
+                    data.flags = validator.check([data.pressure, data.temperature], data.time_stamp);
+                    if data.flags.valid() { BaroRead::Good(data) } else { BaroRead::Rejected(data) }
+                }
+                Err(e) => BaroRead::Failed(e),
+            };
+        }
+        reads
+    }
+
+    /// the sample to use from this cycle's pair, each unit's usable read or None, reference is a third opinion for
+    /// when the units disagree, flagged degraded while the pair is, None when neither unit gave one
+    pub fn select(&mut self, samples: [Option<BaroData>; 2], reference: Option<BaroData>) -> Option<BaroData> {
+        let mut data = self.pair.update(samples, reference, |a, b| (a.pressure - b.pressure).abs())?;
+        if self.pair.degraded() {
+            data.flags.insert(SampleFlags::DEGRADED);
+        }
+        Some(data)
+    }
+
+    pub fn pair(&self) -> &PairMonitor {
+        &self.pair
+    }
+
+    /// pressure with single sample glitches replaced, hPa
+    pub fn reject_outlier(&mut self, pressure: f32) -> f32 {
+        self.outlier_filter.update(pressure)
+    }
+
+    /// filtered altitude and vertical speed from an outlier filtered pressure (hPa) and the sample's temperature (C)
+    pub fn altitude(&mut self, pressure: f32, temperature: f32, time_stamp: u32) -> VerticalState {
+        let altitude = self.hypsometric.update(pressure, temperature);
+        self.estimator.update(altitude, time_stamp)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embassy_futures::block_on;
+
+    use super::*;
+    use crate::atmosphere;
+    use crate::filters::AltitudeKalman;
+    use crate::sensors::MockBarometer;
+
+    const PERIOD: u32 = 50_000;
+
+    fn pipeline() -> BaroPipeline<AltitudeKalman> {
+        BaroPipeline::new(AltitudeKalman::new(0.5, 1.0), 3.0, 0.05)
+    }
+
+    // a part that stops answering after a number of good reads
+    struct Failing {
+        inner: MockBarometer,
+        good_reads: u32,
+    }
+
+    impl Barometer for Failing {
+        type Error = ();
+
+        async fn read(&mut self, time_stamp: u32) -> Result<BaroData, ()> {
+            if self.good_reads == 0 {
+                return Err(());
+            }
+            self.good_reads -= 1;
+            self.inner.read(time_stamp).await
+        }
+    }
+
+    // one full cycle as the task runs it, without the compensation
+    fn cycle<B: Barometer>(pipeline: &mut BaroPipeline<AltitudeKalman>, barometers: &mut [B; 2], time_stamp: u32) -> Option<(BaroData, VerticalState)> {
+        let reads = block_on(pipeline.read(barometers, time_stamp, |pressure, _| pressure));
+        let data = pipeline.select(reads.map(|read| read.sample()), None)?;
+        let pressure = pipeline.reject_outlier(data.pressure);
+        Some((data, pipeline.altitude(pressure, data.temperature, data.time_stamp)))
+    }
+
+    #[test]
+    fn still_pair_settles_on_the_ground_altitude() {
+        let mut pipeline = pipeline();
+        let mut barometers = [MockBarometer::default(), MockBarometer::default()];
+        let mut state = None;
+        for i in 0..100 {
+            state = cycle(&mut pipeline, &mut barometers, i * PERIOD).map(|(_, state)| state);
+        }
+        let state = state.unwrap();
+        assert!(state.altitude.abs() < 1.0, "{}", state.altitude);
+        assert!(state.vertical_speed.abs() < 0.2, "{}", state.vertical_speed);
+        assert_eq!(pipeline.pair().selected(), Unit::Primary);
+        assert!(!pipeline.pair().degraded());
+    }
+
+    #[test]
+    fn climb_shows_as_vertical_speed() {
+        let mut pipeline = pipeline();
+        let mut barometers = [MockBarometer::default(), MockBarometer::default()];
+        let mut state = None;
+        // 5 m/s up from sea level for 20 s
+        for i in 0..400 {
+            let pressure = atmosphere::altitude_to_pressure(5.0 * (i * PERIOD) as f32 / 1e6);
+            barometers.iter_mut().for_each(|barometer| barometer.pressure = pressure);
+            state = cycle(&mut pipeline, &mut barometers, i * PERIOD).map(|(_, state)| state);
+        }
+        let state = state.unwrap();
+        assert!((state.vertical_speed - 5.0).abs() < 0.5, "{}", state.vertical_speed);
+        assert!((state.altitude - 100.0).abs() < 5.0, "{}", state.altitude);
+    }
+
+    #[test]
+    fn failed_unit_hands_over_to_the_other() {
+        let mut pipeline = pipeline();
+        let mut barometers = [Failing { inner: MockBarometer::default(), good_reads: 10 }, Failing { inner: MockBarometer::default(), good_reads: u32::MAX }];
+        for i in 0..10 {
+            cycle(&mut pipeline, &mut barometers, i * PERIOD).unwrap();
+        }
+        assert_eq!(pipeline.pair().selected(), Unit::Primary);
+
+        // the secondary's samples carry on straight away, the switch comes once the primary counts as failed
+        for i in 10..10 + BARO_PAIR_LIMITS.failed_samples as u32 {
+            let (data, _) = cycle(&mut pipeline, &mut barometers, i * PERIOD).unwrap();
+            assert_eq!(data.time_stamp, i * PERIOD);
+        }
+        assert_eq!(pipeline.pair().selected(), Unit::Secondary);
+        let (data, _) = cycle(&mut pipeline, &mut barometers, 100 * PERIOD).unwrap();
+        assert!(data.flags.contains(SampleFlags::DEGRADED));
+    }
+
+    #[test]
+    fn implausible_samples_never_reach_the_filter() {
+        let mut pipeline = pipeline();
+        let mut barometers = [MockBarometer::default(), MockBarometer::default()];
+        for i in 0..5 {
+            cycle(&mut pipeline, &mut barometers, i * PERIOD).unwrap();
+        }
+        barometers.iter_mut().for_each(|barometer| barometer.pressure = 1500.0);
+        let reads = block_on(pipeline.read(&mut barometers, 5 * PERIOD, |pressure, _| pressure));
+        for read in reads {
+            let BaroRead::Rejected(data) = read else { panic!("implausible sample not rejected") };
+            assert!(data.flags.contains(SampleFlags::OUT_OF_RANGE));
+        }
+        assert!(pipeline.select(reads.map(|read| read.sample()), None).is_none());
+    }
+
+    #[test]
+    fn single_glitch_is_replaced_before_the_altitude() {
+        let mut pipeline = pipeline();
+        let mut barometers = [MockBarometer::default(), MockBarometer::default()];
+        for i in 0..20 {
+            cycle(&mut pipeline, &mut barometers, i * PERIOD).unwrap();
+        }
+        // within the plausible rate, but far off the window's median
+        barometers.iter_mut().for_each(|barometer| barometer.pressure = 1013.25 - 4.0);
+        let reads = block_on(pipeline.read(&mut barometers, 20 * PERIOD, |pressure, _| pressure));
+        let data = pipeline.select(reads.map(|read| read.sample()), None).unwrap();
+        let pressure = pipeline.reject_outlier(data.pressure);
+        assert!((data.pressure - 1009.25).abs() < 0.02);
+        assert!((pressure - 1013.25).abs() < 0.02, "{}", pressure);
+    }
+
+    #[test]
+    fn compensation_applies_to_each_unit() {
+        let mut pipeline = pipeline();
+        let mut barometers = [MockBarometer::default(), MockBarometer::default()];
+        let reads = block_on(pipeline.read(&mut barometers, 0, |pressure, temperature| pressure - 0.1 * temperature));
+        for read in reads {
+            assert!((read.sample().unwrap().pressure - (1013.25 - 2.5)).abs() < 0.02);
+        }
+    }
+}
@@ -0,0 +1,325 @@
+use core::cell::UnsafeCell;
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+/// sd card block size, the consumer only ever sees whole blocks
+pub const BLOCK_SIZE: usize = 512;
+
+/// fills the unused tail of a block, a reader skips to the next block when it sees it where a record should start
+pub const PAD: u8 = 0xFF;
+
+/// Why a write grant was refused
+#[derive(Copy, Clone, PartialEq, Eq, Debug, defmt::Format)]
+pub enum GrantError {
+    /// record longer than a block, records never span blocks
+    TooLarge,
+    /// not enough free space, the consumer has fallen behind
+    Full,
+}
+
+/// Lock free single producer single consumer byte queue that hands out whole blocks, in the style of bbqueue
+/// the producer serializes records straight into grants of the backing buffer, a record that doesn't fit in what is
+/// left of the current block starts the next one and the gap is padded, so every block stands on its own and one
+/// corrupt block on the card loses only the records in it
+/// the consumer borrows whole blocks in place, e.g. to hand them to a dma transfer, and releases them when done
+/// N is the buffer size in bytes, a power of two and at least two blocks
+pub struct BlockQueue<const N: usize> {
+    buf: UnsafeCell<[u8; N]>,
+    // free running byte counts, the buffer position is the count mod N
+    write: AtomicUsize,
+    read: AtomicUsize,
+    taken: AtomicBool,
+}
+
+// the producer and consumer only ever touch disjoint parts of the buffer, handed over by the release/acquire counts
+unsafe impl<const N: usize> Sync for BlockQueue<N> {}
+
+impl<const N: usize> Default for BlockQueue<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> BlockQueue<N> {
+    const VALID: () = assert!(N.is_power_of_two() && N >= 2 * BLOCK_SIZE);
+
+    pub const fn new() -> Self {
+        let () = Self::VALID;
+        Self {
+            buf: UnsafeCell::new([0; N]),
+            write: AtomicUsize::new(0),
+            read: AtomicUsize::new(0),
+            taken: AtomicBool::new(false),
+        }
+    }
+
+    /// the producer and consumer halves, only the first call gets them
+    pub fn split(&'static self) -> Option<(Producer<N>, Consumer<N>)> {
+        if self.taken.swap(true, Ordering::AcqRel) {
+            return None;
+        }
+        Some((Producer { queue: self }, Consumer { queue: self }))
+    }
+
+    fn slice(&self, start: usize, len: usize) -> *mut u8 {
+        debug_assert!(start % N + len <= N);
+        unsafe { (self.buf.get() as *mut u8).add(start % N) }
+    }
+}
+
+/// Writing half of a BlockQueue
+pub struct Producer<const N: usize> {
+    queue: &'static BlockQueue<N>,
+}
+
+impl<const N: usize> Producer<N> {
+    /// bytes left in the block being filled, a whole block when the last one was finished
+    pub fn block_remaining(&self) -> usize {
+        BLOCK_SIZE - self.queue.write.load(Ordering::Relaxed) % BLOCK_SIZE
+    }
+
+    /// bytes written but not yet released by the consumer
+    pub fn pending(&self) -> usize {
+        let write = self.queue.write.load(Ordering::Relaxed);
+        write.wrapping_sub(self.queue.read.load(Ordering::Acquire))
+    }
+
+    /// true once the consumer has released everything written
+    pub fn is_empty(&self) -> bool {
+        self.pending() == 0
+    }
+
+    /// space for a record of len bytes, in the current block if it fits there and at the start of the next one if not
+    pub fn grant(&mut self, len: usize) -> Result<WriteGrant<'_, N>, GrantError> {
+        if len > BLOCK_SIZE {
+            return Err(GrantError::TooLarge);
+        }
+        let remaining = self.block_remaining();
+        let pad = if len > remaining { remaining } else { 0 };
+        if N - self.pending() < pad + len {
+            return Err(GrantError::Full);
+        }
+        self.pad(pad);
+
+        let start = self.queue.write.load(Ordering::Relaxed);
+        Ok(WriteGrant { producer: self, start, len })
+    }
+
+    /// pad out the block being filled so the consumer can take it, nothing to do if it is already finished
+    /// the tail of the current block is always free, the consumer only holds whole blocks behind it
+    pub fn finish_block(&mut self) {
+        let remaining = self.block_remaining();
+        if remaining != BLOCK_SIZE {
+            self.pad(remaining);
+        }
+    }
+
+    fn pad(&mut self, len: usize) {
+        if len == 0 {
+            return;
+        }
+        let write = self.queue.write.load(Ordering::Relaxed);
+        unsafe { core::slice::from_raw_parts_mut(self.queue.slice(write, len), len) }.fill(PAD);
+        self.queue.write.store(write.wrapping_add(len), Ordering::Release);
+    }
+}
+
+/// Space for one record, visible to the consumer only once committed, dropping it without a commit writes nothing
+pub struct WriteGrant<'a, const N: usize> {
+    producer: &'a mut Producer<N>,
+    start: usize,
+    len: usize,
+}
+
+impl<const N: usize> WriteGrant<'_, N> {
+    /// hand the first used bytes to the consumer
+    pub fn commit(self, used: usize) {
+        let used = used.min(self.len);
+        self.producer.queue.write.store(self.start.wrapping_add(used), Ordering::Release);
+    }
+}
+
+impl<const N: usize> Deref for WriteGrant<'_, N> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        unsafe { core::slice::from_raw_parts(self.producer.queue.slice(self.start, self.len), self.len) }
+    }
+}
+
+impl<const N: usize> DerefMut for WriteGrant<'_, N> {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        unsafe { core::slice::from_raw_parts_mut(self.producer.queue.slice(self.start, self.len), self.len) }
+    }
+}
+
+/// Reading half of a BlockQueue
+pub struct Consumer<const N: usize> {
+    queue: &'static BlockQueue<N>,
+}
+
+impl<const N: usize> Consumer<N> {
+    /// the oldest finished block, in place, None until the producer has filled or finished one
+    pub fn read(&mut self) -> Option<ReadGrant<'_, N>> {
+        let read = self.queue.read.load(Ordering::Relaxed);
+        let write = self.queue.write.load(Ordering::Acquire);
+        if write.wrapping_sub(read) < BLOCK_SIZE {
+            return None;
+        }
+        Some(ReadGrant { consumer: self, start: read })
+    }
+}
+
+/// One whole block borrowed from the queue, dropping it without a release leaves it to be read again
+pub struct ReadGrant<'a, const N: usize> {
+    consumer: &'a mut Consumer<N>,
+    start: usize,
+}
+
+impl<const N: usize> ReadGrant<'_, N> {
+    /// give the block back to the producer
+    pub fn release(self) {
+        self.consumer.queue.read.store(self.start.wrapping_add(BLOCK_SIZE), Ordering::Release);
+    }
+}
+
+impl<const N: usize> Deref for ReadGrant<'_, N> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        unsafe { core::slice::from_raw_parts(self.consumer.queue.slice(self.start, BLOCK_SIZE), BLOCK_SIZE) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // two blocks, the smallest queue
+    const N: usize = 2 * BLOCK_SIZE;
+
+    fn queue() -> &'static BlockQueue<N> {
+        Box::leak(Box::new(BlockQueue::new()))
+    }
+
+    fn write(producer: &mut Producer<N>, len: usize, byte: u8) {
+        let mut grant = producer.grant(len).unwrap();
+        grant.fill(byte);
+        grant.commit(len);
+    }
+
+    #[test]
+    fn halves_are_handed_out_once() {
+        let queue = queue();
+        assert!(queue.split().is_some());
+        assert!(queue.split().is_none());
+    }
+
+    #[test]
+    fn record_past_the_block_starts_the_next_one() {
+        let (mut producer, mut consumer) = queue().split().unwrap();
+        write(&mut producer, 500, 1);
+        assert!(consumer.read().is_none());
+        write(&mut producer, 20, 2);
+        assert_eq!(producer.block_remaining(), BLOCK_SIZE - 20);
+
+        let block = consumer.read().unwrap();
+        assert!(block[..500].iter().all(|&b| b == 1));
+        assert!(block[500..].iter().all(|&b| b == PAD));
+        block.release();
+
+        // the second block goes to the consumer once it is finished
+        assert!(consumer.read().is_none());
+        producer.finish_block();
+        let block = consumer.read().unwrap();
+        assert!(block[..20].iter().all(|&b| b == 2));
+        assert!(block[20..].iter().all(|&b| b == PAD));
+        block.release();
+        assert!(producer.is_empty());
+
+        // a finished block isn't padded again
+        producer.finish_block();
+        assert!(producer.is_empty());
+    }
+
+    #[test]
+    fn grant_is_refused_when_too_large_or_full() {
+        let (mut producer, mut consumer) = queue().split().unwrap();
+        assert_eq!(producer.grant(BLOCK_SIZE + 1).err(), Some(GrantError::TooLarge));
+        write(&mut producer, BLOCK_SIZE, 1);
+        write(&mut producer, 500, 2);
+        // the last 12 bytes fit a 12 byte record, but a 13 byte one also needs them as padding
+        assert!(producer.grant(12).is_ok());
+        assert_eq!(producer.grant(13).err(), Some(GrantError::Full));
+        write(&mut producer, 12, 3);
+        assert_eq!(producer.grant(1).err(), Some(GrantError::Full));
+
+        consumer.read().unwrap().release();
+        assert!(producer.grant(BLOCK_SIZE).is_ok());
+    }
+
+    #[test]
+    fn only_the_committed_part_of_a_grant_is_written() {
+        let (mut producer, mut consumer) = queue().split().unwrap();
+        let mut grant = producer.grant(100).unwrap();
+        grant[..10].fill(1);
+        grant.commit(10);
+        assert_eq!(producer.pending(), 10);
+
+        // dropped without a commit
+        producer.grant(100).unwrap().fill(2);
+        assert_eq!(producer.pending(), 10);
+
+        // more than granted is the whole grant
+        let mut grant = producer.grant(30).unwrap();
+        grant.fill(3);
+        grant.commit(1000);
+        assert_eq!(producer.pending(), 40);
+
+        producer.finish_block();
+        let block = consumer.read().unwrap();
+        assert!(block[..10].iter().all(|&b| b == 1));
+        assert!(block[10..40].iter().all(|&b| b == 3));
+        assert!(block[40..].iter().all(|&b| b == PAD));
+    }
+
+    #[test]
+    fn dropped_read_grant_is_read_again() {
+        let (mut producer, mut consumer) = queue().split().unwrap();
+        write(&mut producer, BLOCK_SIZE, 7);
+        // dropped at the end of the statement without a release
+        assert_eq!(consumer.read().unwrap()[0], 7);
+        assert_eq!(producer.pending(), BLOCK_SIZE);
+
+        let block = consumer.read().unwrap();
+        assert!(block.iter().all(|&b| b == 7));
+        block.release();
+        assert!(consumer.read().is_none());
+        assert!(producer.is_empty());
+    }
+
+    #[test]
+    fn blocks_wrap_around_the_buffer_and_the_counts() {
+        let queue = queue();
+        // the free running counts a block short of wrapping
+        queue.write.store(0usize.wrapping_sub(BLOCK_SIZE), Ordering::Relaxed);
+        queue.read.store(0usize.wrapping_sub(BLOCK_SIZE), Ordering::Relaxed);
+        let (mut producer, mut consumer) = queue.split().unwrap();
+
+        for block in 0..5u8 {
+            // a 300 byte record then a 300 byte one that goes in the next block
+            write(&mut producer, 300, block);
+            assert!(consumer.read().is_none());
+            write(&mut producer, 300, block + 100);
+            let read = consumer.read().unwrap();
+            assert!(read[..300].iter().all(|&b| b == block));
+            assert!(read[300..].iter().all(|&b| b == PAD));
+            read.release();
+            producer.finish_block();
+            let read = consumer.read().unwrap();
+            assert!(read[..300].iter().all(|&b| b == block + 100));
+            read.release();
+            assert!(producer.is_empty());
+        }
+    }
+}
@@ -0,0 +1,199 @@
+// first stage on the nucleo, it keeps flash sector 0 and the application never writes it
+// it finishes a copy a reset or power loss cut short, installs a received update after backing up the running image,
+// rolls back an update that kept failing its trial, then starts the application in sector 1
+// the decisions and the copies live here rather than in the application, so a bad or half copied image can't take
+// its own way back with it
+// flashed once with probe-rs download --chip STM32F767ZITx, cargo run puts the application after it
+#![no_std]
+#![no_main]
+
+use core::ptr;
+
+use cortex_m_rt::entry;
+use defmt::{error, info, warn};
+use defmt_rtt as _;
+use embassy_stm32::pac;
+use embassy_stm32::pac::flash::vals::Psize;
+use embassy_stm32::pac::iwdg::vals::{Key, Pr};
+
+use avionics_sw_hapsis::crc::crc32;
+use avionics_sw_hapsis::update::{BootAction, SlotTrailer, UpdateRecord, UpdateState};
+
+mod slots;
+
+use slots::*;
+
+// erased flash word, a trailer flag still in this state hasn't been programmed
+const ERASED: u32 = 0xFFFF_FFFF;
+
+#[entry]
+fn main() -> ! {
+    enable_backup_sram();
+    unlock_flash();
+
+    // the source slot of an interrupted copy still holds the whole image, the copy is simply run again
+    for slot in [UPDATE_SLOT, BACKUP_SLOT] {
+        if read_word(slot.0 + STARTED_OFFSET) != ERASED && read_word(slot.0 + DONE_OFFSET) == ERASED {
+            warn!("finishing an interrupted copy from {:#x}", slot.0);
+            copy_to_app(slot);
+        }
+    }
+
+    // without vbat the record doesn't survive a power loss, an update is then just not installed
+    let mut record = unsafe { ptr::read_volatile(UPDATE_RECORD) };
+    if record.state() == Some(UpdateState::Pending) && slot_image(UPDATE_SLOT) != Some((record.size, record.crc)) {
+        error!("update slot doesn't match the pending update, not installing it");
+        record = UpdateRecord::EMPTY;
+    }
+    match record.boot(UPDATE_TRIAL_BOOTS) {
+        BootAction::Install => {
+            info!("backing up the running image");
+            back_up_app();
+            // the record says trial before the copy starts, so a reset during it finishes the install and boots
+            // the image on trial rather than installing it again over a backup of itself
+            unsafe { ptr::write_volatile(UPDATE_RECORD, record) };
+            info!("installing update, {} bytes", record.size);
+            copy_to_app(UPDATE_SLOT);
+        }
+        BootAction::Rollback => {
+            unsafe { ptr::write_volatile(UPDATE_RECORD, record) };
+            if slot_image(BACKUP_SLOT).is_some() {
+                warn!("update failed its trial, rolling back");
+                copy_to_app(BACKUP_SLOT);
+            } else {
+                error!("update failed its trial, but there is no backed up image to roll back to");
+            }
+        }
+        BootAction::Run => unsafe { ptr::write_volatile(UPDATE_RECORD, record) },
+    }
+    lock_flash();
+
+    // an image on trial has the watchdog running from its first instruction, so one that hangs before its own
+    // watchdog task starts still resets and uses up a trial boot, the application takes it over at its own timeout
+    if record.state() == Some(UpdateState::Trial) {
+        start_watchdog();
+    }
+
+    let app = FLASH_BASE + APP_SLOT.0;
+    unsafe {
+        (*cortex_m::peripheral::SCB::PTR).vtor.write(app);
+        cortex_m::asm::bootload(app as *const u32)
+    }
+}
+
+// size and crc of the whole image in an update or backup slot, None without a trailer or when the image doesn't
+// match it
+fn slot_image(slot: (u32, u8, u8)) -> Option<(u32, u32)> {
+    let mut bytes = [0u8; SlotTrailer::LEN];
+    bytes.copy_from_slice(flash_bytes(slot.0 + TRAILER_OFFSET, SlotTrailer::LEN as u32));
+    let trailer = SlotTrailer::from_bytes(&bytes)?;
+    if trailer.size > IMAGE_SLOT_SIZE || crc32(flash_bytes(slot.0, trailer.size)) != trailer.crc {
+        return None;
+    }
+    Some((trailer.size, trailer.crc))
+}
+
+// copy the running image, all of its slot since its length isn't known, and write a trailer for it
+fn back_up_app() {
+    erase(BACKUP_SLOT);
+    program(BACKUP_SLOT.0, flash_bytes(APP_SLOT.0, IMAGE_SLOT_SIZE));
+    let trailer = SlotTrailer { size: IMAGE_SLOT_SIZE, crc: crc32(flash_bytes(BACKUP_SLOT.0, IMAGE_SLOT_SIZE)) };
+    program(BACKUP_SLOT.0 + TRAILER_OFFSET, &trailer.to_bytes());
+}
+
+// copy the image in an update or backup slot over the application, flagging the start and end in the source slot
+fn copy_to_app(slot: (u32, u8, u8)) {
+    let Some((size, _)) = slot_image(slot) else {
+        error!("no whole image at {:#x} to copy", slot.0);
+        return;
+    };
+    if read_word(slot.0 + STARTED_OFFSET) == ERASED {
+        program(slot.0 + STARTED_OFFSET, &[0; 4]);
+    }
+    erase(APP_SLOT);
+    program(APP_SLOT.0, flash_bytes(slot.0, size));
+    if crc32(flash_bytes(APP_SLOT.0, size)) != crc32(flash_bytes(slot.0, size)) {
+        error!("copy from {:#x} doesn't match its source", slot.0);
+        return;
+    }
+    program(slot.0 + DONE_OFFSET, &[0; 4]);
+}
+
+fn flash_bytes(offset: u32, len: u32) -> &'static [u8] {
+    unsafe { core::slice::from_raw_parts((FLASH_BASE + offset) as *const u8, len as usize) }
+}
+
+fn read_word(offset: u32) -> u32 {
+    unsafe { ptr::read_volatile((FLASH_BASE + offset) as *const u32) }
+}
+
+fn unlock_flash() {
+    pac::FLASH.keyr().write_value(0x4567_0123);
+    pac::FLASH.keyr().write_value(0xCDEF_89AB);
+}
+
+fn lock_flash() {
+    pac::FLASH.cr().write(|w| w.set_lock(true));
+}
+
+// wait out the flash operation in progress, logging and clearing any error it ended with
+fn wait_idle() {
+    while pac::FLASH.sr().read().bsy() {}
+    let status = pac::FLASH.sr().read();
+    if status.operr() || status.wrperr() || status.pgaerr() || status.pgperr() || status.erserr() {
+        error!("flash error, status: {:#x}", status.0);
+        pac::FLASH.sr().write_value(status);
+    }
+}
+
+fn erase(slot: (u32, u8, u8)) {
+    for sector in slot.1..slot.1 + slot.2 {
+        pac::FLASH.cr().write(|w| {
+            w.set_psize(Psize::PSIZE32);
+            w.set_ser(true);
+            w.set_snb(sector);
+        });
+        pac::FLASH.cr().modify(|w| w.set_strt(true));
+        wait_idle();
+    }
+    pac::FLASH.cr().write(|_| {});
+}
+
+// program whole words, bytes is a multiple of 4 long
+fn program(offset: u32, bytes: &[u8]) {
+    pac::FLASH.cr().write(|w| {
+        w.set_psize(Psize::PSIZE32);
+        w.set_pg(true);
+    });
+    for (index, word) in bytes.chunks_exact(4).enumerate() {
+        let address = (FLASH_BASE + offset) as usize + index * 4;
+        unsafe { ptr::write_volatile(address as *mut u32, u32::from_le_bytes([word[0], word[1], word[2], word[3]])) };
+        cortex_m::asm::dsb();
+        wait_idle();
+    }
+    pac::FLASH.cr().write(|_| {});
+}
+
+// turn on the backup sram the update record lives in, the application does the same for itself
+fn enable_backup_sram() {
+    pac::RCC.apb1enr().modify(|w| w.set_pwren(true));
+    pac::PWR.cr1().modify(|w| w.set_dbp(true));
+    pac::RCC.ahb1enr().modify(|w| w.set_bkpsramen(true));
+    pac::PWR.csr1().modify(|w| w.set_bre(true));
+    while !pac::PWR.csr1().read().brr() {}
+}
+
+// longest timeout the iwdg has, about 32 s from the 32 kHz lsi
+fn start_watchdog() {
+    pac::IWDG.kr().write(|w| w.set_key(Key::START));
+    pac::IWDG.kr().write(|w| w.set_key(Key::ENABLE));
+    pac::IWDG.pr().write(|w| w.set_pr(Pr::DIVIDE_BY256));
+    pac::IWDG.rlr().write(|w| w.set_rl(0xFFF));
+    while pac::IWDG.sr().read().0 != 0 {}
+}
+
+// a bootloader that can't go on resets and tries again
+#[panic_handler]
+fn panic(_info: &core::panic::PanicInfo) -> ! {
+    cortex_m::peripheral::SCB::sys_reset()
+}
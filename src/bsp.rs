@@ -0,0 +1,1188 @@
+// board support, pin maps and flash layout for every board the firmware runs on
+// the board is picked with a cargo feature, flight board rev b is the default
+
+use embassy_embedded_hal::shared_bus::asynch::spi::SpiDeviceWithConfig;
+use embassy_stm32::flash::Flash;
+// internal flash is only written for config and calibration without the fram, and for updates on the nucleo
+#[cfg(any(not(feature = "fram"), feature = "nucleo-f767"))]
+use embassy_stm32::flash::{self, WRITE_SIZE};
+use embassy_stm32::exti::ExtiInput;
+use embassy_stm32::gpio::{AnyPin, Level, Output, OutputType, Pull, Speed};
+use embassy_stm32::mode::Async;
+use embassy_stm32::peripherals::{IWDG, RTC, TIM3, USB_OTG_FS};
+use embassy_stm32::spi::{self, Spi};
+use embassy_stm32::time::Hertz;
+use embassy_stm32::timer::Channel as TimerChannel;
+use embassy_stm32::timer::low_level::CountingMode;
+use embassy_stm32::timer::simple_pwm::{PwmPin, SimplePwm};
+use embassy_stm32::usart::{self, BufferedUart};
+use embassy_stm32::usb::{self, Driver};
+use embassy_stm32::{Peri, Peripherals};
+use embassy_sync::blocking_mutex::raw::ThreadModeRawMutex;
+use embassy_sync::mutex::Mutex;
+use embassy_time::Delay;
+use static_cell::StaticCell;
+
+use avionics_sw_hapsis::drivers::spinor::SpiNor;
+use avionics_sw_hapsis::rails::Rail;
+use avionics_sw_hapsis::storage::LogStorage;
+
+use crate::arbiter::{Arbiter, Arbitrated, BusPriority};
+#[cfg(feature = "fram")]
+use avionics_sw_hapsis::drivers::eeprom::{self, Eeprom, EepromRegion};
+#[cfg(not(feature = "fram"))]
+use avionics_sw_hapsis::kvstore::KvFlash;
+#[cfg(not(feature = "replay"))]
+use avionics_sw_hapsis::sensors::MockGps;
+#[cfg(any(feature = "replay", feature = "nucleo-f767"))]
+use avionics_sw_hapsis::sensors::{MockActuators, MockBattery, MockCamera, MockCharger};
+#[cfg(all(not(feature = "replay"), feature = "nucleo-f767"))]
+use avionics_sw_hapsis::sensors::{MockBarometer, MockImu};
+#[cfg(all(not(feature = "replay"), any(feature = "board-rev-a", feature = "board-rev-b")))]
+use {
+    avionics_sw_hapsis::drivers::arducam::{self, ArduCam},
+    avionics_sw_hapsis::drivers::icm42688::Icm42688,
+    avionics_sw_hapsis::drivers::ina219::{self, Ina219},
+    avionics_sw_hapsis::drivers::lis3mdl::{self, Lis3mdl},
+    avionics_sw_hapsis::drivers::ms5611::{self, Ms5611},
+    avionics_sw_hapsis::actuation::Channel,
+    avionics_sw_hapsis::busrecovery::{BusMonitor, CLEAR_CLOCKS},
+    avionics_sw_hapsis::power::{ChargeInput, ChargeState},
+    avionics_sw_hapsis::sensors::{self, WithMag},
+    embassy_embedded_hal::shared_bus::asynch::i2c::I2cDevice,
+    embassy_embedded_hal::shared_bus::asynch::spi::SpiDevice,
+    embassy_stm32::adc::{Adc, AdcChannel, AnyAdcChannel},
+    embassy_stm32::gpio::{Flex, Input},
+    embassy_stm32::peripherals::{ADC1, DMA1_CH0, DMA1_CH6, I2C1, PB8, PB9},
+    embassy_stm32::i2c::{self, I2c},
+    embassy_sync::signal::Signal,
+    embassy_time::{Duration, block_for},
+    embedded_hal_bus::spi::ExclusiveDevice,
+};
+#[cfg(feature = "replay")]
+use avionics_sw_hapsis::replay::{ReplayBarometer, ReplayGps, ReplayImu};
+#[cfg(any(feature = "board-rev-a", feature = "board-rev-b"))]
+use avionics_sw_hapsis::drivers::sdcard;
+#[cfg(feature = "nucleo-f767")]
+use {
+    avionics_sw_hapsis::update::SlotTrailer,
+    crate::slots::{FLASH_BASE, IMAGE_SLOT_SIZE, SLOT_SIZE, TRAILER_OFFSET, UPDATE_SLOT},
+    embassy_stm32::peripherals::SDMMC1,
+    embassy_stm32::sdmmc::{self, DataBlock, Sdmmc, SdmmcPeripheral},
+};
+
+#[cfg(not(any(feature = "board-rev-a", feature = "board-rev-b", feature = "nucleo-f767")))]
+compile_error!("no board selected, enable one of board-rev-a, board-rev-b, nucleo-f767");
+
+#[cfg(any(
+    all(feature = "board-rev-a", feature = "board-rev-b"),
+    all(feature = "board-rev-a", feature = "nucleo-f767"),
+    all(feature = "board-rev-b", feature = "nucleo-f767"),
+))]
+compile_error!("more than one board selected, use --no-default-features when picking a board");
+
+#[cfg(all(feature = "fram", any(feature = "nucleo-f767", feature = "replay")))]
+compile_error!("the fram is on the flight board's sensor bus, which the nucleo and replay builds don't have");
+
+// flight board rev a and rev b, stm32f407vg with 1M of flash
+// the calibration store lives in the last sector (sector 11) and config in the one before (sector 10), both 128K
+// the firmware image has the rest, there is no room for an update slot, see src/slots.rs
+#[cfg(any(feature = "board-rev-a", feature = "board-rev-b"))]
+mod flight {
+    use embassy_stm32::bind_interrupts;
+    use embassy_stm32::flash::{self, Async, Flash};
+    use embassy_stm32::peripherals::{I2C1, USART2, USB_OTG_FS};
+    use embassy_stm32::rcc::{AHBPrescaler, APBPrescaler, Hse, HseMode, LsConfig, Pll, PllMul, PllPDiv, PllPreDiv, PllQDiv, PllSource, Sysclk, mux};
+    use embassy_stm32::time::Hertz;
+    use embassy_stm32::{Config, i2c, usart, usb};
+
+    bind_interrupts!(pub struct Irqs {
+        FLASH => flash::InterruptHandler;
+        I2C1_EV => i2c::EventInterruptHandler<I2C1>;
+        I2C1_ER => i2c::ErrorInterruptHandler<I2C1>;
+        OTG_FS => usb::InterruptHandler<USB_OTG_FS>;
+        USART2 => usart::BufferedInterruptHandler<USART2>;
+    });
+
+    pub type Storage = Flash<'static, Async>;
+
+    pub const CALIBRATION_FLASH_OFFSET: u32 = 0xE_0000;
+    // with the fram feature config is kept there and its sector is left alone
+    #[cfg_attr(feature = "fram", allow(dead_code))]
+    pub const CONFIG_FLASH_OFFSET: u32 = 0xC_0000;
+    #[cfg_attr(feature = "fram", allow(dead_code))]
+    pub const FLASH_SECTOR_SIZE: u32 = 0x2_0000;
+
+    // system memory holding st's usb dfu bootloader
+    pub const SYSTEM_BOOTLOADER: u32 = 0x1FFF_0000;
+
+    // 8 MHz crystal on hse, rev b kept the rev a part
+    const HSE_HZ: u32 = 8_000_000;
+
+    // 168 MHz is the f407 maximum, the pll runs from hse at 1 MHz in and 336 MHz vco
+    // the reduced clock halves the core and ahb and drops an apb prescaler step so the bus clocks don't change, the
+    // apb2 timer clock does, it is twice apb2 only while apb2 is divided, nothing runs on tim1 and tim8
+    #[cfg(not(feature = "reduced-clock"))]
+    pub const SYSCLK_HZ: u32 = 168_000_000;
+    #[cfg(feature = "reduced-clock")]
+    pub const SYSCLK_HZ: u32 = 84_000_000;
+    #[cfg(not(feature = "reduced-clock"))]
+    const PLL_P: PllPDiv = PllPDiv::DIV2;
+    #[cfg(feature = "reduced-clock")]
+    const PLL_P: PllPDiv = PllPDiv::DIV4;
+    #[cfg(not(feature = "reduced-clock"))]
+    const APB_PRE: (APBPrescaler, APBPrescaler) = (APBPrescaler::DIV4, APBPrescaler::DIV2);
+    #[cfg(not(feature = "reduced-clock"))]
+    const APB_DIV: (u32, u32) = (4, 2);
+    #[cfg(feature = "reduced-clock")]
+    const APB_PRE: (APBPrescaler, APBPrescaler) = (APBPrescaler::DIV2, APBPrescaler::DIV1);
+    #[cfg(feature = "reduced-clock")]
+    const APB_DIV: (u32, u32) = (2, 1);
+
+    // can bit timing and the spi and sdmmc dividers are worked out from these, the asserts keep them fixed
+    // apb1 (usart2-5, spi2-3, can, i2c, tim2-7 x2) and apb2 (usart1 and 6, spi1, sdio, tim1 and 8 x2 while divided)
+    pub const APB1_HZ: u32 = SYSCLK_HZ / APB_DIV.0;
+    pub const APB2_HZ: u32 = SYSCLK_HZ / APB_DIV.1;
+    const _: () = assert!(APB1_HZ == 42_000_000 && APB2_HZ == 84_000_000);
+    // the time driver (tim4) and the servos (tim3) count on the apb1 timer clock
+    const APB1_TIMER_HZ: u32 = if APB_DIV.0 == 1 { APB1_HZ } else { 2 * APB1_HZ };
+    const _: () = assert!(APB1_TIMER_HZ == 84_000_000);
+    // sdio and usb kernel clock from the pll q output
+    pub const CLK48_HZ: u32 = HSE_HZ / 8 * 336 / 7;
+
+    pub fn config() -> Config {
+        let mut config = Config::default();
+        // the rtc runs from the 32 kHz crystal so it keeps accurate time on vbat through resets
+        config.rcc.ls = LsConfig::default_lse();
+        config.rcc.hse = Some(Hse {
+            freq: Hertz(HSE_HZ),
+            mode: HseMode::Oscillator,
+        });
+        config.rcc.pll_src = PllSource::HSE;
+        config.rcc.pll = Some(Pll {
+            prediv: PllPreDiv::DIV8,
+            mul: PllMul::MUL336,
+            divp: Some(PLL_P),
+            divq: Some(PllQDiv::DIV7),
+            divr: None,
+        });
+        config.rcc.ahb_pre = AHBPrescaler::DIV1;
+        (config.rcc.apb1_pre, config.rcc.apb2_pre) = APB_PRE;
+        config.rcc.sys = Sysclk::PLL1_P;
+        config.rcc.mux.clk48sel = mux::Clk48sel::PLL1_Q;
+        config
+    }
+
+    // erasing a 128K sector takes a while, the async flash driver lets other tasks run meanwhile
+    #[cfg_attr(feature = "fram", allow(dead_code))]
+    pub async fn erase(flash: &mut Storage, from: u32, to: u32) -> Result<(), flash::Error> {
+        flash.erase(from, to).await
+    }
+
+    #[cfg_attr(feature = "fram", allow(dead_code))]
+    pub async fn write(flash: &mut Storage, offset: u32, bytes: &[u8]) -> Result<(), flash::Error> {
+        flash.write(offset, bytes).await
+    }
+}
+
+#[cfg(any(feature = "board-rev-a", feature = "board-rev-b"))]
+pub use flight::*;
+
+// nucleo-f767zi dev board, 2M of flash in single bank mode
+// the calibration store lives in the last sector (sector 11) and config in the one before (sector 10), both 256K
+// the bootloader, the firmware image, and the update and backup slots have sectors 0-9, see src/slots.rs
+#[cfg(feature = "nucleo-f767")]
+mod nucleo {
+    use embassy_stm32::bind_interrupts;
+    use embassy_stm32::flash::{self, Blocking, Flash};
+    use embassy_stm32::peripherals::{SDMMC1, USART3, USB_OTG_FS};
+    use embassy_stm32::rcc::{AHBPrescaler, APBPrescaler, Hse, HseMode, LsConfig, Pll, PllMul, PllPDiv, PllPreDiv, PllQDiv, PllSource, Sysclk, mux};
+    use embassy_stm32::time::Hertz;
+    use embassy_stm32::{Config, sdmmc, usart, usb};
+
+    bind_interrupts!(pub struct Irqs {
+        OTG_FS => usb::InterruptHandler<USB_OTG_FS>;
+        USART3 => usart::BufferedInterruptHandler<USART3>;
+        SDMMC1 => sdmmc::InterruptHandler<SDMMC1>;
+    });
+
+    pub type Storage = Flash<'static, Blocking>;
+
+    pub const CALIBRATION_FLASH_OFFSET: u32 = 0x1E_0000;
+    pub const CONFIG_FLASH_OFFSET: u32 = 0x1C_0000;
+    pub const FLASH_SECTOR_SIZE: u32 = 0x4_0000;
+
+    // system memory holding st's usb dfu bootloader
+    pub const SYSTEM_BOOTLOADER: u32 = 0x1FF0_0000;
+
+    // 8 MHz from the st-link mco, a clock input rather than a crystal
+    const HSE_HZ: u32 = 8_000_000;
+
+    // 216 MHz is the f767 maximum with overdrive, the pll runs from hse at 2 MHz in and 432 MHz vco
+    // the reduced clock halves the core and ahb and drops an apb prescaler step so the bus clocks don't change, the
+    // apb2 timer clock does, it is twice apb2 only while apb2 is divided, nothing runs on tim1 and tim8
+    #[cfg(not(feature = "reduced-clock"))]
+    pub const SYSCLK_HZ: u32 = 216_000_000;
+    #[cfg(feature = "reduced-clock")]
+    pub const SYSCLK_HZ: u32 = 108_000_000;
+    #[cfg(not(feature = "reduced-clock"))]
+    const PLL_P: PllPDiv = PllPDiv::DIV2;
+    #[cfg(feature = "reduced-clock")]
+    const PLL_P: PllPDiv = PllPDiv::DIV4;
+    #[cfg(not(feature = "reduced-clock"))]
+    const APB_PRE: (APBPrescaler, APBPrescaler) = (APBPrescaler::DIV4, APBPrescaler::DIV2);
+    #[cfg(not(feature = "reduced-clock"))]
+    const APB_DIV: (u32, u32) = (4, 2);
+    #[cfg(feature = "reduced-clock")]
+    const APB_PRE: (APBPrescaler, APBPrescaler) = (APBPrescaler::DIV2, APBPrescaler::DIV1);
+    #[cfg(feature = "reduced-clock")]
+    const APB_DIV: (u32, u32) = (2, 1);
+
+    // can bit timing and the spi and sdmmc dividers are worked out from these, the asserts keep them fixed
+    // apb1 (usart2-5, spi2-3, can, i2c, tim2-7 x2) and apb2 (usart1 and 6, spi1 and 4-6, sdmmc, tim1 and 8 x2 while
+    // divided)
+    pub const APB1_HZ: u32 = SYSCLK_HZ / APB_DIV.0;
+    pub const APB2_HZ: u32 = SYSCLK_HZ / APB_DIV.1;
+    const _: () = assert!(APB1_HZ == 54_000_000 && APB2_HZ == 108_000_000);
+    // the time driver (tim4) and the servos (tim3) count on the apb1 timer clock
+    const APB1_TIMER_HZ: u32 = if APB_DIV.0 == 1 { APB1_HZ } else { 2 * APB1_HZ };
+    const _: () = assert!(APB1_TIMER_HZ == 108_000_000);
+    // sdmmc and usb kernel clock from the pll q output
+    pub const CLK48_HZ: u32 = HSE_HZ / 4 * 216 / 9;
+
+    pub fn config() -> Config {
+        let mut config = Config::default();
+        // the rtc runs from the 32 kHz crystal so it keeps accurate time on vbat through resets
+        config.rcc.ls = LsConfig::default_lse();
+        config.rcc.hse = Some(Hse {
+            freq: Hertz(HSE_HZ),
+            mode: HseMode::Bypass,
+        });
+        config.rcc.pll_src = PllSource::HSE;
+        config.rcc.pll = Some(Pll {
+            prediv: PllPreDiv::DIV4,
+            mul: PllMul::MUL216,
+            divp: Some(PLL_P),
+            divq: Some(PllQDiv::DIV9),
+            divr: None,
+        });
+        config.rcc.ahb_pre = AHBPrescaler::DIV1;
+        (config.rcc.apb1_pre, config.rcc.apb2_pre) = APB_PRE;
+        config.rcc.sys = Sysclk::PLL1_P;
+        // sdmmc runs from the 48 MHz clock rather than sysclk so its rate doesn't follow the core
+        config.rcc.mux.clk48sel = mux::Clk48sel::PLL1_Q;
+        config.rcc.mux.sdmmc1sel = mux::Sdmmcsel::CLK48;
+        config
+    }
+
+    // there is no async flash driver for the f7, the erase stalls the cpu but fits in the watchdog timeout, the two
+    // update slot sectors take a few seconds
+    pub async fn erase(flash: &mut Storage, from: u32, to: u32) -> Result<(), flash::Error> {
+        flash.blocking_erase(from, to)
+    }
+
+    pub async fn write(flash: &mut Storage, offset: u32, bytes: &[u8]) -> Result<(), flash::Error> {
+        flash.blocking_write(offset, bytes)
+    }
+}
+
+#[cfg(feature = "nucleo-f767")]
+pub use nucleo::*;
+
+// usb full speed needs exactly 48 MHz, a clock change that moves it fails the build instead of enumeration
+const _: () = assert!(CLK48_HZ == 48_000_000);
+
+// sensor drivers, the flight boards read their parts over dma so the cpu isn't spinning through the transfers,
+// the nucleo runs the mocks, and the gps is a mock until its driver is written
+#[cfg(all(not(feature = "replay"), any(feature = "board-rev-a", feature = "board-rev-b")))]
+pub type Barometer = Ms5611<SensorI2c, Delay>;
+#[cfg(all(not(feature = "replay"), any(feature = "board-rev-a", feature = "board-rev-b")))]
+pub type Imu = WithMag<Icm42688<ImuSpi, Delay>, Lis3mdl<SensorI2c>>;
+#[cfg(all(not(feature = "replay"), any(feature = "board-rev-a", feature = "board-rev-b")))]
+pub type Battery = Ina219<SensorI2c>;
+#[cfg(all(not(feature = "replay"), feature = "nucleo-f767"))]
+pub type Barometer = MockBarometer;
+#[cfg(all(not(feature = "replay"), feature = "nucleo-f767"))]
+pub type Imu = MockImu;
+#[cfg(not(feature = "replay"))]
+pub type Gps = MockGps;
+#[cfg(any(feature = "replay", feature = "nucleo-f767"))]
+pub type Battery = MockBattery;
+
+// solar charger input, an ina219 on the array side of the charger and the charger's status pins, a mock on the
+// nucleo and when replaying
+#[cfg(all(not(feature = "replay"), any(feature = "board-rev-a", feature = "board-rev-b")))]
+pub type Charger = SolarCharger;
+#[cfg(any(feature = "replay", feature = "nucleo-f767"))]
+pub type Charger = MockCharger;
+
+// shunt between the array and the charger input, ohm
+#[cfg(all(not(feature = "replay"), any(feature = "board-rev-a", feature = "board-rev-b")))]
+const SOLAR_SHUNT_OHMS: f32 = 0.05;
+
+// the charger's open drain charging and done pins, pulled up, low while asserted
+#[cfg(all(not(feature = "replay"), any(feature = "board-rev-a", feature = "board-rev-b")))]
+pub struct SolarCharger {
+    monitor: Ina219<SensorI2c>,
+    charging: Input<'static>,
+    done: Input<'static>,
+}
+
+#[cfg(all(not(feature = "replay"), any(feature = "board-rev-a", feature = "board-rev-b")))]
+impl sensors::Charger for SolarCharger {
+    type Error = ina219::Error;
+
+    async fn read(&mut self) -> Result<ChargeInput, ina219::Error> {
+        Ok(ChargeInput {
+            voltage: self.monitor.bus_voltage().await?,
+            current: self.monitor.current(SOLAR_SHUNT_OHMS).await?,
+            state: ChargeState::from_pins(self.charging.is_low(), self.done.is_low()),
+        })
+    }
+}
+
+// payload camera, an arducam on its own spi bus with the sensor set up over the sensor i2c bus, a mock on the
+// nucleo and when replaying since the replay boards don't set up the i2c bus
+#[cfg(all(not(feature = "replay"), any(feature = "board-rev-a", feature = "board-rev-b")))]
+pub type Camera = ArduCam<ExclusiveDevice<Spi<'static, Async>, Output<'static>, Delay>, SensorI2c, Delay>;
+#[cfg(any(feature = "replay", feature = "nucleo-f767"))]
+pub type Camera = MockCamera;
+
+// continuity sense on the actuation channels, read by adc1 on the flight boards, mocks with every load fitted on the
+// nucleo and when replaying
+#[cfg(all(not(feature = "replay"), any(feature = "board-rev-a", feature = "board-rev-b")))]
+pub type Actuators = AdcActuators;
+#[cfg(any(feature = "replay", feature = "nucleo-f767"))]
+pub type Actuators = MockActuators;
+
+// adc1 on the continuity and current sense lines of the cutdown, pyro 1, and pyro 2 channels, and the gate of each
+// channel's firing switch, all in channel id order
+#[cfg(all(not(feature = "replay"), any(feature = "board-rev-a", feature = "board-rev-b")))]
+pub struct AdcActuators {
+    adc: Adc<'static, ADC1>,
+    sense: [AnyAdcChannel<ADC1>; Channel::ALL.len()],
+    current: [AnyAdcChannel<ADC1>; Channel::ALL.len()],
+    outputs: [Output<'static>; Channel::ALL.len()],
+}
+
+#[cfg(all(not(feature = "replay"), any(feature = "board-rev-a", feature = "board-rev-b")))]
+impl sensors::Actuators for AdcActuators {
+    type Error = ();
+
+    // a conversion takes a few us, not worth an await
+    async fn continuity(&mut self, channel: Channel) -> Result<f32, ()> {
+        let counts = self.adc.blocking_read(&mut self.sense[channel.id() as usize]);
+        Ok(counts as f32 * ADC_VREF / ADC_FULL_SCALE)
+    }
+
+    async fn set_output(&mut self, channel: Channel, on: bool) -> Result<(), ()> {
+        self.outputs[channel.id() as usize].set_level(if on { Level::High } else { Level::Low });
+        Ok(())
+    }
+
+    async fn current(&mut self, channel: Channel) -> Result<f32, ()> {
+        let counts = self.adc.blocking_read(&mut self.current[channel.id() as usize]);
+        Ok(counts as f32 * ADC_VREF / ADC_FULL_SCALE / CURRENT_SENSE_VOLTS_PER_AMP)
+    }
+}
+
+// 12 bit conversions against the 3.3 V supply
+#[cfg(all(not(feature = "replay"), any(feature = "board-rev-a", feature = "board-rev-b")))]
+const ADC_VREF: f32 = 3.3;
+#[cfg(all(not(feature = "replay"), any(feature = "board-rev-a", feature = "board-rev-b")))]
+const ADC_FULL_SCALE: f32 = 4095.0;
+// low side shunt into a current sense amplifier on each channel, 3.3 V is 6.6 A
+#[cfg(all(not(feature = "replay"), any(feature = "board-rev-a", feature = "board-rev-b")))]
+const CURRENT_SENSE_VOLTS_PER_AMP: f32 = 0.5;
+
+// baro, mag, and power monitor share the sensor i2c bus, each driver owns a handle that locks the bus for
+// the length of one transaction so the tasks polling them take turns instead of fighting over the peripheral
+#[cfg(all(not(feature = "replay"), any(feature = "board-rev-a", feature = "board-rev-b")))]
+pub type SensorI2c = I2cDevice<'static, ThreadModeRawMutex, RecoverableI2c>;
+#[cfg(all(not(feature = "replay"), any(feature = "board-rev-a", feature = "board-rev-b")))]
+static SENSOR_I2C: StaticCell<Mutex<ThreadModeRawMutex, RecoverableI2c>> = StaticCell::new();
+#[cfg(all(not(feature = "replay"), any(feature = "board-rev-a", feature = "board-rev-b")))]
+static SENSOR_BUS_FAULT: Signal<ThreadModeRawMutex, u16> = Signal::new(); // failed transfers in a row, the sensor bus needs clearing
+
+// half a clock period of the hand driven bus clear, 100 kHz, standard mode for parts that are out of step
+#[cfg(all(not(feature = "replay"), any(feature = "board-rev-a", feature = "board-rev-b")))]
+const BUS_CLEAR_HALF_PERIOD: Duration = Duration::from_micros(5);
+
+// sensor bus on i2c1, PB8 (scl) and PB9 (sda), dma1 stream 6 (tx) and stream 0 (rx)
+#[cfg(all(not(feature = "replay"), any(feature = "board-rev-a", feature = "board-rev-b")))]
+fn sensor_i2c(
+    i2c1: Peri<'static, I2C1>,
+    scl: Peri<'static, PB8>,
+    sda: Peri<'static, PB9>,
+    tx_dma: Peri<'static, DMA1_CH6>,
+    rx_dma: Peri<'static, DMA1_CH0>,
+) -> I2c<'static, Async, i2c::Master> {
+    let mut config = i2c::Config::default();
+    config.frequency = Hertz(SENSOR_I2C_HZ);
+    I2c::new(i2c1, scl, sda, Irqs, tx_dma, rx_dma, config)
+}
+
+/// The sensor i2c peripheral, counting failed transfers so a stuck bus gets cleared
+/// the peripheral is only missing while the bus is being cleared, which holds the bus lock
+#[cfg(all(not(feature = "replay"), any(feature = "board-rev-a", feature = "board-rev-b")))]
+pub struct RecoverableI2c {
+    i2c: Option<I2c<'static, Async, i2c::Master>>,
+    monitor: BusMonitor,
+}
+
+#[cfg(all(not(feature = "replay"), any(feature = "board-rev-a", feature = "board-rev-b")))]
+impl embedded_hal_async::i2c::ErrorType for RecoverableI2c {
+    type Error = i2c::Error;
+}
+
+#[cfg(all(not(feature = "replay"), any(feature = "board-rev-a", feature = "board-rev-b")))]
+impl embedded_hal_async::i2c::I2c for RecoverableI2c {
+    async fn transaction(&mut self, address: u8, operations: &mut [embedded_hal_async::i2c::Operation<'_>]) -> Result<(), i2c::Error> {
+        let Some(bus) = self.i2c.as_mut() else {
+            return Err(i2c::Error::Bus);
+        };
+        let result = embedded_hal_async::i2c::I2c::transaction(bus, address, operations).await;
+        if let Some(failures) = self.monitor.transfer(result.is_ok()) {
+            SENSOR_BUS_FAULT.signal(failures);
+        }
+        result
+    }
+}
+
+/// Clears the sensor i2c bus when its transfers keep failing
+#[cfg(all(not(feature = "replay"), any(feature = "board-rev-a", feature = "board-rev-b")))]
+pub struct SensorBus {
+    i2c: &'static Mutex<ThreadModeRawMutex, RecoverableI2c>,
+}
+
+#[cfg(all(not(feature = "replay"), any(feature = "board-rev-a", feature = "board-rev-b")))]
+impl SensorBus {
+    /// wait until the bus needs clearing, returns the failed transfers in a row
+    pub async fn fault(&self) -> u16 {
+        SENSOR_BUS_FAULT.wait().await
+    }
+
+    /// clock the bus free by hand and set the peripheral up again, the bus is locked meanwhile
+    /// returns the clocks sent before sda came free and whether the bus is free
+    /// the whole clear is a couple of hundred us, not worth an await
+    pub async fn recover(&self) -> (u8, bool) {
+        let mut bus = self.i2c.lock().await;
+        // the peripheral lets go of the pins when it's dropped, then they are driven as open drain gpio
+        bus.i2c = None;
+
+        // the pins and dma channels were the dropped peripheral's, nothing else holds them
+        let (mut scl, mut sda) = unsafe { (Flex::new(PB8::steal()), Flex::new(PB9::steal())) };
+        scl.set_high();
+        sda.set_high();
+        scl.set_as_input_output(Speed::Low);
+        sda.set_as_input_output(Speed::Low);
+        block_for(BUS_CLEAR_HALF_PERIOD);
+
+        // clock until the part holding sda lets go, at most the rest of a byte and its ack
+        let mut clocks = 0;
+        while sda.is_low() && clocks < CLEAR_CLOCKS {
+            scl.set_low();
+            block_for(BUS_CLEAR_HALF_PERIOD);
+            scl.set_high();
+            block_for(BUS_CLEAR_HALF_PERIOD);
+            clocks += 1;
+        }
+
+        // a stop, sda rising while scl is high, ends whatever transfer the parts think is going on
+        scl.set_low();
+        block_for(BUS_CLEAR_HALF_PERIOD);
+        sda.set_low();
+        block_for(BUS_CLEAR_HALF_PERIOD);
+        scl.set_high();
+        block_for(BUS_CLEAR_HALF_PERIOD);
+        sda.set_high();
+        block_for(BUS_CLEAR_HALF_PERIOD);
+        let released = scl.is_high() && sda.is_high();
+        drop((scl, sda));
+
+        bus.i2c = Some(unsafe { sensor_i2c(I2C1::steal(), PB8::steal(), PB9::steal(), DMA1_CH6::steal(), DMA1_CH0::steal()) });
+        (clocks, released)
+    }
+}
+
+/// No sensor i2c bus on the nucleo or when replaying, nothing to clear
+#[cfg(any(feature = "replay", feature = "nucleo-f767"))]
+pub struct SensorBus;
+
+#[cfg(any(feature = "replay", feature = "nucleo-f767"))]
+impl SensorBus {
+    pub async fn fault(&self) -> u16 {
+        core::future::pending().await
+    }
+
+    pub async fn recover(&self) -> (u8, bool) {
+        (0, true)
+    }
+}
+
+// the primary and secondary imus share spi1, each with its own chip select
+#[cfg(all(not(feature = "replay"), any(feature = "board-rev-a", feature = "board-rev-b")))]
+pub type ImuSpi = SpiDevice<'static, ThreadModeRawMutex, Spi<'static, Async>, Output<'static>>;
+#[cfg(all(not(feature = "replay"), any(feature = "board-rev-a", feature = "board-rev-b")))]
+static IMU_SPI: StaticCell<Mutex<ThreadModeRawMutex, Spi<'static, Async>>> = StaticCell::new();
+
+// with the "replay" feature the flight sensors play back the same recorded flight, the log has no battery data
+#[cfg(feature = "replay")]
+pub type Barometer = ReplayBarometer<'static>;
+#[cfg(feature = "replay")]
+pub type Imu = ReplayImu<'static>;
+#[cfg(feature = "replay")]
+pub type Gps = ReplayGps<'static>;
+
+#[cfg(feature = "replay")]
+static FLIGHT_LOG: &[u8] = include_bytes!(env!("REPLAY_LOG"));
+
+// usb full speed device on the otg_fs port, carries the debug console
+pub type UsbDriver = Driver<'static, USB_OTG_FS>;
+
+// receive fifo shared by the usb out endpoints
+static USB_EP_OUT_BUFFER: StaticCell<[u8; 256]> = StaticCell::new();
+
+// console uart on the umbilical connector
+pub type ConsoleUart = BufferedUart<'static>;
+
+// console uart baud rate
+const CONSOLE_BAUD: u32 = 115_200;
+
+static CONSOLE_TX_BUFFER: StaticCell<[u8; 256]> = StaticCell::new();
+static CONSOLE_RX_BUFFER: StaticCell<[u8; 64]> = StaticCell::new();
+
+// sensor bus clocks, fast mode i2c and well under the imu's 24 MHz spi limit
+#[cfg(all(not(feature = "replay"), any(feature = "board-rev-a", feature = "board-rev-b")))]
+const SENSOR_I2C_HZ: u32 = 400_000;
+#[cfg(all(not(feature = "replay"), any(feature = "board-rev-a", feature = "board-rev-b")))]
+const IMU_SPI_HZ: u32 = 10_000_000;
+// the arducam frame buffer takes up to 8 MHz
+#[cfg(all(not(feature = "replay"), any(feature = "board-rev-a", feature = "board-rev-b")))]
+const CAMERA_SPI_HZ: u32 = 8_000_000;
+
+// sd card, radio, and external flash share the storage spi bus, each device has its own chip select and
+// clock, the bus is locked for a whole transaction so the log and radio tasks never interleave on the wire
+// the radio is high priority, between two sd block writes or busy polls it goes ahead of the card and the flash
+// so a downlink or command ack waits for at most one block instead of a queue of them
+pub type StorageSpi = Arbitrated<SpiDeviceWithConfig<'static, ThreadModeRawMutex, Spi<'static, Async>, Output<'static>>>;
+
+static STORAGE_SPI: StaticCell<Mutex<ThreadModeRawMutex, Spi<'static, Async>>> = StaticCell::new();
+static STORAGE_ARBITER: Arbiter = Arbiter::new(); // radio transactions waiting for the storage bus
+
+// the log goes to an sd card, on the storage bus on the flight boards and on sdmmc1 on the nucleo, and to the external
+// flash on either when there's no card
+#[cfg(any(feature = "board-rev-a", feature = "board-rev-b"))]
+pub type SdCard = sdcard::SdCard<StorageSpi, Delay>;
+#[cfg(any(feature = "board-rev-a", feature = "board-rev-b"))]
+pub type LogDevice = SdCard;
+#[cfg(feature = "nucleo-f767")]
+pub type LogDevice = SdmmcCard;
+pub type DataFlash = SpiNor<StorageSpi, Delay>;
+
+// sd cards have to be identified at 400 kHz or less, after init they take the faster clock, a block is then
+// about 200 us on the wire so the radio is never held up long behind one
+const SD_HZ: u32 = 400_000;
+#[cfg(any(feature = "board-rev-a", feature = "board-rev-b"))]
+const SD_FAST_HZ: u32 = 20_000_000;
+// default speed cards take up to 25 MHz, the sdmmc divides it down from its 48 MHz kernel clock
+#[cfg(feature = "nucleo-f767")]
+const SDMMC_HZ: u32 = 24_000_000;
+// sx1278 radio, 10 MHz max
+const RADIO_HZ: u32 = 8_000_000;
+// spi nor flash, reads and page programs well below its limit
+const DATA_FLASH_HZ: u32 = 20_000_000;
+
+// spi config for a device clock, the bus rounds down to the nearest prescaler
+fn spi_config(frequency: u32) -> spi::Config {
+    let mut config = spi::Config::default();
+    config.frequency = Hertz(frequency);
+    config
+}
+
+/// Enable lines of the payload power rails, high to power the rail
+pub struct Rails {
+    enables: [Output<'static>; Rail::ALL.len()],
+}
+
+impl Rails {
+    pub fn set(&mut self, rail: Rail, on: bool) {
+        self.enables[rail.id() as usize].set_level(if on { Level::High } else { Level::Low });
+    }
+}
+
+// servo frame, a pulse every 20 ms, us
+const SERVO_FRAME: u16 = 20_000;
+
+/// Gimbal roll and pitch servos on tim3 channels 1 and 2
+pub struct Servos {
+    pwm: SimplePwm<'static, TIM3>,
+}
+
+impl Servos {
+    /// drive the roll and pitch servos, pulse widths in us
+    pub fn set(&mut self, pulse_widths: [u16; 2]) {
+        for (channel, width) in [TimerChannel::Ch1, TimerChannel::Ch2].into_iter().zip(pulse_widths) {
+            let mut channel = self.pwm.channel(channel);
+            channel.set_duty_cycle_fraction(width, SERVO_FRAME);
+            channel.enable();
+        }
+    }
+
+    /// stop the pulses, the servos stop holding and draw next to nothing
+    pub fn off(&mut self) {
+        for channel in [TimerChannel::Ch1, TimerChannel::Ch2] {
+            self.pwm.channel(channel).disable();
+        }
+    }
+}
+
+/// Sd card on sdmmc1's 4 bit bus
+#[cfg(feature = "nucleo-f767")]
+pub struct SdmmcCard {
+    sdmmc: Sdmmc<'static, SDMMC1>,
+    // the dma reads a block from here, DataBlock is word aligned where the log queue's blocks may not be
+    buffer: DataBlock,
+}
+
+#[cfg(feature = "nucleo-f767")]
+impl SdmmcCard {
+    pub async fn init(&mut self) -> Result<(), sdmmc::Error> {
+        self.sdmmc.init_sd_card(Hertz(SDMMC_HZ)).await
+    }
+}
+
+// the card programs a block before write_block returns and erases as it writes, the driver has no erase command
+#[cfg(feature = "nucleo-f767")]
+impl LogStorage for SdmmcCard {
+    type Error = sdmmc::Error;
+
+    fn capacity(&self) -> u32 {
+        match self.sdmmc.card() {
+            Ok(SdmmcPeripheral::SdCard(card)) => card.csd.block_count() as u32,
+            _ => 0,
+        }
+    }
+
+    fn erase_size(&self) -> u32 {
+        0
+    }
+
+    async fn write_block(&mut self, address: u32, block: &[u8]) -> Result<(), sdmmc::Error> {
+        self.buffer.0.copy_from_slice(block);
+        self.sdmmc.write_block(address, &self.buffer).await
+    }
+
+    async fn read_block(&mut self, address: u32, block: &mut [u8]) -> Result<(), sdmmc::Error> {
+        self.sdmmc.read_block(address, &mut self.buffer).await?;
+        block.copy_from_slice(&self.buffer.0);
+        Ok(())
+    }
+
+    async fn erase(&mut self, _first: u32, _count: u32) -> Result<(), sdmmc::Error> {
+        Ok(())
+    }
+
+    async fn sync(&mut self) -> Result<(), sdmmc::Error> {
+        Ok(())
+    }
+}
+
+// largest chunk write_update accepts, padded up to the flash write size
+#[cfg(feature = "nucleo-f767")]
+const MAX_RECORD_SIZE: usize = 256;
+
+/// Peripherals main hands out, picked from the board's pin map
+pub struct Board {
+    pub led: Output<'static>,
+    /// battery heater switch, high to heat
+    pub heater: Output<'static>,
+    pub rails: Rails,
+    /// rf switch control line, low for the lower antenna and high for the upper
+    pub rf_switch: Output<'static>,
+    pub servos: Servos,
+    /// primary and secondary of the redundant pair
+    pub barometers: [Barometer; 2],
+    /// primary and secondary of the redundant pair
+    pub imus: [Imu; 2],
+    /// primary imu data ready line, rises when a new sample is in the output registers
+    pub imu_data_ready: ExtiInput<'static>,
+    pub gps: Gps,
+    pub battery: Battery,
+    pub charger: Charger,
+    pub camera: Camera,
+    pub actuators: Actuators,
+    pub sensor_bus: SensorBus,
+    pub log_device: LogDevice,
+    pub radio: StorageSpi,
+    pub data_flash: DataFlash,
+    pub usb: UsbDriver,
+    pub console: ConsoleUart,
+    pub flash: Storage,
+    /// fram for config and calibration, with the fram feature
+    pub settings: SettingsMemory,
+    pub rtc: Peri<'static, RTC>,
+    pub iwdg: Peri<'static, IWDG>,
+}
+
+// take the board's peripherals out of the chip
+pub fn init(p: Peripherals) -> Board {
+    // rev b kept the rev a status led pin
+    #[cfg(any(feature = "board-rev-a", feature = "board-rev-b"))]
+    let led = p.PB7;
+    // LD1, the green user led
+    #[cfg(feature = "nucleo-f767")]
+    let led = p.PB0;
+
+    // battery heater mosfet gate on PE5 next to the firing switch gates, D6 PE9 on the nucleo
+    #[cfg(any(feature = "board-rev-a", feature = "board-rev-b"))]
+    let heater = p.PE5;
+    #[cfg(feature = "nucleo-f767")]
+    let heater = p.PE9;
+
+    // rf switch control on PE10, D4 PF14 on the nucleo
+    #[cfg(any(feature = "board-rev-a", feature = "board-rev-b"))]
+    let rf_switch = p.PE10;
+    #[cfg(feature = "nucleo-f767")]
+    let rf_switch = p.PF14;
+
+    // gimbal servos on tim3, PC6 (roll) and PC7 (pitch) on every board, morpho header pins on the nucleo
+    // tim4 is the time driver
+    let servos = Servos {
+        pwm: SimplePwm::new(
+            p.TIM3,
+            Some(PwmPin::new(p.PC6, OutputType::PushPull)),
+            Some(PwmPin::new(p.PC7, OutputType::PushPull)),
+            None,
+            None,
+            Hertz(1_000_000 / SERVO_FRAME as u32),
+            CountingMode::EdgeAlignedUp,
+        ),
+    };
+
+    // payload rail load switch enables in Rail::ALL order (gnc, radio pa, camera), PE6, PE7, and PE8 on the flight
+    // boards, D5 PE11, D3 PE13, and D2 PF15 on the nucleo, all held off until the sequence brings them up
+    #[cfg(any(feature = "board-rev-a", feature = "board-rev-b"))]
+    let rail_enables: [Peri<'static, AnyPin>; 3] = [p.PE6.into(), p.PE7.into(), p.PE8.into()];
+    #[cfg(feature = "nucleo-f767")]
+    let rail_enables: [Peri<'static, AnyPin>; 3] = [p.PE11.into(), p.PE13.into(), p.PF15.into()];
+
+    #[cfg(any(feature = "board-rev-a", feature = "board-rev-b"))]
+    let flash = Flash::new(p.FLASH, Irqs);
+    #[cfg(feature = "nucleo-f767")]
+    let flash = Flash::new_blocking(p.FLASH);
+
+    // imu int1, pulled down so a board without the imu fitted doesn't see edges from a floating pin
+    #[cfg(any(feature = "board-rev-a", feature = "board-rev-b"))]
+    let imu_data_ready = ExtiInput::new(p.PC4, p.EXTI4, Pull::Down);
+    // imu breakout int1 on the morpho header
+    #[cfg(feature = "nucleo-f767")]
+    let imu_data_ready = ExtiInput::new(p.PF13, p.EXTI13, Pull::Down);
+
+    // every board brings the otg_fs port out on PA11 (D-) and PA12 (D+), vbus isn't wired to the mcu
+    let mut usb_config = usb::Config::default();
+    usb_config.vbus_detection = false;
+    let usb = Driver::new_fs(p.USB_OTG_FS, Irqs, p.PA12, p.PA11, USB_EP_OUT_BUFFER.init([0; 256]), usb_config);
+
+    let mut uart_config = usart::Config::default();
+    uart_config.baudrate = CONSOLE_BAUD;
+    let (tx_buffer, rx_buffer) = (CONSOLE_TX_BUFFER.init([0; 256]), CONSOLE_RX_BUFFER.init([0; 64]));
+    // usart2 on PA2 (tx) and PA3 (rx), brought out on the umbilical connector
+    #[cfg(any(feature = "board-rev-a", feature = "board-rev-b"))]
+    let console = BufferedUart::new(p.USART2, p.PA3, p.PA2, tx_buffer, rx_buffer, Irqs, uart_config);
+    // usart3 on PD8 (tx) and PD9 (rx), the st-link virtual com port
+    #[cfg(feature = "nucleo-f767")]
+    let console = BufferedUart::new(p.USART3, p.PD9, p.PD8, tx_buffer, rx_buffer, Irqs, uart_config);
+
+    #[cfg(all(not(feature = "replay"), any(feature = "board-rev-a", feature = "board-rev-b")))]
+    let (barometers, imus, gps, battery, charger, camera, actuators, sensor_bus, settings) = {
+        let i2c = SENSOR_I2C.init(Mutex::new(RecoverableI2c {
+            i2c: Some(sensor_i2c(p.I2C1, p.PB8, p.PB9, p.DMA1_CH6, p.DMA1_CH0)),
+            monitor: BusMonitor::new(),
+        }));
+
+        // imus on spi1, PA5 (sck), PA7 (mosi), PA6 (miso), PA4 (primary cs), and PA8 (secondary cs), dma2 stream 3 (tx)
+        // and stream 0 (rx), only the primary's int1 is wired
+        let spi = IMU_SPI.init(Mutex::new(Spi::new(p.SPI1, p.PA5, p.PA7, p.PA6, p.DMA2_CH3, p.DMA2_CH0, spi_config(IMU_SPI_HZ))));
+        let cs = |pin: Peri<'static, AnyPin>| Output::new(pin, Level::High, Speed::VeryHigh);
+
+        // there's one magnetometer, both imus read it
+        let imus = [
+            WithMag {
+                imu: Icm42688::new(SpiDevice::new(spi, cs(p.PA4.into())), Delay),
+                mag: Lis3mdl::new(I2cDevice::new(i2c), lis3mdl::ADDRESS),
+            },
+            WithMag {
+                imu: Icm42688::new(SpiDevice::new(spi, cs(p.PA8.into())), Delay),
+                mag: Lis3mdl::new(I2cDevice::new(i2c), lis3mdl::ADDRESS),
+            },
+        ];
+        // a second ms5611 with csb tied high next to the first
+        let barometers = [
+            Ms5611::new(I2cDevice::new(i2c), Delay, ms5611::ADDRESS),
+            Ms5611::new(I2cDevice::new(i2c), Delay, ms5611::ADDRESS_CSB_HIGH),
+        ];
+        let battery = Ina219::new(I2cDevice::new(i2c), ina219::ADDRESS);
+        // the array monitor has a0 high, the charger status pins on PD0 (charging) and PD1 (done)
+        let charger = SolarCharger {
+            monitor: Ina219::new(I2cDevice::new(i2c), ina219::ADDRESS_A0_HIGH),
+            charging: Input::new(p.PD0, Pull::Up),
+            done: Input::new(p.PD1, Pull::Up),
+        };
+
+        // camera frame buffer on spi3, PC10 (sck), PC12 (mosi), PC11 (miso), and PA15 (cs), dma1 stream 5 (tx) and stream 2 (rx)
+        let spi = Spi::new(p.SPI3, p.PC10, p.PC12, p.PC11, p.DMA1_CH5, p.DMA1_CH2, spi_config(CAMERA_SPI_HZ));
+        let cs = Output::new(p.PA15, Level::High, Speed::VeryHigh);
+        // only fails if the cs pin can't be driven, a gpio always can
+        let camera = ArduCam::new(ExclusiveDevice::new(spi, cs, Delay).unwrap(), I2cDevice::new(i2c), Delay, arducam::ADDRESS);
+
+        // continuity sense lines on PC0 (cutdown), PC1 (pyro 1), and PC2 (pyro 2), current sense on PC3, PC5, and PB1,
+        // firing switch gates on PE2, PE3, and PE4, held low from here on so nothing fires at boot
+        let actuators = AdcActuators {
+            adc: Adc::new(p.ADC1),
+            sense: [p.PC0.degrade_adc(), p.PC1.degrade_adc(), p.PC2.degrade_adc()],
+            current: [p.PC3.degrade_adc(), p.PC5.degrade_adc(), p.PB1.degrade_adc()],
+            outputs: [
+                Output::new(p.PE2, Level::Low, Speed::Low),
+                Output::new(p.PE3, Level::Low, Speed::Low),
+                Output::new(p.PE4, Level::Low, Speed::Low),
+            ],
+        };
+        // fram with a0 to a2 low for config and calibration
+        #[cfg(feature = "fram")]
+        let settings = Eeprom::new(I2cDevice::new(i2c), Delay, eeprom::ADDRESS, eeprom::MB85RC256V);
+        #[cfg(not(feature = "fram"))]
+        let settings = SettingsMemory;
+        (barometers, imus, MockGps::default(), battery, charger, camera, actuators, SensorBus { i2c }, settings)
+    };
+    #[cfg(all(not(feature = "replay"), feature = "nucleo-f767"))]
+    let (barometers, imus, gps, battery, charger, camera, actuators, sensor_bus, settings) = (
+        [MockBarometer::default(), MockBarometer::default()],
+        [MockImu::default(), MockImu::default()],
+        MockGps::default(),
+        MockBattery::default(),
+        MockCharger::default(),
+        MockCamera::default(),
+        MockActuators::default(),
+        SensorBus,
+        SettingsMemory,
+    );
+    #[cfg(feature = "replay")]
+    let (barometers, imus, gps, battery, charger, camera, actuators, sensor_bus, settings) = (
+        [ReplayBarometer::new(FLIGHT_LOG), ReplayBarometer::new(FLIGHT_LOG)],
+        [ReplayImu::new(FLIGHT_LOG), ReplayImu::new(FLIGHT_LOG)],
+        ReplayGps::new(FLIGHT_LOG),
+        MockBattery::default(),
+        MockCharger::default(),
+        MockCamera::default(),
+        MockActuators::default(),
+        SensorBus,
+        SettingsMemory,
+    );
+
+    // storage bus on spi2 on the flight boards, PB13 (sck), PB15 (mosi), PB14 (miso), dma1 stream 4 (tx) and stream 3 (rx)
+    // chip selects PB12 (sd card), PB10 (radio), and PB11 (external flash)
+    #[cfg(any(feature = "board-rev-a", feature = "board-rev-b"))]
+    let (storage_spi, sd_cs, radio_cs, data_flash_cs) = (
+        Spi::new(p.SPI2, p.PB13, p.PB15, p.PB14, p.DMA1_CH4, p.DMA1_CH3, spi_config(SD_HZ)),
+        p.PB12,
+        p.PB10,
+        p.PB11,
+    );
+    // spi1 on the arduino header, D13 PA5 (sck), D11 PA7 (mosi), D12 PA6 (miso), dma2 stream 3 (tx) and stream 0 (rx)
+    // chip selects D9 PD15 (radio) and D8 PF12 (external flash)
+    #[cfg(feature = "nucleo-f767")]
+    let (storage_spi, radio_cs, data_flash_cs) = (
+        Spi::new(p.SPI1, p.PA5, p.PA7, p.PA6, p.DMA2_CH3, p.DMA2_CH0, spi_config(SD_HZ)),
+        p.PD15,
+        p.PF12,
+    );
+    let storage_spi = STORAGE_SPI.init(Mutex::new(storage_spi));
+    let cs = |pin: Peri<'static, AnyPin>| Output::new(pin, Level::High, Speed::VeryHigh);
+    let device = |pin: Peri<'static, AnyPin>, frequency, priority| {
+        Arbitrated::new(SpiDeviceWithConfig::new(storage_spi, cs(pin), spi_config(frequency)), priority, &STORAGE_ARBITER)
+    };
+
+    #[cfg(any(feature = "board-rev-a", feature = "board-rev-b"))]
+    let log_device = SdCard::new(device(sd_cs.into(), SD_HZ, BusPriority::Low), Delay);
+    // microsd breakout on the sdmmc1 morpho pins, PC12 (ck), PD2 (cmd), and PC8-PC11 (d0-d3), dma2 stream 6
+    #[cfg(feature = "nucleo-f767")]
+    let log_device = SdmmcCard {
+        sdmmc: Sdmmc::new_4bit(p.SDMMC1, Irqs, p.DMA2_CH6, p.PC12, p.PD2, p.PC8, p.PC9, p.PC10, p.PC11, Default::default()),
+        buffer: DataBlock([0; 512]),
+    };
+
+    Board {
+        led: Output::new(led, Level::High, Speed::Low),
+        heater: Output::new(heater, Level::Low, Speed::Low),
+        rails: Rails { enables: rail_enables.map(|pin| Output::new(pin, Level::Low, Speed::Low)) },
+        rf_switch: Output::new(rf_switch, Level::Low, Speed::Low),
+        servos,
+        barometers,
+        imus,
+        imu_data_ready,
+        gps,
+        battery,
+        charger,
+        camera,
+        actuators,
+        sensor_bus,
+        log_device,
+        radio: device(radio_cs.into(), RADIO_HZ, BusPriority::High),
+        data_flash: SpiNor::new(device(data_flash_cs.into(), DATA_FLASH_HZ, BusPriority::Low), Delay),
+        usb,
+        // only fails on an invalid baud rate
+        console: console.unwrap(),
+        flash,
+        settings,
+        rtc: p.RTC,
+        iwdg: p.IWDG,
+    }
+}
+
+// bring up the log device, the card on the storage bus takes its data clock once init has finished
+#[cfg(any(feature = "board-rev-a", feature = "board-rev-b"))]
+pub async fn log_device_init(device: &mut LogDevice) -> Result<(), <LogDevice as LogStorage>::Error> {
+    device.init().await?;
+    device.spi().device_mut().set_config(spi_config(SD_FAST_HZ));
+    Ok(())
+}
+
+#[cfg(feature = "nucleo-f767")]
+pub async fn log_device_init(device: &mut LogDevice) -> Result<(), <LogDevice as LogStorage>::Error> {
+    device.init().await
+}
+
+// fram regions, (first byte, size), config then the calibration store
+#[cfg(feature = "fram")]
+const FRAM_CONFIG: (u32, u32) = (0x0000, 0x0400);
+#[cfg(feature = "fram")]
+const FRAM_CALIBRATION: (u32, u32) = (0x0400, 0x1000);
+
+/// Where config and calibration are kept besides the internal flash, the fram with the fram feature
+#[cfg(feature = "fram")]
+pub type SettingsMemory = Eeprom<SensorI2c, Delay>;
+
+/// Nothing besides the internal flash, config and calibration get a sector of it each
+#[cfg(not(feature = "fram"))]
+pub struct SettingsMemory;
+
+/// Sector of internal flash a settings store lives in, borrowed from the storage task's flash for each call so
+/// updates go through the same driver
+#[cfg(not(feature = "fram"))]
+pub struct FlashRegion<'a> {
+    flash: &'a mut Storage,
+    offset: u32,
+}
+
+#[cfg(not(feature = "fram"))]
+impl KvFlash for FlashRegion<'_> {
+    type Error = flash::Error;
+
+    fn capacity(&self) -> u32 {
+        FLASH_SECTOR_SIZE
+    }
+
+    fn write_size(&self) -> u32 {
+        WRITE_SIZE as u32
+    }
+
+    async fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), flash::Error> {
+        self.flash.blocking_read(self.offset + offset, bytes)
+    }
+
+    async fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), flash::Error> {
+        write(self.flash, self.offset + offset, bytes).await
+    }
+
+    async fn erase(&mut self) -> Result<(), flash::Error> {
+        erase(self.flash, self.offset, self.offset + FLASH_SECTOR_SIZE).await
+    }
+}
+
+// the config record's region
+#[cfg(not(feature = "fram"))]
+pub fn config_region<'a>(flash: &'a mut Storage, _settings: &'a mut SettingsMemory) -> FlashRegion<'a> {
+    FlashRegion { flash, offset: CONFIG_FLASH_OFFSET }
+}
+
+#[cfg(feature = "fram")]
+pub fn config_region<'a>(_flash: &'a mut Storage, settings: &'a mut SettingsMemory) -> EepromRegion<'a, SensorI2c, Delay> {
+    settings.region(FRAM_CONFIG.0, FRAM_CONFIG.1)
+}
+
+// the calibration store's region
+#[cfg(not(feature = "fram"))]
+pub fn calibration_region<'a>(flash: &'a mut Storage, _settings: &'a mut SettingsMemory) -> FlashRegion<'a> {
+    FlashRegion { flash, offset: CALIBRATION_FLASH_OFFSET }
+}
+
+#[cfg(feature = "fram")]
+pub fn calibration_region<'a>(_flash: &'a mut Storage, settings: &'a mut SettingsMemory) -> EepromRegion<'a, SensorI2c, Delay> {
+    settings.region(FRAM_CALIBRATION.0, FRAM_CALIBRATION.1)
+}
+
+// erase the update slot for a new image, the trailer past the image with it
+#[cfg(feature = "nucleo-f767")]
+pub async fn erase_update_slot(flash: &mut Storage) -> Result<(), flash::Error> {
+    erase(flash, UPDATE_SLOT.0, UPDATE_SLOT.0 + SLOT_SIZE).await
+}
+
+// write received image bytes into the update slot, a short last chunk is padded with erased bytes to the flash write size
+#[cfg(feature = "nucleo-f767")]
+pub async fn write_update(flash: &mut Storage, offset: u32, bytes: &[u8]) -> Result<(), flash::Error> {
+    let mut buf = [0xFFu8; MAX_RECORD_SIZE];
+    let len = bytes.len().div_ceil(WRITE_SIZE) * WRITE_SIZE;
+    if len > MAX_RECORD_SIZE {
+        return Err(flash::Error::Size);
+    }
+    buf[..bytes.len()].copy_from_slice(bytes);
+    write(flash, UPDATE_SLOT.0 + offset, &buf[..len]).await
+}
+
+// mark the update slot as holding a whole image, for the bootloader to install
+#[cfg(feature = "nucleo-f767")]
+pub async fn write_update_trailer(flash: &mut Storage, trailer: SlotTrailer) -> Result<(), flash::Error> {
+    write_update(flash, TRAILER_OFFSET, &trailer.to_bytes()).await
+}
+
+// image in the update slot, for checking it before it is installed
+#[cfg(feature = "nucleo-f767")]
+pub fn update_image(len: u32) -> &'static [u8] {
+    let len = len.min(IMAGE_SLOT_SIZE) as usize;
+    unsafe { core::slice::from_raw_parts((FLASH_BASE + UPDATE_SLOT.0) as *const u8, len) }
+}
+
+// half speed while idle on the pad, the core and ahb drop to half and both apb prescalers a step in the same register
+// write, so the bus clocks don't change and neither does the apb1 timer clock the time driver (tim4) and the servos
+// (tim3) run from, only the apb2 timer clock halves and nothing runs on tim1 or tim8
+// the same dividers on the f4 and f7, with the reduced clock the core is at half speed already and the apb1 prescaler
+// can't drop another step without moving the apb1 timer clock, so it stays as it is
+pub fn set_half_speed(half: bool) {
+    #[cfg(not(feature = "reduced-clock"))]
+    {
+        use embassy_stm32::pac;
+        use embassy_stm32::pac::rcc::vals::{Hpre, Ppre};
+
+        let (hpre, ppre1, ppre2) = if half { (Hpre::DIV2, Ppre::DIV2, Ppre::DIV1) } else { (Hpre::DIV1, Ppre::DIV4, Ppre::DIV2) };
+        pac::RCC.cfgr().modify(|w| {
+            w.set_hpre(hpre);
+            w.set_ppre1(ppre1);
+            w.set_ppre2(ppre2);
+        });
+    }
+    #[cfg(feature = "reduced-clock")]
+    let _ = half;
+}
+
+// the core clock right now, the cycle counter counts at this rate
+pub fn core_hz() -> u32 {
+    use embassy_stm32::pac;
+    use embassy_stm32::pac::rcc::vals::Hpre;
+
+    if pac::RCC.cfgr().read().hpre() == Hpre::DIV2 { SYSCLK_HZ / 2 } else { SYSCLK_HZ }
+}
+
+// exti line of the rtc wakeup timer, the same on the f4 and f7
+const RTC_WAKEUP_LINE: usize = 22;
+
+// sit in stop mode for seconds with the pll and every clock but the rtc's off, woken by the rtc wakeup timer every
+// wake_every seconds to feed the independent watchdog, which keeps running in stop mode and can't be paused on the f4
+// blocks with interrupts off and the other exti lines masked so nothing else ends it early, the uptime clock stands
+// still meanwhile, the pll is running the core again when it returns
+pub fn stop(seconds: u32, wake_every: u32) {
+    use embassy_stm32::pac;
+    use embassy_stm32::pac::exti::regs::Lines;
+    use embassy_stm32::pac::iwdg::vals::Key;
+    use embassy_stm32::pac::pwr::vals::Pdds;
+
+    critical_section::with(|_| {
+        let masked = pac::EXTI.imr(0).read();
+        pac::EXTI.imr(0).write_value(Lines(0));
+        pac::EXTI.rtsr(0).modify(|w| w.set_line(RTC_WAKEUP_LINE, true));
+        pac::EXTI.emr(0).modify(|w| w.set_line(RTC_WAKEUP_LINE, true));
+        // the main regulator off too, it costs a few more us to wake up
+        pac::PWR.cr1().modify(|w| {
+            w.set_pdds(Pdds::STOP_MODE);
+            w.set_lpds(true);
+        });
+        let mut scb = unsafe { cortex_m::Peripherals::steal() }.SCB;
+        scb.set_sleepdeep();
+
+        let mut left = seconds;
+        while left > 0 {
+            let step = left.min(wake_every.max(1));
+            start_wakeup_timer(step);
+            // a wfe returns at once on an event left over from before, clear it first
+            cortex_m::asm::sev();
+            cortex_m::asm::wfe();
+            while !pac::RTC.isr().read().wutf() {
+                cortex_m::asm::wfe();
+            }
+            pac::IWDG.kr().write(|w| w.set_key(Key::RESET));
+            left -= step;
+        }
+
+        scb.clear_sleepdeep();
+        stop_wakeup_timer();
+        pac::EXTI.emr(0).modify(|w| w.set_line(RTC_WAKEUP_LINE, false));
+        pac::EXTI.imr(0).write_value(masked);
+        restart_clocks();
+    });
+}
+
+// start the wakeup timer counting seconds from the 1 Hz calendar clock, its flag and exti line cleared
+fn start_wakeup_timer(seconds: u32) {
+    use embassy_stm32::pac;
+    use embassy_stm32::pac::rtc::vals::Wucksel;
+
+    pac::PWR.cr1().modify(|w| w.set_dbp(true));
+    pac::RTC.wpr().write(|w| w.set_key(0xCA));
+    pac::RTC.wpr().write(|w| w.set_key(0x53));
+    pac::RTC.cr().modify(|w| w.set_wute(false));
+    while !pac::RTC.isr().read().wutwf() {}
+    pac::RTC.wutr().write(|w| w.set_wut((seconds - 1) as u16));
+    pac::RTC.cr().modify(|w| {
+        w.set_wucksel(Wucksel::CLOCK_SPARE);
+        w.set_wutie(true);
+        w.set_wute(true);
+    });
+    pac::RTC.isr().modify(|w| w.set_wutf(false));
+    pac::RTC.wpr().write(|w| w.set_key(0xFF));
+    pac::EXTI.pr(0).write(|w| w.set_line(RTC_WAKEUP_LINE, true));
+}
+
+fn stop_wakeup_timer() {
+    use embassy_stm32::pac;
+
+    pac::RTC.wpr().write(|w| w.set_key(0xCA));
+    pac::RTC.wpr().write(|w| w.set_key(0x53));
+    pac::RTC.cr().modify(|w| {
+        w.set_wutie(false);
+        w.set_wute(false);
+    });
+    pac::RTC.isr().modify(|w| w.set_wutf(false));
+    pac::RTC.wpr().write(|w| w.set_key(0xFF));
+    pac::EXTI.pr(0).write(|w| w.set_line(RTC_WAKEUP_LINE, true));
+}
+
+// stop mode leaves the core on the hsi with the hse and pll off, their settings are kept so they only need turning
+// back on, the f7 also leaves over-drive and has to be put back in it before the pll can run the core at full speed
+fn restart_clocks() {
+    use embassy_stm32::pac;
+    use embassy_stm32::pac::rcc::vals::Sw;
+
+    pac::RCC.cr().modify(|w| w.set_hseon(true));
+    while !pac::RCC.cr().read().hserdy() {}
+    pac::RCC.cr().modify(|w| w.set_pllon(true));
+    while !pac::RCC.cr().read().pllrdy() {}
+    #[cfg(feature = "nucleo-f767")]
+    {
+        pac::PWR.cr1().modify(|w| w.set_oden(true));
+        while !pac::PWR.csr1().read().odrdy() {}
+        pac::PWR.cr1().modify(|w| w.set_odswen(true));
+        while !pac::PWR.csr1().read().odswrdy() {}
+    }
+    pac::RCC.cfgr().modify(|w| w.set_sw(Sw::PLL1_P));
+    while pac::RCC.cfgr().read().sws() != Sw::PLL1_P {}
+}
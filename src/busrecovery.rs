@@ -0,0 +1,67 @@
+// i2c bus fault recovery
+// a part that loses track of a transfer part way through (a brownout or a glitch mid read) can hold sda low waiting
+// for clocks that never come, every transfer on the bus then fails, not just the ones to that part
+// clocking scl until the part lets go of sda and sending a stop frees the bus, the peripheral is then set up from
+// scratch and the parts on the bus set up again, any of them may have seen the garbage
+
+/// failed transfers in a row before the bus is cleared, a single missing part only fails its own transfers and the
+/// others succeed in between, so it never gets this far
+pub const FAILED_TRANSFERS: u16 = 8;
+/// most failed transfers in a row between two clears, a bus that doesn't come back is tried less and less often
+pub const MAX_FAILED_TRANSFERS: u16 = 256;
+/// clocks that finish whatever byte a part is in the middle of, 8 data bits and the ack
+pub const CLEAR_CLOCKS: u8 = 9;
+
+/// Counts failed transfers on a bus and says when to clear it
+pub struct BusMonitor {
+    /// failed transfers in a row
+    failures: u16,
+    /// failed transfers in a row that set off the next clear
+    threshold: u16,
+}
+
+impl Default for BusMonitor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BusMonitor {
+    pub const fn new() -> Self {
+        Self {
+            failures: 0,
+            threshold: FAILED_TRANSFERS,
+        }
+    }
+
+    /// count a transfer, returns the failed transfers in a row when it's time to clear the bus
+    pub fn transfer(&mut self, ok: bool) -> Option<u16> {
+        if ok {
+            self.failures = 0;
+            self.threshold = FAILED_TRANSFERS;
+            return None;
+        }
+
+        self.failures = self.failures.saturating_add(1);
+        if self.failures < self.threshold {
+            return None;
+        }
+        // every clear that doesn't bring the bus back doubles the wait for the next one
+        self.threshold = self.threshold.saturating_mul(2).min(MAX_FAILED_TRANSFERS);
+        Some(core::mem::take(&mut self.failures))
+    }
+}
+
+/// One bus clear
+#[derive(Copy, Clone, PartialEq, Eq, Debug, defmt::Format)]
+pub struct BusRecovery {
+    /// failed transfers in a row that set it off
+    pub failures: u16,
+    /// clocks sent before sda came free, CLEAR_CLOCKS if it never did
+    pub clocks: u8,
+    /// sda and scl both high after the stop, the bus is free
+    pub released: bool,
+    /// clears since boot, this one included
+    pub count: u16,
+    pub time_stamp: u32,
+}
@@ -0,0 +1,669 @@
+use libm::{cbrtf, sqrtf};
+
+use crate::ImuData;
+use crate::command::TempPolyTarget;
+use crate::crc::crc32;
+
+/// identifies a calibration record in storage, "CAL1"
+const MAGIC: u32 = 0x4341_4C31;
+
+/// bumped whenever the serialized layout changes, older records fall back to defaults
+const VERSION: u16 = 4;
+
+/// number of f32 values in the serialized payload
+const PAYLOAD_FLOATS: usize = 3 + 3 + 9 + 3 + 3 + TEMP_POLY_TERMS * 7;
+
+/// number of coefficients in a temperature compensation polynomial
+pub const TEMP_POLY_TERMS: usize = 4;
+
+/// temperature the compensation polynomials are centered on, degC
+const TEMP_POLY_REFERENCE: f32 = 25.0;
+
+/// evaluate a bias-vs-temperature polynomial, c0 + c1 dT + c2 dT^2 + c3 dT^3 with dT from 25 degC
+pub fn temperature_offset(coefficients: &[f32; TEMP_POLY_TERMS], temperature: f32) -> f32 {
+    let dt = temperature - TEMP_POLY_REFERENCE;
+    coefficients.iter().rev().fold(0.0, |acc, c| acc * dt + c)
+}
+
+/// longest calibration part as stored, the temperature polynomials of three axes
+pub const CALIBRATION_PART_LEN: usize = TEMP_POLY_TERMS * 3 * 4;
+
+/// Parts of the calibration kept under their own key in the calibration store, so a change rewrites only its part
+#[derive(Copy, Clone, PartialEq, Eq, Debug, defmt::Format)]
+pub enum CalibrationKey {
+    GyroBias,
+    /// hard iron offset and soft iron matrix
+    Mag,
+    /// offset and scale
+    Accel,
+    BaroTempPoly,
+    GyroTempPoly,
+    AccelTempPoly,
+}
+
+impl CalibrationKey {
+    pub const ALL: [CalibrationKey; 6] = [
+        CalibrationKey::GyroBias,
+        CalibrationKey::Mag,
+        CalibrationKey::Accel,
+        CalibrationKey::BaroTempPoly,
+        CalibrationKey::GyroTempPoly,
+        CalibrationKey::AccelTempPoly,
+    ];
+
+    /// layout version of each part, in ALL order, bump a part's when its layout changes and only that part falls back
+    /// to its default
+    const VERSIONS: [u8; 6] = [1; 6];
+
+    /// key in the store
+    pub fn id(self) -> u8 {
+        self as u8
+    }
+
+    pub fn version(self) -> u8 {
+        Self::VERSIONS[self as usize]
+    }
+}
+
+/// Sensor calibration persisted across power cycles
+#[derive(Copy, Clone, PartialEq)]
+pub struct Calibration {
+    /// gyro zero rate offset subtracted from every sample, rad/s
+    pub gyro_bias: [f32; 3],
+    /// magnetometer hard-iron offset subtracted from every sample
+    pub mag_offset: [f32; 3],
+    /// magnetometer soft-iron correction applied after the offset, row major
+    pub mag_matrix: [[f32; 3]; 3],
+    /// accelerometer zero g offset subtracted from every sample, m/s^2
+    pub accel_offset: [f32; 3],
+    /// accelerometer per axis scale factor applied after the offset
+    pub accel_scale: [f32; 3],
+    /// barometer pressure offset vs temperature, hPa
+    pub baro_temp_poly: [f32; TEMP_POLY_TERMS],
+    /// gyro bias vs temperature per axis, rad/s
+    pub gyro_temp_poly: [[f32; TEMP_POLY_TERMS]; 3],
+    /// accelerometer offset vs temperature per axis, m/s^2
+    pub accel_temp_poly: [[f32; TEMP_POLY_TERMS]; 3],
+}
+
+impl Default for Calibration {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+impl Calibration {
+    pub const DEFAULT: Self = Self {
+        gyro_bias: [0.0; 3],
+        mag_offset: [0.0; 3],
+        mag_matrix: [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]],
+        accel_offset: [0.0; 3],
+        accel_scale: [1.0; 3],
+        baro_temp_poly: [0.0; TEMP_POLY_TERMS],
+        gyro_temp_poly: [[0.0; TEMP_POLY_TERMS]; 3],
+        accel_temp_poly: [[0.0; TEMP_POLY_TERMS]; 3],
+    };
+
+    /// serialized size: magic, version, reserved, payload, crc
+    pub const SIZE: usize = 4 + 2 + 2 + PAYLOAD_FLOATS * 4 + 4;
+
+    /// apply the hard and soft iron correction to a raw mag sample
+    pub fn correct_mag(&self, mag: [f32; 3]) -> [f32; 3] {
+        let centered = [
+            mag[0] - self.mag_offset[0],
+            mag[1] - self.mag_offset[1],
+            mag[2] - self.mag_offset[2],
+        ];
+        self.mag_matrix.map(|row| row[0] * centered[0] + row[1] * centered[1] + row[2] * centered[2])
+    }
+
+    /// remove the temperature dependent pressure offset from a raw baro reading
+    pub fn compensate_baro(&self, pressure: f32, temperature: f32) -> f32 {
+        pressure - temperature_offset(&self.baro_temp_poly, temperature)
+    }
+
+    /// remove temperature dependent gyro bias and accel offset from a raw imu sample
+    pub fn compensate_imu(&self, data: &mut ImuData) {
+        for (g, poly) in data.gyro.iter_mut().zip(&self.gyro_temp_poly) {
+            *g -= temperature_offset(poly, data.temperature);
+        }
+        for (a, poly) in data.acceleration.iter_mut().zip(&self.accel_temp_poly) {
+            *a -= temperature_offset(poly, data.temperature);
+        }
+    }
+
+    /// apply the offset and scale correction to a raw accel sample
+    pub fn correct_accel(&self, accel: [f32; 3]) -> [f32; 3] {
+        [0, 1, 2].map(|axis| (accel[axis] - self.accel_offset[axis]) * self.accel_scale[axis])
+    }
+
+    /// a part as stored, its values as little endian f32s, returns its length
+    pub fn encode_part(&self, key: CalibrationKey, bytes: &mut [u8; CALIBRATION_PART_LEN]) -> usize {
+        let mut calibration = *self;
+        let values = calibration.part(key).into_iter().flatten();
+        let mut len = 0;
+        for (chunk, value) in bytes.chunks_exact_mut(4).zip(values) {
+            chunk.copy_from_slice(&value.to_le_bytes());
+            len += 4;
+        }
+        len
+    }
+
+    /// fill in a part from its stored bytes, false with the part left as it was if the length doesn't match
+    pub fn decode_part(&mut self, key: CalibrationKey, bytes: &[u8]) -> bool {
+        let part = self.part(key);
+        if part.iter().map(|values| values.len() * 4).sum::<usize>() != bytes.len() {
+            return false;
+        }
+        for (value, chunk) in part.into_iter().flatten().zip(bytes.chunks_exact(4)) {
+            *value = f32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+        }
+        true
+    }
+
+    /// the parts that differ from other, compared as stored so a nan doesn't count as a change every time
+    pub fn changed_parts(&self, other: &Self) -> impl Iterator<Item = CalibrationKey> {
+        let (calibration, other) = (*self, *other);
+        CalibrationKey::ALL.into_iter().filter(move |&key| {
+            let (mut a, mut b) = ([0; CALIBRATION_PART_LEN], [0; CALIBRATION_PART_LEN]);
+            calibration.encode_part(key, &mut a);
+            other.encode_part(key, &mut b);
+            a != b
+        })
+    }
+
+    // the values a key holds, mutable so loading fills them in place
+    fn part(&mut self, key: CalibrationKey) -> [&mut [f32]; 2] {
+        match key {
+            CalibrationKey::GyroBias => [&mut self.gyro_bias, &mut []],
+            CalibrationKey::Mag => [&mut self.mag_offset, self.mag_matrix.as_flattened_mut()],
+            CalibrationKey::Accel => [&mut self.accel_offset, &mut self.accel_scale],
+            CalibrationKey::BaroTempPoly => [&mut self.baro_temp_poly, &mut []],
+            CalibrationKey::GyroTempPoly => [self.gyro_temp_poly.as_flattened_mut(), &mut []],
+            CalibrationKey::AccelTempPoly => [self.accel_temp_poly.as_flattened_mut(), &mut []],
+        }
+    }
+
+    /// the whole calibration as one record, the layout kept before the calibration store
+    pub fn to_bytes(&self) -> [u8; Self::SIZE] {
+        let mut payload = [0.0; PAYLOAD_FLOATS];
+        payload[0..3].copy_from_slice(&self.gyro_bias);
+        payload[3..6].copy_from_slice(&self.mag_offset);
+        for (i, row) in self.mag_matrix.iter().enumerate() {
+            payload[6 + i * 3..9 + i * 3].copy_from_slice(row);
+        }
+        payload[15..18].copy_from_slice(&self.accel_offset);
+        payload[18..21].copy_from_slice(&self.accel_scale);
+        let polys = core::iter::once(&self.baro_temp_poly).chain(&self.gyro_temp_poly).chain(&self.accel_temp_poly);
+        for (chunk, poly) in payload[21..].chunks_exact_mut(TEMP_POLY_TERMS).zip(polys) {
+            chunk.copy_from_slice(poly);
+        }
+
+        let mut buf = [0u8; Self::SIZE];
+        buf[0..4].copy_from_slice(&MAGIC.to_le_bytes());
+        buf[4..6].copy_from_slice(&VERSION.to_le_bytes());
+        for (chunk, value) in buf[8..].chunks_exact_mut(4).zip(payload) {
+            chunk.copy_from_slice(&value.to_le_bytes());
+        }
+        let crc = crc32(&buf[..Self::SIZE - 4]);
+        buf[Self::SIZE - 4..].copy_from_slice(&crc.to_le_bytes());
+        buf
+    }
+
+    /// parse a whole calibration record, None if it is blank, corrupt, or from another layout version
+    pub fn from_bytes(buf: &[u8]) -> Option<Self> {
+        if buf.len() < Self::SIZE {
+            return None;
+        }
+        let buf = &buf[..Self::SIZE];
+
+        let crc = u32::from_le_bytes(buf[Self::SIZE - 4..].try_into().ok()?);
+        if crc32(&buf[..Self::SIZE - 4]) != crc {
+            return None;
+        }
+        if u32::from_le_bytes(buf[0..4].try_into().ok()?) != MAGIC
+            || u16::from_le_bytes(buf[4..6].try_into().ok()?) != VERSION
+        {
+            return None;
+        }
+
+        let mut payload = [0.0; PAYLOAD_FLOATS];
+        for (value, chunk) in payload.iter_mut().zip(buf[8..Self::SIZE - 4].chunks_exact(4)) {
+            *value = f32::from_le_bytes(chunk.try_into().ok()?);
+        }
+
+        let mut calibration = Self::DEFAULT;
+        calibration.gyro_bias.copy_from_slice(&payload[0..3]);
+        calibration.mag_offset.copy_from_slice(&payload[3..6]);
+        for (i, row) in calibration.mag_matrix.iter_mut().enumerate() {
+            row.copy_from_slice(&payload[6 + i * 3..9 + i * 3]);
+        }
+        calibration.accel_offset.copy_from_slice(&payload[15..18]);
+        calibration.accel_scale.copy_from_slice(&payload[18..21]);
+        let polys = core::iter::once(&mut calibration.baro_temp_poly)
+            .chain(&mut calibration.gyro_temp_poly)
+            .chain(&mut calibration.accel_temp_poly);
+        for (poly, chunk) in polys.zip(payload[21..].chunks_exact(TEMP_POLY_TERMS)) {
+            poly.copy_from_slice(chunk);
+        }
+        Some(calibration)
+    }
+}
+
+/// Part of the calibration that changed, marked in the log when the new values take effect
+#[derive(Copy, Clone, PartialEq, Eq, Debug, defmt::Format)]
+pub enum CalibrationChange {
+    /// new gyro bias from sitting still on the pad
+    GyroBias,
+    /// hard and soft iron correction from a mag calibration run
+    Mag,
+    /// offset and scale from a six position accel calibration
+    Accel,
+    /// temperature compensation polynomial for a sensor channel
+    TempPoly(TempPolyTarget),
+}
+
+impl CalibrationChange {
+    /// the change as a byte for the log, temperature compensation carries the axis in the low bits
+    pub fn code(self) -> u8 {
+        match self {
+            CalibrationChange::GyroBias => 0x00,
+            CalibrationChange::Mag => 0x01,
+            CalibrationChange::Accel => 0x02,
+            CalibrationChange::TempPoly(TempPolyTarget::Baro) => 0x10,
+            CalibrationChange::TempPoly(TempPolyTarget::Gyro(axis)) => 0x20 | (axis & 0x0F),
+            CalibrationChange::TempPoly(TempPolyTarget::Accel(axis)) => 0x30 | (axis & 0x0F),
+        }
+    }
+
+    /// change from its code, None for unknown codes
+    pub fn from_code(code: u8) -> Option<Self> {
+        let axis = code & 0x0F;
+        match code {
+            0x00 => Some(CalibrationChange::GyroBias),
+            0x01 => Some(CalibrationChange::Mag),
+            0x02 => Some(CalibrationChange::Accel),
+            0x10 => Some(CalibrationChange::TempPoly(TempPolyTarget::Baro)),
+            0x20..=0x22 => Some(CalibrationChange::TempPoly(TempPolyTarget::Gyro(axis))),
+            0x30..=0x32 => Some(CalibrationChange::TempPoly(TempPolyTarget::Accel(axis))),
+            _ => None,
+        }
+    }
+}
+
+/// accel magnitude may differ from 1 g by this much and still count as stationary, m/s^2
+const STATIONARY_ACCEL_TOLERANCE: f32 = 0.3;
+
+/// raw gyro magnitude above this is motion rather than bias, rad/s
+const STATIONARY_GYRO_LIMIT: f32 = 0.1;
+
+/// standard gravity, m/s^2
+const GRAVITY: f32 = 9.80665;
+
+/// Estimates gyro bias by averaging raw gyro readings while the payload sits still
+/// any motion restarts the average so a bumped payload never bakes rotation into the bias
+pub struct GyroBiasEstimator<const N: u32> {
+    sum: [f32; 3],
+    count: u32,
+}
+
+impl<const N: u32> Default for GyroBiasEstimator<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: u32> GyroBiasEstimator<N> {
+    pub const fn new() -> Self {
+        Self { sum: [0.0; 3], count: 0 }
+    }
+
+    /// discard the partial average
+    pub fn reset(&mut self) {
+        self.sum = [0.0; 3];
+        self.count = 0;
+    }
+
+    /// add a raw sample, returns the bias once N consecutive stationary samples have been averaged
+    pub fn update(&mut self, acceleration: [f32; 3], gyro: [f32; 3]) -> Option<[f32; 3]> {
+        let accel = sqrtf(acceleration.iter().map(|a| a * a).sum());
+        let rate = sqrtf(gyro.iter().map(|g| g * g).sum());
+        if (accel - GRAVITY).abs() > STATIONARY_ACCEL_TOLERANCE || rate > STATIONARY_GYRO_LIMIT {
+            self.reset();
+            return None;
+        }
+
+        for (sum, g) in self.sum.iter_mut().zip(gyro) {
+            *sum += g;
+        }
+        self.count += 1;
+
+        if self.count < N {
+            return None;
+        }
+
+        let bias = self.sum.map(|sum| sum / self.count as f32);
+        self.reset();
+        Some(bias)
+    }
+}
+
+/// number of unknowns in the general ellipsoid fit
+const ELLIPSOID_PARAMS: usize = 9;
+
+/// each axis must span at least this fraction of the mean span, otherwise the payload wasn't rotated enough
+const MAG_MIN_COVERAGE: f32 = 0.5;
+
+/// Fits magnetometer hard and soft iron correction from samples taken while the payload is rotated
+/// least squares fit of a general ellipsoid, Ax^2 + By^2 + Cz^2 + 2Dxy + 2Exz + 2Fyz + 2Gx + 2Hy + 2Iz = 1,
+/// accumulated as normal equations so no samples need to be stored
+pub struct MagCalibrator {
+    ata: [[f32; ELLIPSOID_PARAMS]; ELLIPSOID_PARAMS],
+    atb: [f32; ELLIPSOID_PARAMS],
+    min: [f32; 3],
+    max: [f32; 3],
+    count: u32,
+}
+
+impl Default for MagCalibrator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MagCalibrator {
+    pub const fn new() -> Self {
+        Self {
+            ata: [[0.0; ELLIPSOID_PARAMS]; ELLIPSOID_PARAMS],
+            atb: [0.0; ELLIPSOID_PARAMS],
+            min: [f32::MAX; 3],
+            max: [f32::MIN; 3],
+            count: 0,
+        }
+    }
+
+    /// number of samples collected so far
+    pub fn count(&self) -> u32 {
+        self.count
+    }
+
+    /// add a raw (uncorrected) mag sample
+    pub fn add(&mut self, mag: [f32; 3]) {
+        let [x, y, z] = mag;
+        if !(x.is_finite() && y.is_finite() && z.is_finite()) || (x == 0.0 && y == 0.0 && z == 0.0) {
+            return;
+        }
+
+        let d = [x * x, y * y, z * z, 2.0 * x * y, 2.0 * x * z, 2.0 * y * z, 2.0 * x, 2.0 * y, 2.0 * z];
+        for i in 0..ELLIPSOID_PARAMS {
+            for j in 0..ELLIPSOID_PARAMS {
+                self.ata[i][j] += d[i] * d[j];
+            }
+            self.atb[i] += d[i];
+        }
+
+        for ((min, max), m) in self.min.iter_mut().zip(self.max.iter_mut()).zip(mag) {
+            *min = min.min(m);
+            *max = max.max(m);
+        }
+        self.count += 1;
+    }
+
+    /// solve for the offset and soft iron matrix, None if coverage was poor or the fit is not an ellipsoid
+    /// the matrix maps the ellipsoid onto a sphere of the same average radius so field strength is preserved
+    pub fn fit(&self) -> Option<([f32; 3], [[f32; 3]; 3])> {
+        if self.count < ELLIPSOID_PARAMS as u32 * 2 {
+            return None;
+        }
+
+        let span = [0, 1, 2].map(|axis| self.max[axis] - self.min[axis]);
+        let mean_span = (span[0] + span[1] + span[2]) / 3.0;
+        if mean_span <= 0.0 || span.iter().any(|s| *s < MAG_MIN_COVERAGE * mean_span) {
+            return None;
+        }
+
+        let p = solve(self.ata, self.atb)?;
+        let m = [[p[0], p[3], p[4]], [p[3], p[1], p[5]], [p[4], p[5], p[2]]];
+        let v = [p[6], p[7], p[8]];
+
+        // center is where the gradient of the quadratic vanishes, M c = -v
+        let m_inv = invert3(&m)?;
+        let offset = m_inv.map(|row| -(row[0] * v[0] + row[1] * v[1] + row[2] * v[2]));
+
+        // (x - c)^T M (x - c) = 1 + c^T M c, normalize so the right side is 1
+        let mc = m.map(|row| row[0] * offset[0] + row[1] * offset[1] + row[2] * offset[2]);
+        let k = 1.0 + offset[0] * mc[0] + offset[1] * mc[1] + offset[2] * mc[2];
+        if k <= 0.0 {
+            return None;
+        }
+        let m = m.map(|row| row.map(|e| e / k));
+
+        // matrix square root through the eigen decomposition, all axes must be positive for an ellipsoid
+        let (values, vectors) = jacobi_eigen(m);
+        if values.iter().any(|v| *v <= 0.0) {
+            return None;
+        }
+        let roots = values.map(sqrtf);
+
+        // rescale the unit sphere back to the geometric mean radius
+        let radius = 1.0 / cbrtf(roots[0] * roots[1] * roots[2]);
+
+        let mut matrix = [[0.0; 3]; 3];
+        for (i, row) in matrix.iter_mut().enumerate() {
+            for (j, e) in row.iter_mut().enumerate() {
+                *e = (0..3).map(|k| vectors[i][k] * roots[k] * vectors[j][k]).sum::<f32>() * radius;
+            }
+        }
+
+        Some((offset, matrix))
+    }
+}
+
+/// gaussian elimination with partial pivoting
+fn solve<const N: usize>(mut a: [[f32; N]; N], mut b: [f32; N]) -> Option<[f32; N]> {
+    for col in 0..N {
+        let pivot = (col..N).max_by(|&i, &j| a[i][col].abs().total_cmp(&a[j][col].abs()))?;
+        if a[pivot][col] == 0.0 || !a[pivot][col].is_finite() {
+            return None;
+        }
+        a.swap(col, pivot);
+        b.swap(col, pivot);
+
+        let pivot_row = a[col];
+        for row in col + 1..N {
+            let factor = a[row][col] / pivot_row[col];
+            for (e, p) in a[row][col..].iter_mut().zip(&pivot_row[col..]) {
+                *e -= factor * p;
+            }
+            b[row] -= factor * b[col];
+        }
+    }
+
+    let mut x = [0.0; N];
+    for row in (0..N).rev() {
+        let sum: f32 = (row + 1..N).map(|k| a[row][k] * x[k]).sum();
+        x[row] = (b[row] - sum) / a[row][row];
+    }
+    Some(x)
+}
+
+fn invert3(m: &[[f32; 3]; 3]) -> Option<[[f32; 3]; 3]> {
+    let det = m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+        - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+        + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0]);
+    if det == 0.0 || !det.is_finite() {
+        return None;
+    }
+
+    Some([
+        [
+            (m[1][1] * m[2][2] - m[1][2] * m[2][1]) / det,
+            (m[0][2] * m[2][1] - m[0][1] * m[2][2]) / det,
+            (m[0][1] * m[1][2] - m[0][2] * m[1][1]) / det,
+        ],
+        [
+            (m[1][2] * m[2][0] - m[1][0] * m[2][2]) / det,
+            (m[0][0] * m[2][2] - m[0][2] * m[2][0]) / det,
+            (m[0][2] * m[1][0] - m[0][0] * m[1][2]) / det,
+        ],
+        [
+            (m[1][0] * m[2][1] - m[1][1] * m[2][0]) / det,
+            (m[0][1] * m[2][0] - m[0][0] * m[2][1]) / det,
+            (m[0][0] * m[1][1] - m[0][1] * m[1][0]) / det,
+        ],
+    ])
+}
+
+/// eigen decomposition of a symmetric 3x3 matrix by cyclic jacobi rotations
+/// returns the eigenvalues and the eigenvectors as columns
+fn jacobi_eigen(mut a: [[f32; 3]; 3]) -> ([f32; 3], [[f32; 3]; 3]) {
+    let mut v = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+
+    for _ in 0..16 {
+        let off = a[0][1] * a[0][1] + a[0][2] * a[0][2] + a[1][2] * a[1][2];
+        if off < 1.0e-12 {
+            break;
+        }
+
+        for (p, q) in [(0, 1), (0, 2), (1, 2)] {
+            if a[p][q].abs() < 1.0e-12 {
+                continue;
+            }
+
+            // rotation angle that zeroes a[p][q]
+            let theta = (a[q][q] - a[p][p]) / (2.0 * a[p][q]);
+            let t = theta.signum() / (theta.abs() + sqrtf(theta * theta + 1.0));
+            let c = 1.0 / sqrtf(t * t + 1.0);
+            let s = t * c;
+
+            for row in a.iter_mut() {
+                let akp = row[p];
+                let akq = row[q];
+                row[p] = c * akp - s * akq;
+                row[q] = s * akp + c * akq;
+            }
+            let (ap, aq) = (a[p], a[q]);
+            a[p] = core::array::from_fn(|k| c * ap[k] - s * aq[k]);
+            a[q] = core::array::from_fn(|k| s * ap[k] + c * aq[k]);
+            for row in v.iter_mut() {
+                let vp = row[p];
+                let vq = row[q];
+                row[p] = c * vp - s * vq;
+                row[q] = s * vp + c * vq;
+            }
+        }
+    }
+
+    ([a[0][0], a[1][1], a[2][2]], v)
+}
+
+/// names of the six accel calibration orientations, indexed by axis * 2 + (0 for up, 1 for down)
+pub const ACCEL_ORIENTATIONS: [&str; 6] = ["+X up", "-X up", "+Y up", "-Y up", "+Z up", "-Z up"];
+
+/// stationary samples averaged per orientation
+const ACCEL_CAPTURE_SAMPLES: u32 = 20;
+
+/// the dominant axis must carry at least this fraction of the measured gravity to count as aligned
+const ACCEL_ALIGNMENT: f32 = 0.9;
+
+/// uncalibrated accel magnitude may be off from 1 g by this fraction and still count as stationary
+const ACCEL_MAGNITUDE_TOLERANCE: f32 = 0.15;
+
+/// up minus down reading on an axis may be off from 2 g by this fraction before the fit is rejected
+const ACCEL_SPAN_TOLERANCE: f32 = 0.2;
+
+/// Six position accelerometer calibration
+/// the payload is set down still with each axis pointing up and then down, the calibrator detects which
+/// orientation it is in and averages it, so the operator only has to follow the prompts
+pub struct AccelCalibrator {
+    captured: [Option<[f32; 3]>; 6],
+    orientation: Option<usize>,
+    sum: [f32; 3],
+    count: u32,
+}
+
+impl Default for AccelCalibrator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AccelCalibrator {
+    pub const fn new() -> Self {
+        Self {
+            captured: [None; 6],
+            orientation: None,
+            sum: [0.0; 3],
+            count: 0,
+        }
+    }
+
+    /// true once all six orientations have been captured
+    pub fn is_complete(&self) -> bool {
+        self.captured.iter().all(Option::is_some)
+    }
+
+    /// next orientation still needed, for prompting the operator
+    pub fn next_orientation(&self) -> Option<usize> {
+        self.captured.iter().position(Option::is_none)
+    }
+
+    /// add a raw sample, returns the orientation index when one finishes capturing
+    pub fn add(&mut self, acceleration: [f32; 3], gyro: [f32; 3]) -> Option<usize> {
+        let magnitude = sqrtf(acceleration.iter().map(|a| a * a).sum());
+        let rate = sqrtf(gyro.iter().map(|g| g * g).sum());
+
+        let orientation = (0..3)
+            .max_by(|&a, &b| acceleration[a].abs().total_cmp(&acceleration[b].abs()))
+            .filter(|&axis| acceleration[axis].abs() >= ACCEL_ALIGNMENT * magnitude)
+            .map(|axis| axis * 2 + if acceleration[axis] > 0.0 { 0 } else { 1 });
+
+        let stationary = rate < STATIONARY_GYRO_LIMIT
+            && (magnitude - GRAVITY).abs() < ACCEL_MAGNITUDE_TOLERANCE * GRAVITY;
+
+        // restart the average whenever the payload moves or changes orientation
+        if !stationary || orientation.is_none() || orientation != self.orientation {
+            self.orientation = if stationary { orientation } else { None };
+            self.sum = [0.0; 3];
+            self.count = 0;
+            return None;
+        }
+
+        let index = orientation?;
+        if self.captured[index].is_some() {
+            return None;
+        }
+
+        for (sum, a) in self.sum.iter_mut().zip(acceleration) {
+            *sum += a;
+        }
+        self.count += 1;
+
+        if self.count < ACCEL_CAPTURE_SAMPLES {
+            return None;
+        }
+
+        self.captured[index] = Some(self.sum.map(|sum| sum / self.count as f32));
+        Some(index)
+    }
+
+    /// solve per axis offset and scale, None until complete or if an axis span is implausible
+    pub fn fit(&self) -> Option<([f32; 3], [f32; 3])> {
+        let mut offset = [0.0; 3];
+        let mut scale = [1.0; 3];
+
+        for axis in 0..3 {
+            let up = self.captured[axis * 2]?[axis];
+            let down = self.captured[axis * 2 + 1]?[axis];
+            let span = up - down;
+            if (span - 2.0 * GRAVITY).abs() > ACCEL_SPAN_TOLERANCE * 2.0 * GRAVITY {
+                return None;
+            }
+
+            offset[axis] = (up + down) / 2.0;
+            scale[axis] = 2.0 * GRAVITY / span;
+        }
+
+        Some((offset, scale))
+    }
+}
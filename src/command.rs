@@ -0,0 +1,117 @@
+use crate::actuation::Channel;
+use crate::assist::AssistCommand;
+use crate::auth::TAG_LEN;
+use crate::calibration::TEMP_POLY_TERMS;
+use crate::config::{ConfigKey, ConfigValue};
+use crate::history::HistoryRequest;
+use crate::update::UpdateCommand;
+
+/// Sensor channel a temperature compensation polynomial applies to
+#[derive(Copy, Clone, PartialEq, Eq, Debug, defmt::Format)]
+pub enum TempPolyTarget {
+    Baro,
+    /// gyro axis 0..3
+    Gyro(u8),
+    /// accel axis 0..3
+    Accel(u8),
+}
+
+/// Commands accepted from the radio uplink, debug console, and can bus
+#[derive(Copy, Clone, PartialEq, defmt::Format)]
+pub enum Command {
+    /// collect mag samples while the payload is rotated by hand, then fit and store hard/soft iron correction
+    CalibrateMag,
+    /// guided six position accel calibration, the payload is set still on each face in turn
+    CalibrateAccel,
+    /// capture the launch site ground pressure so altitude is reported above ground level
+    Arm,
+    /// drop the launch site reference, altitude is reported above sea level again
+    Disarm,
+    /// arm an actuation channel, refused without continuity through its load unless overridden
+    ArmChannel { channel: Channel, override_continuity: bool },
+    DisarmChannel(Channel),
+    /// fire an armed actuation channel
+    Fire(Channel),
+    /// store characterized bias-vs-temperature coefficients for a sensor channel
+    SetTempPoly {
+        target: TempPolyTarget,
+        coefficients: [f32; TEMP_POLY_TERMS],
+    },
+    /// report a config parameter, the staged value if there are uncommitted changes
+    GetConfig(ConfigKey),
+    /// stage a config parameter change, nothing takes effect until committed
+    SetConfig { key: ConfigKey, value: ConfigValue },
+    /// apply the staged config changes and persist them to flash
+    CommitConfig,
+    /// discard the staged config changes
+    RevertConfig,
+    /// close the log and reboot into the chip's usb dfu bootloader for a reflash, only on the pad and with a tag for the
+    /// challenge the board handed out last, see auth
+    EnterBootloader { tag: [u8; TAG_LEN] },
+    /// firmware image transfer step, only on the pad
+    Update(UpdateCommand),
+    /// gnss assistance upload step for the gps receiver, only on the pad
+    Assist(AssistCommand),
+    /// send logged records of a stream back down, replaces a request still being sent
+    SendHistory(HistoryRequest),
+}
+
+/// Where a command came from, a reply goes back the way the command came
+#[derive(Copy, Clone, PartialEq, Eq, Debug, defmt::Format)]
+pub enum Origin {
+    /// the radio uplink, replies go down as telemetry
+    Uplink,
+    UsbConsole,
+    UartConsole,
+    /// a held command or a mission sequence rule, nobody waits for a reply
+    Onboard,
+}
+
+impl Command {
+    /// the command as a byte for the log, the channel commands carry the channel in the low bits
+    pub fn code(&self) -> u8 {
+        match self {
+            Command::CalibrateMag => 0x00,
+            Command::CalibrateAccel => 0x01,
+            Command::Arm => 0x02,
+            Command::Disarm => 0x03,
+            Command::SetTempPoly { .. } => 0x04,
+            Command::GetConfig(_) => 0x05,
+            Command::SetConfig { .. } => 0x06,
+            Command::CommitConfig => 0x07,
+            Command::RevertConfig => 0x08,
+            Command::EnterBootloader { .. } => 0x09,
+            Command::Update(_) => 0x0A,
+            Command::Assist(_) => 0x0B,
+            Command::SendHistory(_) => 0x0C,
+            Command::ArmChannel { channel, .. } => 0x30 | channel.id(),
+            Command::DisarmChannel(channel) => 0x40 | channel.id(),
+            Command::Fire(channel) => 0x50 | channel.id(),
+        }
+    }
+
+    /// name of the command a code is from and its channel for the channel commands, None for unknown codes
+    pub fn describe(code: u8) -> Option<(&'static str, Option<Channel>)> {
+        let channel = |name| Channel::from_id(code & 0x0F).map(|channel| (name, Some(channel)));
+        let name = match code {
+            0x00 => "calibrate mag",
+            0x01 => "calibrate accel",
+            0x02 => "arm",
+            0x03 => "disarm",
+            0x04 => "set temperature compensation",
+            0x05 => "get config",
+            0x06 => "set config",
+            0x07 => "commit config",
+            0x08 => "revert config",
+            0x09 => "enter bootloader",
+            0x0A => "firmware update",
+            0x0B => "gnss assistance",
+            0x0C => "send history",
+            0x30..=0x3F => return channel("arm channel"),
+            0x40..=0x4F => return channel("disarm channel"),
+            0x50..=0x5F => return channel("fire"),
+            _ => return None,
+        };
+        Some((name, None))
+    }
+}
@@ -0,0 +1,311 @@
+use libm::round;
+
+use crate::mission::FlightState;
+
+/// key frame, absolute position, sent first, every KEY_INTERVAL frames, and whenever a delta won't fit
+/// header (0x40 | key sequence), latitude i24, longitude i24, altitude u16, climb rate i8, status, battery
+pub const KEY_FRAME_LEN: usize = 12;
+/// delta frame, position relative to the last key frame
+/// header (key sequence), latitude delta i16, longitude delta i16, altitude u16, climb rate i8, status, battery
+pub const DELTA_FRAME_LEN: usize = 10;
+
+/// frames from one key frame to the next, a lost key frame costs at most this many positions
+const KEY_INTERVAL: u8 = 8;
+
+// the top bit of the header is always clear, packet ids have it set
+const KEY_FLAG: u8 = 0x40;
+const SEQUENCE_MASK: u8 = 0x3F;
+
+/// latitude and longitude as 24 bit fractions of a half turn, about 1.2 m of latitude and 2.4 m of longitude
+/// at the equator, a delta of i16 units then covers about 39 km north and 78 km east of the key frame
+const LATITUDE_SCALE: f64 = (1 << 23) as f64 / 90.0;
+const LONGITUDE_SCALE: f64 = (1 << 23) as f64 / 180.0;
+const I24_MAX: i32 = (1 << 23) - 1;
+
+/// climb rate step, m/s, i8 covers +-63.5 m/s, the first seconds after burst saturate
+const CLIMB_SCALE: f32 = 2.0;
+/// battery voltage step, V, u8 covers a 3s pack
+const VOLTAGE_SCALE: f32 = 20.0;
+
+const STATUS_STATE: u8 = 0x03;
+const STATUS_DEAD_RECKONED: u8 = 1 << 2;
+const STATUS_DEGRADED: u8 = 1 << 3;
+const STATUS_LOW_VOLTAGE: u8 = 1 << 4;
+const STATUS_FAULT: u8 = 1 << 5;
+
+/// Position, climb rate, and status in one small frame so a slow, long range lora setting carries them
+/// in a single cheap transmission
+#[derive(Copy, Clone, PartialEq, Debug, defmt::Format)]
+pub struct CompactPosition {
+    pub latitude: f64,
+    pub longitude: f64,
+    /// above sea level, m, 0 to 65535
+    pub altitude: f32,
+    /// m/s, positive up
+    pub climb_rate: f32,
+    pub state: FlightState,
+    pub dead_reckoned: bool,
+    pub degraded: bool,
+    pub low_voltage: bool,
+    /// any fault active
+    pub fault: bool,
+    /// battery voltage, V, 0 when unknown
+    pub voltage: f32,
+}
+
+/// Packs positions into key and delta frames
+pub struct PositionEncoder {
+    /// key sequence and key position in 24 bit units
+    key: Option<(u8, i32, i32)>,
+    since_key: u8,
+}
+
+impl Default for PositionEncoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PositionEncoder {
+    pub const fn new() -> Self {
+        Self { key: None, since_key: 0 }
+    }
+
+    /// pack the next position into the start of frame, returns the frame length, None if frame is too short
+    pub fn encode(&mut self, position: &CompactPosition, frame: &mut [u8]) -> Option<usize> {
+        let frame = frame.get_mut(..KEY_FRAME_LEN)?;
+        let (latitude, longitude) = to_units(position.latitude, position.longitude);
+        let tail = tail(position);
+
+        if let Some((sequence, key_latitude, key_longitude)) = self.key
+            && self.since_key < KEY_INTERVAL
+        {
+            let delta_latitude = i16::try_from(latitude - key_latitude);
+            let delta_longitude = i16::try_from(wrap_i24(longitude - key_longitude));
+            if let (Ok(delta_latitude), Ok(delta_longitude)) = (delta_latitude, delta_longitude) {
+                self.since_key += 1;
+                frame[0] = sequence;
+                frame[1..3].copy_from_slice(&delta_latitude.to_le_bytes());
+                frame[3..5].copy_from_slice(&delta_longitude.to_le_bytes());
+                frame[5..DELTA_FRAME_LEN].copy_from_slice(&tail);
+                return Some(DELTA_FRAME_LEN);
+            }
+        }
+
+        let sequence = self.key.map_or(0, |(sequence, _, _)| sequence.wrapping_add(1) & SEQUENCE_MASK);
+        self.key = Some((sequence, latitude, longitude));
+        self.since_key = 0;
+        frame[0] = KEY_FLAG | sequence;
+        frame[1..4].copy_from_slice(&latitude.to_le_bytes()[..3]);
+        frame[4..7].copy_from_slice(&longitude.to_le_bytes()[..3]);
+        frame[7..KEY_FRAME_LEN].copy_from_slice(&tail);
+        Some(KEY_FRAME_LEN)
+    }
+}
+
+// latitude clamped to the poles, longitude wrapped so +-180 meet
+fn to_units(latitude: f64, longitude: f64) -> (i32, i32) {
+    let latitude = (round(latitude * LATITUDE_SCALE) as i32).clamp(-I24_MAX, I24_MAX);
+    let longitude = wrap_i24(round(longitude * LONGITUDE_SCALE) as i32);
+    (latitude, longitude)
+}
+
+// sign extend the low 24 bits
+fn wrap_i24(value: i32) -> i32 {
+    (value << 8) >> 8
+}
+
+// altitude, climb rate, status, and battery, the same in both frames
+fn tail(position: &CompactPosition) -> [u8; 5] {
+    let altitude = position.altitude.clamp(0.0, u16::MAX as f32) as u16;
+    let climb_rate = (position.climb_rate * CLIMB_SCALE).clamp(i8::MIN as f32, i8::MAX as f32) as i8;
+    let voltage = (position.voltage * VOLTAGE_SCALE).clamp(0.0, u8::MAX as f32) as u8;
+
+    let mut status = position.state as u8 & STATUS_STATE;
+    for (flag, set) in [
+        (STATUS_DEAD_RECKONED, position.dead_reckoned),
+        (STATUS_DEGRADED, position.degraded),
+        (STATUS_LOW_VOLTAGE, position.low_voltage),
+        (STATUS_FAULT, position.fault),
+    ] {
+        if set {
+            status |= flag;
+        }
+    }
+
+    let altitude = altitude.to_le_bytes();
+    [altitude[0], altitude[1], climb_rate as u8, status, voltage]
+}
+
+/// Why a frame couldn't be decoded
+#[cfg(feature = "std")]
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum DecodeError {
+    /// not a key or delta frame length
+    Length(usize),
+    /// delta frame against a key frame that wasn't received, positions resume at the next key frame
+    MissingKey(u8),
+}
+
+#[cfg(feature = "std")]
+impl std::fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DecodeError::Length(len) => write!(f, "{len} byte frame is neither a key nor a delta frame"),
+            DecodeError::MissingKey(sequence) => write!(f, "delta frame against key frame {sequence}, which was not received"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for DecodeError {}
+
+/// Ground side of PositionEncoder, unpacks frames in the order they were received
+#[cfg(feature = "std")]
+#[derive(Default)]
+pub struct PositionDecoder {
+    key: Option<(u8, i32, i32)>,
+}
+
+#[cfg(feature = "std")]
+impl PositionDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn decode(&mut self, frame: &[u8]) -> Result<CompactPosition, DecodeError> {
+        let (latitude, longitude, tail) = match frame.len() {
+            KEY_FRAME_LEN if frame[0] & !SEQUENCE_MASK == KEY_FLAG => {
+                let latitude = i24(&frame[1..4]);
+                let longitude = i24(&frame[4..7]);
+                self.key = Some((frame[0] & SEQUENCE_MASK, latitude, longitude));
+                (latitude, longitude, &frame[7..])
+            }
+            DELTA_FRAME_LEN if frame[0] & !SEQUENCE_MASK == 0 => {
+                let (_, key_latitude, key_longitude) =
+                    self.key.filter(|&(sequence, _, _)| sequence == frame[0]).ok_or(DecodeError::MissingKey(frame[0]))?;
+                let delta_latitude = i16::from_le_bytes([frame[1], frame[2]]) as i32;
+                let delta_longitude = i16::from_le_bytes([frame[3], frame[4]]) as i32;
+                (key_latitude + delta_latitude, wrap_i24(key_longitude + delta_longitude), &frame[5..])
+            }
+            len => return Err(DecodeError::Length(len)),
+        };
+
+        let status = tail[3];
+        let state = match status & STATUS_STATE {
+            0 => FlightState::Pad,
+            1 => FlightState::Ascent,
+            2 => FlightState::Descent,
+            _ => FlightState::Landed,
+        };
+        Ok(CompactPosition {
+            latitude: latitude as f64 / LATITUDE_SCALE,
+            longitude: longitude as f64 / LONGITUDE_SCALE,
+            altitude: u16::from_le_bytes([tail[0], tail[1]]) as f32,
+            climb_rate: tail[2] as i8 as f32 / CLIMB_SCALE,
+            state,
+            dead_reckoned: status & STATUS_DEAD_RECKONED != 0,
+            degraded: status & STATUS_DEGRADED != 0,
+            low_voltage: status & STATUS_LOW_VOLTAGE != 0,
+            fault: status & STATUS_FAULT != 0,
+            voltage: tail[4] as f32 / VOLTAGE_SCALE,
+        })
+    }
+}
+
+#[cfg(feature = "std")]
+fn i24(bytes: &[u8]) -> i32 {
+    wrap_i24(i32::from_le_bytes([bytes[0], bytes[1], bytes[2], 0]))
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+
+    fn position(latitude: f64, longitude: f64) -> CompactPosition {
+        CompactPosition {
+            latitude,
+            longitude,
+            altitude: 21_350.0,
+            climb_rate: 5.5,
+            state: FlightState::Ascent,
+            dead_reckoned: false,
+            degraded: true,
+            low_voltage: false,
+            fault: true,
+            voltage: 11.1,
+        }
+    }
+
+    // encode and decode one position, returns the frame length
+    fn round_trip(encoder: &mut PositionEncoder, decoder: &mut PositionDecoder, sent: &CompactPosition) -> usize {
+        let mut frame = [0; KEY_FRAME_LEN];
+        let len = encoder.encode(sent, &mut frame).unwrap();
+        let received = decoder.decode(&frame[..len]).unwrap();
+        assert!((received.latitude - sent.latitude).abs() <= 1.0 / LATITUDE_SCALE);
+        assert!((received.longitude - sent.longitude).abs() <= 1.0 / LONGITUDE_SCALE);
+        assert_eq!(received.altitude, sent.altitude);
+        assert_eq!(received.climb_rate, sent.climb_rate);
+        assert_eq!((received.state, received.dead_reckoned, received.degraded, received.low_voltage, received.fault),
+            (sent.state, sent.dead_reckoned, sent.degraded, sent.low_voltage, sent.fault));
+        assert!((received.voltage - sent.voltage).abs() <= 1.0 / VOLTAGE_SCALE);
+        len
+    }
+
+    #[test]
+    fn key_then_deltas_decode_within_a_step() {
+        let mut encoder = PositionEncoder::new();
+        let mut decoder = PositionDecoder::new();
+        let lens: Vec<usize> = (0..=KEY_INTERVAL)
+            .map(|i| round_trip(&mut encoder, &mut decoder, &position(40.4237 + 0.001 * i as f64, -86.9212 - 0.002 * i as f64)))
+            .collect();
+        assert_eq!(lens[0], KEY_FRAME_LEN);
+        assert!(lens[1..KEY_INTERVAL as usize + 1].iter().all(|&len| len == DELTA_FRAME_LEN));
+
+        // every KEY_INTERVAL frames a key frame again
+        assert_eq!(round_trip(&mut encoder, &mut decoder, &position(40.5, -87.0)), KEY_FRAME_LEN);
+    }
+
+    #[test]
+    fn delta_overflow_forces_a_key_frame() {
+        let mut encoder = PositionEncoder::new();
+        let mut decoder = PositionDecoder::new();
+        assert_eq!(round_trip(&mut encoder, &mut decoder, &position(40.0, -87.0)), KEY_FRAME_LEN);
+        assert_eq!(round_trip(&mut encoder, &mut decoder, &position(40.3, -87.0)), DELTA_FRAME_LEN);
+        // half a degree of latitude is past an i16 of units
+        assert_eq!(round_trip(&mut encoder, &mut decoder, &position(40.5, -87.0)), KEY_FRAME_LEN);
+        assert_eq!(round_trip(&mut encoder, &mut decoder, &position(40.5, -86.0)), KEY_FRAME_LEN);
+        assert_eq!(round_trip(&mut encoder, &mut decoder, &position(40.5, -86.001)), DELTA_FRAME_LEN);
+    }
+
+    #[test]
+    fn longitude_wraps_across_the_antimeridian() {
+        let mut encoder = PositionEncoder::new();
+        let mut decoder = PositionDecoder::new();
+        assert_eq!(round_trip(&mut encoder, &mut decoder, &position(-17.0, 179.9995)), KEY_FRAME_LEN);
+        // a few hundred meters east is a small delta, not the whole way around
+        assert_eq!(round_trip(&mut encoder, &mut decoder, &position(-17.0, -179.9995)), DELTA_FRAME_LEN);
+        assert_eq!(round_trip(&mut encoder, &mut decoder, &position(-17.0, 179.999)), DELTA_FRAME_LEN);
+    }
+
+    #[test]
+    fn deltas_after_a_dropped_key_frame_are_refused() {
+        let mut encoder = PositionEncoder::new();
+        let mut frame = [0; KEY_FRAME_LEN];
+        let mut frames = Vec::new();
+        for latitude in [40.0, 40.001, 41.0, 41.001, 42.0] {
+            let len = encoder.encode(&position(latitude, -87.0), &mut frame).unwrap();
+            frames.push(frame[..len].to_vec());
+        }
+
+        let mut decoder = PositionDecoder::new();
+        // nothing to apply a delta to before the first key frame
+        assert_eq!(decoder.decode(&frames[1]), Err(DecodeError::MissingKey(0)));
+        decoder.decode(&frames[0]).unwrap();
+        decoder.decode(&frames[1]).unwrap();
+        // key frame 1 is lost, its delta doesn't decode against key frame 0
+        assert_eq!(decoder.decode(&frames[3]), Err(DecodeError::MissingKey(1)));
+        assert!((decoder.decode(&frames[4]).unwrap().latitude - 42.0).abs() <= 1.0 / LATITUDE_SCALE);
+        assert_eq!(decoder.decode(&frames[0][..11]), Err(DecodeError::Length(11)));
+    }
+}
@@ -0,0 +1,603 @@
+use crate::crc::crc32;
+
+/// identifies a configuration record in storage, "CFG1"
+const MAGIC: u32 = 0x4346_4731;
+
+/// bumped whenever the serialized layout changes, older records fall back to defaults
+const VERSION: u16 = 10;
+
+/// number of 32 bit words in the serialized payload
+const PAYLOAD_WORDS: usize = 44;
+
+/// Tunable flight parameters persisted across power cycles
+/// DEFAULT is compiled in and used whenever the stored record is missing or corrupt
+#[derive(Copy, Clone, PartialEq)]
+pub struct Config {
+    /// madgwick filter gain
+    pub madgwick_beta: f32,
+    /// mahony proportional and integral gains
+    pub mahony_kp: f32,
+    pub mahony_ki: f32,
+    /// altitude kalman filter, how quickly vertical speed may change (m/s^2) and baro altitude noise (m)
+    pub altitude_accel_sigma: f32,
+    pub altitude_measurement_sigma: f32,
+    /// baro outlier rejection threshold in standard deviations and smallest deviation rejected, hPa
+    pub baro_outlier_k: f32,
+    pub baro_outlier_min_deviation: f32,
+    /// gyro bias change worth writing to flash, rad/s
+    pub gyro_bias_save_threshold: f32,
+    /// largest gyro bias that passes the preflight check, rad/s
+    pub gyro_bias_limit: f32,
+    /// landing prediction, position, and health report downlink periods, s
+    pub prediction_period: u32,
+    pub position_period: u32,
+    pub health_report_period: u32,
+    /// geofence center in deg, radius in m and ceiling above sea level in m, a zero radius or ceiling disables that limit
+    pub geofence_latitude: f32,
+    pub geofence_longitude: f32,
+    pub geofence_radius: f32,
+    pub geofence_ceiling: f32,
+    /// radio carrier frequency in Hz, transmit power in dBm, and air data rate in bit/s
+    pub radio_frequency: u32,
+    pub radio_power: i32,
+    pub radio_data_rate: u32,
+    /// payload camera still period in s, 0 turns the camera off, and every how many stills a thumbnail goes down the
+    /// radio as ssdv, 0 for none
+    pub camera_period: u32,
+    pub thumbnail_every: u32,
+    /// vibration bursts taken after each launch, burst, free fall, and landing, 0 turns vibration capture off
+    pub vibration_bursts: u32,
+    /// deploy::DeployMode, 0 balloon and 1 dual deploy, and the main deploy height above the launch site in m
+    pub deploy_mode: u32,
+    pub main_deploy_altitude: f32,
+    /// battery temperature below which high current loads are held off, deg C
+    pub battery_cold_limit: f32,
+    /// when each payload rail comes on after boot, ms, the shortest first, so the delays set the power up order
+    pub gnc_rail_delay: u32,
+    pub radio_pa_rail_delay: u32,
+    pub camera_rail_delay: u32,
+    /// antenna::AntennaMode, 0 follows the flight state, 1 pins the lower and 2 the upper antenna
+    pub antenna_mode: u32,
+    /// gimbal::GimbalMode, 0 off, 1 holds the camera pointing straight down, and 2 at the sun
+    pub gimbal_mode: u32,
+    /// duty cycled float, s from one wakeup to the next, 0 keeps the payload awake, and how long it stays awake
+    pub duty_cycle_period: u32,
+    pub duty_cycle_awake: u32,
+    /// mission sequence rules, see sequence, what each waits for, the altitude, state, or time for it, and what it does
+    pub rule1_when: u32,
+    pub rule1_at: f32,
+    pub rule1_do: u32,
+    pub rule2_when: u32,
+    pub rule2_at: f32,
+    pub rule2_do: u32,
+    pub rule3_when: u32,
+    pub rule3_at: f32,
+    pub rule3_do: u32,
+    pub rule4_when: u32,
+    pub rule4_at: f32,
+    pub rule4_do: u32,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+impl Config {
+    pub const DEFAULT: Self = Self {
+        madgwick_beta: 0.1,
+        mahony_kp: 1.0,
+        mahony_ki: 0.01,
+        altitude_accel_sigma: 0.5,
+        altitude_measurement_sigma: 3.0,
+        baro_outlier_k: 3.0,
+        baro_outlier_min_deviation: 0.5,
+        gyro_bias_save_threshold: 0.002,
+        gyro_bias_limit: 0.05,
+        prediction_period: 30,
+        position_period: 5,
+        health_report_period: 10,
+        geofence_latitude: 0.0,
+        geofence_longitude: 0.0,
+        geofence_radius: 0.0,
+        geofence_ceiling: 0.0,
+        radio_frequency: 433_000_000,
+        radio_power: 14,
+        radio_data_rate: 9600,
+        camera_period: 60,
+        thumbnail_every: 5,
+        vibration_bursts: 2,
+        deploy_mode: 0,
+        main_deploy_altitude: 300.0,
+        battery_cold_limit: -20.0,
+        gnc_rail_delay: 0,
+        radio_pa_rail_delay: 200,
+        camera_rail_delay: 1000,
+        antenna_mode: 0,
+        gimbal_mode: 0,
+        duty_cycle_period: 0,
+        duty_cycle_awake: 120,
+        rule1_when: 0,
+        rule1_at: 0.0,
+        rule1_do: 0,
+        rule2_when: 0,
+        rule2_at: 0.0,
+        rule2_do: 0,
+        rule3_when: 0,
+        rule3_at: 0.0,
+        rule3_do: 0,
+        rule4_when: 0,
+        rule4_at: 0.0,
+        rule4_do: 0,
+    };
+
+    /// serialized size: magic, version, reserved, payload, crc
+    pub const SIZE: usize = 4 + 2 + 2 + PAYLOAD_WORDS * 4 + 4;
+
+    pub fn to_bytes(&self) -> [u8; Self::SIZE] {
+        let payload: [u32; PAYLOAD_WORDS] = [
+            self.madgwick_beta.to_bits(),
+            self.mahony_kp.to_bits(),
+            self.mahony_ki.to_bits(),
+            self.altitude_accel_sigma.to_bits(),
+            self.altitude_measurement_sigma.to_bits(),
+            self.baro_outlier_k.to_bits(),
+            self.baro_outlier_min_deviation.to_bits(),
+            self.gyro_bias_save_threshold.to_bits(),
+            self.gyro_bias_limit.to_bits(),
+            self.prediction_period,
+            self.position_period,
+            self.health_report_period,
+            self.geofence_latitude.to_bits(),
+            self.geofence_longitude.to_bits(),
+            self.geofence_radius.to_bits(),
+            self.geofence_ceiling.to_bits(),
+            self.radio_frequency,
+            self.radio_power as u32,
+            self.radio_data_rate,
+            self.camera_period,
+            self.thumbnail_every,
+            self.vibration_bursts,
+            self.deploy_mode,
+            self.main_deploy_altitude.to_bits(),
+            self.battery_cold_limit.to_bits(),
+            self.gnc_rail_delay,
+            self.radio_pa_rail_delay,
+            self.camera_rail_delay,
+            self.antenna_mode,
+            self.gimbal_mode,
+            self.duty_cycle_period,
+            self.duty_cycle_awake,
+            self.rule1_when,
+            self.rule1_at.to_bits(),
+            self.rule1_do,
+            self.rule2_when,
+            self.rule2_at.to_bits(),
+            self.rule2_do,
+            self.rule3_when,
+            self.rule3_at.to_bits(),
+            self.rule3_do,
+            self.rule4_when,
+            self.rule4_at.to_bits(),
+            self.rule4_do,
+        ];
+
+        let mut buf = [0u8; Self::SIZE];
+        buf[0..4].copy_from_slice(&MAGIC.to_le_bytes());
+        buf[4..6].copy_from_slice(&VERSION.to_le_bytes());
+        for (chunk, word) in buf[8..].chunks_exact_mut(4).zip(payload) {
+            chunk.copy_from_slice(&word.to_le_bytes());
+        }
+        let crc = crc32(&buf[..Self::SIZE - 4]);
+        buf[Self::SIZE - 4..].copy_from_slice(&crc.to_le_bytes());
+        buf
+    }
+
+    /// parse a stored record, None if it is blank, corrupt, or from another layout version
+    pub fn from_bytes(buf: &[u8]) -> Option<Self> {
+        if buf.len() < Self::SIZE {
+            return None;
+        }
+        let buf = &buf[..Self::SIZE];
+
+        let crc = u32::from_le_bytes(buf[Self::SIZE - 4..].try_into().ok()?);
+        if crc32(&buf[..Self::SIZE - 4]) != crc {
+            return None;
+        }
+        if u32::from_le_bytes(buf[0..4].try_into().ok()?) != MAGIC
+            || u16::from_le_bytes(buf[4..6].try_into().ok()?) != VERSION
+        {
+            return None;
+        }
+
+        let mut payload = [0u32; PAYLOAD_WORDS];
+        for (word, chunk) in payload.iter_mut().zip(buf[8..Self::SIZE - 4].chunks_exact(4)) {
+            *word = u32::from_le_bytes(chunk.try_into().ok()?);
+        }
+
+        Some(Self {
+            madgwick_beta: f32::from_bits(payload[0]),
+            mahony_kp: f32::from_bits(payload[1]),
+            mahony_ki: f32::from_bits(payload[2]),
+            altitude_accel_sigma: f32::from_bits(payload[3]),
+            altitude_measurement_sigma: f32::from_bits(payload[4]),
+            baro_outlier_k: f32::from_bits(payload[5]),
+            baro_outlier_min_deviation: f32::from_bits(payload[6]),
+            gyro_bias_save_threshold: f32::from_bits(payload[7]),
+            gyro_bias_limit: f32::from_bits(payload[8]),
+            prediction_period: payload[9],
+            position_period: payload[10],
+            health_report_period: payload[11],
+            geofence_latitude: f32::from_bits(payload[12]),
+            geofence_longitude: f32::from_bits(payload[13]),
+            geofence_radius: f32::from_bits(payload[14]),
+            geofence_ceiling: f32::from_bits(payload[15]),
+            radio_frequency: payload[16],
+            radio_power: payload[17] as i32,
+            radio_data_rate: payload[18],
+            camera_period: payload[19],
+            thumbnail_every: payload[20],
+            vibration_bursts: payload[21],
+            deploy_mode: payload[22],
+            main_deploy_altitude: f32::from_bits(payload[23]),
+            battery_cold_limit: f32::from_bits(payload[24]),
+            gnc_rail_delay: payload[25],
+            radio_pa_rail_delay: payload[26],
+            camera_rail_delay: payload[27],
+            antenna_mode: payload[28],
+            gimbal_mode: payload[29],
+            duty_cycle_period: payload[30],
+            duty_cycle_awake: payload[31],
+            rule1_when: payload[32],
+            rule1_at: f32::from_bits(payload[33]),
+            rule1_do: payload[34],
+            rule2_when: payload[35],
+            rule2_at: f32::from_bits(payload[36]),
+            rule2_do: payload[37],
+            rule3_when: payload[38],
+            rule3_at: f32::from_bits(payload[39]),
+            rule3_do: payload[40],
+            rule4_when: payload[41],
+            rule4_at: f32::from_bits(payload[42]),
+            rule4_do: payload[43],
+        })
+    }
+}
+
+/// Config parameters addressable by key from the uplink and debug console, in layout order
+#[derive(Copy, Clone, PartialEq, Eq, defmt::Format)]
+#[repr(u8)]
+pub enum ConfigKey {
+    MadgwickBeta,
+    MahonyKp,
+    MahonyKi,
+    AltitudeAccelSigma,
+    AltitudeMeasurementSigma,
+    BaroOutlierK,
+    BaroOutlierMinDeviation,
+    GyroBiasSaveThreshold,
+    GyroBiasLimit,
+    PredictionPeriod,
+    PositionPeriod,
+    HealthReportPeriod,
+    GeofenceLatitude,
+    GeofenceLongitude,
+    GeofenceRadius,
+    GeofenceCeiling,
+    RadioFrequency,
+    RadioPower,
+    RadioDataRate,
+    CameraPeriod,
+    ThumbnailEvery,
+    VibrationBursts,
+    DeployMode,
+    MainDeployAltitude,
+    BatteryColdLimit,
+    GncRailDelay,
+    RadioPaRailDelay,
+    CameraRailDelay,
+    AntennaMode,
+    GimbalMode,
+    DutyCyclePeriod,
+    DutyCycleAwake,
+    Rule1When,
+    Rule1At,
+    Rule1Do,
+    Rule2When,
+    Rule2At,
+    Rule2Do,
+    Rule3When,
+    Rule3At,
+    Rule3Do,
+    Rule4When,
+    Rule4At,
+    Rule4Do,
+}
+
+impl ConfigKey {
+    pub const ALL: [ConfigKey; PAYLOAD_WORDS] = [
+        ConfigKey::MadgwickBeta,
+        ConfigKey::MahonyKp,
+        ConfigKey::MahonyKi,
+        ConfigKey::AltitudeAccelSigma,
+        ConfigKey::AltitudeMeasurementSigma,
+        ConfigKey::BaroOutlierK,
+        ConfigKey::BaroOutlierMinDeviation,
+        ConfigKey::GyroBiasSaveThreshold,
+        ConfigKey::GyroBiasLimit,
+        ConfigKey::PredictionPeriod,
+        ConfigKey::PositionPeriod,
+        ConfigKey::HealthReportPeriod,
+        ConfigKey::GeofenceLatitude,
+        ConfigKey::GeofenceLongitude,
+        ConfigKey::GeofenceRadius,
+        ConfigKey::GeofenceCeiling,
+        ConfigKey::RadioFrequency,
+        ConfigKey::RadioPower,
+        ConfigKey::RadioDataRate,
+        ConfigKey::CameraPeriod,
+        ConfigKey::ThumbnailEvery,
+        ConfigKey::VibrationBursts,
+        ConfigKey::DeployMode,
+        ConfigKey::MainDeployAltitude,
+        ConfigKey::BatteryColdLimit,
+        ConfigKey::GncRailDelay,
+        ConfigKey::RadioPaRailDelay,
+        ConfigKey::CameraRailDelay,
+        ConfigKey::AntennaMode,
+        ConfigKey::GimbalMode,
+        ConfigKey::DutyCyclePeriod,
+        ConfigKey::DutyCycleAwake,
+        ConfigKey::Rule1When,
+        ConfigKey::Rule1At,
+        ConfigKey::Rule1Do,
+        ConfigKey::Rule2When,
+        ConfigKey::Rule2At,
+        ConfigKey::Rule2Do,
+        ConfigKey::Rule3When,
+        ConfigKey::Rule3At,
+        ConfigKey::Rule3Do,
+        ConfigKey::Rule4When,
+        ConfigKey::Rule4At,
+        ConfigKey::Rule4Do,
+    ];
+
+    /// key from its numeric id on the wire
+    pub fn from_id(id: u8) -> Option<Self> {
+        Self::ALL.get(id as usize).copied()
+    }
+
+    pub fn id(self) -> u8 {
+        self as u8
+    }
+
+    /// name used by the debug console
+    pub fn name(self) -> &'static str {
+        match self {
+            ConfigKey::MadgwickBeta => "madgwick_beta",
+            ConfigKey::MahonyKp => "mahony_kp",
+            ConfigKey::MahonyKi => "mahony_ki",
+            ConfigKey::AltitudeAccelSigma => "altitude_accel_sigma",
+            ConfigKey::AltitudeMeasurementSigma => "altitude_measurement_sigma",
+            ConfigKey::BaroOutlierK => "baro_outlier_k",
+            ConfigKey::BaroOutlierMinDeviation => "baro_outlier_min_deviation",
+            ConfigKey::GyroBiasSaveThreshold => "gyro_bias_save_threshold",
+            ConfigKey::GyroBiasLimit => "gyro_bias_limit",
+            ConfigKey::PredictionPeriod => "prediction_period",
+            ConfigKey::PositionPeriod => "position_period",
+            ConfigKey::HealthReportPeriod => "health_report_period",
+            ConfigKey::GeofenceLatitude => "geofence_latitude",
+            ConfigKey::GeofenceLongitude => "geofence_longitude",
+            ConfigKey::GeofenceRadius => "geofence_radius",
+            ConfigKey::GeofenceCeiling => "geofence_ceiling",
+            ConfigKey::RadioFrequency => "radio_frequency",
+            ConfigKey::RadioPower => "radio_power",
+            ConfigKey::RadioDataRate => "radio_data_rate",
+            ConfigKey::CameraPeriod => "camera_period",
+            ConfigKey::ThumbnailEvery => "thumbnail_every",
+            ConfigKey::VibrationBursts => "vibration_bursts",
+            ConfigKey::DeployMode => "deploy_mode",
+            ConfigKey::MainDeployAltitude => "main_deploy_altitude",
+            ConfigKey::BatteryColdLimit => "battery_cold_limit",
+            ConfigKey::GncRailDelay => "gnc_rail_delay",
+            ConfigKey::RadioPaRailDelay => "radio_pa_rail_delay",
+            ConfigKey::CameraRailDelay => "camera_rail_delay",
+            ConfigKey::AntennaMode => "antenna_mode",
+            ConfigKey::GimbalMode => "gimbal_mode",
+            ConfigKey::DutyCyclePeriod => "duty_cycle_period",
+            ConfigKey::DutyCycleAwake => "duty_cycle_awake",
+            ConfigKey::Rule1When => "rule1_when",
+            ConfigKey::Rule1At => "rule1_at",
+            ConfigKey::Rule1Do => "rule1_do",
+            ConfigKey::Rule2When => "rule2_when",
+            ConfigKey::Rule2At => "rule2_at",
+            ConfigKey::Rule2Do => "rule2_do",
+            ConfigKey::Rule3When => "rule3_when",
+            ConfigKey::Rule3At => "rule3_at",
+            ConfigKey::Rule3Do => "rule3_do",
+            ConfigKey::Rule4When => "rule4_when",
+            ConfigKey::Rule4At => "rule4_at",
+            ConfigKey::Rule4Do => "rule4_do",
+        }
+    }
+
+    /// key from its console name
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|key| key.name() == name)
+    }
+
+    /// valid range for the parameter, anything outside it is rejected before it reaches the flight code
+    pub fn range(self) -> (ConfigValue, ConfigValue) {
+        use ConfigValue::{F32, I32, U32};
+        match self {
+            ConfigKey::MadgwickBeta => (F32(0.0), F32(1.0)),
+            ConfigKey::MahonyKp => (F32(0.0), F32(10.0)),
+            ConfigKey::MahonyKi => (F32(0.0), F32(1.0)),
+            ConfigKey::AltitudeAccelSigma => (F32(0.01), F32(10.0)),
+            ConfigKey::AltitudeMeasurementSigma => (F32(0.1), F32(50.0)),
+            ConfigKey::BaroOutlierK => (F32(1.0), F32(10.0)),
+            ConfigKey::BaroOutlierMinDeviation => (F32(0.0), F32(10.0)),
+            ConfigKey::GyroBiasSaveThreshold => (F32(0.0), F32(0.1)),
+            ConfigKey::GyroBiasLimit => (F32(0.0), F32(0.5)),
+            // the position and prediction reports run from a schedule table, which keeps periods under half the us time
+            // stamp wrap
+            ConfigKey::PredictionPeriod | ConfigKey::PositionPeriod => (U32(1), U32(1800)),
+            ConfigKey::HealthReportPeriod => (U32(1), U32(3600)),
+            ConfigKey::GeofenceLatitude => (F32(-90.0), F32(90.0)),
+            ConfigKey::GeofenceLongitude => (F32(-180.0), F32(180.0)),
+            ConfigKey::GeofenceRadius => (F32(0.0), F32(1_000_000.0)),
+            ConfigKey::GeofenceCeiling => (F32(0.0), F32(50_000.0)),
+            ConfigKey::RadioFrequency => (U32(400_000_000), U32(930_000_000)),
+            ConfigKey::RadioPower => (I32(-9), I32(22)),
+            ConfigKey::RadioDataRate => (U32(300), U32(250_000)),
+            ConfigKey::CameraPeriod => (U32(0), U32(3600)),
+            ConfigKey::ThumbnailEvery => (U32(0), U32(1000)),
+            ConfigKey::VibrationBursts => (U32(0), U32(10)),
+            ConfigKey::DeployMode => (U32(0), U32(1)),
+            ConfigKey::MainDeployAltitude => (F32(50.0), F32(3000.0)),
+            ConfigKey::BatteryColdLimit => (F32(-60.0), F32(20.0)),
+            ConfigKey::GncRailDelay | ConfigKey::RadioPaRailDelay | ConfigKey::CameraRailDelay => (U32(0), U32(60_000)),
+            ConfigKey::AntennaMode => (U32(0), U32(2)),
+            ConfigKey::GimbalMode => (U32(0), U32(2)),
+            ConfigKey::DutyCyclePeriod => (U32(0), U32(86_400)),
+            ConfigKey::DutyCycleAwake => (U32(10), U32(3600)),
+            ConfigKey::Rule1When | ConfigKey::Rule2When | ConfigKey::Rule3When | ConfigKey::Rule4When => (U32(0), U32(4)),
+            ConfigKey::Rule1At | ConfigKey::Rule2At | ConfigKey::Rule3At | ConfigKey::Rule4At => (F32(-1000.0), F32(1_000_000.0)),
+            ConfigKey::Rule1Do | ConfigKey::Rule2Do | ConfigKey::Rule3Do | ConfigKey::Rule4Do => (U32(0), U32(0x05FF)),
+        }
+    }
+}
+
+/// A config parameter value, the variant must match the parameter's type
+#[derive(Copy, Clone, PartialEq, Debug, defmt::Format)]
+pub enum ConfigValue {
+    F32(f32),
+    U32(u32),
+    I32(i32),
+}
+
+/// Why a config write was rejected
+#[derive(Copy, Clone, PartialEq, Eq, Debug, defmt::Format)]
+pub enum ConfigError {
+    /// value variant doesn't match the parameter type
+    WrongType,
+    /// value outside the parameter's valid range
+    OutOfRange,
+}
+
+impl Config {
+    pub fn get(&self, key: ConfigKey) -> ConfigValue {
+        use ConfigValue::{F32, I32, U32};
+        match key {
+            ConfigKey::MadgwickBeta => F32(self.madgwick_beta),
+            ConfigKey::MahonyKp => F32(self.mahony_kp),
+            ConfigKey::MahonyKi => F32(self.mahony_ki),
+            ConfigKey::AltitudeAccelSigma => F32(self.altitude_accel_sigma),
+            ConfigKey::AltitudeMeasurementSigma => F32(self.altitude_measurement_sigma),
+            ConfigKey::BaroOutlierK => F32(self.baro_outlier_k),
+            ConfigKey::BaroOutlierMinDeviation => F32(self.baro_outlier_min_deviation),
+            ConfigKey::GyroBiasSaveThreshold => F32(self.gyro_bias_save_threshold),
+            ConfigKey::GyroBiasLimit => F32(self.gyro_bias_limit),
+            ConfigKey::PredictionPeriod => U32(self.prediction_period),
+            ConfigKey::PositionPeriod => U32(self.position_period),
+            ConfigKey::HealthReportPeriod => U32(self.health_report_period),
+            ConfigKey::GeofenceLatitude => F32(self.geofence_latitude),
+            ConfigKey::GeofenceLongitude => F32(self.geofence_longitude),
+            ConfigKey::GeofenceRadius => F32(self.geofence_radius),
+            ConfigKey::GeofenceCeiling => F32(self.geofence_ceiling),
+            ConfigKey::RadioFrequency => U32(self.radio_frequency),
+            ConfigKey::RadioPower => I32(self.radio_power),
+            ConfigKey::RadioDataRate => U32(self.radio_data_rate),
+            ConfigKey::CameraPeriod => U32(self.camera_period),
+            ConfigKey::ThumbnailEvery => U32(self.thumbnail_every),
+            ConfigKey::VibrationBursts => U32(self.vibration_bursts),
+            ConfigKey::DeployMode => U32(self.deploy_mode),
+            ConfigKey::MainDeployAltitude => F32(self.main_deploy_altitude),
+            ConfigKey::BatteryColdLimit => F32(self.battery_cold_limit),
+            ConfigKey::GncRailDelay => U32(self.gnc_rail_delay),
+            ConfigKey::RadioPaRailDelay => U32(self.radio_pa_rail_delay),
+            ConfigKey::CameraRailDelay => U32(self.camera_rail_delay),
+            ConfigKey::AntennaMode => U32(self.antenna_mode),
+            ConfigKey::GimbalMode => U32(self.gimbal_mode),
+            ConfigKey::DutyCyclePeriod => U32(self.duty_cycle_period),
+            ConfigKey::DutyCycleAwake => U32(self.duty_cycle_awake),
+            ConfigKey::Rule1When => U32(self.rule1_when),
+            ConfigKey::Rule1At => F32(self.rule1_at),
+            ConfigKey::Rule1Do => U32(self.rule1_do),
+            ConfigKey::Rule2When => U32(self.rule2_when),
+            ConfigKey::Rule2At => F32(self.rule2_at),
+            ConfigKey::Rule2Do => U32(self.rule2_do),
+            ConfigKey::Rule3When => U32(self.rule3_when),
+            ConfigKey::Rule3At => F32(self.rule3_at),
+            ConfigKey::Rule3Do => U32(self.rule3_do),
+            ConfigKey::Rule4When => U32(self.rule4_when),
+            ConfigKey::Rule4At => F32(self.rule4_at),
+            ConfigKey::Rule4Do => U32(self.rule4_do),
+        }
+    }
+
+    /// validate and store a parameter, returns the previous value
+    pub fn set(&mut self, key: ConfigKey, value: ConfigValue) -> Result<ConfigValue, ConfigError> {
+        let (min, max) = key.range();
+        let in_range = match (value, min, max) {
+            (ConfigValue::F32(v), ConfigValue::F32(min), ConfigValue::F32(max)) => (min..=max).contains(&v),
+            (ConfigValue::U32(v), ConfigValue::U32(min), ConfigValue::U32(max)) => (min..=max).contains(&v),
+            (ConfigValue::I32(v), ConfigValue::I32(min), ConfigValue::I32(max)) => (min..=max).contains(&v),
+            _ => return Err(ConfigError::WrongType),
+        };
+        if !in_range {
+            return Err(ConfigError::OutOfRange);
+        }
+
+        let old = self.get(key);
+        match (key, value) {
+            (ConfigKey::MadgwickBeta, ConfigValue::F32(v)) => self.madgwick_beta = v,
+            (ConfigKey::MahonyKp, ConfigValue::F32(v)) => self.mahony_kp = v,
+            (ConfigKey::MahonyKi, ConfigValue::F32(v)) => self.mahony_ki = v,
+            (ConfigKey::AltitudeAccelSigma, ConfigValue::F32(v)) => self.altitude_accel_sigma = v,
+            (ConfigKey::AltitudeMeasurementSigma, ConfigValue::F32(v)) => self.altitude_measurement_sigma = v,
+            (ConfigKey::BaroOutlierK, ConfigValue::F32(v)) => self.baro_outlier_k = v,
+            (ConfigKey::BaroOutlierMinDeviation, ConfigValue::F32(v)) => self.baro_outlier_min_deviation = v,
+            (ConfigKey::GyroBiasSaveThreshold, ConfigValue::F32(v)) => self.gyro_bias_save_threshold = v,
+            (ConfigKey::GyroBiasLimit, ConfigValue::F32(v)) => self.gyro_bias_limit = v,
+            (ConfigKey::PredictionPeriod, ConfigValue::U32(v)) => self.prediction_period = v,
+            (ConfigKey::PositionPeriod, ConfigValue::U32(v)) => self.position_period = v,
+            (ConfigKey::HealthReportPeriod, ConfigValue::U32(v)) => self.health_report_period = v,
+            (ConfigKey::GeofenceLatitude, ConfigValue::F32(v)) => self.geofence_latitude = v,
+            (ConfigKey::GeofenceLongitude, ConfigValue::F32(v)) => self.geofence_longitude = v,
+            (ConfigKey::GeofenceRadius, ConfigValue::F32(v)) => self.geofence_radius = v,
+            (ConfigKey::GeofenceCeiling, ConfigValue::F32(v)) => self.geofence_ceiling = v,
+            (ConfigKey::RadioFrequency, ConfigValue::U32(v)) => self.radio_frequency = v,
+            (ConfigKey::RadioPower, ConfigValue::I32(v)) => self.radio_power = v,
+            (ConfigKey::RadioDataRate, ConfigValue::U32(v)) => self.radio_data_rate = v,
+            (ConfigKey::CameraPeriod, ConfigValue::U32(v)) => self.camera_period = v,
+            (ConfigKey::ThumbnailEvery, ConfigValue::U32(v)) => self.thumbnail_every = v,
+            (ConfigKey::VibrationBursts, ConfigValue::U32(v)) => self.vibration_bursts = v,
+            (ConfigKey::DeployMode, ConfigValue::U32(v)) => self.deploy_mode = v,
+            (ConfigKey::MainDeployAltitude, ConfigValue::F32(v)) => self.main_deploy_altitude = v,
+            (ConfigKey::BatteryColdLimit, ConfigValue::F32(v)) => self.battery_cold_limit = v,
+            (ConfigKey::GncRailDelay, ConfigValue::U32(v)) => self.gnc_rail_delay = v,
+            (ConfigKey::RadioPaRailDelay, ConfigValue::U32(v)) => self.radio_pa_rail_delay = v,
+            (ConfigKey::CameraRailDelay, ConfigValue::U32(v)) => self.camera_rail_delay = v,
+            (ConfigKey::AntennaMode, ConfigValue::U32(v)) => self.antenna_mode = v,
+            (ConfigKey::GimbalMode, ConfigValue::U32(v)) => self.gimbal_mode = v,
+            (ConfigKey::DutyCyclePeriod, ConfigValue::U32(v)) => self.duty_cycle_period = v,
+            (ConfigKey::DutyCycleAwake, ConfigValue::U32(v)) => self.duty_cycle_awake = v,
+            (ConfigKey::Rule1When, ConfigValue::U32(v)) => self.rule1_when = v,
+            (ConfigKey::Rule1At, ConfigValue::F32(v)) => self.rule1_at = v,
+            (ConfigKey::Rule1Do, ConfigValue::U32(v)) => self.rule1_do = v,
+            (ConfigKey::Rule2When, ConfigValue::U32(v)) => self.rule2_when = v,
+            (ConfigKey::Rule2At, ConfigValue::F32(v)) => self.rule2_at = v,
+            (ConfigKey::Rule2Do, ConfigValue::U32(v)) => self.rule2_do = v,
+            (ConfigKey::Rule3When, ConfigValue::U32(v)) => self.rule3_when = v,
+            (ConfigKey::Rule3At, ConfigValue::F32(v)) => self.rule3_at = v,
+            (ConfigKey::Rule3Do, ConfigValue::U32(v)) => self.rule3_do = v,
+            (ConfigKey::Rule4When, ConfigValue::U32(v)) => self.rule4_when = v,
+            (ConfigKey::Rule4At, ConfigValue::F32(v)) => self.rule4_at = v,
+            (ConfigKey::Rule4Do, ConfigValue::U32(v)) => self.rule4_do = v,
+            _ => return Err(ConfigError::WrongType),
+        }
+        Ok(old)
+    }
+}
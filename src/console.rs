@@ -0,0 +1,465 @@
+use crate::actuation::Channel;
+use crate::assist::{self, AssistCommand};
+use crate::auth;
+use crate::command::Command;
+use crate::config::{ConfigKey, ConfigValue};
+use crate::crc::crc32;
+use crate::flightlog::Stream;
+use crate::history::HistoryRequest;
+use crate::pending::Condition;
+use crate::update::{self, UpdateCommand};
+
+/// longest command line accepted, longer lines are discarded, an assist or update data line with a full chunk fits
+pub const LINE_LEN: usize = 160;
+
+/// most records a log tail prints
+pub const TAIL_RECORDS: usize = 12;
+
+/// Shell usage, printed by the help command
+pub const HELP: &str = "commands:\r\n\
+    \x20 status                     flight state, battery, faults\r\n\
+    \x20 version                    firmware version, git hash, and build features\r\n\
+    \x20 log                        recent fault events, newest first\r\n\
+    \x20 log tail <n> [stream]      last n records written to events (default), gps, baro, or imu, up to 12\r\n\
+    \x20 arm | disarm               capture or drop the launch site reference\r\n\
+    \x20 arm <channel> [override]  arm cutdown, pyro1, or pyro2, override arms it without continuity\r\n\
+    \x20 disarm <channel>          disarm an actuation channel\r\n\
+    \x20 fire <channel>            fire an armed actuation channel\r\n\
+    \x20 calibrate mag|accel        start a calibration run\r\n\
+    \x20 config get <key>           read a parameter\r\n\
+    \x20 config set <key> <value>   stage a parameter change\r\n\
+    \x20 config commit|revert       apply or discard staged changes\r\n\
+    \x20 config keys                list parameter names\r\n\
+    \x20 assist begin <size>        start a gnss assistance upload, on the pad\r\n\
+    \x20 assist data <offset> <hex> assistance bytes, up to 64 per line\r\n\
+    \x20 assist finish              end the upload\r\n\
+    \x20 update begin <size> <crc>  start or resume a firmware image transfer, on the pad\r\n\
+    \x20 update data <offset> <hex> image bytes, 64 per line but the last\r\n\
+    \x20 update finish|abort        check and install the image, or drop the transfer\r\n\
+    \x20 history <stream> <from> <to> [every]  send logged records down, uptime in ms, one in every n\r\n\
+    \x20 at met <h:mm:ss> <command>  hold a command until a mission elapsed time\r\n\
+    \x20 at alt <m | km> <command>   hold a command until an altitude, 28000 or 28km\r\n\
+    \x20 pending                    list the held commands\r\n\
+    \x20 cancel <id> | all          drop a held command\r\n\
+    \x20 bootloader challenge       get a nonce to work out the bootloader tag for\r\n\
+    \x20 bootloader <tag>           close the log and reboot into the usb dfu bootloader\r\n";
+
+/// What a console line asks for, a Command for the control task or a query the console answers itself
+#[derive(Copy, Clone, PartialEq, defmt::Format)]
+pub enum Request {
+    Command(Command),
+    /// flight state, battery voltage, and active faults
+    Status,
+    /// recent fault events
+    LogDump,
+    /// the last records of a binary stream, read back from storage
+    LogTail { stream: Stream, count: u8 },
+    /// hold a command until its condition, for the control task to carry out then
+    Tagged { command: Command, condition: Condition },
+    /// list the held commands
+    Pending,
+    /// drop a held command by id, or all of them
+    Cancel(Option<u8>),
+    /// list the config parameter names
+    ConfigKeys,
+    /// firmware identity
+    Version,
+    /// hand out a nonce for a bootloader tag
+    BootloaderChallenge,
+    Help,
+}
+
+/// Why a console line was rejected
+#[derive(Copy, Clone, PartialEq, Eq, Debug, defmt::Format)]
+pub enum ParseError {
+    UnknownCommand,
+    MissingArgument,
+    UnknownKey,
+    UnknownChannel,
+    UnknownStream,
+    /// value doesn't parse as the parameter's type
+    BadValue,
+}
+
+/// parse one command line, words are separated by whitespace
+pub fn parse(line: &str) -> Result<Request, ParseError> {
+    let mut words = line.split_whitespace();
+    let request = match words.next().ok_or(ParseError::MissingArgument)? {
+        "status" => Request::Status,
+        "log" => match words.next() {
+            Some("tail") => {
+                let count = parse_u32(words.next().ok_or(ParseError::MissingArgument)?)
+                    .filter(|&count| (1..=TAIL_RECORDS as u32).contains(&count))
+                    .ok_or(ParseError::BadValue)?;
+                // the debug stream is text, it has no records to decode
+                let stream = match words.next() {
+                    Some(name) => Stream::from_name(name).filter(|&stream| stream != Stream::Debug).ok_or(ParseError::UnknownStream)?,
+                    None => Stream::Events,
+                };
+                Request::LogTail { stream, count: count as u8 }
+            }
+            Some(_) => return Err(ParseError::UnknownCommand),
+            None => Request::LogDump,
+        },
+        "version" => Request::Version,
+        "help" | "?" => Request::Help,
+        "bootloader" => match words.next().ok_or(ParseError::MissingArgument)? {
+            "challenge" => Request::BootloaderChallenge,
+            tag => Request::Command(Command::EnterBootloader { tag: auth::parse_tag(tag).ok_or(ParseError::BadValue)? }),
+        },
+        "assist" => Request::Command(Command::Assist(parse_assist(&mut words)?)),
+        "update" => Request::Command(Command::Update(parse_update(&mut words)?)),
+        "at" => {
+            let condition = match words.next().ok_or(ParseError::MissingArgument)? {
+                "met" => Condition::Met(parse_met(words.next().ok_or(ParseError::MissingArgument)?).ok_or(ParseError::BadValue)?),
+                "alt" => Condition::Altitude(parse_altitude(words.next().ok_or(ParseError::MissingArgument)?).ok_or(ParseError::BadValue)?),
+                _ => return Err(ParseError::UnknownCommand),
+            };
+            // the rest of the line is the command to hold, the words are slices of line so it starts where the next does
+            let rest = words.next().ok_or(ParseError::MissingArgument)?;
+            let start = rest.as_ptr() as usize - line.as_ptr() as usize;
+            // only commands for the control task wait, not queries or another held command
+            return match parse(&line[start..])? {
+                Request::Command(command) => Ok(Request::Tagged { command, condition }),
+                _ => Err(ParseError::UnknownCommand),
+            };
+        }
+        "pending" => Request::Pending,
+        "cancel" => match words.next().ok_or(ParseError::MissingArgument)? {
+            "all" => Request::Cancel(None),
+            id => Request::Cancel(Some(parse_u32(id).and_then(|id| u8::try_from(id).ok()).ok_or(ParseError::BadValue)?)),
+        },
+        "history" => {
+            let stream = Stream::from_name(words.next().ok_or(ParseError::MissingArgument)?).ok_or(ParseError::UnknownStream)?;
+            let from = parse_u32(words.next().ok_or(ParseError::MissingArgument)?).ok_or(ParseError::BadValue)?;
+            let to = parse_u32(words.next().ok_or(ParseError::MissingArgument)?).ok_or(ParseError::BadValue)?;
+            let every = match words.next() {
+                Some(word) => parse_u32(word).and_then(|every| u16::try_from(every).ok()).ok_or(ParseError::BadValue)?,
+                None => 1,
+            };
+            Request::Command(Command::SendHistory(HistoryRequest { stream, from, to, every }))
+        }
+        "arm" => match words.next() {
+            Some(channel) => {
+                let channel = Channel::from_name(channel).ok_or(ParseError::UnknownChannel)?;
+                let override_continuity = match words.next() {
+                    Some("override") => true,
+                    Some(_) => return Err(ParseError::UnknownCommand),
+                    None => false,
+                };
+                Request::Command(Command::ArmChannel { channel, override_continuity })
+            }
+            None => Request::Command(Command::Arm),
+        },
+        "fire" => {
+            let channel = words.next().ok_or(ParseError::MissingArgument)?;
+            Request::Command(Command::Fire(Channel::from_name(channel).ok_or(ParseError::UnknownChannel)?))
+        }
+        "disarm" => match words.next() {
+            Some(channel) => Request::Command(Command::DisarmChannel(Channel::from_name(channel).ok_or(ParseError::UnknownChannel)?)),
+            None => Request::Command(Command::Disarm),
+        },
+        "calibrate" => match words.next().ok_or(ParseError::MissingArgument)? {
+            "mag" => Request::Command(Command::CalibrateMag),
+            "accel" => Request::Command(Command::CalibrateAccel),
+            _ => return Err(ParseError::UnknownCommand),
+        },
+        "config" => match words.next().ok_or(ParseError::MissingArgument)? {
+            "get" => Request::Command(Command::GetConfig(parse_key(words.next())?)),
+            "set" => {
+                let key = parse_key(words.next())?;
+                let value = parse_value(key, words.next().ok_or(ParseError::MissingArgument)?)?;
+                Request::Command(Command::SetConfig { key, value })
+            }
+            "commit" => Request::Command(Command::CommitConfig),
+            "revert" => Request::Command(Command::RevertConfig),
+            "keys" => Request::ConfigKeys,
+            _ => return Err(ParseError::UnknownCommand),
+        },
+        _ => return Err(ParseError::UnknownCommand),
+    };
+
+    if words.next().is_some() {
+        return Err(ParseError::UnknownCommand);
+    }
+    Ok(request)
+}
+
+fn parse_key(word: Option<&str>) -> Result<ConfigKey, ParseError> {
+    ConfigKey::from_name(word.ok_or(ParseError::MissingArgument)?).ok_or(ParseError::UnknownKey)
+}
+
+// the value is parsed as the parameter's type, range checking is left to Config::set
+fn parse_value(key: ConfigKey, word: &str) -> Result<ConfigValue, ParseError> {
+    let value = match key.range().0 {
+        ConfigValue::F32(_) => word.parse().map(ConfigValue::F32).ok(),
+        ConfigValue::U32(_) => word.parse().map(ConfigValue::U32).ok(),
+        ConfigValue::I32(_) => word.parse().map(ConfigValue::I32).ok(),
+    };
+    value.ok_or(ParseError::BadValue)
+}
+
+// the console link is reliable, so the chunk crc is worked out here rather than typed in
+fn parse_assist<'a>(words: &mut impl Iterator<Item = &'a str>) -> Result<AssistCommand, ParseError> {
+    let step = match words.next().ok_or(ParseError::MissingArgument)? {
+        "begin" => {
+            let size = parse_u32(words.next().ok_or(ParseError::MissingArgument)?).ok_or(ParseError::BadValue)?;
+            AssistCommand::Begin { size }
+        }
+        "data" => {
+            let offset = parse_u32(words.next().ok_or(ParseError::MissingArgument)?).ok_or(ParseError::BadValue)?;
+            let mut data = [0u8; assist::CHUNK_LEN];
+            let len = parse_hex(words.next().ok_or(ParseError::MissingArgument)?, &mut data)?;
+            AssistCommand::Chunk {
+                offset,
+                len: len as u8,
+                data,
+                crc: crc32(&data[..len]),
+            }
+        }
+        "finish" => AssistCommand::Finish,
+        _ => return Err(ParseError::UnknownCommand),
+    };
+    Ok(step)
+}
+
+// same as an assist upload, the image crc is typed in since it is what a resumed transfer is matched on
+fn parse_update<'a>(words: &mut impl Iterator<Item = &'a str>) -> Result<UpdateCommand, ParseError> {
+    let step = match words.next().ok_or(ParseError::MissingArgument)? {
+        "begin" => {
+            let size = parse_u32(words.next().ok_or(ParseError::MissingArgument)?).ok_or(ParseError::BadValue)?;
+            let crc = parse_u32(words.next().ok_or(ParseError::MissingArgument)?).ok_or(ParseError::BadValue)?;
+            UpdateCommand::Begin { size, crc }
+        }
+        "data" => {
+            let offset = parse_u32(words.next().ok_or(ParseError::MissingArgument)?).ok_or(ParseError::BadValue)?;
+            let mut data = [0u8; update::CHUNK_LEN];
+            let len = parse_hex(words.next().ok_or(ParseError::MissingArgument)?, &mut data)?;
+            UpdateCommand::Chunk {
+                offset,
+                len: len as u8,
+                data,
+                crc: crc32(&data[..len]),
+            }
+        }
+        "finish" => UpdateCommand::Finish,
+        "abort" => UpdateCommand::Abort,
+        _ => return Err(ParseError::UnknownCommand),
+    };
+    Ok(step)
+}
+
+// hex digit pairs into the start of data, returns how many bytes there were
+fn parse_hex(word: &str, data: &mut [u8]) -> Result<usize, ParseError> {
+    let hex = word.as_bytes();
+    if !hex.len().is_multiple_of(2) || hex.len() / 2 > data.len() {
+        return Err(ParseError::BadValue);
+    }
+    for (byte, pair) in data.iter_mut().zip(hex.chunks(2)) {
+        let pair = core::str::from_utf8(pair).map_err(|_| ParseError::BadValue)?;
+        *byte = u8::from_str_radix(pair, 16).map_err(|_| ParseError::BadValue)?;
+    }
+    Ok(hex.len() / 2)
+}
+
+// h:mm:ss, m:ss, or seconds, in s
+fn parse_met(word: &str) -> Option<u32> {
+    let mut seconds: u32 = 0;
+    for (index, part) in word.split(':').enumerate() {
+        if index > 2 || part.is_empty() {
+            return None;
+        }
+        seconds = seconds.checked_mul(60)?.checked_add(part.parse().ok()?)?;
+    }
+    Some(seconds)
+}
+
+// m, with an optional m or km unit
+fn parse_altitude(word: &str) -> Option<f32> {
+    let (number, scale) = match word.strip_suffix("km") {
+        Some(km) => (km, 1000.0),
+        None => (word.strip_suffix('m').unwrap_or(word), 1.0),
+    };
+    number.parse::<f32>().ok().filter(|altitude| altitude.is_finite()).map(|altitude| altitude * scale)
+}
+
+// decimal or 0x prefixed hex
+fn parse_u32(word: &str) -> Option<u32> {
+    match word.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+        None => word.parse().ok(),
+    }
+}
+
+/// Collects received bytes into command lines, handling backspace and any mix of cr and lf line endings
+pub struct LineBuffer {
+    buf: [u8; LINE_LEN],
+    len: usize,
+    /// the current line ran past LINE_LEN, it is dropped at the next line ending
+    overflow: bool,
+}
+
+impl Default for LineBuffer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LineBuffer {
+    pub const fn new() -> Self {
+        Self {
+            buf: [0; LINE_LEN],
+            len: 0,
+            overflow: false,
+        }
+    }
+
+    /// add a received byte, returns the line when it completes one
+    /// blank, overlong, and non utf-8 lines are dropped
+    pub fn push(&mut self, byte: u8) -> Option<&str> {
+        match byte {
+            b'\r' | b'\n' => {
+                let len = core::mem::take(&mut self.len);
+                if core::mem::take(&mut self.overflow) || len == 0 {
+                    return None;
+                }
+                core::str::from_utf8(&self.buf[..len]).ok()
+            }
+            // backspace and delete
+            0x08 | 0x7F => {
+                self.len = self.len.saturating_sub(1);
+                None
+            }
+            _ => {
+                if self.len == LINE_LEN {
+                    self.overflow = true;
+                } else {
+                    self.buf[self.len] = byte;
+                    self.len += 1;
+                }
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn command(line: &str) -> Command {
+        match parse(line) {
+            Ok(Request::Command(command)) => command,
+            _ => panic!("{line} isn't a command"),
+        }
+    }
+
+    #[test]
+    fn queries_and_commands_parse() {
+        assert!(parse("status") == Ok(Request::Status));
+        assert!(parse("  help  ") == Ok(Request::Help));
+        assert!(parse("?") == Ok(Request::Help));
+        assert!(parse("log") == Ok(Request::LogDump));
+        assert!(parse("log tail 5") == Ok(Request::LogTail { stream: Stream::Events, count: 5 }));
+        assert!(parse("log tail 12 gps") == Ok(Request::LogTail { stream: Stream::Gps, count: 12 }));
+        assert!(parse("config keys") == Ok(Request::ConfigKeys));
+        assert!(parse("cancel all") == Ok(Request::Cancel(None)));
+        assert!(parse("cancel 0x03") == Ok(Request::Cancel(Some(3))));
+        assert!(parse("bootloader challenge") == Ok(Request::BootloaderChallenge));
+
+        assert!(command("arm") == Command::Arm);
+        assert!(command("arm pyro1 override") == Command::ArmChannel { channel: Channel::Pyro1, override_continuity: true });
+        assert!(command("disarm cutdown") == Command::DisarmChannel(Channel::Cutdown));
+        assert!(command("fire pyro2") == Command::Fire(Channel::Pyro2));
+        assert!(command("config set madgwick_beta 0.05") == Command::SetConfig { key: ConfigKey::MadgwickBeta, value: ConfigValue::F32(0.05) });
+        assert!(command("config set radio_power -3") == Command::SetConfig { key: ConfigKey::RadioPower, value: ConfigValue::I32(-3) });
+        assert!(command("history baro 1000 0x2000 4") == Command::SendHistory(HistoryRequest { stream: Stream::Baro, from: 1000, to: 0x2000, every: 4 }));
+        assert!(command("bootloader 000102030405060708090a0b0c0d0e0f")
+            == Command::EnterBootloader { tag: [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15] });
+    }
+
+    #[test]
+    fn held_commands_take_the_rest_of_the_line() {
+        assert!(parse("at met 1:02:03 fire cutdown")
+            == Ok(Request::Tagged { command: Command::Fire(Channel::Cutdown), condition: Condition::Met(3723) }));
+        assert!(parse("at alt 28km  disarm") == Ok(Request::Tagged { command: Command::Disarm, condition: Condition::Altitude(28_000.0) }));
+        assert!(parse("at alt 500m arm") == Ok(Request::Tagged { command: Command::Arm, condition: Condition::Altitude(500.0) }));
+        // only commands wait, and only once
+        assert_eq!(parse("at met 60 status").err(), Some(ParseError::UnknownCommand));
+        assert_eq!(parse("at met 60 at met 90 arm").err(), Some(ParseError::UnknownCommand));
+        assert_eq!(parse("at met 1:2:3:4 arm").err(), Some(ParseError::BadValue));
+        assert_eq!(parse("at alt high arm").err(), Some(ParseError::BadValue));
+        assert_eq!(parse("at met 60").err(), Some(ParseError::MissingArgument));
+    }
+
+    #[test]
+    fn transfer_chunks_carry_their_bytes_and_crc() {
+        let Command::Update(UpdateCommand::Chunk { offset, len, data, crc }) = command("update data 64 00ff10Ab") else {
+            panic!("not an update chunk");
+        };
+        assert_eq!((offset, len, &data[..4], crc), (64, 4, &[0x00, 0xFF, 0x10, 0xAB][..], crc32(&[0x00, 0xFF, 0x10, 0xAB])));
+        assert!(command("update begin 200000 0xDEADBEEF") == Command::Update(UpdateCommand::Begin { size: 200_000, crc: 0xDEAD_BEEF }));
+        assert!(command("update abort") == Command::Update(UpdateCommand::Abort));
+        assert!(command("assist finish") == Command::Assist(AssistCommand::Finish));
+
+        let full = "ab".repeat(assist::CHUNK_LEN);
+        assert!(parse(&format!("assist data 0 {full}")).is_ok());
+        assert_eq!(parse(&format!("assist data 0 {full}ab")).err(), Some(ParseError::BadValue));
+        assert_eq!(parse("update data 0 abc").err(), Some(ParseError::BadValue));
+        assert_eq!(parse("update data 0 zz").err(), Some(ParseError::BadValue));
+        assert_eq!(parse("update begin 100").err(), Some(ParseError::MissingArgument));
+    }
+
+    #[test]
+    fn bad_lines_say_why() {
+        assert_eq!(parse("").err(), Some(ParseError::MissingArgument));
+        assert_eq!(parse("launch").err(), Some(ParseError::UnknownCommand));
+        assert_eq!(parse("status now").err(), Some(ParseError::UnknownCommand));
+        assert_eq!(parse("fire").err(), Some(ParseError::MissingArgument));
+        assert_eq!(parse("fire pyro3").err(), Some(ParseError::UnknownChannel));
+        assert_eq!(parse("arm pyro1 now").err(), Some(ParseError::UnknownCommand));
+        assert_eq!(parse("config get").err(), Some(ParseError::MissingArgument));
+        assert_eq!(parse("config get beta").err(), Some(ParseError::UnknownKey));
+        assert_eq!(parse("config set madgwick_beta fast").err(), Some(ParseError::BadValue));
+        assert_eq!(parse("log tail 13").err(), Some(ParseError::BadValue));
+        assert_eq!(parse("log tail 0").err(), Some(ParseError::BadValue));
+        assert_eq!(parse("log tail 3 debug").err(), Some(ParseError::UnknownStream));
+        assert_eq!(parse("history wind 0 1").err(), Some(ParseError::UnknownStream));
+        assert_eq!(parse("history gps 0 1 70000").err(), Some(ParseError::BadValue));
+        assert_eq!(parse("cancel 256").err(), Some(ParseError::BadValue));
+        assert_eq!(parse("bootloader 0011").err(), Some(ParseError::BadValue));
+    }
+
+    // the lines a run of bytes completes
+    fn lines(buffer: &mut LineBuffer, bytes: &[u8]) -> Vec<String> {
+        bytes.iter().filter_map(|&byte| buffer.push(byte).map(String::from)).collect()
+    }
+
+    #[test]
+    fn line_buffer_splits_on_any_line_ending() {
+        let mut buffer = LineBuffer::new();
+        assert_eq!(lines(&mut buffer, b"status\r\nlog\rversion\n\r\n"), ["status", "log", "version"]);
+    }
+
+    #[test]
+    fn line_buffer_backspace_and_delete_erase() {
+        let mut buffer = LineBuffer::new();
+        assert_eq!(lines(&mut buffer, b"stat\x08\x08atus\r"), ["status"]);
+        // erasing past the start of the line does nothing
+        assert_eq!(lines(&mut buffer, b"x\x7f\x7f\x7farm\n"), ["arm"]);
+    }
+
+    #[test]
+    fn line_buffer_drops_overlong_and_invalid_lines() {
+        let mut buffer = LineBuffer::new();
+        let mut long = vec![b'a'; LINE_LEN];
+        assert_eq!(lines(&mut buffer, &long), Vec::<String>::new());
+        assert_eq!(lines(&mut buffer, b"\n"), [String::from_utf8(long.clone()).unwrap()]);
+        long.push(b'a');
+        long.push(b'\r');
+        // the line after an overlong one is whole again
+        long.extend(b"status\r");
+        assert_eq!(lines(&mut buffer, &long), ["status"]);
+        assert_eq!(lines(&mut buffer, b"\xff\xfe\rhelp\r"), ["help"]);
+    }
+}
@@ -0,0 +1,158 @@
+// flight control, what the control task does with each vertical state, here so it runs on the host
+// the flight state machine, the highest altitude since launch and in dual deploy mode the drogue and main channels
+// the task carries out what comes back, the events, the resume record and the fire requests
+
+use crate::VerticalState;
+use crate::actuation::{Channel, ChannelFlags};
+use crate::deploy::{DeployMode, DualDeploy};
+use crate::mission::{FlightState, Mission};
+
+/// What one vertical state changed
+#[derive(Copy, Clone, PartialEq, Debug, Default)]
+pub struct ControlUpdate {
+    /// (previous, new) flight state when it changed
+    pub transition: Option<(FlightState, FlightState)>,
+    /// the deploy channel to fire now
+    pub fire: Option<Channel>,
+}
+
+/// The flight state machine and what hangs off it
+pub struct FlightControl {
+    mission: Mission,
+    /// highest filtered altitude since launch, m
+    max_altitude: Option<f32>,
+    /// only in dual deploy mode
+    deploy: Option<DualDeploy>,
+}
+
+impl FlightControl {
+    /// pick up in the flight state saved before a reset, main_altitude is the main deploy height above the launch site,
+    /// fired the channels fired before the reset
+    pub fn new(state: FlightState, mode: DeployMode, main_altitude: f32, fired: ChannelFlags) -> Self {
+        Self {
+            mission: Mission::resume(state),
+            max_altitude: None,
+            deploy: (mode == DeployMode::DualDeploy).then(|| DualDeploy::new(main_altitude, fired)),
+        }
+    }
+
+    pub fn state(&self) -> FlightState {
+        self.mission.state()
+    }
+
+    /// highest filtered altitude since launch, the burst altitude once descending, None on the pad
+    pub fn max_altitude(&self) -> Option<f32> {
+        self.max_altitude
+    }
+
+    /// feed a new vertical state, ground_altitude is the launch site's from the arm reference, without it there's no
+    /// main deploy height to fire at
+    pub fn update(&mut self, state: &VerticalState, ground_altitude: Option<f32>) -> ControlUpdate {
+        if self.mission.state() != FlightState::Pad {
+            self.max_altitude = Some(self.max_altitude.map_or(state.altitude, |max| max.max(state.altitude)));
+        }
+
+        let previous = self.mission.state();
+        let transition = self.mission.update(state).map(|next| (previous, next));
+
+        let height = ground_altitude.map_or(f32::NAN, |ground| state.altitude - ground);
+        let fire = self.deploy.as_mut().and_then(|deploy| deploy.update(self.mission.state(), height));
+        ControlUpdate { transition, fire }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::deploy::{APOGEE_CHANNEL, MAIN_CHANNEL};
+
+    // a short rocket like flight, 2 s on the pad, 20 s up at 50 m/s, 60 s down at 10 m/s then still, 10 Hz
+    fn flight() -> impl Iterator<Item = VerticalState> {
+        (0..1200u32).map(|i| {
+            let t = i as f32 / 10.0;
+            let (altitude, vertical_speed) = match t {
+                t if t < 2.0 => (1000.0, 0.0),
+                t if t < 22.0 => (1000.0 + 50.0 * (t - 2.0), 50.0),
+                t if t < 82.0 => (2000.0 - 15.0 * (t - 22.0), -15.0),
+                _ => (1100.0, 0.0),
+            };
+            VerticalState { altitude, vertical_speed, time_stamp: i * 100_000 }
+        })
+    }
+
+    #[test]
+    fn flight_goes_through_every_state_once() {
+        let mut control = FlightControl::new(FlightState::Pad, DeployMode::Balloon, 150.0, ChannelFlags::NONE);
+        let transitions: Vec<_> = flight().filter_map(|state| control.update(&state, Some(1000.0)).transition).collect();
+        assert_eq!(transitions, [
+            (FlightState::Pad, FlightState::Ascent),
+            (FlightState::Ascent, FlightState::Descent),
+            (FlightState::Descent, FlightState::Landed),
+        ]);
+        assert_eq!(control.state(), FlightState::Landed);
+    }
+
+    #[test]
+    fn max_altitude_is_the_apogee() {
+        let mut control = FlightControl::new(FlightState::Pad, DeployMode::Balloon, 150.0, ChannelFlags::NONE);
+        for state in flight().take(10) {
+            control.update(&state, None);
+        }
+        assert_eq!(control.max_altitude(), None);
+        for state in flight().skip(10) {
+            control.update(&state, None);
+        }
+        assert!((control.max_altitude().unwrap() - 2000.0).abs() < 5.0);
+    }
+
+    #[test]
+    fn balloon_mode_never_fires() {
+        let mut control = FlightControl::new(FlightState::Pad, DeployMode::Balloon, 150.0, ChannelFlags::NONE);
+        assert!(flight().all(|state| control.update(&state, Some(1000.0)).fire.is_none()));
+    }
+
+    #[test]
+    fn dual_deploy_fires_drogue_at_apogee_then_main() {
+        let mut control = FlightControl::new(FlightState::Pad, DeployMode::DualDeploy, 150.0, ChannelFlags::NONE);
+        let fires: Vec<_> = flight()
+            .filter_map(|state| control.update(&state, Some(1000.0)).fire.map(|channel| (channel, state.altitude)))
+            .collect();
+        assert_eq!(fires.len(), 2);
+        assert_eq!(fires[0].0, APOGEE_CHANNEL);
+        assert!(fires[0].1 > 1900.0, "{}", fires[0].1);
+        assert_eq!(fires[1].0, MAIN_CHANNEL);
+        assert!(fires[1].1 <= 1150.0 && fires[1].1 > 1130.0, "{}", fires[1].1);
+    }
+
+    #[test]
+    fn main_waits_for_the_ground_reference() {
+        let mut control = FlightControl::new(FlightState::Pad, DeployMode::DualDeploy, 150.0, ChannelFlags::NONE);
+        let fires: Vec<_> = flight().filter_map(|state| control.update(&state, None).fire).collect();
+        assert_eq!(fires, [APOGEE_CHANNEL]);
+    }
+
+    #[test]
+    fn resumed_descent_keeps_going() {
+        let mut control = FlightControl::new(FlightState::Descent, DeployMode::Balloon, 150.0, ChannelFlags::NONE);
+        let transitions: Vec<_> = flight().skip(900).filter_map(|state| control.update(&state, None).transition).collect();
+        assert_eq!(transitions, [(FlightState::Descent, FlightState::Landed)]);
+    }
+
+    #[test]
+    fn resumed_dual_deploy_doesnt_fire_spent_channels() {
+        let mut fired = ChannelFlags::NONE;
+        fired.set(APOGEE_CHANNEL, true);
+        fired.set(MAIN_CHANNEL, true);
+        let mut control = FlightControl::new(FlightState::Descent, DeployMode::DualDeploy, 150.0, fired);
+        assert!(flight().skip(300).all(|state| control.update(&state, Some(1000.0)).fire.is_none()));
+    }
+
+    #[test]
+    fn resumed_dual_deploy_fires_only_the_main() {
+        let mut fired = ChannelFlags::NONE;
+        fired.set(APOGEE_CHANNEL, true);
+        let mut control = FlightControl::new(FlightState::Descent, DeployMode::DualDeploy, 150.0, fired);
+        let fires: Vec<_> = flight().skip(300).filter_map(|state| control.update(&state, Some(1000.0)).fire).collect();
+        assert_eq!(fires, [MAIN_CHANNEL]);
+    }
+}
@@ -0,0 +1,145 @@
+use core::fmt::{self, Write};
+
+use crate::crc::crc32;
+
+/// longest panic message kept, longer messages are truncated
+pub const MESSAGE_LEN: usize = 96;
+
+/// longest source file path kept, a longer one keeps its end, where the file name is
+pub const FILE_LEN: usize = 32;
+
+/// marks a written record, "PANC"
+const MAGIC: u32 = 0x434E_4150;
+
+/// Panic details written to backup sram by the panic handler and reported at the next boot
+/// file and line are where the panic was raised, uptime is in us
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct PanicRecord {
+    magic: u32,
+    pub line: u32,
+    pub uptime: u64,
+    file_len: u32,
+    file: [u8; FILE_LEN],
+    len: u32,
+    message: [u8; MESSAGE_LEN],
+    crc: u32,
+}
+
+impl PanicRecord {
+    /// record that never validates, used to clear the stored record once it has been reported
+    pub const EMPTY: PanicRecord = PanicRecord {
+        magic: 0,
+        line: 0,
+        uptime: 0,
+        file_len: 0,
+        file: [0; FILE_LEN],
+        len: 0,
+        message: [0; MESSAGE_LEN],
+        crc: 0,
+    };
+
+    pub fn new(file: &str, line: u32, uptime: u64, message: impl fmt::Display) -> Self {
+        let mut writer = Truncating {
+            buf: [0; MESSAGE_LEN],
+            len: 0,
+        };
+        // truncating writer never fails
+        let _ = write!(writer, "{}", message);
+
+        let mut start = file.len().saturating_sub(FILE_LEN);
+        while !file.is_char_boundary(start) {
+            start += 1;
+        }
+        let tail = &file.as_bytes()[start..];
+        let mut path = [0; FILE_LEN];
+        path[..tail.len()].copy_from_slice(tail);
+
+        let mut record = PanicRecord {
+            magic: MAGIC,
+            line,
+            uptime,
+            file_len: tail.len() as u32,
+            file: path,
+            len: writer.len as u32,
+            message: writer.buf,
+            crc: 0,
+        };
+        record.crc = record.checksum();
+        record
+    }
+
+    /// true if the record was fully written, backup sram holds garbage after a power loss without vbat
+    pub fn is_valid(&self) -> bool {
+        self.magic == MAGIC && self.len as usize <= MESSAGE_LEN && self.file_len as usize <= FILE_LEN && self.crc == self.checksum()
+    }
+
+    /// source file the panic was raised in, the end of its path if it was long
+    pub fn file(&self) -> &str {
+        let bytes = &self.file[..(self.file_len as usize).min(FILE_LEN)];
+        core::str::from_utf8(bytes).unwrap_or("<invalid utf8>")
+    }
+
+    pub fn message(&self) -> &str {
+        let bytes = &self.message[..(self.len as usize).min(MESSAGE_LEN)];
+        core::str::from_utf8(bytes).unwrap_or("<invalid utf8>")
+    }
+
+    fn checksum(&self) -> u32 {
+        let mut buf = [0u8; 24 + FILE_LEN + MESSAGE_LEN];
+        buf[0..4].copy_from_slice(&self.magic.to_le_bytes());
+        buf[4..8].copy_from_slice(&self.line.to_le_bytes());
+        buf[8..16].copy_from_slice(&self.uptime.to_le_bytes());
+        buf[16..20].copy_from_slice(&self.file_len.to_le_bytes());
+        buf[20..20 + FILE_LEN].copy_from_slice(&self.file);
+        buf[20 + FILE_LEN..24 + FILE_LEN].copy_from_slice(&self.len.to_le_bytes());
+        buf[24 + FILE_LEN..].copy_from_slice(&self.message);
+        crc32(&buf)
+    }
+}
+
+/// fmt writer into a fixed buffer that drops whatever doesn't fit, cutting only on char boundaries
+struct Truncating {
+    buf: [u8; MESSAGE_LEN],
+    len: usize,
+}
+
+impl Write for Truncating {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let mut n = s.len().min(MESSAGE_LEN - self.len);
+        while !s.is_char_boundary(n) {
+            n -= 1;
+        }
+        self.buf[self.len..self.len + n].copy_from_slice(&s.as_bytes()[..n]);
+        self.len += n;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_location_and_message() {
+        let record = PanicRecord::new("src/main.rs", 812, 5_000_000, format_args!("index {} out of range", 9));
+        assert!(record.is_valid());
+        assert_eq!((record.file(), record.line, record.uptime), ("src/main.rs", 812, 5_000_000));
+        assert_eq!(record.message(), "index 9 out of range");
+        assert!(!PanicRecord::EMPTY.is_valid());
+
+        let mut corrupt = record;
+        corrupt.line = 813;
+        assert!(!corrupt.is_valid());
+    }
+
+    #[test]
+    fn long_path_keeps_the_file_name() {
+        let path = "/home/builder/.cargo/registry/src/index.crates.io-6f17d22bba15001f/heapless-0.9.1/src/vec.rs";
+        let record = PanicRecord::new(path, 1, 0, "");
+        assert_eq!(record.file().len(), FILE_LEN);
+        assert!(record.file().ends_with("heapless-0.9.1/src/vec.rs"));
+        let long = "x".repeat(MESSAGE_LEN + 10);
+        assert_eq!(PanicRecord::new("", 0, 0, &long).message().len(), MESSAGE_LEN);
+    }
+}
@@ -0,0 +1,17 @@
+/// CRC-32 (IEEE 802.3, reflected, poly 0xEDB88320) used to validate persisted records
+pub fn crc32(data: &[u8]) -> u32 {
+    !crc32_update(0xFFFF_FFFF, data)
+}
+
+/// continue a CRC-32 over more data, start from 0xFFFF_FFFF and invert the result when done
+/// for checksums of data that arrives in pieces
+pub fn crc32_update(mut crc: u32, data: &[u8]) -> u32 {
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    crc
+}
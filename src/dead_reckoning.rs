@@ -0,0 +1,69 @@
+use crate::nav::to_geodetic;
+use crate::wind::WindEstimator;
+use crate::gnss::FixQuality;
+use crate::{FixType, GpsData, PositionEstimate, VerticalState};
+
+/// a fix older than this no longer counts as current, us
+const FIX_TIMEOUT: u32 = 5_000_000;
+
+/// Position estimate that keeps going through gps outages
+/// while fixes are good it reports the fix, once they stop it drifts the last fix with the measured wind
+/// at the current baro altitude, since a balloon or parachute moves with the air mass
+pub struct DeadReckoning {
+    last_fix: Option<GpsData>,
+    drift: [f32; 2],
+    last_update: Option<u32>,
+}
+
+impl Default for DeadReckoning {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DeadReckoning {
+    pub const fn new() -> Self {
+        Self {
+            last_fix: None,
+            drift: [0.0; 2],
+            last_update: None,
+        }
+    }
+
+    /// feed a gps fix, unusable or repeated fixes are ignored and leave dead reckoning running
+    pub fn add_fix(&mut self, gps: &GpsData) {
+        let repeated = self.last_fix.is_some_and(|fix| fix.time_stamp == gps.time_stamp);
+        if gps.quality.usable() && !repeated {
+            self.last_fix = Some(*gps);
+            self.drift = [0.0; 2];
+            self.last_update = Some(gps.time_stamp);
+        }
+    }
+
+    /// position at the vertical state timestamp, None until the first good fix
+    pub fn update(&mut self, vertical: &VerticalState, wind: &WindEstimator) -> Option<PositionEstimate> {
+        let fix = self.last_fix?;
+        let now = vertical.time_stamp;
+        let dead_reckoned = now.wrapping_sub(fix.time_stamp) > FIX_TIMEOUT;
+
+        if dead_reckoned {
+            let dt = self.last_update.map_or(0.0, |last| now.wrapping_sub(last) as f32 / 1_000_000.0);
+            if let Some(w) = wind.wind_at(vertical.altitude) {
+                self.drift[0] += w[0] * dt;
+                self.drift[1] += w[1] * dt;
+            }
+        }
+        self.last_update = Some(now);
+
+        let (latitude, longitude) = to_geodetic(fix.latitude, fix.longitude, self.drift[0], self.drift[1]);
+        Some(PositionEstimate {
+            latitude,
+            longitude,
+            // a 2d fix carries a held altitude, not a measured one
+            altitude: if dead_reckoned || fix.fix != FixType::ThreeD { vertical.altitude } else { fix.altitude },
+            dead_reckoned,
+            degraded: fix.quality == FixQuality::Degraded,
+            time_stamp: now,
+        })
+    }
+}
@@ -0,0 +1,66 @@
+// dual deploy altimeter mode for flying on the rocket team's test vehicles, the drogue channel fires at apogee and the
+// main channel on the way down at a set height above the launch site
+// apogee is the flight state machine's switch to descent, the same detection that marks burst on a balloon flight,
+// and the channels fire through the actuation task like any other, so only armed channels ever fire
+
+use crate::actuation::{Channel, ChannelFlags};
+use crate::mission::FlightState;
+
+/// fired at apogee
+pub const APOGEE_CHANNEL: Channel = Channel::Pyro1;
+/// fired at the main deploy height
+pub const MAIN_CHANNEL: Channel = Channel::Pyro2;
+
+/// What the payload is flying on, picked by config
+#[derive(Copy, Clone, PartialEq, Eq, Debug, defmt::Format)]
+pub enum DeployMode {
+    /// nothing fires on its own, the cutdown and pyro channels only fire on command
+    Balloon,
+    DualDeploy,
+}
+
+impl DeployMode {
+    /// mode from its config value, unknown values are balloon so nothing fires unasked
+    pub fn from_u32(value: u32) -> Self {
+        match value {
+            1 => DeployMode::DualDeploy,
+            _ => DeployMode::Balloon,
+        }
+    }
+}
+
+/// Picks when the drogue and main channels fire, each fires once
+pub struct DualDeploy {
+    main_altitude: f32,
+    apogee_fired: bool,
+    main_fired: bool,
+}
+
+impl DualDeploy {
+    /// main_altitude is the main deploy height above the launch site, m, fired the channels already fired before a
+    /// reset, they aren't fired again
+    pub fn new(main_altitude: f32, fired: ChannelFlags) -> Self {
+        Self {
+            main_altitude,
+            apogee_fired: fired.contains(APOGEE_CHANNEL),
+            main_fired: fired.contains(MAIN_CHANNEL),
+        }
+    }
+
+    /// feed the flight state and height above the launch site (m), returns the channel to fire now
+    /// the main waits for the apogee channel, a nan height never fires it
+    pub fn update(&mut self, state: FlightState, height: f32) -> Option<Channel> {
+        if state != FlightState::Descent {
+            return None;
+        }
+        if !self.apogee_fired {
+            self.apogee_fired = true;
+            return Some(APOGEE_CHANNEL);
+        }
+        if !self.main_fired && height <= self.main_altitude {
+            self.main_fired = true;
+            return Some(MAIN_CHANNEL);
+        }
+        None
+    }
+}
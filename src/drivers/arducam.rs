@@ -0,0 +1,253 @@
+use embedded_hal_async::delay::DelayNs;
+use embedded_hal_async::i2c::I2c;
+use embedded_hal_async::spi::{Operation, SpiDevice};
+
+use crate::sensors::{Camera, ImageSize};
+
+/// sccb (i2c) address of the ov2640
+pub const ADDRESS: u8 = 0x30;
+
+// arducam frame buffer registers on spi, the top bit of the address set for a write
+const REG_TEST: u8 = 0x00;
+const REG_CAPTURE_CONTROL: u8 = 0x01;
+const REG_FIFO_CONTROL: u8 = 0x04;
+const REG_BURST_READ: u8 = 0x3C;
+const REG_STATUS: u8 = 0x41;
+const REG_FIFO_SIZE: u8 = 0x42;
+const WRITE: u8 = 0x80;
+
+const FIFO_CLEAR_DONE: u8 = 0x01;
+const FIFO_START: u8 = 0x02;
+const STATUS_CAPTURE_DONE: u8 = 0x08;
+const TEST_PATTERN: u8 = 0x55;
+
+// the frame buffer is 384K, a longer length means the capture went wrong
+const FIFO_LEN: u32 = 0x6_0000;
+
+// ov2640 registers come in two banks, selected through the bank register
+const REG_BANK: u8 = 0xFF;
+const BANK_DSP: u8 = 0x00;
+const BANK_SENSOR: u8 = 0x01;
+
+// sensor bank
+const REG_PIDH: u8 = 0x0A;
+const REG_COM7: u8 = 0x12;
+const COM7_RESET: u8 = 0x80;
+const COM7_SVGA: u8 = 0x40;
+const PIDH: u8 = 0x26;
+
+// dsp bank
+const REG_BYPASS: u8 = 0x05;
+const REG_QS: u8 = 0x44;
+const REG_HSIZE: u8 = 0x51;
+const REG_VSIZE: u8 = 0x52;
+const REG_XOFFL: u8 = 0x53;
+const REG_YOFFL: u8 = 0x54;
+const REG_VHYX: u8 = 0x55;
+const REG_TEST_DSP: u8 = 0x57;
+const REG_ZMOW: u8 = 0x5A;
+const REG_ZMOH: u8 = 0x5B;
+const REG_ZMHH: u8 = 0x5C;
+const REG_CTRL2: u8 = 0x86;
+const REG_HSIZE8: u8 = 0xC0;
+const REG_VSIZE8: u8 = 0xC1;
+const REG_IMAGE_MODE: u8 = 0xDA;
+const REG_RESET: u8 = 0xE0;
+const IMAGE_MODE_JPEG: u8 = 0x10;
+const RESET_DVP: u8 = 0x04;
+// dcw, sde, uv adjust, uv average, and colour matrix on
+const CTRL2_ENABLE: u8 = 0x3D;
+
+// sensor window in svga mode, the dsp scales it down to the output size
+const WINDOW: (u16, u16) = (800, 600);
+// output sizes, the thumbnail is a multiple of 16 each way for ssdv
+const FULL: (u16, u16) = (800, 600);
+const THUMBNAIL: (u16, u16) = (320, 240);
+
+// jpeg quantisation scale, lower is better quality and a longer jpeg
+const QUALITY_SCALE: u8 = 0x0C;
+
+// the reset takes a few ms, a frame after a size change still has the old size, about 15 frames/s in svga
+const RESET_MS: u32 = 5;
+const FRAME_MS: u32 = 100;
+// a capture is one frame, given a few
+const CAPTURE_POLLS: u32 = 50;
+const CAPTURE_POLL_MS: u32 = 10;
+
+#[derive(Copy, Clone, PartialEq, Eq, defmt::Format)]
+pub enum Error {
+    /// spi transfer to the frame buffer failed
+    Bus,
+    /// sccb transfer to the sensor failed, no ack or bus error
+    Sccb,
+    /// the frame buffer doesn't echo its test register, the module is missing
+    NoFrameBuffer,
+    /// product id doesn't match, the module has a different sensor
+    WrongDevice(u8),
+    /// no capture finished in time
+    Timeout,
+    /// the frame buffer length is empty or too long, or the picture doesn't start as a jpeg
+    BadImage,
+}
+
+/// ArduCAM Mini 2MP, an OV2640 sensor set up over sccb and a frame buffer read over spi
+/// the sensor makes the jpeg itself, a picture is captured into the frame buffer and read out from there
+/// the module is set up on the first capture
+pub struct ArduCam<S, I, D> {
+    spi: S,
+    i2c: I,
+    delay: D,
+    address: u8,
+    size: Option<ImageSize>,
+    /// bytes of the last picture left in the frame buffer
+    remaining: u32,
+    /// none of it read yet
+    first: bool,
+}
+
+impl<S: SpiDevice, I: I2c, D: DelayNs> ArduCam<S, I, D> {
+    pub fn new(spi: S, i2c: I, delay: D, address: u8) -> Self {
+        Self { spi, i2c, delay, address, size: None, remaining: 0, first: false }
+    }
+
+    async fn write_register(&mut self, register: u8, value: u8) -> Result<(), Error> {
+        self.spi.write(&[register | WRITE, value]).await.map_err(|_| Error::Bus)
+    }
+
+    async fn read_register(&mut self, register: u8) -> Result<u8, Error> {
+        let mut buf = [0u8];
+        self.spi
+            .transaction(&mut [Operation::Write(&[register]), Operation::Read(&mut buf)])
+            .await
+            .map_err(|_| Error::Bus)?;
+        Ok(buf[0])
+    }
+
+    async fn write_sensor(&mut self, registers: &[(u8, u8)]) -> Result<(), Error> {
+        for &(register, value) in registers {
+            self.i2c.write(self.address, &[register, value]).await.map_err(|_| Error::Sccb)?;
+        }
+        Ok(())
+    }
+
+    async fn configure(&mut self) -> Result<(), Error> {
+        self.write_register(REG_TEST, TEST_PATTERN).await?;
+        if self.read_register(REG_TEST).await? != TEST_PATTERN {
+            return Err(Error::NoFrameBuffer);
+        }
+
+        self.write_sensor(&[(REG_BANK, BANK_SENSOR), (REG_COM7, COM7_RESET)]).await?;
+        self.delay.delay_ms(RESET_MS).await;
+        let mut pid = [0u8];
+        self.i2c.write_read(self.address, &[REG_PIDH], &mut pid).await.map_err(|_| Error::Sccb)?;
+        if pid[0] != PIDH {
+            return Err(Error::WrongDevice(pid[0]));
+        }
+
+        // svga window into the dsp, jpeg out of it, the dvp held in reset while it changes
+        let (width, height) = (WINDOW.0 / 4, WINDOW.1 / 4);
+        self.write_sensor(&[
+            (REG_COM7, COM7_SVGA),
+            (REG_BANK, BANK_DSP),
+            (REG_BYPASS, 0x01),
+            (REG_RESET, RESET_DVP),
+            (REG_HSIZE8, (WINDOW.0 >> 3) as u8),
+            (REG_VSIZE8, (WINDOW.1 >> 3) as u8),
+            (REG_CTRL2, CTRL2_ENABLE),
+            (REG_HSIZE, width as u8),
+            (REG_VSIZE, height as u8),
+            (REG_XOFFL, 0),
+            (REG_YOFFL, 0),
+            (REG_VHYX, (((height >> 8) & 0x01) << 7 | ((width >> 8) & 0x07) << 3) as u8),
+            (REG_TEST_DSP, 0),
+            (REG_IMAGE_MODE, IMAGE_MODE_JPEG),
+            (REG_QS, QUALITY_SCALE),
+            (REG_RESET, 0),
+            (REG_BYPASS, 0),
+        ])
+        .await?;
+        // one picture per capture
+        self.write_register(REG_CAPTURE_CONTROL, 0).await
+    }
+
+    // scale the window to the output size
+    async fn set_size(&mut self, size: ImageSize) -> Result<(), Error> {
+        let (width, height) = match size {
+            ImageSize::Full => FULL,
+            ImageSize::Thumbnail => THUMBNAIL,
+        };
+        let (width, height) = (width / 4, height / 4);
+        self.write_sensor(&[
+            (REG_BANK, BANK_DSP),
+            (REG_RESET, RESET_DVP),
+            (REG_ZMOW, width as u8),
+            (REG_ZMOH, height as u8),
+            (REG_ZMHH, ((width >> 8) & 0x03 | ((height >> 8) & 0x01) << 2) as u8),
+            (REG_RESET, 0),
+        ])
+        .await?;
+        self.delay.delay_ms(FRAME_MS).await;
+        self.size = Some(size);
+        Ok(())
+    }
+}
+
+impl<S: SpiDevice, I: I2c, D: DelayNs> Camera for ArduCam<S, I, D> {
+    type Error = Error;
+
+    async fn capture(&mut self, size: ImageSize) -> Result<u32, Error> {
+        self.remaining = 0;
+        if self.size.is_none() {
+            self.configure().await?;
+        }
+        if self.size != Some(size) {
+            self.set_size(size).await?;
+        }
+
+        self.write_register(REG_FIFO_CONTROL, FIFO_CLEAR_DONE).await?;
+        self.write_register(REG_FIFO_CONTROL, FIFO_START).await?;
+        let mut polls = 0;
+        while self.read_register(REG_STATUS).await? & STATUS_CAPTURE_DONE == 0 {
+            polls += 1;
+            if polls == CAPTURE_POLLS {
+                return Err(Error::Timeout);
+            }
+            self.delay.delay_ms(CAPTURE_POLL_MS).await;
+        }
+
+        let mut len = [0u8; 3];
+        for (i, byte) in len.iter_mut().enumerate() {
+            *byte = self.read_register(REG_FIFO_SIZE + i as u8).await?;
+        }
+        let len = u32::from_le_bytes([len[0], len[1], len[2] & 0x7F, 0]);
+        if len == 0 || len >= FIFO_LEN {
+            return Err(Error::BadImage);
+        }
+        self.remaining = len;
+        self.first = true;
+        Ok(len)
+    }
+
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        let len = buf.len().min(self.remaining as usize);
+        if len == 0 {
+            return Ok(0);
+        }
+        // the read pointer carries on from the last burst
+        self.spi
+            .transaction(&mut [Operation::Write(&[REG_BURST_READ]), Operation::Read(&mut buf[..len])])
+            .await
+            .map_err(|_| Error::Bus)?;
+        if self.first && !buf[..len].starts_with(&[0xFF, 0xD8]) {
+            self.remaining = 0;
+            return Err(Error::BadImage);
+        }
+        self.first = false;
+        self.remaining -= len as u32;
+        Ok(len)
+    }
+
+    fn reinit(&mut self) {
+        self.size = None;
+    }
+}
@@ -0,0 +1,146 @@
+// i2c fram and eeproms with two address bytes, the mb85rc fram and the m24cxx eeproms
+// the fram writes as fast as the bus and never wears, an eeprom takes a write cycle after each page
+// transfers are kept short, the part shares a bus with the sensors and a transfer holds it throughout
+
+use embedded_hal_async::delay::DelayNs;
+use embedded_hal_async::i2c::I2c;
+
+use crate::kvstore::KvFlash;
+
+/// i2c address with a0 to a2 tied low
+pub const ADDRESS: u8 = 0x50;
+
+// longest transfer, at 400 kHz about a millisecond of the bus
+const CHUNK: usize = 32;
+
+/// Size and write timing of a part
+#[derive(Copy, Clone, PartialEq, Eq, Debug, defmt::Format)]
+pub struct Part {
+    pub capacity: u32,
+    /// a write can't cross a page boundary
+    pub page_size: u32,
+    /// wait after writing a page before the part answers again, us
+    pub write_time: u32,
+}
+
+/// 32K fram
+pub const MB85RC256V: Part = Part { capacity: 32 * 1024, page_size: 32 * 1024, write_time: 0 };
+
+/// 8K eeprom
+pub const M24C64: Part = Part { capacity: 8 * 1024, page_size: 32, write_time: 5_000 };
+
+/// 32K eeprom
+pub const M24256: Part = Part { capacity: 32 * 1024, page_size: 64, write_time: 5_000 };
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug, defmt::Format)]
+pub enum Error {
+    /// i2c transfer failed, no ack or bus error
+    Bus,
+    /// past the end of the part or of a region
+    OutOfRange,
+}
+
+pub struct Eeprom<I, D> {
+    i2c: I,
+    delay: D,
+    address: u8,
+    part: Part,
+}
+
+impl<I: I2c, D: DelayNs> Eeprom<I, D> {
+    pub fn new(i2c: I, delay: D, address: u8, part: Part) -> Self {
+        Self { i2c, delay, address, part }
+    }
+
+    pub fn part(&self) -> Part {
+        self.part
+    }
+
+    pub async fn read(&mut self, address: u32, bytes: &mut [u8]) -> Result<(), Error> {
+        self.check(address, bytes.len())?;
+        for (index, chunk) in bytes.chunks_mut(CHUNK).enumerate() {
+            let at = (address as usize + index * CHUNK) as u16;
+            self.i2c.write_read(self.address, &at.to_be_bytes(), chunk).await.map_err(|_| Error::Bus)?;
+        }
+        Ok(())
+    }
+
+    /// write bytes, split at page boundaries and waiting out each page's write cycle
+    pub async fn write(&mut self, address: u32, bytes: &[u8]) -> Result<(), Error> {
+        self.check(address, bytes.len())?;
+        let mut at = address;
+        let mut rest = bytes;
+        while !rest.is_empty() {
+            let len = rest.len().min(CHUNK).min((self.part.page_size - at % self.part.page_size) as usize);
+            let mut frame = [0u8; 2 + CHUNK];
+            frame[..2].copy_from_slice(&(at as u16).to_be_bytes());
+            frame[2..2 + len].copy_from_slice(&rest[..len]);
+            self.i2c.write(self.address, &frame[..2 + len]).await.map_err(|_| Error::Bus)?;
+            if self.part.write_time > 0 {
+                self.delay.delay_us(self.part.write_time).await;
+            }
+            at += len as u32;
+            rest = &rest[len..];
+        }
+        Ok(())
+    }
+
+    /// part of the part as a store's region, from first for size bytes
+    pub fn region(&mut self, first: u32, size: u32) -> EepromRegion<'_, I, D> {
+        EepromRegion { eeprom: self, first, size }
+    }
+
+    fn check(&self, address: u32, len: usize) -> Result<(), Error> {
+        match address.checked_add(len as u32) {
+            Some(end) if end <= self.part.capacity => Ok(()),
+            _ => Err(Error::OutOfRange),
+        }
+    }
+}
+
+/// Run of bytes of a part a store lives in, erased by writing 0xFF over it
+pub struct EepromRegion<'a, I, D> {
+    eeprom: &'a mut Eeprom<I, D>,
+    first: u32,
+    size: u32,
+}
+
+impl<I, D> EepromRegion<'_, I, D> {
+    fn check(&self, offset: u32, len: usize) -> Result<u32, Error> {
+        match offset.checked_add(len as u32) {
+            Some(end) if end <= self.size => Ok(self.first + offset),
+            _ => Err(Error::OutOfRange),
+        }
+    }
+}
+
+impl<I: I2c, D: DelayNs> KvFlash for EepromRegion<'_, I, D> {
+    type Error = Error;
+
+    fn capacity(&self) -> u32 {
+        self.size
+    }
+
+    fn write_size(&self) -> u32 {
+        1
+    }
+
+    async fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Error> {
+        let address = self.check(offset, bytes.len())?;
+        self.eeprom.read(address, bytes).await
+    }
+
+    async fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Error> {
+        let address = self.check(offset, bytes.len())?;
+        self.eeprom.write(address, bytes).await
+    }
+
+    async fn erase(&mut self) -> Result<(), Error> {
+        let blank = [0xFF; CHUNK];
+        for offset in (0..self.size).step_by(CHUNK) {
+            let len = (self.size - offset).min(CHUNK as u32) as usize;
+            self.eeprom.write(self.first + offset, &blank[..len]).await?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,224 @@
+use embedded_hal_async::delay::DelayNs;
+use embedded_hal_async::spi::{Operation, SpiDevice};
+
+use crate::ImuData;
+use crate::drivers::register::{self, Addressing};
+use crate::sensors::Imu;
+use crate::validate::SampleFlags;
+
+const REG_DEVICE_CONFIG: u8 = 0x11;
+const REG_INT_CONFIG: u8 = 0x14;
+const REG_FIFO_CONFIG: u8 = 0x16;
+const REG_TEMP_DATA1: u8 = 0x1D;
+const REG_FIFO_COUNTH: u8 = 0x2E;
+const REG_FIFO_DATA: u8 = 0x30;
+const REG_SIGNAL_PATH_RESET: u8 = 0x4B;
+const REG_PWR_MGMT0: u8 = 0x4E;
+const REG_GYRO_CONFIG0: u8 = 0x4F;
+const REG_ACCEL_CONFIG0: u8 = 0x50;
+const REG_INT_CONFIG1: u8 = 0x64;
+const REG_FIFO_CONFIG1: u8 = 0x5F;
+const REG_INT_SOURCE0: u8 = 0x65;
+const REG_WHO_AM_I: u8 = 0x75;
+
+const WHO_AM_I: u8 = 0x47;
+const READ: u8 = 0x80;
+
+const FIFO_BYPASS: u8 = 0x00;
+const FIFO_STREAM: u8 = 0x40;
+const FIFO_ACCEL_ONLY: u8 = 0x01;
+const FIFO_FLUSH: u8 = 0x02;
+// accel only fifo packets are a header, accel x y z, and temperature, the header has only its accel bit of the top three set
+const FIFO_PACKET_LEN: usize = 8;
+const FIFO_HEADER_MASK: u8 = 0xE0;
+const FIFO_HEADER_ACCEL: u8 = 0x40;
+// the fifo holds 2K, read in pieces so the buffer stays small, fast enough to keep ahead of it at the burst rate
+const FIFO_READ_PACKETS: usize = 32;
+const FIFO_POLL_US: u32 = 1_000;
+const FIFO_POLLS: u32 = 20;
+
+// accel output data rate code and period (us) during a vibration burst, 4 kHz
+const BURST_RATE: (u8, u32) = (0x04, 250);
+
+// soft reset takes 1 ms, registers can't be written for 200 us after the sensors are powered up
+const RESET_US: u32 = 1_000;
+const POWER_UP_US: u32 = 200;
+
+// output data rate codes and their periods (us), fastest first, the same codes apply to the gyro and accel
+const OUTPUT_RATES: [(u8, u32); 7] = [
+    (0x06, 1_000),
+    (0x0F, 2_000),
+    (0x07, 5_000),
+    (0x08, 10_000),
+    (0x09, 20_000),
+    (0x0A, 40_000),
+    (0x0B, 80_000),
+];
+
+// +-16 g and +-2000 dps full scale, a balloon burst and the tumble after it saturate anything smaller
+const ACCEL_LSB_PER_G: f32 = 2048.0;
+const GYRO_LSB_PER_DPS: f32 = 16.4;
+const GRAVITY: f32 = 9.80665;
+
+#[derive(Copy, Clone, PartialEq, Eq, defmt::Format)]
+pub enum Error {
+    /// spi transfer failed
+    Bus,
+    /// who am i doesn't match, the part is missing or a different one is fitted
+    WrongDevice(u8),
+    /// a fifo packet that isn't accel data, or the fifo stopped filling during a burst
+    Fifo,
+    /// a setup register didn't read back as written, the register
+    Verify(u8),
+}
+
+/// ICM-42688-P accel and gyro on spi, there is no mag so mag reads as zero, pair it with one in a WithMag
+/// samples are burst read in one transaction so temperature, accel, and gyro all come from the same instant
+/// the part is set up the first time the output rate is set, it has to be before the first read
+/// vibration bursts run the accel faster through the fifo, then put the output rate back
+pub struct Icm42688<S, D> {
+    spi: S,
+    delay: D,
+    configured: bool,
+    /// output data rate code last set
+    odr: u8,
+}
+
+impl<S: SpiDevice, D: DelayNs> Icm42688<S, D> {
+    pub fn new(spi: S, delay: D) -> Self {
+        Self { spi, delay, configured: false, odr: OUTPUT_RATES[0].0 }
+    }
+
+    async fn write_register(&mut self, register: u8, value: u8) -> Result<(), Error> {
+        self.spi.write(&[register, value]).await.map_err(|_| Error::Bus)
+    }
+
+    // setup the part can't run right without, read back and written again if it didn't take
+    async fn write_register_verified(&mut self, register: u8, value: u8) -> Result<(), Error> {
+        match register::write_verified(&mut self.spi, Addressing::READ_BIT, register, value).await {
+            Ok(_) => Ok(()),
+            Err(register::WriteError::Bus) => Err(Error::Bus),
+            Err(register::WriteError::Mismatch { register, .. }) => Err(Error::Verify(register)),
+        }
+    }
+
+    async fn read_registers(&mut self, register: u8, buf: &mut [u8]) -> Result<(), Error> {
+        self.spi
+            .transaction(&mut [Operation::Write(&[register | READ]), Operation::Read(buf)])
+            .await
+            .map_err(|_| Error::Bus)
+    }
+
+    async fn configure(&mut self) -> Result<(), Error> {
+        self.write_register(REG_DEVICE_CONFIG, 0x01).await?;
+        self.delay.delay_us(RESET_US).await;
+
+        let mut who_am_i = [0u8];
+        self.read_registers(REG_WHO_AM_I, &mut who_am_i).await?;
+        if who_am_i[0] != WHO_AM_I {
+            return Err(Error::WrongDevice(who_am_i[0]));
+        }
+
+        // int1 push pull active high pulses on data ready, async reset cleared as the datasheet requires
+        self.write_register_verified(REG_INT_CONFIG, 0x03).await?;
+        self.write_register_verified(REG_INT_CONFIG1, 0x00).await?;
+        self.write_register_verified(REG_INT_SOURCE0, 0x08).await?;
+
+        // gyro and accel in low noise mode, reads are fine during the power up wait, only writes aren't
+        self.write_register_verified(REG_PWR_MGMT0, 0x0F).await?;
+        self.delay.delay_us(POWER_UP_US).await;
+        self.configured = true;
+        Ok(())
+    }
+
+    // read accel packets out of the fifo until samples is full
+    async fn drain_fifo(&mut self, samples: &mut [[f32; 3]]) -> Result<(), Error> {
+        let mut filled = 0;
+        let mut polls = 0;
+        let mut buf = [0u8; FIFO_READ_PACKETS * FIFO_PACKET_LEN];
+        while filled < samples.len() {
+            let mut count = [0u8; 2];
+            self.read_registers(REG_FIFO_COUNTH, &mut count).await?;
+            let packets = (u16::from_be_bytes(count) as usize / FIFO_PACKET_LEN).min(FIFO_READ_PACKETS).min(samples.len() - filled);
+            if packets == 0 {
+                polls += 1;
+                if polls == FIFO_POLLS {
+                    return Err(Error::Fifo);
+                }
+                self.delay.delay_us(FIFO_POLL_US).await;
+                continue;
+            }
+            polls = 0;
+
+            let buf = &mut buf[..packets * FIFO_PACKET_LEN];
+            self.read_registers(REG_FIFO_DATA, buf).await?;
+            for packet in buf.chunks_exact(FIFO_PACKET_LEN) {
+                if packet[0] & FIFO_HEADER_MASK != FIFO_HEADER_ACCEL {
+                    return Err(Error::Fifo);
+                }
+                let accel = |i: usize| i16::from_be_bytes([packet[1 + 2 * i], packet[2 + 2 * i]]) as f32 / ACCEL_LSB_PER_G * GRAVITY;
+                samples[filled] = [accel(0), accel(1), accel(2)];
+                filled += 1;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// output data rate code and its period (us) for a sample period (us), the slowest rate that is at least as fast as asked
+pub fn output_rate(period_us: u32) -> (u8, u32) {
+    *OUTPUT_RATES.iter().rev().find(|&&(_, period)| period <= period_us).unwrap_or(&OUTPUT_RATES[0])
+}
+
+impl<S: SpiDevice, D: DelayNs> Imu for Icm42688<S, D> {
+    type Error = Error;
+
+    async fn read(&mut self, time_stamp: u32) -> Result<ImuData, Error> {
+        let mut buf = [0u8; 14];
+        if let Err(e) = self.read_registers(REG_TEMP_DATA1, &mut buf).await {
+            self.configured = false;
+            return Err(e);
+        }
+
+        let word = |i: usize| i16::from_be_bytes([buf[2 * i], buf[2 * i + 1]]) as f32;
+        let accel = |i: usize| word(1 + i) / ACCEL_LSB_PER_G * GRAVITY;
+        let gyro = |i: usize| (word(4 + i) / GYRO_LSB_PER_DPS).to_radians();
+        Ok(ImuData {
+            acceleration: [accel(0), accel(1), accel(2)],
+            gyro: [gyro(0), gyro(1), gyro(2)],
+            mag: [0.0; 3],
+            temperature: word(0) / 132.48 + 25.0,
+            flags: SampleFlags::NONE,
+            time_stamp,
+        })
+    }
+
+    // the slowest output rate is 12.5 Hz, longer periods get samples faster than asked and the caller skips the extras
+    async fn set_output_rate(&mut self, period_us: u32) -> Result<u32, Error> {
+        if !self.configured {
+            self.configure().await?;
+        }
+        let (odr, output_period_us) = output_rate(period_us);
+        self.write_register_verified(REG_GYRO_CONFIG0, odr).await?;
+        self.write_register_verified(REG_ACCEL_CONFIG0, odr).await?;
+        self.odr = odr;
+        Ok(output_period_us)
+    }
+
+    // accel only packets streamed into a flushed fifo, the gyro carries on at its rate and isn't stored
+    async fn burst(&mut self, samples: &mut [[f32; 3]]) -> Result<u32, Error> {
+        if !self.configured {
+            self.configure().await?;
+        }
+        self.write_register(REG_ACCEL_CONFIG0, BURST_RATE.0).await?;
+        self.write_register(REG_FIFO_CONFIG1, FIFO_ACCEL_ONLY).await?;
+        self.write_register(REG_SIGNAL_PATH_RESET, FIFO_FLUSH).await?;
+        self.write_register(REG_FIFO_CONFIG, FIFO_STREAM).await?;
+        let drained = self.drain_fifo(samples).await;
+
+        // back to normal output whether or not the burst worked, a missed write here would leave the accel at the burst rate
+        self.write_register_verified(REG_FIFO_CONFIG, FIFO_BYPASS).await?;
+        self.write_register_verified(REG_ACCEL_CONFIG0, self.odr).await?;
+        drained.map(|()| BURST_RATE.1)
+    }
+}
@@ -0,0 +1,67 @@
+use embedded_hal_async::i2c::I2c;
+
+use crate::sensors::Battery;
+
+/// i2c address with a0 and a1 tied low
+pub const ADDRESS: u8 = 0x40;
+
+/// i2c address with a0 tied high and a1 low
+pub const ADDRESS_A0_HIGH: u8 = 0x41;
+
+const REG_SHUNT_VOLTAGE: u8 = 0x01;
+const REG_BUS_VOLTAGE: u8 = 0x02;
+
+// bus voltage lsb, the reading is in bits 15 to 3
+const BUS_VOLTAGE_LSB: f32 = 0.004;
+// shunt voltage lsb, signed, at the power on 320 mV range
+const SHUNT_VOLTAGE_LSB: f32 = 10e-6;
+
+#[derive(Copy, Clone, PartialEq, Eq, defmt::Format)]
+pub enum Error {
+    /// i2c transfer failed, no ack or bus error
+    Bus,
+    /// the conversion overflowed, the battery is outside the 32 V range
+    Overflow,
+}
+
+/// INA219 power monitor, reads the bus and shunt voltages at its power on defaults (32 V and 320 mV ranges, continuous)
+pub struct Ina219<I> {
+    i2c: I,
+    address: u8,
+}
+
+impl<I: I2c> Ina219<I> {
+    pub fn new(i2c: I, address: u8) -> Self {
+        Self { i2c, address }
+    }
+
+    /// read the bus voltage, V
+    pub async fn bus_voltage(&mut self) -> Result<f32, Error> {
+        let raw = self.read_register(REG_BUS_VOLTAGE).await?;
+        // bit 0 flags a math overflow
+        if raw & 0x0001 != 0 {
+            return Err(Error::Overflow);
+        }
+        Ok((raw >> 3) as f32 * BUS_VOLTAGE_LSB)
+    }
+
+    /// read the current through the shunt from its resistance (ohm), A, positive from vin+ to vin-
+    pub async fn current(&mut self, shunt: f32) -> Result<f32, Error> {
+        let raw = self.read_register(REG_SHUNT_VOLTAGE).await? as i16;
+        Ok(raw as f32 * SHUNT_VOLTAGE_LSB / shunt)
+    }
+
+    async fn read_register(&mut self, register: u8) -> Result<u16, Error> {
+        let mut buf = [0u8; 2];
+        self.i2c.write_read(self.address, &[register], &mut buf).await.map_err(|_| Error::Bus)?;
+        Ok(u16::from_be_bytes(buf))
+    }
+}
+
+impl<I: I2c> Battery for Ina219<I> {
+    type Error = Error;
+
+    async fn read(&mut self) -> Result<f32, Error> {
+        self.bus_voltage().await
+    }
+}
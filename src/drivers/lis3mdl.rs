@@ -0,0 +1,83 @@
+use embedded_hal_async::i2c::I2c;
+
+use crate::sensors::Magnetometer;
+
+/// i2c address with sdo tied low
+pub const ADDRESS: u8 = 0x1C;
+
+const REG_WHO_AM_I: u8 = 0x0F;
+const REG_CTRL_REG1: u8 = 0x20;
+const REG_OUT_X_L: u8 = 0x28;
+
+const WHO_AM_I: u8 = 0x3D;
+// register address bit that makes a multi byte access step through the registers
+const AUTO_INCREMENT: u8 = 0x80;
+
+// ctrl_reg1 to ctrl_reg5: ultra high performance xy at 80 Hz, +-4 gauss, continuous, ultra high performance z,
+// block data update so a read never mixes two samples
+const CONFIG: [u8; 5] = [0x7C, 0x00, 0x00, 0x0C, 0x40];
+
+// +-4 gauss full scale, 1 gauss is 100 uT
+const LSB_PER_GAUSS: f32 = 6842.0;
+
+#[derive(Copy, Clone, PartialEq, Eq, defmt::Format)]
+pub enum Error {
+    /// i2c transfer failed, no ack or bus error
+    Bus,
+    /// who am i doesn't match, the part is missing or a different one is fitted
+    WrongDevice(u8),
+}
+
+/// LIS3MDL magnetometer on i2c, set up on the first read and again after any failed read
+pub struct Lis3mdl<I> {
+    i2c: I,
+    address: u8,
+    configured: bool,
+}
+
+impl<I: I2c> Lis3mdl<I> {
+    pub fn new(i2c: I, address: u8) -> Self {
+        Self { i2c, address, configured: false }
+    }
+
+    async fn configure(&mut self) -> Result<(), Error> {
+        let mut who_am_i = [0u8];
+        self.i2c.write_read(self.address, &[REG_WHO_AM_I], &mut who_am_i).await.map_err(|_| Error::Bus)?;
+        if who_am_i[0] != WHO_AM_I {
+            return Err(Error::WrongDevice(who_am_i[0]));
+        }
+
+        let mut buf = [0u8; 6];
+        buf[0] = REG_CTRL_REG1 | AUTO_INCREMENT;
+        buf[1..].copy_from_slice(&CONFIG);
+        self.i2c.write(self.address, &buf).await.map_err(|_| Error::Bus)?;
+        self.configured = true;
+        Ok(())
+    }
+
+    async fn sample(&mut self) -> Result<[f32; 3], Error> {
+        if !self.configured {
+            self.configure().await?;
+        }
+        let mut buf = [0u8; 6];
+        self.i2c.write_read(self.address, &[REG_OUT_X_L | AUTO_INCREMENT], &mut buf).await.map_err(|_| Error::Bus)?;
+        let axis = |i: usize| i16::from_le_bytes([buf[2 * i], buf[2 * i + 1]]) as f32 / LSB_PER_GAUSS * 100.0;
+        Ok([axis(0), axis(1), axis(2)])
+    }
+}
+
+impl<I: I2c> Magnetometer for Lis3mdl<I> {
+    type Error = Error;
+
+    async fn read(&mut self) -> Result<[f32; 3], Error> {
+        let result = self.sample().await;
+        if result.is_err() {
+            self.configured = false;
+        }
+        result
+    }
+
+    fn reinit(&mut self) {
+        self.configured = false;
+    }
+}
@@ -0,0 +1,11 @@
+// drivers for the flight board parts, async over embedded-hal so the board can hand them dma buses
+
+pub mod arducam;
+pub mod eeprom;
+pub mod icm42688;
+pub mod ina219;
+pub mod lis3mdl;
+pub mod ms5611;
+pub mod register;
+pub mod sdcard;
+pub mod spinor;
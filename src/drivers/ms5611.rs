@@ -0,0 +1,132 @@
+use embedded_hal_async::delay::DelayNs;
+use embedded_hal_async::i2c::I2c;
+
+use crate::BaroData;
+use crate::sensors::Barometer;
+use crate::validate::SampleFlags;
+
+/// i2c address with csb tied low
+pub const ADDRESS: u8 = 0x77;
+/// i2c address with csb tied high, for a second part on the same bus
+pub const ADDRESS_CSB_HIGH: u8 = 0x76;
+
+const CMD_RESET: u8 = 0x1E;
+const CMD_CONVERT_D1_4096: u8 = 0x48;
+const CMD_CONVERT_D2_4096: u8 = 0x58;
+const CMD_ADC_READ: u8 = 0x00;
+const CMD_PROM_READ: u8 = 0xA0;
+
+// worst case conversion time at osr 4096 is 9.04 ms
+const CONVERSION_US: u32 = 9_100;
+// prom reload after reset
+const RESET_US: u32 = 2_800;
+
+#[derive(Copy, Clone, PartialEq, Eq, defmt::Format)]
+pub enum Error {
+    /// i2c transfer failed, no ack or bus error
+    Bus,
+    /// calibration prom reads as all zeros or all ones, the part is missing or damaged
+    BadProm,
+}
+
+/// MS5611 barometer on i2c
+/// the calibration prom is read on the first sample, and again after any failed read in case the part was reset
+pub struct Ms5611<I, D> {
+    i2c: I,
+    delay: D,
+    address: u8,
+    /// prom coefficients C1 to C6
+    prom: Option<[i64; 6]>,
+}
+
+impl<I: I2c, D: DelayNs> Ms5611<I, D> {
+    pub fn new(i2c: I, delay: D, address: u8) -> Self {
+        Self { i2c, delay, address, prom: None }
+    }
+
+    async fn command(&mut self, command: u8) -> Result<(), Error> {
+        self.i2c.write(self.address, &[command]).await.map_err(|_| Error::Bus)
+    }
+
+    async fn load_prom(&mut self) -> Result<[i64; 6], Error> {
+        self.command(CMD_RESET).await?;
+        self.delay.delay_us(RESET_US).await;
+
+        let mut prom = [0i64; 6];
+        for (i, c) in prom.iter_mut().enumerate() {
+            let mut buf = [0u8; 2];
+            self.i2c.write_read(self.address, &[CMD_PROM_READ + 2 * (i as u8 + 1)], &mut buf).await.map_err(|_| Error::Bus)?;
+            *c = u16::from_be_bytes(buf) as i64;
+        }
+        if prom.iter().all(|&c| c == 0) || prom.iter().all(|&c| c == 0xFFFF) {
+            return Err(Error::BadProm);
+        }
+        Ok(prom)
+    }
+
+    async fn convert(&mut self, command: u8) -> Result<i64, Error> {
+        self.command(command).await?;
+        self.delay.delay_us(CONVERSION_US).await;
+        let mut buf = [0u8; 3];
+        self.i2c.write_read(self.address, &[CMD_ADC_READ], &mut buf).await.map_err(|_| Error::Bus)?;
+        Ok(((buf[0] as i64) << 16) | ((buf[1] as i64) << 8) | buf[2] as i64)
+    }
+
+    async fn sample(&mut self) -> Result<(f32, f32), Error> {
+        let c = match self.prom {
+            Some(prom) => prom,
+            None => {
+                let prom = self.load_prom().await?;
+                self.prom = Some(prom);
+                prom
+            }
+        };
+        let d1 = self.convert(CMD_CONVERT_D1_4096).await?;
+        let d2 = self.convert(CMD_CONVERT_D2_4096).await?;
+        Ok(compensate(&c, d1, d2))
+    }
+}
+
+/// pressure (hPa) and temperature (C) from the raw conversions, with the datasheet's second order correction below 20 C
+pub fn compensate(c: &[i64; 6], d1: i64, d2: i64) -> (f32, f32) {
+    let dt = d2 - (c[4] << 8);
+    let mut temp = 2000 + ((dt * c[5]) >> 23);
+    let mut off = (c[1] << 16) + ((c[3] * dt) >> 7);
+    let mut sens = (c[0] << 15) + ((c[2] * dt) >> 8);
+
+    // a balloon spends most of the flight well below 20 C, the first order result is off by several hPa there
+    if temp < 2000 {
+        let t2 = (dt * dt) >> 31;
+        let mut off2 = 5 * (temp - 2000) * (temp - 2000) / 2;
+        let mut sens2 = 5 * (temp - 2000) * (temp - 2000) / 4;
+        if temp < -1500 {
+            off2 += 7 * (temp + 1500) * (temp + 1500);
+            sens2 += 11 * (temp + 1500) * (temp + 1500) / 2;
+        }
+        temp -= t2;
+        off -= off2;
+        sens -= sens2;
+    }
+
+    // pressure in 0.01 mbar, temperature in 0.01 C
+    let pressure = (((d1 * sens) >> 21) - off) >> 15;
+    (pressure as f32 / 100.0, temp as f32 / 100.0)
+}
+
+impl<I: I2c, D: DelayNs> Barometer for Ms5611<I, D> {
+    type Error = Error;
+
+    async fn read(&mut self, time_stamp: u32) -> Result<BaroData, Error> {
+        match self.sample().await {
+            Ok((pressure, temperature)) => Ok(BaroData { pressure, temperature, flags: SampleFlags::NONE, time_stamp }),
+            Err(e) => {
+                self.prom = None;
+                Err(e)
+            }
+        }
+    }
+
+    fn reinit(&mut self) {
+        self.prom = None;
+    }
+}
@@ -0,0 +1,49 @@
+// verified register writes for setup that has to be right, the write is read back and made again until the part
+// holds it, a transfer glitched by vibration on a connector can lose or corrupt a write without any bus error and
+// the part would carry on misconfigured with nothing noticing
+// only for plain setup registers, ones with self clearing or status bits never read back as written
+
+use embedded_hal_async::spi::{Operation, SpiDevice};
+
+/// writes after the first before a verified write gives up
+pub const WRITE_RETRIES: u8 = 2;
+
+/// How a part marks a register access as a read or a write in the address byte
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Addressing {
+    pub read: u8,
+    pub write: u8,
+}
+
+impl Addressing {
+    /// top bit set for a read, most mems parts
+    pub const READ_BIT: Addressing = Addressing { read: 0x80, write: 0x00 };
+    /// top bit set for a write, the sx127x radios
+    pub const WRITE_BIT: Addressing = Addressing { read: 0x00, write: 0x80 };
+}
+
+/// Why a verified write failed
+#[derive(Copy, Clone, PartialEq, Eq, Debug, defmt::Format)]
+pub enum WriteError {
+    /// the last try's spi transfer failed
+    Bus,
+    /// the last try read back something else
+    Mismatch { register: u8, value: u8, read: u8 },
+}
+
+/// write a register and read it back, writing again until it reads back as written
+/// returns the writes it took past the first
+pub async fn write_verified<S: SpiDevice>(spi: &mut S, addressing: Addressing, register: u8, value: u8) -> Result<u8, WriteError> {
+    let mut result = Err(WriteError::Bus);
+    for retry in 0..=WRITE_RETRIES {
+        let mut read = [0u8];
+        let transferred = spi.write(&[register | addressing.write, value]).await.is_ok()
+            && spi.transaction(&mut [Operation::Write(&[register | addressing.read]), Operation::Read(&mut read)]).await.is_ok();
+        result = match (transferred, read[0]) {
+            (false, _) => Err(WriteError::Bus),
+            (true, read) if read == value => return Ok(retry),
+            (true, read) => Err(WriteError::Mismatch { register, value, read }),
+        };
+    }
+    result
+}
@@ -0,0 +1,259 @@
+use embedded_hal_async::delay::DelayNs;
+use embedded_hal_async::spi::{Operation, SpiDevice};
+
+use crate::blockqueue::BLOCK_SIZE;
+use crate::storage::LogStorage;
+
+// command frames are 0x40 | index, a 4 byte argument, and a crc that is only checked for CMD0 and CMD8 in spi mode
+const CMD_GO_IDLE: u8 = 0;
+const CMD_SEND_IF_COND: u8 = 8;
+const CMD_SEND_CSD: u8 = 9;
+const CMD_READ_BLOCK: u8 = 17;
+const CMD_WRITE_BLOCK: u8 = 24;
+const CMD_ERASE_START: u8 = 32;
+const CMD_ERASE_END: u8 = 33;
+const CMD_ERASE: u8 = 38;
+const CMD_APP: u8 = 55;
+const ACMD_SEND_OP_COND: u8 = 41;
+
+const R1_IDLE: u8 = 0x01;
+const R1_ILLEGAL_COMMAND: u8 = 0x04;
+const DATA_START: u8 = 0xFE;
+const DATA_ACCEPTED: u8 = 0x05;
+
+// 2.7-3.6 V and the check pattern, the card echoes them back if it supports them
+const IF_COND: u32 = 0x1AA;
+// host supports high capacity cards
+const HCS: u32 = 1 << 30;
+
+// a card leaves idle within a second of ACMD41 starting init, programs a block within 250 ms, and erases within a
+// few seconds however much is erased
+const INIT_ATTEMPTS: u32 = 100;
+const INIT_RETRY_US: u32 = 10_000;
+const BUSY_POLLS: u32 = 250;
+const ERASE_BUSY_POLLS: u32 = 10_000;
+const BUSY_POLL_US: u32 = 1_000;
+
+// the csd follows its r1 within 8 bytes, then 16 bytes and a crc
+const CSD_RESPONSE: usize = 8 + 8 + 16 + 2;
+// csd version 2.0, the high capacity layout
+const CSD_V2: u8 = 1;
+
+// a read's data token comes within this many bytes of its r1, chip select has to stay low until the block is in so
+// the whole window is clocked in one transaction, the block and its crc after it
+const READ_WINDOW: usize = 1024;
+
+#[derive(Copy, Clone, PartialEq, Eq, defmt::Format)]
+pub enum Error {
+    /// spi transfer failed
+    Bus,
+    /// nothing answered CMD0, no card in the slot
+    NoCard,
+    /// the card answered a command with an error, the r1 response
+    Command(u8),
+    /// the card rejected a data block, the data response token, or answered a read with an error token
+    Rejected(u8),
+    /// the card didn't finish init or programming in time
+    Timeout,
+}
+
+/// SD card in spi mode, written a block at a time from a caller owned buffer so the block goes out by dma without a copy
+/// only v2 high capacity cards are supported, those are block addressed, every card sold for years is one
+pub struct SdCard<S, D> {
+    spi: S,
+    delay: D,
+    // blocks on the card, read from its csd at init
+    blocks: u32,
+}
+
+impl<S: SpiDevice, D: DelayNs> SdCard<S, D> {
+    pub fn new(spi: S, delay: D) -> Self {
+        Self { spi, delay, blocks: 0 }
+    }
+
+    /// the card's spi device, e.g. to raise the clock once init is done
+    pub fn spi(&mut self) -> &mut S {
+        &mut self.spi
+    }
+
+    // send a command and return its r1, the card answers within 8 bytes, a missing card leaves miso high
+    async fn command(&mut self, index: u8, argument: u32, crc: u8) -> Result<u8, Error> {
+        let argument = argument.to_be_bytes();
+        let frame = [0x40 | index, argument[0], argument[1], argument[2], argument[3], crc];
+        let mut response = [0xFFu8; 8];
+        self.spi
+            .transaction(&mut [Operation::Write(&frame), Operation::TransferInPlace(&mut response)])
+            .await
+            .map_err(|_| Error::Bus)?;
+        response.iter().copied().find(|r| r & 0x80 == 0).ok_or(Error::NoCard)
+    }
+
+    /// wake the card with 80 clocks, drop it into spi mode, and run init until it is ready for data
+    pub async fn init(&mut self) -> Result<(), Error> {
+        self.spi.write(&[0xFF; 10]).await.map_err(|_| Error::Bus)?;
+
+        let r1 = self.command(CMD_GO_IDLE, 0, 0x95).await?;
+        if r1 != R1_IDLE {
+            return Err(Error::Command(r1));
+        }
+        // v1 cards don't know CMD8 and are standard capacity, byte addressed
+        let r1 = self.command(CMD_SEND_IF_COND, IF_COND, 0x87).await?;
+        if r1 & R1_ILLEGAL_COMMAND != 0 {
+            return Err(Error::Command(r1));
+        }
+
+        for _ in 0..INIT_ATTEMPTS {
+            self.command(CMD_APP, 0, 0xFF).await?;
+            match self.command(ACMD_SEND_OP_COND, HCS, 0xFF).await? {
+                0 => {
+                    self.blocks = self.read_capacity().await?;
+                    return Ok(());
+                }
+                R1_IDLE => self.delay.delay_us(INIT_RETRY_US).await,
+                r1 => return Err(Error::Command(r1)),
+            }
+        }
+        Err(Error::Timeout)
+    }
+
+    /// write one block at a block address and wait for the card to finish programming it
+    pub async fn write_block(&mut self, address: u32, block: &[u8]) -> Result<(), Error> {
+        debug_assert_eq!(block.len(), BLOCK_SIZE);
+        let argument = address.to_be_bytes();
+        let frame = [0x40 | CMD_WRITE_BLOCK, argument[0], argument[1], argument[2], argument[3], 0xFF];
+        let mut r1 = [0xFFu8; 8];
+        let mut response = [0xFFu8; 4];
+
+        // command, data, and the data response in one transaction, chip select has to stay low across all of it
+        self.spi
+            .transaction(&mut [
+                Operation::Write(&frame),
+                Operation::TransferInPlace(&mut r1),
+                Operation::Write(&[DATA_START]),
+                Operation::Write(block),
+                Operation::Write(&[0xFF, 0xFF]),
+                Operation::TransferInPlace(&mut response),
+            ])
+            .await
+            .map_err(|_| Error::Bus)?;
+
+        match r1.iter().copied().find(|r| r & 0x80 == 0) {
+            Some(0) => {}
+            Some(r1) => return Err(Error::Command(r1)),
+            None => return Err(Error::NoCard),
+        }
+        let token = response.iter().copied().find(|r| *r != 0xFF).unwrap_or(0xFF);
+        if token & 0x1F != DATA_ACCEPTED {
+            return Err(Error::Rejected(token));
+        }
+
+        self.wait_ready(BUSY_POLLS).await
+    }
+
+    /// read one block at a block address
+    pub async fn read_block(&mut self, address: u32, block: &mut [u8]) -> Result<(), Error> {
+        debug_assert_eq!(block.len(), BLOCK_SIZE);
+        let argument = address.to_be_bytes();
+        let frame = [0x40 | CMD_READ_BLOCK, argument[0], argument[1], argument[2], argument[3], 0xFF];
+        let mut r1 = [0xFFu8; 8];
+        let mut response = [0xFFu8; READ_WINDOW + BLOCK_SIZE + 2];
+        self.spi
+            .transaction(&mut [
+                Operation::Write(&frame),
+                Operation::TransferInPlace(&mut r1),
+                Operation::TransferInPlace(&mut response),
+            ])
+            .await
+            .map_err(|_| Error::Bus)?;
+
+        match r1.iter().copied().find(|r| r & 0x80 == 0) {
+            Some(0) => {}
+            Some(r1) => return Err(Error::Command(r1)),
+            None => return Err(Error::NoCard),
+        }
+        let start = response[..READ_WINDOW].iter().position(|r| *r != 0xFF).ok_or(Error::Timeout)?;
+        if response[start] != DATA_START {
+            return Err(Error::Rejected(response[start]));
+        }
+        block.copy_from_slice(&response[start + 1..start + 1 + BLOCK_SIZE]);
+        Ok(())
+    }
+
+    /// erase count blocks from first, they read back as all zeros or all ones depending on the card
+    pub async fn erase(&mut self, first: u32, count: u32) -> Result<(), Error> {
+        if count == 0 {
+            return Ok(());
+        }
+        for (index, argument) in [(CMD_ERASE_START, first), (CMD_ERASE_END, first + count - 1), (CMD_ERASE, 0)] {
+            match self.command(index, argument, 0xFF).await? {
+                0 => {}
+                r1 => return Err(Error::Command(r1)),
+            }
+        }
+        self.wait_ready(ERASE_BUSY_POLLS).await
+    }
+
+    // card size from the c_size field of its csd, in 512 KB units on a high capacity card
+    async fn read_capacity(&mut self) -> Result<u32, Error> {
+        let frame = [0x40 | CMD_SEND_CSD, 0, 0, 0, 0, 0xFF];
+        let mut response = [0xFFu8; CSD_RESPONSE];
+        self.spi
+            .transaction(&mut [Operation::Write(&frame), Operation::TransferInPlace(&mut response)])
+            .await
+            .map_err(|_| Error::Bus)?;
+
+        let r1_at = response.iter().position(|r| r & 0x80 == 0).ok_or(Error::NoCard)?;
+        if response[r1_at] != 0 {
+            return Err(Error::Command(response[r1_at]));
+        }
+        let start = r1_at + 1 + response[r1_at + 1..].iter().position(|r| *r == DATA_START).ok_or(Error::Timeout)?;
+        let csd = response.get(start + 1..start + 17).ok_or(Error::Timeout)?;
+        if csd[0] >> 6 != CSD_V2 {
+            return Err(Error::Command(csd[0]));
+        }
+        let c_size = u32::from(csd[7] & 0x3F) << 16 | u32::from(csd[8]) << 8 | u32::from(csd[9]);
+        Ok((c_size + 1) * 1024)
+    }
+
+    // the card holds miso low while it is busy, it keeps going with chip select released
+    async fn wait_ready(&mut self, polls: u32) -> Result<(), Error> {
+        for _ in 0..polls {
+            let mut busy = [0u8];
+            self.spi.transfer_in_place(&mut busy).await.map_err(|_| Error::Bus)?;
+            if busy[0] == 0xFF {
+                return Ok(());
+            }
+            self.delay.delay_us(BUSY_POLL_US).await;
+        }
+        Err(Error::Timeout)
+    }
+}
+
+// blocks are programmed before write_block returns and the card erases as it writes
+impl<S: SpiDevice, D: DelayNs> LogStorage for SdCard<S, D> {
+    type Error = Error;
+
+    fn capacity(&self) -> u32 {
+        self.blocks
+    }
+
+    fn erase_size(&self) -> u32 {
+        0
+    }
+
+    async fn write_block(&mut self, address: u32, block: &[u8]) -> Result<(), Error> {
+        SdCard::write_block(self, address, block).await
+    }
+
+    async fn read_block(&mut self, address: u32, block: &mut [u8]) -> Result<(), Error> {
+        SdCard::read_block(self, address, block).await
+    }
+
+    async fn erase(&mut self, first: u32, count: u32) -> Result<(), Error> {
+        SdCard::erase(self, first, count).await
+    }
+
+    async fn sync(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+}
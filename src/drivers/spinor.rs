@@ -0,0 +1,180 @@
+use embedded_hal_async::delay::DelayNs;
+use embedded_hal_async::spi::{Operation, SpiDevice};
+
+use crate::blockqueue::BLOCK_SIZE;
+use crate::storage::LogStorage;
+
+const CMD_READ_ID: u8 = 0x9F;
+const CMD_READ: u8 = 0x03;
+const CMD_WRITE_ENABLE: u8 = 0x06;
+const CMD_READ_STATUS: u8 = 0x05;
+const CMD_PAGE_PROGRAM: u8 = 0x02;
+const CMD_SECTOR_ERASE: u8 = 0x20;
+
+// write in progress
+const STATUS_BUSY: u8 = 0x01;
+
+const PAGE_SIZE: usize = 256;
+// smallest erase, 4 KB, eight blocks
+const SECTOR_BLOCKS: u32 = 4096 / BLOCK_SIZE as u32;
+
+// capacity byte of the jedec id, log2 of the size in bytes, parts from 64 KB to 16 MB
+// 3 byte addresses reach 16 MB, a bigger part is used up to there
+const MIN_CAPACITY_LOG2: u8 = 16;
+const MAX_CAPACITY_LOG2: u8 = 24;
+
+// a page programs within 3 ms and a sector erases within 400 ms
+const PROGRAM_POLLS: u32 = 50;
+const ERASE_POLLS: u32 = 5_000;
+const BUSY_POLL_US: u32 = 100;
+
+#[derive(Copy, Clone, PartialEq, Eq, defmt::Format)]
+pub enum Error {
+    /// spi transfer failed
+    Bus,
+    /// the jedec id read back blank or with a capacity this driver doesn't know
+    NoFlash([u8; 3]),
+    /// the flash didn't finish programming or erasing in time
+    Timeout,
+    /// erase not on whole sectors
+    Unaligned,
+    /// block address past the end of the flash
+    OutOfRange,
+}
+
+/// Spi nor flash with the common command set (winbond, macronix, gigadevice and the like), written in 512 byte
+/// blocks of two pages and erased in 4 KB sectors
+/// a program or erase is started and left running, the next command waits for it, so the caller gets on with other
+/// work while the flash is busy and sync waits for the last one
+pub struct SpiNor<S, D> {
+    spi: S,
+    delay: D,
+    id: [u8; 3],
+    // blocks on the flash, from the jedec id at init
+    blocks: u32,
+    // how long the operation left running may take, in busy polls
+    pending_polls: u32,
+}
+
+impl<S: SpiDevice, D: DelayNs> SpiNor<S, D> {
+    pub fn new(spi: S, delay: D) -> Self {
+        Self { spi, delay, id: [0; 3], blocks: 0, pending_polls: 0 }
+    }
+
+    /// manufacturer, memory type, and capacity bytes of the jedec id, read at init
+    pub fn id(&self) -> [u8; 3] {
+        self.id
+    }
+
+    /// read the jedec id and the size from it
+    pub async fn init(&mut self) -> Result<(), Error> {
+        let mut id = [0u8; 3];
+        self.spi
+            .transaction(&mut [Operation::Write(&[CMD_READ_ID]), Operation::Read(&mut id)])
+            .await
+            .map_err(|_| Error::Bus)?;
+        if id == [0xFF; 3] || id == [0; 3] || id[2] < MIN_CAPACITY_LOG2 {
+            return Err(Error::NoFlash(id));
+        }
+        self.id = id;
+        self.blocks = (1 << id[2].min(MAX_CAPACITY_LOG2)) / BLOCK_SIZE as u32;
+        Ok(())
+    }
+
+    /// program one block at a block address, the flash under it has to be erased
+    pub async fn write_block(&mut self, address: u32, block: &[u8]) -> Result<(), Error> {
+        debug_assert_eq!(block.len(), BLOCK_SIZE);
+        if address >= self.blocks {
+            return Err(Error::OutOfRange);
+        }
+        for (index, page) in block.chunks(PAGE_SIZE).enumerate() {
+            let byte_address = address * BLOCK_SIZE as u32 + (index * PAGE_SIZE) as u32;
+            self.start(CMD_PAGE_PROGRAM, byte_address, page, PROGRAM_POLLS).await?;
+        }
+        Ok(())
+    }
+
+    /// read one block at a block address, waits out a program or erase still running
+    pub async fn read_block(&mut self, address: u32, block: &mut [u8]) -> Result<(), Error> {
+        if address >= self.blocks {
+            return Err(Error::OutOfRange);
+        }
+        self.wait_ready().await?;
+        let address = (address * BLOCK_SIZE as u32).to_be_bytes();
+        self.spi
+            .transaction(&mut [Operation::Write(&[CMD_READ, address[1], address[2], address[3]]), Operation::Read(block)])
+            .await
+            .map_err(|_| Error::Bus)
+    }
+
+    /// erase count blocks from first, whole sectors
+    pub async fn erase(&mut self, first: u32, count: u32) -> Result<(), Error> {
+        if !first.is_multiple_of(SECTOR_BLOCKS) || !count.is_multiple_of(SECTOR_BLOCKS) {
+            return Err(Error::Unaligned);
+        }
+        if first + count > self.blocks {
+            return Err(Error::OutOfRange);
+        }
+        for sector in (first..first + count).step_by(SECTOR_BLOCKS as usize) {
+            self.start(CMD_SECTOR_ERASE, sector * BLOCK_SIZE as u32, &[], ERASE_POLLS).await?;
+        }
+        Ok(())
+    }
+
+    // wait out the last operation, then enable writes and start a program or erase at a byte address
+    async fn start(&mut self, command: u8, address: u32, data: &[u8], polls: u32) -> Result<(), Error> {
+        self.wait_ready().await?;
+        let address = address.to_be_bytes();
+        self.spi.write(&[CMD_WRITE_ENABLE]).await.map_err(|_| Error::Bus)?;
+        self.spi
+            .transaction(&mut [Operation::Write(&[command, address[1], address[2], address[3]]), Operation::Write(data)])
+            .await
+            .map_err(|_| Error::Bus)?;
+        self.pending_polls = polls;
+        Ok(())
+    }
+
+    async fn wait_ready(&mut self) -> Result<(), Error> {
+        for _ in 0..=self.pending_polls {
+            let mut status = [0u8];
+            self.spi
+                .transaction(&mut [Operation::Write(&[CMD_READ_STATUS]), Operation::Read(&mut status)])
+                .await
+                .map_err(|_| Error::Bus)?;
+            if status[0] & STATUS_BUSY == 0 {
+                self.pending_polls = 0;
+                return Ok(());
+            }
+            self.delay.delay_us(BUSY_POLL_US).await;
+        }
+        Err(Error::Timeout)
+    }
+}
+
+impl<S: SpiDevice, D: DelayNs> LogStorage for SpiNor<S, D> {
+    type Error = Error;
+
+    fn capacity(&self) -> u32 {
+        self.blocks
+    }
+
+    fn erase_size(&self) -> u32 {
+        SECTOR_BLOCKS
+    }
+
+    async fn write_block(&mut self, address: u32, block: &[u8]) -> Result<(), Error> {
+        SpiNor::write_block(self, address, block).await
+    }
+
+    async fn read_block(&mut self, address: u32, block: &mut [u8]) -> Result<(), Error> {
+        SpiNor::read_block(self, address, block).await
+    }
+
+    async fn erase(&mut self, first: u32, count: u32) -> Result<(), Error> {
+        SpiNor::erase(self, first, count).await
+    }
+
+    async fn sync(&mut self) -> Result<(), Error> {
+        self.wait_ready().await
+    }
+}
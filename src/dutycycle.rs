@@ -0,0 +1,104 @@
+// duty cycled operation for multi day floats, the payload is awake for a window every period to sample, log and
+// beacon, and sleeps out the rest of the period in stop mode with the payload rails off, woken by the rtc
+// only a float sleeps, an ascent whose vertical speed has stayed near zero for a while or a payload back on the ground
+// waiting for recovery, never on the pad where the crew works with it or on the way down
+// ram is kept through stop mode so a wakeup carries on where it left off, but the uptime clock stands still meanwhile
+
+use crate::mission::FlightState;
+
+/// vertical speed within which an ascent counts as floating, m/s
+pub const FLOAT_SPEED: f32 = 0.5;
+
+/// how long the vertical speed has to stay within FLOAT_SPEED before the first sleep, us
+pub const FLOAT_HOLD: u32 = 600_000_000;
+
+/// shortest sleep worth powering the rails down and back up for, s
+pub const MIN_SLEEP: u32 = 10;
+
+/// What the duty cycle looks at each pass
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct DutyInput {
+    pub state: FlightState,
+    /// filtered vertical speed, m/s, None before the first baro sample
+    pub vertical_speed: Option<f32>,
+    /// something the payload has to stay up for, a firing or a queued command
+    pub busy: bool,
+    /// s until something has to be done awake, a held command's mission elapsed time, a sleep ends by then
+    pub wake_by: Option<u32>,
+    /// us since boot, not counting sleeps
+    pub time_stamp: u32,
+}
+
+/// Decides when the payload sleeps and for how long
+pub struct DutyCycle {
+    // wake to wake and the awake window, s, a zero period turns duty cycling off
+    period: u32,
+    awake: u32,
+    // start of the current run of vertical speeds within FLOAT_SPEED
+    quiet_since: Option<u32>,
+    // the float was confirmed, later wakeups don't wait out FLOAT_HOLD again
+    floating: bool,
+    // start of the awake window, moved on by a wakeup or an uplinked command
+    awake_since: Option<u32>,
+}
+
+impl Default for DutyCycle {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DutyCycle {
+    pub const fn new() -> Self {
+        Self {
+            period: 0,
+            awake: 0,
+            quiet_since: None,
+            floating: false,
+            awake_since: None,
+        }
+    }
+
+    /// period and awake window, s, a zero period turns duty cycling off
+    pub fn configure(&mut self, period: u32, awake: u32) {
+        self.period = period;
+        self.awake = awake;
+    }
+
+    /// a float or a landing was confirmed
+    pub fn floating(&self) -> bool {
+        self.floating
+    }
+
+    /// restart the awake window, after a wakeup or when the ground is talking to the payload
+    pub fn stay_awake(&mut self, time_stamp: u32) {
+        self.awake_since = Some(time_stamp);
+    }
+
+    /// seconds to sleep once the awake window is over, None while the payload has to stay up
+    pub fn update(&mut self, input: &DutyInput) -> Option<u32> {
+        match (input.state, input.vertical_speed) {
+            (FlightState::Ascent, Some(speed)) if speed.abs() <= FLOAT_SPEED => {
+                let since = *self.quiet_since.get_or_insert(input.time_stamp);
+                if input.time_stamp.wrapping_sub(since) >= FLOAT_HOLD {
+                    self.floating = true;
+                }
+            }
+            (FlightState::Ascent, None) => {}
+            (FlightState::Landed, _) => self.floating = true,
+            // climbing or sinking again, or burst, a new float has to be confirmed before sleeping
+            _ => {
+                self.quiet_since = None;
+                self.floating = false;
+            }
+        }
+
+        let awake_since = *self.awake_since.get_or_insert(input.time_stamp);
+        let awake_for = input.time_stamp.wrapping_sub(awake_since) / 1_000_000;
+        if self.period == 0 || !self.floating || input.busy || awake_for < self.awake {
+            return None;
+        }
+        let sleep = self.period.saturating_sub(awake_for).min(input.wake_by.unwrap_or(u32::MAX));
+        (sleep >= MIN_SLEEP).then_some(sleep)
+    }
+}
@@ -0,0 +1,90 @@
+use crate::filters::{AltitudeKalman, MovingAverage};
+use crate::{AttitudeData, ImuData, VerticalState};
+
+/// Turns barometric altitude samples into filtered altitude and vertical speed
+/// the baro task only talks to this trait, so the filter behind it is chosen per build
+pub trait AltitudeEstimator {
+    /// add an altitude sample (m) taken at time_stamp (us) and return the new estimate
+    fn update(&mut self, altitude: f32, time_stamp: u32) -> VerticalState;
+}
+
+/// Turns imu samples into an attitude estimate
+/// the attitude task only talks to this trait, so the filter behind it is chosen per build
+pub trait AttitudeEstimator {
+    /// add an imu sample and return the new estimate, the interval comes from the sample timestamps
+    fn update(&mut self, data: &ImuData) -> AttitudeData;
+
+    /// magnitude of the gravity direction error from the last update
+    fn error(&self) -> f32;
+
+    /// true once the estimate has settled and can be trusted
+    fn converged(&self) -> bool;
+}
+
+/// seconds between the previous timestamp and now, and remember now as the previous one
+/// None on the first sample or a repeated timestamp, timestamps are microseconds and wrap
+pub fn seconds_since(previous: &mut Option<u32>, now: u32) -> Option<f32> {
+    let dt = previous.replace(now).map(|prev| now.wrapping_sub(prev) as f32 / 1_000_000.0)?;
+    (dt > 0.0).then_some(dt)
+}
+
+impl AltitudeEstimator for AltitudeKalman {
+    fn update(&mut self, altitude: f32, time_stamp: u32) -> VerticalState {
+        let (altitude, vertical_speed) = AltitudeKalman::update(self, altitude, time_stamp);
+        VerticalState {
+            altitude,
+            vertical_speed,
+            time_stamp,
+        }
+    }
+}
+
+/// Rolling average altitude with vertical speed from the low passed slope of the average
+/// simple and predictable, lags more than the kalman filter
+pub struct RollingAverageAltitude<const N: usize> {
+    average: MovingAverage<N>,
+    /// smoothing factor for the vertical speed (0..1, higher is less smoothing)
+    alpha: f32,
+    previous: Option<(f32, u32)>,
+    vertical_speed: f32,
+}
+
+impl<const N: usize> RollingAverageAltitude<N> {
+    pub const fn new(alpha: f32) -> Self {
+        Self {
+            average: MovingAverage::new(),
+            alpha,
+            previous: None,
+            vertical_speed: 0.0,
+        }
+    }
+}
+
+impl<const N: usize> AltitudeEstimator for RollingAverageAltitude<N> {
+    fn update(&mut self, altitude: f32, time_stamp: u32) -> VerticalState {
+        // like the kalman filter, a non finite sample is skipped rather than averaged into every later output
+        if !altitude.is_finite() {
+            return VerticalState {
+                altitude: self.average.value(),
+                vertical_speed: self.vertical_speed,
+                time_stamp,
+            };
+        }
+        let average = self.average.update(altitude);
+
+        if let Some((prev_average, prev_time_stamp)) = self.previous {
+            let dt = time_stamp.wrapping_sub(prev_time_stamp) as f32 / 1_000_000.0;
+            if dt > 0.0 {
+                let raw_speed = (average - prev_average) / dt;
+                self.vertical_speed += self.alpha * (raw_speed - self.vertical_speed);
+            }
+        }
+        self.previous = Some((average, time_stamp));
+
+        VerticalState {
+            altitude: average,
+            vertical_speed: self.vertical_speed,
+            time_stamp,
+        }
+    }
+}
@@ -0,0 +1,157 @@
+use crate::health::Stream;
+
+/// Things that can go wrong in flight, reported by whichever task notices them
+#[derive(Copy, Clone, PartialEq, Eq, Debug, defmt::Format)]
+pub enum Fault {
+    /// a data stream stopped producing
+    Stale(Stream),
+    /// writing the calibration to flash failed
+    CalibrationWrite,
+    /// the board was reset by a panic, reported at the next boot
+    Panic,
+    /// a task missed its watchdog check in, the board resets shortly after
+    Watchdog,
+    /// gps fix lost, position is dead reckoned
+    GpsLost,
+    /// supply voltage low, logging stopped and radio down to the beacon
+    LowVoltage,
+    /// gps receiver looks locked out near the cocom altitude, its fixes are ignored
+    GpsLockout,
+    /// an armed actuation channel lost continuity through its load
+    Continuity,
+    /// the two barometers disagree or one stopped reading
+    BaroDivergence,
+    /// the two imus disagree or one stopped reading
+    ImuDivergence,
+    /// the control loop missed several periods, outputs held in the safe state until it keeps up again
+    ControlHang,
+    /// battery below the cold limit, firing, full radio power, and load shedding of the heaters held off
+    BatteryCold,
+}
+
+impl Fault {
+    /// every fault, in bit order
+    pub const ALL: [Fault; 16] = [
+        Fault::Stale(Stream::Baro),
+        Fault::Stale(Stream::Imu),
+        Fault::Stale(Stream::Attitude),
+        Fault::Stale(Stream::Gps),
+        Fault::Stale(Stream::Nav),
+        Fault::CalibrationWrite,
+        Fault::Panic,
+        Fault::Watchdog,
+        Fault::GpsLost,
+        Fault::LowVoltage,
+        Fault::GpsLockout,
+        Fault::Continuity,
+        Fault::BaroDivergence,
+        Fault::ImuDivergence,
+        Fault::ControlHang,
+        Fault::BatteryCold,
+    ];
+
+    fn bit(self) -> u16 {
+        let index = match self {
+            Fault::Stale(stream) => stream as u16,
+            Fault::CalibrationWrite => 5,
+            Fault::Panic => 6,
+            Fault::Watchdog => 7,
+            Fault::GpsLost => 8,
+            Fault::LowVoltage => 9,
+            Fault::GpsLockout => 10,
+            Fault::Continuity => 11,
+            Fault::BaroDivergence => 12,
+            Fault::ImuDivergence => 13,
+            Fault::ControlHang => 14,
+            Fault::BatteryCold => 15,
+        };
+        1 << index
+    }
+}
+
+/// Set of currently active faults, one bit per fault
+#[derive(Copy, Clone, PartialEq, Eq, Default, defmt::Format)]
+pub struct FaultFlags(pub u16);
+
+impl FaultFlags {
+    pub const NONE: FaultFlags = FaultFlags(0);
+
+    pub fn contains(self, fault: Fault) -> bool {
+        self.0 & fault.bit() != 0
+    }
+
+    pub fn set(&mut self, fault: Fault, active: bool) {
+        if active {
+            self.0 |= fault.bit();
+        } else {
+            self.0 &= !fault.bit();
+        }
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    /// the faults in the set, in bit order
+    pub fn iter(self) -> impl Iterator<Item = Fault> {
+        Fault::ALL.into_iter().filter(move |&fault| self.contains(fault))
+    }
+}
+
+/// A fault being raised (active) or cleared
+#[derive(Copy, Clone, PartialEq, Eq, defmt::Format)]
+pub struct FaultEvent {
+    pub fault: Fault,
+    pub active: bool,
+    pub time_stamp: u32,
+}
+
+/// Ring buffer of the last N fault events plus the currently active set
+pub struct FaultLog<const N: usize> {
+    events: [Option<FaultEvent>; N],
+    next: usize,
+    flags: FaultFlags,
+}
+
+impl<const N: usize> Default for FaultLog<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> FaultLog<N> {
+    pub const fn new() -> Self {
+        Self {
+            events: [None; N],
+            next: 0,
+            flags: FaultFlags::NONE,
+        }
+    }
+
+    pub fn flags(&self) -> FaultFlags {
+        self.flags
+    }
+
+    /// record an event, returns false and keeps nothing if it doesn't change the active set
+    /// so a task can report its condition every loop without flooding the buffer
+    pub fn report(&mut self, event: FaultEvent) -> bool {
+        if self.flags.contains(event.fault) == event.active {
+            return false;
+        }
+
+        self.flags.set(event.fault, event.active);
+        self.events[self.next] = Some(event);
+        self.next = (self.next + 1) % N;
+        true
+    }
+
+    /// the M most recent events, newest first
+    pub fn recent<const M: usize>(&self) -> [Option<FaultEvent>; M] {
+        core::array::from_fn(|i| {
+            if i >= N {
+                return None;
+            }
+            self.events[(self.next + N - 1 - i) % N]
+        })
+    }
+}
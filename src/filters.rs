@@ -0,0 +1,424 @@
+use libm::{cosf, sinf};
+
+/// Moving average over the last N samples
+pub struct MovingAverage<const N: usize> {
+    window: [f32; N],
+    len: usize,
+    next: usize,
+}
+
+impl<const N: usize> Default for MovingAverage<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> MovingAverage<N> {
+    /// empty filter, averages over however many samples it has seen until the window fills
+    pub const fn new() -> Self {
+        Self {
+            window: [0.0; N],
+            len: 0,
+            next: 0,
+        }
+    }
+
+    /// filter with the window already full of a known starting value
+    pub const fn filled(value: f32) -> Self {
+        Self {
+            window: [value; N],
+            len: N,
+            next: 0,
+        }
+    }
+
+    /// add a sample and return the new average
+    pub fn update(&mut self, sample: f32) -> f32 {
+        self.window[self.next] = sample;
+        self.next = (self.next + 1) % N;
+        self.len = (self.len + 1).min(N);
+        self.value()
+    }
+
+    /// true once N samples have been added
+    pub fn is_full(&self) -> bool {
+        self.len == N
+    }
+
+    /// current average, zero if no samples have been added
+    pub fn value(&self) -> f32 {
+        if self.len == 0 {
+            return 0.0;
+        }
+        // the window fills from index 0, so the first len entries are the valid ones until it wraps
+        self.window[..self.len].iter().sum::<f32>() / self.len as f32
+    }
+}
+
+/// Second order IIR filter (biquad), direct form II transposed
+/// coefficients are normalized so a0 = 1
+pub struct Biquad {
+    b: [f32; 3],
+    a: [f32; 2],
+    z: [f32; 2],
+}
+
+impl Biquad {
+    /// filter from raw normalized coefficients, b0 b1 b2 feed forward and a1 a2 feedback
+    pub const fn new(b: [f32; 3], a: [f32; 2]) -> Self {
+        Self { b, a, z: [0.0; 2] }
+    }
+
+    /// butterworth style low pass (RBJ cookbook), q of 0.7071 gives a maximally flat passband
+    pub fn low_pass(sample_rate: f32, cutoff: f32, q: f32) -> Self {
+        let (cos_w, alpha) = Self::prewarp(sample_rate, cutoff, q);
+        let a0 = 1.0 + alpha;
+        let b1 = (1.0 - cos_w) / a0;
+        Self::new([b1 / 2.0, b1, b1 / 2.0], [-2.0 * cos_w / a0, (1.0 - alpha) / a0])
+    }
+
+    /// high pass counterpart of low_pass
+    pub fn high_pass(sample_rate: f32, cutoff: f32, q: f32) -> Self {
+        let (cos_w, alpha) = Self::prewarp(sample_rate, cutoff, q);
+        let a0 = 1.0 + alpha;
+        let b1 = -(1.0 + cos_w) / a0;
+        Self::new([-b1 / 2.0, b1, -b1 / 2.0], [-2.0 * cos_w / a0, (1.0 - alpha) / a0])
+    }
+
+    /// set the internal state as if the filter had been settled at a constant input, avoids a start up transient
+    pub fn reset(&mut self, value: f32) {
+        let [b0, b1, b2] = self.b;
+        let [a1, a2] = self.a;
+        let gain = (b0 + b1 + b2) / (1.0 + a1 + a2);
+        let y = value * gain;
+        self.z[1] = b2 * value - a2 * y;
+        self.z[0] = b1 * value - a1 * y + self.z[1];
+    }
+
+    /// filter one sample
+    pub fn update(&mut self, sample: f32) -> f32 {
+        let [b0, b1, b2] = self.b;
+        let [a1, a2] = self.a;
+        let y = b0 * sample + self.z[0];
+        self.z[0] = b1 * sample - a1 * y + self.z[1];
+        self.z[1] = b2 * sample - a2 * y;
+        y
+    }
+
+    fn prewarp(sample_rate: f32, cutoff: f32, q: f32) -> (f32, f32) {
+        let w = 2.0 * core::f32::consts::PI * cutoff / sample_rate;
+        (cosf(w), sinf(w) / (2.0 * q))
+    }
+}
+
+/// Hampel outlier filter
+/// keeps the last N samples and replaces any sample that is further than k scaled median absolute deviations
+/// from the window median with the median, clean data passes through unchanged with no added lag
+pub struct HampelFilter<const N: usize> {
+    window: [f32; N],
+    len: usize,
+    next: usize,
+    k: f32,
+    min_deviation: f32,
+}
+
+/// scales the median absolute deviation to a standard deviation for normally distributed noise
+const MAD_SCALE: f32 = 1.4826;
+
+impl<const N: usize> HampelFilter<N> {
+    /// k is the rejection threshold in standard deviations, min_deviation is the smallest deviation ever
+    /// rejected so perfectly steady data doesn't turn every tiny change into an outlier
+    pub const fn new(k: f32, min_deviation: f32) -> Self {
+        Self {
+            window: [0.0; N],
+            len: 0,
+            next: 0,
+            k,
+            min_deviation,
+        }
+    }
+
+    /// filter one sample, returns the sample or the window median if it was rejected as an outlier
+    pub fn update(&mut self, sample: f32) -> f32 {
+        // not enough history to judge yet, and non finite samples are always outliers
+        if self.len < N {
+            if sample.is_finite() {
+                self.push(sample);
+            }
+            return sample;
+        }
+
+        let center = median(self.window);
+        let mad = median(self.window.map(|x| (x - center).abs()));
+        let threshold = (self.k * MAD_SCALE * mad).max(self.min_deviation);
+
+        let output = if sample.is_finite() && (sample - center).abs() <= threshold {
+            sample
+        } else {
+            center
+        };
+
+        // the window keeps raw samples, the median is robust to the odd glitch and this way a genuine
+        // fast pressure change can't get locked out by a window full of replaced values
+        if sample.is_finite() {
+            self.push(sample);
+        }
+        output
+    }
+
+    fn push(&mut self, sample: f32) {
+        self.window[self.next] = sample;
+        self.next = (self.next + 1) % N;
+        self.len = (self.len + 1).min(N);
+    }
+}
+
+/// median of a small window, sorts a copy
+fn median<const N: usize>(mut window: [f32; N]) -> f32 {
+    window.sort_unstable_by(f32::total_cmp);
+    if N % 2 == 1 {
+        window[N / 2]
+    } else {
+        (window[N / 2 - 1] + window[N / 2]) / 2.0
+    }
+}
+
+/// Constant velocity Kalman filter for altitude and vertical speed
+/// driven by the sample timestamps, so late or missed samples are handled by propagating over the real interval
+pub struct AltitudeKalman {
+    /// altitude (m) and vertical speed (m/s)
+    x: [f32; 2],
+    p: [[f32; 2]; 2],
+    accel_sigma: f32,
+    measurement_sigma: f32,
+    last_time_stamp: Option<u32>,
+}
+
+/// initial vertical speed uncertainty, m/s
+const INITIAL_SPEED_SIGMA: f32 = 10.0;
+
+impl AltitudeKalman {
+    /// accel_sigma is how hard the vertical speed is expected to change (m/s^2),
+    /// measurement_sigma is the altitude measurement noise (m)
+    pub const fn new(accel_sigma: f32, measurement_sigma: f32) -> Self {
+        Self {
+            x: [0.0; 2],
+            p: [[0.0; 2]; 2],
+            accel_sigma,
+            measurement_sigma,
+            last_time_stamp: None,
+        }
+    }
+
+    pub fn altitude(&self) -> f32 {
+        self.x[0]
+    }
+
+    pub fn vertical_speed(&self) -> f32 {
+        self.x[1]
+    }
+
+    /// add an altitude measurement taken at time_stamp (us), returns (altitude, vertical speed)
+    pub fn update(&mut self, altitude: f32, time_stamp: u32) -> (f32, f32) {
+        if !altitude.is_finite() {
+            return (self.x[0], self.x[1]);
+        }
+
+        let Some(prev) = self.last_time_stamp.replace(time_stamp) else {
+            // first sample, start at the measurement with unknown speed
+            self.x = [altitude, 0.0];
+            self.p = [
+                [self.measurement_sigma * self.measurement_sigma, 0.0],
+                [0.0, INITIAL_SPEED_SIGMA * INITIAL_SPEED_SIGMA],
+            ];
+            return (self.x[0], self.x[1]);
+        };
+
+        // timestamps are microseconds and wrap, wrapping_sub gives the right interval across one wrap
+        // a repeated timestamp just skips the prediction
+        let dt = time_stamp.wrapping_sub(prev) as f32 / 1_000_000.0;
+        if dt > 0.0 {
+            self.predict(dt);
+        }
+
+        // measurement update, only altitude is observed
+        let r = self.measurement_sigma * self.measurement_sigma;
+        let s = self.p[0][0] + r;
+        let k = [self.p[0][0] / s, self.p[1][0] / s];
+        let innovation = altitude - self.x[0];
+        self.x[0] += k[0] * innovation;
+        self.x[1] += k[1] * innovation;
+
+        let p = self.p;
+        self.p = [
+            [(1.0 - k[0]) * p[0][0], (1.0 - k[0]) * p[0][1]],
+            [p[1][0] - k[1] * p[0][0], p[1][1] - k[1] * p[0][1]],
+        ];
+
+        (self.x[0], self.x[1])
+    }
+
+    fn predict(&mut self, dt: f32) {
+        self.x[0] += self.x[1] * dt;
+
+        // P = F P F^T + Q, white acceleration noise
+        let p = self.p;
+        let q = self.accel_sigma * self.accel_sigma;
+        let dt2 = dt * dt;
+        self.p = [
+            [
+                p[0][0] + dt * (p[0][1] + p[1][0]) + dt2 * p[1][1] + 0.25 * dt2 * dt2 * q,
+                p[0][1] + dt * p[1][1] + 0.5 * dt2 * dt * q,
+            ],
+            [
+                p[1][0] + dt * p[1][1] + 0.5 * dt2 * dt * q,
+                p[1][1] + dt2 * q,
+            ],
+        ];
+    }
+}
+
+/// Complementary filter blending an integrated rate with an absolute measurement
+/// the rate is trusted over short time scales and the measurement over long ones
+pub struct Complementary {
+    /// weight on the integrated rate (0..1), higher follows the rate longer before the measurement pulls it back
+    alpha: f32,
+    value: Option<f32>,
+}
+
+impl Complementary {
+    pub const fn new(alpha: f32) -> Self {
+        Self { alpha, value: None }
+    }
+
+    /// fuse one sample, rate is the derivative of the measured quantity and dt is in seconds
+    pub fn update(&mut self, measurement: f32, rate: f32, dt: f32) -> f32 {
+        let value = match self.value {
+            Some(value) => self.alpha * (value + rate * dt) + (1.0 - self.alpha) * measurement,
+            None => measurement,
+        };
+        self.value = Some(value);
+        value
+    }
+}
+
+/// Scalar Kalman filter for a slowly wandering value (random walk model)
+pub struct Kalman1D {
+    x: f32,
+    p: f32,
+    /// process noise variance added each step
+    q: f32,
+    /// measurement noise variance
+    r: f32,
+}
+
+impl Kalman1D {
+    /// starts at initial with variance p, q and r are the process and measurement noise variances
+    pub const fn new(initial: f32, p: f32, q: f32, r: f32) -> Self {
+        Self { x: initial, p, q, r }
+    }
+
+    /// add a measurement and return the new estimate
+    pub fn update(&mut self, measurement: f32) -> f32 {
+        self.p += self.q;
+        let k = self.p / (self.p + self.r);
+        self.x += k * (measurement - self.x);
+        self.p *= 1.0 - k;
+        self.x
+    }
+
+    pub fn value(&self) -> f32 {
+        self.x
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // baro trace in hPa, a slow climb with the ways a read on the shared sensor bus goes wrong, a read that came back
+    // 0, a flipped high bit, a two sample spike, and a non finite conversion
+    const GLITCHY_PRESSURE: [f32; 24] = [
+        1013.20, 1013.18, 1013.21, 1013.17, 1013.15, 1013.16, 1013.12, 0.0, 1013.10, 1013.09, 1013.07, 1141.06,
+        1013.04, 1013.03, 1021.90, 1021.85, 1013.00, 1012.98, f32::NAN, 1012.95, 1012.94, 1012.92, 1012.90, 1012.89,
+    ];
+    const GLITCHES: [usize; 5] = [7, 11, 14, 15, 18];
+
+    #[test]
+    fn hampel_replaces_glitches_only() {
+        let mut filter = HampelFilter::<7>::new(3.0, 0.5);
+        for (index, &sample) in GLITCHY_PRESSURE.iter().enumerate() {
+            let output = filter.update(sample);
+            if GLITCHES.contains(&index) {
+                assert!((output - 1013.1).abs() < 0.15, "glitch at {index} let through as {output}");
+            } else {
+                assert_eq!(output, sample, "clean sample at {index} changed");
+            }
+        }
+    }
+
+    #[test]
+    fn hampel_follows_a_real_step() {
+        // a pressure change that persists is let through once it fills half the window
+        let mut filter = HampelFilter::<7>::new(3.0, 0.5);
+        for _ in 0..7 {
+            filter.update(1000.0);
+        }
+        let outputs: [f32; 7] = core::array::from_fn(|_| filter.update(990.0));
+        assert_eq!(outputs[0], 1000.0);
+        assert_eq!(outputs[6], 990.0);
+        assert!(outputs.iter().position(|&output| output == 990.0).is_some_and(|index| index <= 4));
+    }
+
+    #[test]
+    fn hampel_passes_samples_until_the_window_fills() {
+        let mut filter = HampelFilter::<5>::new(3.0, 0.5);
+        assert_eq!(filter.update(1013.0), 1013.0);
+        assert_eq!(filter.update(0.0), 0.0);
+        assert!(filter.update(f32::NAN).is_nan());
+    }
+
+    #[test]
+    fn moving_average_fills_then_slides() {
+        let mut average = MovingAverage::<4>::new();
+        assert_eq!(average.value(), 0.0);
+        assert_eq!(average.update(2.0), 2.0);
+        assert_eq!(average.update(4.0), 3.0);
+        assert!(!average.is_full());
+        average.update(6.0);
+        assert_eq!(average.update(8.0), 5.0);
+        assert!(average.is_full());
+        // the oldest sample drops out
+        assert_eq!(average.update(10.0), 7.0);
+        assert_eq!(MovingAverage::<4>::filled(3.0).update(7.0), 4.0);
+    }
+
+    #[test]
+    fn biquad_low_pass_passes_dc_and_cuts_high_frequencies() {
+        let mut filter = Biquad::low_pass(100.0, 5.0, core::f32::consts::FRAC_1_SQRT_2);
+        let settled = (0..500).map(|_| filter.update(1.0)).last().unwrap();
+        assert!((settled - 1.0).abs() < 1e-4, "{settled}");
+
+        // 40 Hz is three octaves past the cutoff, a second order butterworth takes it down by well over 30 dB
+        let mut filter = Biquad::low_pass(100.0, 5.0, core::f32::consts::FRAC_1_SQRT_2);
+        let tone = |n: usize| sinf(2.0 * core::f32::consts::PI * 40.0 * n as f32 / 100.0);
+        let peak = (0..500).map(|n| filter.update(tone(n)).abs()).skip(400).fold(0.0, f32::max);
+        assert!(peak < 0.03, "{peak}");
+    }
+
+    #[test]
+    fn biquad_high_pass_removes_dc() {
+        let mut filter = Biquad::high_pass(100.0, 1.0, core::f32::consts::FRAC_1_SQRT_2);
+        let settled = (0..2000).map(|_| filter.update(5.0)).last().unwrap();
+        assert!(settled.abs() < 1e-3, "{settled}");
+    }
+
+    #[test]
+    fn biquad_reset_starts_settled() {
+        let mut filter = Biquad::low_pass(100.0, 5.0, core::f32::consts::FRAC_1_SQRT_2);
+        filter.reset(1013.25);
+        for _ in 0..50 {
+            assert!((filter.update(1013.25) - 1013.25).abs() < 1e-2);
+        }
+    }
+}
@@ -0,0 +1,215 @@
+use core::ops::{Add, Div, Mul, Sub};
+
+/// Signed Q16.16 fixed point number, 16 integer bits and 16 fraction bits
+/// range is about +-32768 with a resolution of 1/65536, the filters below track their f32 counterparts
+/// in filters.rs to within that resolution on boards without an fpu
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default, defmt::Format)]
+pub struct Q16(pub i32);
+
+const FRACTION_BITS: u32 = 16;
+
+impl Q16 {
+    pub const ZERO: Q16 = Q16(0);
+    pub const ONE: Q16 = Q16(1 << FRACTION_BITS);
+
+    pub const fn from_int(value: i16) -> Self {
+        Q16((value as i32) << FRACTION_BITS)
+    }
+
+    /// conversion for constants and logging, saturates outside the representable range
+    pub fn from_f32(value: f32) -> Self {
+        Q16((value * Self::ONE.0 as f32) as i32)
+    }
+
+    pub fn to_f32(self) -> f32 {
+        self.0 as f32 / Self::ONE.0 as f32
+    }
+}
+
+impl Add for Q16 {
+    type Output = Q16;
+    fn add(self, rhs: Q16) -> Q16 {
+        Q16(self.0.saturating_add(rhs.0))
+    }
+}
+
+impl Sub for Q16 {
+    type Output = Q16;
+    fn sub(self, rhs: Q16) -> Q16 {
+        Q16(self.0.saturating_sub(rhs.0))
+    }
+}
+
+impl Mul for Q16 {
+    type Output = Q16;
+    fn mul(self, rhs: Q16) -> Q16 {
+        saturate((self.0 as i64 * rhs.0 as i64) >> FRACTION_BITS)
+    }
+}
+
+/// division rounding towards zero, saturates on overflow and on division by zero
+impl Div for Q16 {
+    type Output = Q16;
+    fn div(self, rhs: Q16) -> Q16 {
+        if rhs.0 == 0 {
+            return if self.0 < 0 { Q16(i32::MIN) } else { Q16(i32::MAX) };
+        }
+        saturate(((self.0 as i64) << FRACTION_BITS) / rhs.0 as i64)
+    }
+}
+
+fn saturate(value: i64) -> Q16 {
+    Q16(value.clamp(i32::MIN as i64, i32::MAX as i64) as i32)
+}
+
+/// Fixed point counterpart of filters::MovingAverage
+pub struct FixedMovingAverage<const N: usize> {
+    window: [Q16; N],
+    len: usize,
+    next: usize,
+}
+
+impl<const N: usize> Default for FixedMovingAverage<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> FixedMovingAverage<N> {
+    pub const fn new() -> Self {
+        Self {
+            window: [Q16::ZERO; N],
+            len: 0,
+            next: 0,
+        }
+    }
+
+    /// add a sample and return the new average
+    pub fn update(&mut self, sample: Q16) -> Q16 {
+        self.window[self.next] = sample;
+        self.next = (self.next + 1) % N;
+        self.len = (self.len + 1).min(N);
+        self.value()
+    }
+
+    /// current average, zero if no samples have been added
+    pub fn value(&self) -> Q16 {
+        if self.len == 0 {
+            return Q16::ZERO;
+        }
+        // sum in 64 bits so a full window of large values can't overflow
+        let sum: i64 = self.window[..self.len].iter().map(|x| x.0 as i64).sum();
+        saturate(sum / self.len as i64)
+    }
+}
+
+/// Fixed point counterpart of filters::Complementary
+pub struct FixedComplementary {
+    alpha: Q16,
+    value: Option<Q16>,
+}
+
+impl FixedComplementary {
+    pub const fn new(alpha: Q16) -> Self {
+        Self { alpha, value: None }
+    }
+
+    /// fuse one sample, rate is the derivative of the measured quantity and dt is in seconds
+    pub fn update(&mut self, measurement: Q16, rate: Q16, dt: Q16) -> Q16 {
+        let value = match self.value {
+            Some(value) => self.alpha * (value + rate * dt) + (Q16::ONE - self.alpha) * measurement,
+            None => measurement,
+        };
+        self.value = Some(value);
+        value
+    }
+}
+
+/// Fixed point counterpart of filters::Kalman1D
+/// variances are small numbers, keep q and r well above the resolution or the gain rounds to zero
+pub struct FixedKalman1D {
+    x: Q16,
+    p: Q16,
+    q: Q16,
+    r: Q16,
+}
+
+impl FixedKalman1D {
+    pub const fn new(initial: Q16, p: Q16, q: Q16, r: Q16) -> Self {
+        Self { x: initial, p, q, r }
+    }
+
+    /// add a measurement and return the new estimate
+    pub fn update(&mut self, measurement: Q16) -> Q16 {
+        self.p = self.p + self.q;
+        let k = self.p / (self.p + self.r);
+        self.x = self.x + k * (measurement - self.x);
+        self.p = (Q16::ONE - k) * self.p;
+        self.x
+    }
+
+    pub fn value(&self) -> Q16 {
+        self.x
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filters::{Complementary, Kalman1D, MovingAverage};
+
+    /// noisy ramp, the same samples for both versions, from a small lcg so the test is repeatable
+    fn samples() -> impl Iterator<Item = f32> {
+        let mut state = 0x1234_5678u32;
+        (0..400).map(move |n| {
+            state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            n as f32 * 0.05 + (state >> 8) as f32 / (1 << 24) as f32 - 0.5
+        })
+    }
+
+    #[test]
+    fn arithmetic() {
+        let a = Q16::from_f32(3.25);
+        let b = Q16::from_f32(-1.5);
+        assert_eq!((a + b).to_f32(), 1.75);
+        assert_eq!((a - b).to_f32(), 4.75);
+        assert_eq!((a * b).to_f32(), -4.875);
+        assert!(((a / b).to_f32() + 2.166_666).abs() < 1.0 / 65536.0);
+        assert_eq!(Q16::from_int(7), Q16::from_f32(7.0));
+        // saturates instead of wrapping
+        assert_eq!(Q16::from_int(30000) * Q16::from_int(30000), Q16(i32::MAX));
+        assert_eq!(Q16::from_int(-1) / Q16::ZERO, Q16(i32::MIN));
+        assert_eq!(Q16::from_f32(1e9), Q16(i32::MAX));
+    }
+
+    #[test]
+    fn moving_average_matches_f32() {
+        let mut fixed = FixedMovingAverage::<8>::new();
+        let mut float = MovingAverage::<8>::new();
+        for sample in samples() {
+            let difference = fixed.update(Q16::from_f32(sample)).to_f32() - float.update(sample);
+            assert!(difference.abs() < 2e-4, "{difference}");
+        }
+    }
+
+    #[test]
+    fn complementary_matches_f32() {
+        let mut fixed = FixedComplementary::new(Q16::from_f32(0.98));
+        let mut float = Complementary::new(0.98);
+        for sample in samples() {
+            let difference = fixed.update(Q16::from_f32(sample), Q16::ONE, Q16::from_f32(0.05)).to_f32()
+                - float.update(sample, 1.0, 0.05);
+            assert!(difference.abs() < 1e-2, "{difference}");
+        }
+    }
+
+    #[test]
+    fn kalman_matches_f32() {
+        let mut fixed = FixedKalman1D::new(Q16::ZERO, Q16::ONE, Q16::from_f32(0.01), Q16::from_f32(0.25));
+        let mut float = Kalman1D::new(0.0, 1.0, 0.01, 0.25);
+        for sample in samples() {
+            let difference = fixed.update(Q16::from_f32(sample)).to_f32() - float.update(sample);
+            assert!(difference.abs() < 1e-2, "{difference}");
+        }
+    }
+}
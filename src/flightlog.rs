@@ -0,0 +1,576 @@
+// flight log layout, written by the log task and read back from the card by the ground tools
+// the log is split into streams, each written to its own part of a boot's log region like a file of its own, so the
+// imu's rate can't hold up the event record or crowd it out of a full queue
+// a binary stream is a run of BLOCK_SIZE blocks, each a run of records, a record is its tag then its fields little
+// endian, a PAD byte where a tag should be ends the block
+// the debug stream is plain text lines, a block's PAD bytes after its last line are padding
+
+use crate::actuation::{Channel, FireReport};
+use crate::blockqueue::{BLOCK_SIZE, PAD};
+use crate::busrecovery::BusRecovery;
+use crate::calibration::CalibrationChange;
+use crate::command::Command;
+use crate::config::ConfigKey;
+use crate::faults::FaultFlags;
+use crate::gnss::FixQuality;
+use crate::heater::HeaterReport;
+use crate::mission::FlightState;
+use crate::power::ChargeState;
+use crate::validate::SampleFlags;
+use crate::vibration::VibrationSummary;
+use crate::{AttitudeData, BaroData, DropCounts, FixType, FlightEvent, GpsData, ImuData, NavMode, PowerData, StackUsage, StateVector, TimeSync, WindProfile};
+
+/// Record tags
+pub const LOG_FIRMWARE: u8 = 0x01;
+pub const LOG_SESSION: u8 = 0x02;
+pub const LOG_CONFIG_CHANGE: u8 = 0x03;
+pub const LOG_STACK_USAGE: u8 = 0x04;
+pub const LOG_FAULT: u8 = 0x05;
+pub const LOG_BARO: u8 = 0x06;
+pub const LOG_IMU: u8 = 0x07;
+pub const LOG_ATTITUDE: u8 = 0x08;
+pub const LOG_GPS: u8 = 0x09;
+pub const LOG_WIND: u8 = 0x0A;
+pub const LOG_STATE_VECTOR: u8 = 0x0B;
+pub const LOG_EVENT: u8 = 0x0C;
+pub const LOG_TIME_SYNC: u8 = 0x0D;
+pub const LOG_MET_SYNC: u8 = 0x0E;
+pub const LOG_DROP_COUNTS: u8 = 0x0F;
+pub const LOG_VIBRATION_SAMPLES: u8 = 0x10;
+pub const LOG_VIBRATION: u8 = 0x11;
+pub const LOG_FIRING: u8 = 0x12;
+pub const LOG_BUS_RECOVERY: u8 = 0x13;
+pub const LOG_HEATER: u8 = 0x14;
+pub const LOG_POWER: u8 = 0x15;
+pub const LOG_STATE: u8 = 0x16;
+pub const LOG_COMMAND: u8 = 0x17;
+pub const LOG_CALIBRATION: u8 = 0x18;
+
+/// Log streams, in the order the storage task writes their blocks and their parts of the log region are laid out
+#[derive(Copy, Clone, PartialEq, Eq, Debug, defmt::Format)]
+pub enum Stream {
+    /// flight state changes, commands, events, faults, config and calibration changes, and the low rate housekeeping
+    /// records, enough on its own to put the flight's timeline back together
+    Events,
+    /// gps fixes and the nav solution and wind from them
+    Gps,
+    Baro,
+    /// text lines for reading without the ground tools
+    Debug,
+    /// imu samples, attitude, and vibration bursts
+    Imu,
+}
+
+impl Stream {
+    pub const ALL: [Stream; 5] = [Stream::Events, Stream::Gps, Stream::Baro, Stream::Debug, Stream::Imu];
+
+    /// shares of a boot's log region, in ALL order, the imu gets half of it
+    pub const SHARES: [u32; 5] = [2, 2, 2, 2, 8];
+
+    pub fn from_id(id: u8) -> Option<Self> {
+        Self::ALL.get(id as usize).copied()
+    }
+
+    pub fn id(self) -> u8 {
+        self as u8
+    }
+
+    /// name on the console and in the ground tools
+    pub fn name(self) -> &'static str {
+        match self {
+            Stream::Events => "events",
+            Stream::Gps => "gps",
+            Stream::Baro => "baro",
+            Stream::Debug => "debug",
+            Stream::Imu => "imu",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|stream| stream.name() == name)
+    }
+
+    /// name of the stream's dump, what the ground tools look for
+    pub fn file_name(self) -> &'static str {
+        match self {
+            Stream::Events => "events.bin",
+            Stream::Gps => "gps.bin",
+            Stream::Baro => "baro.bin",
+            Stream::Debug => "debug.txt",
+            Stream::Imu => "imu.bin",
+        }
+    }
+
+    /// stream a record goes to, the firmware record starts every binary stream on top of this
+    pub fn for_tag(tag: u8) -> Self {
+        match tag {
+            LOG_IMU | LOG_ATTITUDE | LOG_VIBRATION_SAMPLES => Stream::Imu,
+            LOG_BARO => Stream::Baro,
+            LOG_GPS | LOG_WIND | LOG_STATE_VECTOR => Stream::Gps,
+            _ => Stream::Events,
+        }
+    }
+
+    /// longest a partly filled block is held before it is padded out and written, ms, None to only ever write whole
+    /// blocks, the events go out quickly so a reset loses little of them, the imu fills a block in a moment anyway
+    pub fn flush_period(self) -> Option<u32> {
+        match self {
+            Stream::Events => Some(1_000),
+            Stream::Gps | Stream::Baro | Stream::Debug => Some(5_000),
+            Stream::Imu => None,
+        }
+    }
+}
+
+/// One record from a flight log
+/// codes the log stores as bytes (reset reason, config key and value type, event, command, calibration change) are left
+/// as the bytes
+#[derive(Copy, Clone)]
+pub enum Record<'a> {
+    /// first record of every log
+    Firmware {
+        dirty: bool,
+        version: &'a str,
+        git_hash: &'a str,
+        profile: &'a str,
+        features: &'a str,
+    },
+    Session {
+        ground_pressure: f32,
+        ground_altitude: f32,
+        reset_reason: u8,
+        boot_count: u32,
+        time_stamp: u32,
+    },
+    /// value type 0 f32, 1 u32, 2 i32, old and new are the value's bytes
+    ConfigChange {
+        key: u8,
+        kind: u8,
+        old: [u8; 4],
+        new: [u8; 4],
+        time_stamp: u32,
+    },
+    StackUsage(StackUsage),
+    /// the fault as its bit in the fault flags, one fault per record
+    Fault { fault: FaultFlags, active: bool, time_stamp: u32 },
+    Baro(BaroData),
+    Imu(ImuData),
+    Attitude(AttitudeData),
+    Gps(GpsData),
+    Wind(WindProfile),
+    StateVector(StateVector),
+    /// FlightEvent::code
+    Event { code: u8, time_stamp: u32 },
+    TimeSync(TimeSync),
+    /// launch time stamp, mission elapsed time is time_stamp - launch
+    MetSync { launch: u32, time_stamp: u32 },
+    DropCounts(DropCounts, u32),
+    /// part of a vibration burst from its first sample on, three i16 counts per sample, vibration::from_counts reads them
+    VibrationSamples {
+        time_stamp: u32,
+        sample_period: u32,
+        first: u16,
+        samples: &'a [u8],
+    },
+    Vibration(VibrationSummary),
+    Firing(FireReport),
+    BusRecovery(BusRecovery),
+    Heater(HeaterReport),
+    Power(PowerData),
+    /// flight state machine transition
+    State { from: FlightState, to: FlightState, time_stamp: u32 },
+    /// Command::code of a command the control task took, and whether it was carried out or refused
+    Command { code: u8, accepted: bool, time_stamp: u32 },
+    /// CalibrationChange::code of calibration values that took effect
+    Calibration { code: u8, time_stamp: u32 },
+}
+
+impl Record<'_> {
+    /// when the record was logged, us since boot, None for the firmware record
+    pub fn time_stamp(&self) -> Option<u32> {
+        let time_stamp = match self {
+            Record::Firmware { .. } => return None,
+            Record::Session { time_stamp, .. }
+            | Record::ConfigChange { time_stamp, .. }
+            | Record::Fault { time_stamp, .. }
+            | Record::Event { time_stamp, .. }
+            | Record::MetSync { time_stamp, .. }
+            | Record::DropCounts(_, time_stamp)
+            | Record::VibrationSamples { time_stamp, .. }
+            | Record::State { time_stamp, .. }
+            | Record::Command { time_stamp, .. }
+            | Record::Calibration { time_stamp, .. } => *time_stamp,
+            Record::StackUsage(usage) => usage.time_stamp,
+            Record::Baro(baro) => baro.time_stamp,
+            Record::Imu(imu) => imu.time_stamp,
+            Record::Attitude(attitude) => attitude.time_stamp,
+            Record::Gps(gps) => gps.time_stamp,
+            Record::Wind(wind) => wind.time_stamp,
+            Record::StateVector(state) => state.time_stamp,
+            Record::TimeSync(sync) => sync.time_stamp,
+            Record::Vibration(vibration) => vibration.time_stamp,
+            Record::Firing(firing) => firing.time_stamp,
+            Record::BusRecovery(recovery) => recovery.time_stamp,
+            Record::Heater(heater) => heater.time_stamp,
+            Record::Power(power) => power.time_stamp,
+        };
+        Some(time_stamp)
+    }
+}
+
+// one line, what the console's log tail prints, the time stamp is left to the caller
+impl core::fmt::Display for Record<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match *self {
+            Record::Firmware { dirty, version, git_hash, .. } => {
+                write!(f, "firmware {version} {git_hash}{}", if dirty { " dirty" } else { "" })
+            }
+            Record::Session { ground_altitude, reset_reason, boot_count, .. } => {
+                write!(f, "session, ground {ground_altitude:.1} m, boot {boot_count}, reset {reset_reason}")
+            }
+            Record::ConfigChange { key, .. } => match ConfigKey::from_id(key) {
+                Some(key) => write!(f, "config {} changed", key.name()),
+                None => write!(f, "config {key:#04x} changed"),
+            },
+            Record::StackUsage(usage) => write!(f, "stack {} of {} bytes", usage.used, usage.size),
+            Record::Fault { fault, active, .. } => {
+                write!(f, "fault {:#06x} {}", fault.0, if active { "raised" } else { "cleared" })
+            }
+            Record::Baro(baro) => write!(f, "baro {:.2} hPa, {:.1} C", baro.pressure, baro.temperature),
+            Record::Imu(imu) => {
+                let [x, y, z] = imu.acceleration;
+                write!(f, "imu accel {x:.2} {y:.2} {z:.2}, {:.1} C", imu.temperature)
+            }
+            Record::Attitude(attitude) => {
+                let [w, x, y, z] = attitude.quaternion;
+                write!(f, "attitude {w:.3} {x:.3} {y:.3} {z:.3}{}", if attitude.converged { "" } else { ", converging" })
+            }
+            Record::Gps(gps) => write!(f, "gps {:.6} {:.6} {:.1} m, {} sats, {:?}", gps.latitude, gps.longitude,
+                gps.altitude, gps.satellites, gps.quality),
+            Record::Wind(wind) => write!(f, "wind at {:.0} m, {:.1} {:.1} m/s", wind.altitude, wind.wind[0], wind.wind[1]),
+            Record::StateVector(state) => {
+                let [north, east, altitude] = state.position;
+                write!(f, "state vector {north:.1} {east:.1} {altitude:.1} m, up {:.1} m/s", state.velocity[2])
+            }
+            Record::Event { code, .. } => match FlightEvent::from_code(code) {
+                Some(event) => write!(f, "event {event:?}"),
+                None => write!(f, "event {code:#04x}"),
+            },
+            Record::TimeSync(sync) => write!(f, "time sync, utc {} ms", sync.utc),
+            Record::MetSync { launch, .. } => write!(f, "met sync, launch at {launch} us"),
+            Record::DropCounts(drops, _) => write!(f, "dropped baro {}, imu {}, gps {}, log {}, telemetry {}",
+                drops.baro, drops.imu, drops.gps, drops.log, drops.telemetry),
+            Record::VibrationSamples { first, samples, .. } => {
+                write!(f, "vibration samples {} from {first}", samples.len() / 6)
+            }
+            Record::Vibration(vibration) => write!(f, "{vibration:?}"),
+            Record::Firing(firing) => write!(f, "{firing:?}"),
+            Record::BusRecovery(recovery) => write!(f, "{recovery:?}"),
+            Record::Heater(heater) => write!(f, "{heater:?}"),
+            Record::Power(power) => write!(f, "power {:.2} V, solar {:.2} V {:.3} A, {:?}", power.battery_voltage,
+                power.solar_voltage, power.solar_current, power.charge),
+            Record::State { from, to, .. } => write!(f, "state {from:?} -> {to:?}"),
+            Record::Command { code, accepted, .. } => {
+                let outcome = if accepted { "carried out" } else { "refused" };
+                match Command::describe(code) {
+                    Some((name, Some(channel))) => write!(f, "command {name} {} {outcome}", channel.name()),
+                    Some((name, None)) => write!(f, "command {name} {outcome}"),
+                    None => write!(f, "command {code:#04x} {outcome}"),
+                }
+            }
+            Record::Calibration { code, .. } => match CalibrationChange::from_code(code) {
+                Some(change) => write!(f, "calibration {change:?} updated"),
+                None => write!(f, "calibration {code:#04x} updated"),
+            },
+        }
+    }
+}
+
+/// the record at the start of bytes and its length, tag included, None at a PAD byte, a record cut short, or an
+/// unknown tag
+pub fn parse(bytes: &[u8]) -> Option<(Record<'_>, usize)> {
+    let (&tag, fields) = bytes.split_first().filter(|&(&tag, _)| tag != PAD)?;
+    let mut reader = Reader { log: &[], block: fields, skipped: 0 };
+    let record = reader.record(tag)?;
+    Some((record, bytes.len() - reader.block.len()))
+}
+
+/// Reads the records out of a flight log, as dumped from the card from the start of a boot's region
+/// a record that is cut short or has an unknown tag drops the rest of its block, the next block starts clean
+/// the log ends at the first block that starts with PAD or zero (never written, or an erased card)
+/// a region reused from an earlier boot still holds that boot's blocks past the end of this one, nothing marks the
+/// boundary, so dump only as far as the boot logged
+#[derive(Clone)]
+pub struct Reader<'a> {
+    /// blocks not started yet
+    log: &'a [u8],
+    /// rest of the current block
+    block: &'a [u8],
+    skipped: u32,
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(log: &'a [u8]) -> Self {
+        Self { log, block: &[], skipped: 0 }
+    }
+
+    /// blocks that had a bad record, corruption on the card or a reader older than the firmware
+    pub fn skipped(&self) -> u32 {
+        self.skipped
+    }
+
+    fn take<const N: usize>(&mut self) -> Option<[u8; N]> {
+        let (bytes, rest) = self.block.split_first_chunk::<N>()?;
+        self.block = rest;
+        Some(*bytes)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        self.take::<1>().map(|[byte]| byte)
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        self.take().map(u16::from_le_bytes)
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        self.take().map(u32::from_le_bytes)
+    }
+
+    fn f32(&mut self) -> Option<f32> {
+        self.take().map(f32::from_le_bytes)
+    }
+
+    fn f64(&mut self) -> Option<f64> {
+        self.take().map(f64::from_le_bytes)
+    }
+
+    fn floats<const N: usize>(&mut self) -> Option<[f32; N]> {
+        let mut floats = [0.0; N];
+        for float in floats.iter_mut() {
+            *float = self.f32()?;
+        }
+        Some(floats)
+    }
+
+    fn bytes(&mut self, len: usize) -> Option<&'a [u8]> {
+        let block: &'a [u8] = self.block;
+        let (bytes, rest) = block.split_at_checked(len)?;
+        self.block = rest;
+        Some(bytes)
+    }
+
+    // length prefixed text
+    fn text(&mut self) -> Option<&'a str> {
+        let len = self.u8()? as usize;
+        core::str::from_utf8(self.bytes(len)?).ok()
+    }
+
+    fn record(&mut self, tag: u8) -> Option<Record<'a>> {
+        let record = match tag {
+            LOG_FIRMWARE => Record::Firmware {
+                dirty: self.u8()? != 0,
+                version: self.text()?,
+                git_hash: self.text()?,
+                profile: self.text()?,
+                features: self.text()?,
+            },
+            LOG_SESSION => Record::Session {
+                ground_pressure: self.f32()?,
+                ground_altitude: self.f32()?,
+                reset_reason: self.u8()?,
+                boot_count: self.u32()?,
+                time_stamp: self.u32()?,
+            },
+            LOG_CONFIG_CHANGE => Record::ConfigChange {
+                key: self.u8()?,
+                kind: self.u8()?,
+                old: self.take()?,
+                new: self.take()?,
+                time_stamp: self.u32()?,
+            },
+            LOG_STACK_USAGE => Record::StackUsage(StackUsage {
+                used: self.u32()?,
+                size: self.u32()?,
+                time_stamp: self.u32()?,
+            }),
+            LOG_FAULT => Record::Fault {
+                fault: FaultFlags(self.u16()?),
+                active: self.u8()? != 0,
+                time_stamp: self.u32()?,
+            },
+            // only samples that passed validation reach the log, so the flags aren't recorded, the gps quality is
+            LOG_BARO => Record::Baro(BaroData {
+                pressure: self.f32()?,
+                temperature: self.f32()?,
+                flags: SampleFlags::NONE,
+                time_stamp: self.u32()?,
+            }),
+            LOG_IMU => Record::Imu(ImuData {
+                acceleration: self.floats()?,
+                gyro: self.floats()?,
+                mag: self.floats()?,
+                temperature: self.f32()?,
+                flags: SampleFlags::NONE,
+                time_stamp: self.u32()?,
+            }),
+            LOG_ATTITUDE => Record::Attitude(AttitudeData {
+                quaternion: self.floats()?,
+                converged: self.u8()? != 0,
+                time_stamp: self.u32()?,
+            }),
+            // utc of zero means the receiver had no time yet
+            LOG_GPS => Record::Gps(GpsData {
+                latitude: self.f64()?,
+                longitude: self.f64()?,
+                altitude: self.f32()?,
+                velocity: self.floats()?,
+                satellites: self.u8()?,
+                fix: FixType::from_u8(self.u8()?),
+                quality: FixQuality::from_u8(self.u8()?),
+                hdop: self.f32()?,
+                flags: SampleFlags::NONE,
+                utc: Some(u64::from_le_bytes(self.take()?)).filter(|&utc| utc != 0),
+                time_stamp: self.u32()?,
+            }),
+            LOG_WIND => Record::Wind(WindProfile {
+                altitude: self.f32()?,
+                wind: self.floats()?,
+                samples: self.u16()?,
+                time_stamp: self.u32()?,
+            }),
+            LOG_STATE_VECTOR => Record::StateVector(StateVector {
+                position: self.floats()?,
+                velocity: self.floats()?,
+                attitude: self.floats()?,
+                mode: NavMode::from_u8(self.u8()?),
+                time_stamp: self.u32()?,
+            }),
+            LOG_EVENT => Record::Event {
+                code: self.u8()?,
+                time_stamp: self.u32()?,
+            },
+            LOG_TIME_SYNC => Record::TimeSync(TimeSync {
+                utc: u64::from_le_bytes(self.take()?),
+                time_stamp: self.u32()?,
+            }),
+            LOG_MET_SYNC => Record::MetSync {
+                launch: self.u32()?,
+                time_stamp: self.u32()?,
+            },
+            LOG_DROP_COUNTS => Record::DropCounts(
+                DropCounts {
+                    baro: self.u32()?,
+                    imu: self.u32()?,
+                    gps: self.u32()?,
+                    log: self.u32()?,
+                    telemetry: self.u32()?,
+                },
+                self.u32()?,
+            ),
+            LOG_VIBRATION_SAMPLES => Record::VibrationSamples {
+                time_stamp: self.u32()?,
+                sample_period: self.u32()?,
+                first: self.u16()?,
+                samples: {
+                    let count = self.u8()? as usize;
+                    self.bytes(count * 6)?
+                },
+            },
+            LOG_VIBRATION => Record::Vibration(VibrationSummary {
+                rms: self.floats()?,
+                peak: self.floats()?,
+                bands: [self.floats()?, self.floats()?, self.floats()?],
+                sample_period: self.u32()?,
+                time_stamp: self.u32()?,
+            }),
+            LOG_FIRING => Record::Firing(FireReport {
+                channel: Channel::from_id(self.u8()?)?,
+                confirmed: self.u8()? != 0,
+                opened: self.u8()? != 0,
+                peak_current: self.f32()?,
+                conducted: self.u32()?,
+                dwell: self.u32()?,
+                time_stamp: self.u32()?,
+            }),
+            LOG_BUS_RECOVERY => Record::BusRecovery(BusRecovery {
+                failures: self.u16()?,
+                clocks: self.u8()?,
+                released: self.u8()? != 0,
+                count: self.u16()?,
+                time_stamp: self.u32()?,
+            }),
+            LOG_HEATER => Record::Heater(HeaterReport {
+                on: self.u8()? != 0,
+                held: self.u8()? != 0,
+                temperature: self.f32()?,
+                period_energy: self.f32()?,
+                total_energy: self.f32()?,
+                time_stamp: self.u32()?,
+            }),
+            LOG_POWER => Record::Power(PowerData {
+                battery_voltage: self.f32()?,
+                solar_voltage: self.f32()?,
+                solar_current: self.f32()?,
+                charge: ChargeState::from_id(self.u8()?)?,
+                time_stamp: self.u32()?,
+            }),
+            LOG_STATE => Record::State {
+                from: FlightState::from_id(self.u8()?)?,
+                to: FlightState::from_id(self.u8()?)?,
+                time_stamp: self.u32()?,
+            },
+            LOG_COMMAND => Record::Command {
+                code: self.u8()?,
+                accepted: self.u8()? != 0,
+                time_stamp: self.u32()?,
+            },
+            LOG_CALIBRATION => Record::Calibration {
+                code: self.u8()?,
+                time_stamp: self.u32()?,
+            },
+            _ => return None,
+        };
+        Some(record)
+    }
+}
+
+/// Lines of the debug stream, as dumped from the card from the start of its part of a boot's region, it ends at the
+/// first block that starts with PAD or zero like a binary stream, a block that isn't text is skipped
+pub fn text_lines(log: &[u8]) -> impl Iterator<Item = &str> {
+    log.chunks_exact(BLOCK_SIZE)
+        .take_while(|block| block[0] != PAD && block[0] != 0)
+        .filter_map(|block| {
+            let len = block.iter().position(|&byte| byte == PAD).unwrap_or(BLOCK_SIZE);
+            core::str::from_utf8(&block[..len]).ok()
+        })
+        .flat_map(str::lines)
+}
+
+impl<'a> Iterator for Reader<'a> {
+    type Item = Record<'a>;
+
+    fn next(&mut self) -> Option<Record<'a>> {
+        loop {
+            match self.block.first() {
+                Some(&tag) if tag != PAD => {
+                    self.block = &self.block[1..];
+                    if let Some(record) = self.record(tag) {
+                        return Some(record);
+                    }
+                    self.skipped += 1;
+                    self.block = &[];
+                }
+                _ => {
+                    let (block, rest) = self.log.split_at_checked(BLOCK_SIZE)?;
+                    if block[0] == PAD || block[0] == 0 {
+                        self.log = &[];
+                        return None;
+                    }
+                    self.block = block;
+                    self.log = rest;
+                }
+            }
+        }
+    }
+}
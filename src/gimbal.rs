@@ -0,0 +1,125 @@
+// two axis payload gimbal, a roll servo carrying a pitch servo carrying the camera, which looks along the body -z axis
+// with both servos centred
+// the attitude estimate puts the target direction in the body frame and the servo angles that bring the camera onto
+// it follow from that directly, so the loop keeps the camera on target as the payload swings under the balloon
+// the angles are held to the servos' travel and slew rate so estimator noise and a tumble don't hammer the servos
+
+use core::f32::consts::FRAC_PI_4;
+
+use libm::{asinf, atan2f, sqrtf};
+
+use crate::AttitudeData;
+use crate::sun::SunPosition;
+
+/// servo pulse width with the servo centred, us
+pub const SERVO_CENTRE: u16 = 1500;
+
+/// servo travel either side of centre, rad, reached at SERVO_CENTRE plus or minus SERVO_HALF_WIDTH
+pub const SERVO_HALF_TRAVEL: f32 = FRAC_PI_4;
+pub const SERVO_HALF_WIDTH: u16 = 500;
+
+/// What the gimbal points the camera at, picked by config
+#[derive(Copy, Clone, PartialEq, Eq, Debug, defmt::Format)]
+pub enum GimbalMode {
+    /// servos unpowered, the camera hangs where the payload points it
+    Off,
+    /// straight down
+    Nadir,
+    /// at the sun, held at centre while the sun is down or its position isn't known
+    Sun,
+}
+
+impl GimbalMode {
+    /// mode from its config value, 0 off, 1 nadir, and 2 sun, unknown values are off
+    pub fn from_u32(value: u32) -> Self {
+        match value {
+            1 => GimbalMode::Nadir,
+            2 => GimbalMode::Sun,
+            _ => GimbalMode::Off,
+        }
+    }
+
+    /// direction to point the camera in the earth frame (x north, y west, z up), None with the gimbal off
+    /// sun is where the sun is, if known
+    pub fn target(self, sun: Option<SunPosition>) -> Option<[f32; 3]> {
+        match self {
+            GimbalMode::Off => None,
+            GimbalMode::Nadir => Some([0.0, 0.0, -1.0]),
+            // with no sun to point at the camera is held at centre, a sun past the servos' travel leaves them at its end
+            GimbalMode::Sun => Some(sun.filter(SunPosition::is_up).map_or([0.0, 0.0, -1.0], |sun| sun.direction())),
+        }
+    }
+}
+
+/// Points the camera at a direction from the attitude estimate
+pub struct GimbalController {
+    /// most either servo may turn from centre, rad
+    limit: f32,
+    /// fastest either servo may turn, rad/s
+    max_rate: f32,
+    /// roll and pitch servo angles, rad
+    angles: [f32; 2],
+    last_time_stamp: Option<u32>,
+}
+
+impl GimbalController {
+    pub const fn new(limit: f32, max_rate: f32) -> Self {
+        Self {
+            limit,
+            max_rate,
+            angles: [0.0; 2],
+            last_time_stamp: None,
+        }
+    }
+
+    /// roll and pitch servo angles, rad
+    pub fn angles(&self) -> [f32; 2] {
+        self.angles
+    }
+
+    /// feed the attitude and the target direction in the earth frame (x north, y west, z up), returns the roll and
+    /// pitch servo angles, rad, the servos head back to centre while the attitude hasn't converged
+    pub fn update(&mut self, attitude: &AttitudeData, target: [f32; 3]) -> [f32; 2] {
+        let dt = self
+            .last_time_stamp
+            .replace(attitude.time_stamp)
+            .map_or(0.0, |last| attitude.time_stamp.wrapping_sub(last) as f32 * 1e-6);
+
+        let wanted = if attitude.converged {
+            let [x, y, z] = to_body(attitude.quaternion, target);
+            let norm = sqrtf(x * x + y * y + z * z);
+            if norm > 0.0 {
+                // the camera axis after roll then pitch is (-sin pitch, cos pitch sin roll, -cos pitch cos roll)
+                [atan2f(y, -z), asinf((-x / norm).clamp(-1.0, 1.0))]
+            } else {
+                [0.0; 2]
+            }
+        } else {
+            [0.0; 2]
+        };
+
+        let step = self.max_rate * dt;
+        for (angle, wanted) in self.angles.iter_mut().zip(wanted) {
+            let wanted = wanted.clamp(-self.limit, self.limit);
+            *angle += (wanted - *angle).clamp(-step, step);
+        }
+        self.angles
+    }
+}
+
+/// servo pulse width for an angle from centre, us, past the servo's travel it stays at the end
+pub fn pulse_width(angle: f32) -> u16 {
+    let offset = (angle / SERVO_HALF_TRAVEL).clamp(-1.0, 1.0) * SERVO_HALF_WIDTH as f32;
+    (SERVO_CENTRE as f32 + offset) as u16
+}
+
+// rotate an earth frame vector into the body frame, the transpose of the attitude's body to earth rotation
+fn to_body(q: [f32; 4], v: [f32; 3]) -> [f32; 3] {
+    let [q0, q1, q2, q3] = q;
+    let [x, y, z] = v;
+    [
+        x * (1.0 - 2.0 * (q2 * q2 + q3 * q3)) + y * 2.0 * (q1 * q2 + q0 * q3) + z * 2.0 * (q1 * q3 - q0 * q2),
+        x * 2.0 * (q1 * q2 - q0 * q3) + y * (1.0 - 2.0 * (q1 * q1 + q3 * q3)) + z * 2.0 * (q2 * q3 + q0 * q1),
+        x * 2.0 * (q1 * q3 + q0 * q2) + y * 2.0 * (q2 * q3 - q0 * q1) + z * (1.0 - 2.0 * (q1 * q1 + q2 * q2)),
+    ]
+}
@@ -0,0 +1,132 @@
+use libm::sqrtf;
+
+use crate::validate::SampleFlags;
+use crate::{FixType, GpsData};
+
+/// fewest satellites for any fix to be used, four is the minimum for a 3d solution
+pub const MIN_SATELLITES: u8 = 4;
+
+/// fewest satellites for a fix to count as good, with fewer the geometry is usually poor
+const GOOD_SATELLITES: u8 = 6;
+
+/// horizontal dilution of precision above which a fix is degraded, and above which it is junk
+const GOOD_HDOP: f32 = 2.5;
+const MAX_HDOP: f32 = 10.0;
+
+/// altitudes where consumer receivers stop solving or freeze their output, m
+/// the COCOM limit is 18 km (some modules apply it without the speed condition), and a u-blox left in its
+/// default portable dynamic model gives up at 12 km
+const LOCKOUT_ALTITUDES: [f32; 2] = [12_000.0, 18_000.0];
+
+/// how close to a lockout altitude the fix has to be for a loss or freeze to be blamed on it, m
+/// wide enough to cover the receiver testing ellipsoid height rather than msl
+const LOCKOUT_BAND: f32 = 1_500.0;
+
+/// COCOM speed limit, m/s, a balloon never gets near it but a receiver reporting it is lying or about to stop
+const LOCKOUT_SPEED: f32 = 515.0;
+
+/// identical fixes in a row in a lockout band before the output is taken as frozen
+const FROZEN_FIXES: u8 = 3;
+
+/// How far a fix can be trusted
+#[derive(Copy, Clone, PartialEq, Eq, Default, Debug, defmt::Format)]
+pub enum FixQuality {
+    /// no fix, too few satellites, hdop past MAX_HDOP, or a suspected lockout, not to be used at all
+    #[default]
+    Invalid = 0,
+    /// 2d fix, few satellites, or poor hdop, position usable with more noise and altitude only from a 3d fix
+    Degraded = 1,
+    Good = 2,
+}
+
+impl FixQuality {
+    pub fn usable(self) -> bool {
+        self != FixQuality::Invalid
+    }
+
+    /// sample status of a fresh fix of this quality
+    pub fn flags(self) -> SampleFlags {
+        match self {
+            FixQuality::Invalid => SampleFlags::FAILED,
+            FixQuality::Degraded => SampleFlags(SampleFlags::FRESH.0 | SampleFlags::DEGRADED.0),
+            FixQuality::Good => SampleFlags::FRESH,
+        }
+    }
+
+    /// quality from its number in the log, unknown numbers read as Invalid
+    pub fn from_u8(value: u8) -> Self {
+        match value {
+            1 => FixQuality::Degraded,
+            2 => FixQuality::Good,
+            _ => FixQuality::Invalid,
+        }
+    }
+}
+
+/// Grades each fix on fix type, hdop, and satellite count, and watches for the receiver locking out near the
+/// COCOM altitude, either by dropping the fix with plenty of satellites in view or by repeating its last one
+/// a lockout marks every fix invalid until the receiver reports a fresh position again
+pub struct FixGate {
+    /// last fix with a position, to spot a frozen output and where the fix was lost
+    last: Option<GpsData>,
+    frozen: u8,
+    lockout: bool,
+}
+
+impl Default for FixGate {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FixGate {
+    pub const fn new() -> Self {
+        Self {
+            last: None,
+            frozen: 0,
+            lockout: false,
+        }
+    }
+
+    /// true while the receiver looks locked out
+    pub fn lockout(&self) -> bool {
+        self.lockout
+    }
+
+    /// grade the next fix from the receiver
+    pub fn check(&mut self, gps: &GpsData) -> FixQuality {
+        if gps.fix == FixType::None {
+            // losing the fix with a good sky view while climbing into a lockout band is the receiver, not the antenna
+            if let Some(last) = self.last
+                && gps.satellites >= MIN_SATELLITES
+                && last.velocity[2] > 0.0
+                && in_lockout_band(last.altitude)
+            {
+                self.lockout = true;
+            }
+            self.frozen = 0;
+            return FixQuality::Invalid;
+        }
+
+        let repeated = self
+            .last
+            .is_some_and(|last| last.latitude == gps.latitude && last.longitude == gps.longitude && last.altitude == gps.altitude);
+        self.frozen = if repeated && in_lockout_band(gps.altitude) { self.frozen.saturating_add(1) } else { 0 };
+        let speed = sqrtf(gps.velocity.iter().map(|v| v * v).sum());
+        self.lockout = self.frozen >= FROZEN_FIXES || speed >= LOCKOUT_SPEED;
+        self.last = Some(*gps);
+
+        // nan hdop is junk too
+        if self.lockout || gps.satellites < MIN_SATELLITES || gps.hdop.is_nan() || gps.hdop > MAX_HDOP {
+            FixQuality::Invalid
+        } else if gps.fix == FixType::TwoD || gps.satellites < GOOD_SATELLITES || gps.hdop > GOOD_HDOP {
+            FixQuality::Degraded
+        } else {
+            FixQuality::Good
+        }
+    }
+}
+
+fn in_lockout_band(altitude: f32) -> bool {
+    LOCKOUT_ALTITUDES.iter().any(|&limit| (altitude - limit).abs() <= LOCKOUT_BAND)
+}
@@ -0,0 +1,51 @@
+use crate::faults::{Fault, FaultFlags};
+
+/// Data streams watched for staleness by the supervisor
+#[derive(Copy, Clone, PartialEq, Eq, Debug, defmt::Format)]
+pub enum Stream {
+    Baro,
+    Imu,
+    Attitude,
+    Gps,
+    Nav,
+}
+
+impl Stream {
+    pub const ALL: [Stream; 5] = [Stream::Baro, Stream::Imu, Stream::Attitude, Stream::Gps, Stream::Nav];
+}
+
+/// Tracks the newest sample time stamp of each stream and flags the ones that stop arriving
+/// streams that have never produced count from the first check
+pub struct StreamMonitor {
+    /// newest sample time stamp per stream, us
+    last: [Option<u32>; Stream::ALL.len()],
+    /// longest allowed gap per stream, us
+    timeout: [u32; Stream::ALL.len()],
+}
+
+impl StreamMonitor {
+    /// timeouts (us) are in Stream::ALL order
+    pub const fn new(timeout: [u32; Stream::ALL.len()]) -> Self {
+        Self {
+            last: [None; Stream::ALL.len()],
+            timeout,
+        }
+    }
+
+    /// note a sample from a stream
+    pub fn record(&mut self, stream: Stream, time_stamp: u32) {
+        self.last[stream as usize] = Some(time_stamp);
+    }
+
+    /// Fault::Stale flags for the streams with no sample within their timeout
+    /// timestamps are microseconds and wrap, wrapping_sub gives the right interval across one wrap
+    pub fn check(&mut self, now: u32) -> FaultFlags {
+        let mut flags = FaultFlags::NONE;
+        for stream in Stream::ALL {
+            let i = stream as usize;
+            let last = *self.last[i].get_or_insert(now);
+            flags.set(Fault::Stale(stream), now.wrapping_sub(last) > self.timeout[i]);
+        }
+        flags
+    }
+}
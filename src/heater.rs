@@ -0,0 +1,107 @@
+// battery heater control, a thermostat on the battery temperature with an energy budget per hour on top
+// a heater left to its thermostat in a cold sky can run flat out for the whole flight, the budget caps what it takes
+// from the battery each hour so thermal management can't eat the mission's power margin, and the energy it has used is
+// reported so a heater held off by its budget shows up on the ground instead of as a cold battery
+
+/// length of one budget period, an hour, us
+pub const BUDGET_PERIOD: u32 = 3_600_000_000;
+
+/// Battery heater thermostat with an hourly energy budget
+pub struct HeaterController {
+    /// battery temperature below which the heater switches on, deg C
+    on_below: f32,
+    /// battery temperature above which it switches off again, deg C
+    off_above: f32,
+    /// heater power while on, W
+    power: f32,
+    /// most energy per budget period, J
+    budget: f32,
+    on: bool,
+    /// the thermostat wants heat the budget or load shedding won't give
+    held: bool,
+    /// start of the current budget period and time stamp of the last update, None before the first update
+    period_start: Option<u32>,
+    last: Option<u32>,
+    /// energy used in the current budget period and since boot, J
+    period_energy: f32,
+    total_energy: f32,
+}
+
+impl HeaterController {
+    pub const fn new(on_below: f32, off_above: f32, power: f32, budget: f32) -> Self {
+        Self {
+            on_below,
+            off_above,
+            power,
+            budget,
+            on: false,
+            held: false,
+            period_start: None,
+            last: None,
+            period_energy: 0.0,
+            total_energy: 0.0,
+        }
+    }
+
+    /// feed the battery temperature (deg C) and whether the heater load may run at all, returns whether the heater
+    /// should be on until the next update, updates have to come more often than the time stamps wrap
+    pub fn update(&mut self, temperature: f32, enabled: bool, time_stamp: u32) -> bool {
+        // the interval just gone is charged at the state the heater was in over it
+        if self.on
+            && let Some(last) = self.last
+        {
+            let energy = self.power * time_stamp.wrapping_sub(last) as f32 * 1e-6;
+            self.period_energy += energy;
+            self.total_energy += energy;
+        }
+        self.last = Some(time_stamp);
+
+        let period_start = *self.period_start.get_or_insert(time_stamp);
+        if time_stamp.wrapping_sub(period_start) >= BUDGET_PERIOD {
+            self.period_start = Some(time_stamp);
+            self.period_energy = 0.0;
+        }
+
+        // a nan temperature leaves the thermostat where it was
+        let wanted = if temperature.is_nan() {
+            self.on || self.held
+        } else if self.on || self.held {
+            temperature <= self.off_above
+        } else {
+            temperature < self.on_below
+        };
+        self.on = wanted && enabled && !self.exhausted();
+        self.held = wanted && !self.on;
+        self.on
+    }
+
+    /// the budget for this period is used up, the heater stays off until the next one
+    pub fn exhausted(&self) -> bool {
+        self.period_energy >= self.budget
+    }
+
+    pub fn report(&self, temperature: f32, time_stamp: u32) -> HeaterReport {
+        HeaterReport {
+            on: self.on,
+            held: self.held,
+            temperature,
+            period_energy: self.period_energy,
+            total_energy: self.total_energy,
+            time_stamp,
+        }
+    }
+}
+
+/// Battery heater state and the energy it has used
+#[derive(Copy, Clone, PartialEq, Debug, defmt::Format)]
+pub struct HeaterReport {
+    pub on: bool,
+    /// the thermostat wants heat but the budget is used up or the heaters are shed
+    pub held: bool,
+    /// battery temperature, deg C
+    pub temperature: f32,
+    /// energy used in the current budget period and since boot, J
+    pub period_energy: f32,
+    pub total_energy: f32,
+    pub time_stamp: u32,
+}
@@ -0,0 +1,204 @@
+// logged records read back from storage and sent down on request, so if the payload is lost the ground still has
+// what it logged over the gap before loss of signal
+// the storage task reads the requested stream back a block at a time between log writes, each record in the window
+// goes down as it was logged, one per frame, thinned out to one in every so many
+// the window is uptime in ms, the log's us time stamps wrap every 71.6 minutes, so they are unwrapped as the stream
+// is read, the same way the ground tools do
+// the console's log tail reads the newest few records back the same way, to show they really reached the medium
+
+use crate::blockqueue::BLOCK_SIZE;
+use crate::flightlog::{self, Record, Stream};
+use crate::packet::HISTORY_PACKET_ID;
+use crate::storage::{LogStorage, LogWriter};
+
+/// longest record sent down, longer ones (the firmware record, vibration summaries and sample chunks) are skipped
+pub const HISTORY_RECORD_LEN: usize = 64;
+
+/// Logged records to send down
+#[derive(Copy, Clone, PartialEq, Eq, Debug, defmt::Format)]
+pub struct HistoryRequest {
+    pub stream: Stream,
+    /// window of uptime, ms, both ends included
+    pub from: u32,
+    pub to: u32,
+    /// send one in every this many records in the window, 0 and 1 send them all
+    pub every: u16,
+}
+
+/// One logged record on its way down, as it is in the log, tag first
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct HistoryRecord {
+    pub stream: Stream,
+    len: u8,
+    bytes: [u8; HISTORY_RECORD_LEN],
+}
+
+impl HistoryRecord {
+    /// None for a record longer than HISTORY_RECORD_LEN
+    pub fn new(stream: Stream, record: &[u8]) -> Option<Self> {
+        let mut bytes = [0; HISTORY_RECORD_LEN];
+        bytes.get_mut(..record.len())?.copy_from_slice(record);
+        Some(Self { stream, len: record.len() as u8, bytes })
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes[..self.len as usize]
+    }
+
+    /// the record parsed, None if the ground tools are older than the firmware that logged it
+    pub fn record(&self) -> Option<Record<'_>> {
+        flightlog::parse(self.bytes()).map(|(record, _)| record)
+    }
+
+    /// write the frame, the packet id, the stream id, then the record, returns its length, None if frame is too short
+    pub fn encode(&self, frame: &mut [u8]) -> Option<usize> {
+        let len = 2 + self.len as usize;
+        let frame = frame.get_mut(..len)?;
+        frame[0] = HISTORY_PACKET_ID;
+        frame[1] = self.stream.id();
+        frame[2..].copy_from_slice(self.bytes());
+        Some(len)
+    }
+
+    /// from a received frame after its packet id
+    pub fn from_frame(frame: &[u8]) -> Option<Self> {
+        let (&stream, record) = frame.split_first()?;
+        Self::new(Stream::from_id(stream)?, record)
+    }
+}
+
+/// Walks a stream's blocks for the records a request asks for
+pub struct HistoryReader {
+    request: HistoryRequest,
+    // next block of the stream to read
+    offset: u32,
+    block: [u8; BLOCK_SIZE],
+    // where the next record starts in block, BLOCK_SIZE when it has been read through
+    at: usize,
+    // (last time stamp, us since boot) to unwrap the time stamps
+    last: Option<(u32, u64)>,
+    // records in the window so far, for the thinning
+    matched: u32,
+    done: bool,
+}
+
+impl HistoryReader {
+    pub fn new(request: HistoryRequest) -> Self {
+        Self {
+            request,
+            offset: 0,
+            block: [0; BLOCK_SIZE],
+            at: BLOCK_SIZE,
+            last: None,
+            matched: 0,
+            done: false,
+        }
+    }
+
+    pub fn request(&self) -> HistoryRequest {
+        self.request
+    }
+
+    /// past the window or the end of what the stream has written
+    pub fn is_done(&self) -> bool {
+        self.done
+    }
+
+    /// next record to send, reading at most one block so the log writes in between don't wait on a long search,
+    /// None when the block read had nothing more to send, check is_done to tell that from the end
+    pub async fn next<S: LogStorage, const STREAMS: usize>(
+        &mut self,
+        log: &mut LogWriter<S, STREAMS>,
+    ) -> Result<Option<HistoryRecord>, S::Error> {
+        if self.done {
+            return Ok(None);
+        }
+        if self.at >= BLOCK_SIZE {
+            // moved on first, a block that fails to read is skipped rather than retried forever
+            let offset = self.offset;
+            self.offset += 1;
+            if !log.read(self.request.stream.id() as usize, offset, &mut self.block).await? {
+                self.done = true;
+                return Ok(None);
+            }
+            self.at = 0;
+        }
+
+        // the rest of a block after a record that doesn't parse is padding or can't be trusted
+        while let Some((time_stamp, len)) = flightlog::parse(&self.block[self.at..]).map(|(record, len)| (record.time_stamp(), len)) {
+            let start = self.at;
+            self.at += len;
+            let (Some(time_stamp), true) = (time_stamp, len <= HISTORY_RECORD_LEN) else {
+                continue;
+            };
+            let time = self.unwrap(time_stamp) / 1000;
+            // streams are written in time order, nothing after this is in the window
+            if time > self.request.to as u64 {
+                self.done = true;
+                return Ok(None);
+            }
+            if time < self.request.from as u64 {
+                continue;
+            }
+            let send = self.matched.is_multiple_of(self.request.every.max(1) as u32);
+            self.matched += 1;
+            if send {
+                return Ok(HistoryRecord::new(self.request.stream, &self.block[start..self.at]));
+            }
+        }
+        self.at = BLOCK_SIZE;
+        Ok(None)
+    }
+
+    // us since boot of a time stamp, records are close enough together that the shortest step between neighbours is
+    // the real one
+    fn unwrap(&mut self, time_stamp: u32) -> u64 {
+        let time = match self.last {
+            Some((last, time)) => time.saturating_add_signed(time_stamp.wrapping_sub(last) as i32 as i64),
+            None => time_stamp as u64,
+        };
+        self.last = Some((time_stamp, time));
+        time
+    }
+}
+
+/// the last records a stream has written, up to count of them and N, oldest first, for the console's log tail
+/// reads back at most count blocks from the newest, records too long to go down are left out here too
+pub async fn tail<S: LogStorage, const STREAMS: usize, const N: usize>(
+    log: &mut LogWriter<S, STREAMS>,
+    stream: Stream,
+    count: usize,
+) -> Result<[Option<HistoryRecord>; N], S::Error> {
+    let count = count.min(N);
+    let mut records = [None; N];
+    let mut block = [0; BLOCK_SIZE];
+    // filled from the back, a block's records go in front of the newer ones already found
+    let mut found = 0;
+    let written = log.written(stream.id() as usize);
+    for offset in (written.saturating_sub(count as u32)..written).rev() {
+        if found == count {
+            break;
+        }
+        log.read(stream.id() as usize, offset, &mut block).await?;
+        let in_block = fitting(&block).count();
+        let take = in_block.min(count - found);
+        let start = count - found - take;
+        for (slot, record) in records[start..].iter_mut().zip(fitting(&block).skip(in_block - take)) {
+            *slot = HistoryRecord::new(stream, record);
+        }
+        found += take;
+    }
+    records[..count].rotate_left(count - found);
+    Ok(records)
+}
+
+// the records in a block that fit in a HistoryRecord
+fn fitting(block: &[u8]) -> impl Iterator<Item = &[u8]> {
+    let mut at = 0;
+    core::iter::from_fn(move || {
+        let (_, len) = flightlog::parse(&block[at..])?;
+        at += len;
+        Some(&block[at - len..at])
+    })
+    .filter(|record| record.len() <= HISTORY_RECORD_LEN)
+}
@@ -0,0 +1,85 @@
+// payload camera stills on the card, a stream of its own next to the flight log, written by the camera task and read
+// back by the ground tools
+// every picture starts on a block boundary with a header, the jpeg follows it and the last block is padded with blockqueue::PAD,
+// the flight log's capture event at the same time stamp marks it in the log
+
+use crate::blockqueue::BLOCK_SIZE;
+
+/// "IMG1", the first bytes of a picture's first block
+pub const MAGIC: u32 = 0x3147_4D49;
+
+/// magic, number, time stamp, utc, and jpeg length
+pub const HEADER_LEN: usize = 4 + 4 + 4 + 8 + 4;
+
+/// Start of one picture in the stream
+#[derive(Copy, Clone, PartialEq, Eq, Debug, defmt::Format)]
+pub struct ImageHeader {
+    /// counts up from 0 each boot
+    pub number: u32,
+    /// uptime when it was taken, us
+    pub time_stamp: u32,
+    /// ms since the unix epoch, None until the rtc has been set
+    pub utc: Option<u64>,
+    /// jpeg length, bytes
+    pub len: u32,
+}
+
+impl ImageHeader {
+    pub fn to_bytes(&self) -> [u8; HEADER_LEN] {
+        let mut buf = [0u8; HEADER_LEN];
+        buf[0..4].copy_from_slice(&MAGIC.to_le_bytes());
+        buf[4..8].copy_from_slice(&self.number.to_le_bytes());
+        buf[8..12].copy_from_slice(&self.time_stamp.to_le_bytes());
+        // 0 is unknown, the rtc is never set to the epoch
+        buf[12..20].copy_from_slice(&self.utc.unwrap_or(0).to_le_bytes());
+        buf[20..24].copy_from_slice(&self.len.to_le_bytes());
+        buf
+    }
+
+    /// the header at the start of a block, None if the block doesn't start a picture
+    pub fn parse(block: &[u8]) -> Option<Self> {
+        let word = |at: usize| block.get(at..at + 4).map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]));
+        if word(0)? != MAGIC {
+            return None;
+        }
+        let utc = u64::from(word(12)?) | u64::from(word(16)?) << 32;
+        Some(Self {
+            number: word(4)?,
+            time_stamp: word(8)?,
+            utc: Some(utc).filter(|&utc| utc != 0),
+            len: word(20)?,
+        })
+    }
+
+    /// blocks the picture takes on the card, header and padding included
+    pub fn blocks(&self) -> usize {
+        (HEADER_LEN + self.len as usize).div_ceil(BLOCK_SIZE)
+    }
+}
+
+/// Reads the pictures out of an image stream, as dumped from the card from the start of a boot's region
+/// the stream ends at the first block that doesn't start a picture, a picture cut short by the end of the dump comes
+/// back short
+#[derive(Clone)]
+pub struct Reader<'a> {
+    stream: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(stream: &'a [u8]) -> Self {
+        Self { stream }
+    }
+}
+
+impl<'a> Iterator for Reader<'a> {
+    type Item = (ImageHeader, &'a [u8]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let header = ImageHeader::parse(self.stream)?;
+        let jpeg = self.stream.get(HEADER_LEN..).unwrap_or_default();
+        let jpeg = &jpeg[..jpeg.len().min(header.len as usize)];
+        self.stream = self.stream.get(header.blocks() * BLOCK_SIZE..).unwrap_or_default();
+        Some((header, jpeg))
+    }
+}
+
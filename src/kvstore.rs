@@ -0,0 +1,449 @@
+// small key value store for values that change one at a time, the calibration parts first of all
+// entries are appended to the region and the newest entry for a key is its value, so saving a new gyro bias writes a
+// few dozen bytes rather than erasing a flash sector, the region is only erased once it fills, and the newest entry
+// of every key is then copied back after the erase
+// the region header counts the erases, to keep an eye on the flash's wear
+// each entry carries its key's layout version, an entry in another version reads as no value, so only the key whose
+// layout changed falls back to its default
+// an entry cut short by a reset fails its crc and ends the scan at boot, the next write compacts past it
+// a compaction that couldn't make room for the new entry leaves the region as it was
+// a reset between the erase and the copy back loses the store, the window is the write of a few hundred bytes
+
+use core::future::Future;
+
+use crate::crc::crc32;
+use crate::storage::RamError;
+
+/// Byte addressed region the store lives in, a sector of internal flash or anything like it
+pub trait KvFlash {
+    type Error: defmt::Format;
+
+    /// bytes in the region
+    fn capacity(&self) -> u32;
+
+    /// writes are whole multiples of this at offsets aligned to it, up to KV_WRITE_SIZE
+    fn write_size(&self) -> u32;
+
+    fn read(&mut self, offset: u32, bytes: &mut [u8]) -> impl Future<Output = Result<(), Self::Error>>;
+
+    /// write over erased bytes, each byte is written once between erases
+    fn write(&mut self, offset: u32, bytes: &[u8]) -> impl Future<Output = Result<(), Self::Error>>;
+
+    /// clear the whole region to 0xFF
+    fn erase(&mut self) -> impl Future<Output = Result<(), Self::Error>>;
+}
+
+/// keys are 0 up to KV_KEYS
+pub const KV_KEYS: usize = 8;
+
+/// longest value
+pub const KV_VALUE_LEN: usize = 64;
+
+/// largest write size a KvFlash can have
+pub const KV_WRITE_SIZE: usize = 32;
+
+/// identifies a formatted region, "KVS1"
+const MAGIC: u32 = 0x4B56_5331;
+
+// magic, erase count, crc of the two
+const HEADER_LEN: usize = 12;
+
+// key, version, value length, then the value and a crc of all of it
+const ENTRY_HEADER_LEN: usize = 4;
+
+// the longest entry padded out to the largest write size
+const ENTRY_BUF_LEN: usize = (ENTRY_HEADER_LEN + KV_VALUE_LEN + 4).div_ceil(KV_WRITE_SIZE) * KV_WRITE_SIZE;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug, defmt::Format)]
+pub enum KvError<E> {
+    Flash(E),
+    /// key past KV_KEYS or a value longer than KV_VALUE_LEN
+    Invalid,
+    /// the newest entries don't leave room for the new one even after compacting
+    Full,
+}
+
+// where a key's newest entry is
+#[derive(Copy, Clone)]
+struct Entry {
+    offset: u32,
+    version: u8,
+    len: u16,
+}
+
+/// Index of a region's entries, built by scanning it, the region itself is passed to each call so the flash can be
+/// shared with whatever else lives on it
+pub struct KvStore {
+    // each key's newest entry
+    latest: [Option<Entry>; KV_KEYS],
+    // where the next entry goes, the capacity when the next write has to erase first
+    end: u32,
+    erases: u32,
+    formatted: bool,
+}
+
+impl KvStore {
+    /// scan a region, a region without a valid header (blank, or something else was kept there) is empty and is
+    /// erased on the first write
+    pub async fn open<F: KvFlash>(flash: &mut F) -> Result<Self, F::Error> {
+        let mut store = Self::empty(flash);
+        let mut header = [0u8; HEADER_LEN];
+        flash.read(0, &mut header).await?;
+        let word = |at: usize| u32::from_le_bytes([header[at], header[at + 1], header[at + 2], header[at + 3]]);
+        if word(0) != MAGIC || word(8) != crc32(&header[..8]) {
+            return Ok(store);
+        }
+        store.formatted = true;
+        store.erases = word(4);
+
+        let mut offset = align(flash, HEADER_LEN);
+        let mut entry = [0u8; ENTRY_BUF_LEN];
+        store.end = loop {
+            if offset as usize + ENTRY_HEADER_LEN + 4 > flash.capacity() as usize {
+                break offset;
+            }
+            flash.read(offset, &mut entry[..ENTRY_HEADER_LEN]).await?;
+            let [key, version, len_low, len_high] = [entry[0], entry[1], entry[2], entry[3]];
+            if key == 0xFF {
+                break offset;
+            }
+            // anything that doesn't check out was cut short, nothing after it can be trusted
+            let len = u16::from_le_bytes([len_low, len_high]);
+            let total = ENTRY_HEADER_LEN + len as usize + 4;
+            if key as usize >= KV_KEYS || len as usize > KV_VALUE_LEN || offset as usize + total > flash.capacity() as usize {
+                break flash.capacity();
+            }
+            flash.read(offset, &mut entry[..total]).await?;
+            if !entry_crc_ok(&entry[..total]) {
+                break flash.capacity();
+            }
+            store.latest[key as usize] = Some(Entry { offset, version, len });
+            offset += align(flash, total);
+        };
+        Ok(store)
+    }
+
+    /// no entries, the region is erased on the first write, for starting over when the region can't be read
+    pub fn empty<F: KvFlash>(flash: &F) -> Self {
+        Self {
+            latest: [None; KV_KEYS],
+            end: flash.capacity(),
+            erases: 0,
+            formatted: false,
+        }
+    }
+
+    /// the region has a store in it, false before the first write to a blank region
+    pub fn is_formatted(&self) -> bool {
+        self.formatted
+    }
+
+    /// times the region has been erased, the store's share of the flash's wear
+    pub fn erases(&self) -> u32 {
+        self.erases
+    }
+
+    /// bytes of the region in use, entries replaced since the last erase included
+    pub fn used(&self) -> u32 {
+        self.end
+    }
+
+    /// read a key's value into value, returns its length, None with no value, a value in another version, or a value
+    /// that no longer checks out
+    pub async fn get<F: KvFlash>(&self, flash: &mut F, key: u8, version: u8, value: &mut [u8]) -> Result<Option<usize>, F::Error> {
+        let Some(Some(entry)) = self.latest.get(key as usize) else {
+            return Ok(None);
+        };
+        let len = entry.len as usize;
+        if entry.version != version || value.len() < len {
+            return Ok(None);
+        }
+        let mut bytes = [0u8; ENTRY_BUF_LEN];
+        let bytes = &mut bytes[..ENTRY_HEADER_LEN + len + 4];
+        flash.read(entry.offset, bytes).await?;
+        if !entry_crc_ok(bytes) {
+            return Ok(None);
+        }
+        value[..len].copy_from_slice(&bytes[ENTRY_HEADER_LEN..ENTRY_HEADER_LEN + len]);
+        Ok(Some(len))
+    }
+
+    /// write a key's new value, compacting the region first when it is full
+    pub async fn set<F: KvFlash>(&mut self, flash: &mut F, key: u8, version: u8, value: &[u8]) -> Result<(), KvError<F::Error>> {
+        if key as usize >= KV_KEYS || value.len() > KV_VALUE_LEN {
+            return Err(KvError::Invalid);
+        }
+        let mut entry = [0xFFu8; ENTRY_BUF_LEN];
+        let len = encode_entry(&mut entry, key, version, value);
+        let padded = align(flash, len);
+        if self.end as usize + padded as usize > flash.capacity() as usize {
+            self.compact(flash, key, padded).await?;
+        }
+        flash.write(self.end, &entry[..padded as usize]).await.map_err(KvError::Flash)?;
+        self.latest[key as usize] = Some(Entry { offset: self.end, version, len: value.len() as u16 });
+        self.end += padded;
+        Ok(())
+    }
+
+    // erase the region and write back the newest entry of every key but the one about to be replaced, making room for
+    // an entry of padded bytes, when there wouldn't be room the region is left alone so the replaced value survives
+    async fn compact<F: KvFlash>(&mut self, flash: &mut F, replaced: u8, padded: u32) -> Result<(), KvError<F::Error>> {
+        let mut kept = [[0xFFu8; ENTRY_BUF_LEN]; KV_KEYS];
+        let mut lens = [0usize; KV_KEYS];
+        for (key, entry) in self.latest.iter().enumerate() {
+            let Some(entry) = entry.filter(|_| key != replaced as usize) else {
+                continue;
+            };
+            let len = ENTRY_HEADER_LEN + entry.len as usize + 4;
+            flash.read(entry.offset, &mut kept[key][..len]).await.map_err(KvError::Flash)?;
+            // a value gone bad since the scan is dropped rather than copied forward
+            if entry_crc_ok(&kept[key][..len]) {
+                lens[key] = len;
+            }
+        }
+        let needed = align(flash, HEADER_LEN) + lens.iter().map(|&len| align(flash, len)).sum::<u32>() + padded;
+        if needed > flash.capacity() {
+            return Err(KvError::Full);
+        }
+
+        flash.erase().await.map_err(KvError::Flash)?;
+        self.erases += 1;
+        self.formatted = true;
+        self.latest = [None; KV_KEYS];
+        let mut header = [0xFFu8; KV_WRITE_SIZE];
+        header[0..4].copy_from_slice(&MAGIC.to_le_bytes());
+        header[4..8].copy_from_slice(&self.erases.to_le_bytes());
+        let crc = crc32(&header[..8]);
+        header[8..12].copy_from_slice(&crc.to_le_bytes());
+        flash.write(0, &header[..align(flash, HEADER_LEN) as usize]).await.map_err(KvError::Flash)?;
+        self.end = align(flash, HEADER_LEN);
+
+        for (key, (entry, len)) in kept.iter().zip(lens).enumerate().filter(|(_, (_, len))| *len > 0) {
+            let padded = align(flash, len);
+            flash.write(self.end, &entry[..padded as usize]).await.map_err(KvError::Flash)?;
+            self.latest[key] = Some(Entry { offset: self.end, version: entry[1], len: (len - ENTRY_HEADER_LEN - 4) as u16 });
+            self.end += padded;
+        }
+        Ok(())
+    }
+}
+
+// an entry into the start of buf, returns its length before padding
+fn encode_entry(buf: &mut [u8], key: u8, version: u8, value: &[u8]) -> usize {
+    let len = ENTRY_HEADER_LEN + value.len();
+    buf[0] = key;
+    buf[1] = version;
+    buf[2..4].copy_from_slice(&(value.len() as u16).to_le_bytes());
+    buf[ENTRY_HEADER_LEN..len].copy_from_slice(value);
+    let crc = crc32(&buf[..len]);
+    buf[len..len + 4].copy_from_slice(&crc.to_le_bytes());
+    len + 4
+}
+
+fn entry_crc_ok(entry: &[u8]) -> bool {
+    let (body, crc) = entry.split_at(entry.len() - 4);
+    crc32(body).to_le_bytes() == crc
+}
+
+// length rounded up to the region's write size
+fn align<F: KvFlash>(flash: &F, len: usize) -> u32 {
+    let write_size = flash.write_size().max(1);
+    (len as u32).div_ceil(write_size) * write_size
+}
+
+/// Mock region in ram, for running the store on the host, holds to the flash rules, a byte written twice without an
+/// erase in between fails rather than ending up with the two writes anded together
+pub struct RamFlash<const BYTES: usize> {
+    bytes: [u8; BYTES],
+    written: [bool; BYTES],
+    write_size: u32,
+    erases: u32,
+}
+
+impl<const BYTES: usize> RamFlash<BYTES> {
+    pub fn new(write_size: u32) -> Self {
+        Self {
+            bytes: [0xFF; BYTES],
+            written: [false; BYTES],
+            write_size,
+            erases: 0,
+        }
+    }
+
+    pub fn bytes(&self) -> &[u8; BYTES] {
+        &self.bytes
+    }
+
+    /// times erase was called
+    pub fn erases(&self) -> u32 {
+        self.erases
+    }
+}
+
+impl<const BYTES: usize> KvFlash for RamFlash<BYTES> {
+    type Error = RamError;
+
+    fn capacity(&self) -> u32 {
+        BYTES as u32
+    }
+
+    fn write_size(&self) -> u32 {
+        self.write_size
+    }
+
+    async fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), RamError> {
+        let start = offset as usize;
+        bytes.copy_from_slice(self.bytes.get(start..start + bytes.len()).ok_or(RamError::OutOfRange)?);
+        Ok(())
+    }
+
+    async fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), RamError> {
+        let write_size = self.write_size.max(1);
+        if !offset.is_multiple_of(write_size) || !(bytes.len() as u32).is_multiple_of(write_size) {
+            return Err(RamError::Unaligned);
+        }
+        let range = offset as usize..offset as usize + bytes.len();
+        if range.end > BYTES {
+            return Err(RamError::OutOfRange);
+        }
+        if self.written[range.clone()].iter().any(|&written| written) {
+            return Err(RamError::NotErased);
+        }
+        self.bytes[range.clone()].copy_from_slice(bytes);
+        self.written[range].fill(true);
+        Ok(())
+    }
+
+    async fn erase(&mut self) -> Result<(), RamError> {
+        self.bytes.fill(0xFF);
+        self.written.fill(false);
+        self.erases += 1;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embassy_futures::block_on;
+
+    use super::*;
+
+    // room for the header and four entries of 16 byte values at a write size of 8
+    type Flash = RamFlash<120>;
+
+    async fn value(store: &KvStore, flash: &mut Flash, key: u8, version: u8) -> Option<Vec<u8>> {
+        let mut value = [0u8; KV_VALUE_LEN];
+        let len = store.get(flash, key, version, &mut value).await.unwrap()?;
+        Some(value[..len].to_vec())
+    }
+
+    #[test]
+    fn blank_region_is_formatted_on_the_first_write() {
+        let mut flash = Flash::new(8);
+        block_on(async {
+            let mut store = KvStore::open(&mut flash).await.unwrap();
+            assert!(!store.is_formatted());
+            assert_eq!(value(&store, &mut flash, 0, 1).await, None);
+            store.set(&mut flash, 0, 1, &[1, 2, 3]).await.unwrap();
+            store.set(&mut flash, 5, 1, &[4; 16]).await.unwrap();
+            assert!(store.is_formatted());
+            assert_eq!(store.erases(), 1);
+
+            let store = KvStore::open(&mut flash).await.unwrap();
+            assert_eq!(value(&store, &mut flash, 0, 1).await, Some(vec![1, 2, 3]));
+            assert_eq!(value(&store, &mut flash, 5, 1).await, Some(vec![4; 16]));
+        });
+    }
+
+    #[test]
+    fn newest_entry_is_the_value() {
+        let mut flash = Flash::new(8);
+        block_on(async {
+            let mut store = KvStore::open(&mut flash).await.unwrap();
+            store.set(&mut flash, 2, 1, &[1; 16]).await.unwrap();
+            let used = store.used();
+            store.set(&mut flash, 2, 1, &[2; 16]).await.unwrap();
+            // appended after the old entry rather than erasing
+            assert!(store.used() > used);
+            assert_eq!(store.erases(), 1);
+
+            let store = KvStore::open(&mut flash).await.unwrap();
+            assert_eq!(value(&store, &mut flash, 2, 1).await, Some(vec![2; 16]));
+        });
+    }
+
+    #[test]
+    fn other_version_reads_as_no_value() {
+        let mut flash = Flash::new(8);
+        block_on(async {
+            let mut store = KvStore::open(&mut flash).await.unwrap();
+            store.set(&mut flash, 1, 1, &[7; 4]).await.unwrap();
+            store.set(&mut flash, 3, 1, &[8; 4]).await.unwrap();
+            assert_eq!(value(&store, &mut flash, 1, 2).await, None);
+            // only the key whose layout changed loses its value
+            assert_eq!(value(&store, &mut flash, 3, 1).await, Some(vec![8; 4]));
+        });
+    }
+
+    #[test]
+    fn full_region_compacts_to_the_newest_entries() {
+        let mut flash = Flash::new(8);
+        block_on(async {
+            let mut store = KvStore::open(&mut flash).await.unwrap();
+            store.set(&mut flash, 0, 1, &[1; 16]).await.unwrap();
+            for round in 0..10u8 {
+                store.set(&mut flash, 1, 1, &[round; 16]).await.unwrap();
+            }
+            assert!(store.erases() > 1);
+            assert_eq!(store.erases(), flash.erases());
+
+            let store = KvStore::open(&mut flash).await.unwrap();
+            assert_eq!(store.erases(), flash.erases());
+            assert_eq!(value(&store, &mut flash, 0, 1).await, Some(vec![1; 16]));
+            assert_eq!(value(&store, &mut flash, 1, 1).await, Some(vec![9; 16]));
+        });
+    }
+
+    #[test]
+    fn entry_that_wont_fit_keeps_the_old_value() {
+        let mut flash = Flash::new(8);
+        block_on(async {
+            let mut store = KvStore::open(&mut flash).await.unwrap();
+            for key in 0..4 {
+                store.set(&mut flash, key, 1, &[key; 16]).await.unwrap();
+            }
+            let erases = flash.erases();
+            assert_eq!(store.set(&mut flash, 3, 1, &[9; KV_VALUE_LEN]).await, Err(KvError::Full));
+            assert_eq!(flash.erases(), erases);
+
+            let store = KvStore::open(&mut flash).await.unwrap();
+            for key in 0..4 {
+                assert_eq!(value(&store, &mut flash, key, 1).await, Some(vec![key; 16]));
+            }
+        });
+    }
+
+    #[test]
+    fn write_cut_short_is_skipped_and_compacted_past() {
+        let mut flash = Flash::new(8);
+        block_on(async {
+            let mut store = KvStore::open(&mut flash).await.unwrap();
+            store.set(&mut flash, 0, 1, &[1; 16]).await.unwrap();
+            // the first write of an entry for key 1, the reset came before the rest of it
+            let mut entry = [0xFFu8; ENTRY_BUF_LEN];
+            encode_entry(&mut entry, 1, 1, &[2; 16]);
+            flash.write(store.used(), &entry[..8]).await.unwrap();
+
+            let mut store = KvStore::open(&mut flash).await.unwrap();
+            assert_eq!(value(&store, &mut flash, 0, 1).await, Some(vec![1; 16]));
+            assert_eq!(value(&store, &mut flash, 1, 1).await, None);
+            let erases = store.erases();
+            store.set(&mut flash, 1, 1, &[3; 16]).await.unwrap();
+            assert_eq!(store.erases(), erases + 1);
+
+            let store = KvStore::open(&mut flash).await.unwrap();
+            assert_eq!(value(&store, &mut flash, 0, 1).await, Some(vec![1; 16]));
+            assert_eq!(value(&store, &mut flash, 1, 1).await, Some(vec![3; 16]));
+        });
+    }
+}
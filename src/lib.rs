@@ -1,10 +1,75 @@
-#![no_std]
+#![cfg_attr(not(any(test, feature = "std")), no_std)]
+
+pub mod actuation;
+pub mod ahrs;
+pub mod antenna;
+pub mod assist;
+pub mod atmosphere;
+pub mod auth;
+pub mod baro;
+pub mod blockqueue;
+pub mod busrecovery;
+pub mod calibration;
+pub mod command;
+pub mod compact;
+pub mod config;
+pub mod console;
+pub mod control;
+pub mod crash;
+pub mod crc;
+pub mod dead_reckoning;
+pub mod deploy;
+pub mod drivers;
+pub mod dutycycle;
+pub mod estimator;
+pub mod faults;
+pub mod filters;
+#[cfg(feature = "fixed-point")]
+pub mod fixed;
+pub mod flightlog;
+pub mod gimbal;
+pub mod gnss;
+pub mod health;
+pub mod heater;
+pub mod history;
+pub mod imagelog;
+pub mod kvstore;
+#[cfg(feature = "mavlink")]
+pub mod mavlink;
+pub mod met;
+pub mod mission;
+pub mod nav;
+pub mod packet;
+pub mod pending;
+pub mod power;
+pub mod rails;
+pub mod prediction;
+pub mod redundancy;
+#[cfg(feature = "replay")]
+pub mod replay;
+pub mod reset;
+pub mod resume;
+pub mod schedule;
+pub mod sil;
+pub mod ssdv;
+pub mod storage;
+pub mod sun;
+pub mod timing;
+pub mod update;
+pub mod validate;
+pub mod sensors;
+pub mod sequence;
+pub mod vibration;
+pub mod watchdog;
+pub mod wind;
 
 /// Time stamped barometer data structure
 #[derive(Copy, Clone)]
 pub struct BaroData {
     pub pressure: f32,
     pub temperature: f32,
+    /// status and the checks the sample failed, drivers leave it empty and the baro task fills it in
+    pub flags: validate::SampleFlags,
     pub time_stamp: u32,
 }
 
@@ -14,5 +79,394 @@ pub struct ImuData {
     pub acceleration: [f32; 3],
     pub gyro: [f32; 3],
     pub mag: [f32; 3],
+    pub temperature: f32,
+    /// status and the checks the sample failed, drivers leave it empty and the imu task fills it in
+    pub flags: validate::SampleFlags,
+    pub time_stamp: u32,
+}
+
+/// Time stamped gps fix, velocity is (north, east, up) in m/s and altitude is above sea level in m
+/// utc is the fix time in ms since the unix epoch, None until the receiver has time
+/// quality is graded by the gps task's FixGate, drivers leave it Invalid
+#[derive(Copy, Clone)]
+pub struct GpsData {
+    pub latitude: f64,
+    pub longitude: f64,
+    pub altitude: f32,
+    pub velocity: [f32; 3],
+    pub satellites: u8,
+    pub fix: FixType,
+    /// horizontal dilution of precision
+    pub hdop: f32,
+    pub quality: gnss::FixQuality,
+    /// status, drivers leave it empty and the gps task sets it from the quality
+    pub flags: validate::SampleFlags,
+    pub utc: Option<u64>,
+    pub time_stamp: u32,
+}
+
+/// Gps solution type, numbered as the receiver reports it
+#[derive(Copy, Clone, PartialEq, Eq, Debug, defmt::Format)]
+pub enum FixType {
+    None = 0,
+    /// altitude held at a fixed value, only the horizontal position is solved
+    TwoD = 2,
+    ThreeD = 3,
+}
+
+impl FixType {
+    /// anything but a 2d or 3d solution (dead reckoning only, time only) is no fix
+    pub fn from_u8(value: u8) -> Self {
+        match value {
+            2 => FixType::TwoD,
+            3 => FixType::ThreeD,
+            _ => FixType::None,
+        }
+    }
+}
+
+/// Time stamped vertical state estimate (altitude in m, vertical speed in m/s, positive up)
+#[derive(Copy, Clone)]
+pub struct VerticalState {
+    pub altitude: f32,
+    pub vertical_speed: f32,
+    pub time_stamp: u32,
+}
+
+/// Time stamped attitude estimate, quaternion is (w, x, y, z) rotating body frame into earth frame
+#[derive(Copy, Clone)]
+pub struct AttitudeData {
+    pub quaternion: [f32; 4],
+    pub converged: bool,
+    pub time_stamp: u32,
+}
+
+/// Which sensors the navigation estimate is currently built from
+#[derive(Copy, Clone, PartialEq, Eq, defmt::Format)]
+pub enum NavMode {
+    /// no gps fix yet, only altitude is meaningful
+    Initializing,
+    /// gps, baro, and imu all contributing
+    Full,
+    /// gps lost (e.g. above 18 km), horizontal position is coasting on the last velocity
+    NoGps,
+    /// attitude unreliable, accelerometer ignored
+    Tumbling,
+}
+
+impl NavMode {
+    /// mode from its number in the log, unknown numbers read as Initializing
+    pub fn from_u8(value: u8) -> Self {
+        match value {
+            1 => NavMode::Full,
+            2 => NavMode::NoGps,
+            3 => NavMode::Tumbling,
+            _ => NavMode::Initializing,
+        }
+    }
+}
+
+/// Time stamped navigation state
+/// position is (north, east, altitude) in m relative to the first gps fix, velocity is (north, east, up) in m/s
+#[derive(Copy, Clone)]
+pub struct StateVector {
+    pub position: [f32; 3],
+    pub velocity: [f32; 3],
+    pub attitude: [f32; 4],
+    pub mode: NavMode,
+    pub time_stamp: u32,
+}
+
+/// Wind averaged over one altitude layer during ascent, altitude is the layer center in m and wind is (north, east) in m/s
+#[derive(Copy, Clone)]
+pub struct WindProfile {
+    pub altitude: f32,
+    pub wind: [f32; 2],
+    pub samples: u16,
+    pub time_stamp: u32,
+}
+
+/// Predicted burst and landing, times in s and landing position in deg
+#[derive(Copy, Clone)]
+pub struct Prediction {
+    pub burst_altitude: f32,
+    pub time_to_burst: f32,
+    pub descent_duration: f32,
+    pub landing_latitude: f64,
+    pub landing_longitude: f64,
+    pub time_stamp: u32,
+}
+
+/// Best known position, dead_reckoned is set when it was propagated from an old fix rather than measured
+/// and degraded when the fix it comes from was graded degraded
+#[derive(Copy, Clone)]
+pub struct PositionEstimate {
+    pub latitude: f64,
+    pub longitude: f64,
+    pub altitude: f32,
+    pub dead_reckoned: bool,
+    pub degraded: bool,
+    pub time_stamp: u32,
+}
+
+/// Launch site reference captured when arming, written at the start of the log and downlinked
+/// ground_pressure is in hPa and ground_altitude is its ISA altitude in m, altitude above ground is measured from it
+/// boot carries the last reset reason and boot count so a reset between arming and landing shows up
+#[derive(Copy, Clone)]
+pub struct SessionHeader {
+    pub ground_pressure: f32,
+    pub ground_altitude: f32,
+    pub boot: reset::BootInfo,
+    pub time_stamp: u32,
+}
+
+/// Identity of the running firmware, embedded at build time, written at the start of the log and downlinked at boot
+/// dirty is set when the build had uncommitted changes, features lists the enabled cargo features separated by commas
+#[derive(Copy, Clone, defmt::Format)]
+pub struct FirmwareInfo {
+    pub version: &'static str,
+    pub git_hash: &'static str,
+    pub dirty: bool,
+    pub profile: &'static str,
+    pub features: &'static str,
+}
+
+/// First downlink frame after every boot, what is running and why it (re)started
+#[derive(Copy, Clone)]
+pub struct BootReport {
+    pub firmware: FirmwareInfo,
+    pub boot: reset::BootInfo,
+}
+
+/// Identity of this build
+pub const FIRMWARE: FirmwareInfo = FirmwareInfo {
+    version: env!("CARGO_PKG_VERSION"),
+    git_hash: env!("FIRMWARE_GIT_HASH"),
+    dirty: matches!(env!("FIRMWARE_DIRTY").as_bytes(), b"true"),
+    profile: env!("FIRMWARE_PROFILE"),
+    features: env!("FIRMWARE_FEATURES"),
+};
+
+/// Pairs an uptime time stamp with UTC (ms since the unix epoch) so logged records can be converted to real time
+#[derive(Copy, Clone)]
+pub struct TimeSync {
+    pub utc: u64,
+    pub time_stamp: u32,
+}
+
+/// Audit record of a staged config parameter change
+#[derive(Copy, Clone)]
+pub struct ConfigChange {
+    pub key: config::ConfigKey,
+    pub old: config::ConfigValue,
+    pub new: config::ConfigValue,
+    pub time_stamp: u32,
+}
+
+/// Reply to a config read, pending is set when the value is staged but not yet committed
+#[derive(Copy, Clone)]
+pub struct ConfigReport {
+    pub key: config::ConfigKey,
+    pub value: config::ConfigValue,
+    pub pending: bool,
+    pub time_stamp: u32,
+}
+
+/// Deepest main stack use seen since boot and the stack size, in bytes
+#[derive(Copy, Clone)]
+pub struct StackUsage {
+    pub used: u32,
+    pub size: u32,
+    pub time_stamp: u32,
+}
+
+/// Number of recent fault events carried in each health report
+pub const HEALTH_RECENT_FAULTS: usize = 4;
+
+/// Items lost since boot because their consumer fell behind, baro, imu, and gps samples on their way to the log and
+/// filters, log records that didn't fit in the sd card queue, and telemetry items the radio had no room for
+#[derive(Copy, Clone, PartialEq, Eq, Default, defmt::Format)]
+pub struct DropCounts {
+    pub baro: u32,
+    pub imu: u32,
+    pub gps: u32,
+    pub log: u32,
+    pub telemetry: u32,
+}
+
+/// Active faults and the most recent fault events, newest first, downlinked periodically and whenever a fault changes
+/// cpu_load (percent) and loop_overruns cover the time since the previous report
+#[derive(Copy, Clone)]
+pub struct HealthReport {
+    pub faults: faults::FaultFlags,
+    pub recent: [Option<faults::FaultEvent>; HEALTH_RECENT_FAULTS],
+    pub cpu_load: u8,
+    pub loop_overruns: u16,
+    pub dropped: DropCounts,
+    /// actuation channels with continuity and armed channels
+    pub continuity: actuation::ChannelFlags,
+    pub armed: actuation::ChannelFlags,
+    /// antenna the rf switch has selected
+    pub antenna: antenna::Antenna,
+    /// battery temperature, deg C, nan when unknown
+    pub battery_temperature: f32,
+    pub time_stamp: u32,
+}
+
+/// Minimal position beacon sent in low voltage safe mode so the payload can still be found
+#[derive(Copy, Clone)]
+pub struct Beacon {
+    pub latitude: f64,
+    pub longitude: f64,
+    pub altitude: f32,
+    pub voltage: f32,
+    pub time_stamp: u32,
+}
+
+/// Battery and solar charger state, so a long float can see whether the array keeps up with the load
+#[derive(Copy, Clone, PartialEq, Debug, defmt::Format)]
+pub struct PowerData {
+    /// battery voltage, V
+    pub battery_voltage: f32,
+    /// array voltage at the charger input, V
+    pub solar_voltage: f32,
+    /// current from the array into the charger, A
+    pub solar_current: f32,
+    pub charge: power::ChargeState,
+    pub time_stamp: u32,
+}
+
+/// Items queued for the radio downlink
+#[derive(Copy, Clone)]
+pub enum Telemetry {
+    Prediction(Prediction),
+    Position(PositionEstimate),
+    Session(SessionHeader),
+    Health(HealthReport),
+    Panic(crash::PanicRecord),
+    Config(ConfigReport),
+    Beacon(Beacon),
+    Power(PowerData),
+    Boot(BootReport),
+    Update(update::UpdateStatus),
+    Assist(assist::AssistStatus),
+    Event(EventRecord),
+}
+
+/// Notable moments in the flight, broadcast to every task that reacts to them and marked in the black box log
+#[derive(Copy, Clone, PartialEq, Eq, Debug, defmt::Format)]
+pub enum FlightEvent {
+    /// acceleration near zero, usually balloon burst
+    FreeFall,
+    /// launch site reference captured, altitude is above ground from here
+    Armed,
+    /// launch site reference dropped on the pad
+    Disarmed,
+    /// launch detected, mission elapsed time starts here
+    Launch,
+    /// sustained descent, the balloon burst or was cut down
+    Burst,
+    /// back on the ground
+    Landed,
+    /// a fault was raised or cleared
+    Fault { fault: faults::Fault, active: bool },
+    /// staged config changes were applied
+    ConfigCommitted,
+    /// a load was switched off to save the battery
+    LoadShed(power::Load),
+    /// a shed load was switched back on after the battery recovered
+    LoadRestored(power::Load),
+    /// the payload camera took a still, its time stamp is the one in the image stream header
+    ImageCaptured,
+    /// an actuation channel was armed, and disarmed
+    ChannelArmed(actuation::Channel),
+    ChannelDisarmed(actuation::Channel),
+    /// an actuation channel fired, confirmed if current was seen through its load
+    ChannelFired { channel: actuation::Channel, confirmed: bool },
+    /// the altitude path moved to the other barometer of the pair
+    BaroSwitched(redundancy::Unit),
+    /// imu data moved to the other imu of the pair
+    ImuSwitched(redundancy::Unit),
+    /// a payload power rail was switched on, and off
+    RailOn(rails::Rail),
+    RailOff(rails::Rail),
+    /// the rf switch moved the radio to the antenna
+    AntennaSwitched(antenna::Antenna),
+    /// a duty cycled float went to sleep with the rails off, and woke up again
+    Sleep,
+    Wake,
+    /// a mission sequence rule fired, by its index
+    RuleFired(u8),
+}
+
+impl FlightEvent {
+    /// the event as a byte for the log and downlink, load, channel, and sensor switch events carry the load, channel,
+    /// or unit in the low bits, and a rule firing its index, faults go in as their fault flag instead
+    pub fn code(self) -> u8 {
+        match self {
+            FlightEvent::FreeFall => 0x00,
+            FlightEvent::Launch => 0x01,
+            FlightEvent::ConfigCommitted => 0x02,
+            FlightEvent::Armed => 0x03,
+            FlightEvent::Disarmed => 0x04,
+            FlightEvent::Burst => 0x05,
+            FlightEvent::Landed => 0x06,
+            FlightEvent::ImageCaptured => 0x07,
+            FlightEvent::Sleep => 0x08,
+            FlightEvent::Wake => 0x09,
+            FlightEvent::LoadShed(load) => 0x10 | load as u8,
+            FlightEvent::LoadRestored(load) => 0x20 | load as u8,
+            FlightEvent::ChannelArmed(channel) => 0x30 | channel.id(),
+            FlightEvent::ChannelDisarmed(channel) => 0x40 | channel.id(),
+            FlightEvent::ChannelFired { channel, confirmed: true } => 0x50 | channel.id(),
+            FlightEvent::ChannelFired { channel, confirmed: false } => 0x60 | channel.id(),
+            FlightEvent::BaroSwitched(unit) => 0x70 | unit.id(),
+            FlightEvent::ImuSwitched(unit) => 0x80 | unit.id(),
+            FlightEvent::RailOn(rail) => 0x90 | rail.id(),
+            FlightEvent::RailOff(rail) => 0xA0 | rail.id(),
+            FlightEvent::AntennaSwitched(antenna) => 0xB0 | antenna.id(),
+            FlightEvent::RuleFired(index) => 0xC0 | index & 0x0F,
+            FlightEvent::Fault { .. } => 0xFE,
+        }
+    }
+
+    /// event from its code, None for unknown codes and faults
+    pub fn from_code(code: u8) -> Option<Self> {
+        let load = || power::Load::ALL.get((code & 0x0F) as usize).copied();
+        let channel = || actuation::Channel::from_id(code & 0x0F);
+        let unit = || redundancy::Unit::from_id(code & 0x0F);
+        let rail = || rails::Rail::from_id(code & 0x0F);
+        match code {
+            0x00 => Some(FlightEvent::FreeFall),
+            0x01 => Some(FlightEvent::Launch),
+            0x02 => Some(FlightEvent::ConfigCommitted),
+            0x03 => Some(FlightEvent::Armed),
+            0x04 => Some(FlightEvent::Disarmed),
+            0x05 => Some(FlightEvent::Burst),
+            0x06 => Some(FlightEvent::Landed),
+            0x07 => Some(FlightEvent::ImageCaptured),
+            0x08 => Some(FlightEvent::Sleep),
+            0x09 => Some(FlightEvent::Wake),
+            0x10..=0x1F => load().map(FlightEvent::LoadShed),
+            0x20..=0x2F => load().map(FlightEvent::LoadRestored),
+            0x30..=0x3F => channel().map(FlightEvent::ChannelArmed),
+            0x40..=0x4F => channel().map(FlightEvent::ChannelDisarmed),
+            0x50..=0x5F => channel().map(|channel| FlightEvent::ChannelFired { channel, confirmed: true }),
+            0x60..=0x6F => channel().map(|channel| FlightEvent::ChannelFired { channel, confirmed: false }),
+            0x70..=0x7F => unit().map(FlightEvent::BaroSwitched),
+            0x80..=0x8F => unit().map(FlightEvent::ImuSwitched),
+            0x90..=0x9F => rail().map(FlightEvent::RailOn),
+            0xA0..=0xAF => rail().map(FlightEvent::RailOff),
+            0xB0..=0xBF => antenna::Antenna::from_id(code & 0x0F).map(FlightEvent::AntennaSwitched),
+            0xC0..=0xCF => Some(FlightEvent::RuleFired(code & 0x0F)),
+            _ => None,
+        }
+    }
+}
+
+/// Time stamped flight event
+#[derive(Copy, Clone)]
+pub struct EventRecord {
+    pub event: FlightEvent,
     pub time_stamp: u32,
 }
@@ -2,196 +2,4417 @@
 #![no_main]
 
 use defmt::*;
-use embassy_executor::{Spawner, task};
-use embassy_stm32::gpio::{Level, Output, Speed};
+// the glob above brings in defmt macros named like core's, these are the core ones
+use core::{assert, write};
+use embassy_executor::{InterruptExecutor, Spawner, task};
+use cortex_m::peripheral::DWT;
+use core::cell::{Cell, RefCell};
+use core::mem::MaybeUninit;
+use core::fmt::Write as _;
+
+use embassy_stm32::exti::ExtiInput;
+use embassy_stm32::gpio::{Level, Output};
+use embassy_stm32::interrupt;
+use embassy_stm32::interrupt::{InterruptExt, Priority};
+use embassy_stm32::peripherals::IWDG;
+use embassy_stm32::rtc::{Rtc, RtcConfig};
+use embassy_stm32::wdg::IndependentWatchdog;
 use embassy_time::{
-    Duration, Instant, Timer, WithTimeout
+    Duration, Instant, Ticker, Timer, WithTimeout
 };
+use embassy_futures::select::{Either, Either3, Either4, select, select3, select4};
 use embassy_sync::{
-    channel::Channel,
-    mutex::Mutex,
-    blocking_mutex::raw::ThreadModeRawMutex,
+    pubsub::{PubSubChannel, WaitResult},
+    signal::Signal,
+    watch::Watch,
+    blocking_mutex::{Mutex, raw::{CriticalSectionRawMutex, ThreadModeRawMutex}},
 };
+use embassy_usb::class::cdc_acm::{CdcAcmClass, State};
+use embassy_usb::driver::EndpointError;
+use embassy_usb::{Builder, UsbDevice};
+use embedded_hal_async::spi::{Operation, SpiDevice};
+use embedded_io_async::{Read as _, Write as _};
+use heapless::String;
+use static_cell::StaticCell;
 use avionics_sw_hapsis::*;
-use {defmt_rtt as _, panic_probe as _};
+use avionics_sw_hapsis::antenna::{Antenna, AntennaMode};
+use avionics_sw_hapsis::actuation::{Actuation, CONTINUITY_MAX_VOLTS, Channel, FireMonitor, FireReport};
+use avionics_sw_hapsis::assist::{AssistCommand, AssistError, AssistReceiver, AssistStatus};
+use avionics_sw_hapsis::auth::{self, Challenge};
+use avionics_sw_hapsis::baro::{BaroPipeline, BaroRead};
+use avionics_sw_hapsis::calibration::{
+    ACCEL_ORIENTATIONS, AccelCalibrator, CALIBRATION_PART_LEN, Calibration, CalibrationChange, CalibrationKey, GyroBiasEstimator, MagCalibrator,
+};
+use avionics_sw_hapsis::crash::PanicRecord;
+use avionics_sw_hapsis::command::{Command, Origin, TempPolyTarget};
+use avionics_sw_hapsis::compact::{CompactPosition, PositionEncoder};
+use avionics_sw_hapsis::packet::{
+    AssistPacket, BeaconPacket, BootPacket, ConfigPacket, EventPacket, HealthPacket, MAX_PACKET_LEN, Name, Packet, PanicPacket,
+    PowerPacket, PredictionPacket, SessionPacket, Temperature, UpdatePacket, Voltage,
+};
+#[cfg(not(feature = "mavlink"))]
+use avionics_sw_hapsis::packet::IMAGE_PACKET_ID;
+use avionics_sw_hapsis::blockqueue::{BLOCK_SIZE, BlockQueue, Consumer, PAD, Producer};
+use avionics_sw_hapsis::busrecovery::BusRecovery;
+use avionics_sw_hapsis::config::{Config, ConfigKey, ConfigValue};
+use avionics_sw_hapsis::drivers::register::{Addressing, WriteError, write_verified};
+use avionics_sw_hapsis::console::{self, LineBuffer, Request};
+use avionics_sw_hapsis::dead_reckoning::DeadReckoning;
+use avionics_sw_hapsis::control::FlightControl;
+use avionics_sw_hapsis::deploy::{APOGEE_CHANNEL, DeployMode, MAIN_CHANNEL};
+use avionics_sw_hapsis::dutycycle::{DutyCycle, DutyInput};
+use avionics_sw_hapsis::estimator::{AltitudeEstimator, AttitudeEstimator};
+use avionics_sw_hapsis::gimbal::{self, GimbalController, GimbalMode};
+use avionics_sw_hapsis::gnss::FixGate;
+use avionics_sw_hapsis::filters::MovingAverage;
+use avionics_sw_hapsis::faults::{Fault, FaultEvent, FaultFlags, FaultLog};
+use avionics_sw_hapsis::flightlog::{
+    Stream as LogStream, LOG_ATTITUDE, LOG_BARO, LOG_BUS_RECOVERY, LOG_CALIBRATION, LOG_COMMAND, LOG_CONFIG_CHANGE, LOG_DROP_COUNTS,
+    LOG_EVENT, LOG_FAULT, LOG_FIRING, LOG_FIRMWARE, LOG_GPS, LOG_HEATER, LOG_IMU, LOG_MET_SYNC, LOG_POWER, LOG_SESSION, LOG_STACK_USAGE,
+    LOG_STATE, LOG_STATE_VECTOR, LOG_TIME_SYNC, LOG_VIBRATION, LOG_VIBRATION_SAMPLES, LOG_WIND,
+};
+use avionics_sw_hapsis::health::{Stream, StreamMonitor};
+use avionics_sw_hapsis::heater::{HeaterController, HeaterReport};
+use avionics_sw_hapsis::imagelog::{self, ImageHeader};
+use avionics_sw_hapsis::mission::{FlightState, FreeFallDetector};
+use avionics_sw_hapsis::nav::NavFilter;
+use avionics_sw_hapsis::prediction::{PredictorConfig, PredictorInput};
+use avionics_sw_hapsis::redundancy::{PairLimits, PairMonitor, Unit};
+use avionics_sw_hapsis::validate::{Limits, SampleFlags, Validator};
+use avionics_sw_hapsis::reset::{BootInfo, ResetReason};
+use avionics_sw_hapsis::met::Met;
+use avionics_sw_hapsis::pending::{Condition, PENDING_COMMANDS, PendingCommands};
+use avionics_sw_hapsis::resume::{RESUME_REGISTERS, ResumeRecord, ResumeState};
+use avionics_sw_hapsis::power::{ChargeState, ColdProtection, Load, LoadShedder, LowVoltageDetector};
+use avionics_sw_hapsis::rails::{Rail, RailFlags, RailSequencer};
+use avionics_sw_hapsis::sequence::{self, Action, MISSION_RULES, Sequence, SequenceInput};
+use avionics_sw_hapsis::schedule::Scheduler;
+use avionics_sw_hapsis::sensors::{Actuators, Barometer, Battery, Camera, Charger, Gps, ImageSize, Imu};
+use avionics_sw_hapsis::storage::{BootLayout, LogStorage, LogWriter};
+use avionics_sw_hapsis::history::{self, HistoryReader, HistoryRecord, HistoryRequest};
+#[cfg(not(feature = "mavlink"))]
+use avionics_sw_hapsis::history::HISTORY_RECORD_LEN;
+use avionics_sw_hapsis::kvstore::{KV_WRITE_SIZE, KvFlash, KvStore};
+use avionics_sw_hapsis::sun::SunPosition;
+use avionics_sw_hapsis::timing::{BusyTime, LoopId, LoopTimings};
+use avionics_sw_hapsis::vibration::{self, BURST_SAMPLES, CHUNK_SAMPLES, SampleChunk, VibrationSummary};
+use avionics_sw_hapsis::update::{ImageReceiver, UpdateCommand, UpdateError, UpdateRecord, UpdateState, UpdateStatus};
+#[cfg(feature = "nucleo-f767")]
+use avionics_sw_hapsis::update::SlotTrailer;
+use avionics_sw_hapsis::watchdog::{CheckIns, TaskId};
+use avionics_sw_hapsis::wind::WindEstimator;
+use defmt_rtt as _;
+
+use libm::sqrtf;
+
+mod arbiter;
+mod bsp;
+mod queue;
+mod rates;
+mod slots;
+
+use queue::{Overflow, Queue};
+use rates::*;
+use slots::{IMAGE_SLOT_SIZE, UPDATE_RECORD, UPDATE_TRIAL_BOOTS};
+
+static BARO_DATA_CHANNEL: Queue<BaroData, DATA_CHANNEL_DEPTH> = Queue::new("baro data", Overflow::DropOldest); // baro data to send to sd card
+static IMU_DATA_PUBSUB: PubSubChannel<ThreadModeRawMutex, ImuData, IMU_QUEUE_DEPTH, IMU_SUBSCRIBERS, 0> = PubSubChannel::new(); // imu data to the sd card, gnc, and attitude estimator, every subscriber gets every sample
+static ATTITUDE_DATA_CHANNEL: Queue<AttitudeData, DATA_CHANNEL_DEPTH> = Queue::new("attitude data", Overflow::DropOldest); // attitude to send to sd card
+static GPS_DATA_CHANNEL: Queue<GpsData, DATA_CHANNEL_DEPTH> = Queue::new("gps data", Overflow::DropOldest); // gps data to send to sd card
+static NAV_GPS_CHANNEL: Queue<GpsData, DATA_CHANNEL_DEPTH> = Queue::new("nav gps", Overflow::DropOldest); // gps data to send to nav filter
+static NAV_INERTIAL_CHANNEL: Queue<(ImuData, AttitudeData), DATA_CHANNEL_DEPTH> = Queue::new("nav inertial", Overflow::DropOldest); // imu sample and matching attitude to send to nav filter
+static STATE_VECTOR_CHANNEL: Queue<StateVector, DATA_CHANNEL_DEPTH> = Queue::new("state vector", Overflow::DropOldest); // nav state to send to sd card
+static WIND_GPS_CHANNEL: Queue<GpsData, DATA_CHANNEL_DEPTH> = Queue::new("wind gps", Overflow::DropOldest); // gps data to send to wind estimator
+static WIND_PROFILE_CHANNEL: Queue<WindProfile, DATA_CHANNEL_DEPTH> = Queue::new("wind profile", Overflow::DropOldest); // finished wind layers to send to sd card
+static TELEMETRY_CHANNEL: Queue<Telemetry, TELEMETRY_DEPTH> = Queue::new("telemetry", Overflow::DropNewest); // items to send over the radio downlink
+static EVENT_BUS: PubSubChannel<ThreadModeRawMutex, EventRecord, EVENT_DEPTH, EVENT_SUBSCRIBERS, 0> = PubSubChannel::new(); // flight events for the log, the downlink, and any task that reacts to them
+static CONFIG_AUDIT_CHANNEL: Queue<ConfigChange, CONFIG_AUDIT_DEPTH> = Queue::new("config audit", Overflow::Block(FAST_SEND_TIMEOUT)); // config changes to write to sd card
+static STATE_CHANGE_CHANNEL: Queue<(FlightState, FlightState, u32), 2> = Queue::new("state change", Overflow::Block(FAST_SEND_TIMEOUT)); // flight state transitions, from and to, and time stamp to write to sd card
+static COMMAND_AUDIT_CHANNEL: Queue<(u8, bool, u32), COMMAND_DEPTH> = Queue::new("command audit", Overflow::Block(FAST_SEND_TIMEOUT)); // command codes the control task took, whether carried out, and time stamp to write to sd card
+static CALIBRATION_CHANGE_CHANNEL: Queue<(CalibrationChange, u32), 2> = Queue::new("calibration change", Overflow::DropNewest); // calibration changes and time stamp to write to sd card
+static SESSION_CHANNEL: Queue<SessionHeader, 2> = Queue::new("session", Overflow::Block(SLOW_SEND_TIMEOUT)); // session header to write to sd card
+static STACK_USAGE_CHANNEL: Queue<StackUsage, 2> = Queue::new("stack usage", Overflow::DropNewest); // new stack high water marks to write to sd card
+static DROP_COUNTS_CHANNEL: Queue<(DropCounts, u32), 2> = Queue::new("drop counts", Overflow::DropOldest); // drop counts and time stamp to write to sd card whenever they grow
+static IMAGE_CHANNEL: Queue<(u32, [u8; BLOCK_SIZE]), 2> = Queue::new("image", Overflow::Block(SLOW_SEND_TIMEOUT)); // camera still blocks and where they go in the image region, to write to sd card
+static VIBRATION_SAMPLES_CHANNEL: Queue<SampleChunk, VIBRATION_DEPTH> = Queue::new("vibration samples", Overflow::DropNewest); // raw vibration burst samples to write to sd card
+static VIBRATION_CHANNEL: Queue<VibrationSummary, 2> = Queue::new("vibration", Overflow::DropNewest); // vibration burst summaries to write to sd card
+static FIRE_CHANNEL: Queue<Channel, 3> = Queue::new("fire", Overflow::DropNewest); // armed channels to fire, for the actuation task
+static FIRING_CHANNEL: Queue<FireReport, 3> = Queue::new("firing", Overflow::Block(SLOW_SEND_TIMEOUT)); // current seen through fired channels, to write to sd card
+static BUS_RECOVERY_CHANNEL: Queue<BusRecovery, 3> = Queue::new("bus recovery", Overflow::DropOldest); // sensor bus clears to write to sd card
+static POWER_CHANNEL: Queue<PowerData, 2> = Queue::new("power", Overflow::DropOldest); // battery and solar charger readings to write to sd card
+static HEATER_CHANNEL: Queue<HeaterReport, 2> = Queue::new("heater", Overflow::DropOldest); // battery heater state and energy to write to sd card
+
+// state data, a new value replaces the old one so readers always get the freshest and never a backlog
+static VERTICAL_STATE_WATCH: Watch<ThreadModeRawMutex, VerticalState, 2> = Watch::new(); // filtered altitude and vertical speed for the control task and nav filter
+static ATTITUDE_WATCH: Watch<ThreadModeRawMutex, AttitudeData, 1> = Watch::new(); // attitude to send to gnc can bus, and the latest one for the gimbal
+static GNC_STATE_WATCH: Watch<ThreadModeRawMutex, StateVector, 1> = Watch::new(); // nav state to send to gnc can bus
+
+static FLIGHT_STATE: Mutex<ThreadModeRawMutex, Cell<FlightState>> = Mutex::new(Cell::new(FlightState::Pad)); // current flight state, set by control task
+static CALIBRATION: Mutex<ThreadModeRawMutex, Cell<Calibration>> = Mutex::new(Cell::new(Calibration::DEFAULT)); // active sensor calibration, loaded from flash at boot
+static CONFIG: Mutex<ThreadModeRawMutex, Cell<Config>> = Mutex::new(Cell::new(Config::DEFAULT)); // tunable parameters, loaded from flash at boot
+static CONFIG_SAVE_SIGNAL: Signal<ThreadModeRawMutex, Config> = Signal::new(); // config to persist to flash
+static USB_CONFIG_REPORT_SIGNAL: Signal<ThreadModeRawMutex, ConfigReport> = Signal::new(); // reply to a config read from the usb console
+static UART_CONFIG_REPORT_SIGNAL: Signal<ThreadModeRawMutex, ConfigReport> = Signal::new(); // reply to a config read from the uart console
+static CALIBRATION_SAVE_SIGNAL: Signal<ThreadModeRawMutex, Calibration> = Signal::new(); // calibration to persist to flash
+static PREFLIGHT_SIGNAL: Signal<ThreadModeRawMutex, ()> = Signal::new(); // rerun the preflight check after calibration, arming, or continuity changes
+static FINALIZE_LOG_SIGNAL: Signal<ThreadModeRawMutex, ()> = Signal::new(); // flush and close the log file for good before a reboot
+static LOG_FINALIZED_SIGNAL: Signal<ThreadModeRawMutex, ()> = Signal::new(); // log file closed, safe to reboot
+static LOG_BLOCK_SIGNAL: Signal<ThreadModeRawMutex, ()> = Signal::new(); // a finished log block is waiting for the log storage task
+static LOG_QUEUES: [BlockQueue<LOG_QUEUE_SIZE>; LOG_STREAMS] = [const { BlockQueue::new() }; LOG_STREAMS]; // serialized log records on their way to the sd card, a queue for each stream
+static COMMAND_CHANNEL: Queue<(Command, Origin), COMMAND_DEPTH> = Queue::new("command", Overflow::Block(CONSOLE_COMMAND_TIMEOUT)); // commands from uplink, consoles, and the onboard rules to control task, with where each came from
+static UPDATE_CHANNEL: Queue<UpdateCommand, COMMAND_DEPTH> = Queue::new("update", Overflow::DropNewest); // firmware image transfer steps to write to flash
+static ASSIST_CHANNEL: Queue<AssistCommand, COMMAND_DEPTH> = Queue::new("assist", Overflow::DropNewest); // gnss assistance upload steps for the gps task
+static ASSIST_STATUS_SIGNAL: Signal<ThreadModeRawMutex, AssistStatus> = Signal::new(); // reply to an assistance upload step, for the consoles
+static UPDATE_STATUS_SIGNAL: Signal<ThreadModeRawMutex, UpdateStatus> = Signal::new(); // reply to a firmware transfer step, for the consoles
+static MAG_CALIBRATION_SIGNAL: Signal<ThreadModeRawMutex, ()> = Signal::new(); // start a mag calibration run in the imu task
+static ACCEL_CALIBRATION_SIGNAL: Signal<ThreadModeRawMutex, ()> = Signal::new(); // start a six position accel calibration in the imu task
+static ARM_SIGNAL: Signal<ThreadModeRawMutex, ()> = Signal::new(); // capture the ground pressure reference in the baro task
+static IMU_DATA_READY_SIGNAL: Signal<CriticalSectionRawMutex, u32> = Signal::new(); // time stamp (us) of the latest imu data ready edge, set from the high priority executor
+static STREAM_MONITOR: Mutex<ThreadModeRawMutex, RefCell<StreamMonitor>> = Mutex::new(RefCell::new(StreamMonitor::new(STREAM_TIMEOUTS))); // newest sample time of each data stream
+static FAULT_LOG: Mutex<ThreadModeRawMutex, RefCell<FaultLog<FAULT_LOG_LEN>>> = Mutex::new(RefCell::new(FaultLog::new())); // recent fault events and the active fault set, reported by any task
+static RTC: Mutex<ThreadModeRawMutex, RefCell<Option<Rtc>>> = Mutex::new(RefCell::new(None)); // real time clock on vbat, disciplined from gps utc
+static WATCHDOG: Mutex<ThreadModeRawMutex, RefCell<CheckIns>> = Mutex::new(RefCell::new(CheckIns::new())); // critical tasks that must check in before the watchdog is fed
+static LOOP_TIMINGS: Mutex<ThreadModeRawMutex, RefCell<LoopTimings>> = Mutex::new(RefCell::new(LoopTimings::new())); // execution time and jitter of the task loops
+static BUSY_TIME: Mutex<CriticalSectionRawMutex, RefCell<BusyTime>> = Mutex::new(RefCell::new(BusyTime::new())); // cycles spent polling tasks, from the executor trace hooks on both executors
+static BOOT_INFO: Mutex<ThreadModeRawMutex, Cell<BootInfo>> = Mutex::new(Cell::new(BootInfo { reset_reason: ResetReason::Unknown, boot_count: 0 })); // last reset reason and boot count, set at boot
+static SESSION: Mutex<ThreadModeRawMutex, Cell<Option<SessionHeader>>> = Mutex::new(Cell::new(None)); // launch site reference, set when armed
+static LAUNCH_TIME: Mutex<ThreadModeRawMutex, Cell<Option<u32>>> = Mutex::new(Cell::new(None)); // uptime time stamp (us) of launch detection, mission elapsed time counts from here
+static MAX_ALTITUDE: Mutex<ThreadModeRawMutex, Cell<Option<f32>>> = Mutex::new(Cell::new(None)); // highest filtered altitude since launch, the burst altitude once descending, set by control task
+static LAUNCH_UTC: Mutex<ThreadModeRawMutex, Cell<Option<u64>>> = Mutex::new(Cell::new(None)); // utc of launch detection, kept for a warm restart, None if the rtc wasn't set
+static WIND_ESTIMATOR: Mutex<ThreadModeRawMutex, RefCell<WindEstimator>> = Mutex::new(RefCell::new(WindEstimator::new())); // wind profile measured so far, built by wind task
+static LATEST_GPS: Mutex<ThreadModeRawMutex, Cell<Option<GpsData>>> = Mutex::new(Cell::new(None)); // most recent gps fix
+static LATEST_BARO: Mutex<ThreadModeRawMutex, Cell<Option<BaroData>>> = Mutex::new(Cell::new(None)); // most recent baro sample, calibrated
+static LATEST_POSITION: Mutex<ThreadModeRawMutex, Cell<Option<PositionEstimate>>> = Mutex::new(Cell::new(None)); // most recent position, measured or dead reckoned
+static HIGH_RATE_LOGGING: Mutex<ThreadModeRawMutex, Cell<bool>> = Mutex::new(Cell::new(false)); // sample and log as fast as possible, set on free fall until landing
+static IMU_DROPPED: Mutex<ThreadModeRawMutex, Cell<u32>> = Mutex::new(Cell::new(0)); // imu samples a subscriber fell too far behind to get
+static LOG_DROPPED: Mutex<ThreadModeRawMutex, Cell<u32>> = Mutex::new(Cell::new(0)); // log records that didn't fit in the sd card queue
+static SENSOR_BUS_CLEARS: Mutex<ThreadModeRawMutex, Cell<u16>> = Mutex::new(Cell::new(0)); // sensor bus clears since boot, the tasks on the bus set their parts up again when it moves
+static SAFE_STATE: Mutex<ThreadModeRawMutex, Cell<bool>> = Mutex::new(Cell::new(false)); // control loop hung, fire requests dropped, outputs held off, and the beacon on, set by supervisor job
+static CONTROL_LOOP: Mutex<ThreadModeRawMutex, Cell<Option<LoopId>>> = Mutex::new(Cell::new(None)); // the control loop's timing entry, for the supervisor to watch
+static LOW_POWER: Mutex<ThreadModeRawMutex, Cell<bool>> = Mutex::new(Cell::new(false)); // supply voltage low, log file closed and radio only beaconing
+static BATTERY_COLD: Mutex<ThreadModeRawMutex, Cell<bool>> = Mutex::new(Cell::new(false)); // battery below the cold limit, high current loads held off and the heaters kept on, set by power job
+static LATEST_POWER: Mutex<ThreadModeRawMutex, Cell<Option<PowerData>>> = Mutex::new(Cell::new(None)); // most recent battery and solar charger reading, set by charger job
+static LATEST_HEATER: Mutex<ThreadModeRawMutex, Cell<Option<HeaterReport>>> = Mutex::new(Cell::new(None)); // battery heater state and energy, set by heater job
+static ANTENNA: Mutex<ThreadModeRawMutex, Cell<Antenna>> = Mutex::new(Cell::new(Antenna::Lower)); // antenna the rf switch has selected, set by radio task
+static RAILS: Mutex<ThreadModeRawMutex, Cell<RailFlags>> = Mutex::new(Cell::new(RailFlags::NONE)); // payload rails powered, set by rail job
+static IMAGE_BLOCKS: Mutex<ThreadModeRawMutex, Cell<u32>> = Mutex::new(Cell::new(0)); // blocks in this boot's image region, set by log storage task once its device is up
+static HISTORY_SIGNAL: Signal<ThreadModeRawMutex, HistoryRequest> = Signal::new(); // logged records the ground asked for, replaces a request still being sent
+static HISTORY_CHANNEL: Queue<HistoryRecord, HISTORY_DEPTH> = Queue::new("history", Overflow::DropNewest); // logged records read back by the log storage task, for the radio to send down
+static LOG_TAIL_SIGNAL: Signal<ThreadModeRawMutex, (LogStream, u8)> = Signal::new(); // stream and record count a console asked to tail, for the log storage task
+static LOG_TAIL_REPLY_SIGNAL: Signal<ThreadModeRawMutex, Option<[Option<HistoryRecord>; console::TAIL_RECORDS]>> = Signal::new(); // last records read back by the log storage task, None with no log to read, for the consoles
+static LATEST_VOLTAGE: Mutex<ThreadModeRawMutex, Cell<Option<f32>>> = Mutex::new(Cell::new(None)); // most recent battery voltage, set by power job
+static LOAD_SHEDDER: Mutex<ThreadModeRawMutex, RefCell<LoadShedder>> = Mutex::new(RefCell::new(LoadShedder::new(LOAD_SHED_THRESHOLDS, LOAD_SHED_HYSTERESIS))); // loads switched off to save the battery, set by load job
+static DUTY_CYCLE: Mutex<ThreadModeRawMutex, RefCell<DutyCycle>> = Mutex::new(RefCell::new(DutyCycle::new())); // whether a float may sleep, its awake window restarted by each command
+static RAILS_HELD: Mutex<ThreadModeRawMutex, Cell<bool>> = Mutex::new(Cell::new(false)); // payload rails held off for a sleep, set by sleep task
+static RAILS_DISABLED: Mutex<ThreadModeRawMutex, Cell<RailFlags>> = Mutex::new(Cell::new(RailFlags::NONE)); // payload rails kept off by the mission sequence, set by control task
+static SLEEPS: Mutex<ThreadModeRawMutex, Cell<u16>> = Mutex::new(Cell::new(0)); // sleeps since boot, the uptime clock stood still through each, so filters over time start over
+static BOOTLOADER_CHALLENGE: Mutex<ThreadModeRawMutex, RefCell<Challenge>> = Mutex::new(RefCell::new(Challenge::new(BOOTLOADER_KEY))); // nonce handed out by the consoles for the bootloader command's tag, checked by control task
+static PENDING: Mutex<ThreadModeRawMutex, RefCell<PendingCommands<PENDING_COMMANDS>>> = Mutex::new(RefCell::new(PendingCommands::new())); // commands held until a mission elapsed time or altitude, added and cancelled from the consoles, carried out by control task
+static ACTUATION: Mutex<ThreadModeRawMutex, RefCell<Actuation>> = Mutex::new(RefCell::new(Actuation::new())); // continuity and arming of the pyro and cutdown channels, sensed by actuation task
+#[cfg(not(feature = "mavlink"))]
+static THUMBNAIL: Mutex<ThreadModeRawMutex, RefCell<heapless::Vec<u8, THUMBNAIL_MAX_LEN>>> = Mutex::new(RefCell::new(heapless::Vec::new())); // payload camera jpeg thumbnail going down as ssdv
+#[cfg(not(feature = "mavlink"))]
+static THUMBNAIL_SIGNAL: Signal<ThreadModeRawMutex, u8> = Signal::new(); // image id of a new thumbnail, replaces the one going down
+
+// runs time critical tasks from the otherwise unused uart5 interrupt, preempting the thread mode executor
+static HIGH_PRIORITY_EXECUTOR: InterruptExecutor = InterruptExecutor::new();
+
+#[interrupt]
+unsafe fn UART5() {
+    unsafe { HIGH_PRIORITY_EXECUTOR.on_interrupt() }
+}
+
+// imu data subscribers: log, gnc, and attitude tasks
+const IMU_SUBSCRIBERS: usize = 3;
+
+// mavlink system and component ids, the only vehicle on the link
+#[cfg(feature = "mavlink")]
+const MAVLINK_SYSTEM_ID: u8 = 1;
+#[cfg(feature = "mavlink")]
+const MAVLINK_COMPONENT_ID: u8 = 1;
+
+// largest thumbnail the radio takes, about 100 packets, several minutes of image slots
+#[cfg(not(feature = "mavlink"))]
+const THUMBNAIL_MAX_LEN: usize = 12 * 1024;
+
+// ssdv callsign, up to 6 characters, the ground decoder names images by it
+#[cfg(not(feature = "mavlink"))]
+const SSDV_CALLSIGN: &str = "HAPSIS";
+
+// flight event subscribers: log, radio, wind, and imu tasks
+const EVENT_SUBSCRIBERS: usize = 4;
+
+// number of stationary imu samples averaged for one gyro bias estimate
+const GYRO_BIAS_SAMPLES: u32 = 100;
+
+// how long the payload is rotated during a mag calibration run
+const MAG_CALIBRATION_DURATION: Duration = Duration::from_secs(60);
+
+// guided accel calibration is abandoned if all six orientations aren't captured in time
+const ACCEL_CALIBRATION_TIMEOUT: Duration = Duration::from_secs(300);
+
+// landing prediction parameters for this flight
+const PREDICTOR_CONFIG: PredictorConfig = PredictorConfig {
+    burst_altitude: 30_000.0,
+    sea_level_descent_rate: 5.0,
+    ground_altitude: 190.0,
+};
+
+// sx127x version register and the value every part in the family reads
+const RADIO_REG_VERSION: u8 = 0x42;
+const RADIO_VERSION: u8 = 0x12;
+
+// sx127x fsk setup registers, op mode values are fsk asleep or in standby, with the low frequency bit below 525 MHz
+const RADIO_REG_OP_MODE: u8 = 0x01;
+const RADIO_REG_BITRATE_MSB: u8 = 0x02;
+const RADIO_REG_BITRATE_LSB: u8 = 0x03;
+const RADIO_REG_FRF_MSB: u8 = 0x06;
+const RADIO_REG_FRF_MID: u8 = 0x07;
+const RADIO_REG_FRF_LSB: u8 = 0x08;
+const RADIO_REG_PA_CONFIG: u8 = 0x09;
+const RADIO_MODE_SLEEP: u8 = 0x00;
+const RADIO_MODE_STANDBY: u8 = 0x01;
+const RADIO_MODE_LOW_FREQUENCY: u8 = 0x08;
+const RADIO_LOW_BAND_HZ: u32 = 525_000_000;
+// pa_boost output, the low bits are the power above 2 dBm, up to 17 dBm without the high power dac
+const RADIO_PA_BOOST: u8 = 0x80;
+const RADIO_XOSC_HZ: u64 = 32_000_000;
+
+type LogProducer = Producer<LOG_QUEUE_SIZE>;
+type LogConsumer = Consumer<LOG_QUEUE_SIZE>;
+
+const LOG_STREAMS: usize = LogStream::ALL.len();
+
+// longest debug stream line, a longer one is cut short
+const DEBUG_LINE_LEN: usize = 120;
+
+// the log device is written raw, each boot gets its own log and image region, 64 of each and at most 32 MB apiece,
+// on a card of 4 GB or more the logs fill the first 2 GB and camera stills (about 150 svga ones a boot) the next
+// smaller devices, the external flash among them, get smaller regions
+const LOG_BLOCKS_PER_BOOT: u32 = 65_536;
+const LOG_BOOT_REGIONS: u32 = 64;
+
+// panic record lives at the start of the 4 KB backup sram, which survives resets and runs from vbat without main power
+const PANIC_RECORD: *mut PanicRecord = 0x4002_4000 as *mut PanicRecord;
+const _: () = assert!(size_of::<PanicRecord>() <= 0x100);
+
+// set in uninitialized ram before a reset to have the next boot jump straight into the system bootloader, "DFU!"
+const BOOTLOADER_MAGIC: u32 = 0x2155_4644;
+
+// hmac key the bootloader command's tag is made with, 64 hex digits from BOOTLOADER_KEY at build time so it never sits
+// in the repository, a build without one can't be put into the bootloader by command
+const BOOTLOADER_KEY: Option<[u8; auth::KEY_LEN]> = match option_env!("BOOTLOADER_KEY") {
+    Some(hex) => match auth::parse_key(hex) {
+        Some(key) => Some(key),
+        None => core::panic!("BOOTLOADER_KEY must be 64 hex digits"),
+    },
+    None => None,
+};
+
+// survives a software reset since cortex-m-rt doesn't touch .uninit at startup
+#[unsafe(link_section = ".uninit.BOOTLOADER_REQUEST")]
+static mut BOOTLOADER_REQUEST: MaybeUninit<u32> = MaybeUninit::uninit();
+
+// flight state and launch site reference live in backup sram after the update record
+const RESUME_RECORD: *mut ResumeRecord = 0x4002_4200 as *mut ResumeRecord;
+
+// a new image is confirmed once every watchdog task has kept checking in this long
+const UPDATE_CONFIRM_AFTER: Duration = Duration::from_secs(60);
+
+// rtc backup register holding RTC_SYNC_MAGIC once the clock has been set from gps, survives resets on vbat
+const RTC_SYNC_REGISTER: usize = 0;
+const RTC_SYNC_MAGIC: u32 = 0x5554_4331;
+
+// rtc backup register counting boots, survives resets on vbat
+const BOOT_COUNT_REGISTER: usize = 1;
+
+// first of the rtc backup registers holding a copy of the resume record, the rest follow it
+const RESUME_FIRST_REGISTER: usize = 2;
+
+// rtc error against gps utc before the clock is reset, ms
+const RTC_MAX_DRIFT_MS: u64 = 1000;
+
+// 2S battery thresholds, low below 6.4 V (3.2 V per cell) for 5 s, recovered above 7.0 V for 5 s
+const LOW_VOLTAGE: f32 = 6.4;
+const RECOVERED_VOLTAGE: f32 = 7.0;
+const LOW_VOLTAGE_DURATION: Duration = Duration::from_secs(5);
+
+// cells in series in the flight battery
+const BATTERY_CELLS: u8 = 2;
+
+// battery temperature above the cold limit before cold protection ends, deg C
+const BATTERY_COLD_HYSTERESIS: f32 = 3.0;
+
+// most transmit power while the battery is cold, dBm, about a third of the current of full power
+const COLD_RADIO_POWER: i32 = 10;
+
+// battery heater thermostat, on below and off above, deg C, the element's power in W, and the most energy it may take
+// in an hour, J, 1.5 Wh is about 5% of the pack each hour
+const HEATER_ON_BELOW: f32 = 5.0;
+const HEATER_OFF_ABOVE: f32 = 10.0;
+const HEATER_POWER: f32 = 2.0;
+const HEATER_BUDGET: f32 = 5400.0;
+
+// gimbal servo travel used either side of centre, rad, and fastest slew, rad/s, the swing under a balloon is slow
+// and a gentler slew keeps the servo current down
+const GIMBAL_LIMIT: f32 = 0.7;
+const GIMBAL_MAX_RATE: f32 = 2.0;
+
+// faults that switch each payload rail off until they clear, in Rail::ALL order: gnc, radio pa, camera
+// the radio pa stays up through everything so the beacon keeps going out
+const RAIL_SHUTDOWN_FAULTS: [&[Fault]; Rail::ALL.len()] = [
+    &[Fault::LowVoltage],
+    &[],
+    &[Fault::LowVoltage, Fault::BatteryCold],
+];
+
+// state of charge (percent) below which each load is shed, in Load::ALL order: camera, secondary payloads, heaters, high rate logging
+// all of them are gone well before the low voltage safe mode at about 5%
+const LOAD_SHED_THRESHOLDS: [f32; Load::ALL.len()] = [50.0, 35.0, 25.0, 15.0];
+
+// state of charge above a threshold before its load is restored, percent
+const LOAD_SHED_HYSTERESIS: f32 = 5.0;
+
+
+// voltage samples averaged for the load shedding decision, in load shedding job periods
+const LOAD_SHED_AVERAGE_WINDOW: usize = 10;
+
+// stack use that gets a warning, percent of the stack size
+const STACK_WARN_PERCENT: u32 = 80;
+
+// control periods missed in a row before the supervisor takes the outputs to the safe state, well inside the check in
+// deadline so the safe state holds from there until the watchdog resets the board if the loop never comes back
+const CONTROL_HANG_PERIODS: u32 = 3;
+
+// usb ids for the console, the pid.codes test vid/pid, only ever seen by the team's laptops
+const USB_VID: u16 = 0x1209;
+const USB_PID: u16 = 0x0001;
+
+// usb full speed bulk packet size
+const CONSOLE_PACKET_SIZE: usize = 64;
+
+// longest console reply and the most fault events a log dump prints
+const CONSOLE_REPLY_LEN: usize = 1024;
+const CONSOLE_LOG_EVENTS: usize = 16;
+
+// fault events kept in ram
+const FAULT_LOG_LEN: usize = 32;
+
+// baro samples averaged into the ground pressure reference when arming
+const GROUND_PRESSURE_SAMPLES: usize = 20;
+
+// oldest gps fix that still settles a barometer disagreement, us
+const BARO_REFERENCE_AGE: u32 = 5_000_000;
+
+// redundant imus, a difference of IMU_ACCEL_DIVERGENCE in acceleration magnitude (m/s^2) or IMU_GYRO_DIVERGENCE in
+// rotation rate (rad/s) is one unit of divergence, the accel is compared by magnitude so the lever arm between the
+// parts only shows in a spin, 3 failed reads in a row is a failed part so the gnc stream has a short gap at most
+const IMU_ACCEL_DIVERGENCE: f32 = 2.0;
+const IMU_GYRO_DIVERGENCE: f32 = 0.2;
+const IMU_PAIR_LIMITS: PairLimits = PairLimits { divergence: 1.0, divergence_samples: 5, failed_samples: 3 };
+
+// what a working imu can read, the +-16 g and +-2000 dps full scale with a little room for the compensation, no rate
+// check as vibration moves both faster than any limit worth having, six axes identical for 10 samples is a frozen part
+const IMU_ACCEL_LIMITS: Limits = Limits { min: -160.0, max: 160.0, max_rate: f32::INFINITY };
+const IMU_GYRO_LIMITS: Limits = Limits { min: -35.0, max: 35.0, max_rate: f32::INFINITY };
+const IMU_FROZEN_SAMPLES: u16 = 10;
+
+// rolling average altitude window and vertical speed smoothing factor (0..1, higher is less smoothing)
+#[cfg(feature = "altitude-average")]
+const ALTITUDE_AVERAGE_WINDOW: usize = 10;
+#[cfg(feature = "altitude-average")]
+const VERTICAL_SPEED_ALPHA: f32 = 0.3;
+
+// attitude filter is selected at compile time, madgwick by default, mahony with the "mahony" feature
+// gains come from the config
+#[cfg(not(feature = "mahony"))]
+fn attitude_estimator(config: Config) -> impl AttitudeEstimator {
+    avionics_sw_hapsis::ahrs::Madgwick::new(config.madgwick_beta)
+}
 
-use libm::powf;
+#[cfg(feature = "mahony")]
+fn attitude_estimator(config: Config) -> impl AttitudeEstimator {
+    avionics_sw_hapsis::ahrs::Mahony::new(config.mahony_kp, config.mahony_ki)
+}
+
+// altitude estimator is selected at compile time, kalman by default, rolling average with the "altitude-average" feature
+#[cfg(not(feature = "altitude-average"))]
+fn altitude_estimator(config: Config) -> impl AltitudeEstimator {
+    avionics_sw_hapsis::filters::AltitudeKalman::new(config.altitude_accel_sigma, config.altitude_measurement_sigma)
+}
 
-static BARO_DATA_CHANNEL: Channel<ThreadModeRawMutex, BaroData, 4> = Channel::new(); // baro data to send to sd card
-static BARO_ALT_CHANNEL: Channel<ThreadModeRawMutex, f32, 4> = Channel::new(); // filtered altitude to send to control task
-static IMU_DATA_CHANNEL: Channel<ThreadModeRawMutex, ImuData, 4> = Channel::new(); // imu data to send to sd card and gnc
+#[cfg(feature = "altitude-average")]
+fn altitude_estimator(_config: Config) -> impl AltitudeEstimator {
+    avionics_sw_hapsis::estimator::RollingAverageAltitude::<ALTITUDE_AVERAGE_WINDOW>::new(VERTICAL_SPEED_ALPHA)
+}
 
 #[embassy_executor::main]
 async fn main(_spawner: Spawner) {
-    let p = embassy_stm32::init(Default::default());
+    // a bootloader request has to be served before any clock or peripheral is set up, the bootloader expects reset state
+    let request = (&raw mut BOOTLOADER_REQUEST).cast::<u32>();
+    if unsafe { core::ptr::read_volatile(request) } == BOOTLOADER_MAGIC {
+        unsafe {
+            core::ptr::write_volatile(request, 0);
+            cortex_m::asm::bootload(bsp::SYSTEM_BOOTLOADER as *const u32);
+        }
+    }
+
+    // on the nucleo the bootloader has installed or rolled back an update by now and left the record saying which
+    enable_backup_sram();
+    let update = unsafe { core::ptr::read_volatile(UPDATE_RECORD) };
+
+    // the reset flags stay set until cleared, clear them so the next reset reports only its own cause
+    let reset_reason = ResetReason::from_csr(embassy_stm32::pac::RCC.csr().read().0);
+    embassy_stm32::pac::RCC.csr().modify(|w| w.set_rmvf(true));
+
+    // pll at the board's rate from the external clock, lse for the rtc
+    let p = embassy_stm32::init(bsp::config());
     info!("Hello World!");
 
-    let led = Output::new(p.PB7, Level::High, Speed::Low);
+    let board = bsp::init(p);
+    info!("clocks: sys: {} Hz, apb1: {} Hz, apb2: {} Hz", bsp::SYSCLK_HZ, bsp::APB1_HZ, bsp::APB2_HZ);
+
+    // the cycle counter times the task polls for the cpu load, the trace hooks read it from here on
+    let mut core = unsafe { cortex_m::Peripherals::steal() };
+    core.DCB.enable_trace();
+    core.DWT.enable_cycle_counter();
+
+    info!("firmware: {} ({}{}), {} build, features: {}", FIRMWARE.version, FIRMWARE.git_hash,
+        if FIRMWARE.dirty { ", dirty" } else { "" }, FIRMWARE.profile, FIRMWARE.features);
+
+    let rtc = Rtc::new(board.rtc, RtcConfig::default());
+    if rtc.read_backup_register(RTC_SYNC_REGISTER) == Some(RTC_SYNC_MAGIC) {
+        info!("rtc kept utc through reset: {}", rtc.now().ok());
+    } else {
+        warn!("rtc not set, utc unknown until gps time");
+    }
+
+    // the boot counter starts over only when the backup domain loses power
+    let boot_count = rtc.read_backup_register(BOOT_COUNT_REGISTER).unwrap_or(0).wrapping_add(1);
+    rtc.write_backup_register(BOOT_COUNT_REGISTER, boot_count);
+    RTC.lock(|r| r.replace(Some(rtc)));
+
+    let boot = BootInfo { reset_reason, boot_count };
+    BOOT_INFO.lock(|b| b.set(boot));
+    if reset_reason.is_unexpected() {
+        error!("unexpected reset: {}, boot {}", reset_reason, boot_count);
+    } else {
+        info!("reset reason: {}, boot {}", reset_reason, boot_count);
+    }
+
+    TELEMETRY_CHANNEL.send(Telemetry::Boot(BootReport { firmware: FIRMWARE, boot })).await;
+
+    // after a reset in flight carry on in the same state with the same launch site reference
+    // a power cycle or the reset button means the crew wants a fresh start
+    // the backup register copy is read first, the backup sram record covers a register write cut short by the reset
+    let resume = read_resume_registers().or_else(|| unsafe { core::ptr::read_volatile(RESUME_RECORD) }.state());
+    match resume {
+        Some(resume) if !matches!(reset_reason, ResetReason::PowerOn | ResetReason::Pin) => {
+            warn!("resuming after reset: state: {}, armed: {}, channels armed: {}, fired: {}, high rate logging: {}",
+                resume.state, resume.ground.is_some(), resume.armed, resume.fired, resume.high_rate_logging);
+            FLIGHT_STATE.lock(|s| s.set(resume.state));
+            HIGH_RATE_LOGGING.lock(|h| h.set(resume.high_rate_logging));
+            ACTUATION.lock(|a| a.borrow_mut().resume(resume.armed, resume.fired));
+
+            // uptime restarted from zero, rebuild the launch time stamp from the rtc so met carries on
+            if let Some(launch_utc) = resume.launch_utc {
+                LAUNCH_UTC.lock(|l| l.set(Some(launch_utc)));
+                match time_sync() {
+                    Some(sync) => {
+                        // uptime time stamps wrap, so truncating the interval to u32 us keeps it right modulo the wrap
+                        let launch = sync.time_stamp.wrapping_sub((sync.utc.saturating_sub(launch_utc) * 1000) as u32);
+                        LAUNCH_TIME.lock(|l| l.set(Some(launch)));
+                        info!("mission elapsed time resumed at {}", Met::since(launch, sync.time_stamp));
+                    }
+                    None => warn!("rtc lost, mission elapsed time unknown after reset"),
+                }
+            }
+            if let Some((ground_pressure, ground_altitude)) = resume.ground {
+                let session = SessionHeader {
+                    ground_pressure,
+                    ground_altitude,
+                    boot,
+                    time_stamp: Instant::now().as_micros() as u32,
+                };
+                SESSION.lock(|s| s.set(Some(session)));
+
+                // the new log file needs the reference too
+                SESSION_CHANNEL.send(session).await;
+            }
+        }
+        _ => save_resume_record(),
+    }
+
+    // on the pad the core waits at half speed, launch detection brings it back to full speed
+    if pad_idle() {
+        bsp::set_half_speed(true);
+        info!("pad idle, core at {} Hz", bsp::core_hz());
+    }
+
+    match update.state() {
+        Some(UpdateState::Trial) => warn!("running updated firmware on trial, boot {} of {}", update.boots + 1, UPDATE_TRIAL_BOOTS + 1),
+        Some(UpdateState::RolledBack) => error!("updated firmware failed its trial, rolled back"),
+        _ => {}
+    }
+
+    // report a panic from before the last reset, then clear it so it is only reported once
+    let record = unsafe { core::ptr::read_volatile(PANIC_RECORD) };
+    if record.is_valid() {
+        error!("recovered from panic: {}, at {}:{}, uptime: {} us", record.message(), record.file(), record.line, record.uptime);
+        report_fault(Fault::Panic, true);
+        TELEMETRY_CHANNEL.send(Telemetry::Panic(record)).await;
+        unsafe { core::ptr::write_volatile(PANIC_RECORD, PanicRecord::EMPTY) };
+    }
+
+    // load config and calibration before the tasks start so they see the stored values from the first sample
+    let mut flash = board.flash;
+    let mut settings = board.settings;
+    let mut buf = [0u8; Config::SIZE];
+    let stored = bsp::config_region(&mut flash, &mut settings).read(0, &mut buf).await;
+    match stored.ok().and_then(|_| Config::from_bytes(&buf)) {
+        Some(stored) => {
+            info!("loaded config");
+            CONFIG.lock(|c| c.set(stored));
+        }
+        None => warn!("no valid config stored, using defaults"),
+    }
+
+    // each calibration part from the store, a part missing or in an older layout keeps its default
+    // a sector still holding the one record kept before the store is read once, the storage task moves it into the store
+    let calibration_store = match KvStore::open(&mut bsp::calibration_region(&mut flash, &mut settings)).await {
+        Ok(store) => store,
+        Err(e) => {
+            error!("calibration store unreadable ({}), starting it over", e);
+            KvStore::empty(&bsp::calibration_region(&mut flash, &mut settings))
+        }
+    };
+    let mut saved_calibration = Calibration::DEFAULT;
+    if calibration_store.is_formatted() {
+        info!("calibration store: {} bytes used, erased {} times", calibration_store.used(), calibration_store.erases());
+        for key in CalibrationKey::ALL {
+            let mut bytes = [0u8; CALIBRATION_PART_LEN];
+            let stored = calibration_store.get(&mut bsp::calibration_region(&mut flash, &mut settings), key.id(), key.version(), &mut bytes).await;
+            match stored {
+                Ok(Some(len)) if saved_calibration.decode_part(key, &bytes[..len]) => {}
+                _ => info!("no stored {}, using defaults", key),
+            }
+        }
+        CALIBRATION.lock(|c| c.set(saved_calibration));
+    } else {
+        let mut buf = [0u8; Calibration::SIZE];
+        match flash.blocking_read(bsp::CALIBRATION_FLASH_OFFSET, &mut buf).ok().and_then(|_| Calibration::from_bytes(&buf)) {
+            Some(calibration) => {
+                info!("loaded calibration record from flash, moving it to the calibration store");
+                CALIBRATION.lock(|c| c.set(calibration));
+                CALIBRATION_SAVE_SIGNAL.signal(calibration);
+            }
+            None => warn!("no valid calibration in flash, using defaults"),
+        }
+    }
+
+    // the data ready edge is time stamped on the high priority executor, ahead of whatever thread mode task is running
+    interrupt::UART5.set_priority(Priority::P6);
+    let high_priority = HIGH_PRIORITY_EXECUTOR.start(interrupt::UART5);
+    high_priority.spawn(imu_data_ready_task(board.imu_data_ready)).unwrap();
+
+    // the rails come up first so nothing waits on a payload that was never powered
+    _spawner.spawn(housekeeping_task(board.battery, board.charger, board.rails, board.heater)).unwrap();
+    _spawner.spawn(control_task(board.led)).unwrap();
+    _spawner.spawn(baro_task(board.barometers)).unwrap();
+    _spawner.spawn(imu_task(board.imus)).unwrap();
+    // only the first split of each queue succeeds and this is it
+    let mut log_consumers = [const { None }; LOG_STREAMS];
+    let log_producers = core::array::from_fn(|stream| {
+        let (producer, consumer) = LOG_QUEUES[stream].split().unwrap();
+        log_consumers[stream] = Some(consumer);
+        producer
+    });
+    _spawner.spawn(log_task(LogStreams::new(log_producers))).unwrap();
+    _spawner.spawn(log_storage_task(board.log_device, board.data_flash, log_consumers.map(Option::unwrap))).unwrap();
+    _spawner.spawn(attitude_task()).unwrap();
+    _spawner.spawn(gnc_task()).unwrap();
+    _spawner.spawn(gps_task(board.gps)).unwrap();
+    _spawner.spawn(nav_task()).unwrap();
+    _spawner.spawn(storage_task(flash, settings, calibration_store, saved_calibration)).unwrap();
+    _spawner.spawn(wind_task()).unwrap();
+    _spawner.spawn(report_task()).unwrap();
+    _spawner.spawn(radio_task(board.radio, board.rf_switch)).unwrap();
+    _spawner.spawn(sleep_task()).unwrap();
+    _spawner.spawn(camera_task(board.camera)).unwrap();
+    _spawner.spawn(gimbal_task(board.servos)).unwrap();
+    _spawner.spawn(actuation_task(board.actuators)).unwrap();
+    _spawner.spawn(sensor_bus_task(board.sensor_bus)).unwrap();
 
-    _spawner.spawn(control_task(led)).unwrap();
-    _spawner.spawn(baro_task()).unwrap();
-    _spawner.spawn(imu_task()).unwrap();
-    _spawner.spawn(log_task()).unwrap();
+    let (usb, console) = usb_console(board.usb);
+    _spawner.spawn(usb_task(usb)).unwrap();
+    _spawner.spawn(console_task(console)).unwrap();
+    _spawner.spawn(uart_console_task(board.console)).unwrap();
+    _spawner.spawn(watchdog_task(IndependentWatchdog::new(board.iwdg, WATCHDOG_TIMEOUT.as_micros() as u32))).unwrap();
 
     info!("All tasks spawned");
 }
 
 
+// turn on the backup sram and its regulator so the panic record is kept across resets and on vbat
+fn enable_backup_sram() {
+    use embassy_stm32::pac;
+
+    pac::RCC.apb1enr().modify(|w| w.set_pwren(true));
+    pac::PWR.cr1().modify(|w| w.set_dbp(true));
+    pac::RCC.ahb1enr().modify(|w| w.set_bkpsramen(true));
+    pac::PWR.csr1().modify(|w| w.set_bre(true));
+    while !pac::PWR.csr1().read().brr() {}
+}
+
+// saves the panic message, source location, and uptime to backup sram and resets, so an in flight panic
+// costs a reboot instead of the rest of the flight and can be diagnosed from the report at next boot
+// kept in its own module since the defmt glob import shadows the builtin panic_handler attribute
+mod panic {
+    use defmt::{Display2Format, error};
+    use embassy_time::Instant;
+
+    use avionics_sw_hapsis::crash::PanicRecord;
+
+    use super::PANIC_RECORD;
+
+    #[panic_handler]
+    fn panic(info: &core::panic::PanicInfo) -> ! {
+        cortex_m::interrupt::disable();
+
+        // the location is where the panic was raised, the return address here would only point into the panic machinery
+        let (file, line) = info.location().map_or(("", 0), |location| (location.file(), location.line()));
+        let record = PanicRecord::new(file, line, Instant::now().as_micros(), info.message());
+        unsafe { core::ptr::write_volatile(PANIC_RECORD, record) };
+        error!("panic: {}", Display2Format(info));
+
+        // on the bench stop in the fault handler so the debugger shows a backtrace
+        if cortex_m::peripheral::DCB::is_debugger_attached() {
+            cortex_m::asm::udf();
+        }
+        cortex_m::peripheral::SCB::sys_reset()
+    }
+}
+
 #[task]
 async fn control_task(mut led: Output<'static>) {
 
     info!("Starting main control loop");
 
+    let watchdog = watchdog_register("control", CONTROL_CHECK_IN_DEADLINE);
+
+    // config edits are staged here until committed so a half finished set of changes never flies
+    let mut staged_config: Option<Config> = None;
+
+    // on a rocket the drogue and main fire on their own, a deploy mode change takes effect at the next boot
+    let config = CONFIG.lock(|c| c.get());
+    let deploy_mode = DeployMode::from_u32(config.deploy_mode);
+    // channels fired before a warm restart stay fired, dual deploy doesn't fire them again
+    let fired = ACTUATION.lock(|a| a.borrow().fired());
+    let mut control = FlightControl::new(FLIGHT_STATE.lock(|s| s.get()), deploy_mode, config.main_deploy_altitude, fired);
+    info!("deploy mode: {}", deploy_mode);
+
+    let timing = loop_register("control", Some(CONTROL_PERIOD));
+    CONTROL_LOOP.lock(|c| c.set(Some(timing)));
+
+    // only the newest altitude matters, samples that arrived between ticks are skipped rather than queued
+    let mut vertical_state = VERTICAL_STATE_WATCH.receiver().unwrap();
+
+    // flight specific rules from config, reloaded every loop so a committed change applies without a reboot
+    let mut rules: Sequence<MISSION_RULES> = Sequence::new();
+
+    preflight_check();
+
+    // fixed rate so an overrun shows up as a late tick instead of silently stretching the period
+    let mut ticker = Ticker::every(CONTROL_PERIOD);
+
     loop {
+        watchdog_check_in(watchdog);
+        loop_start(timing);
+
         // do control stuff here
 
         // blink led to show alive
         led.set_low();
 
-        if let Ok(alt) = BARO_ALT_CHANNEL.try_receive() {
-            info!("Current altitude: {} m", alt);
+        if let Some(state) = vertical_state.try_changed() {
+            match SESSION.lock(|s| s.get()) {
+                Some(session) => info!("Current altitude: {} m AGL, vertical speed: {} m/s",
+                    state.altitude - session.ground_altitude, state.vertical_speed),
+                None => info!("Current altitude: {} m, vertical speed: {} m/s", state.altitude, state.vertical_speed),
+            }
+
+            // heights are above the launch site, without the arm reference there's no main deploy height to fire at
+            let update = control.update(&state, SESSION.lock(|s| s.get()).map(|session| session.ground_altitude));
+            MAX_ALTITUDE.lock(|m| m.set(control.max_altitude()));
+
+            if let Some((previous, flight_state)) = update.transition {
+                info!("flight state: {}", flight_state);
+                FLIGHT_STATE.lock(|s| s.set(flight_state));
+                STATE_CHANGE_CHANNEL.send((previous, flight_state, state.time_stamp)).await;
+
+                if flight_state == FlightState::Ascent {
+                    bsp::set_half_speed(false);
+                    info!("launch detected, leaving pad idle, core at {} Hz", bsp::core_hz());
+                    LAUNCH_TIME.lock(|l| l.set(Some(state.time_stamp)));
+                    LAUNCH_UTC.lock(|l| l.set(time_sync().map(|sync| sync.utc)));
+
+                    publish_event(FlightEvent::Launch, state.time_stamp);
+                }
+
+                if flight_state == FlightState::Descent {
+                    publish_event(FlightEvent::Burst, state.time_stamp);
+                }
+
+                // nothing interesting happens on the ground, drop back to the normal rate
+                if flight_state == FlightState::Landed {
+                    HIGH_RATE_LOGGING.lock(|h| h.set(false));
+                    publish_event(FlightEvent::Landed, state.time_stamp);
+                }
+                save_resume_record();
+            }
+
+            if let Some(channel) = update.fire {
+                info!("dual deploy: firing {} at {} m", channel.name(), state.altitude);
+                request_fire(channel).await;
+            }
+        }
+
+        // a held command whose condition is met goes through the command channel like a received one, it stays held
+        // while the channel is full
+        let time_stamp = Instant::now().as_micros() as u32;
+        let altitude = VERTICAL_STATE_WATCH.try_get().map(|v| v.altitude);
+        if let Some(pending) = PENDING.lock(|p| p.borrow_mut().due(LAUNCH_TIME.lock(|l| l.get()), altitude, time_stamp))
+            && COMMAND_CHANNEL.try_send((pending.command, Origin::Onboard)).is_ok()
+        {
+            PENDING.lock(|p| p.borrow_mut().cancel(pending.id));
+            info!("held command {} due at {}", pending.id, pending.condition);
+        }
+
+        // one mission sequence rule per loop, a channel action goes through the command channel too and is tried
+        // again while the channel is full
+        rules.load(sequence::rules(&CONFIG.lock(|c| c.get())));
+        let input = SequenceInput {
+            state: control.state(),
+            altitude,
+            floating: DUTY_CYCLE.lock(|d| d.borrow().floating()),
+            launch: LAUNCH_TIME.lock(|l| l.get()),
+            time_stamp,
+        };
+        if let Some((index, action)) = rules.due(&input) {
+            let done = match action {
+                Action::Rail { rail, on } => {
+                    RAILS_DISABLED.lock(|d| d.set({
+                        let mut disabled = d.get();
+                        disabled.set(rail, !on);
+                        disabled
+                    }));
+                    true
+                }
+                Action::Command(command) => COMMAND_CHANNEL.try_send((command, Origin::Onboard)).is_ok(),
+            };
+            if done {
+                rules.done(index);
+                info!("mission rule {} fired: {}", index + 1, action);
+                publish_event(FlightEvent::RuleFired(index as u8), time_stamp);
+            }
+        }
+
+        while let Ok((command, origin)) = COMMAND_CHANNEL.try_receive() {
+            info!("received command: {} from {}", command, origin);
+            // the ground is talking to the payload, a duty cycled float stays up for another window, held commands and
+            // mission rules firing on their own don't keep it up
+            if origin != Origin::Onboard {
+                DUTY_CYCLE.lock(|d| d.borrow_mut().stay_awake(Instant::now().as_micros() as u32));
+            }
+            // every command goes in the log with whether it was carried out, a refused one is as telling as the rest
+            let accepted = match command {
+                Command::CalibrateMag => {
+                    // calibration needs the payload in hand, never start it in flight
+                    if control.state() == FlightState::Pad {
+                        MAG_CALIBRATION_SIGNAL.signal(());
+                        true
+                    } else {
+                        warn!("mag calibration rejected, not on pad");
+                        false
+                    }
+                }
+                Command::CalibrateAccel => {
+                    if control.state() == FlightState::Pad {
+                        ACCEL_CALIBRATION_SIGNAL.signal(());
+                        true
+                    } else {
+                        warn!("accel calibration rejected, not on pad");
+                        false
+                    }
+                }
+                Command::Arm => {
+                    // the reference has to be the launch site, rearming on the pad recaptures it
+                    if control.state() == FlightState::Pad {
+                        ARM_SIGNAL.signal(());
+                        true
+                    } else {
+                        warn!("arm rejected, not on pad");
+                        false
+                    }
+                }
+                Command::Disarm => {
+                    if control.state() == FlightState::Pad {
+                        SESSION.lock(|s| s.set(None));
+                        save_resume_record();
+                        info!("disarmed, altitude reported above sea level");
+                        publish_event(FlightEvent::Disarmed, Instant::now().as_micros() as u32);
+                        true
+                    } else {
+                        warn!("disarm rejected, not on pad");
+                        false
+                    }
+                }
+                Command::ArmChannel { channel, override_continuity } => {
+                    match ACTUATION.lock(|a| a.borrow_mut().arm(channel, override_continuity)) {
+                        Ok(()) => {
+                            save_resume_record();
+                            if override_continuity {
+                                warn!("{} armed, continuity check overridden", channel.name());
+                            } else {
+                                info!("{} armed", channel.name());
+                            }
+                            publish_event(FlightEvent::ChannelArmed(channel), Instant::now().as_micros() as u32);
+                            PREFLIGHT_SIGNAL.signal(());
+                            true
+                        }
+                        Err(e) => {
+                            warn!("{} arm rejected: {}", channel.name(), e);
+                            false
+                        }
+                    }
+                }
+                Command::Fire(channel) => request_fire(channel).await,
+                Command::DisarmChannel(channel) => {
+                    ACTUATION.lock(|a| a.borrow_mut().disarm(channel));
+                    save_resume_record();
+                    info!("{} disarmed", channel.name());
+                    publish_event(FlightEvent::ChannelDisarmed(channel), Instant::now().as_micros() as u32);
+                    PREFLIGHT_SIGNAL.signal(());
+                    true
+                }
+                Command::EnterBootloader { tag } => {
+                    if let Err(e) = BOOTLOADER_CHALLENGE.lock(|c| c.borrow_mut().verify(&tag)) {
+                        warn!("bootloader rejected: {}", e);
+                        false
+                    } else if control.state() != FlightState::Pad {
+                        warn!("bootloader rejected, not on pad");
+                        false
+                    } else {
+                        info!("closing log and rebooting into the bootloader");
+                        FINALIZE_LOG_SIGNAL.signal(());
+                        if LOG_FINALIZED_SIGNAL.wait().with_timeout(LOG_FINALIZE_TIMEOUT).await.is_err() {
+                            warn!("log not closed in time, rebooting anyway");
+                        }
+                        reboot_to_bootloader();
+                    }
+                }
+                Command::Update(step) => {
+                    // rewriting flash and rebooting is for the pad only
+                    if control.state() != FlightState::Pad {
+                        warn!("firmware update rejected, not on pad");
+                        false
+                    } else {
+                        UPDATE_CHANNEL.send(step).await
+                    }
+                }
+                Command::Assist(step) => {
+                    // only worth it before the receiver has a fix, and the upload shouldn't compete with flight traffic
+                    if control.state() != FlightState::Pad {
+                        warn!("gnss assistance rejected, not on pad");
+                        false
+                    } else {
+                        ASSIST_CHANNEL.send(step).await
+                    }
+                }
+                // the debug stream is text lines, and mavlink has no frame for a raw record
+                Command::SendHistory(request) => {
+                    if request.stream == LogStream::Debug {
+                        warn!("history rejected, the debug stream has no records");
+                        false
+                    } else if cfg!(feature = "mavlink") {
+                        warn!("history rejected, not sent in mavlink mode");
+                        false
+                    } else {
+                        info!("sending {} records from {} to {} ms, one in every {}", request.stream, request.from, request.to, request.every.max(1));
+                        HISTORY_SIGNAL.signal(request);
+                        true
+                    }
+                }
+                Command::GetConfig(key) => {
+                    let config = staged_config.unwrap_or_else(|| CONFIG.lock(|c| c.get()));
+                    let report = ConfigReport {
+                        key,
+                        value: config.get(key),
+                        pending: staged_config.is_some_and(|staged| staged.get(key) != CONFIG.lock(|c| c.get()).get(key)),
+                        time_stamp: Instant::now().as_micros() as u32,
+                    };
+                    info!("config {}: {}, pending: {}", key, report.value, report.pending);
+                    // each console waits on its own reply, the uplink's goes down with the telemetry
+                    match origin {
+                        Origin::UsbConsole => USB_CONFIG_REPORT_SIGNAL.signal(report),
+                        Origin::UartConsole => UART_CONFIG_REPORT_SIGNAL.signal(report),
+                        Origin::Uplink => {
+                            TELEMETRY_CHANNEL.send(Telemetry::Config(report)).await;
+                        }
+                        Origin::Onboard => {}
+                    }
+                    true
+                }
+                Command::SetConfig { key, value } => {
+                    let config = staged_config.get_or_insert_with(|| CONFIG.lock(|c| c.get()));
+                    match config.set(key, value) {
+                        Ok(old) => {
+                            let change = ConfigChange {
+                                key,
+                                old,
+                                new: value,
+                                time_stamp: Instant::now().as_micros() as u32,
+                            };
+                            // every change goes in the log, one the audit queue dropped is undone rather than flown unrecorded
+                            if CONFIG_AUDIT_CHANNEL.send(change).await {
+                                info!("config {} staged: {} -> {}", key, old, value);
+                                true
+                            } else {
+                                config.set(key, old).ok();
+                                warn!("config {} not staged, audit record dropped", key);
+                                false
+                            }
+                        }
+                        Err(e) => {
+                            warn!("config {} rejected: {}, value: {}", key, e, value);
+                            false
+                        }
+                    }
+                }
+                Command::CommitConfig => match staged_config.take() {
+                    // gains are read when the filters are built, so those changes apply from the next boot
+                    Some(config) => {
+                        CONFIG.lock(|c| c.set(config));
+                        CONFIG_SAVE_SIGNAL.signal(config);
+                        info!("config committed");
+
+                        publish_event(FlightEvent::ConfigCommitted, Instant::now().as_micros() as u32);
+                        true
+                    }
+                    None => {
+                        info!("config commit with no staged changes");
+                        false
+                    }
+                },
+                Command::RevertConfig => {
+                    let staged = staged_config.take().is_some();
+                    if staged {
+                        info!("staged config changes discarded");
+                    }
+                    staged
+                }
+                Command::SetTempPoly { target, coefficients } => {
+                    let mut calibration = CALIBRATION.lock(|c| c.get());
+                    let poly = match target {
+                        TempPolyTarget::Baro => Some(&mut calibration.baro_temp_poly),
+                        TempPolyTarget::Gyro(axis) => calibration.gyro_temp_poly.get_mut(axis as usize),
+                        TempPolyTarget::Accel(axis) => calibration.accel_temp_poly.get_mut(axis as usize),
+                    };
+
+                    match poly {
+                        Some(poly) => {
+                            *poly = coefficients;
+                            set_calibration(calibration, CalibrationChange::TempPoly(target)).await;
+                            info!("temperature compensation updated for {}", target);
+                            true
+                        }
+                        None => {
+                            warn!("temperature compensation rejected, invalid axis for {}", target);
+                            false
+                        }
+                    }
+                }
+            };
+            log_command(command, accepted).await;
+        }
+
+        if PREFLIGHT_SIGNAL.try_take().is_some() && control.state() == FlightState::Pad {
+            preflight_check();
+        }
+
+        loop_end(timing);
+        ticker.next().await;
+    }
+
+}
+
+// reset and have the next boot jump into the system usb dfu bootloader
+fn reboot_to_bootloader() -> ! {
+    let request = (&raw mut BOOTLOADER_REQUEST).cast::<u32>();
+    unsafe { core::ptr::write_volatile(request, BOOTLOADER_MAGIC) };
+    cortex_m::peripheral::SCB::sys_reset()
+}
+
+// save the flight state, launch site reference, armed and fired channels, and logging rate to backup sram and the rtc backup
+// registers for a warm restart
+fn save_resume_record() {
+    let resume = ResumeState {
+        state: FLIGHT_STATE.lock(|s| s.get()),
+        ground: SESSION.lock(|s| s.get()).map(|session| (session.ground_pressure, session.ground_altitude)),
+        high_rate_logging: HIGH_RATE_LOGGING.lock(|h| h.get()),
+        armed: ACTUATION.lock(|a| a.borrow().armed()),
+        fired: ACTUATION.lock(|a| a.borrow().fired()),
+        launch_utc: LAUNCH_UTC.lock(|l| l.get()),
+    };
+    unsafe { core::ptr::write_volatile(RESUME_RECORD, ResumeRecord::new(resume)) };
+    RTC.lock(|r| {
+        if let Some(rtc) = r.borrow().as_ref() {
+            for (index, word) in resume.to_registers().into_iter().enumerate() {
+                rtc.write_backup_register(RESUME_FIRST_REGISTER + index, word);
+            }
+        }
+    });
+}
+
+// the resume record's copy in the rtc backup registers, None if it isn't there or is torn
+fn read_resume_registers() -> Option<ResumeState> {
+    RTC.lock(|r| {
+        let rtc = r.borrow();
+        let rtc = rtc.as_ref()?;
+        let mut registers = [0; RESUME_REGISTERS];
+        for (index, word) in registers.iter_mut().enumerate() {
+            *word = rtc.read_backup_register(RESUME_FIRST_REGISTER + index)?;
         }
+        ResumeState::from_registers(&registers)
+    })
+}
+
+// true while waiting on the pad, sensors and logging run at the reduced pad rates and the core at half speed
+// the executor sleeps the cpu (wfe) whenever no task is ready, so fewer wakeups and cheaper ones between them
+fn pad_idle() -> bool {
+    FLIGHT_STATE.lock(|s| s.get()) == FlightState::Pad
+}
+
+// move a sampling loop to a new period, the ticker restarts from now so the first interval at the new rate is whole
+// true if the period changed
+fn set_period(ticker: &mut Ticker, period: &mut Duration, new_period: Duration) -> bool {
+    if new_period == *period {
+        return false;
+    }
+    *period = new_period;
+    *ticker = Ticker::every(new_period);
+    true
+}
+
+// true while sampling and logging at the maximum rate, after free fall unless shed to save the battery
+fn high_rate_logging() -> bool {
+    HIGH_RATE_LOGGING.lock(|h| h.get()) && load_enabled(Load::HighRateLogging)
+}
+
+// hand a channel to the actuation task to fire, only if it is armed
+async fn request_fire(channel: Channel) -> bool {
+    // a cold pack can't source the firing current without sagging the flight computer into a brownout
+    if BATTERY_COLD.lock(|c| c.get()) {
+        warn!("{} fire rejected, battery too cold", channel.name());
+        false
+    } else if ACTUATION.lock(|a| a.borrow().armed().contains(channel)) {
+        FIRE_CHANNEL.send(channel).await
+    } else {
+        warn!("{} fire rejected, not armed", channel.name());
+        false
+    }
+}
+
+// put a command the control task took in the log, with whether it was carried out
+async fn log_command(command: Command, accepted: bool) {
+    COMMAND_AUDIT_CHANNEL.send((command.code(), accepted, Instant::now().as_micros() as u32)).await;
+}
 
-        Timer::after(Duration::from_millis(100)).await;
+// make new calibration values the active ones, persist them, and mark what changed in the log
+async fn set_calibration(calibration: Calibration, change: CalibrationChange) {
+    CALIBRATION.lock(|c| c.set(calibration));
+    CALIBRATION_SAVE_SIGNAL.signal(calibration);
+    CALIBRATION_CHANGE_CHANNEL.send((change, Instant::now().as_micros() as u32)).await;
+}
+
+// checks that the payload is ready to fly and logs the results, returns true if every check passed
+fn preflight_check() -> bool {
+    let calibration = CALIBRATION.lock(|c| c.get());
+    let bias = calibration.gyro_bias;
+    let bias_magnitude = sqrtf(bias.iter().map(|b| b * b).sum());
+    let gyro_ok = bias_magnitude < CONFIG.lock(|c| c.get()).gyro_bias_limit;
+
+    info!("preflight: gyro bias: ({}, {}, {}) rad/s, {}", bias[0], bias[1], bias[2], if gyro_ok { "ok" } else { "FAIL" });
+
+    // an unarmed channel without continuity is only reported, it may have nothing fitted on purpose
+    let actuation = ACTUATION.lock(|a| *a.borrow());
+    for channel in Channel::ALL {
+        let continuity = actuation.continuity().contains(channel);
+        let state = if actuation.overridden().contains(channel) {
+            "armed, override"
+        } else if actuation.armed().contains(channel) {
+            "armed"
+        } else {
+            "safe"
+        };
+        info!("preflight: {} continuity: {}, {}", channel.name(), continuity, state);
+    }
+    let continuity_ok = actuation.lost().is_empty();
+    if !continuity_ok {
+        warn!("preflight: armed channels without continuity: {}", actuation.lost());
     }
 
+    // dual deploy needs both channels armed and the launch site reference for the main deploy height
+    let config = CONFIG.lock(|c| c.get());
+    let deploy_ok = match DeployMode::from_u32(config.deploy_mode) {
+        DeployMode::Balloon => true,
+        DeployMode::DualDeploy => {
+            let armed = actuation.armed().contains(APOGEE_CHANNEL) && actuation.armed().contains(MAIN_CHANNEL);
+            let referenced = SESSION.lock(|s| s.get()).is_some();
+            info!("preflight: dual deploy, main at {} m, channels armed: {}, launch site reference: {}, {}",
+                config.main_deploy_altitude, armed, referenced, if armed && referenced { "ok" } else { "FAIL" });
+            armed && referenced
+        }
+    };
+
+    let passed = gyro_ok && continuity_ok && deploy_ok;
+    if passed {
+        info!("preflight passed");
+    } else {
+        warn!("preflight failed");
+    }
+    passed
 }
 
 // barometer data acquisition, timestamping, and altitude filtering task
 // reads sensor data, filters altitude to ensure proper launch procedure followed in control task
-// sends filtered data to control task at low rate (1Hz or so)
+// sends filtered altitude and vertical speed to control task at low rate (1Hz or so)
 // sends data to logging task at higher rate (10-20Hz)
+// reads both barometers of the redundant pair, everything downstream sees only the one the cross check picked
 #[task]
-async fn baro_task() {
+async fn baro_task(mut barometers: [bsp::Barometer; 2]) {
     info!("Starting barometer task");
 
-    // altitude filter buffer
-    // we start at 0m altitude so we don't need to fill the buffer with initial values
-    let mut alt_buffer: [f32; 10] = [0.0; 10];
+    // each unit validated on its own, the pair cross check, outlier rejection and the altitude filter
+    let config = CONFIG.lock(|c| c.get());
+    let mut pipeline = BaroPipeline::new(altitude_estimator(config), config.baro_outlier_k, config.baro_outlier_min_deviation);
+    let mut bus_clears = 0;
+    let mut sleeps = 0;
+
+    // ground pressure being averaged after an arm command
+    let mut ground_capture: Option<MovingAverage<GROUND_PRESSURE_SAMPLES>> = None;
+
+    let watchdog = watchdog_register("baro", SENSOR_CHECK_IN_DEADLINE);
+
+    // fixed rate so the samples are evenly spaced for the altitude filter whatever the loop takes
+    let mut period = baro_period();
+    let mut ticker = Ticker::every(period);
+    // the period is longer on the pad, the timing follows it
+    let timing = loop_register("baro", Some(period));
 
     loop {
-        // fake data
+        watchdog_check_in(watchdog);
+        loop_start(timing);
+
+        // the barometers are on the sensor bus, whatever garbage cleared it may have reached them
+        if sensor_bus_cleared(&mut bus_clears) {
+            barometers.iter_mut().for_each(|barometer| barometer.reinit());
+        }
+
+        // after a sleep the pressure has moved on over what looks like one sample period
+        if SLEEPS.lock(|s| s.get()) != sleeps {
+            sleeps = SLEEPS.lock(|s| s.get());
+            let config = CONFIG.lock(|c| c.get());
+            pipeline.restart(altitude_estimator(config), config.baro_outlier_k, config.baro_outlier_min_deviation);
+        }
+
         let time_stamp = Instant::now().as_micros() as u32;
-        let data = BaroData {
-            pressure: 1013.25,
-            temperature: 25.0,
-            time_stamp: time_stamp,
+        // remove the temperature dependent offset, cheap sensors drift badly at float temperatures
+        let calibration = CALIBRATION.lock(|c| c.get());
+        let reads = pipeline.read(&mut barometers, time_stamp, |pressure, temperature| calibration.compensate_baro(pressure, temperature)).await;
+        for (read, unit) in reads.iter().zip(Unit::ALL) {
+            match read {
+                BaroRead::Rejected(data) => warn!("{} baro sample rejected: {}, p: {} hPa, t: {}", unit, data.flags, data.pressure, data.temperature),
+                BaroRead::Failed(e) => warn!("{} baro read failed: {}", unit, e),
+                BaroRead::Good(_) | BaroRead::Missing => {}
+            }
+        }
+
+        // a recent good gps altitude as a pressure is the third opinion when the two disagree, a degraded fix's altitude
+        // can be held or too loose to settle a few hPa
+        let reference = LATEST_GPS
+            .lock(|g| g.get())
+            .filter(|gps| gps.flags.valid() && !gps.flags.contains(SampleFlags::DEGRADED)
+                && time_stamp.wrapping_sub(gps.time_stamp) < BARO_REFERENCE_AGE)
+            .map(|gps| BaroData { pressure: atmosphere::altitude_to_pressure(gps.altitude), temperature: f32::NAN,
+                flags: SampleFlags::ESTIMATED, time_stamp });
+
+        let previous = pipeline.pair().selected();
+        let selected = pipeline.select(reads.each_ref().map(|read| read.sample()), reference);
+        let pair = pipeline.pair();
+        report_fault(Fault::BaroDivergence, pair.degraded());
+        if pair.selected() != previous {
+            warn!("baro switched to the {} unit, primary healthy: {}, secondary healthy: {}, diverged: {}", pair.selected(),
+                pair.healthy(Unit::Primary), pair.healthy(Unit::Secondary), pair.diverged());
+            publish_event(FlightEvent::BaroSwitched(pair.selected()), time_stamp);
+        }
+
+        let Some(data) = selected else {
+            loop_end(timing);
+            ticker.next().await;
+            continue;
         };
 
-        // try sending data, if channel is full, flush it and send again
-        match BARO_DATA_CHANNEL.try_send(data) {
-            Ok(_) => {
-                info!("sent baro data: p: {}, t: {}, ts: {}", data.pressure, data.temperature, data.time_stamp);
-            }
-            Err(_) => {
-                warn!("baro data channel full, flushing data");
-                BARO_DATA_CHANNEL.clear();
+        LATEST_BARO.lock(|b| b.set(Some(data)));
+        // if the channel is full the oldest sample makes room
+        if BARO_DATA_CHANNEL.send(data).await {
+            info!("sent baro data: p: {}, t: {}, ts: {}", data.pressure, data.temperature, data.time_stamp);
+        }
 
-                // if queue is empty wait until we can send until timeout
-                BARO_DATA_CHANNEL.send(data).with_timeout(Duration::from_millis(200)).await.ok(); 
-            }
+        // raw pressure is logged above, only the altitude path sees the outlier filtered value
+        let pressure = pipeline.reject_outlier(data.pressure);
+        if pressure != data.pressure {
+            warn!("rejected baro outlier: {} hPa, using {} hPa", data.pressure, pressure);
+        }
+
+        if ARM_SIGNAL.try_take().is_some() {
+            info!("arming, capturing ground pressure");
+            ground_capture = Some(MovingAverage::new());
         }
 
-        alt_buffer.rotate_right(1);
-        alt_buffer[0] = 44330.0 * (1.0 - powf(data.pressure / 1013.25, 1.0 / 5.255));
+        // average the ground pressure over a few seconds, then record it as the launch site reference
+        if let Some(average) = ground_capture.as_mut() {
+            average.update(pressure);
 
-        // filter altitide
-        // ex: rolling average
-        let alt_sum: f32 = alt_buffer.iter().sum();
-        let alt_avg: f32 = alt_sum / alt_buffer.len() as f32;
+            if average.is_full() {
+                let ground_pressure = average.value();
+                let session = SessionHeader {
+                    ground_pressure,
+                    ground_altitude: atmosphere::pressure_to_altitude(ground_pressure),
+                    boot: BOOT_INFO.lock(|b| b.get()),
+                    time_stamp: data.time_stamp,
+                };
+                info!("armed, ground pressure: {} hPa, ground altitude: {} m", session.ground_pressure, session.ground_altitude);
+                SESSION.lock(|s| s.set(Some(session)));
+                save_resume_record();
+                publish_event(FlightEvent::Armed, data.time_stamp);
+                PREFLIGHT_SIGNAL.signal(());
 
-        // try sending filtered altitude, if channel is full, flush it and send again
-        match BARO_ALT_CHANNEL.try_send(alt_avg) {
-            Ok(_) => {
-                info!("sent filtered altitude: {}", alt_avg);
+                SESSION_CHANNEL.send(session).await;
+                TELEMETRY_CHANNEL.send(Telemetry::Session(session)).await;
+                ground_capture = None;
             }
-            Err(_) => {
-                warn!("baro alt channel full, flushing data");
-                BARO_ALT_CHANNEL.clear();
-                BARO_ALT_CHANNEL.send(alt_avg).with_timeout(Duration::from_millis(200)).await.ok();
-            }
-        };
+        }
+
+        // filter altitude, vertical speed comes out of the filter rather than differentiating noisy altitude
+        let state = pipeline.altitude(pressure, data.temperature, data.time_stamp);
+
+        stream_seen(Stream::Baro, state.time_stamp);
+        VERTICAL_STATE_WATCH.sender().send(state);
+        info!("sent vertical state: alt: {}, vs: {}", state.altitude, state.vertical_speed);
+
+        loop_end(timing);
 
-        // no need for perfectly timed data, simple delay is fine
-        Timer::after(Duration::from_millis(500)).await;
+        if set_period(&mut ticker, &mut period, baro_period()) {
+            loop_set_period(timing, Some(period));
+        }
+        ticker.next().await;
     }
 }
 
+fn baro_period() -> Duration {
+    if pad_idle() { BARO_PAD_PERIOD } else { BARO_PERIOD }
+}
+
 // imu data acquisition and timestamping. Most likely no filtering is needed
 // sends data to GNC can bus task at high rate (50-100Hz, or whatever GNC needs)
 // sends data to logging task at higher rate (10-20Hz)
+// reads both imus of the redundant pair on the primary's data ready edge, everything downstream sees only the one the
+// cross check picked, the calibration is shared so a switch can show up as a small step
 #[task]
-async fn imu_task() {
-    info!("Starting barometer task");
+async fn imu_task(mut imus: [bsp::Imu; 2]) {
+    info!("Starting imu task");
+
+    let mut imu_pair = PairMonitor::new(IMU_PAIR_LIMITS);
+    // each unit is validated on its own, a rejected sample counts against the unit like a failed read
+    let mut validators = Unit::ALL.map(|_| Validator::new([IMU_ACCEL_LIMITS, IMU_ACCEL_LIMITS, IMU_ACCEL_LIMITS,
+        IMU_GYRO_LIMITS, IMU_GYRO_LIMITS, IMU_GYRO_LIMITS], IMU_FROZEN_SAMPLES));
+    let mut bus_clears = 0;
+
+    let mut bias_estimator = GyroBiasEstimator::<GYRO_BIAS_SAMPLES>::new();
+
+    // active mag calibration run and when it started
+    let mut mag_calibration: Option<(MagCalibrator, Instant)> = None;
+
+    // active accel calibration run and when it started
+    let mut accel_calibration: Option<(AccelCalibrator, Instant)> = None;
+
+    let mut free_fall = FreeFallDetector::new();
+
+    // vibration bursts still to take after the last event, and when the next one is due
+    let mut events = EVENT_BUS.subscriber().unwrap();
+    let mut bursts_due = 0;
+    let mut next_burst = Instant::now();
+
+    let watchdog = watchdog_register("imu", SENSOR_CHECK_IN_DEADLINE);
+
+    // the imu samples at its own output rate and each sample is read on its data ready edge, stamped with
+    // the time of the edge so the time stamp is the measurement instant rather than when this task woke up
+    // without data ready edges (no imu, a mock) the loop polls at the same rate on a fixed-rate ticker
+    let mut period = imu_period(false);
+    let mut ticker = Ticker::every(period);
+    // the period changes with the logging rate, the timing follows it
+    let timing = loop_register("imu", Some(period));
+    let mut polling = false;
+    // the imu's slowest output rate can be faster than the period, then only every decimation-th edge is sampled
+    let (mut output_period, mut decimation) = set_imu_rates(&mut imus, period).await;
+    let mut edges = 0;
 
     loop {
-        // fake data
-        let time_stamp = Instant::now().as_micros() as u32;
-        let data = ImuData {
-            acceleration: [0.0, 0.0, 9.81],
-            gyro: [0.0, 0.0, 0.0],
-            mag: [0.0, 0.0, 0.0],
-            time_stamp: time_stamp,
+        let time_stamp = if polling {
+            ticker.next().await;
+            match IMU_DATA_READY_SIGNAL.try_take() {
+                Some(time_stamp) => {
+                    info!("imu data ready is back, sampling on it");
+                    polling = false;
+                    time_stamp
+                }
+                None => Instant::now().as_micros() as u32,
+            }
+        } else {
+            match IMU_DATA_READY_SIGNAL.wait().with_timeout(output_period + IMU_DATA_READY_MARGIN).await {
+                Ok(time_stamp) => {
+                    edges += 1;
+                    if edges < decimation {
+                        continue;
+                    }
+                    edges = 0;
+                    time_stamp
+                }
+                Err(_) => {
+                    warn!("no imu data ready, polling");
+                    polling = true;
+                    ticker.reset();
+                    Instant::now().as_micros() as u32
+                }
+            }
         };
 
-        // try sending data, if channel is full, flush it and send again
-        match IMU_DATA_CHANNEL.try_send(data) {
-            Ok(_) => {
-                info!("sent imu data: a: ({}, {}, {}), g: ({}, {}, {}), m: ({}, {}, {}), ts: {}", 
-                    data.acceleration[0], data.acceleration[1], data.acceleration[2],
-                    data.gyro[0], data.gyro[1], data.gyro[2],
-                    data.mag[0], data.mag[1], data.mag[2],
-                    data.time_stamp);
-            }
-            Err(_) => {
-                warn!("imu data channel full, flushing data");
-                IMU_DATA_CHANNEL.clear();
+        watchdog_check_in(watchdog);
+        loop_start(timing);
+
+        // the magnetometer is on the sensor bus
+        if sensor_bus_cleared(&mut bus_clears) {
+            imus.iter_mut().for_each(|imu| imu.reinit());
+        }
 
-                // if queue is empty wait until we can send until timeout
-                IMU_DATA_CHANNEL.send(data).with_timeout(Duration::from_millis(50)).await.ok(); 
+        let mut samples = [None; 2];
+        for (((sample, imu), validator), unit) in samples.iter_mut().zip(imus.iter_mut()).zip(validators.iter_mut()).zip(Unit::ALL) {
+            match imu.read(time_stamp).await {
+                Ok(mut data) => {
+                    // temperature compensation applies to the raw sensor output, before any calibration is estimated or applied
+                    CALIBRATION.lock(|c| c.get()).compensate_imu(&mut data);
+                    let [ax, ay, az] = data.acceleration;
+                    let [gx, gy, gz] = data.gyro;
+                    data.flags = validator.check([ax, ay, az, gx, gy, gz], data.time_stamp);
+                    if data.flags.valid() {
+                        *sample = Some(data);
+                    } else {
+                        warn!("{} imu sample rejected: {}", unit, data.flags);
+                    }
+                }
+                // the imu may have been reset, set it up again, only until it counts as failed so a dead part
+                // doesn't cost a setup every sample
+                Err(e) => {
+                    warn!("{} imu read failed: {}", unit, e);
+                    if imu_pair.healthy(unit) {
+                        let rate = set_imu_rate(imu, period).await;
+                        if unit == Unit::Primary {
+                            (output_period, decimation) = rate;
+                        }
+                    }
+                }
             }
-        };
+        }
 
-        // no need for perfectly timed data, simple delay is fine
-        Timer::after(Duration::from_millis(500)).await;
-    }
-}
+        let previous = imu_pair.selected();
+        let selected = imu_pair.update(samples, None, imu_difference);
+        report_fault(Fault::ImuDivergence, imu_pair.degraded());
+        if imu_pair.selected() != previous {
+            warn!("imu switched to the {} unit, primary healthy: {}, secondary healthy: {}, diverged: {}", imu_pair.selected(),
+                imu_pair.healthy(Unit::Primary), imu_pair.healthy(Unit::Secondary), imu_pair.diverged());
+            publish_event(FlightEvent::ImuSwitched(imu_pair.selected()), time_stamp);
+        }
 
-// receives sensor data, adds to byte buffer. Once buffer reaches 256 bytes writes data to sd card
-#[task]
-async fn log_task() {
-    info!("Entered logging task");
+        let Some(mut data) = selected else {
+            loop_end(timing);
+            continue;
+        };
+        if imu_pair.degraded() {
+            data.flags.insert(SampleFlags::DEGRADED);
+        }
 
-    let mut buf_index: u16 = 0;
+        // estimate gyro bias from raw samples while sitting on the pad, not from a degraded pair as the part the
+        // samples come from may be the one drifting
+        if FLIGHT_STATE.lock(|s| s.get()) == FlightState::Pad && !data.flags.contains(SampleFlags::DEGRADED) {
+            if let Some(bias) = bias_estimator.update(data.acceleration, data.gyro) {
+                let mut calibration = CALIBRATION.lock(|c| c.get());
+                let change = sqrtf(bias.iter().zip(calibration.gyro_bias).map(|(a, b)| (a - b) * (a - b)).sum());
+                info!("gyro bias estimate: ({}, {}, {}) rad/s", bias[0], bias[1], bias[2]);
 
-    loop {
-        // check for baro data
-        while let Ok(data) = BARO_DATA_CHANNEL.try_receive() {
-            info!("received baro data: p: {}, t: {}, ts: {}", data.pressure, data.temperature, data.time_stamp);
+                // only rewrite flash when the new bias differs enough from the stored one
+                if change > CONFIG.lock(|c| c.get()).gyro_bias_save_threshold {
+                    calibration.gyro_bias = bias;
+                    set_calibration(calibration, CalibrationChange::GyroBias).await;
+                    PREFLIGHT_SIGNAL.signal(());
+                }
+            }
+        } else {
+            bias_estimator.reset();
+        }
 
-            // add to byte buffer
-            buf_index += 12;
+        if MAG_CALIBRATION_SIGNAL.try_take().is_some() {
+            info!("mag calibration started, rotate the payload through all orientations");
+            mag_calibration = Some((MagCalibrator::new(), Instant::now()));
         }
-        
-        while let Ok(data) = IMU_DATA_CHANNEL.try_receive() {
-            info!("received imu data: a: ({}, {}, {}), g: ({}, {}, {}), m: ({}, {}, {}), ts: {}", 
-                data.acceleration[0], data.acceleration[1], data.acceleration[2],
-                data.gyro[0], data.gyro[1], data.gyro[2],
-                data.mag[0], data.mag[1], data.mag[2],
-                data.time_stamp);
 
-                // add to byte buffer
-                buf_index += 40;
+        // collect raw mag samples until the run is over, then fit and store the correction
+        if let Some((calibrator, started)) = mag_calibration.as_mut() {
+            calibrator.add(data.mag);
+
+            if started.elapsed() >= MAG_CALIBRATION_DURATION {
+                match calibrator.fit() {
+                    Some((offset, matrix)) => {
+                        info!("mag calibration done with {} samples, offset: ({}, {}, {})",
+                            calibrator.count(), offset[0], offset[1], offset[2]);
+                        let mut calibration = CALIBRATION.lock(|c| c.get());
+                        calibration.mag_offset = offset;
+                        calibration.mag_matrix = matrix;
+                        set_calibration(calibration, CalibrationChange::Mag).await;
+                    }
+                    None => warn!("mag calibration failed with {} samples, payload not rotated enough", calibrator.count()),
+                }
+                mag_calibration = None;
+            }
         }
 
-        // if byte buffer has 256 bytes, send to sd card
-        if buf_index >= 256 {
-            info!("buffer full, writing to sd card");
-            buf_index -= 256;
+        if ACCEL_CALIBRATION_SIGNAL.try_take().is_some() {
+            info!("accel calibration started, set the payload still with {} facing up", ACCEL_ORIENTATIONS[0]);
+            accel_calibration = Some((AccelCalibrator::new(), Instant::now()));
         }
-    
-        // wait state to let other tasks run
-        Timer::after(Duration::from_millis(50)).await;
 
-    }
-}
\ No newline at end of file
+        // capture each orientation as the operator sets the payload down, prompting for the next one
+        if let Some((calibrator, started)) = accel_calibration.as_mut() {
+            if let Some(index) = calibrator.add(data.acceleration, data.gyro) {
+                info!("captured {}", ACCEL_ORIENTATIONS[index]);
+                if let Some(next) = calibrator.next_orientation() {
+                    info!("set the payload still with {} facing up", ACCEL_ORIENTATIONS[next]);
+                }
+            }
+
+            if calibrator.is_complete() {
+                match calibrator.fit() {
+                    Some((offset, scale)) => {
+                        info!("accel calibration done, offset: ({}, {}, {}), scale: ({}, {}, {})",
+                            offset[0], offset[1], offset[2], scale[0], scale[1], scale[2]);
+                        let mut calibration = CALIBRATION.lock(|c| c.get());
+                        calibration.accel_offset = offset;
+                        calibration.accel_scale = scale;
+                        set_calibration(calibration, CalibrationChange::Accel).await;
+                    }
+                    None => warn!("accel calibration failed, readings out of range"),
+                }
+                accel_calibration = None;
+            } else if started.elapsed() >= ACCEL_CALIBRATION_TIMEOUT {
+                warn!("accel calibration timed out");
+                accel_calibration = None;
+            }
+        }
+
+        // remove sensor errors before anything downstream sees the data
+        let calibration = CALIBRATION.lock(|c| c.get());
+        data.acceleration = calibration.correct_accel(data.acceleration);
+        for (g, b) in data.gyro.iter_mut().zip(calibration.gyro_bias) {
+            *g -= b;
+        }
+        data.mag = calibration.correct_mag(data.mag);
+
+        // free fall is the best burst indicator, capture everything from here on and mark the log
+        if free_fall.update(data.acceleration, data.time_stamp) {
+            warn!("free fall detected, logging at maximum rate");
+            HIGH_RATE_LOGGING.lock(|h| h.set(true));
+            save_resume_record();
+
+            publish_event(FlightEvent::FreeFall, data.time_stamp);
+        }
+
+        stream_seen(Stream::Imu, data.time_stamp);
+
+        // never blocks the imu, a subscriber that falls behind loses its oldest samples and is told how many
+        IMU_DATA_PUBSUB.immediate_publisher().publish_immediate(data);
+        info!("sent imu data: a: ({}, {}, {}), g: ({}, {}, {}), m: ({}, {}, {}), ts: {}",
+            data.acceleration[0], data.acceleration[1], data.acceleration[2],
+            data.gyro[0], data.gyro[1], data.gyro[2],
+            data.mag[0], data.mag[1], data.mag[2],
+            data.time_stamp);
+
+        loop_end(timing);
+
+        // the structure's response to launch, burst, and landing, a few bursts a couple of seconds apart
+        while let Some(result) = events.try_next_message() {
+            if let WaitResult::Message(data) = result
+                && matches!(data.event, FlightEvent::Launch | FlightEvent::Burst | FlightEvent::FreeFall | FlightEvent::Landed)
+            {
+                bursts_due = CONFIG.lock(|c| c.get()).vibration_bursts;
+                next_burst = Instant::now();
+            }
+        }
+        if bursts_due > 0 && Instant::now() >= next_burst {
+            bursts_due -= 1;
+            next_burst = Instant::now() + VIBRATION_BURST_SPACING;
+            vibration_burst(&mut imus[imu_pair.selected().id() as usize]).await;
+            // the edges during the burst came at the burst rate, wait for one at the output rate
+            IMU_DATA_READY_SIGNAL.reset();
+        }
+
+        let calibrating = mag_calibration.is_some() || accel_calibration.is_some();
+        let next_period = imu_period(calibrating);
+        if next_period != period {
+            (output_period, decimation) = set_imu_rates(&mut imus, next_period).await;
+        }
+        if set_period(&mut ticker, &mut period, next_period) {
+            loop_set_period(timing, Some(period));
+        }
+    }
+}
+
+// take a vibration burst and send its raw samples and summary to the log, imus that can't are skipped quietly
+// the samples are raw, calibration offsets drop out with the mean and the bands don't need the scale
+async fn vibration_burst(imu: &mut bsp::Imu) {
+    let mut samples = [[0.0; 3]; BURST_SAMPLES];
+    let time_stamp = Instant::now().as_micros() as u32;
+    let sample_period = match imu.burst(&mut samples).await {
+        Ok(0) => return,
+        Ok(period) => period,
+        Err(e) => {
+            warn!("vibration burst failed: {}", e);
+            return;
+        }
+    };
+
+    let summary = VibrationSummary::new(&samples, sample_period, time_stamp);
+    info!("vibration burst: rms: ({}, {}, {}), peak: ({}, {}, {}), period: {} us, ts: {}",
+        summary.rms[0], summary.rms[1], summary.rms[2],
+        summary.peak[0], summary.peak[1], summary.peak[2],
+        sample_period, time_stamp);
+
+    for (i, chunk) in samples.chunks_exact(CHUNK_SAMPLES).enumerate() {
+        let mut counts = [[0; 3]; CHUNK_SAMPLES];
+        for (count, &sample) in counts.iter_mut().zip(chunk) {
+            *count = vibration::to_counts(sample);
+        }
+        VIBRATION_SAMPLES_CHANNEL.send(SampleChunk { time_stamp, sample_period, first: (i * CHUNK_SAMPLES) as u16, samples: counts }).await;
+    }
+    VIBRATION_CHANNEL.send(summary).await;
+}
+
+// set both imus to the period, the data ready line is the primary's so its output rate is the one sampled at
+async fn set_imu_rates(imus: &mut [bsp::Imu; 2], period: Duration) -> (Duration, u32) {
+    let rate = set_imu_rate(&mut imus[0], period).await;
+    set_imu_rate(&mut imus[1], period).await;
+    rate
+}
+
+// how far apart two imu samples are, in units of the divergence limits, the larger of the accel and gyro differences
+fn imu_difference(a: &ImuData, b: &ImuData) -> f32 {
+    let norm = |v: [f32; 3]| sqrtf(v.iter().map(|x| x * x).sum());
+    let accel = (norm(a.acceleration) - norm(b.acceleration)).abs() / IMU_ACCEL_DIVERGENCE;
+    let gyro = norm([a.gyro[0] - b.gyro[0], a.gyro[1] - b.gyro[1], a.gyro[2] - b.gyro[2]]) / IMU_GYRO_DIVERGENCE;
+    accel.max(gyro)
+}
+
+// set the imu output rate for a sample period, returns the output period it actually runs at and how many
+// data ready edges go by per sample
+async fn set_imu_rate(imu: &mut bsp::Imu, period: Duration) -> (Duration, u32) {
+    let period_us = period.as_micros() as u32;
+    match imu.set_output_rate(period_us).await {
+        Ok(output_us) => (Duration::from_micros(output_us as u64), (period_us / output_us.max(1)).max(1)),
+        Err(e) => {
+            warn!("imu rate setup failed: {}", e);
+            (period, 1)
+        }
+    }
+}
+
+// stamps each imu data ready edge, runs on the high priority executor so the stamp isn't delayed by a busy thread mode task
+#[task]
+async fn imu_data_ready_task(mut data_ready: ExtiInput<'static>) {
+    loop {
+        data_ready.wait_for_rising_edge().await;
+        IMU_DATA_READY_SIGNAL.signal(Instant::now().as_micros() as u32);
+    }
+}
+
+// calibration runs need every sample they can get, even on the pad
+fn imu_period(calibrating: bool) -> Duration {
+    if high_rate_logging() {
+        IMU_HIGH_RATE_PERIOD
+    } else if pad_idle() && !calibrating {
+        IMU_PAD_PERIOD
+    } else {
+        IMU_PERIOD
+    }
+}
+
+// attitude estimation task, runs the selected attitude filter on every imu sample
+// sends attitude to logging task and to gnc can bus task at the imu rate
+#[task]
+async fn attitude_task() {
+    info!("Starting attitude task");
+
+    let mut estimator = attitude_estimator(CONFIG.lock(|c| c.get()));
+    let mut was_converged = false;
+    let watchdog = watchdog_register("attitude", SENSOR_CHECK_IN_DEADLINE);
+    let timing = loop_register("attitude", None);
+    let mut imu_data = IMU_DATA_PUBSUB.subscriber().unwrap();
+
+    loop {
+        // runs at whatever rate the imu produces data
+        let imu = match imu_data.next_message().await {
+            WaitResult::Message(imu) => imu,
+            WaitResult::Lagged(missed) => {
+                warn!("attitude estimator fell behind, missed {} imu samples", missed);
+                count_drops(&IMU_DROPPED, missed);
+                continue;
+            }
+        };
+        watchdog_check_in(watchdog);
+        loop_start(timing);
+
+        let data = estimator.update(&imu);
+
+        // report convergence changes, gnc should not trust attitude until converged
+        if data.converged != was_converged {
+            if data.converged {
+                info!("attitude filter converged, error: {}", estimator.error());
+            } else {
+                warn!("attitude filter lost convergence, error: {}", estimator.error());
+            }
+            was_converged = data.converged;
+        }
+
+        let q = data.quaternion;
+        stream_seen(Stream::Attitude, data.time_stamp);
+
+        if ATTITUDE_DATA_CHANNEL.send(data).await {
+            info!("sent attitude data: q: ({}, {}, {}, {}), ts: {}", q[0], q[1], q[2], q[3], data.time_stamp);
+        }
+
+        ATTITUDE_WATCH.sender().send(data);
+
+        // nav filter needs the raw accel together with the attitude used to rotate it
+        NAV_INERTIAL_CHANNEL.send((imu, data)).await;
+
+        loop_end(timing);
+    }
+}
+
+// gps acquisition task, sends fixes to logging and the nav filter at the receiver rate (1Hz)
+// also relays uploaded assistance data to the receiver so it gets a first fix on the pad within seconds
+#[task]
+async fn gps_task(mut gps: bsp::Gps) {
+    info!("Starting gps task");
+
+    let timing = loop_register("gps", Some(GPS_PERIOD));
+    let mut ticker = Ticker::every(GPS_PERIOD);
+    let mut assist = AssistReceiver::new();
+    let mut gate = FixGate::new();
+
+    loop {
+        // steps queued while waiting on the ticker
+        while let Ok(step) = ASSIST_CHANNEL.try_receive() {
+            assist_step(&mut gps, &mut assist, step).await;
+        }
+
+        let time_stamp = Instant::now().as_micros() as u32;
+        // a receiver without a fix can be waiting a long time for the next one, the upload goes on meanwhile
+        let next = select(gps.read(time_stamp), ASSIST_CHANNEL.receive()).await;
+        // the wait for the receiver isn't part of the loop body, an upload step isn't an iteration at all
+        let mut data = match next {
+            Either::First(result) => {
+                loop_start(timing);
+                match result {
+                    Ok(data) => data,
+                    Err(e) => {
+                        warn!("gps read failed: {}", e);
+                        loop_end(timing);
+                        ticker.next().await;
+                        continue;
+                    }
+                }
+            }
+            Either::Second(step) => {
+                assist_step(&mut gps, &mut assist, step).await;
+                continue;
+            }
+        };
+
+        // every consumer checks the grade, junk fixes are still logged and counted as the stream being alive
+        data.quality = gate.check(&data);
+        data.flags = data.quality.flags();
+        report_fault(Fault::GpsLockout, gate.lockout());
+
+        // the receiver's time is good with any fix, even one whose position isn't
+        if let (FixType::TwoD | FixType::ThreeD, Some(utc)) = (data.fix, data.utc) {
+            discipline_rtc(utc);
+        }
+
+        stream_seen(Stream::Gps, data.time_stamp);
+        LATEST_GPS.lock(|g| g.set(Some(data)));
+
+        if GPS_DATA_CHANNEL.send(data).await {
+            info!("sent gps data: lat: {}, lon: {}, alt: {}, sats: {}, fix: {}, hdop: {}, quality: {}, utc: {}, ts: {}",
+                data.latitude, data.longitude, data.altitude, data.satellites, data.fix, data.hdop, data.quality, data.utc, data.time_stamp);
+        }
+
+        NAV_GPS_CHANNEL.send(data).await;
+
+        WIND_GPS_CHANNEL.send(data).await;
+
+        loop_end(timing);
+        ticker.next().await;
+    }
+}
+
+// run one assistance upload step and ack it with the upload progress
+async fn assist_step(gps: &mut bsp::Gps, assist: &mut AssistReceiver, step: AssistCommand) {
+    let result = match step {
+        AssistCommand::Begin { size } => assist.begin(size).inspect(|_| info!("gnss assistance upload started, {} bytes", size)),
+        AssistCommand::Chunk { offset, len, data, crc } => match data.get(..len as usize) {
+            Some(data) => match assist.accept(offset, data, crc) {
+                Ok(true) => {
+                    for &byte in data {
+                        let Some(message) = assist.push(byte) else {
+                            continue;
+                        };
+                        let sent = gps.assist(message).await;
+                        if let Err(e) = sent {
+                            warn!("gps rejected assistance message: {}", e);
+                        }
+                        assist.count(sent.is_ok());
+                    }
+                    assist.advance(data.len());
+                    Ok(())
+                }
+                Ok(false) => Ok(()),
+                Err(e) => Err(e),
+            },
+            None => Err(AssistError::BadChunk),
+        },
+        AssistCommand::Finish => assist.finish().map(|(messages, rejected)| {
+            info!("gnss assistance upload finished, {} messages sent, {} rejected", messages, rejected);
+        }),
+    };
+    if let Err(e) = result {
+        warn!("gnss assistance step rejected: {}", e);
+    }
+
+    let status = assist.status(result, Instant::now().as_micros() as u32);
+    ASSIST_STATUS_SIGNAL.signal(status);
+    TELEMETRY_CHANNEL.send(Telemetry::Assist(status)).await;
+}
+
+// wind estimation task, averages gps ground velocity per altitude layer during ascent
+// sends each layer to logging as the balloon climbs out of it
+#[task]
+async fn wind_task() {
+    info!("Starting wind task");
+
+    // only ascent gives clean drift, on the pad there is no wind signal and descent is under parachute
+    // a warm restart can come back mid ascent, after that launch and burst say when it starts and ends
+    let mut ascending = FLIGHT_STATE.lock(|s| s.get()) == FlightState::Ascent;
+    let mut events = EVENT_BUS.subscriber().unwrap();
+
+    loop {
+        let gps = match select(WIND_GPS_CHANNEL.receive(), events.next_message_pure()).await {
+            Either::First(gps) => gps,
+            Either::Second(record) => {
+                match record.event {
+                    FlightEvent::Launch => ascending = true,
+                    FlightEvent::Burst | FlightEvent::FreeFall | FlightEvent::Landed => ascending = false,
+                    _ => {}
+                }
+                continue;
+            }
+        };
+
+        if !ascending {
+            continue;
+        }
+
+        let Some(profile) = WIND_ESTIMATOR.lock(|w| w.borrow_mut().add(&gps)) else {
+            continue;
+        };
+
+        if WIND_PROFILE_CHANNEL.send(profile).await {
+            info!("sent wind profile: alt: {}, wind: ({}, {}), n: {}",
+                profile.altitude, profile.wind[0], profile.wind[1], profile.samples);
+        }
+    }
+}
+
+// report task, runs the position and landing prediction reports in REPORT_SCHEDULE one at a time, the highest
+// priority of those due first, at the periods in the config
+// a period change takes effect once the current wait is over
+#[task]
+async fn report_task() {
+    info!("Starting report task");
+
+    let mut scheduler = Scheduler::new(REPORT_SCHEDULE);
+    let mut skipped = [0; REPORT_SCHEDULE.len()];
+    let mut position = PositionReporter::new();
+
+    loop {
+        let now = Instant::now().as_micros() as u32;
+        let config = CONFIG.lock(|c| c.get());
+        scheduler.set_period(Report::Position, config.position_period.saturating_mul(1_000_000), now);
+        scheduler.set_period(Report::Prediction, config.prediction_period.saturating_mul(1_000_000), now);
+        let Some(job) = scheduler.next(now) else {
+            Timer::after(Duration::from_micros(scheduler.wait(now) as u64)).await;
+            continue;
+        };
+
+        let job_skipped = scheduler.skipped(job);
+        if job_skipped != skipped[job as usize] {
+            warn!("{} report fell behind, {} periods skipped since boot", job, job_skipped);
+            skipped[job as usize] = job_skipped;
+        }
+
+        match job {
+            Report::Position => position.run().await,
+            Report::Prediction => predict().await,
+        }
+    }
+}
+
+// landing prediction job, predicts burst and landing from the latest position, ascent rate, and measured wind
+// profile, and downlinks it so the chase team can stage early
+async fn predict() {
+    let (Some(position), Some(vertical)) = (LATEST_POSITION.lock(|p| p.get()), VERTICAL_STATE_WATCH.try_get()) else {
+        return;
+    };
+
+    // a prediction from a frozen vertical speed would be confidently wrong, wait for the baro to come back
+    if FAULT_LOG.lock(|f| f.borrow().flags()).contains(Fault::Stale(Stream::Baro)) {
+        warn!("baro stale, skipping prediction");
+        return;
+    }
+
+    let input = PredictorInput {
+        state: FLIGHT_STATE.lock(|s| s.get()),
+        latitude: position.latitude,
+        longitude: position.longitude,
+        altitude: position.altitude,
+        vertical_speed: vertical.vertical_speed,
+        max_altitude: MAX_ALTITUDE.lock(|m| m.get()),
+        time_stamp: vertical.time_stamp,
+    };
+
+    let Some(prediction) = WIND_ESTIMATOR.lock(|w| PREDICTOR_CONFIG.predict(&input, &w.borrow())) else {
+        return;
+    };
+
+    info!("prediction: burst: {} m in {} s, descent: {} s, landing: ({}, {})",
+        prediction.burst_altitude, prediction.time_to_burst, prediction.descent_duration,
+        prediction.landing_latitude, prediction.landing_longitude);
+
+    TELEMETRY_CHANNEL.send(Telemetry::Prediction(prediction)).await;
+}
+
+// position job, reports the gps position while fixes are good and dead reckons with the wind profile and baro
+// altitude through outages (burst tumble, cocom limits, antenna shading)
+struct PositionReporter {
+    dead_reckoning: DeadReckoning,
+    was_dead_reckoned: bool,
+}
+
+impl PositionReporter {
+    fn new() -> Self {
+        Self { dead_reckoning: DeadReckoning::new(), was_dead_reckoned: false }
+    }
+
+    async fn run(&mut self) {
+        if let Some(gps) = LATEST_GPS.lock(|g| g.get()) {
+            self.dead_reckoning.add_fix(&gps);
+        }
+
+        let Some(vertical) = VERTICAL_STATE_WATCH.try_get() else {
+            return;
+        };
+        let Some(position) = WIND_ESTIMATOR.lock(|w| self.dead_reckoning.update(&vertical, &w.borrow())) else {
+            return;
+        };
+
+        if position.dead_reckoned != self.was_dead_reckoned {
+            if position.dead_reckoned {
+                warn!("gps fix lost, dead reckoning position");
+            } else {
+                info!("gps fix restored");
+            }
+            self.was_dead_reckoned = position.dead_reckoned;
+        }
+        report_fault(Fault::GpsLost, position.dead_reckoned);
+
+        LATEST_POSITION.lock(|p| p.set(Some(position)));
+
+        TELEMETRY_CHANNEL.send(Telemetry::Position(position)).await;
+    }
+}
+
+// fsk setup from the config, every register is read back so a write lost to a glitch can't leave the radio on the
+// wrong frequency or power unnoticed, the deviation stays at the part's 5 kHz default
+async fn configure_radio(radio: &mut bsp::StorageSpi, config: &Config) -> Result<(), WriteError> {
+    let frf = ((config.radio_frequency as u64) << 19) / RADIO_XOSC_HZ;
+    // the divider is 16 bits, rates under about 490 bit/s run at the slowest it gives
+    let bitrate = (RADIO_XOSC_HZ / config.radio_data_rate.max(1) as u64).min(u16::MAX as u64) as u16;
+    let band = if config.radio_frequency < RADIO_LOW_BAND_HZ { RADIO_MODE_LOW_FREQUENCY } else { 0 };
+    let registers = [
+        // frequency and modulation only change while asleep
+        (RADIO_REG_OP_MODE, band | RADIO_MODE_SLEEP),
+        (RADIO_REG_BITRATE_MSB, (bitrate >> 8) as u8),
+        (RADIO_REG_BITRATE_LSB, bitrate as u8),
+        (RADIO_REG_FRF_MSB, (frf >> 16) as u8),
+        (RADIO_REG_FRF_MID, (frf >> 8) as u8),
+        (RADIO_REG_FRF_LSB, frf as u8),
+        (RADIO_REG_PA_CONFIG, RADIO_PA_BOOST | (config.radio_power.clamp(2, 17) - 2) as u8),
+        (RADIO_REG_OP_MODE, band | RADIO_MODE_STANDBY),
+    ];
+    for (register, value) in registers {
+        let retries = write_verified(radio, Addressing::WRITE_BIT, register, value).await?;
+        if retries > 0 {
+            warn!("radio: register {:#04x} took {} writes to stick", register, retries + 1);
+        }
+    }
+    Ok(())
+}
+
+// radio downlink task, sends queued telemetry items
+#[task]
+async fn radio_task(mut radio: bsp::StorageSpi, mut rf_switch: Output<'static>) {
+    info!("Starting radio task");
+
+    let mut version = [0u8];
+    match radio.transaction(&mut [Operation::Write(&[RADIO_REG_VERSION]), Operation::Read(&mut version)]).await {
+        Ok(_) if version[0] == RADIO_VERSION => info!("radio: sx127x found"),
+        Ok(_) => warn!("radio: unexpected version {:#04x}", version[0]),
+        Err(_) => error!("radio: spi transfer failed"),
+    }
+
+    let mut cold = BATTERY_COLD.lock(|c| c.get());
+    let config = radio_config();
+    match configure_radio(&mut radio, &config).await {
+        Ok(()) => info!("radio: {} Hz, {} dBm, {} bit/s", config.radio_frequency, config.radio_power, config.radio_data_rate),
+        Err(e) => error!("radio: setup failed: {}", e),
+    }
+
+    // flight events go down as they happen so the ground hears about burst, landing, and faults right away
+    let mut events = EVENT_BUS.subscriber().unwrap();
+    // positions go down packed, key frames and deltas against them
+    let mut position_encoder = PositionEncoder::new();
+    let mut frame = [0u8; MAX_PACKET_LEN];
+    #[cfg(feature = "mavlink")]
+    let mut mavlink = mavlink::Encoder::new(MAVLINK_SYSTEM_ID, MAVLINK_COMPONENT_ID);
+    #[cfg(feature = "mavlink")]
+    let mut heartbeat = Ticker::every(MAVLINK_HEARTBEAT_PERIOD);
+
+    // a thumbnail trickles down one packet per image slot between telemetry frames, as do logged records the ground
+    // asked for, none in mavlink mode
+    #[cfg(not(feature = "mavlink"))]
+    let mut image: Option<ssdv::Encoder> = None;
+    #[cfg(not(feature = "mavlink"))]
+    let mut image_slot = Ticker::every(IMAGE_PACKET_PERIOD);
+
+    loop {
+        #[cfg(not(feature = "mavlink"))]
+        let next = select3(TELEMETRY_CHANNEL.receive(), events.next_message_pure(), image_slot.next()).await;
+        // the heartbeat keeps its own time so a quiet link isn't dropped by the ground control station
+        #[cfg(feature = "mavlink")]
+        let next = select3(TELEMETRY_CHANNEL.receive(), events.next_message_pure(), heartbeat.next()).await;
+
+        // the switch only moves between frames, never while one is going out
+        switch_antenna(&mut rf_switch);
+
+        #[cfg(not(feature = "mavlink"))]
+        let item = match next {
+            Either3::First(item) => item,
+            Either3::Second(record) => Telemetry::Event(record),
+            Either3::Third(()) => {
+                if let Some(image_id) = THUMBNAIL_SIGNAL.try_take() {
+                    image = match THUMBNAIL.lock(|t| ssdv::Encoder::new(&t.borrow(), SSDV_CALLSIGN, image_id, ssdv::DEFAULT_QUALITY)) {
+                        Ok(encoder) => Some(encoder),
+                        Err(e) => {
+                            warn!("radio: thumbnail {} can't go as ssdv: {}", image_id, e);
+                            None
+                        }
+                    };
+                }
+                if LOW_POWER.lock(|l| l.get()) || !rail_on(Rail::RadioPa) {
+                    continue;
+                }
+                // logged records the ground asked for go ahead of the thumbnail, as they were logged
+                if let Ok(record) = HISTORY_CHANNEL.try_receive() {
+                    let mut packet = [0u8; 2 + HISTORY_RECORD_LEN];
+                    if let Some(len) = record.encode(&mut packet) {
+                        // send over radio here
+                        trace!("downlink history {}: {=[u8]:02x}", record.stream, packet[..len]);
+                    }
+                    continue;
+                }
+                let Some(encoder) = image.as_mut() else {
+                    continue;
+                };
+                // the packet id stands in for the ssdv sync byte
+                let mut packet = [0u8; ssdv::PACKET_LEN];
+                match THUMBNAIL.lock(|t| encoder.next_packet(&t.borrow(), &mut packet)) {
+                    Ok(true) => {
+                        packet[0] = IMAGE_PACKET_ID;
+                        // send over radio here
+                        trace!("downlink image {} packet {}: {=[u8]:02x}", packet[6], encoder.packets() - 1, packet[..]);
+                    }
+                    Ok(false) => image = None,
+                    Err(e) => {
+                        warn!("radio: thumbnail stopped at packet {}: {}", encoder.packets(), e);
+                        image = None;
+                    }
+                }
+                continue;
+            }
+        };
+        #[cfg(feature = "mavlink")]
+        let item = match next {
+            Either3::First(item) => item,
+            Either3::Second(record) => Telemetry::Event(record),
+            Either3::Third(()) => {
+                if !LOW_POWER.lock(|l| l.get()) && rail_on(Rail::RadioPa) {
+                    send_mavlink(&mut mavlink, &mavlink_heartbeat(), &mut frame);
+                }
+                continue;
+            }
+        };
+
+        // the transmit power follows the battery temperature, set again between frames when it changes
+        if BATTERY_COLD.lock(|c| c.get()) != cold {
+            cold = !cold;
+            let config = radio_config();
+            match configure_radio(&mut radio, &config).await {
+                Ok(()) => info!("radio: transmit power now {} dBm", config.radio_power),
+                Err(e) => error!("radio: power change failed: {}", e),
+            }
+        }
+
+        // every transmission drains the battery, on low voltage only the beacon goes out
+        if LOW_POWER.lock(|l| l.get()) && !matches!(item, Telemetry::Beacon(_)) {
+            continue;
+        }
+        // nothing goes out without the power amplifier, the item is dropped rather than queued behind it
+        if !rail_on(Rail::RadioPa) {
+            continue;
+        }
+
+        // every frame carries utc so the ground can place it in real time, None until the rtc has been set
+        // and mission elapsed time so operators can read it without converting, None before launch
+        let utc = time_sync().map(|sync| sync.utc);
+        let met = met(Instant::now().as_micros() as u32);
+
+        // in mavlink mode the standard messages standing in for the item go down in place of its packet
+        #[cfg(feature = "mavlink")]
+        let messages = mavlink_messages(&item);
+
+        // every item goes down as one frame, the trace is the same item for a debug probe
+        #[cfg_attr(feature = "mavlink", allow(unused_variables))]
+        let len = match item {
+            Telemetry::Prediction(prediction) => {
+                trace!("downlink prediction: burst: {}, landing: ({}, {}), ts: {}, utc: {}, met: {}",
+                    prediction.burst_altitude, prediction.landing_latitude, prediction.landing_longitude,
+                    prediction.time_stamp, utc, met);
+                PredictionPacket {
+                    burst_altitude: prediction.burst_altitude,
+                    time_to_burst: prediction.time_to_burst,
+                    descent_duration: prediction.descent_duration,
+                    landing_latitude: prediction.landing_latitude,
+                    landing_longitude: prediction.landing_longitude,
+                    time_stamp: prediction.time_stamp,
+                }
+                .encode(&mut frame)
+            }
+            Telemetry::Position(position) => {
+                trace!("downlink position: ({}, {}), alt: {}, dead reckoned: {}, degraded: {}, ts: {}, utc: {}, met: {}",
+                    position.latitude, position.longitude, position.altitude, position.dead_reckoned, position.degraded,
+                    position.time_stamp, utc, met);
+                let compact = CompactPosition {
+                    latitude: position.latitude,
+                    longitude: position.longitude,
+                    altitude: position.altitude,
+                    climb_rate: VERTICAL_STATE_WATCH.try_get().map_or(0.0, |v| v.vertical_speed),
+                    state: FLIGHT_STATE.lock(|s| s.get()),
+                    dead_reckoned: position.dead_reckoned,
+                    degraded: position.degraded,
+                    low_voltage: LOW_POWER.lock(|l| l.get()),
+                    fault: !FAULT_LOG.lock(|f| f.borrow().flags()).is_empty(),
+                    voltage: LATEST_VOLTAGE.lock(|v| v.get()).unwrap_or(0.0),
+                };
+                position_encoder.encode(&compact, &mut frame)
+            }
+            Telemetry::Health(report) => {
+                trace!("downlink health: faults: {}, recent: {}, cpu load: {}%, overruns: {}, dropped: {}, ts: {}, utc: {}, met: {}",
+                    report.faults, report.recent, report.cpu_load, report.loop_overruns, report.dropped, report.time_stamp, utc, met);
+                HealthPacket {
+                    faults: report.faults.0,
+                    cpu_load: report.cpu_load,
+                    loop_overruns: report.loop_overruns,
+                    dropped_baro: report.dropped.baro,
+                    dropped_imu: report.dropped.imu,
+                    dropped_gps: report.dropped.gps,
+                    dropped_log: report.dropped.log,
+                    dropped_telemetry: report.dropped.telemetry,
+                    continuity: report.continuity.0,
+                    armed: report.armed.0,
+                    antenna: report.antenna.id(),
+                    battery_temperature: Temperature(report.battery_temperature),
+                    time_stamp: report.time_stamp,
+                }
+                .encode(&mut frame)
+            }
+            Telemetry::Panic(record) => {
+                trace!("downlink panic: {}, at {}:{}, uptime: {}, utc: {}, met: {}", record.message(), record.file(), record.line, record.uptime, utc, met);
+                PanicPacket {
+                    file: Name::new(record.file()),
+                    line: record.line,
+                    uptime: (record.uptime / 1000) as u32,
+                }
+                .encode(&mut frame)
+            }
+            Telemetry::Config(report) => {
+                trace!("downlink config: {}: {}, pending: {}, ts: {}, utc: {}, met: {}", report.key, report.value, report.pending, report.time_stamp, utc, met);
+                ConfigPacket {
+                    key: report.key.id(),
+                    value: u32::from_le_bytes(config_value_bytes(report.value).1),
+                    pending: report.pending,
+                    time_stamp: report.time_stamp,
+                }
+                .encode(&mut frame)
+            }
+            Telemetry::Beacon(beacon) => {
+                trace!("downlink beacon: ({}, {}), alt: {}, battery: {} V, ts: {}, utc: {}, met: {}",
+                    beacon.latitude, beacon.longitude, beacon.altitude, beacon.voltage, beacon.time_stamp, utc, met);
+                BeaconPacket {
+                    latitude: beacon.latitude,
+                    longitude: beacon.longitude,
+                    altitude: beacon.altitude,
+                    voltage: Voltage(beacon.voltage),
+                    time_stamp: beacon.time_stamp,
+                }
+                .encode(&mut frame)
+            }
+            Telemetry::Power(power) => {
+                trace!("downlink power: battery: {} V, solar: {} V, {} A, {}, ts: {}, utc: {}, met: {}",
+                    power.battery_voltage, power.solar_voltage, power.solar_current, power.charge, power.time_stamp, utc, met);
+                PowerPacket {
+                    battery_voltage: Voltage(power.battery_voltage),
+                    solar_voltage: Voltage(power.solar_voltage),
+                    solar_current: power.solar_current,
+                    charge: power.charge.id(),
+                    time_stamp: power.time_stamp,
+                }
+                .encode(&mut frame)
+            }
+            Telemetry::Boot(report) => {
+                let firmware = report.firmware;
+                trace!("downlink boot: version: {}, git: {}, dirty: {}, profile: {}, features: {}, reset: {}, boot: {}, utc: {}, met: {}",
+                    firmware.version, firmware.git_hash, firmware.dirty, firmware.profile, firmware.features,
+                    report.boot.reset_reason, report.boot.boot_count, utc, met);
+                BootPacket {
+                    reset_reason: report.boot.reset_reason as u8,
+                    boot_count: report.boot.boot_count,
+                    dirty: firmware.dirty,
+                }
+                .encode(&mut frame)
+            }
+            Telemetry::Update(status) => {
+                trace!("downlink update: {} of {} bytes, result: {}, ts: {}, utc: {}, met: {}",
+                    status.next_offset, status.size, status.result, status.time_stamp, utc, met);
+                UpdatePacket {
+                    size: status.size,
+                    next_offset: status.next_offset,
+                    error: status.result.map_or_else(|e| e as u8 + 1, |_| 0),
+                    time_stamp: status.time_stamp,
+                }
+                .encode(&mut frame)
+            }
+            Telemetry::Assist(status) => {
+                trace!("downlink assist: {} of {} bytes, messages: {}, rejected: {}, result: {}, ts: {}, utc: {}, met: {}",
+                    status.next_offset, status.size, status.messages, status.rejected, status.result, status.time_stamp, utc, met);
+                AssistPacket {
+                    size: status.size,
+                    next_offset: status.next_offset,
+                    messages: status.messages,
+                    rejected: status.rejected,
+                    error: status.result.map_or_else(|e| e as u8 + 1, |_| 0),
+                    time_stamp: status.time_stamp,
+                }
+                .encode(&mut frame)
+            }
+            Telemetry::Event(record) => {
+                trace!("downlink event: {}, ts: {}, utc: {}, met: {}", record.event, record.time_stamp, utc, met);
+                // the fault as its bit in the fault flags, as in the log
+                let (fault, active) = match record.event {
+                    FlightEvent::Fault { fault, active } => {
+                        let mut flags = FaultFlags::NONE;
+                        flags.set(fault, true);
+                        (flags.0, active)
+                    }
+                    _ => (0, false),
+                };
+                EventPacket {
+                    event: record.event.code(),
+                    fault,
+                    active,
+                    time_stamp: record.time_stamp,
+                }
+                .encode(&mut frame)
+            }
+            Telemetry::Session(session) => {
+                trace!("downlink session: ground pressure: {}, ground altitude: {}, reset: {}, boot: {}, ts: {}, utc: {}, met: {}",
+                    session.ground_pressure, session.ground_altitude, session.boot.reset_reason, session.boot.boot_count,
+                    session.time_stamp, utc, met);
+                SessionPacket {
+                    ground_pressure: session.ground_pressure,
+                    ground_altitude: session.ground_altitude,
+                    reset_reason: session.boot.reset_reason as u8,
+                    boot_count: session.boot.boot_count,
+                    time_stamp: session.time_stamp,
+                }
+                .encode(&mut frame)
+            }
+        };
+
+        #[cfg(feature = "mavlink")]
+        for message in messages.iter().flatten() {
+            send_mavlink(&mut mavlink, message, &mut frame);
+        }
+
+        // every packet fits MAX_PACKET_LEN, checked at compile time
+        #[cfg(not(feature = "mavlink"))]
+        {
+            let Some(len) = len else {
+                continue;
+            };
+            // send over radio here
+            trace!("downlink frame: {=[u8]:02x}", frame[..len]);
+        }
+    }
+}
+
+// every mavlink frame fits the radio frame buffer
+#[cfg(feature = "mavlink")]
+const _: () = assert!(mavlink::MAX_FRAME_LEN <= MAX_PACKET_LEN);
+
+// one mavlink message as its own radio frame
+#[cfg(feature = "mavlink")]
+fn send_mavlink(encoder: &mut mavlink::Encoder, message: &mavlink::Message, frame: &mut [u8]) {
+    let Some(len) = encoder.encode(message, frame) else {
+        return;
+    };
+    // send over radio here
+    trace!("downlink mavlink frame: {=[u8]:02x}", frame[..len]);
+}
+
+// heartbeat for the current flight state, armed once the launch site has been captured
+#[cfg(feature = "mavlink")]
+fn mavlink_heartbeat() -> mavlink::Message {
+    mavlink::Message::Heartbeat(mavlink::Heartbeat {
+        state: FLIGHT_STATE.lock(|s| s.get()),
+        armed: SESSION.lock(|s| s.get()).is_some(),
+        fault: !FAULT_LOG.lock(|f| f.borrow().flags()).is_empty(),
+        low_voltage: LOW_POWER.lock(|l| l.get()),
+    })
+}
+
+// standard messages for a telemetry item, items a ground control station has no message for send nothing
+// positions send the raw gps fix and the barometer, as a ground control station shows them
+// beacons bring their own heartbeat since the timed one is held back in low power
+#[cfg(feature = "mavlink")]
+fn mavlink_messages(item: &Telemetry) -> [Option<mavlink::Message>; 2] {
+    let gps = || LATEST_GPS.lock(|g| g.get()).map(|gps| mavlink::Message::GpsRawInt(mavlink::GpsRawInt { gps }));
+    match item {
+        Telemetry::Position(_) => [
+            gps(),
+            LATEST_BARO.lock(|b| b.get()).map(|baro| mavlink::Message::ScaledPressure(mavlink::ScaledPressure { baro })),
+        ],
+        Telemetry::Beacon(_) => [Some(mavlink_heartbeat()), gps()],
+        Telemetry::Health(report) => {
+            let voltage = LATEST_VOLTAGE.lock(|v| v.get());
+            [
+                Some(mavlink::Message::SysStatus(mavlink::SysStatus {
+                    faults: report.faults,
+                    cpu_load: report.cpu_load,
+                    voltage,
+                    battery_remaining: voltage.map(|v| power::state_of_charge(v, BATTERY_CELLS) as u8),
+                })),
+                None,
+            ]
+        }
+        _ => [None, None],
+    }
+}
+
+// navigation filter task, fuses gps, baro altitude, and imu into the full state vector
+// predicts on every imu sample and publishes the state to logging and gnc at the imu rate
+#[task]
+async fn nav_task() {
+    info!("Starting nav task");
+
+    let mut filter = NavFilter::new();
+    let mut prev_mode = filter.mode();
+
+    // the inertial input arrives at the imu rate, so nav checks in at least that often
+    let watchdog = watchdog_register("nav", SENSOR_CHECK_IN_DEADLINE);
+    let mut vertical_state = VERTICAL_STATE_WATCH.receiver().unwrap();
+
+    loop {
+        watchdog_check_in(watchdog);
+
+        match select3(NAV_INERTIAL_CHANNEL.receive(), NAV_GPS_CHANNEL.receive(), vertical_state.changed()).await {
+            Either3::First((imu, attitude)) => filter.predict(&imu, &attitude),
+            Either3::Second(gps) => {
+                filter.update_gps(&gps);
+                continue;
+            }
+            Either3::Third(baro) => {
+                filter.update_baro(&baro);
+                continue;
+            }
+        }
+
+        let state = filter.state();
+        if state.mode != prev_mode {
+            match state.mode {
+                NavMode::Full => info!("nav mode: {}", state.mode),
+                _ => warn!("nav mode: {}", state.mode),
+            }
+            prev_mode = state.mode;
+        }
+
+        stream_seen(Stream::Nav, state.time_stamp);
+
+        if STATE_VECTOR_CHANNEL.send(state).await {
+            info!("sent state vector: p: ({}, {}, {}), v: ({}, {}, {}), ts: {}",
+                state.position[0], state.position[1], state.position[2],
+                state.velocity[0], state.velocity[1], state.velocity[2],
+                state.time_stamp);
+        }
+
+        GNC_STATE_WATCH.sender().send(state);
+    }
+}
+
+// gnc can bus interface, forwards raw imu, attitude, and nav state to the gnc computer
+#[task]
+async fn gnc_task() {
+    info!("Starting gnc task");
+
+    // the can bus wants the current attitude and state, a value that was replaced before it went out is stale anyway
+    let mut attitude = ATTITUDE_WATCH.receiver().unwrap();
+    let mut nav_state = GNC_STATE_WATCH.receiver().unwrap();
+    let mut imu_data = IMU_DATA_PUBSUB.subscriber().unwrap();
+
+    loop {
+        let message = select3(attitude.changed(), nav_state.changed(), imu_data.next_message_pure()).await;
+        // nothing to send to with the gnc rail off, the watches and the subscriber are kept drained
+        if !rail_on(Rail::Gnc) {
+            continue;
+        }
+        match message {
+            Either3::First(data) => {
+                // send over can bus here
+                trace!("gnc attitude: q: ({}, {}, {}, {}), converged: {}, ts: {}",
+                    data.quaternion[0], data.quaternion[1], data.quaternion[2], data.quaternion[3],
+                    data.converged, data.time_stamp);
+            }
+            Either3::Second(state) => {
+                // send over can bus here
+                trace!("gnc state: p: ({}, {}, {}), v: ({}, {}, {}), mode: {}, ts: {}",
+                    state.position[0], state.position[1], state.position[2],
+                    state.velocity[0], state.velocity[1], state.velocity[2],
+                    state.mode, state.time_stamp);
+            }
+            Either3::Third(data) => {
+                // send over can bus here
+                trace!("gnc imu: a: ({}, {}, {}), g: ({}, {}, {}), ts: {}",
+                    data.acceleration[0], data.acceleration[1], data.acceleration[2],
+                    data.gyro[0], data.gyro[1], data.gyro[2], data.time_stamp);
+            }
+        }
+    }
+}
+
+// persists calibration and config to flash whenever they change, only the calibration parts that changed since the
+// last save go into the calibration store
+// also writes uplinked firmware images to the update slot, acking every step with the transfer progress,
+// and reboots into the bootloader's install of a complete image once its crc checks out
+#[task]
+async fn storage_task(
+    mut flash: bsp::Storage,
+    mut settings: bsp::SettingsMemory,
+    mut calibration_store: KvStore,
+    mut saved_calibration: Calibration,
+) {
+    info!("Starting storage task");
+
+    let mut receiver = ImageReceiver::new(IMAGE_SLOT_SIZE);
+
+    loop {
+        let result = match select3(CALIBRATION_SAVE_SIGNAL.wait(), CONFIG_SAVE_SIGNAL.wait(), UPDATE_CHANNEL.receive()).await {
+            Either3::Third(step) => {
+                let result = update_step(&mut flash, &mut receiver, step).await;
+                let (size, next_offset) = receiver.progress();
+                let status = UpdateStatus {
+                    size,
+                    next_offset,
+                    result: result.map(|_| ()),
+                    time_stamp: Instant::now().as_micros() as u32,
+                };
+                UPDATE_STATUS_SIGNAL.signal(status);
+                TELEMETRY_CHANNEL.send(Telemetry::Update(status)).await;
+
+                if let Ok(Some((size, crc))) = result {
+                    info!("firmware image received, {} bytes, crc: {:#010x}, rebooting to install", size, crc);
+                    unsafe { core::ptr::write_volatile(UPDATE_RECORD, UpdateRecord::new(UpdateState::Pending, size, crc)) };
+                    FINALIZE_LOG_SIGNAL.signal(());
+                    LOG_FINALIZED_SIGNAL.wait().with_timeout(LOG_FINALIZE_TIMEOUT).await.ok();
+                    cortex_m::peripheral::SCB::sys_reset();
+                }
+                continue;
+            }
+            Either3::First(calibration) => {
+                // parts already written stay written if a later one fails, all that differ are tried again next time
+                let mut result = Ok(());
+                for key in calibration.changed_parts(&saved_calibration) {
+                    let mut bytes = [0u8; CALIBRATION_PART_LEN];
+                    let len = calibration.encode_part(key, &mut bytes);
+                    result = calibration_store.set(&mut bsp::calibration_region(&mut flash, &mut settings), key.id(), key.version(), &bytes[..len]).await;
+                    if result.is_err() {
+                        break;
+                    }
+                }
+                match result {
+                    Ok(()) => {
+                        saved_calibration = calibration;
+                        info!("calibration saved, store {} bytes used, erased {} times", calibration_store.used(), calibration_store.erases());
+                    }
+                    Err(e) => error!("failed to save calibration: {}", e),
+                }
+                report_fault(Fault::CalibrationWrite, result.is_err());
+                continue;
+            }
+            Either3::Second(config) => write_config(&mut bsp::config_region(&mut flash, &mut settings), &config).await,
+        };
+
+        match result {
+            Ok(_) => info!("config saved"),
+            Err(e) => error!("failed to save config: {}", e),
+        }
+    }
+}
+
+// erase the config region and write the record at its start, padded with erased bytes to the write size
+async fn write_config<F: KvFlash>(region: &mut F, config: &Config) -> Result<(), F::Error> {
+    let mut bytes = [0xFFu8; Config::SIZE + KV_WRITE_SIZE];
+    bytes[..Config::SIZE].copy_from_slice(&config.to_bytes());
+    let write_size = region.write_size() as usize;
+    region.erase().await?;
+    region.write(0, &bytes[..Config::SIZE.div_ceil(write_size) * write_size]).await
+}
+
+// run one image transfer step, returns the image size and crc once a finished image has been verified and marked
+// for the bootloader to install
+#[cfg(feature = "nucleo-f767")]
+async fn update_step(flash: &mut bsp::Storage, receiver: &mut ImageReceiver, step: UpdateCommand) -> Result<Option<(u32, u32)>, UpdateError> {
+    match step {
+        UpdateCommand::Begin { size, crc } => {
+            if receiver.begin(size, crc)? {
+                info!("firmware transfer started, {} bytes, erasing update slot", size);
+                if let Err(e) = bsp::erase_update_slot(flash).await {
+                    error!("failed to erase update slot: {}", e);
+                    receiver.abort();
+                    return Err(UpdateError::Flash);
+                }
+            } else {
+                info!("firmware transfer resumed at {}", receiver.progress().1);
+            }
+            Ok(None)
+        }
+        UpdateCommand::Chunk { offset, len, data, crc } => {
+            let data = data.get(..len as usize).ok_or(UpdateError::BadChunk)?;
+            if receiver.accept(offset, data, crc)? {
+                if let Err(e) = bsp::write_update(flash, offset, data).await {
+                    error!("failed to write update chunk at {}: {}", offset, e);
+                    return Err(UpdateError::Flash);
+                }
+                receiver.advance(data);
+            }
+            Ok(None)
+        }
+        UpdateCommand::Finish => {
+            let (size, crc) = receiver.finish()?;
+            // check what actually landed in flash, not just what was received
+            if crc::crc32(bsp::update_image(size)) != crc {
+                error!("update slot doesn't match the received image");
+                return Err(UpdateError::ImageCrc);
+            }
+            if let Err(e) = bsp::write_update_trailer(flash, SlotTrailer { size, crc }).await {
+                error!("failed to write the update slot trailer: {}", e);
+                return Err(UpdateError::Flash);
+            }
+            Ok(Some((size, crc)))
+        }
+        UpdateCommand::Abort => {
+            info!("firmware transfer aborted");
+            receiver.abort();
+            Ok(None)
+        }
+    }
+}
+
+// the flight board has no update slot, see src/slots.rs
+#[cfg(not(feature = "nucleo-f767"))]
+async fn update_step(_flash: &mut bsp::Storage, _receiver: &mut ImageReceiver, _step: UpdateCommand) -> Result<Option<(u32, u32)>, UpdateError> {
+    Err(UpdateError::NoSlot)
+}
+
+// receives sensor data and serializes it into the log streams' queues, the log storage task writes each block as
+// it fills, or sooner as the stream's flush period runs out
+#[task]
+async fn log_task(mut logs: LogStreams) {
+    info!("Entered logging task");
+
+    let watchdog = watchdog_register("log", LOG_CHECK_IN_DEADLINE);
+    let timing = loop_register("log", None);
+    // closed for a reboot, stays closed whatever the supply does
+    let mut finalized = false;
+    let mut imu_data = IMU_DATA_PUBSUB.subscriber().unwrap();
+    let mut events = EVENT_BUS.subscriber().unwrap();
+    let mut flushed = [Instant::now(); LOG_STREAMS];
+
+    // every binary stream starts with the identity of the firmware that wrote it
+    info!("firmware record: {}", FIRMWARE);
+    let text = |s: &'static str| [s.len() as u8];
+    for stream in LogStream::ALL.into_iter().filter(|&stream| stream != LogStream::Debug) {
+        log_record(&mut logs.producers[stream.id() as usize], LOG_FIRMWARE, &[
+            &[FIRMWARE.dirty as u8],
+            &text(FIRMWARE.version), FIRMWARE.version.as_bytes(),
+            &text(FIRMWARE.git_hash), FIRMWARE.git_hash.as_bytes(),
+            &text(FIRMWARE.profile), FIRMWARE.profile.as_bytes(),
+            &text(FIRMWARE.features), FIRMWARE.features.as_bytes(),
+        ]);
+    }
+    logs.line(Instant::now().as_micros() as u32, format_args!("firmware {} {} {}", FIRMWARE.version, FIRMWARE.git_hash, FIRMWARE.profile));
+
+    loop {
+        watchdog_check_in(watchdog);
+        loop_start(timing);
+
+        // an sd card losing power mid write can corrupt the whole file system, so on low voltage
+        // whatever is buffered is written out and the file closed while there is still power to do it
+        let low_power = LOW_POWER.lock(|l| l.get());
+        let now = Instant::now();
+        if FINALIZE_LOG_SIGNAL.try_take().is_some() {
+            if logs.open {
+                info!("flushing {} bytes to sd card and closing log file", logs.pending());
+                logs.line(now.as_micros() as u32, format_args!("log closed for a reboot"));
+                logs.finish_blocks();
+                while !logs.is_empty() {
+                    Timer::after(LOG_HIGH_RATE_PERIOD).await;
+                }
+                logs.open = false;
+            }
+            finalized = true;
+            LOG_FINALIZED_SIGNAL.signal(());
+        } else if low_power && logs.open {
+            warn!("low voltage, flushing {} bytes to sd card and closing log file", logs.pending());
+            logs.line(now.as_micros() as u32, format_args!("low voltage, log closed"));
+            logs.finish_blocks();
+            logs.open = false;
+        } else if !low_power && !logs.open && !finalized {
+            info!("voltage recovered, reopening log file");
+            logs.open = true;
+            logs.line(now.as_micros() as u32, format_args!("voltage recovered, log reopened"));
+        }
+
+        // keep draining the channels while the file is closed so the producers don't stall, the data is dropped
+
+        // session header goes in ahead of the data captured after it
+        while let Ok(data) = SESSION_CHANNEL.try_receive() {
+            info!("received session header: ground pressure: {}, ground altitude: {}, reset: {}, boot: {}, ts: {}",
+                data.ground_pressure, data.ground_altitude, data.boot.reset_reason, data.boot.boot_count, data.time_stamp);
+
+            logs.record(LOG_SESSION, &[
+                &data.ground_pressure.to_le_bytes(),
+                &data.ground_altitude.to_le_bytes(),
+                &[data.boot.reset_reason as u8],
+                &data.boot.boot_count.to_le_bytes(),
+                &data.time_stamp.to_le_bytes(),
+            ]);
+        }
+
+        while let Ok(data) = CONFIG_AUDIT_CHANNEL.try_receive() {
+            info!("received config change: {}: {} -> {}, ts: {}", data.key, data.old, data.new, data.time_stamp);
+            logs.line(data.time_stamp, format_args!("config {}: {:?} -> {:?}", data.key.name(), data.old, data.new));
+
+            let (kind, old) = config_value_bytes(data.old);
+            let (_, new) = config_value_bytes(data.new);
+            logs.record(LOG_CONFIG_CHANGE, &[&[data.key.id(), kind], &old, &new, &data.time_stamp.to_le_bytes()]);
+        }
+
+        while let Ok((from, to, time_stamp)) = STATE_CHANGE_CHANNEL.try_receive() {
+            info!("received state change: {} -> {}, ts: {}", from, to, time_stamp);
+            logs.line(time_stamp, format_args!("state {:?} -> {:?}", from, to));
+
+            logs.record(LOG_STATE, &[&[from.id(), to.id()], &time_stamp.to_le_bytes()]);
+        }
+
+        while let Ok((code, accepted, time_stamp)) = COMMAND_AUDIT_CHANNEL.try_receive() {
+            info!("received command audit: {:#04x}, accepted: {}, ts: {}", code, accepted, time_stamp);
+            let outcome = if accepted { "carried out" } else { "refused" };
+            match Command::describe(code) {
+                Some((name, Some(channel))) => logs.line(time_stamp, format_args!("command {} {} {}", name, channel.name(), outcome)),
+                Some((name, None)) => logs.line(time_stamp, format_args!("command {} {}", name, outcome)),
+                None => logs.line(time_stamp, format_args!("command {:#04x} {}", code, outcome)),
+            }
+
+            logs.record(LOG_COMMAND, &[&[code, accepted as u8], &time_stamp.to_le_bytes()]);
+        }
+
+        while let Ok((change, time_stamp)) = CALIBRATION_CHANGE_CHANNEL.try_receive() {
+            info!("received calibration change: {}, ts: {}", change, time_stamp);
+            logs.line(time_stamp, format_args!("calibration {:?} updated", change));
+
+            logs.record(LOG_CALIBRATION, &[&[change.code()], &time_stamp.to_le_bytes()]);
+        }
+
+        while let Ok(data) = STACK_USAGE_CHANNEL.try_receive() {
+            info!("received stack usage: {} of {} bytes, ts: {}", data.used, data.size, data.time_stamp);
+
+            logs.record(LOG_STACK_USAGE, &[&data.used.to_le_bytes(), &data.size.to_le_bytes(), &data.time_stamp.to_le_bytes()]);
+        }
+
+        // check for baro data
+        while let Ok(data) = BARO_DATA_CHANNEL.try_receive() {
+            info!("received baro data: p: {}, t: {}, ts: {}", data.pressure, data.temperature, data.time_stamp);
+
+            logs.record(LOG_BARO, &[&data.pressure.to_le_bytes(), &data.temperature.to_le_bytes(), &data.time_stamp.to_le_bytes()]);
+        }
+        
+        while let Some(result) = imu_data.try_next_message() {
+            let data = match result {
+                WaitResult::Message(data) => data,
+                WaitResult::Lagged(missed) => {
+                    warn!("log fell behind, missed {} imu samples", missed);
+                    count_drops(&IMU_DROPPED, missed);
+                    continue;
+                }
+            };
+            info!("received imu data: a: ({}, {}, {}), g: ({}, {}, {}), m: ({}, {}, {}), t: {}, ts: {}", 
+                data.acceleration[0], data.acceleration[1], data.acceleration[2],
+                data.gyro[0], data.gyro[1], data.gyro[2],
+                data.mag[0], data.mag[1], data.mag[2],
+                data.temperature, data.time_stamp);
+
+            logs.record(LOG_IMU, &[
+                data.acceleration.map(f32::to_le_bytes).as_flattened(),
+                data.gyro.map(f32::to_le_bytes).as_flattened(),
+                data.mag.map(f32::to_le_bytes).as_flattened(),
+                &data.temperature.to_le_bytes(),
+                &data.time_stamp.to_le_bytes(),
+            ]);
+        }
+
+        while let Ok(data) = ATTITUDE_DATA_CHANNEL.try_receive() {
+            info!("received attitude data: q: ({}, {}, {}, {}), converged: {}, ts: {}",
+                data.quaternion[0], data.quaternion[1], data.quaternion[2], data.quaternion[3],
+                data.converged, data.time_stamp);
+
+            logs.record(LOG_ATTITUDE, &[
+                data.quaternion.map(f32::to_le_bytes).as_flattened(),
+                &[data.converged as u8],
+                &data.time_stamp.to_le_bytes(),
+            ]);
+        }
+
+        while let Ok(data) = GPS_DATA_CHANNEL.try_receive() {
+            info!("received gps data: lat: {}, lon: {}, alt: {}, v: ({}, {}, {}), sats: {}, fix: {}, hdop: {}, quality: {}, utc: {}, ts: {}",
+                data.latitude, data.longitude, data.altitude,
+                data.velocity[0], data.velocity[1], data.velocity[2],
+                data.satellites, data.fix, data.hdop, data.quality, data.utc, data.time_stamp);
+
+            // utc 0 until the receiver has time
+            logs.record(LOG_GPS, &[
+                &data.latitude.to_le_bytes(),
+                &data.longitude.to_le_bytes(),
+                &data.altitude.to_le_bytes(),
+                data.velocity.map(f32::to_le_bytes).as_flattened(),
+                &[data.satellites, data.fix as u8, data.quality as u8],
+                &data.hdop.to_le_bytes(),
+                &data.utc.unwrap_or(0).to_le_bytes(),
+                &data.time_stamp.to_le_bytes(),
+            ]);
+        }
+
+        while let Ok(data) = WIND_PROFILE_CHANNEL.try_receive() {
+            info!("received wind profile: alt: {}, wind: ({}, {}), n: {}, ts: {}",
+                data.altitude, data.wind[0], data.wind[1], data.samples, data.time_stamp);
+
+            logs.record(LOG_WIND, &[
+                &data.altitude.to_le_bytes(),
+                data.wind.map(f32::to_le_bytes).as_flattened(),
+                &data.samples.to_le_bytes(),
+                &data.time_stamp.to_le_bytes(),
+            ]);
+        }
+
+        while let Ok(data) = STATE_VECTOR_CHANNEL.try_receive() {
+            info!("received state vector: p: ({}, {}, {}), v: ({}, {}, {}), q: ({}, {}, {}, {}), mode: {}, ts: {}",
+                data.position[0], data.position[1], data.position[2],
+                data.velocity[0], data.velocity[1], data.velocity[2],
+                data.attitude[0], data.attitude[1], data.attitude[2], data.attitude[3],
+                data.mode, data.time_stamp);
+
+            logs.record(LOG_STATE_VECTOR, &[
+                data.position.map(f32::to_le_bytes).as_flattened(),
+                data.velocity.map(f32::to_le_bytes).as_flattened(),
+                data.attitude.map(f32::to_le_bytes).as_flattened(),
+                &[data.mode as u8],
+                &data.time_stamp.to_le_bytes(),
+            ]);
+        }
+
+        while let Some(result) = events.try_next_message() {
+            let data = match result {
+                WaitResult::Message(data) => data,
+                WaitResult::Lagged(missed) => {
+                    warn!("log fell behind, missed {} flight events", missed);
+                    continue;
+                }
+            };
+            info!("received flight event: {}, ts: {}", data.event, data.time_stamp);
+            logs.line(data.time_stamp, format_args!("{:?}", data.event));
+
+            match data.event {
+                // the fault goes in as its bit in the fault flags, the same numbering as the health report
+                FlightEvent::Fault { fault, active } => {
+                    let mut flags = FaultFlags::NONE;
+                    flags.set(fault, true);
+                    logs.record(LOG_FAULT, &[&flags.0.to_le_bytes(), &[active as u8], &data.time_stamp.to_le_bytes()]);
+                }
+                event => logs.record(LOG_EVENT, &[&[event.code()], &data.time_stamp.to_le_bytes()]),
+            }
+        }
+
+        while let Ok((data, time_stamp)) = DROP_COUNTS_CHANNEL.try_receive() {
+            info!("received drop counts: {}, ts: {}", data, time_stamp);
+
+            logs.record(LOG_DROP_COUNTS, &[
+                &data.baro.to_le_bytes(),
+                &data.imu.to_le_bytes(),
+                &data.gps.to_le_bytes(),
+                &data.log.to_le_bytes(),
+                &data.telemetry.to_le_bytes(),
+                &time_stamp.to_le_bytes(),
+            ]);
+        }
+
+        while let Ok(data) = VIBRATION_SAMPLES_CHANNEL.try_receive() {
+            logs.record(LOG_VIBRATION_SAMPLES, &[
+                &data.time_stamp.to_le_bytes(),
+                &data.sample_period.to_le_bytes(),
+                &data.first.to_le_bytes(),
+                &[CHUNK_SAMPLES as u8],
+                data.samples.map(|sample| sample.map(i16::to_le_bytes)).as_flattened().as_flattened(),
+            ]);
+        }
+
+        while let Ok(data) = VIBRATION_CHANNEL.try_receive() {
+            info!("received vibration summary: rms: ({}, {}, {}), ts: {}", data.rms[0], data.rms[1], data.rms[2], data.time_stamp);
+
+            logs.record(LOG_VIBRATION, &[
+                data.rms.map(f32::to_le_bytes).as_flattened(),
+                data.peak.map(f32::to_le_bytes).as_flattened(),
+                data.bands.map(|axis| axis.map(f32::to_le_bytes)).as_flattened().as_flattened(),
+                &data.sample_period.to_le_bytes(),
+                &data.time_stamp.to_le_bytes(),
+            ]);
+        }
+
+        while let Ok(data) = FIRING_CHANNEL.try_receive() {
+            info!("received firing: {}, confirmed: {}, peak: {} A, ts: {}", data.channel, data.confirmed, data.peak_current, data.time_stamp);
+
+            logs.record(LOG_FIRING, &[
+                &[data.channel.id(), data.confirmed as u8, data.opened as u8],
+                &data.peak_current.to_le_bytes(),
+                &data.conducted.to_le_bytes(),
+                &data.dwell.to_le_bytes(),
+                &data.time_stamp.to_le_bytes(),
+            ]);
+        }
+
+        while let Ok(data) = POWER_CHANNEL.try_receive() {
+            logs.record(LOG_POWER, &[
+                &data.battery_voltage.to_le_bytes(),
+                &data.solar_voltage.to_le_bytes(),
+                &data.solar_current.to_le_bytes(),
+                &[data.charge.id()],
+                &data.time_stamp.to_le_bytes(),
+            ]);
+        }
+
+        while let Ok(data) = HEATER_CHANNEL.try_receive() {
+            logs.record(LOG_HEATER, &[
+                &[data.on as u8, data.held as u8],
+                &data.temperature.to_le_bytes(),
+                &data.period_energy.to_le_bytes(),
+                &data.total_energy.to_le_bytes(),
+                &data.time_stamp.to_le_bytes(),
+            ]);
+        }
+
+        while let Ok(data) = BUS_RECOVERY_CHANNEL.try_receive() {
+            logs.record(LOG_BUS_RECOVERY, &[
+                &data.failures.to_le_bytes(),
+                &[data.clocks, data.released as u8],
+                &data.count.to_le_bytes(),
+                &data.time_stamp.to_le_bytes(),
+            ]);
+        }
+
+        let dropped = core::mem::take(&mut logs.dropped);
+        if dropped > 0 {
+            warn!("sd card fell behind, dropped {} log records", dropped);
+            count_drops(&LOG_DROPPED, dropped as u64);
+            logs.line(now.as_micros() as u32, format_args!("dropped {} log records", dropped));
+        }
+
+        // a partly filled block is padded out once its stream's flush period has run out, then the log storage task is
+        // woken once there is a whole block for it
+        for (stream, flushed) in LogStream::ALL.into_iter().zip(flushed.iter_mut()) {
+            let Some(period) = stream.flush_period() else {
+                continue;
+            };
+            if now.duration_since(*flushed) >= Duration::from_millis(period as u64) {
+                logs.producers[stream.id() as usize].finish_block();
+                *flushed = now;
+            }
+        }
+        if logs.producers.iter().any(|log| log.pending() >= BLOCK_SIZE) {
+            LOG_BLOCK_SIGNAL.signal(());
+        }
+    
+        loop_end(timing);
+
+        // wait state to let other tasks run, poll faster while logging at high rate so channels don't overflow
+        let period = if high_rate_logging() {
+            LOG_HIGH_RATE_PERIOD
+        } else if pad_idle() {
+            LOG_PAD_PERIOD
+        } else {
+            LOG_PERIOD
+        };
+        Timer::after(period).await;
+
+    }
+}
+
+// the log task's side of the stream queues, records go to their stream by tag
+struct LogStreams {
+    producers: [LogProducer; LOG_STREAMS],
+    // cleared to close the log on low voltage or for a reboot, records are dropped without counting while it is
+    open: bool,
+    // records that didn't fit in their stream's queue
+    dropped: u32,
+}
+
+impl LogStreams {
+    fn new(producers: [LogProducer; LOG_STREAMS]) -> Self {
+        Self { producers, open: true, dropped: 0 }
+    }
+
+    fn record(&mut self, tag: u8, fields: &[&[u8]]) {
+        if self.open && !log_record(&mut self.producers[LogStream::for_tag(tag).id() as usize], tag, fields) {
+            self.dropped += 1;
+        }
+    }
+
+    // a line of text to the debug stream, after the time stamp it is about
+    fn line(&mut self, time_stamp: u32, args: core::fmt::Arguments) {
+        if !self.open {
+            return;
+        }
+        let mut text = String::<DEBUG_LINE_LEN>::new();
+        // a line too long for the buffer is cut short
+        let _ = write!(text, "{} {}", time_stamp, args);
+        let len = text.len() + 1;
+        let Ok(mut grant) = self.producers[LogStream::Debug.id() as usize].grant(len) else {
+            self.dropped += 1;
+            return;
+        };
+        grant[..len - 1].copy_from_slice(text.as_bytes());
+        grant[len - 1] = b'\n';
+        grant.commit(len);
+    }
+
+    // pad out every stream's block and wake the storage task to write them
+    fn finish_blocks(&mut self) {
+        for log in self.producers.iter_mut() {
+            log.finish_block();
+        }
+        LOG_BLOCK_SIGNAL.signal(());
+    }
+
+    fn pending(&self) -> usize {
+        self.producers.iter().map(LogProducer::pending).sum()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.producers.iter().all(LogProducer::is_empty)
+    }
+}
+
+// serialize a record straight into the log queue, the tag then each field's bytes, returns false if the queue is full
+// a record that doesn't fit in what is left of the block starts the next one, behind a utc anchor so the uptime time
+// stamps in the block can be converted to real time, and a met anchor after launch so it reads in mission time too
+fn log_record(log: &mut LogProducer, tag: u8, fields: &[&[u8]]) -> bool {
+    let len = record_len(fields);
+    if len > log.block_remaining() || log.block_remaining() == BLOCK_SIZE {
+        log.finish_block();
+        if let Some(sync) = time_sync() {
+            info!("time sync: utc: {}, ts: {}", sync.utc, sync.time_stamp);
+            let sync_fields: [&[u8]; 2] = [&sync.utc.to_le_bytes(), &sync.time_stamp.to_le_bytes()];
+            put_record(log, LOG_TIME_SYNC, &sync_fields, record_len(&sync_fields));
+        }
+        if let Some(launch) = LAUNCH_TIME.lock(|l| l.get()) {
+            let time_stamp = Instant::now().as_micros() as u32;
+            info!("met sync: {}, launch ts: {}, ts: {}", Met::since(launch, time_stamp), launch, time_stamp);
+            let met_fields: [&[u8]; 2] = [&launch.to_le_bytes(), &time_stamp.to_le_bytes()];
+            put_record(log, LOG_MET_SYNC, &met_fields, record_len(&met_fields));
+        }
+    }
+    put_record(log, tag, fields, len)
+}
+
+// bytes a record takes, the tag and its fields
+fn record_len(fields: &[&[u8]]) -> usize {
+    1 + fields.iter().map(|field| field.len()).sum::<usize>()
+}
+
+fn put_record(log: &mut LogProducer, tag: u8, fields: &[&[u8]], len: usize) -> bool {
+    let Ok(mut grant) = log.grant(len) else {
+        return false;
+    };
+    grant[0] = tag;
+    let mut at = 1;
+    for field in fields {
+        grant[at..at + field.len()].copy_from_slice(field);
+        at += field.len();
+    }
+    grant.commit(len);
+    true
+}
+
+// value type (0 f32, 1 u32, 2 i32) and bytes of a config value
+fn config_value_bytes(value: ConfigValue) -> (u8, [u8; 4]) {
+    match value {
+        ConfigValue::F32(v) => (0, v.to_le_bytes()),
+        ConfigValue::U32(v) => (1, v.to_le_bytes()),
+        ConfigValue::I32(v) => (2, v.to_le_bytes()),
+    }
+}
+
+// writes finished log blocks to the board's log device straight out of the log queue, the dma reads them in place
+// the external flash takes the log if the device doesn't come up, it is checked either way so a bad part shows up on
+// the bench, with neither the log goes to rtt only
+#[task]
+async fn log_storage_task(mut device: bsp::LogDevice, mut data_flash: bsp::DataFlash, mut blocks: [LogConsumer; LOG_STREAMS]) {
+    info!("Entered log storage task");
+
+    let flash_ok = match data_flash.init().await {
+        Ok(()) => {
+            let id = data_flash.id();
+            info!("external flash: manufacturer {:#04x}, device {:#04x}{:02x}", id[0], id[1], id[2]);
+            true
+        }
+        Err(e) => {
+            warn!("external flash not responding ({})", e);
+            false
+        }
+    };
+    let boot_count = BOOT_INFO.lock(|b| b.get()).boot_count;
+
+    match bsp::log_device_init(&mut device).await {
+        Ok(()) => write_log(device, boot_count, blocks).await,
+        Err(e) if flash_ok => {
+            error!("no sd card ({}), logging to the external flash", e);
+            write_log(data_flash, boot_count, blocks).await
+        }
+        Err(e) => error!("no sd card ({}), logging to rtt only", e),
+    }
+
+    // nowhere to write, blocks are dropped as they come so the log queues don't back up
+    loop {
+        for stream in blocks.iter_mut() {
+            while let Some(block) = stream.read() {
+                block.release();
+            }
+        }
+        if let Either3::Third(_) = select3(LOG_BLOCK_SIGNAL.wait(), IMAGE_CHANNEL.receive(), LOG_TAIL_SIGNAL.wait()).await {
+            LOG_TAIL_REPLY_SIGNAL.signal(None);
+        }
+    }
+}
+
+// log to a storage device for good, in this boot's regions of it
+async fn write_log<S: LogStorage>(storage: S, boot_count: u32, mut blocks: [LogConsumer; LOG_STREAMS]) -> ! {
+    let layout = BootLayout::new(storage.capacity(), storage.erase_size(), LOG_BOOT_REGIONS, LOG_BLOCKS_PER_BOOT, boot_count);
+    info!("images: {} blocks from {}", layout.images.blocks, layout.images.first);
+    IMAGE_BLOCKS.lock(|b| b.set(layout.images.blocks));
+    let mut log = LogWriter::new(storage, layout, LogStream::SHARES);
+    for stream in LogStream::ALL {
+        let region = log.stream(stream.id() as usize);
+        info!("{}: {} blocks from {}", stream.file_name(), region.blocks, region.first);
+    }
+
+    // logged records the ground asked for, read back a block at a time
+    let mut history: Option<HistoryReader> = None;
+
+    loop {
+        // the events never wait behind a run of imu blocks, a block that can't be written is dropped
+        log.drain(&mut blocks, |address, e| warn!("log write of block {} failed: {}", address, e)).await;
+        // the queue is empty, get what was written onto the medium before waiting for more
+        if let Err(e) = log.sync().await {
+            warn!("log sync failed: {}", e);
+        }
+
+        // a new request replaces one still being read, what is still queued from it is dropped
+        if let Some(request) = HISTORY_SIGNAL.try_take() {
+            HISTORY_CHANNEL.clear();
+            history = Some(HistoryReader::new(request));
+        }
+        // a console's log tail is a few blocks at most, it goes in between log blocks too
+        if let Some((stream, count)) = LOG_TAIL_SIGNAL.try_take() {
+            log_tail(&mut log, stream, count).await;
+        }
+
+        // history reads go in between log blocks like camera blocks, and only as fast as the radio takes the records
+        // a full queue is looked at again with the next log block, the imu fills one several times a second
+        if let Some(reader) = history.as_mut()
+            && !HISTORY_CHANNEL.is_full()
+        {
+            if let Ok((offset, block)) = IMAGE_CHANNEL.try_receive() {
+                write_image(&mut log, offset, &block).await;
+            }
+            match reader.next(&mut log).await {
+                Ok(Some(record)) => {
+                    HISTORY_CHANNEL.send(record).await;
+                }
+                Ok(None) => {}
+                // the block is skipped, the next read moves on past it
+                Err(e) => warn!("history read failed: {}", e),
+            }
+            if reader.is_done() {
+                info!("history of {} read back", reader.request().stream);
+                history = None;
+            }
+            continue;
+        }
+
+        // camera blocks go in between log blocks, the log comes first
+        match select4(LOG_BLOCK_SIGNAL.wait(), IMAGE_CHANNEL.receive(), HISTORY_SIGNAL.wait(), LOG_TAIL_SIGNAL.wait()).await {
+            Either4::First(()) => {}
+            Either4::Second((offset, block)) => write_image(&mut log, offset, &block).await,
+            Either4::Third(request) => {
+                HISTORY_CHANNEL.clear();
+                history = Some(HistoryReader::new(request));
+            }
+            Either4::Fourth((stream, count)) => log_tail(&mut log, stream, count).await,
+        }
+    }
+}
+
+// each camera block knows its place in the image region, so one that can't be written leaves a hole rather than
+// moving the rest
+async fn write_image<S: LogStorage>(log: &mut LogWriter<S, LOG_STREAMS>, offset: u32, block: &[u8]) {
+    if let Err(e) = log.write_image(offset, block).await {
+        warn!("write of image block {} failed: {}", offset, e);
+    }
+}
+
+// read back the last records of a stream for a console, a failed read answers with none rather than leaving it waiting
+async fn log_tail<S: LogStorage>(log: &mut LogWriter<S, LOG_STREAMS>, stream: LogStream, count: u8) {
+    let records = match history::tail(log, stream, count as usize).await {
+        Ok(records) => Some(records),
+        Err(e) => {
+            warn!("log tail of {} failed: {}", stream, e);
+            None
+        }
+    };
+    LOG_TAIL_REPLY_SIGNAL.signal(records);
+}
+
+// register the calling task with the watchdog, it must then check in within the deadline or the board resets
+fn watchdog_register(name: &'static str, deadline: Duration) -> TaskId {
+    let now = Instant::now().as_micros() as u32;
+    WATCHDOG.lock(|w| w.borrow_mut().register(name, deadline.as_micros() as u32, now)).unwrap()
+}
+
+fn watchdog_check_in(id: TaskId) {
+    let now = Instant::now().as_micros() as u32;
+    WATCHDOG.lock(|w| w.borrow_mut().check_in(id, now));
+}
+
+// register a task loop for timing metrics, period is None for loops without a fixed rate
+fn loop_register(name: &'static str, period: Option<Duration>) -> LoopId {
+    let period = period.map(|p| p.as_micros() as u32);
+    LOOP_TIMINGS.lock(|t| t.borrow_mut().register(name, period)).unwrap()
+}
+
+fn loop_start(id: LoopId) {
+    let now = Instant::now().as_micros() as u32;
+    LOOP_TIMINGS.lock(|t| t.borrow_mut().start(id, now));
+}
+
+fn loop_set_period(id: LoopId, period: Option<Duration>) {
+    let period = period.map(|p| p.as_micros() as u32);
+    LOOP_TIMINGS.lock(|t| t.borrow_mut().set_period(id, period));
+}
+
+// executor trace hooks, every task poll on either executor is timed with the cycle counter for the cpu load, the
+// rest of the trace events aren't used
+#[unsafe(no_mangle)]
+fn _embassy_trace_task_exec_begin(_executor_id: u32, _task_id: u32) {
+    BUSY_TIME.lock(|b| b.borrow_mut().begin(DWT::cycle_count()));
+}
+
+#[unsafe(no_mangle)]
+fn _embassy_trace_task_exec_end(_executor_id: u32, _task_id: u32) {
+    BUSY_TIME.lock(|b| b.borrow_mut().end(DWT::cycle_count()));
+}
+
+#[unsafe(no_mangle)]
+fn _embassy_trace_poll_start(_executor_id: u32) {}
+
+#[unsafe(no_mangle)]
+fn _embassy_trace_executor_idle(_executor_id: u32) {}
+
+#[unsafe(no_mangle)]
+fn _embassy_trace_task_new(_executor_id: u32, _task_id: u32) {}
+
+#[unsafe(no_mangle)]
+fn _embassy_trace_task_end(_executor_id: u32, _task_id: u32) {}
+
+#[unsafe(no_mangle)]
+fn _embassy_trace_task_ready_begin(_executor_id: u32, _task_id: u32) {}
+
+// mark the end of the loop body, warns when it ran longer than the loop period
+fn loop_end(id: LoopId) {
+    let now = Instant::now().as_micros() as u32;
+    let overrun = LOOP_TIMINGS.lock(|t| {
+        let mut timings = t.borrow_mut();
+        timings.end(id, now).map(|execution| (timings.name(id), execution))
+    });
+    if let Some((name, execution)) = overrun {
+        warn!("{} loop overran its period: {} us", name, execution);
+    }
+}
+
+// watchdog feeder task, only pets the independent watchdog while every registered task is checking in
+// a hung task stops the feeding and the watchdog resets the board
+#[task]
+async fn watchdog_task(mut wdg: IndependentWatchdog<'static, IWDG>) {
+    info!("Starting watchdog task");
+
+    wdg.unleash();
+    let mut was_healthy = true;
+    // an updated image on trial is confirmed after running healthy for a while, a reset clears this
+    let mut healthy_since = Some(Instant::now());
+
+    loop {
+        let now = Instant::now().as_micros() as u32;
+        match WATCHDOG.lock(|w| w.borrow().overdue(now)) {
+            None => {
+                wdg.pet();
+                was_healthy = true;
+
+                if healthy_since.is_some_and(|t| t.elapsed() >= UPDATE_CONFIRM_AFTER) {
+                    let mut update = unsafe { core::ptr::read_volatile(UPDATE_RECORD) };
+                    if update.confirm() {
+                        unsafe { core::ptr::write_volatile(UPDATE_RECORD, update) };
+                        info!("updated firmware confirmed");
+                    }
+                    healthy_since = None;
+                }
+            }
+            Some(name) => {
+                // an image on trial is only confirmed after an unbroken healthy stretch
+                if healthy_since.is_some() {
+                    healthy_since = Some(Instant::now());
+                }
+                if was_healthy {
+                    error!("{} task missed its watchdog check in, withholding feed", name);
+                    report_fault(Fault::Watchdog, true);
+                }
+                was_healthy = false;
+            }
+        }
+
+        Timer::after(WATCHDOG_FEED_PERIOD).await;
+    }
+}
+
+// note a new sample from a data stream for the supervisor
+fn stream_seen(stream: Stream, time_stamp: u32) {
+    STREAM_MONITOR.lock(|m| m.borrow_mut().record(stream, time_stamp));
+}
+
+// housekeeping task, runs the slow periodic jobs in HOUSEKEEPING_SCHEDULE one at a time, the highest priority of
+// those due first, each job keeps its own state between runs
+#[task]
+async fn housekeeping_task(battery: bsp::Battery, charger: bsp::Charger, rails: bsp::Rails, heater: Output<'static>) {
+    info!("Starting housekeeping task");
+
+    let mut scheduler = Scheduler::new(HOUSEKEEPING_SCHEDULE);
+    let mut skipped = [0; HOUSEKEEPING_SCHEDULE.len()];
+    let mut power = PowerMonitor::new(battery);
+    let mut rails = RailControl::new(rails);
+    let mut supervisor = Supervisor::new();
+    let mut heater = Heater::new(heater);
+    let mut loads = LoadManager::new();
+    let mut charger = ChargerMonitor::new(charger);
+
+    loop {
+        let now = Instant::now().as_micros() as u32;
+        let Some(job) = scheduler.next(now) else {
+            Timer::after(Duration::from_micros(scheduler.wait(now) as u64)).await;
+            continue;
+        };
+
+        // a job waiting behind a slow one runs late once rather than catching up run after run
+        let job_skipped = scheduler.skipped(job);
+        if job_skipped != skipped[job as usize] {
+            warn!("{} job fell behind, {} periods skipped since boot", job, job_skipped);
+            skipped[job as usize] = job_skipped;
+        }
+
+        match job {
+            Housekeeping::Power => power.run().await,
+            Housekeeping::Rails => rails.run(),
+            Housekeeping::Health => supervisor.run().await,
+            Housekeeping::Heater => heater.run().await,
+            Housekeeping::LoadShed => loads.run(),
+            Housekeeping::PowerReport => charger.run().await,
+        }
+    }
+}
+
+// supervisor job, flags data streams that stop producing so consumers don't silently act on old data
+// downlinks a health report with the active faults and recent fault events periodically and whenever they change
+// each report closes a timing window, the cpu load and per loop timings over it are logged and the load downlinked
+// the stack high water mark is checked at the same time and written to the sd card whenever it grows
+struct Supervisor {
+    prev_faults: FaultFlags,
+    last_report: Instant,
+    stack_high_water: u32,
+    prev_dropped: DropCounts,
+}
+
+impl Supervisor {
+    fn new() -> Self {
+        Self {
+            prev_faults: FaultFlags::NONE,
+            last_report: Instant::now(),
+            stack_high_water: 0,
+            prev_dropped: DropCounts::default(),
+        }
+    }
+
+    async fn run(&mut self) {
+        let now = Instant::now().as_micros() as u32;
+        let stale = STREAM_MONITOR.lock(|m| m.borrow_mut().check(now));
+        for stream in Stream::ALL {
+            let fault = Fault::Stale(stream);
+            report_fault(fault, stale.contains(fault));
+        }
+
+        // a hung control loop can't fire anything it should and might fire something it shouldn't, so the outputs go
+        // to the safe state: fire requests dropped and outputs held off, the beacon on, and the heaters, which have no
+        // software control beyond load shedding, left on their thermostat, the fault event marks the takeover in the log
+        let missed = CONTROL_LOOP.lock(|c| c.get()).map_or(0, |id| LOOP_TIMINGS.lock(|t| t.borrow().missed(id, now)));
+        let hung = missed >= CONTROL_HANG_PERIODS;
+        if hung != SAFE_STATE.lock(|s| s.replace(hung)) {
+            if hung {
+                error!("control loop missed {} periods, taking outputs to the safe state", missed);
+            } else {
+                info!("control loop keeping up again, leaving the safe state");
+            }
+            report_fault(Fault::ControlHang, hung);
+        }
+
+        let (faults, recent) = FAULT_LOG.lock(|f| {
+            let log = f.borrow();
+            (log.flags(), log.recent::<HEALTH_RECENT_FAULTS>())
+        });
+        let report_period = Duration::from_secs(CONFIG.lock(|c| c.get()).health_report_period as u64);
+        if faults == self.prev_faults && self.last_report.elapsed() < report_period {
+            return;
+        }
+        self.prev_faults = faults;
+        self.last_report = Instant::now();
+
+        let (cpu_load, loop_overruns) = LOOP_TIMINGS.lock(|t| {
+            let mut timings = t.borrow_mut();
+            let busy = BUSY_TIME.lock(|b| b.borrow_mut().take(DWT::cycle_count())) / (bsp::core_hz() / 1_000_000) as u64;
+            let load = timings.cpu_load(busy, now);
+            for (name, stats) in timings.stats() {
+                info!("{} loop: {} iterations, busy: {} us, max execution: {} us, max jitter: {} us, overruns: {}",
+                    name, stats.iterations, stats.busy, stats.max_execution, stats.max_jitter, stats.overruns);
+            }
+            let overruns = timings.overruns();
+            timings.reset(now);
+            (load, overruns)
+        });
+        info!("cpu load: {}%", cpu_load);
+
+        let (stack_used, stack_size) = stack_usage();
+        info!("stack: {} of {} bytes used", stack_used, stack_size);
+        if stack_used > self.stack_high_water {
+            self.stack_high_water = stack_used;
+            if stack_used * 100 > stack_size * STACK_WARN_PERCENT {
+                warn!("stack high water mark at {} of {} bytes", stack_used, stack_size);
+            }
+
+            let usage = StackUsage { used: stack_used, size: stack_size, time_stamp: now };
+            STACK_USAGE_CHANNEL.send(usage).await;
+        }
+
+        // a growing count means a consumer can't keep up, the log gets a record so it shows where the gaps came from
+        let dropped = drop_counts();
+        if dropped != self.prev_dropped {
+            warn!("dropped since boot: {}", dropped);
+            self.prev_dropped = dropped;
+            DROP_COUNTS_CHANNEL.send((dropped, now)).await;
+        }
+
+        let report = HealthReport {
+            faults,
+            recent,
+            cpu_load: cpu_load.clamp(0.0, 100.0) as u8,
+            loop_overruns,
+            dropped,
+            continuity: ACTUATION.lock(|a| a.borrow().continuity()),
+            armed: ACTUATION.lock(|a| a.borrow().armed()),
+            antenna: ANTENNA.lock(|a| a.get()),
+            // the same stand in for the battery temperature as the cold protection
+            battery_temperature: LATEST_BARO.lock(|b| b.get()).map_or(f32::NAN, |baro| baro.temperature),
+            time_stamp: now,
+        };
+        TELEMETRY_CHANNEL.send(Telemetry::Health(report)).await;
+    }
+}
+
+// add to a drop counter, saturating, a count that wraps back to small would hide the problem
+fn count_drops(counter: &Mutex<ThreadModeRawMutex, Cell<u32>>, dropped: u64) {
+    counter.lock(|c| c.set(c.get().saturating_add(dropped.min(u32::MAX as u64) as u32)));
+}
+
+// items lost since boot, per stream, the gps count covers its log, nav, and wind channels
+fn drop_counts() -> DropCounts {
+    DropCounts {
+        baro: BARO_DATA_CHANNEL.dropped(),
+        imu: IMU_DROPPED.lock(|d| d.get()),
+        gps: GPS_DATA_CHANNEL.dropped().saturating_add(NAV_GPS_CHANNEL.dropped()).saturating_add(WIND_GPS_CHANNEL.dropped()),
+        log: LOG_DROPPED.lock(|d| d.get()),
+        telemetry: TELEMETRY_CHANNEL.dropped(),
+    }
+}
+
+// stack bounds from the cortex-m-rt linker script, the stack grows down from _stack_start towards _stack_end
+unsafe extern "C" {
+    static _stack_start: u32;
+    static _stack_end: u32;
+}
+
+// stack size and deepest use since boot in bytes
+// cortex-m-rt paints the stack at startup, scanning up from the bottom for the first overwritten word finds the deepest point
+fn stack_usage() -> (u32, u32) {
+    let bottom = &raw const _stack_end as usize;
+    let top = &raw const _stack_start as usize;
+
+    let mut addr = bottom;
+    while addr < top && unsafe { core::ptr::read_volatile(addr as *const u32) } == cortex_m_rt::STACK_PAINT_VALUE {
+        addr += 4;
+    }
+    ((top - addr) as u32, (top - bottom) as u32)
+}
+
+// supply voltage job, on sustained low voltage closes the log file and cuts the radio down to a
+// position beacon, so a dying battery doesn't corrupt the sd card or run out before the payload is found
+// also watches the battery temperature and holds off high current loads while it's below the cold limit
+struct PowerMonitor {
+    battery: bsp::Battery,
+    detector: LowVoltageDetector,
+    cold: ColdProtection,
+    last_beacon: Option<Instant>,
+}
+
+impl PowerMonitor {
+    fn new(battery: bsp::Battery) -> Self {
+        Self {
+            battery,
+            detector: LowVoltageDetector::new(LOW_VOLTAGE, RECOVERED_VOLTAGE, LOW_VOLTAGE_DURATION.as_micros() as u32),
+            cold: ColdProtection::new(BATTERY_COLD_HYSTERESIS),
+            last_beacon: None,
+        }
+    }
+
+    async fn run(&mut self) {
+        let time_stamp = Instant::now().as_micros() as u32;
+
+        // the battery has no sensor of its own, the barometer shares its insulated box and stands in for it
+        let limit = CONFIG.lock(|c| c.get()).battery_cold_limit;
+        if let Some(baro) = LATEST_BARO.lock(|b| b.get())
+            && let Some(active) = self.cold.update(baro.temperature, limit)
+        {
+            if active {
+                warn!("battery at {} C, below {} C, holding off high current loads", baro.temperature, limit);
+            } else {
+                info!("battery at {} C, warm again", baro.temperature);
+            }
+            BATTERY_COLD.lock(|c| c.set(active));
+            report_fault(Fault::BatteryCold, active);
+        }
+
+        let voltage = match self.battery.read().await {
+            Ok(voltage) => voltage,
+            Err(e) => {
+                warn!("battery read failed: {}", e);
+                return;
+            }
+        };
+
+        LATEST_VOLTAGE.lock(|v| v.set(Some(voltage)));
+
+        match self.detector.update(voltage, time_stamp) {
+            Some(true) => {
+                error!("supply voltage low: {} V, entering safe mode", voltage);
+                LOW_POWER.lock(|l| l.set(true));
+                report_fault(Fault::LowVoltage, true);
+                self.last_beacon = None;
+            }
+            Some(false) => {
+                info!("supply voltage recovered: {} V, leaving safe mode", voltage);
+                LOW_POWER.lock(|l| l.set(false));
+                report_fault(Fault::LowVoltage, false);
+            }
+            None => {}
+        }
+
+        // the safe state beacons too, a hung control loop may well be followed by a lost payload
+        if !(self.detector.active() || SAFE_STATE.lock(|s| s.get())) || self.last_beacon.is_some_and(|t| t.elapsed() < BEACON_PERIOD) {
+            return;
+        }
+        let Some(position) = LATEST_POSITION.lock(|p| p.get()) else {
+            return;
+        };
+
+        let beacon = Beacon {
+            latitude: position.latitude,
+            longitude: position.longitude,
+            altitude: position.altitude,
+            voltage,
+            time_stamp,
+        };
+        TELEMETRY_CHANNEL.send(Telemetry::Beacon(beacon)).await;
+        self.last_beacon = Some(Instant::now());
+    }
+}
+
+// solar charger job, reads the array side of the charger every report period and sends it down and to the log with
+// the battery voltage, on a long float the array current against the battery trend says whether the array keeps up
+struct ChargerMonitor {
+    charger: bsp::Charger,
+    charge: Option<ChargeState>,
+}
+
+impl ChargerMonitor {
+    fn new(charger: bsp::Charger) -> Self {
+        Self { charger, charge: None }
+    }
+
+    async fn run(&mut self) {
+        let input = match self.charger.read().await {
+            Ok(input) => input,
+            Err(e) => {
+                warn!("charger read failed: {}", e);
+                return;
+            }
+        };
+        if self.charge.replace(input.state) != Some(input.state) {
+            info!("charger: {}, array at {} V, {} A", input.state, input.voltage, input.current);
+        }
+
+        let power = PowerData {
+            battery_voltage: LATEST_VOLTAGE.lock(|v| v.get()).unwrap_or(f32::NAN),
+            solar_voltage: input.voltage,
+            solar_current: input.current,
+            charge: input.state,
+            time_stamp: Instant::now().as_micros() as u32,
+        };
+        LATEST_POWER.lock(|p| p.set(Some(power)));
+        POWER_CHANNEL.send(power).await;
+        TELEMETRY_CHANNEL.send(Telemetry::Power(power)).await;
+    }
+}
+
+// payload rail job, brings the rails up one at a time in the configured order after boot, then switches each off
+// while one of its shutdown faults is active and back on once they clear, every switch marks the log
+struct RailControl {
+    rails: bsp::Rails,
+    sequencer: RailSequencer,
+}
+
+impl RailControl {
+    fn new(rails: bsp::Rails) -> Self {
+        // the delays are only used on the way up, a config change takes effect at the next boot
+        let config = CONFIG.lock(|c| c.get());
+        let delays = [config.gnc_rail_delay, config.radio_pa_rail_delay, config.camera_rail_delay].map(|ms| ms * 1000);
+        Self { rails, sequencer: RailSequencer::new(delays, RAIL_SHUTDOWN_FAULTS) }
+    }
+
+    fn run(&mut self) {
+        let time_stamp = Instant::now().as_micros() as u32;
+        let faults = FAULT_LOG.lock(|f| f.borrow().flags());
+        let held = RAILS_HELD.lock(|h| h.get());
+        let disabled = RAILS_DISABLED.lock(|d| d.get());
+        self.sequencer.hold(held);
+        self.sequencer.disable(disabled);
+        // one rail per run, the rest follow on the next ones
+        let Some((rail, on)) = self.sequencer.update(faults, time_stamp) else {
+            return;
+        };
+        self.rails.set(rail, on);
+        RAILS.lock(|r| r.set(self.sequencer.on()));
+        let event = if on {
+            info!("rail {} on", rail.name());
+            FlightEvent::RailOn(rail)
+        } else if held {
+            info!("rail {} off for sleep", rail.name());
+            FlightEvent::RailOff(rail)
+        } else if disabled.contains(rail) {
+            info!("rail {} off, disabled by mission sequence", rail.name());
+            FlightEvent::RailOff(rail)
+        } else {
+            warn!("rail {} off, shutdown fault active", rail.name());
+            FlightEvent::RailOff(rail)
+        };
+        publish_event(event, time_stamp);
+    }
+}
+
+// load management job, switches loads off in priority order as the battery state of charge falls and back on
+// as it recovers, so the flight critical systems keep running as long as possible, each decision marks the log
+struct LoadManager {
+    // radio transmissions sag the voltage for a moment, average over a few seconds before judging the charge
+    voltage_average: MovingAverage<LOAD_SHED_AVERAGE_WINDOW>,
+}
+
+impl LoadManager {
+    fn new() -> Self {
+        Self { voltage_average: MovingAverage::new() }
+    }
+
+    fn run(&mut self) {
+        let Some(voltage) = LATEST_VOLTAGE.lock(|v| v.get()) else {
+            return;
+        };
+        self.voltage_average.update(voltage);
+        if !self.voltage_average.is_full() {
+            return;
+        }
+
+        let soc = power::state_of_charge(self.voltage_average.value(), BATTERY_CELLS);
+        let Some((load, enabled)) = LOAD_SHEDDER.lock(|l| l.borrow_mut().update(soc)) else {
+            return;
+        };
+
+        // switch the load here
+        let event = if enabled {
+            info!("battery at {}%, restoring {}", soc, load);
+            FlightEvent::LoadRestored(load)
+        } else {
+            warn!("battery at {}%, shedding {}", soc, load);
+            FlightEvent::LoadShed(load)
+        };
+
+        publish_event(event, Instant::now().as_micros() as u32);
+    }
+}
+
+// duty cycle task, once a float's awake window is over it switches the payload rails off, lets the queued telemetry
+// go down, and sleeps in stop mode for the rest of the duty cycle period, the rtc wakes it to sample, log and beacon
+// for another window, the rails come back up in their usual sequence
+#[task]
+async fn sleep_task() {
+    info!("Starting sleep task");
+
+    loop {
+        Timer::after(DUTY_CYCLE_PERIOD).await;
+
+        let config = CONFIG.lock(|c| c.get());
+        let input = DutyInput {
+            state: FLIGHT_STATE.lock(|s| s.get()),
+            vertical_speed: VERTICAL_STATE_WATCH.try_get().map(|v| v.vertical_speed),
+            busy: !FIRE_CHANNEL.is_empty() || !COMMAND_CHANNEL.is_empty() || SAFE_STATE.lock(|s| s.get()),
+            wake_by: PENDING.lock(|p| p.borrow().until_met()),
+            time_stamp: Instant::now().as_micros() as u32,
+        };
+        let sleep = DUTY_CYCLE.lock(|d| {
+            let mut duty_cycle = d.borrow_mut();
+            duty_cycle.configure(config.duty_cycle_period, config.duty_cycle_awake);
+            duty_cycle.update(&input)
+        });
+        let Some(seconds) = sleep else {
+            continue;
+        };
+
+        info!("awake window over, sleeping {} s", seconds);
+        publish_event(FlightEvent::Sleep, Instant::now().as_micros() as u32);
+        // the radio's amplifier is one of the rails, so the queued telemetry goes down before they are switched off
+        let drained = async {
+            while !TELEMETRY_CHANNEL.is_empty() {
+                Timer::after(DUTY_CYCLE_PERIOD).await;
+            }
+        };
+        if drained.with_timeout(SLEEP_DRAIN_TIMEOUT).await.is_err() {
+            warn!("telemetry still queued, sleeping anyway");
+        }
+        RAILS_HELD.lock(|h| h.set(true));
+        let rails_off = async {
+            while RAILS.lock(|r| r.get()) != RailFlags::NONE {
+                Timer::after(DUTY_CYCLE_PERIOD).await;
+            }
+        };
+        if rails_off.with_timeout(RAILS_OFF_TIMEOUT).await.is_err() {
+            warn!("rails still on, sleeping anyway");
+        }
+
+        // every task stands still until this returns
+        bsp::stop(seconds, (WATCHDOG_TIMEOUT.as_secs() / 2) as u32);
+
+        // mission elapsed time counts from an uptime time stamp, move launch back by the sleep the uptime didn't see
+        let time_stamp = Instant::now().as_micros() as u32;
+        LAUNCH_TIME.lock(|l| l.set(l.get().map(|launch| launch.wrapping_sub((seconds as u64 * 1_000_000) as u32))));
+        SLEEPS.lock(|s| s.set(s.get().wrapping_add(1)));
+        DUTY_CYCLE.lock(|d| d.borrow_mut().stay_awake(time_stamp));
+        RAILS_HELD.lock(|h| h.set(false));
+        info!("awake after {} s", seconds);
+        publish_event(FlightEvent::Wake, time_stamp);
+    }
+}
+
+// battery heater job, runs the heater on a thermostat within its hourly energy budget and logs the energy it has
+// used every report period, and whenever the budget or load shedding starts or stops holding it off
+struct Heater {
+    heater: Output<'static>,
+    controller: HeaterController,
+    last_report: Option<Instant>,
+    held: bool,
+}
+
+impl Heater {
+    fn new(heater: Output<'static>) -> Self {
+        Self {
+            heater,
+            controller: HeaterController::new(HEATER_ON_BELOW, HEATER_OFF_ABOVE, HEATER_POWER, HEATER_BUDGET),
+            last_report: None,
+            held: false,
+        }
+    }
+
+    async fn run(&mut self) {
+        let time_stamp = Instant::now().as_micros() as u32;
+        // the same stand in for the battery temperature as the cold protection
+        let temperature = LATEST_BARO.lock(|b| b.get()).map_or(f32::NAN, |baro| baro.temperature);
+        let on = self.controller.update(temperature, load_enabled(Load::Heaters), time_stamp);
+        self.heater.set_level(if on { Level::High } else { Level::Low });
+
+        let report = self.controller.report(temperature, time_stamp);
+        LATEST_HEATER.lock(|h| h.set(Some(report)));
+
+        if report.held != self.held {
+            self.held = report.held;
+            if self.held {
+                warn!("battery heater held off at {} C, {} J used this hour", temperature, report.period_energy);
+            } else {
+                info!("battery heater no longer held off");
+            }
+        } else if self.last_report.is_some_and(|t| t.elapsed() < HEATER_REPORT_PERIOD) {
+            return;
+        }
+        self.last_report = Some(Instant::now());
+        HEATER_CHANNEL.send(report).await;
+    }
+}
+
+// actuation task, reads the continuity sense line of every channel and reruns the preflight check when one changes
+// an armed channel that loses continuity raises a fault, it stays armed
+// fires the channels the control task sends it, watching the current through the load to confirm it fired
+#[task]
+async fn actuation_task(mut actuators: bsp::Actuators) {
+    info!("Starting actuation task");
+
+    loop {
+        // the safe state takes the outputs from whatever was requested before the control loop hung
+        if SAFE_STATE.lock(|s| s.get()) {
+            while let Ok(channel) = FIRE_CHANNEL.try_receive() {
+                warn!("{} fire dropped, outputs in the safe state", channel.name());
+            }
+            for channel in Channel::ALL {
+                if let Err(e) = actuators.set_output(channel, false).await {
+                    error!("{} output failed to switch off: {}", channel.name(), e);
+                }
+            }
+        }
+
+        while let Ok(channel) = FIRE_CHANNEL.try_receive() {
+            let report = fire(&mut actuators, channel).await;
+            if report.confirmed {
+                info!("{} fired and confirmed: peak {} A, {} us conducting", channel.name(), report.peak_current, report.conducted);
+            } else {
+                warn!("{} fired, no current detected: peak {} A", channel.name(), report.peak_current);
+            }
+            ACTUATION.lock(|a| a.borrow_mut().set_fired(channel));
+            save_resume_record();
+            publish_event(FlightEvent::ChannelFired { channel, confirmed: report.confirmed }, report.time_stamp);
+            FIRING_CHANNEL.send(report).await;
+        }
+
+        for channel in Channel::ALL {
+            let volts = match actuators.continuity(channel).await {
+                Ok(volts) => volts,
+                Err(e) => {
+                    warn!("{} continuity read failed: {}", channel.name(), e);
+                    continue;
+                }
+            };
+            if ACTUATION.lock(|a| a.borrow_mut().sense(channel, volts)) {
+                info!("{} continuity: {} ({} V)", channel.name(), volts <= CONTINUITY_MAX_VOLTS, volts);
+                PREFLIGHT_SIGNAL.signal(());
+            }
+        }
+        report_fault(Fault::Continuity, !ACTUATION.lock(|a| a.borrow().lost()).is_empty());
+
+        // a fire command cuts the wait short
+        FIRE_CHANNEL.ready_to_receive().with_timeout(ACTUATION_PERIOD).await.ok();
+    }
+}
+
+// switch a channel on for the dwell, sampling the current through its load, then off again whatever happened
+async fn fire(actuators: &mut bsp::Actuators, channel: Channel) -> FireReport {
+    let start = Instant::now();
+    let mut monitor = FireMonitor::new(channel, start.as_micros() as u32);
+    if let Err(e) = actuators.set_output(channel, true).await {
+        error!("{} output failed to switch on: {}", channel.name(), e);
+    }
+
+    let mut ticker = Ticker::every(FIRE_SAMPLE_PERIOD);
+    while start.elapsed() < FIRE_DWELL {
+        ticker.next().await;
+        match actuators.current(channel).await {
+            Ok(amps) => monitor.update(amps, Instant::now().as_micros() as u32),
+            Err(e) => warn!("{} current read failed: {}", channel.name(), e),
+        }
+    }
+
+    if let Err(e) = actuators.set_output(channel, false).await {
+        error!("{} output failed to switch off: {}", channel.name(), e);
+    }
+    monitor.finish(Instant::now().as_micros() as u32)
+}
+
+// sensor bus task, clears the i2c bus when its transfers keep failing so one part stuck part way through a transfer
+// doesn't take every sensor on the bus down with it, the tasks on the bus set their parts up again afterwards
+#[task]
+async fn sensor_bus_task(sensor_bus: bsp::SensorBus) {
+    info!("Starting sensor bus task");
+
+    let mut count = 0u16;
+    loop {
+        let failures = sensor_bus.fault().await;
+        warn!("sensor bus: {} failed transfers in a row, clearing the bus", failures);
+        let (clocks, released) = sensor_bus.recover().await;
+        count = count.saturating_add(1);
+        let time_stamp = Instant::now().as_micros() as u32;
+        if released {
+            info!("sensor bus: free after {} clocks, clear {}", clocks, count);
+        } else {
+            error!("sensor bus: still stuck after {} clocks, clear {}", clocks, count);
+        }
+
+        SENSOR_BUS_CLEARS.lock(|c| c.set(count));
+        BUS_RECOVERY_CHANNEL.send(BusRecovery { failures, clocks, released, count, time_stamp }).await;
+    }
+}
+
+// true once for every sensor bus clear since the caller last looked, seen is the clear count it last saw
+fn sensor_bus_cleared(seen: &mut u16) -> bool {
+    let clears = SENSOR_BUS_CLEARS.lock(|c| c.get());
+    let cleared = clears != *seen;
+    *seen = clears;
+    cleared
+}
+
+// payload camera task, takes a still every camera period into this boot's image region on the card and marks the
+// log with a capture event, every few stills it also takes a thumbnail for the radio to send down as ssdv
+// nothing is taken while the camera load is shed or in low voltage safe mode
+#[task]
+async fn camera_task(mut camera: bsp::Camera) {
+    info!("Starting camera task");
+
+    let mut number = 0u32;
+    // next free block of the image region
+    let mut next_block = 0u32;
+    let mut bus_clears = 0;
+    // the camera's rail was on at the last capture, it loses its setup whenever the rail goes off
+    let mut powered = false;
+
+    loop {
+        let config = CONFIG.lock(|c| c.get());
+        Timer::after(Duration::from_secs(config.camera_period.max(1) as u64)).await;
+        if !rail_on(Rail::Camera) {
+            powered = false;
+            continue;
+        }
+        if config.camera_period == 0 || !load_enabled(Load::Camera) || LOW_POWER.lock(|l| l.get()) {
+            continue;
+        }
+
+        if sensor_bus_cleared(&mut bus_clears) || !powered {
+            camera.reinit();
+        }
+        powered = true;
+
+        let time_stamp = Instant::now().as_micros() as u32;
+        let utc = time_sync().map(|sync| sync.utc);
+        let len = match camera.capture(ImageSize::Full).await {
+            Ok(len) => len,
+            Err(e) => {
+                warn!("camera: capture failed: {}", e);
+                continue;
+            }
+        };
+        let header = ImageHeader { number, time_stamp, utc, len };
+        if next_block + header.blocks() as u32 > IMAGE_BLOCKS.lock(|b| b.get()) {
+            warn!("camera: image region full, still {} not stored", number);
+            continue;
+        }
+        // the blocks are taken before the read, a still cut short leaves the rest of them as a hole
+        let first_block = next_block;
+        next_block += header.blocks() as u32;
+        if let Err(e) = store_image(&mut camera, &header, first_block).await {
+            warn!("camera: still {} cut short: {}", number, e);
+        }
+        info!("camera: still {}, {} bytes, ts: {}", number, len, time_stamp);
+        publish_event(FlightEvent::ImageCaptured, time_stamp);
+
+        #[cfg(not(feature = "mavlink"))]
+        if config.thumbnail_every > 0 && number.is_multiple_of(config.thumbnail_every) {
+            match load_thumbnail(&mut camera).await {
+                Ok(true) => THUMBNAIL_SIGNAL.signal(number as u8),
+                Ok(false) => warn!("camera: thumbnail too long for the radio"),
+                Err(e) => warn!("camera: thumbnail capture failed: {}", e),
+            }
+        }
+        number += 1;
+    }
+}
+
+// gimbal task, points the camera with the two gimbal servos from the latest attitude every servo frame, when the
+// config turns the gimbal on, the servos are let go while it is off and in low voltage safe mode
+#[task]
+async fn gimbal_task(mut servos: bsp::Servos) {
+    info!("Starting gimbal task");
+
+    let mut controller = GimbalController::new(GIMBAL_LIMIT, GIMBAL_MAX_RATE);
+    let mut mode = GimbalMode::Off;
+    servos.off();
+
+    loop {
+        Timer::after(GIMBAL_PERIOD).await;
+
+        let wanted = if LOW_POWER.lock(|l| l.get()) {
+            GimbalMode::Off
+        } else {
+            GimbalMode::from_u32(CONFIG.lock(|c| c.get()).gimbal_mode)
+        };
+        if wanted != mode {
+            mode = wanted;
+            info!("gimbal: {}", mode);
+        }
+
+        let (Some(target), Some(attitude)) = (mode.target(sun_position()), ATTITUDE_WATCH.try_get()) else {
+            servos.off();
+            continue;
+        };
+        let [roll, pitch] = controller.update(&attitude, target);
+        servos.set([gimbal::pulse_width(roll), gimbal::pulse_width(pitch)]);
+    }
+}
+
+// read the captured still out of the camera into the image region a block at a time, the header in front of it
+async fn store_image(camera: &mut bsp::Camera, header: &ImageHeader, first_block: u32) -> Result<(), <bsp::Camera as Camera>::Error> {
+    let mut block = [PAD; BLOCK_SIZE];
+    block[..imagelog::HEADER_LEN].copy_from_slice(&header.to_bytes());
+    let mut at = imagelog::HEADER_LEN;
+    let mut offset = first_block;
+    loop {
+        let len = camera.read(&mut block[at..]).await?;
+        at += len;
+        if at == BLOCK_SIZE || (len == 0 && at > 0) {
+            block[at..].fill(PAD);
+            IMAGE_CHANNEL.send((offset, block)).await;
+            offset += 1;
+            at = 0;
+        }
+        if len == 0 {
+            return Ok(());
+        }
+    }
+}
+
+// capture a thumbnail into the radio's thumbnail buffer, false if it is too long for it
+// a thumbnail still going down is cut off, the radio moves on to this one at its next image slot
+#[cfg(not(feature = "mavlink"))]
+async fn load_thumbnail(camera: &mut bsp::Camera) -> Result<bool, <bsp::Camera as Camera>::Error> {
+    let len = camera.capture(ImageSize::Thumbnail).await?;
+    THUMBNAIL.lock(|t| t.borrow_mut().clear());
+    if len as usize > THUMBNAIL_MAX_LEN {
+        return Ok(false);
+    }
+    let mut chunk = [0u8; BLOCK_SIZE];
+    loop {
+        let len = camera.read(&mut chunk).await?;
+        if len == 0 {
+            return Ok(true);
+        }
+        if THUMBNAIL.lock(|t| t.borrow_mut().extend_from_slice(&chunk[..len])).is_err() {
+            return Ok(false);
+        }
+    }
+}
+
+// true while the payload rail is powered
+fn rail_on(rail: Rail) -> bool {
+    RAILS.lock(|r| r.get().contains(rail))
+}
+
+// false while the load is shed to save the battery
+// the heaters are never shed while the battery is cold, keeping it warm comes before everything they'd save
+fn load_enabled(load: Load) -> bool {
+    (load == Load::Heaters && BATTERY_COLD.lock(|c| c.get())) || LOAD_SHEDDER.lock(|l| l.borrow().enabled(load))
+}
+
+// where the sun is from the latest position, None until the rtc is set and there is a position
+fn sun_position() -> Option<SunPosition> {
+    let position = LATEST_POSITION.lock(|p| p.get())?;
+    Some(SunPosition::at(time_sync()?.utc, position.latitude, position.longitude))
+}
+
+// move the rf switch to the antenna the config and flight state pick, marking the log when it moves
+fn switch_antenna(rf_switch: &mut Output<'static>) {
+    let mode = AntennaMode::from_u32(CONFIG.lock(|c| c.get()).antenna_mode);
+    let antenna = mode.select(FLIGHT_STATE.lock(|s| s.get()));
+    if ANTENNA.lock(|a| a.replace(antenna)) == antenna {
+        return;
+    }
+    rf_switch.set_level(if antenna == Antenna::Upper { Level::High } else { Level::Low });
+    info!("radio: {} antenna selected", antenna.name());
+    publish_event(FlightEvent::AntennaSwitched(antenna), Instant::now().as_micros() as u32);
+}
+
+// the config's radio settings, with the transmit power held down while the battery is cold
+fn radio_config() -> Config {
+    let mut config = CONFIG.lock(|c| c.get());
+    if BATTERY_COLD.lock(|c| c.get()) {
+        config.radio_power = config.radio_power.min(COLD_RADIO_POWER);
+    }
+    config
+}
+
+// build the usb device with a single cdc-acm serial port for the debug console
+fn usb_console(driver: bsp::UsbDriver) -> (UsbDevice<'static, bsp::UsbDriver>, CdcAcmClass<'static, bsp::UsbDriver>) {
+    static CONFIG_DESCRIPTOR: StaticCell<[u8; 256]> = StaticCell::new();
+    static BOS_DESCRIPTOR: StaticCell<[u8; 256]> = StaticCell::new();
+    static CONTROL_BUF: StaticCell<[u8; 64]> = StaticCell::new();
+    static CDC_STATE: StaticCell<State> = StaticCell::new();
+
+    let mut config = embassy_usb::Config::new(USB_VID, USB_PID);
+    config.manufacturer = Some("Purdue Orbital");
+    config.product = Some("HAPSIS avionics console");
+    config.max_power = 100;
+    config.max_packet_size_0 = 64;
+
+    let mut builder = Builder::new(
+        driver,
+        config,
+        CONFIG_DESCRIPTOR.init([0; 256]),
+        BOS_DESCRIPTOR.init([0; 256]),
+        &mut [],
+        CONTROL_BUF.init([0; 64]),
+    );
+    let class = CdcAcmClass::new(&mut builder, CDC_STATE.init(State::new()), CONSOLE_PACKET_SIZE as u16);
+    (builder.build(), class)
+}
+
+// runs the usb stack, enumeration and control requests
+#[task]
+async fn usb_task(mut device: UsbDevice<'static, bsp::UsbDriver>) -> ! {
+    device.run().await
+}
+
+// debug console over usb serial, lets the pad crew check status, set config, calibrate, and arm from a laptop
+// without a debug probe, typed characters are echoed back and each line is run as a command
+#[task]
+async fn console_task(mut class: CdcAcmClass<'static, bsp::UsbDriver>) {
+    info!("Starting console task");
+
+    let mut line = LineBuffer::new();
+    let mut packet = [0u8; CONSOLE_PACKET_SIZE];
+
+    loop {
+        class.wait_connection().await;
+        info!("usb console connected");
+        let _ = console_write(&mut class, b"hapsis console, type help for commands\r\n> ").await;
+
+        'connection: loop {
+            let n = match class.read_packet(&mut packet).await {
+                Ok(n) => n,
+                Err(_) => break,
+            };
+            if console_write(&mut class, &packet[..n]).await.is_err() {
+                break;
+            }
+
+            for &byte in &packet[..n] {
+                let Some(text) = line.push(byte) else {
+                    continue;
+                };
+                let reply = shell_execute(text, Origin::UsbConsole).await;
+                // a failed write means the host went away, wait for it to connect again
+                if console_write(&mut class, reply.as_bytes()).await.is_err() {
+                    break 'connection;
+                }
+            }
+        }
+        info!("usb console disconnected");
+    }
+}
+
+// the same console over the umbilical uart, works without usb through the connector on the pad
+#[task]
+async fn uart_console_task(mut uart: bsp::ConsoleUart) {
+    info!("Starting uart console task");
+
+    let mut line = LineBuffer::new();
+    let mut buf = [0u8; 32];
+    let _ = uart.write_all(b"hapsis console, type help for commands\r\n> ").await;
+
+    loop {
+        let n = match uart.read(&mut buf).await {
+            Ok(n) => n,
+            Err(e) => {
+                // framing and noise errors are expected while the umbilical is being plugged in
+                warn!("console uart read failed: {}", e);
+                continue;
+            }
+        };
+        let _ = uart.write_all(&buf[..n]).await;
+
+        for &byte in &buf[..n] {
+            let Some(text) = line.push(byte) else {
+                continue;
+            };
+            let reply = shell_execute(text, Origin::UartConsole).await;
+            let _ = uart.write_all(reply.as_bytes()).await;
+        }
+    }
+}
+
+// write to the usb console in packet sized chunks
+async fn console_write(class: &mut CdcAcmClass<'static, bsp::UsbDriver>, bytes: &[u8]) -> Result<(), EndpointError> {
+    for chunk in bytes.chunks(CONSOLE_PACKET_SIZE) {
+        class.write_packet(chunk).await?;
+    }
+    // a full last packet needs a zero length packet after it so the host sees the end of the transfer
+    if bytes.len().is_multiple_of(CONSOLE_PACKET_SIZE) {
+        class.write_packet(&[]).await?;
+    }
+    Ok(())
+}
+
+// run one console line and return the reply text, ending with the next prompt, shared by the usb and uart consoles
+// commands go through the same command channel as the radio uplink and can bus, so the control task applies the same checks
+async fn shell_execute(line: &str, origin: Origin) -> String<CONSOLE_REPLY_LEN> {
+    let mut reply = String::new();
+    // replies longer than the buffer are cut short, which is fine for a console
+    let _ = shell_reply(line, origin, &mut reply).await;
+    let _ = reply.push_str("> ");
+    reply
+}
+
+async fn shell_reply(line: &str, origin: Origin, reply: &mut String<CONSOLE_REPLY_LEN>) -> core::fmt::Result {
+    reply.push_str("\r\n").map_err(|_| core::fmt::Error)?;
+
+    let request = match console::parse(line) {
+        Ok(request) => request,
+        Err(e) => return write!(reply, "error: {:?}, type help for commands\r\n", e),
+    };
+    info!("console: {}", request);
+
+    match request {
+        Request::Help => reply.push_str(console::HELP).map_err(|_| core::fmt::Error),
+        Request::Status => {
+            write!(reply, "state: {:?}\r\n", FLIGHT_STATE.lock(|s| s.get()))?;
+            match LATEST_VOLTAGE.lock(|v| v.get()) {
+                Some(voltage) => write!(reply, "battery: {:.2} V\r\n", voltage)?,
+                None => write!(reply, "battery: unknown\r\n")?,
+            }
+            if let Some(power) = LATEST_POWER.lock(|p| p.get()) {
+                write!(reply, "solar: {:.2} V, {:.3} A, {:?}\r\n", power.solar_voltage, power.solar_current, power.charge)?;
+            }
+            match SESSION.lock(|s| s.get()) {
+                Some(session) => write!(reply, "armed, ground altitude: {:.1} m\r\n", session.ground_altitude)?,
+                None => write!(reply, "not armed\r\n")?,
+            }
+            if let Some(met) = met(Instant::now().as_micros() as u32) {
+                write!(reply, "met: {}\r\n", met)?;
+            }
+            for load in Load::ALL.into_iter().filter(|&load| !load_enabled(load)) {
+                write!(reply, "shed: {:?}\r\n", load)?;
+            }
+            for rail in Rail::ALL {
+                write!(reply, "rail {}: {}\r\n", rail.name(), if rail_on(rail) { "on" } else { "off" })?;
+            }
+            write!(reply, "antenna: {}\r\n", ANTENNA.lock(|a| a.get()).name())?;
+            if let Some(sun) = sun_position() {
+                write!(reply, "sun: azimuth {:.1} deg, elevation {:.1} deg\r\n", sun.azimuth, sun.elevation)?;
+            }
+            if let Some(heater) = LATEST_HEATER.lock(|h| h.get()) {
+                write!(reply, "heater: {}, {:.0} of {:.0} J this hour, {:.0} J since boot\r\n",
+                    if heater.on { "on" } else if heater.held { "held off" } else { "off" },
+                    heater.period_energy, HEATER_BUDGET, heater.total_energy)?;
+            }
+            let actuation = ACTUATION.lock(|a| *a.borrow());
+            for channel in Channel::ALL {
+                write!(reply, "{}: continuity {}, {}\r\n", channel.name(),
+                    if actuation.continuity().contains(channel) { "yes" } else { "no" },
+                    if actuation.armed().contains(channel) { "armed" } else { "safe" })?;
+            }
+            let dropped = drop_counts();
+            write!(reply, "dropped: baro {}, imu {}, gps {}, log {}, telemetry {}\r\n",
+                dropped.baro, dropped.imu, dropped.gps, dropped.log, dropped.telemetry)?;
+            write!(reply, "faults: {:#06x}\r\n", FAULT_LOG.lock(|f| f.borrow().flags()).0)
+        }
+        Request::LogDump => {
+            let recent = FAULT_LOG.lock(|f| f.borrow().recent::<CONSOLE_LOG_EVENTS>());
+            for event in recent.into_iter().flatten() {
+                let change = if event.active { "raised" } else { "cleared" };
+                write!(reply, "{} us: {:?} {}\r\n", event.time_stamp, event.fault, change)?;
+            }
+            Ok(())
+        }
+        Request::LogTail { stream, count } => {
+            LOG_TAIL_REPLY_SIGNAL.reset();
+            LOG_TAIL_SIGNAL.signal((stream, count));
+            let records = match LOG_TAIL_REPLY_SIGNAL.wait().with_timeout(CONSOLE_COMMAND_TIMEOUT).await {
+                Ok(Some(records)) => records,
+                Ok(None) => return write!(reply, "error: log can't be read back\r\n"),
+                Err(_) => return write!(reply, "error: no reply\r\n"),
+            };
+            if records.iter().all(Option::is_none) {
+                return write!(reply, "no {} records written yet\r\n", stream.name());
+            }
+            for record in records.iter().flatten().filter_map(HistoryRecord::record) {
+                match record.time_stamp() {
+                    Some(time_stamp) => write!(reply, "{} us: {}\r\n", time_stamp, record)?,
+                    None => write!(reply, "{}\r\n", record)?,
+                }
+            }
+            Ok(())
+        }
+        Request::Tagged { command, condition } => match PENDING.lock(|p| p.borrow_mut().add(command, condition)) {
+            Some(id) => {
+                info!("holding command {} until {}", id, condition);
+                write!(reply, "held as {}\r\n", id)
+            }
+            None => write!(reply, "error: {} commands already held\r\n", PENDING_COMMANDS),
+        },
+        Request::Pending => PENDING.lock(|p| {
+            let pending = p.borrow();
+            if pending.pending().next().is_none() {
+                return write!(reply, "no held commands\r\n");
+            }
+            for held in pending.pending() {
+                let (name, channel) = Command::describe(held.command.code()).unwrap_or(("unknown", None));
+                write!(reply, "{}: {}", held.id, name)?;
+                if let Some(channel) = channel {
+                    write!(reply, " {}", channel.name())?;
+                }
+                match held.condition {
+                    Condition::Met(seconds) => write!(reply, " at {}\r\n", Met(seconds.saturating_mul(1000)))?,
+                    Condition::Altitude(altitude) => write!(reply, " at {:.0} m\r\n", altitude)?,
+                }
+            }
+            Ok(())
+        }),
+        Request::Cancel(Some(id)) => match PENDING.lock(|p| p.borrow_mut().cancel(id)) {
+            Some(_) => {
+                info!("held command {} cancelled", id);
+                write!(reply, "cancelled {}\r\n", id)
+            }
+            None => write!(reply, "error: no held command {}\r\n", id),
+        },
+        Request::Cancel(None) => {
+            let count = PENDING.lock(|p| p.borrow_mut().cancel_all());
+            info!("{} held commands cancelled", count);
+            write!(reply, "cancelled {}\r\n", count)
+        }
+        Request::Version => {
+            write!(reply, "version: {}\r\ngit: {}{}\r\nprofile: {}\r\nfeatures: {}\r\n", FIRMWARE.version, FIRMWARE.git_hash,
+                if FIRMWARE.dirty { " (dirty)" } else { "" }, FIRMWARE.profile, FIRMWARE.features)
+        }
+        Request::BootloaderChallenge => {
+            let boot_count = BOOT_INFO.lock(|b| b.get()).boot_count;
+            match BOOTLOADER_CHALLENGE.lock(|c| c.borrow_mut().issue(boot_count)) {
+                Ok(nonce) => write!(reply, "nonce: {:016x}\r\n", nonce),
+                Err(e) => write!(reply, "error: {:?}\r\n", e),
+            }
+        }
+        Request::ConfigKeys => {
+            for key in ConfigKey::ALL {
+                write!(reply, "{}\r\n", key.name())?;
+            }
+            Ok(())
+        }
+        Request::Command(command) => {
+            let config_report = if origin == Origin::UartConsole { &UART_CONFIG_REPORT_SIGNAL } else { &USB_CONFIG_REPORT_SIGNAL };
+            config_report.reset();
+            ASSIST_STATUS_SIGNAL.reset();
+            UPDATE_STATUS_SIGNAL.reset();
+            if !COMMAND_CHANNEL.send((command, origin)).await {
+                return write!(reply, "error: command queue full\r\n");
+            }
+
+            // the ack carries where to resume, an upload script reads it before sending the next line
+            if let Command::Assist(_) = command {
+                return match ASSIST_STATUS_SIGNAL.wait().with_timeout(CONSOLE_COMMAND_TIMEOUT).await {
+                    Ok(status) => write!(reply, "assist: {} of {} bytes, {} messages, {} rejected, {:?}\r\n",
+                        status.next_offset, status.size, status.messages, status.rejected, status.result),
+                    Err(_) => write!(reply, "error: no reply\r\n"),
+                };
+            }
+            if let Command::Update(_) = command {
+                return match UPDATE_STATUS_SIGNAL.wait().with_timeout(CONSOLE_UPDATE_TIMEOUT).await {
+                    Ok(status) => write!(reply, "update: {} of {} bytes, {:?}\r\n", status.next_offset, status.size, status.result),
+                    Err(_) => write!(reply, "error: no reply\r\n"),
+                };
+            }
+
+            let Command::GetConfig(key) = command else {
+                return write!(reply, "ok\r\n");
+            };
+            match config_report.wait().with_timeout(CONSOLE_COMMAND_TIMEOUT).await {
+                Ok(report) => write!(reply, "{} = {:?}{}\r\n", key.name(), report.value, if report.pending { " (staged)" } else { "" }),
+                Err(_) => write!(reply, "error: no reply\r\n"),
+            }
+        }
+    }
+}
+
+// raise or clear a fault, repeated reports of an unchanged fault are ignored
+// every change is logged, kept in the fault ring buffer, and broadcast as a flight event
+fn report_fault(fault: Fault, active: bool) {
+    let event = FaultEvent {
+        fault,
+        active,
+        time_stamp: Instant::now().as_micros() as u32,
+    };
+    if !FAULT_LOG.lock(|f| f.borrow_mut().report(event)) {
+        return;
+    }
+
+    if active {
+        error!("fault raised: {}", fault);
+    } else {
+        info!("fault cleared: {}", fault);
+    }
+
+    publish_event(FlightEvent::Fault { fault, active }, event.time_stamp);
+}
+
+// broadcast a flight event, never blocks, a subscriber that falls behind loses the oldest events and is told how many
+fn publish_event(event: FlightEvent, time_stamp: u32) {
+    EVENT_BUS.immediate_publisher().publish_immediate(EventRecord { event, time_stamp });
+}
+
+// mission elapsed time of an uptime time stamp, None before launch
+fn met(time_stamp: u32) -> Option<Met> {
+    LAUNCH_TIME.lock(|l| l.get()).map(|launch| Met::since(launch, time_stamp))
+}
+
+// current utc paired with the uptime time stamp, None until the rtc has been set from gps
+fn time_sync() -> Option<TimeSync> {
+    let time_stamp = Instant::now().as_micros() as u32;
+    let now = RTC.lock(|r| {
+        let rtc = r.borrow();
+        let rtc = rtc.as_ref()?;
+        if rtc.read_backup_register(RTC_SYNC_REGISTER) != Some(RTC_SYNC_MAGIC) {
+            return None;
+        }
+        rtc.now().ok()
+    })?;
+    let utc = chrono::NaiveDateTime::from(now).and_utc().timestamp_millis();
+    Some(TimeSync { utc: utc as u64, time_stamp })
+}
+
+// set the rtc from gps utc (ms since the unix epoch) when it is unset or has drifted
+fn discipline_rtc(utc: u64) {
+    if time_sync().is_some_and(|sync| sync.utc.abs_diff(utc) <= RTC_MAX_DRIFT_MS) {
+        return;
+    }
+
+    let Some(time) = chrono::DateTime::from_timestamp_millis(utc as i64) else {
+        warn!("gps utc out of range: {}", utc);
+        return;
+    };
+
+    RTC.lock(|r| {
+        let mut rtc = r.borrow_mut();
+        let Some(rtc) = rtc.as_mut() else {
+            return;
+        };
+        match rtc.set_datetime(time.naive_utc().into()) {
+            Ok(_) => {
+                rtc.write_backup_register(RTC_SYNC_REGISTER, RTC_SYNC_MAGIC);
+                info!("rtc set from gps utc: {}", utc);
+            }
+            Err(e) => warn!("failed to set rtc: {}", e),
+        }
+    });
+}
@@ -0,0 +1,266 @@
+// mavlink 2 messages for the downlink compatibility mode, so a stock ground control station (QGroundControl, Mission
+// Planner) can show the payload without the ground tools, only the few common.xml messages it needs to draw a vehicle
+// fields go on the wire largest type first, the order the mavlink generator puts them in, trailing zero bytes of the
+// payload are trimmed as mavlink 2 senders do
+
+use libm::{atan2f, sqrtf};
+
+use crate::faults::{Fault, FaultFlags};
+use crate::health::Stream;
+use crate::mission::FlightState;
+use crate::{BaroData, FixType, GpsData};
+
+const STX: u8 = 0xFD;
+// stx, length, incompat flags, compat flags, sequence, system, component, 3 byte message id
+const HEADER_LEN: usize = 10;
+const CHECKSUM_LEN: usize = 2;
+const MAX_PAYLOAD_LEN: usize = SysStatus::LEN;
+
+/// longest frame this encoder produces
+pub const MAX_FRAME_LEN: usize = HEADER_LEN + MAX_PAYLOAD_LEN + CHECKSUM_LEN;
+
+const MAV_TYPE_FREE_BALLOON: u8 = 7;
+const MAV_AUTOPILOT_GENERIC: u8 = 0;
+const MAV_MODE_FLAG_CUSTOM_MODE_ENABLED: u8 = 1;
+const MAV_MODE_FLAG_SAFETY_ARMED: u8 = 128;
+const MAV_STATE_STANDBY: u8 = 3;
+const MAV_STATE_ACTIVE: u8 = 4;
+const MAV_STATE_CRITICAL: u8 = 5;
+const MAV_STATE_EMERGENCY: u8 = 6;
+const MAVLINK_VERSION: u8 = 3;
+
+const SENSOR_3D_GYRO: u32 = 1 << 0;
+const SENSOR_3D_ACCEL: u32 = 1 << 1;
+const SENSOR_3D_MAG: u32 = 1 << 2;
+const SENSOR_ABSOLUTE_PRESSURE: u32 = 1 << 3;
+const SENSOR_GPS: u32 = 1 << 5;
+const SENSORS: u32 = SENSOR_3D_GYRO | SENSOR_3D_ACCEL | SENSOR_3D_MAG | SENSOR_ABSOLUTE_PRESSURE | SENSOR_GPS;
+
+const GPS_FIX_TYPE_NO_FIX: u8 = 1;
+const GPS_FIX_TYPE_2D_FIX: u8 = 2;
+const GPS_FIX_TYPE_3D_FIX: u8 = 3;
+
+/// HEARTBEAT, flight state as the custom mode
+#[derive(Copy, Clone)]
+pub struct Heartbeat {
+    pub state: FlightState,
+    pub armed: bool,
+    /// any fault active, shown as critical
+    pub fault: bool,
+    /// supply voltage low, shown as an emergency
+    pub low_voltage: bool,
+}
+
+impl Heartbeat {
+    const ID: u32 = 0;
+    const CRC_EXTRA: u8 = 50;
+    const LEN: usize = 9;
+
+    fn payload(&self, payload: &mut [u8]) {
+        let mut base_mode = MAV_MODE_FLAG_CUSTOM_MODE_ENABLED;
+        if self.armed {
+            base_mode |= MAV_MODE_FLAG_SAFETY_ARMED;
+        }
+        let system_status = if self.low_voltage {
+            MAV_STATE_EMERGENCY
+        } else if self.fault {
+            MAV_STATE_CRITICAL
+        } else if matches!(self.state, FlightState::Ascent | FlightState::Descent) {
+            MAV_STATE_ACTIVE
+        } else {
+            MAV_STATE_STANDBY
+        };
+
+        payload[0..4].copy_from_slice(&(self.state as u32).to_le_bytes());
+        payload[4] = MAV_TYPE_FREE_BALLOON;
+        payload[5] = MAV_AUTOPILOT_GENERIC;
+        payload[6] = base_mode;
+        payload[7] = system_status;
+        payload[8] = MAVLINK_VERSION;
+    }
+}
+
+/// SYS_STATUS, sensor health from the stale stream faults and battery state
+#[derive(Copy, Clone)]
+pub struct SysStatus {
+    pub faults: FaultFlags,
+    /// percent
+    pub cpu_load: u8,
+    /// V
+    pub voltage: Option<f32>,
+    /// percent
+    pub battery_remaining: Option<u8>,
+}
+
+impl SysStatus {
+    const ID: u32 = 1;
+    const CRC_EXTRA: u8 = 124;
+    const LEN: usize = 31;
+
+    fn payload(&self, payload: &mut [u8]) {
+        let mut health = SENSORS;
+        for (fault, sensors) in [
+            (Fault::Stale(Stream::Imu), SENSOR_3D_GYRO | SENSOR_3D_ACCEL | SENSOR_3D_MAG),
+            (Fault::ImuDivergence, SENSOR_3D_GYRO | SENSOR_3D_ACCEL),
+            (Fault::Stale(Stream::Baro), SENSOR_ABSOLUTE_PRESSURE),
+            (Fault::BaroDivergence, SENSOR_ABSOLUTE_PRESSURE),
+            (Fault::Stale(Stream::Gps), SENSOR_GPS),
+            (Fault::GpsLost, SENSOR_GPS),
+            (Fault::GpsLockout, SENSOR_GPS),
+        ] {
+            if self.faults.contains(fault) {
+                health &= !sensors;
+            }
+        }
+        // u16::MAX and -1 are unknown
+        let voltage = self.voltage.map_or(u16::MAX, |v| (v * 1000.0).clamp(0.0, (u16::MAX - 1) as f32) as u16);
+        let battery_remaining = self.battery_remaining.map_or(-1, |b| b.min(100) as i8);
+
+        payload[0..4].copy_from_slice(&SENSORS.to_le_bytes());
+        payload[4..8].copy_from_slice(&SENSORS.to_le_bytes());
+        payload[8..12].copy_from_slice(&health.to_le_bytes());
+        payload[12..14].copy_from_slice(&(self.cpu_load as u16 * 10).to_le_bytes());
+        payload[14..16].copy_from_slice(&voltage.to_le_bytes());
+        payload[16..18].copy_from_slice(&(-1i16).to_le_bytes());
+        // comm drop rate, comm errors, and the four autopilot error counts
+        payload[18..30].fill(0);
+        payload[30] = battery_remaining as u8;
+    }
+}
+
+/// GPS_RAW_INT, the receiver's fix as it came in
+#[derive(Copy, Clone)]
+pub struct GpsRawInt {
+    pub gps: GpsData,
+}
+
+impl GpsRawInt {
+    const ID: u32 = 24;
+    const CRC_EXTRA: u8 = 24;
+    const LEN: usize = 30;
+
+    fn payload(&self, payload: &mut [u8]) {
+        let gps = &self.gps;
+        // unix epoch when the receiver has time, since boot before, both are allowed
+        let time = gps.utc.map_or(gps.time_stamp as u64, |utc| utc * 1000);
+        let [north, east, _] = gps.velocity;
+        let speed = (sqrtf(north * north + east * east) * 100.0).clamp(0.0, (u16::MAX - 1) as f32) as u16;
+        let course = (atan2f(east, north).to_degrees() + 360.0) % 360.0;
+        let fix_type = match gps.fix {
+            FixType::None => GPS_FIX_TYPE_NO_FIX,
+            FixType::TwoD => GPS_FIX_TYPE_2D_FIX,
+            FixType::ThreeD => GPS_FIX_TYPE_3D_FIX,
+        };
+
+        payload[0..8].copy_from_slice(&time.to_le_bytes());
+        payload[8..12].copy_from_slice(&((gps.latitude * 1e7) as i32).to_le_bytes());
+        payload[12..16].copy_from_slice(&((gps.longitude * 1e7) as i32).to_le_bytes());
+        payload[16..20].copy_from_slice(&((gps.altitude * 1000.0) as i32).to_le_bytes());
+        payload[20..22].copy_from_slice(&((gps.hdop * 100.0).clamp(0.0, (u16::MAX - 1) as f32) as u16).to_le_bytes());
+        // no vdop
+        payload[22..24].copy_from_slice(&u16::MAX.to_le_bytes());
+        payload[24..26].copy_from_slice(&speed.to_le_bytes());
+        payload[26..28].copy_from_slice(&((course * 100.0) as u16 % 36000).to_le_bytes());
+        payload[28] = fix_type;
+        payload[29] = gps.satellites;
+    }
+}
+
+/// SCALED_PRESSURE, the barometer
+#[derive(Copy, Clone)]
+pub struct ScaledPressure {
+    pub baro: BaroData,
+}
+
+impl ScaledPressure {
+    const ID: u32 = 29;
+    const CRC_EXTRA: u8 = 115;
+    const LEN: usize = 14;
+
+    fn payload(&self, payload: &mut [u8]) {
+        payload[0..4].copy_from_slice(&(self.baro.time_stamp / 1000).to_le_bytes());
+        payload[4..8].copy_from_slice(&self.baro.pressure.to_le_bytes());
+        // no differential pressure sensor
+        payload[8..12].copy_from_slice(&0.0f32.to_le_bytes());
+        payload[12..14].copy_from_slice(&((self.baro.temperature * 100.0) as i16).to_le_bytes());
+    }
+}
+
+/// One message the compatibility mode sends
+#[derive(Copy, Clone)]
+pub enum Message {
+    Heartbeat(Heartbeat),
+    SysStatus(SysStatus),
+    GpsRawInt(GpsRawInt),
+    ScaledPressure(ScaledPressure),
+}
+
+impl Message {
+    // id, crc extra, and full payload length
+    fn info(&self) -> (u32, u8, usize) {
+        match self {
+            Message::Heartbeat(_) => (Heartbeat::ID, Heartbeat::CRC_EXTRA, Heartbeat::LEN),
+            Message::SysStatus(_) => (SysStatus::ID, SysStatus::CRC_EXTRA, SysStatus::LEN),
+            Message::GpsRawInt(_) => (GpsRawInt::ID, GpsRawInt::CRC_EXTRA, GpsRawInt::LEN),
+            Message::ScaledPressure(_) => (ScaledPressure::ID, ScaledPressure::CRC_EXTRA, ScaledPressure::LEN),
+        }
+    }
+
+    fn payload(&self, payload: &mut [u8]) {
+        match self {
+            Message::Heartbeat(message) => message.payload(payload),
+            Message::SysStatus(message) => message.payload(payload),
+            Message::GpsRawInt(message) => message.payload(payload),
+            Message::ScaledPressure(message) => message.payload(payload),
+        }
+    }
+}
+
+/// Frames messages from one mavlink system and component, numbering them for the ground's loss count
+pub struct Encoder {
+    system: u8,
+    component: u8,
+    sequence: u8,
+}
+
+impl Encoder {
+    pub const fn new(system: u8, component: u8) -> Self {
+        Self { system, component, sequence: 0 }
+    }
+
+    /// write one message frame to the start of frame, returns its length, None if frame is too short
+    pub fn encode(&mut self, message: &Message, frame: &mut [u8]) -> Option<usize> {
+        let (id, crc_extra, len) = message.info();
+        let frame = frame.get_mut(..HEADER_LEN + len + CHECKSUM_LEN)?;
+        message.payload(&mut frame[HEADER_LEN..HEADER_LEN + len]);
+        // at least one payload byte stays
+        let payload = &frame[HEADER_LEN..HEADER_LEN + len];
+        let len = payload.iter().rposition(|&byte| byte != 0).map_or(1, |last| last + 1);
+
+        frame[0] = STX;
+        frame[1] = len as u8;
+        frame[2] = 0;
+        frame[3] = 0;
+        frame[4] = self.sequence;
+        frame[5] = self.system;
+        frame[6] = self.component;
+        frame[7..10].copy_from_slice(&id.to_le_bytes()[..3]);
+        let mut crc = crc_x25(0xFFFF, &frame[1..HEADER_LEN + len]);
+        crc = crc_x25(crc, &[crc_extra]);
+        frame[HEADER_LEN + len..HEADER_LEN + len + CHECKSUM_LEN].copy_from_slice(&crc.to_le_bytes());
+
+        self.sequence = self.sequence.wrapping_add(1);
+        Some(HEADER_LEN + len + CHECKSUM_LEN)
+    }
+}
+
+// crc-16/mcrf4xx as mavlink calls it, everything after stx then the message's crc extra
+fn crc_x25(mut crc: u16, data: &[u8]) -> u16 {
+    for &byte in data {
+        let mut tmp = byte ^ crc as u8;
+        tmp ^= tmp << 4;
+        let tmp = tmp as u16;
+        crc = (crc >> 8) ^ (tmp << 8) ^ (tmp << 3) ^ (tmp >> 4);
+    }
+    crc
+}
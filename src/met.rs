@@ -0,0 +1,73 @@
+/// Mission elapsed time in ms since launch detection, formatted as T+h:mm:ss.mmm for operators
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Met(pub u32);
+
+impl Met {
+    /// met of an uptime time stamp (us) given the launch time stamp (us)
+    /// timestamps wrap, wrapping_sub gives the right interval across one wrap
+    pub fn since(launch: u32, time_stamp: u32) -> Self {
+        Met(time_stamp.wrapping_sub(launch) / 1000)
+    }
+
+    pub fn hours(self) -> u32 {
+        self.0 / 3_600_000
+    }
+
+    pub fn minutes(self) -> u32 {
+        self.0 / 60_000 % 60
+    }
+
+    pub fn seconds(self) -> u32 {
+        self.0 / 1000 % 60
+    }
+
+    pub fn millis(self) -> u32 {
+        self.0 % 1000
+    }
+}
+
+/// Follows the mission elapsed time past the 71.6 minute wrap of the us time stamps, for times set hours ahead
+/// it has to be updated more often than the wrap
+#[derive(Copy, Clone, Default)]
+pub struct MetClock {
+    // (time stamp less launch, mission elapsed time in us) at the last update
+    last: Option<(u32, u64)>,
+}
+
+impl MetClock {
+    pub const fn new() -> Self {
+        Self { last: None }
+    }
+
+    /// mission elapsed time in us, launch is the uptime time stamp of launch detection, None before launch
+    pub fn update(&mut self, launch: Option<u32>, time_stamp: u32) -> Option<u64> {
+        let Some(launch) = launch else {
+            self.last = None;
+            return None;
+        };
+        let since = time_stamp.wrapping_sub(launch);
+        let met = match self.last {
+            Some((last, met)) => met + since.wrapping_sub(last) as u64,
+            None => since as u64,
+        };
+        self.last = Some((since, met));
+        Some(met)
+    }
+
+    /// mission elapsed time in us as of the last update
+    pub fn last(&self) -> Option<u64> {
+        self.last.map(|(_, met)| met)
+    }
+}
+
+impl defmt::Format for Met {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(f, "T+{}:{=u32:02}:{=u32:02}.{=u32:03}", self.hours(), self.minutes(), self.seconds(), self.millis())
+    }
+}
+
+impl core::fmt::Display for Met {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        write!(f, "T+{}:{:02}:{:02}.{:03}", self.hours(), self.minutes(), self.seconds(), self.millis())
+    }
+}
@@ -0,0 +1,148 @@
+use libm::sqrtf;
+
+use crate::VerticalState;
+
+/// Flight phase of the payload
+#[derive(Copy, Clone, PartialEq, Eq, Debug, defmt::Format)]
+pub enum FlightState {
+    /// on the ground waiting for launch
+    Pad,
+    /// balloon ascent
+    Ascent,
+    /// after burst or cutdown, falling under parachute
+    Descent,
+    /// back on the ground
+    Landed,
+}
+
+impl FlightState {
+    pub const ALL: [FlightState; 4] = [FlightState::Pad, FlightState::Ascent, FlightState::Descent, FlightState::Landed];
+
+    pub fn from_id(id: u8) -> Option<Self> {
+        Self::ALL.get(id as usize).copied()
+    }
+
+    pub fn id(self) -> u8 {
+        self as u8
+    }
+}
+
+/// climb rate that counts as launched, m/s
+const LAUNCH_SPEED: f32 = 2.0;
+
+/// sink rate that counts as descending, m/s
+const DESCENT_SPEED: f32 = -2.0;
+
+/// vertical speed magnitude below which the payload is considered stationary, m/s
+const LANDED_SPEED: f32 = 0.5;
+
+/// consecutive vertical state samples a condition must hold before transitioning
+const CONFIRM_SAMPLES: u8 = 3;
+
+/// Flight state machine driven by the vertical state estimate
+pub struct Mission {
+    state: FlightState,
+    confirm: u8,
+}
+
+impl Default for Mission {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Mission {
+    pub const fn new() -> Self {
+        Self {
+            state: FlightState::Pad,
+            confirm: 0,
+        }
+    }
+
+    /// pick up in a state saved before a reset
+    pub const fn resume(state: FlightState) -> Self {
+        Self { state, confirm: 0 }
+    }
+
+    pub fn state(&self) -> FlightState {
+        self.state
+    }
+
+    /// feed a new vertical state, returns the new flight state if a transition happened
+    pub fn update(&mut self, vertical: &VerticalState) -> Option<FlightState> {
+        let next = match self.state {
+            FlightState::Pad if vertical.vertical_speed > LAUNCH_SPEED => Some(FlightState::Ascent),
+            FlightState::Ascent if vertical.vertical_speed < DESCENT_SPEED => Some(FlightState::Descent),
+            FlightState::Descent if vertical.vertical_speed.abs() < LANDED_SPEED => Some(FlightState::Landed),
+            _ => None,
+        };
+
+        // require the condition for several samples in a row so one noisy sample can't change state
+        let Some(next) = next else {
+            self.confirm = 0;
+            return None;
+        };
+
+        self.confirm += 1;
+        if self.confirm < CONFIRM_SAMPLES {
+            return None;
+        }
+
+        self.confirm = 0;
+        self.state = next;
+        Some(next)
+    }
+}
+
+/// acceleration magnitude below which the payload counts as falling freely, m/s^2 (about 0.3 g)
+const FREE_FALL_ACCELERATION: f32 = 3.0;
+
+/// how long the acceleration must stay low before free fall is declared, us
+const FREE_FALL_DURATION: u32 = 100_000;
+
+/// Free fall detector, the accelerometer reads near zero when the payload falls without drag
+/// after balloon burst, this is the earliest sign of burst we get
+pub struct FreeFallDetector {
+    /// time stamp of the first low acceleration sample in the current run
+    started: Option<u32>,
+    active: bool,
+}
+
+impl Default for FreeFallDetector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FreeFallDetector {
+    pub const fn new() -> Self {
+        Self {
+            started: None,
+            active: false,
+        }
+    }
+
+    /// true while the payload is in free fall
+    pub fn active(&self) -> bool {
+        self.active
+    }
+
+    /// feed an accelerometer sample (m/s^2), returns true only on the sample where free fall is first detected
+    pub fn update(&mut self, acceleration: [f32; 3], time_stamp: u32) -> bool {
+        let magnitude = sqrtf(acceleration.iter().map(|a| a * a).sum());
+
+        if magnitude >= FREE_FALL_ACCELERATION || !magnitude.is_finite() {
+            self.started = None;
+            self.active = false;
+            return false;
+        }
+
+        let started = *self.started.get_or_insert(time_stamp);
+        if self.active || time_stamp.wrapping_sub(started) < FREE_FALL_DURATION {
+            return false;
+        }
+
+        self.active = true;
+        true
+    }
+}
@@ -0,0 +1,358 @@
+use libm::{cos, sqrtf};
+
+use crate::{AttitudeData, FixType, GpsData, ImuData, NavMode, StateVector, VerticalState};
+
+/// number of states in the navigation filter
+const N: usize = 7;
+
+// state indices: local position (m) and velocity (m/s) in north, east, up, plus barometer offset (m)
+const NORTH: usize = 0;
+const EAST: usize = 1;
+const UP: usize = 2;
+const V_NORTH: usize = 3;
+const V_EAST: usize = 4;
+const V_UP: usize = 5;
+const BARO_BIAS: usize = 6;
+
+/// standard gravity, m/s^2
+const GRAVITY: f32 = 9.80665;
+
+/// mean earth radius used for the local tangent plane projection, m
+const EARTH_RADIUS: f64 = 6_371_000.0;
+
+/// accelerometer noise driving the position/velocity prediction, m/s^2
+const ACCEL_SIGMA: f32 = 0.5;
+
+/// horizontal velocity random walk used when the accelerometer is not trusted, m/s^2
+/// balloon horizontal motion follows the wind, which changes slowly
+const COAST_SIGMA: f32 = 0.2;
+
+/// barometer offset random walk, m/sqrt(s)
+const BARO_BIAS_SIGMA: f32 = 0.05;
+
+/// measurement noise, standard deviations
+const GPS_HORIZONTAL_SIGMA: f32 = 5.0;
+const GPS_VERTICAL_SIGMA: f32 = 10.0;
+const GPS_VELOCITY_SIGMA: f32 = 0.5;
+const BARO_SIGMA: f32 = 2.0;
+
+/// measurements with an innovation further out than this many standard deviations are rejected
+const INNOVATION_GATE: f32 = 5.0;
+
+/// time without a usable gps fix before the filter reports a gps outage, us
+const GPS_TIMEOUT: u32 = 3_000_000;
+
+/// body rotation rate above which the payload is considered tumbling, rad/s
+/// the attitude (and so the rotated accelerometer) is unreliable while tumbling
+const TUMBLE_RATE: f32 = 3.0;
+
+/// longest prediction step accepted, longer gaps are clamped so a stalled task can't blow up the covariance
+const MAX_DT: f32 = 1.0;
+
+/// Extended Kalman filter fusing gps, barometric altitude, and imu into a navigation state
+/// position is kept in a local north/east/up frame around the first gps fix, with up as altitude above sea level
+/// attitude comes from the attitude filter and is used to rotate the accelerometer into the local frame
+///
+/// degrades gracefully:
+/// without gps (no fix yet, or lost above 18 km) the horizontal state coasts on the last velocity and
+/// the vertical state runs on baro and accel, while tumbling the accelerometer is ignored entirely
+pub struct NavFilter {
+    x: [f32; N],
+    p: [[f32; N]; N],
+    attitude: AttitudeData,
+    origin: Option<(f64, f64)>,
+    last_predict: Option<u32>,
+    last_gps: Option<u32>,
+    altitude_initialized: bool,
+    tumbling: bool,
+    time_stamp: u32,
+}
+
+impl Default for NavFilter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl NavFilter {
+    pub const fn new() -> Self {
+        let mut p = [[0.0; N]; N];
+        let mut i = 0;
+        while i < N {
+            p[i][i] = 1.0e4;
+            i += 1;
+        }
+
+        Self {
+            x: [0.0; N],
+            p,
+            attitude: AttitudeData {
+                quaternion: [1.0, 0.0, 0.0, 0.0],
+                converged: false,
+                time_stamp: 0,
+            },
+            origin: None,
+            last_predict: None,
+            last_gps: None,
+            altitude_initialized: false,
+            tumbling: false,
+            time_stamp: 0,
+        }
+    }
+
+    /// latitude and longitude (deg) of the local frame origin, set by the first usable gps fix
+    pub fn origin(&self) -> Option<(f64, f64)> {
+        self.origin
+    }
+
+    /// current navigation mode
+    pub fn mode(&self) -> NavMode {
+        if self.origin.is_none() {
+            NavMode::Initializing
+        } else if self.tumbling {
+            NavMode::Tumbling
+        } else if self.gps_lost(self.time_stamp) {
+            NavMode::NoGps
+        } else {
+            NavMode::Full
+        }
+    }
+
+    /// current state estimate
+    pub fn state(&self) -> StateVector {
+        StateVector {
+            position: [self.x[NORTH], self.x[EAST], self.x[UP]],
+            velocity: [self.x[V_NORTH], self.x[V_EAST], self.x[V_UP]],
+            attitude: self.attitude.quaternion,
+            mode: self.mode(),
+            time_stamp: self.time_stamp,
+        }
+    }
+
+    /// one sigma position uncertainty (north, east, up), m
+    pub fn position_sigma(&self) -> [f32; 3] {
+        [sqrtf(self.p[NORTH][NORTH]), sqrtf(self.p[EAST][EAST]), sqrtf(self.p[UP][UP])]
+    }
+
+    /// propagate the state to the imu timestamp using the accelerometer rotated by the attitude estimate
+    pub fn predict(&mut self, imu: &ImuData, attitude: &AttitudeData) {
+        self.attitude = *attitude;
+        self.time_stamp = imu.time_stamp;
+
+        let [gx, gy, gz] = imu.gyro;
+        self.tumbling = sqrtf(gx * gx + gy * gy + gz * gz) > TUMBLE_RATE || !attitude.converged;
+
+        let Some(prev) = self.last_predict.replace(imu.time_stamp) else {
+            return;
+        };
+        let dt = (imu.time_stamp.wrapping_sub(prev) as f32 / 1_000_000.0).min(MAX_DT);
+        if dt <= 0.0 {
+            return;
+        }
+
+        // accel in the local frame, only when the attitude can be trusted
+        // horizontal accel is also dropped without gps, a low grade imu integrated open loop drifts
+        // much faster than the wind changes
+        let accel = if self.tumbling {
+            None
+        } else {
+            Some(local_acceleration(attitude.quaternion, imu.acceleration))
+        };
+        let use_horizontal = accel.is_some() && !self.gps_lost(imu.time_stamp);
+
+        for axis in 0..3 {
+            let a = match accel {
+                Some(a) if axis == 2 || use_horizontal => a[axis],
+                _ => 0.0,
+            };
+            self.x[axis] += self.x[axis + 3] * dt + 0.5 * a * dt * dt;
+            self.x[axis + 3] += a * dt;
+        }
+
+        // covariance, P = F P F^T + Q with position integrating velocity
+        let mut f = identity();
+        for axis in 0..3 {
+            f[axis][axis + 3] = dt;
+        }
+        let mut p = mul(&mul(&f, &self.p), &transpose(&f));
+
+        for axis in 0..3 {
+            let sigma = match accel {
+                Some(_) if axis == 2 || use_horizontal => ACCEL_SIGMA,
+                Some(_) => COAST_SIGMA,
+                // no accel at all, vertical has to absorb ascent rate changes so use the larger noise
+                None if axis == 2 => ACCEL_SIGMA,
+                None => COAST_SIGMA,
+            };
+            let q = sigma * sigma;
+            p[axis][axis] += 0.25 * dt * dt * dt * dt * q;
+            p[axis][axis + 3] += 0.5 * dt * dt * dt * q;
+            p[axis + 3][axis] += 0.5 * dt * dt * dt * q;
+            p[axis + 3][axis + 3] += dt * dt * q;
+        }
+        p[BARO_BIAS][BARO_BIAS] += BARO_BIAS_SIGMA * BARO_BIAS_SIGMA * dt;
+
+        self.p = p;
+    }
+
+    /// correct the state with a gps fix, invalid fixes are ignored, the horizontal noise grows with hdop,
+    /// and a 2d fix corrects only the horizontal state since its altitude is held rather than measured
+    pub fn update_gps(&mut self, gps: &GpsData) {
+        if !gps.quality.usable() {
+            return;
+        }
+        let has_altitude = gps.fix == FixType::ThreeD;
+        let horizontal_sigma = GPS_HORIZONTAL_SIGMA * gps.hdop.max(1.0);
+
+        let Some((lat0, lon0)) = self.origin else {
+            // first fix defines the local frame, start from it directly
+            self.origin = Some((gps.latitude, gps.longitude));
+            self.x[NORTH] = 0.0;
+            self.x[EAST] = 0.0;
+            self.x[V_NORTH] = gps.velocity[0];
+            self.x[V_EAST] = gps.velocity[1];
+            self.x[V_UP] = gps.velocity[2];
+            self.p[NORTH][NORTH] = horizontal_sigma * horizontal_sigma;
+            self.p[EAST][EAST] = horizontal_sigma * horizontal_sigma;
+            self.p[V_NORTH][V_NORTH] = GPS_VELOCITY_SIGMA * GPS_VELOCITY_SIGMA;
+            self.p[V_EAST][V_EAST] = GPS_VELOCITY_SIGMA * GPS_VELOCITY_SIGMA;
+            self.p[V_UP][V_UP] = GPS_VELOCITY_SIGMA * GPS_VELOCITY_SIGMA;
+
+            // baro may have initialized altitude already, in that case let the update sort out the offset
+            if !self.altitude_initialized && has_altitude {
+                self.x[UP] = gps.altitude;
+                self.p[UP][UP] = GPS_VERTICAL_SIGMA * GPS_VERTICAL_SIGMA;
+                self.altitude_initialized = true;
+            }
+            self.last_gps = Some(gps.time_stamp);
+            return;
+        };
+
+        let [north, east] = to_local(lat0, lon0, gps.latitude, gps.longitude);
+        let measurements = [
+            (NORTH, north, horizontal_sigma),
+            (EAST, east, horizontal_sigma),
+            (UP, gps.altitude, GPS_VERTICAL_SIGMA),
+            (V_NORTH, gps.velocity[0], GPS_VELOCITY_SIGMA),
+            (V_EAST, gps.velocity[1], GPS_VELOCITY_SIGMA),
+            (V_UP, gps.velocity[2], GPS_VELOCITY_SIGMA),
+        ];
+
+        let mut accepted = false;
+        for (state, z, sigma) in measurements {
+            if state == UP && !has_altitude {
+                continue;
+            }
+            let mut h = [0.0; N];
+            h[state] = 1.0;
+            accepted |= self.update_scalar(&h, z, sigma * sigma);
+        }
+
+        if accepted {
+            self.last_gps = Some(gps.time_stamp);
+        }
+    }
+
+    /// correct the vertical state with barometric altitude, the barometer offset against gps is estimated
+    pub fn update_baro(&mut self, baro: &VerticalState) {
+        if !self.altitude_initialized {
+            self.x[UP] = baro.altitude;
+            self.p[UP][UP] = BARO_SIGMA * BARO_SIGMA;
+            self.altitude_initialized = true;
+            return;
+        }
+
+        // baro altitude = true altitude + offset
+        let mut h = [0.0; N];
+        h[UP] = 1.0;
+        h[BARO_BIAS] = 1.0;
+        self.update_scalar(&h, baro.altitude, BARO_SIGMA * BARO_SIGMA);
+    }
+
+    fn gps_lost(&self, now: u32) -> bool {
+        match self.last_gps {
+            Some(last) => now.wrapping_sub(last) > GPS_TIMEOUT,
+            None => true,
+        }
+    }
+
+    /// sequential scalar measurement update, returns false if the measurement was gated out
+    fn update_scalar(&mut self, h: &[f32; N], z: f32, r: f32) -> bool {
+        let mut ph = [0.0; N];
+        for (ph, row) in ph.iter_mut().zip(&self.p) {
+            *ph = dot(row, h);
+        }
+
+        let innovation = z - dot(h, &self.x);
+        let s = dot(h, &ph) + r;
+        if s <= 0.0 || innovation * innovation > INNOVATION_GATE * INNOVATION_GATE * s {
+            return false;
+        }
+
+        for i in 0..N {
+            let k = ph[i] / s;
+            self.x[i] += k * innovation;
+            for (p, ph) in self.p[i].iter_mut().zip(&ph) {
+                *p -= k * ph;
+            }
+        }
+        true
+    }
+}
+
+/// latitude/longitude (deg) to north/east offsets (m) from an origin on a local tangent plane
+pub fn to_local(lat0: f64, lon0: f64, lat: f64, lon: f64) -> [f32; 2] {
+    let north = (lat - lat0).to_radians() * EARTH_RADIUS;
+    let east = (lon - lon0).to_radians() * EARTH_RADIUS * cos(lat0.to_radians());
+    [north as f32, east as f32]
+}
+
+/// north/east offsets (m) from an origin back to latitude/longitude (deg)
+pub fn to_geodetic(lat0: f64, lon0: f64, north: f32, east: f32) -> (f64, f64) {
+    let lat = lat0 + (north as f64 / EARTH_RADIUS).to_degrees();
+    let lon = lon0 + (east as f64 / (EARTH_RADIUS * cos(lat0.to_radians()))).to_degrees();
+    (lat, lon)
+}
+
+/// rotate body frame specific force into north/east/up and remove gravity
+/// the attitude filter earth frame is north/west/up, so y is flipped
+fn local_acceleration(q: [f32; 4], accel: [f32; 3]) -> [f32; 3] {
+    let [q0, q1, q2, q3] = q;
+    let [ax, ay, az] = accel;
+    let x = ax * (1.0 - 2.0 * (q2 * q2 + q3 * q3)) + ay * 2.0 * (q1 * q2 - q0 * q3) + az * 2.0 * (q1 * q3 + q0 * q2);
+    let y = ax * 2.0 * (q1 * q2 + q0 * q3) + ay * (1.0 - 2.0 * (q1 * q1 + q3 * q3)) + az * 2.0 * (q2 * q3 - q0 * q1);
+    let z = ax * 2.0 * (q1 * q3 - q0 * q2) + ay * 2.0 * (q2 * q3 + q0 * q1) + az * (1.0 - 2.0 * (q1 * q1 + q2 * q2));
+    [x, -y, z - GRAVITY]
+}
+
+fn dot(a: &[f32; N], b: &[f32; N]) -> f32 {
+    a.iter().zip(b).map(|(a, b)| a * b).sum()
+}
+
+fn identity() -> [[f32; N]; N] {
+    let mut m = [[0.0; N]; N];
+    for (i, row) in m.iter_mut().enumerate() {
+        row[i] = 1.0;
+    }
+    m
+}
+
+fn transpose(m: &[[f32; N]; N]) -> [[f32; N]; N] {
+    let mut t = [[0.0; N]; N];
+    for i in 0..N {
+        for j in 0..N {
+            t[j][i] = m[i][j];
+        }
+    }
+    t
+}
+
+fn mul(a: &[[f32; N]; N], b: &[[f32; N]; N]) -> [[f32; N]; N] {
+    let mut c = [[0.0; N]; N];
+    for i in 0..N {
+        for j in 0..N {
+            c[i][j] = (0..N).map(|k| a[i][k] * b[k][j]).sum();
+        }
+    }
+    c
+}
@@ -0,0 +1,571 @@
+// telemetry packets for the downlink, each defined once in the packets! invocation below, which generates
+// the packet struct, its encoder, its decoder for the ground (std only), and the field names, types, and units
+// a packet is its id byte then its fields little endian in definition order, no padding
+// ids have the top bit set, a frame with it clear is a compact position frame, see compact
+
+/// longest packet, sizes the radio frame buffer
+pub const MAX_PACKET_LEN: usize = 48;
+
+/// first byte of every packet, the rest of the byte is the packet id
+pub const PACKET_FLAG: u8 = 0x80;
+
+/// ssdv image packet id, the id then one ssdv packet without its sync byte, ssdv::PACKET_LEN long
+/// sent from its own buffer, it doesn't fit MAX_PACKET_LEN or the packets! fields
+pub const IMAGE_PACKET_ID: u8 = PACKET_FLAG | 0x7F;
+
+/// logged record sent down on request, the id, the stream id, then the record as it is in the log, see history
+/// sent from its own buffer like the image packets, its length is the record's
+pub const HISTORY_PACKET_ID: u8 = PACKET_FLAG | 0x7E;
+
+/// Wire type of a field
+#[derive(Copy, Clone, PartialEq, Eq, Debug, defmt::Format)]
+pub enum FieldKind {
+    Bool,
+    U8,
+    U16,
+    U32,
+    I8,
+    I16,
+    I32,
+    F32,
+    F64,
+    /// Temperature, an i8 of 0.25 deg C steps
+    Temperature,
+    /// Voltage, a u16 of 10 mV steps
+    Voltage,
+    /// Name, Name::LEN bytes of utf8 padded with zeros
+    Name,
+}
+
+impl FieldKind {
+    pub const fn size(self) -> usize {
+        match self {
+            FieldKind::Bool | FieldKind::U8 | FieldKind::I8 | FieldKind::Temperature => 1,
+            FieldKind::U16 | FieldKind::I16 | FieldKind::Voltage => 2,
+            FieldKind::U32 | FieldKind::I32 | FieldKind::F32 => 4,
+            FieldKind::F64 => 8,
+            FieldKind::Name => Name::LEN,
+        }
+    }
+}
+
+/// Name, wire type, and unit of one packet field, for ground tools that print or store packets generically
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct FieldInfo {
+    pub name: &'static str,
+    pub kind: FieldKind,
+    /// empty for counts, flags, and codes
+    pub unit: &'static str,
+}
+
+/// A value that can be a packet field
+pub trait Field: Copy {
+    const KIND: FieldKind;
+    const SIZE: usize = Self::KIND.size();
+
+    /// write into exactly SIZE bytes
+    fn put(self, bytes: &mut [u8]);
+    /// read from exactly SIZE bytes
+    fn get(bytes: &[u8]) -> Self;
+}
+
+macro_rules! number_fields {
+    ($($ty:ty => $kind:ident),* $(,)?) => {
+        $(
+            impl Field for $ty {
+                const KIND: FieldKind = FieldKind::$kind;
+
+                fn put(self, bytes: &mut [u8]) {
+                    bytes.copy_from_slice(&self.to_le_bytes());
+                }
+
+                fn get(bytes: &[u8]) -> Self {
+                    let mut le = [0; size_of::<$ty>()];
+                    le.copy_from_slice(bytes);
+                    <$ty>::from_le_bytes(le)
+                }
+            }
+        )*
+    };
+}
+
+number_fields!(u8 => U8, u16 => U16, u32 => U32, i8 => I8, i16 => I16, i32 => I32, f32 => F32, f64 => F64);
+
+impl Field for bool {
+    const KIND: FieldKind = FieldKind::Bool;
+
+    fn put(self, bytes: &mut [u8]) {
+        bytes[0] = self as u8;
+    }
+
+    fn get(bytes: &[u8]) -> Self {
+        bytes[0] != 0
+    }
+}
+
+// status floats that don't need f32 go down quantized, a quarter of the bytes for a temperature and half for a voltage
+// readings past the range saturate at its ends, an unknown (nan) reading has its own code and comes back as nan
+
+/// Temperature field, deg C, sent in 0.25 deg C steps from -31.75 to 31.75 deg C
+#[derive(Copy, Clone, PartialEq, Debug, defmt::Format)]
+pub struct Temperature(pub f32);
+
+impl Temperature {
+    pub const STEP: f32 = 0.25;
+    const UNKNOWN: i8 = i8::MIN;
+}
+
+impl Field for Temperature {
+    const KIND: FieldKind = FieldKind::Temperature;
+
+    fn put(self, bytes: &mut [u8]) {
+        let code = if self.0.is_nan() {
+            Self::UNKNOWN
+        } else {
+            // the unknown code is left out of the range
+            libm::roundf(self.0 / Self::STEP).clamp(-(i8::MAX as f32), i8::MAX as f32) as i8
+        };
+        bytes[0] = code as u8;
+    }
+
+    fn get(bytes: &[u8]) -> Self {
+        match bytes[0] as i8 {
+            Self::UNKNOWN => Temperature(f32::NAN),
+            code => Temperature(code as f32 * Self::STEP),
+        }
+    }
+}
+
+/// Voltage field, V, sent in 10 mV steps from 0 to 655.34 V
+#[derive(Copy, Clone, PartialEq, Debug, defmt::Format)]
+pub struct Voltage(pub f32);
+
+impl Voltage {
+    pub const STEP: f32 = 0.01;
+    const UNKNOWN: u16 = u16::MAX;
+}
+
+impl Field for Voltage {
+    const KIND: FieldKind = FieldKind::Voltage;
+
+    fn put(self, bytes: &mut [u8]) {
+        let code = if self.0.is_nan() {
+            Self::UNKNOWN
+        } else {
+            libm::roundf(self.0 / Self::STEP).clamp(0.0, (u16::MAX - 1) as f32) as u16
+        };
+        bytes.copy_from_slice(&code.to_le_bytes());
+    }
+
+    fn get(bytes: &[u8]) -> Self {
+        match u16::get(bytes) {
+            Self::UNKNOWN => Voltage(f32::NAN),
+            code => Voltage(code as f32 * Self::STEP),
+        }
+    }
+}
+
+/// Short text field, a longer string keeps its end, where a path has its file name
+#[derive(Copy, Clone, PartialEq, defmt::Format)]
+pub struct Name(pub [u8; Name::LEN]);
+
+impl Name {
+    pub const LEN: usize = 16;
+
+    pub fn new(text: &str) -> Self {
+        let mut start = text.len().saturating_sub(Self::LEN);
+        while !text.is_char_boundary(start) {
+            start += 1;
+        }
+        let tail = &text.as_bytes()[start..];
+        let mut bytes = [0; Self::LEN];
+        bytes[..tail.len()].copy_from_slice(tail);
+        Name(bytes)
+    }
+
+    pub fn as_str(&self) -> &str {
+        let len = self.0.iter().position(|&b| b == 0).unwrap_or(Self::LEN);
+        core::str::from_utf8(&self.0[..len]).unwrap_or("<invalid utf8>")
+    }
+}
+
+impl core::fmt::Debug for Name {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{:?}", self.as_str())
+    }
+}
+
+impl Field for Name {
+    const KIND: FieldKind = FieldKind::Name;
+
+    fn put(self, bytes: &mut [u8]) {
+        bytes.copy_from_slice(&self.0);
+    }
+
+    fn get(bytes: &[u8]) -> Self {
+        let mut name = [0; Self::LEN];
+        name.copy_from_slice(bytes);
+        Name(name)
+    }
+}
+
+/// A telemetry packet, implemented by packets!
+pub trait Packet: Sized {
+    const ID: u8;
+    const NAME: &'static str;
+    /// id byte included
+    const LEN: usize;
+    const FIELDS: &'static [FieldInfo];
+
+    /// write the packet to the start of frame, returns its length, None if frame is too short
+    fn encode(&self, frame: &mut [u8]) -> Option<usize>;
+
+    /// read a packet of this type, the whole frame
+    #[cfg(feature = "std")]
+    fn decode(frame: &[u8]) -> Result<Self, DecodeError>;
+}
+
+/// Why a frame couldn't be decoded
+#[cfg(feature = "std")]
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum DecodeError {
+    Empty,
+    /// not a packet id this build knows, the ground tools are older than the firmware
+    UnknownId(u8),
+    /// the frame doesn't match the packet's length, received id and length
+    Length(u8, usize),
+    Position(crate::compact::DecodeError),
+}
+
+#[cfg(feature = "std")]
+impl std::fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DecodeError::Empty => write!(f, "empty frame"),
+            DecodeError::UnknownId(id) => write!(f, "unknown packet id {id:#04x}"),
+            DecodeError::Length(id, len) => write!(f, "packet {id:#04x} with the wrong length, {len} bytes"),
+            DecodeError::Position(e) => write!(f, "position frame: {e}"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for DecodeError {}
+
+macro_rules! packets {
+    ($(
+        $(#[$meta:meta])*
+        $name:ident = $id:literal {
+            $($(#[$field_meta:meta])* $field:ident: $ty:ty = $unit:literal),* $(,)?
+        }
+    )*) => {
+        $(
+            $(#[$meta])*
+            #[derive(Copy, Clone, PartialEq, Debug, defmt::Format)]
+            pub struct $name {
+                $($(#[$field_meta])* pub $field: $ty,)*
+            }
+
+            impl Packet for $name {
+                const ID: u8 = PACKET_FLAG | $id;
+                const NAME: &'static str = stringify!($name);
+                const LEN: usize = 1 $(+ <$ty as Field>::SIZE)*;
+                const FIELDS: &'static [FieldInfo] = &[$(FieldInfo {
+                    name: stringify!($field),
+                    kind: <$ty as Field>::KIND,
+                    unit: $unit,
+                }),*];
+
+                fn encode(&self, frame: &mut [u8]) -> Option<usize> {
+                    let frame = frame.get_mut(..Self::LEN)?;
+                    frame[0] = Self::ID;
+                    let mut at = 1;
+                    $(
+                        self.$field.put(&mut frame[at..at + <$ty as Field>::SIZE]);
+                        at += <$ty as Field>::SIZE;
+                    )*
+                    Some(at)
+                }
+
+                #[cfg(feature = "std")]
+                #[allow(unused_assignments)]
+                fn decode(frame: &[u8]) -> Result<Self, DecodeError> {
+                    if frame.len() != Self::LEN {
+                        return Err(DecodeError::Length(Self::ID, frame.len()));
+                    }
+                    let mut at = 1;
+                    Ok(Self {
+                        $($field: {
+                            let value = <$ty as Field>::get(&frame[at..at + <$ty as Field>::SIZE]);
+                            at += <$ty as Field>::SIZE;
+                            value
+                        },)*
+                    })
+                }
+            }
+
+            const _: () = assert!(<$name as Packet>::LEN <= MAX_PACKET_LEN);
+        )*
+
+        /// id, name, and fields of every packet
+        pub const PACKETS: &[(u8, &str, &[FieldInfo])] = &[$((<$name>::ID, <$name>::NAME, <$name>::FIELDS)),*];
+
+        /// Any downlink frame, decoded
+        #[cfg(feature = "std")]
+        #[derive(Copy, Clone, PartialEq, Debug)]
+        pub enum Frame {
+            Position(crate::compact::CompactPosition),
+            Image(crate::ssdv::ReceivedPacket),
+            History(crate::history::HistoryRecord),
+            $($name($name),)*
+        }
+
+        /// decode a frame by its first byte, positions need the decoder that has seen the earlier frames
+        #[cfg(feature = "std")]
+        pub fn decode(frame: &[u8], positions: &mut crate::compact::PositionDecoder) -> Result<Frame, DecodeError> {
+            let id = *frame.first().ok_or(DecodeError::Empty)?;
+            if id & PACKET_FLAG == 0 {
+                return positions.decode(frame).map(Frame::Position).map_err(DecodeError::Position);
+            }
+            match id {
+                IMAGE_PACKET_ID => crate::ssdv::ReceivedPacket::from_frame(&frame[1..]).map(Frame::Image).ok_or(DecodeError::Length(id, frame.len())),
+                HISTORY_PACKET_ID => crate::history::HistoryRecord::from_frame(&frame[1..]).map(Frame::History).ok_or(DecodeError::Length(id, frame.len())),
+                $($name::ID => $name::decode(frame).map(Frame::$name),)*
+                _ => Err(DecodeError::UnknownId(id)),
+            }
+        }
+    };
+}
+
+packets! {
+    /// position beacon in low voltage safe mode
+    BeaconPacket = 0x01 {
+        latitude: f64 = "deg",
+        longitude: f64 = "deg",
+        altitude: f32 = "m",
+        voltage: Voltage = "V",
+        time_stamp: u32 = "us",
+    }
+
+    /// active faults, load, drop counts, and actuation channel state, the fault events themselves come down as event packets
+    HealthPacket = 0x02 {
+        faults: u16 = "",
+        cpu_load: u8 = "%",
+        loop_overruns: u16 = "",
+        dropped_baro: u32 = "",
+        dropped_imu: u32 = "",
+        dropped_gps: u32 = "",
+        dropped_log: u32 = "",
+        dropped_telemetry: u32 = "",
+        /// actuation channel bits, Channel::id
+        continuity: u8 = "",
+        armed: u8 = "",
+        /// antenna the rf switch has selected, Antenna::id
+        antenna: u8 = "",
+        /// battery temperature
+        battery_temperature: Temperature = "C",
+        time_stamp: u32 = "us",
+    }
+
+    /// flight event, code as in the log, fault and active are set for a fault event
+    EventPacket = 0x03 {
+        event: u8 = "",
+        /// fault flag bit, 0 for other events
+        fault: u16 = "",
+        active: bool = "",
+        time_stamp: u32 = "us",
+    }
+
+    /// launch site reference, sent on arming
+    SessionPacket = 0x04 {
+        ground_pressure: f32 = "hPa",
+        ground_altitude: f32 = "m",
+        reset_reason: u8 = "",
+        boot_count: u32 = "",
+        time_stamp: u32 = "us",
+    }
+
+    /// landing prediction
+    PredictionPacket = 0x05 {
+        burst_altitude: f32 = "m",
+        time_to_burst: f32 = "s",
+        descent_duration: f32 = "s",
+        landing_latitude: f64 = "deg",
+        landing_longitude: f64 = "deg",
+        time_stamp: u32 = "us",
+    }
+
+    /// config parameter reply, value is the raw bits of the key's type
+    ConfigPacket = 0x06 {
+        key: u8 = "",
+        value: u32 = "",
+        pending: bool = "",
+        time_stamp: u32 = "us",
+    }
+
+    /// reset reason and boot count at startup, the version strings are in the log and on the console
+    BootPacket = 0x07 {
+        reset_reason: u8 = "",
+        boot_count: u32 = "",
+        dirty: bool = "",
+    }
+
+    /// panic location from the previous boot, the message is in the log and on the console
+    PanicPacket = 0x08 {
+        /// end of the source file path
+        file: Name = "",
+        line: u32 = "",
+        uptime: u32 = "ms",
+    }
+
+    /// firmware transfer progress, error is 0 or the UpdateError plus one
+    UpdatePacket = 0x09 {
+        size: u32 = "B",
+        next_offset: u32 = "B",
+        error: u8 = "",
+        time_stamp: u32 = "us",
+    }
+
+    /// assistance upload progress, error is 0 or the AssistError plus one
+    AssistPacket = 0x0A {
+        size: u32 = "B",
+        next_offset: u32 = "B",
+        messages: u16 = "",
+        rejected: u16 = "",
+        error: u8 = "",
+        time_stamp: u32 = "us",
+    }
+
+    /// battery and solar charger input, charge is the ChargeState id
+    PowerPacket = 0x0B {
+        battery_voltage: Voltage = "V",
+        solar_voltage: Voltage = "V",
+        solar_current: f32 = "A",
+        charge: u8 = "",
+        time_stamp: u32 = "us",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a field's bytes and the value read back from them
+    fn field<F: Field>(value: F) -> (Vec<u8>, F) {
+        let mut bytes = vec![0; F::SIZE];
+        value.put(&mut bytes);
+        let value = F::get(&bytes);
+        (bytes, value)
+    }
+
+    #[test]
+    fn temperature_saturates_and_keeps_unknown_apart() {
+        assert_eq!(field(Temperature(21.3)), (vec![85], Temperature(21.25)));
+        assert_eq!(field(Temperature(-0.1)), (vec![0], Temperature(0.0)));
+        assert_eq!(field(Temperature(100.0)), (vec![127], Temperature(31.75)));
+        // the lowest code is unknown, so the range stops one short of it
+        assert_eq!(field(Temperature(-100.0)), (vec![0x81], Temperature(-31.75)));
+        let (bytes, unknown) = field(Temperature(f32::NAN));
+        assert_eq!(bytes, [0x80]);
+        assert!(unknown.0.is_nan());
+    }
+
+    #[test]
+    fn voltage_saturates_and_keeps_unknown_apart() {
+        let (bytes, voltage) = field(Voltage(11.1));
+        assert_eq!(bytes, 1110u16.to_le_bytes());
+        assert!((voltage.0 - 11.1).abs() < Voltage::STEP / 2.0);
+        assert_eq!(field(Voltage(-3.0)), (vec![0, 0], Voltage(0.0)));
+        let (bytes, voltage) = field(Voltage(1000.0));
+        assert_eq!(bytes, 0xFFFEu16.to_le_bytes());
+        assert!((voltage.0 - 655.34).abs() < Voltage::STEP / 2.0);
+        let (bytes, unknown) = field(Voltage(f32::NAN));
+        assert_eq!(bytes, [0xFF, 0xFF]);
+        assert!(unknown.0.is_nan());
+    }
+
+    #[test]
+    fn name_keeps_the_end_on_a_char_boundary() {
+        assert_eq!(Name::new("main.rs").as_str(), "main.rs");
+        assert_eq!(Name::new("src/flight/control.rs").as_str(), "light/control.rs");
+        // all 16 bytes used, no terminating zero
+        assert_eq!(Name::new("0123456789abcdef").as_str(), "0123456789abcdef");
+        // the 16th byte from the end is the second of a two byte char, which is dropped whole
+        assert_eq!(Name::new("a\u{e9}/mod/handler.rs").as_str(), "/mod/handler.rs");
+        assert_eq!(Name::new("\u{e9}\u{e9}\u{e9}\u{e9}\u{e9}\u{e9}\u{e9}\u{e9}\u{e9}").as_str(), "\u{e9}\u{e9}\u{e9}\u{e9}\u{e9}\u{e9}\u{e9}\u{e9}");
+        assert_eq!(field(Name::new("bsp.rs")).1.as_str(), "bsp.rs");
+    }
+
+    #[test]
+    fn every_packet_fits_the_frame_buffer() {
+        for (i, &(id, name, fields)) in PACKETS.iter().enumerate() {
+            let len = 1 + fields.iter().map(|field| field.kind.size()).sum::<usize>();
+            assert!(len <= MAX_PACKET_LEN, "{name} is {len} bytes");
+            assert!(id & PACKET_FLAG != 0 && id != IMAGE_PACKET_ID && id != HISTORY_PACKET_ID, "{name} id {id:#04x}");
+            assert!(PACKETS[i + 1..].iter().all(|&(other, _, _)| other != id), "{name} id {id:#04x} used twice");
+        }
+    }
+
+    // encoded at its length and decoded back
+    #[cfg(feature = "std")]
+    fn round_trip<P: Packet>(packet: &P) -> P {
+        let mut frame = [0; MAX_PACKET_LEN];
+        assert_eq!(packet.encode(&mut frame), Some(P::LEN));
+        assert_eq!(frame[0], P::ID);
+        assert_eq!(packet.encode(&mut frame[..P::LEN - 1]), None);
+        assert_eq!(P::decode(&frame[..P::LEN - 1]).err(), Some(DecodeError::Length(P::ID, P::LEN - 1)));
+        P::decode(&frame[..P::LEN]).unwrap()
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn packets_round_trip() {
+        // whole quarter degrees, and a voltage whose code times the step is the same float again
+        let beacon = BeaconPacket { latitude: 40.4237, longitude: -86.9212, altitude: 31_204.5, voltage: Voltage(2.5), time_stamp: 1 };
+        assert_eq!(round_trip(&beacon), beacon);
+        let health = HealthPacket {
+            faults: 0x0104, cpu_load: 37, loop_overruns: 2, dropped_baro: 3, dropped_imu: 4, dropped_gps: 5, dropped_log: 6,
+            dropped_telemetry: 7, continuity: 0x03, armed: 0x01, antenna: 1, battery_temperature: Temperature(-12.75), time_stamp: 2,
+        };
+        assert_eq!(round_trip(&health), health);
+        let event = EventPacket { event: 9, fault: 0x0010, active: true, time_stamp: 3 };
+        assert_eq!(round_trip(&event), event);
+        let session = SessionPacket { ground_pressure: 991.25, ground_altitude: 187.0, reset_reason: 2, boot_count: 41, time_stamp: 4 };
+        assert_eq!(round_trip(&session), session);
+        let prediction = PredictionPacket {
+            burst_altitude: 32_000.0, time_to_burst: 1_800.0, descent_duration: 2_400.0, landing_latitude: 40.9,
+            landing_longitude: -85.3, time_stamp: 5,
+        };
+        assert_eq!(round_trip(&prediction), prediction);
+        let config = ConfigPacket { key: 12, value: 0xDEAD_BEEF, pending: false, time_stamp: 6 };
+        assert_eq!(round_trip(&config), config);
+        let boot = BootPacket { reset_reason: 5, boot_count: 42, dirty: true };
+        assert_eq!(round_trip(&boot), boot);
+        let panic = PanicPacket { file: Name::new("src/main.rs"), line: 1_234, uptime: 56_789 };
+        assert_eq!(round_trip(&panic), panic);
+        let update = UpdatePacket { size: 200_000, next_offset: 4_096, error: 0, time_stamp: 7 };
+        assert_eq!(round_trip(&update), update);
+        let assist = AssistPacket { size: 9_000, next_offset: 8_000, messages: 31, rejected: 1, error: 2, time_stamp: 8 };
+        assert_eq!(round_trip(&assist), assist);
+        let power = PowerPacket { battery_voltage: Voltage(2.5), solar_voltage: Voltage(f32::NAN), solar_current: 0.125, charge: 2, time_stamp: 9 };
+        let received = round_trip(&power);
+        assert!(received.solar_voltage.0.is_nan());
+        assert_eq!(received.battery_voltage, power.battery_voltage);
+        assert_eq!((received.solar_current, received.charge, received.time_stamp), (0.125, 2, 9));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn frames_decode_by_their_first_byte() {
+        let mut positions = crate::compact::PositionDecoder::new();
+        let boot = BootPacket { reset_reason: 1, boot_count: 2, dirty: false };
+        let mut frame = [0; MAX_PACKET_LEN];
+        let len = boot.encode(&mut frame).unwrap();
+        assert_eq!(decode(&frame[..len], &mut positions), Ok(Frame::BootPacket(boot)));
+        assert_eq!(decode(&[], &mut positions), Err(DecodeError::Empty));
+        assert_eq!(decode(&[PACKET_FLAG | 0x70], &mut positions), Err(DecodeError::UnknownId(PACKET_FLAG | 0x70)));
+        assert_eq!(decode(&[IMAGE_PACKET_ID, 0], &mut positions), Err(DecodeError::Length(IMAGE_PACKET_ID, 2)));
+        // the top bit clear is a position frame, a delta before any key frame
+        assert_eq!(decode(&[0; 10], &mut positions), Err(DecodeError::Position(crate::compact::DecodeError::MissingKey(0))));
+    }
+}
@@ -0,0 +1,107 @@
+// uplinked commands held back until a condition is met, a mission elapsed time or an altitude reached, so a cutdown
+// can be set up for a time or height the payload may be out of radio range at
+// the control task checks the table every loop and carries a due command out as if it had just been received, with
+// the same checks, a command refused then is refused at its time too
+// mission elapsed time counts from launch detection, the table follows it across the wraps of the us time stamps, so
+// it has to be checked more often than every 71.6 minutes
+// the table is in ram, pending commands don't survive a reset
+
+use crate::command::Command;
+use crate::met::MetClock;
+
+/// most commands waiting at once
+pub const PENDING_COMMANDS: usize = 8;
+
+/// When a pending command is carried out
+#[derive(Copy, Clone, PartialEq, Debug, defmt::Format)]
+pub enum Condition {
+    /// mission elapsed time reached, s
+    Met(u32),
+    /// filtered baro altitude above sea level reached, m
+    Altitude(f32),
+}
+
+/// A command waiting on its condition
+#[derive(Copy, Clone, PartialEq, defmt::Format)]
+pub struct PendingCommand {
+    /// given when the command is added, for cancelling it
+    pub id: u8,
+    pub command: Command,
+    pub condition: Condition,
+}
+
+/// Table of commands waiting on their conditions
+pub struct PendingCommands<const N: usize> {
+    commands: [Option<PendingCommand>; N],
+    next_id: u8,
+    met: MetClock,
+}
+
+impl<const N: usize> Default for PendingCommands<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> PendingCommands<N> {
+    pub const fn new() -> Self {
+        Self {
+            commands: [None; N],
+            next_id: 1,
+            met: MetClock::new(),
+        }
+    }
+
+    /// hold a command until its condition, returns its id, None if the table is full
+    pub fn add(&mut self, command: Command, condition: Condition) -> Option<u8> {
+        let slot = self.commands.iter().position(Option::is_none)?;
+        // ids are never 0 and skip the ones still waiting, so a cancel can't hit a newer command by mistake
+        let mut id = self.next_id;
+        while id == 0 || self.commands.iter().flatten().any(|pending| pending.id == id) {
+            id = id.wrapping_add(1);
+        }
+        self.next_id = id.wrapping_add(1);
+        self.commands[slot] = Some(PendingCommand { id, command, condition });
+        Some(id)
+    }
+
+    /// drop a waiting command, None if there is none with the id
+    pub fn cancel(&mut self, id: u8) -> Option<PendingCommand> {
+        self.commands.iter_mut().find(|slot| slot.is_some_and(|pending| pending.id == id))?.take()
+    }
+
+    /// drop every waiting command, returns how many there were
+    pub fn cancel_all(&mut self) -> usize {
+        self.commands.iter_mut().filter_map(Option::take).count()
+    }
+
+    /// waiting commands, oldest slot first
+    pub fn pending(&self) -> impl Iterator<Item = &PendingCommand> {
+        self.commands.iter().flatten()
+    }
+
+    /// the first command whose condition is met, left in the table until cancelled so one that can't be queued yet
+    /// is tried again, launch is the uptime time stamp of launch detection, None before launch, altitude None until
+    /// the first baro sample
+    pub fn due(&mut self, launch: Option<u32>, altitude: Option<f32>, time_stamp: u32) -> Option<PendingCommand> {
+        let met = self.met.update(launch, time_stamp);
+        self.pending()
+            .find(|pending| match pending.condition {
+                Condition::Met(seconds) => met.is_some_and(|met| met >= seconds as u64 * 1_000_000),
+                Condition::Altitude(target) => altitude.is_some_and(|altitude| altitude >= target),
+            })
+            .copied()
+    }
+
+    /// seconds until the next command waiting on a mission elapsed time is due, as of the last check, None if none is
+    /// or the payload hasn't launched, so a sleep can end in time
+    pub fn until_met(&self) -> Option<u32> {
+        let met = self.met.last()?;
+        self.pending()
+            .filter_map(|pending| match pending.condition {
+                Condition::Met(seconds) => Some((seconds as u64).saturating_sub(met / 1_000_000) as u32),
+                Condition::Altitude(_) => None,
+            })
+            .min()
+    }
+}
@@ -0,0 +1,204 @@
+/// Low supply voltage detector with hysteresis
+/// the voltage has to stay past a threshold for a while before the state changes,
+/// so a short sag (radio transmit, cold cells waking up) doesn't trip it and it doesn't chatter at the threshold
+pub struct LowVoltageDetector {
+    /// voltage below which the supply counts as low, V
+    low: f32,
+    /// voltage above which a low supply counts as recovered, V
+    recover: f32,
+    /// how long a condition must hold before the state changes, us
+    duration: u32,
+    /// time stamp of the first sample in the current run on the other side of the threshold
+    since: Option<u32>,
+    active: bool,
+}
+
+impl LowVoltageDetector {
+    pub const fn new(low: f32, recover: f32, duration: u32) -> Self {
+        Self {
+            low,
+            recover,
+            duration,
+            since: None,
+            active: false,
+        }
+    }
+
+    /// true while the supply is low
+    pub fn active(&self) -> bool {
+        self.active
+    }
+
+    /// feed a voltage sample (V), returns the new state when it changes
+    pub fn update(&mut self, voltage: f32, time_stamp: u32) -> Option<bool> {
+        // a nan reading can't be trusted either way, treat it as low
+        let crossing = if self.active { voltage > self.recover } else { voltage < self.low || voltage.is_nan() };
+        if !crossing {
+            self.since = None;
+            return None;
+        }
+
+        let since = *self.since.get_or_insert(time_stamp);
+        if time_stamp.wrapping_sub(since) < self.duration {
+            return None;
+        }
+
+        self.since = None;
+        self.active = !self.active;
+        Some(self.active)
+    }
+}
+
+/// Battery cold protection with hysteresis
+/// lithium cells lose most of their current capability in the cold, a heavy load on a cold pack can sag it far enough
+/// to brown out the flight computer, so high current loads are held off until the battery has warmed back up
+pub struct ColdProtection {
+    /// margin above the limit before the battery counts as warm again, deg C
+    hysteresis: f32,
+    active: bool,
+}
+
+impl ColdProtection {
+    pub const fn new(hysteresis: f32) -> Self {
+        Self { hysteresis, active: false }
+    }
+
+    /// true while the battery is too cold for high current loads
+    pub fn active(&self) -> bool {
+        self.active
+    }
+
+    /// feed a battery temperature and the limit (deg C), returns the new state when it changes
+    /// the limit is passed in so a config change takes effect at the next sample, a nan reading changes nothing
+    pub fn update(&mut self, temperature: f32, limit: f32) -> Option<bool> {
+        let cold = if self.active { temperature <= limit + self.hysteresis } else { temperature < limit };
+        if cold == self.active || temperature.is_nan() {
+            return None;
+        }
+        self.active = cold;
+        Some(cold)
+    }
+}
+
+/// What the solar charger says it's doing, from its charging and done status pins
+#[derive(Copy, Clone, PartialEq, Eq, Debug, defmt::Format)]
+pub enum ChargeState {
+    /// neither pin asserted, the array isn't giving enough to charge, night or shaded
+    NoInput,
+    Charging,
+    /// the battery is full, the charger is only topping it up
+    Done,
+    /// both pins asserted, the charger's fault indication (bad cell, timer expired, or out of its temperature range)
+    Fault,
+}
+
+impl ChargeState {
+    pub const ALL: [ChargeState; 4] = [ChargeState::NoInput, ChargeState::Charging, ChargeState::Done, ChargeState::Fault];
+
+    /// from the status pins, true where the charger pulls the pin active
+    pub fn from_pins(charging: bool, done: bool) -> Self {
+        match (charging, done) {
+            (false, false) => ChargeState::NoInput,
+            (true, false) => ChargeState::Charging,
+            (false, true) => ChargeState::Done,
+            (true, true) => ChargeState::Fault,
+        }
+    }
+
+    pub fn from_id(id: u8) -> Option<Self> {
+        Self::ALL.get(id as usize).copied()
+    }
+
+    pub fn id(self) -> u8 {
+        self as u8
+    }
+}
+
+/// One reading of the solar charger input
+#[derive(Copy, Clone, PartialEq, Debug, defmt::Format)]
+pub struct ChargeInput {
+    /// array voltage at the charger input, V
+    pub voltage: f32,
+    /// current from the array into the charger, A
+    pub current: f32,
+    pub state: ChargeState,
+}
+
+/// Switchable loads in shedding order, the first is switched off first as the battery runs down
+/// and the last is kept as long as possible
+#[derive(Copy, Clone, PartialEq, Eq, Debug, defmt::Format)]
+pub enum Load {
+    Camera,
+    SecondaryPayload,
+    Heaters,
+    HighRateLogging,
+}
+
+impl Load {
+    pub const ALL: [Load; 4] = [Load::Camera, Load::SecondaryPayload, Load::Heaters, Load::HighRateLogging];
+}
+
+// resting cell voltage against state of charge for a li-ion cell, percent, ascending
+const CELL_CURVE: [(f32, f32); 7] = [(3.0, 0.0), (3.3, 5.0), (3.5, 15.0), (3.6, 30.0), (3.7, 50.0), (3.9, 75.0), (4.2, 100.0)];
+
+/// state of charge of a pack of series cells from its voltage, percent
+/// only meaningful when the pack isn't under a heavy load, the caller should average out transmit sags
+pub fn state_of_charge(voltage: f32, cells: u8) -> f32 {
+    let cell = voltage / cells as f32;
+    let (first, last) = (CELL_CURVE[0], CELL_CURVE[CELL_CURVE.len() - 1]);
+    if cell <= first.0 || cell.is_nan() {
+        return first.1;
+    }
+    if cell >= last.0 {
+        return last.1;
+    }
+
+    for pair in CELL_CURVE.windows(2) {
+        let ((v0, soc0), (v1, soc1)) = (pair[0], pair[1]);
+        if cell <= v1 {
+            return soc0 + (cell - v0) / (v1 - v0) * (soc1 - soc0);
+        }
+    }
+    last.1
+}
+
+/// Sheds loads one at a time in Load::ALL order as the state of charge falls below each load's threshold,
+/// and restores them in reverse once it climbs back past the threshold plus a hysteresis margin
+/// at most one load changes per update so every decision is seen and logged separately
+pub struct LoadShedder {
+    /// state of charge below which each load is shed, percent, in Load::ALL order and descending
+    thresholds: [f32; Load::ALL.len()],
+    /// margin above a threshold before its load is restored, percent
+    hysteresis: f32,
+    /// number of loads shed, always a prefix of Load::ALL
+    shed: usize,
+}
+
+impl LoadShedder {
+    pub const fn new(thresholds: [f32; Load::ALL.len()], hysteresis: f32) -> Self {
+        Self {
+            thresholds,
+            hysteresis,
+            shed: 0,
+        }
+    }
+
+    /// false while the load is shed
+    pub fn enabled(&self, load: Load) -> bool {
+        load as usize >= self.shed
+    }
+
+    /// feed a state of charge (percent), returns the load that changed and whether it is now enabled
+    pub fn update(&mut self, soc: f32) -> Option<(Load, bool)> {
+        if self.shed < Load::ALL.len() && soc < self.thresholds[self.shed] {
+            self.shed += 1;
+            return Some((Load::ALL[self.shed - 1], false));
+        }
+
+        if self.shed > 0 && soc > self.thresholds[self.shed - 1] + self.hysteresis {
+            self.shed -= 1;
+            return Some((Load::ALL[self.shed], true));
+        }
+        None
+    }
+}
@@ -0,0 +1,141 @@
+use libm::{expf, sqrtf};
+
+use crate::mission::FlightState;
+use crate::nav::to_geodetic;
+use crate::wind::WindEstimator;
+use crate::Prediction;
+
+/// scale height of the exponential atmosphere used for parachute descent, m
+const SCALE_HEIGHT: f32 = 7200.0;
+
+/// altitude step used when integrating the trajectory, m
+const STEP: f32 = 50.0;
+
+/// ascent rates below this are too small to extrapolate to burst, m/s
+const MIN_ASCENT_RATE: f32 = 0.5;
+
+/// Flight specific parameters for the landing prediction
+#[derive(Copy, Clone)]
+pub struct PredictorConfig {
+    /// expected burst altitude above sea level, m
+    pub burst_altitude: f32,
+    /// parachute descent rate at sea level, m/s, scales with air density on the way down
+    pub sea_level_descent_rate: f32,
+    /// ground elevation at the landing site, m
+    pub ground_altitude: f32,
+}
+
+/// Where the payload is now, as input to the prediction
+#[derive(Copy, Clone)]
+pub struct PredictorInput {
+    pub state: FlightState,
+    pub latitude: f64,
+    pub longitude: f64,
+    /// altitude above sea level, m
+    pub altitude: f32,
+    /// m/s, positive up
+    pub vertical_speed: f32,
+    /// highest altitude seen since launch, m, None before launch
+    pub max_altitude: Option<f32>,
+    pub time_stamp: u32,
+}
+
+impl PredictorConfig {
+    /// parachute descent rate at an altitude, terminal velocity goes with 1/sqrt(density)
+    pub fn descent_rate(&self, altitude: f32) -> f32 {
+        self.sea_level_descent_rate / sqrtf(expf(-altitude / SCALE_HEIGHT))
+    }
+
+    /// predict burst and landing from the current position, None on the pad, after landing, or while
+    /// the ascent rate is too small to extrapolate
+    pub fn predict(&self, input: &PredictorInput, wind: &WindEstimator) -> Option<Prediction> {
+        let mut drift = [0.0f32; 2];
+        let mut altitude = input.altitude;
+
+        let (burst_altitude, time_to_burst) = match input.state {
+            FlightState::Ascent => {
+                if input.vertical_speed < MIN_ASCENT_RATE {
+                    return None;
+                }
+                let burst_altitude = self.burst_altitude.max(altitude);
+
+                // drift up to burst at the current ascent rate
+                let mut time = 0.0;
+                while altitude < burst_altitude {
+                    let step = STEP.min(burst_altitude - altitude);
+                    let dt = step / input.vertical_speed;
+                    add_drift(&mut drift, wind, altitude + step / 2.0, dt);
+                    time += dt;
+                    altitude += step;
+                }
+                (burst_altitude, time)
+            }
+            // already past burst, the highest point seen is where it burst
+            FlightState::Descent => (input.max_altitude.map_or(altitude, |max| max.max(altitude)), 0.0),
+            FlightState::Pad | FlightState::Landed => return None,
+        };
+
+        // drift down to the ground under the parachute
+        let mut descent_duration = 0.0;
+        while altitude > self.ground_altitude {
+            let step = STEP.min(altitude - self.ground_altitude);
+            let middle = altitude - step / 2.0;
+            let dt = step / self.descent_rate(middle);
+            add_drift(&mut drift, wind, middle, dt);
+            descent_duration += dt;
+            altitude -= step;
+        }
+
+        let (landing_latitude, landing_longitude) = to_geodetic(input.latitude, input.longitude, drift[0], drift[1]);
+
+        Some(Prediction {
+            burst_altitude,
+            time_to_burst,
+            descent_duration,
+            landing_latitude,
+            landing_longitude,
+            time_stamp: input.time_stamp,
+        })
+    }
+}
+
+/// no wind data yet means no drift rather than no prediction, the vertical timeline is still useful
+fn add_drift(drift: &mut [f32; 2], wind: &WindEstimator, altitude: f32, dt: f32) {
+    if let Some(w) = wind.wind_at(altitude) {
+        drift[0] += w[0] * dt;
+        drift[1] += w[1] * dt;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: PredictorConfig = PredictorConfig { burst_altitude: 30_000.0, sea_level_descent_rate: 5.0, ground_altitude: 200.0 };
+
+    fn input(state: FlightState, altitude: f32, vertical_speed: f32, max_altitude: Option<f32>) -> PredictorInput {
+        PredictorInput { state, latitude: 40.4, longitude: -86.9, altitude, vertical_speed, max_altitude, time_stamp: 0 }
+    }
+
+    #[test]
+    fn descent_keeps_the_burst_altitude() {
+        let wind = WindEstimator::new();
+        let prediction = CONFIG.predict(&input(FlightState::Descent, 21_000.0, -20.0, Some(28_400.0)), &wind).unwrap();
+        assert_eq!(prediction.burst_altitude, 28_400.0);
+        assert_eq!(prediction.time_to_burst, 0.0);
+        // no wind profile, no drift
+        assert_eq!((prediction.landing_latitude, prediction.landing_longitude), (40.4, -86.9));
+    }
+
+    #[test]
+    fn ascent_extrapolates_to_the_configured_burst() {
+        let wind = WindEstimator::new();
+        let prediction = CONFIG.predict(&input(FlightState::Ascent, 10_000.0, 5.0, Some(10_000.0)), &wind).unwrap();
+        assert_eq!(prediction.burst_altitude, 30_000.0);
+        assert!((prediction.time_to_burst - 4000.0).abs() < 1.0);
+        // thinner air higher up, the descent is faster than the sea level rate would give
+        assert!(prediction.descent_duration < (30_000.0 - 200.0) / 5.0);
+        assert!(CONFIG.predict(&input(FlightState::Ascent, 10_000.0, 0.1, None), &wind).is_none());
+        assert!(CONFIG.predict(&input(FlightState::Pad, 200.0, 0.0, None), &wind).is_none());
+    }
+}
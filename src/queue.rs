@@ -0,0 +1,92 @@
+// channels with a fixed policy for what a producer does when the consumer has fallen behind, so every send site
+// behaves the same way for the same kind of data and every dropped item is counted
+
+use core::cell::Cell;
+use core::ops::Deref;
+
+use defmt::warn;
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_sync::blocking_mutex::raw::ThreadModeRawMutex;
+use embassy_sync::channel::{Channel, TrySendError};
+use embassy_time::{Duration, WithTimeout};
+
+// what to give up when the channel is full
+#[derive(Copy, Clone, PartialEq, Eq, defmt::Format)]
+pub enum Overflow {
+    // drop the oldest queued item to make room, for streams where the freshest sample matters most
+    DropOldest,
+    // drop the new item, for items that are resent or superseded anyway
+    DropNewest,
+    // wait up to the deadline for room and drop the new item if none frees up, for records that must get through
+    Block(Duration),
+}
+
+// a channel, its overflow policy, and how many items it has lost
+// receivers use the channel directly through deref
+pub struct Queue<T, const N: usize> {
+    name: &'static str,
+    policy: Overflow,
+    channel: Channel<ThreadModeRawMutex, T, N>,
+    dropped: Mutex<ThreadModeRawMutex, Cell<u32>>,
+}
+
+impl<T, const N: usize> Queue<T, N> {
+    pub const fn new(name: &'static str, policy: Overflow) -> Self {
+        Self {
+            name,
+            policy,
+            channel: Channel::new(),
+            dropped: Mutex::new(Cell::new(0)),
+        }
+    }
+
+    // queue an item under the channel's policy, returns false if the new item was dropped
+    // only Block ever waits, the drop policies finish without yielding
+    pub async fn send(&self, item: T) -> bool {
+        let item = match self.channel.try_send(item) {
+            Ok(()) => return true,
+            Err(TrySendError::Full(item)) => item,
+        };
+
+        match self.policy {
+            Overflow::DropOldest => {
+                self.overflowed();
+                // nothing else runs between these on the thread mode executor, the freed slot is still free
+                self.channel.try_receive().ok();
+                self.channel.try_send(item).is_ok()
+            }
+            Overflow::DropNewest => {
+                self.overflowed();
+                false
+            }
+            Overflow::Block(deadline) => {
+                let sent = self.channel.send(item).with_timeout(deadline).await.is_ok();
+                if !sent {
+                    self.overflowed();
+                }
+                sent
+            }
+        }
+    }
+
+    // items lost to overflow since boot
+    pub fn dropped(&self) -> u32 {
+        self.dropped.lock(|d| d.get())
+    }
+
+    fn overflowed(&self) {
+        let dropped = self.dropped.lock(|d| {
+            d.set(d.get().wrapping_add(1));
+            d.get()
+        });
+        warn!("{} channel full ({}), {} items dropped since boot", self.name, self.policy, dropped);
+    }
+}
+
+impl<T, const N: usize> Deref for Queue<T, N> {
+    type Target = Channel<ThreadModeRawMutex, T, N>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.channel
+    }
+}
@@ -0,0 +1,139 @@
+// payload power rails, each behind its own enable line instead of wired on, so the rails come up one at a time after
+// boot rather than all at once on a battery still recovering from the inrush, and a rail whose load is a risk under a
+// fault can be switched off while it lasts
+// each rail comes on its own delay after the sequence starts, so the delays give the order as well, and goes off
+// whenever one of its shutdown faults is active, coming back once they have all cleared
+// a rail can also be disabled, by the mission sequence, and stays off like a tripped one until enabled again
+
+use crate::faults::{Fault, FaultFlags};
+
+/// Switched payload power rail
+#[derive(Copy, Clone, PartialEq, Eq, Debug, defmt::Format)]
+pub enum Rail {
+    /// the gnc computer on the can bus
+    Gnc,
+    /// the radio's power amplifier, nothing goes out without it
+    RadioPa,
+    Camera,
+}
+
+impl Rail {
+    pub const ALL: [Rail; 3] = [Rail::Gnc, Rail::RadioPa, Rail::Camera];
+
+    pub fn from_id(id: u8) -> Option<Self> {
+        Self::ALL.get(id as usize).copied()
+    }
+
+    pub fn id(self) -> u8 {
+        self as u8
+    }
+
+    /// name used by the debug console
+    pub fn name(self) -> &'static str {
+        match self {
+            Rail::Gnc => "gnc",
+            Rail::RadioPa => "radio_pa",
+            Rail::Camera => "camera",
+        }
+    }
+}
+
+/// Set of rails, one bit per rail id
+#[derive(Copy, Clone, PartialEq, Eq, Default, Debug, defmt::Format)]
+pub struct RailFlags(pub u8);
+
+impl RailFlags {
+    pub const NONE: RailFlags = RailFlags(0);
+
+    pub fn contains(self, rail: Rail) -> bool {
+        self.0 & 1 << rail.id() != 0
+    }
+
+    pub fn set(&mut self, rail: Rail, on: bool) {
+        if on {
+            self.0 |= 1 << rail.id();
+        } else {
+            self.0 &= !(1 << rail.id());
+        }
+    }
+}
+
+/// Brings the rails up in sequence and switches them off and on again with their shutdown faults
+pub struct RailSequencer {
+    /// when each rail comes on after the sequence starts, us, in Rail::ALL order
+    delays: [u32; Rail::ALL.len()],
+    /// faults that switch each rail off, in Rail::ALL order
+    shutdown_on: [&'static [Fault]; Rail::ALL.len()],
+    start: Option<u32>,
+    /// rails whose delay has passed, kept so the sequence isn't rerun when the time stamps wrap
+    due: RailFlags,
+    on: RailFlags,
+    /// every rail held off for a sleep
+    held: bool,
+    /// rails kept off until enabled again
+    disabled: RailFlags,
+}
+
+impl RailSequencer {
+    pub const fn new(delays: [u32; Rail::ALL.len()], shutdown_on: [&'static [Fault]; Rail::ALL.len()]) -> Self {
+        Self {
+            delays,
+            shutdown_on,
+            start: None,
+            due: RailFlags::NONE,
+            on: RailFlags::NONE,
+            held: false,
+            disabled: RailFlags::NONE,
+        }
+    }
+
+    /// switch every rail off, one per update, until released, then the sequence starts over from the first update
+    pub fn hold(&mut self, held: bool) {
+        self.held = held;
+    }
+
+    /// keep these rails off, they go off one per update like a tripped rail and come back in sequence once enabled
+    pub fn disable(&mut self, disabled: RailFlags) {
+        self.disabled = disabled;
+    }
+
+    /// rails switched on
+    pub fn on(&self) -> RailFlags {
+        self.on
+    }
+
+    /// feed the active faults, the first update starts the sequence, returns the rail that changed and whether it is
+    /// now on, at most one rail changes per update so every switch is seen and logged separately
+    /// a rail going off comes first, then the due rail with the shortest delay
+    pub fn update(&mut self, faults: FaultFlags, time_stamp: u32) -> Option<(Rail, bool)> {
+        if self.held {
+            self.start = None;
+            self.due = RailFlags::NONE;
+            let rail = Rail::ALL.into_iter().find(|&rail| self.on.contains(rail))?;
+            self.on.set(rail, false);
+            return Some((rail, false));
+        }
+
+        let elapsed = time_stamp.wrapping_sub(*self.start.get_or_insert(time_stamp));
+        for rail in Rail::ALL {
+            if elapsed >= self.delays[rail.id() as usize] {
+                self.due.set(rail, true);
+            }
+        }
+
+        let tripped = |rail: Rail| {
+            self.disabled.contains(rail) || self.shutdown_on[rail.id() as usize].iter().any(|&fault| faults.contains(fault))
+        };
+        if let Some(rail) = Rail::ALL.into_iter().find(|&rail| self.on.contains(rail) && tripped(rail)) {
+            self.on.set(rail, false);
+            return Some((rail, false));
+        }
+
+        let rail = Rail::ALL
+            .into_iter()
+            .filter(|&rail| self.due.contains(rail) && !self.on.contains(rail) && !tripped(rail))
+            .min_by_key(|&rail| self.delays[rail.id() as usize])?;
+        self.on.set(rail, true);
+        Some((rail, true))
+    }
+}
@@ -0,0 +1,184 @@
+// sample periods, deadlines, timeouts, and queue depths in one place so a rate change is made once and the
+// values that have to agree with each other can be checked side by side
+// the "bench" feature switches to the bench profile, the pad rates are the flight rates so a board on the desk
+// behaves as it will in flight without having to fake a launch, and safe mode beacons often enough to watch
+
+use embassy_time::Duration;
+
+use avionics_sw_hapsis::health::Stream;
+use avionics_sw_hapsis::schedule::Job;
+use avionics_sw_hapsis::vibration::{BURST_SAMPLES, CHUNK_SAMPLES};
+
+// a launch can be delayed for hours, on the pad the sensors and logging run slower so the cpu sleeps
+// longer between wakeups and the flight battery lasts, everything returns to flight rates at launch
+// the pad rates stay inside the watchdog check in deadlines and stale stream timeouts
+
+// baro sample period on the pad and in flight
+#[cfg(not(feature = "bench"))]
+pub const BARO_PAD_PERIOD: Duration = Duration::from_millis(1000);
+#[cfg(feature = "bench")]
+pub const BARO_PAD_PERIOD: Duration = BARO_PERIOD;
+pub const BARO_PERIOD: Duration = Duration::from_millis(500);
+
+// imu sample period on the pad, in normal flight, and after free fall is detected
+#[cfg(not(feature = "bench"))]
+pub const IMU_PAD_PERIOD: Duration = Duration::from_millis(1000);
+#[cfg(feature = "bench")]
+pub const IMU_PAD_PERIOD: Duration = IMU_PERIOD;
+pub const IMU_PERIOD: Duration = Duration::from_millis(500);
+pub const IMU_HIGH_RATE_PERIOD: Duration = Duration::from_millis(10);
+
+// how long past the imu period to wait for a data ready edge before polling instead
+pub const IMU_DATA_READY_MARGIN: Duration = Duration::from_millis(100);
+
+// gps receiver output rate
+pub const GPS_PERIOD: Duration = Duration::from_millis(1000);
+
+// log task polling period on the pad, in normal flight, and at high rate
+#[cfg(not(feature = "bench"))]
+pub const LOG_PAD_PERIOD: Duration = Duration::from_millis(200);
+#[cfg(feature = "bench")]
+pub const LOG_PAD_PERIOD: Duration = LOG_PERIOD;
+pub const LOG_PERIOD: Duration = Duration::from_millis(50);
+pub const LOG_HIGH_RATE_PERIOD: Duration = Duration::from_millis(10);
+
+// control loop period, a loop body running longer than this is an overrun
+pub const CONTROL_PERIOD: Duration = Duration::from_millis(100);
+
+// how often the actuation channels' continuity is read
+pub const ACTUATION_PERIOD: Duration = Duration::from_secs(1);
+
+// how long a firing channel's output stays on, long enough for the cutdown nichrome to burn through the line,
+// and how often the current through the load is sampled while it does
+pub const FIRE_DWELL: Duration = Duration::from_secs(3);
+pub const FIRE_SAMPLE_PERIOD: Duration = Duration::from_millis(1);
+
+// how often the battery heater's energy goes in the log
+pub const HEATER_REPORT_PERIOD: Duration = Duration::from_secs(60);
+
+// how often the gimbal servos are pointed, once per servo frame
+pub const GIMBAL_PERIOD: Duration = Duration::from_millis(20);
+
+// slow periodic jobs, run one at a time by the housekeeping task, when several are due together the highest priority
+// goes first, the supply voltage ahead of everything since it decides safe mode, then the rail shutdowns
+#[derive(Copy, Clone, PartialEq, Eq, defmt::Format)]
+pub enum Housekeeping {
+    // sample the supply voltage and battery temperature, and beacon in safe mode
+    Power,
+    // step the payload rail sequence and shutdown faults
+    Rails,
+    // check the data streams and control loop, and send the health report when it's due
+    Health,
+    // run the battery heater thermostat
+    Heater,
+    // reevaluate the state of charge for load shedding
+    LoadShed,
+    // read, log, and send down the solar charger input
+    PowerReport,
+}
+
+pub const HOUSEKEEPING_SCHEDULE: [Job<Housekeeping>; 6] = [
+    job(Housekeeping::Power, Duration::from_millis(200), 5),
+    job(Housekeeping::Rails, Duration::from_millis(100), 4),
+    job(Housekeeping::Health, Duration::from_millis(500), 3),
+    job(Housekeeping::Heater, Duration::from_secs(1), 2),
+    job(Housekeeping::LoadShed, Duration::from_secs(1), 1),
+    job(Housekeeping::PowerReport, Duration::from_secs(30), 0),
+];
+
+// position and landing prediction reports, run one at a time by the report task, the position first since the chase
+// team steers by it, their periods are the config's, the ones here are the defaults it starts from
+#[derive(Copy, Clone, PartialEq, Eq, defmt::Format)]
+pub enum Report {
+    // report the gps or dead reckoned position
+    Position,
+    // predict burst and landing from the latest position, ascent rate, and wind profile
+    Prediction,
+}
+
+pub const REPORT_SCHEDULE: [Job<Report>; 2] = [
+    job(Report::Position, Duration::from_secs(5), 1),
+    job(Report::Prediction, Duration::from_secs(30), 0),
+];
+
+const fn job<J>(job: J, period: Duration, priority: u8) -> Job<J> {
+    Job { job, period: period.as_micros() as u32, priority }
+}
+
+// how often a duty cycled float checks whether its awake window is over, how long it waits for the telemetry queue to
+// empty and then for the rails to go off before it sleeps
+pub const DUTY_CYCLE_PERIOD: Duration = Duration::from_secs(1);
+pub const SLEEP_DRAIN_TIMEOUT: Duration = Duration::from_secs(10);
+pub const RAILS_OFF_TIMEOUT: Duration = Duration::from_secs(2);
+
+// position beacon period in low voltage safe mode
+#[cfg(not(feature = "bench"))]
+pub const BEACON_PERIOD: Duration = Duration::from_secs(30);
+#[cfg(feature = "bench")]
+pub const BEACON_PERIOD: Duration = Duration::from_secs(5);
+
+// one ssdv image packet or requested logged record per period while either is going down, queued telemetry goes first
+#[cfg(not(feature = "mavlink"))]
+pub const IMAGE_PACKET_PERIOD: Duration = Duration::from_secs(2);
+
+// mavlink heartbeat period, ground control stations count the link lost after a few seconds without one
+#[cfg(feature = "mavlink")]
+pub const MAVLINK_HEARTBEAT_PERIOD: Duration = Duration::from_secs(1);
+
+// time between the vibration bursts taken after a flight event
+pub const VIBRATION_BURST_SPACING: Duration = Duration::from_secs(2);
+
+// independent watchdog timeout, long enough to ride out a flash sector erase stalling the cpu
+pub const WATCHDOG_TIMEOUT: Duration = Duration::from_secs(4);
+
+// how often the watchdog task checks the registry and feeds the watchdog
+pub const WATCHDOG_FEED_PERIOD: Duration = Duration::from_millis(500);
+
+// longest allowed gap between check ins, a few loop periods so one slow iteration doesn't reset the board
+pub const CONTROL_CHECK_IN_DEADLINE: Duration = Duration::from_secs(1);
+pub const SENSOR_CHECK_IN_DEADLINE: Duration = Duration::from_secs(2);
+pub const LOG_CHECK_IN_DEADLINE: Duration = Duration::from_secs(1);
+
+// longest gap before a data stream is flagged stale (us), in Stream::ALL order: baro, imu, attitude, gps, nav
+pub const STREAM_TIMEOUTS: [u32; Stream::ALL.len()] = [5_000_000, 2_000_000, 2_000_000, 5_000_000, 2_000_000];
+
+// how long a producer waits on a full channel before dropping the item, short for the imu rate streams so a
+// stalled consumer can't hold up the next sample, longer for the slow streams
+pub const FAST_SEND_TIMEOUT: Duration = Duration::from_millis(50);
+pub const SLOW_SEND_TIMEOUT: Duration = Duration::from_millis(200);
+
+// how long the log task gets to flush and close the file before rebooting into the bootloader
+pub const LOG_FINALIZE_TIMEOUT: Duration = Duration::from_millis(500);
+
+// how long the console waits for the control task to take a command or answer a config read
+pub const CONSOLE_COMMAND_TIMEOUT: Duration = Duration::from_secs(1);
+// and for a firmware transfer step, beginning one erases the update slot first, which takes a few seconds
+pub const CONSOLE_UPDATE_TIMEOUT: Duration = Duration::from_secs(10);
+
+// queue depths, a few periods of the slowest consumer
+// sensor data channels to the log, nav, and wind tasks
+pub const DATA_CHANNEL_DEPTH: usize = 4;
+// imu samples held for the slowest subscriber, the log task drains them every LOG_HIGH_RATE_PERIOD
+pub const IMU_QUEUE_DEPTH: usize = 8;
+// downlink items waiting for the radio
+pub const TELEMETRY_DEPTH: usize = 8;
+// flight events, faults included, they come in bursts when a sensor drops out, and config changes for the log
+pub const EVENT_DEPTH: usize = 8;
+pub const CONFIG_AUDIT_DEPTH: usize = 4;
+// raw vibration samples for the log, a whole burst so the imu task never waits on the log task
+pub const VIBRATION_DEPTH: usize = BURST_SAMPLES / CHUNK_SAMPLES;
+// commands and firmware update steps
+pub const COMMAND_DEPTH: usize = 4;
+// logged records read back for the downlink, the storage task reads on as the radio takes them
+pub const HISTORY_DEPTH: usize = 4;
+
+// log records queued for the sd card, for each stream, eight blocks is most of a second of high rate imu logging if
+// the card stalls
+pub const LOG_QUEUE_SIZE: usize = 4096;
+
+// the pad rates can't be faster than flight, and stale stream timeouts must outlast the pad sample periods
+const _: () = assert!(BARO_PAD_PERIOD.as_ticks() >= BARO_PERIOD.as_ticks());
+const _: () = assert!(IMU_PAD_PERIOD.as_ticks() >= IMU_PERIOD.as_ticks());
+const _: () = assert!(STREAM_TIMEOUTS[0] as u64 > BARO_PAD_PERIOD.as_micros());
+const _: () = assert!(STREAM_TIMEOUTS[1] as u64 > IMU_PAD_PERIOD.as_micros());
+const _: () = assert!(STREAM_TIMEOUTS[3] as u64 > GPS_PERIOD.as_micros());
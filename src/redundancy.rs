@@ -0,0 +1,124 @@
+// redundant sensor pairs, both units are read every cycle and compared, the pair's output comes from one of them
+// a unit whose reads keep failing is unhealthy and the output moves to the other
+// two units that disagree can't say which one is wrong by themselves, a third opinion (gps for the barometers) picks
+// the one closer to it, without one the output stays where it is and only the divergence is flagged
+
+/// One unit of a redundant pair
+#[derive(Copy, Clone, PartialEq, Eq, Debug, defmt::Format)]
+pub enum Unit {
+    Primary,
+    Secondary,
+}
+
+impl Unit {
+    pub const ALL: [Unit; 2] = [Unit::Primary, Unit::Secondary];
+
+    pub fn from_id(id: u8) -> Option<Self> {
+        Self::ALL.get(id as usize).copied()
+    }
+
+    pub fn id(self) -> u8 {
+        self as u8
+    }
+
+    pub fn other(self) -> Self {
+        match self {
+            Unit::Primary => Unit::Secondary,
+            Unit::Secondary => Unit::Primary,
+        }
+    }
+}
+
+/// When a pair counts as failed or diverged
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct PairLimits {
+    /// difference between the units beyond which they disagree, in the units of the difference function
+    pub divergence: f32,
+    /// cycles in a row the units have to disagree, or agree again, before it counts
+    pub divergence_samples: u16,
+    /// cycles in a row a unit has to give no sample before it counts as failed
+    pub failed_samples: u16,
+}
+
+/// Cross checks the two units of a redundant pair and picks the one to use
+pub struct PairMonitor {
+    limits: PairLimits,
+    /// cycles in a row each unit has given no sample
+    misses: [u16; 2],
+    selected: Unit,
+    diverged: bool,
+    /// cycles in a row the divergence state has wanted to change
+    pending: u16,
+}
+
+impl PairMonitor {
+    pub const fn new(limits: PairLimits) -> Self {
+        Self {
+            limits,
+            misses: [0; 2],
+            selected: Unit::Primary,
+            diverged: false,
+            pending: 0,
+        }
+    }
+
+    /// feed this cycle's sample from each unit, None for a failed or implausible read, and a reference to break a
+    /// disagreement if there is one, difference gives how far apart two samples are
+    /// returns the sample to use, None when neither unit gave one
+    pub fn update<T: Copy>(&mut self, samples: [Option<T>; 2], reference: Option<T>, difference: impl Fn(&T, &T) -> f32) -> Option<T> {
+        for (misses, sample) in self.misses.iter_mut().zip(samples) {
+            *misses = if sample.is_some() { 0 } else { misses.saturating_add(1) };
+        }
+
+        // only fresh samples from both units are compared, a nan difference counts as disagreeing
+        if let [Some(primary), Some(secondary)] = samples {
+            let agree = difference(&primary, &secondary) <= self.limits.divergence;
+            let apart = !agree;
+            if apart != self.diverged {
+                self.pending += 1;
+                if self.pending >= self.limits.divergence_samples {
+                    self.diverged = apart;
+                    self.pending = 0;
+                }
+            } else {
+                self.pending = 0;
+            }
+
+            if self.diverged
+                && let Some(reference) = reference
+            {
+                let (to_primary, to_secondary) = (difference(&primary, &reference), difference(&secondary, &reference));
+                if to_primary < to_secondary {
+                    self.selected = Unit::Primary;
+                } else if to_secondary < to_primary {
+                    self.selected = Unit::Secondary;
+                }
+            }
+        }
+
+        if !self.healthy(self.selected) && self.healthy(self.selected.other()) {
+            self.selected = self.selected.other();
+        }
+        samples[self.selected.id() as usize].or(samples[self.selected.other().id() as usize])
+    }
+
+    /// the unit the output comes from
+    pub fn selected(&self) -> Unit {
+        self.selected
+    }
+
+    /// false once a unit has gone the failed sample count without a sample
+    pub fn healthy(&self, unit: Unit) -> bool {
+        self.misses[unit.id() as usize] < self.limits.failed_samples
+    }
+
+    /// the units disagree
+    pub fn diverged(&self) -> bool {
+        self.diverged
+    }
+
+    /// something is wrong with the pair, the units disagree or one has failed
+    pub fn degraded(&self) -> bool {
+        self.diverged || !self.healthy(Unit::Primary) || !self.healthy(Unit::Secondary)
+    }
+}
@@ -0,0 +1,236 @@
+use crate::sensors::{Barometer, Gps, Imu};
+use crate::validate::SampleFlags;
+use crate::{BaroData, FixType, GpsData, ImuData};
+
+/// Record tags in a replay log
+/// every record is a tag byte followed by its fields, little endian, in the same order as the data struct
+const TAG_BARO: u8 = 1;
+const TAG_IMU: u8 = 2;
+const TAG_GPS: u8 = 3;
+
+/// One sensor sample from a recorded flight
+#[derive(Copy, Clone)]
+pub enum Record {
+    Baro(BaroData),
+    Imu(ImuData),
+    Gps(GpsData),
+}
+
+impl Record {
+    pub fn time_stamp(&self) -> u32 {
+        match self {
+            Record::Baro(data) => data.time_stamp,
+            Record::Imu(data) => data.time_stamp,
+            Record::Gps(data) => data.time_stamp,
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, defmt::Format)]
+pub enum ReplayError {
+    /// the log has no records for this sensor, or none before the current replay time
+    NoData,
+}
+
+/// Reads records out of a recorded flight log
+/// stops at the end of the log or at the first unknown tag or truncated record
+#[derive(Clone)]
+pub struct Reader<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(buf: &'a [u8]) -> Self {
+        Self { buf }
+    }
+
+    fn take<const N: usize>(&mut self) -> Option<[u8; N]> {
+        let (bytes, rest) = self.buf.split_first_chunk::<N>()?;
+        self.buf = rest;
+        Some(*bytes)
+    }
+
+    fn f32(&mut self) -> Option<f32> {
+        self.take().map(f32::from_le_bytes)
+    }
+
+    fn f64(&mut self) -> Option<f64> {
+        self.take().map(f64::from_le_bytes)
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        self.take().map(u32::from_le_bytes)
+    }
+
+    fn vector(&mut self) -> Option<[f32; 3]> {
+        Some([self.f32()?, self.f32()?, self.f32()?])
+    }
+
+    fn record(&mut self) -> Option<Record> {
+        let [tag] = self.take()?;
+        let record = match tag {
+            TAG_BARO => Record::Baro(BaroData {
+                pressure: self.f32()?,
+                temperature: self.f32()?,
+                flags: SampleFlags::NONE,
+                time_stamp: self.u32()?,
+            }),
+            TAG_IMU => Record::Imu(ImuData {
+                acceleration: self.vector()?,
+                gyro: self.vector()?,
+                mag: self.vector()?,
+                temperature: self.f32()?,
+                flags: SampleFlags::NONE,
+                time_stamp: self.u32()?,
+            }),
+            // utc of zero means the receiver had no time yet, quality isn't recorded, the gps task grades the fix again
+            TAG_GPS => Record::Gps(GpsData {
+                latitude: self.f64()?,
+                longitude: self.f64()?,
+                altitude: self.f32()?,
+                velocity: self.vector()?,
+                satellites: self.take::<1>()?[0],
+                fix: FixType::from_u8(self.take::<1>()?[0]),
+                hdop: self.f32()?,
+                quality: Default::default(),
+                flags: SampleFlags::NONE,
+                utc: Some(u64::from_le_bytes(self.take()?)).filter(|&utc| utc != 0),
+                time_stamp: self.u32()?,
+            }),
+            _ => return None,
+        };
+        Some(record)
+    }
+}
+
+impl Iterator for Reader<'_> {
+    type Item = Record;
+
+    fn next(&mut self) -> Option<Record> {
+        let record = self.record();
+        if record.is_none() {
+            self.buf = &[];
+        }
+        record
+    }
+}
+
+/// Plays one sensor's records back in real time
+/// the recorded time stamps are replayed relative to the first read, a read returns the newest record
+/// whose time has come, so the task rate doesn't have to match the recorded rate
+/// after the last record the final sample is repeated, the payload stays where it landed
+struct Playback<'a, T> {
+    reader: Reader<'a>,
+    pick: fn(Record) -> Option<T>,
+    stamp: fn(&T) -> u32,
+    /// (recorded time stamp of the first record, time stamp of the first read)
+    start: Option<(u32, u32)>,
+    next: Option<T>,
+    current: Option<T>,
+}
+
+impl<'a, T: Copy> Playback<'a, T> {
+    fn new(log: &'a [u8], pick: fn(Record) -> Option<T>, stamp: fn(&T) -> u32) -> Self {
+        let mut playback = Self {
+            reader: Reader::new(log),
+            pick,
+            stamp,
+            start: None,
+            next: None,
+            current: None,
+        };
+        playback.next = playback.advance();
+        playback
+    }
+
+    fn advance(&mut self) -> Option<T> {
+        let pick = self.pick;
+        self.reader.by_ref().find_map(pick)
+    }
+
+    fn read(&mut self, time_stamp: u32) -> Option<T> {
+        let first = self.next.map(|n| (self.stamp)(&n)).unwrap_or(0);
+        let (recorded_start, replay_start) = *self.start.get_or_insert((first, time_stamp));
+        let elapsed = time_stamp.wrapping_sub(replay_start);
+
+        while let Some(next) = self.next {
+            if (self.stamp)(&next).wrapping_sub(recorded_start) > elapsed {
+                break;
+            }
+            self.current = Some(next);
+            self.next = self.advance();
+        }
+        self.current
+    }
+}
+
+/// Barometer replaying a recorded flight log
+pub struct ReplayBarometer<'a>(Playback<'a, BaroData>);
+
+impl<'a> ReplayBarometer<'a> {
+    pub fn new(log: &'a [u8]) -> Self {
+        Self(Playback::new(
+            log,
+            |r| if let Record::Baro(data) = r { Some(data) } else { None },
+            |data| data.time_stamp,
+        ))
+    }
+}
+
+impl Barometer for ReplayBarometer<'_> {
+    type Error = ReplayError;
+
+    async fn read(&mut self, time_stamp: u32) -> Result<BaroData, ReplayError> {
+        let data = self.0.read(time_stamp).ok_or(ReplayError::NoData)?;
+        Ok(BaroData { time_stamp, ..data })
+    }
+}
+
+/// Imu replaying a recorded flight log
+pub struct ReplayImu<'a>(Playback<'a, ImuData>);
+
+impl<'a> ReplayImu<'a> {
+    pub fn new(log: &'a [u8]) -> Self {
+        Self(Playback::new(
+            log,
+            |r| if let Record::Imu(data) = r { Some(data) } else { None },
+            |data| data.time_stamp,
+        ))
+    }
+}
+
+impl Imu for ReplayImu<'_> {
+    type Error = ReplayError;
+
+    async fn read(&mut self, time_stamp: u32) -> Result<ImuData, ReplayError> {
+        let data = self.0.read(time_stamp).ok_or(ReplayError::NoData)?;
+        Ok(ImuData { time_stamp, ..data })
+    }
+}
+
+/// Gps replaying a recorded flight log
+pub struct ReplayGps<'a>(Playback<'a, GpsData>);
+
+impl<'a> ReplayGps<'a> {
+    pub fn new(log: &'a [u8]) -> Self {
+        Self(Playback::new(
+            log,
+            |r| if let Record::Gps(data) = r { Some(data) } else { None },
+            |data| data.time_stamp,
+        ))
+    }
+}
+
+impl Gps for ReplayGps<'_> {
+    type Error = ReplayError;
+
+    async fn read(&mut self, time_stamp: u32) -> Result<GpsData, ReplayError> {
+        let data = self.0.read(time_stamp).ok_or(ReplayError::NoData)?;
+        Ok(GpsData { time_stamp, ..data })
+    }
+
+    // the recorded fixes don't depend on it
+    async fn assist(&mut self, _message: &[u8]) -> Result<(), ReplayError> {
+        Ok(())
+    }
+}
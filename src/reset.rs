@@ -0,0 +1,76 @@
+/// Why the mcu last came out of reset, from the rcc reset flags
+#[derive(Copy, Clone, PartialEq, Eq, Debug, defmt::Format)]
+pub enum ResetReason {
+    /// supply came up from off
+    PowerOn,
+    /// supply dipped below the brownout threshold
+    Brownout,
+    /// reset pin, a debugger or the reset button
+    Pin,
+    /// software reset, a panic, the bootloader command, or a firmware update
+    Software,
+    /// the independent watchdog fired, a task hung
+    IndependentWatchdog,
+    WindowWatchdog,
+    /// entering standby or stop while the option bytes forbid it
+    LowPower,
+    /// no flag set
+    Unknown,
+}
+
+// flag bits in RCC_CSR, the same on the f4 and f7
+const LPWRRSTF: u32 = 1 << 31;
+const WWDGRSTF: u32 = 1 << 30;
+const IWDGRSTF: u32 = 1 << 29;
+const SFTRSTF: u32 = 1 << 28;
+const PORRSTF: u32 = 1 << 27;
+const PINRSTF: u32 = 1 << 26;
+const BORRSTF: u32 = 1 << 25;
+
+impl ResetReason {
+    /// every reason, in the order of their numbers in the log and downlink
+    pub const ALL: [ResetReason; 8] = [
+        ResetReason::PowerOn,
+        ResetReason::Brownout,
+        ResetReason::Pin,
+        ResetReason::Software,
+        ResetReason::IndependentWatchdog,
+        ResetReason::WindowWatchdog,
+        ResetReason::LowPower,
+        ResetReason::Unknown,
+    ];
+
+    /// reason from the RCC_CSR register
+    /// several flags are set at once (a power on also sets the pin and brownout flags), the most specific one wins
+    pub fn from_csr(csr: u32) -> Self {
+        if csr & IWDGRSTF != 0 {
+            ResetReason::IndependentWatchdog
+        } else if csr & WWDGRSTF != 0 {
+            ResetReason::WindowWatchdog
+        } else if csr & LPWRRSTF != 0 {
+            ResetReason::LowPower
+        } else if csr & SFTRSTF != 0 {
+            ResetReason::Software
+        } else if csr & PORRSTF != 0 {
+            ResetReason::PowerOn
+        } else if csr & BORRSTF != 0 {
+            ResetReason::Brownout
+        } else if csr & PINRSTF != 0 {
+            ResetReason::Pin
+        } else {
+            ResetReason::Unknown
+        }
+    }
+
+    /// true for resets nobody asked for, the ones worth raising in flight
+    pub fn is_unexpected(self) -> bool {
+        matches!(self, ResetReason::Brownout | ResetReason::IndependentWatchdog | ResetReason::WindowWatchdog | ResetReason::LowPower)
+    }
+}
+
+/// Why the mcu last reset and how many times it has booted, the count survives resets on vbat
+#[derive(Copy, Clone, PartialEq, Eq, defmt::Format)]
+pub struct BootInfo {
+    pub reset_reason: ResetReason,
+    pub boot_count: u32,
+}
@@ -0,0 +1,219 @@
+use crate::actuation::ChannelFlags;
+use crate::crc::crc32;
+use crate::mission::FlightState;
+
+/// marks a written resume record, "RSUM"
+const MAGIC: u32 = 0x4D55_5352;
+
+/// rtc backup registers the register copy takes
+pub const RESUME_REGISTERS: usize = 6;
+
+/// marks the register copy in the top half of its first register, "RS"
+const REGISTER_MAGIC: u32 = 0x5352;
+
+/// Flight progress kept in backup sram so a reset in flight (watchdog, panic, brownout) picks up where it left off
+/// instead of going back to the pad and dropping the launch site reference
+/// fields are stored as plain words since the memory holds garbage after a power loss without vbat
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct ResumeRecord {
+    magic: u32,
+    state: u32,
+    /// launch site ground pressure (hPa) and altitude (m), NaN when not armed
+    ground_pressure: f32,
+    ground_altitude: f32,
+    high_rate_logging: u32,
+    armed: u32,
+    /// launch time in ms since the unix epoch, u64::MAX when unknown
+    launch_utc: u64,
+    fired: u32,
+    crc: u32,
+}
+
+/// What a valid resume record restores
+#[derive(Copy, Clone, PartialEq, defmt::Format)]
+pub struct ResumeState {
+    pub state: FlightState,
+    /// launch site ground pressure (hPa) and altitude (m) if armed
+    pub ground: Option<(f32, f32)>,
+    pub high_rate_logging: bool,
+    /// actuation channels armed, they stay armed across the reset
+    pub armed: ChannelFlags,
+    /// actuation channels fired, none is fired again on its own after the reset
+    pub fired: ChannelFlags,
+    /// launch time in ms since the unix epoch, so mission elapsed time carries on across the reset
+    /// None before launch or if the rtc wasn't set at launch
+    pub launch_utc: Option<u64>,
+}
+
+impl ResumeRecord {
+    pub fn new(resume: ResumeState) -> Self {
+        let (ground_pressure, ground_altitude) = resume.ground.unwrap_or((f32::NAN, f32::NAN));
+        let mut record = ResumeRecord {
+            magic: MAGIC,
+            state: resume.state as u32,
+            ground_pressure,
+            ground_altitude,
+            high_rate_logging: resume.high_rate_logging as u32,
+            armed: resume.armed.0 as u32,
+            launch_utc: resume.launch_utc.unwrap_or(u64::MAX),
+            fired: resume.fired.0 as u32,
+            crc: 0,
+        };
+        record.crc = record.checksum();
+        record
+    }
+
+    /// None when nothing was saved or the record is garbage
+    pub fn state(&self) -> Option<ResumeState> {
+        if self.magic != MAGIC || self.crc != self.checksum() {
+            return None;
+        }
+
+        let state = u8::try_from(self.state).ok().and_then(FlightState::from_id)?;
+        let ground = (!self.ground_pressure.is_nan()).then_some((self.ground_pressure, self.ground_altitude));
+        Some(ResumeState {
+            state,
+            ground,
+            high_rate_logging: self.high_rate_logging != 0,
+            armed: ChannelFlags(self.armed as u8),
+            fired: ChannelFlags(self.fired as u8),
+            launch_utc: (self.launch_utc != u64::MAX).then_some(self.launch_utc),
+        })
+    }
+
+    fn checksum(&self) -> u32 {
+        checksum(&[
+            self.magic,
+            self.state,
+            self.ground_pressure.to_bits(),
+            self.ground_altitude.to_bits(),
+            self.high_rate_logging,
+            self.armed,
+            self.launch_utc as u32,
+            (self.launch_utc >> 32) as u32,
+            self.fired,
+        ])
+    }
+}
+
+impl ResumeState {
+    /// the copy kept in rtc backup registers, the magic, state and flags packed into the first word, crc last
+    /// the registers are write protected and kept on vbat without the backup regulator, so a crash writing through a
+    /// stray pointer or a regulator left off still leaves this copy
+    pub fn to_registers(&self) -> [u32; RESUME_REGISTERS] {
+        let (ground_pressure, ground_altitude) = self.ground.unwrap_or((f32::NAN, f32::NAN));
+        let launch_utc = self.launch_utc.unwrap_or(u64::MAX);
+        // one bit per channel, three channels each
+        let flags = self.high_rate_logging as u32 | (self.armed.0 as u32 & 0x7) << 1 | (self.fired.0 as u32 & 0x7) << 4;
+        let mut registers = [
+            REGISTER_MAGIC << 16 | (self.state.id() as u32) << 8 | flags,
+            ground_pressure.to_bits(),
+            ground_altitude.to_bits(),
+            launch_utc as u32,
+            (launch_utc >> 32) as u32,
+            0,
+        ];
+        registers[RESUME_REGISTERS - 1] = checksum(&registers[..RESUME_REGISTERS - 1]);
+        registers
+    }
+
+    /// None when the registers were never written, were cleared with the backup domain, or a write was cut short
+    pub fn from_registers(registers: &[u32; RESUME_REGISTERS]) -> Option<Self> {
+        let [header, ground_pressure, ground_altitude, launch_low, launch_high, crc] = *registers;
+        if header >> 16 != REGISTER_MAGIC || crc != checksum(&registers[..RESUME_REGISTERS - 1]) {
+            return None;
+        }
+
+        let state = FlightState::from_id((header >> 8) as u8)?;
+        let ground_pressure = f32::from_bits(ground_pressure);
+        let launch_utc = (launch_high as u64) << 32 | launch_low as u64;
+        Some(ResumeState {
+            state,
+            ground: (!ground_pressure.is_nan()).then_some((ground_pressure, f32::from_bits(ground_altitude))),
+            high_rate_logging: header & 1 != 0,
+            armed: ChannelFlags((header >> 1) as u8 & 0x7),
+            fired: ChannelFlags((header >> 4) as u8 & 0x7),
+            launch_utc: (launch_utc != u64::MAX).then_some(launch_utc),
+        })
+    }
+}
+
+fn checksum(words: &[u32]) -> u32 {
+    let mut buf = [0u8; 36];
+    for (chunk, word) in buf.chunks_exact_mut(4).zip(words) {
+        chunk.copy_from_slice(&word.to_le_bytes());
+    }
+    crc32(&buf[..words.len() * 4])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DESCENT: ResumeState = ResumeState {
+        state: FlightState::Descent,
+        ground: Some((991.25, 187.5)),
+        high_rate_logging: true,
+        armed: ChannelFlags(0b110),
+        fired: ChannelFlags(0b001),
+        launch_utc: Some(1_781_438_401_000),
+    };
+
+    const PAD: ResumeState = ResumeState {
+        state: FlightState::Pad,
+        ground: None,
+        high_rate_logging: false,
+        armed: ChannelFlags::NONE,
+        fired: ChannelFlags::NONE,
+        launch_utc: None,
+    };
+
+    #[test]
+    fn registers_round_trip() {
+        for resume in [DESCENT, PAD] {
+            let registers = resume.to_registers();
+            assert_eq!(registers[0] >> 16, REGISTER_MAGIC);
+            assert!(ResumeState::from_registers(&registers) == Some(resume));
+        }
+    }
+
+    #[test]
+    fn any_corrupted_word_is_refused() {
+        let registers = DESCENT.to_registers();
+        for word in 0..RESUME_REGISTERS {
+            for bit in [0, 13, 31] {
+                let mut corrupted = registers;
+                corrupted[word] ^= 1 << bit;
+                assert!(ResumeState::from_registers(&corrupted).is_none(), "word {word} bit {bit}");
+            }
+        }
+    }
+
+    #[test]
+    fn power_on_registers_are_refused() {
+        // cleared with the backup domain, and never written on a board that has never had vbat
+        assert!(ResumeState::from_registers(&[0; RESUME_REGISTERS]).is_none());
+        assert!(ResumeState::from_registers(&[u32::MAX; RESUME_REGISTERS]).is_none());
+    }
+
+    #[test]
+    fn unknown_state_is_refused_even_with_a_good_crc() {
+        let mut registers = PAD.to_registers();
+        registers[0] |= 0x7 << 8;
+        registers[RESUME_REGISTERS - 1] = checksum(&registers[..RESUME_REGISTERS - 1]);
+        assert!(ResumeState::from_registers(&registers).is_none());
+    }
+
+    #[test]
+    fn sram_record_round_trips_and_refuses_garbage() {
+        let record = ResumeRecord::new(DESCENT);
+        assert!(record.state() == Some(DESCENT));
+        let mut garbage = record;
+        garbage.ground_altitude = 0.0;
+        assert!(garbage.state().is_none());
+        let mut garbage = record;
+        garbage.magic = 0;
+        assert!(garbage.state().is_none());
+    }
+}
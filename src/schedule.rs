@@ -0,0 +1,154 @@
+// periodic jobs declared in one table with their periods and priorities, run one at a time by a single task, instead
+// of a task with its own timer loop for each
+// a job is due every period from the first update, when several are due the highest priority goes first and ties go
+// in table order, a job that fell more than a period behind skips the periods it missed rather than running them
+// back to back
+// time stamps are us and wrap, a period has to stay well under half the wrap, about 35 minutes
+
+/// One periodic job in a schedule table
+#[derive(Copy, Clone, PartialEq, Eq, Debug, defmt::Format)]
+pub struct Job<J> {
+    pub job: J,
+    /// us
+    pub period: u32,
+    /// higher runs first when several jobs are due together
+    pub priority: u8,
+}
+
+/// Picks the next due job from a table
+pub struct Scheduler<J, const N: usize> {
+    jobs: [Job<J>; N],
+    /// when each job is next due, in table order, None before the first update
+    due: [Option<u32>; N],
+    /// periods skipped since boot, in table order
+    skipped: [u32; N],
+}
+
+impl<J: Copy + PartialEq, const N: usize> Scheduler<J, N> {
+    pub const fn new(jobs: [Job<J>; N]) -> Self {
+        Self {
+            jobs,
+            due: [None; N],
+            skipped: [0; N],
+        }
+    }
+
+    /// the highest priority job that is due, moved on to its next period, None if nothing is due yet
+    pub fn next(&mut self, now: u32) -> Option<J> {
+        let mut next: Option<usize> = None;
+        for (index, due) in self.due.iter_mut().enumerate() {
+            let due = *due.get_or_insert(now);
+            if (now.wrapping_sub(due) as i32) < 0 {
+                continue;
+            }
+            if next.is_none_or(|best| self.jobs[index].priority > self.jobs[best].priority) {
+                next = Some(index);
+            }
+        }
+
+        let index = next?;
+        let period = self.jobs[index].period;
+        let due = self.due[index].unwrap_or(now).wrapping_add(period);
+        let late = now.wrapping_sub(due);
+        self.due[index] = Some(if (late as i32) < 0 {
+            due
+        } else {
+            self.skipped[index] = self.skipped[index].saturating_add(late / period.max(1) + 1);
+            now.wrapping_add(period)
+        });
+        Some(self.jobs[index].job)
+    }
+
+    /// change a job's period (us) to one from the config, its next run moves to a period after its last one, or now if
+    /// that has already gone by
+    pub fn set_period(&mut self, job: J, period: u32, now: u32) {
+        for (entry, due) in self.jobs.iter_mut().zip(&mut self.due) {
+            if entry.job != job || entry.period == period {
+                continue;
+            }
+            if let Some(due) = due.as_mut() {
+                let next = due.wrapping_sub(entry.period).wrapping_add(period);
+                *due = if (next.wrapping_sub(now) as i32) < 0 { now } else { next };
+            }
+            entry.period = period;
+        }
+    }
+
+    /// us until the next job is due, 0 if one already is
+    pub fn wait(&self, now: u32) -> u32 {
+        self.due
+            .iter()
+            .map(|due| due.map_or(0, |due| due.wrapping_sub(now) as i32).max(0) as u32)
+            .min()
+            .unwrap_or(0)
+    }
+
+    /// periods a job has skipped since boot because it was still running or waiting behind others
+    pub fn skipped(&self, job: J) -> u32 {
+        self.jobs.iter().zip(self.skipped).filter(|(entry, _)| entry.job == job).map(|(_, skipped)| skipped).sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Copy, Clone, PartialEq, Eq, Debug, defmt::Format)]
+    enum Test {
+        Fast,
+        Slow,
+    }
+
+    fn scheduler() -> Scheduler<Test, 2> {
+        Scheduler::new([
+            Job { job: Test::Fast, period: 100, priority: 0 },
+            Job { job: Test::Slow, period: 1000, priority: 1 },
+        ])
+    }
+
+    #[test]
+    fn higher_priority_goes_first() {
+        let mut scheduler = scheduler();
+        assert_eq!(scheduler.next(0), Some(Test::Slow));
+        assert_eq!(scheduler.next(0), Some(Test::Fast));
+        assert_eq!(scheduler.next(0), None);
+        assert_eq!(scheduler.wait(0), 100);
+        assert_eq!(scheduler.next(100), Some(Test::Fast));
+        assert_eq!(scheduler.next(1000), Some(Test::Slow));
+    }
+
+    #[test]
+    fn late_job_skips_the_periods_it_missed() {
+        let mut scheduler = scheduler();
+        scheduler.next(0);
+        scheduler.next(0);
+        assert_eq!(scheduler.next(350), Some(Test::Fast));
+        assert_eq!(scheduler.skipped(Test::Fast), 2);
+        assert_eq!(scheduler.wait(350), 100);
+    }
+
+    #[test]
+    fn shorter_period_pulls_the_next_run_in() {
+        let mut scheduler = scheduler();
+        scheduler.next(0);
+        scheduler.next(0);
+        scheduler.set_period(Test::Slow, 200, 50);
+        assert_eq!(scheduler.next(200), Some(Test::Slow));
+        // a period already gone by runs now without counting as skipped
+        scheduler.set_period(Test::Slow, 50, 300);
+        assert_eq!(scheduler.next(300), Some(Test::Slow));
+        assert_eq!(scheduler.skipped(Test::Slow), 0);
+        assert_eq!(scheduler.wait(300), 0);
+    }
+
+    #[test]
+    fn longer_period_pushes_the_next_run_out() {
+        let mut scheduler = scheduler();
+        scheduler.next(0);
+        scheduler.next(0);
+        scheduler.set_period(Test::Fast, 500, 50);
+        assert_eq!(scheduler.next(100), None);
+        assert_eq!(scheduler.wait(100), 400);
+        assert_eq!(scheduler.next(500), Some(Test::Fast));
+    }
+}
@@ -0,0 +1,394 @@
+use core::future::Future;
+
+use crate::actuation::{Channel, ChannelFlags};
+use crate::power::{ChargeInput, ChargeState};
+use crate::validate::SampleFlags;
+use crate::{BaroData, FixType, GpsData, ImuData};
+
+/// Barometer driver, returns raw (uncompensated) pressure in hPa and temperature in C
+pub trait Barometer {
+    type Error: defmt::Format;
+
+    /// take one sample, stamped with the given time stamp
+    fn read(&mut self, time_stamp: u32) -> impl Future<Output = Result<BaroData, Self::Error>>;
+
+    /// forget the part's setup so the next read sets it up again, after its bus was recovered
+    fn reinit(&mut self) {}
+}
+
+/// Imu driver, returns raw (uncalibrated) acceleration in m/s^2, gyro in rad/s, and mag
+pub trait Imu {
+    type Error: defmt::Format;
+
+    /// take one sample, stamped with the given time stamp
+    fn read(&mut self, time_stamp: u32) -> impl Future<Output = Result<ImuData, Self::Error>>;
+
+    /// set the output data rate to about one sample per period (us) and raise the data ready line for each one
+    /// returns the output period the sensor actually runs at, which can be shorter than asked
+    /// sensors without a data ready line leave this as is and are polled
+    fn set_output_rate(&mut self, period_us: u32) -> impl Future<Output = Result<u32, Self::Error>> {
+        async move { Ok(period_us) }
+    }
+
+    /// fill samples with raw acceleration (m/s^2) at the sensor's fastest rate, a vibration burst
+    /// returns the sample period (us), 0 from sensors that can't, which leave the samples as they are
+    /// the output rate is the same afterwards as before
+    fn burst(&mut self, _samples: &mut [[f32; 3]]) -> impl Future<Output = Result<u32, Self::Error>> {
+        async move { Ok(0) }
+    }
+
+    /// forget the part's setup so the next read sets it up again, after its bus was recovered
+    fn reinit(&mut self) {}
+}
+
+/// Magnetometer on its own part, for imus without one
+pub trait Magnetometer {
+    type Error: defmt::Format;
+
+    /// read the field, uT
+    fn read(&mut self) -> impl Future<Output = Result<[f32; 3], Self::Error>>;
+
+    /// forget the part's setup so the next read sets it up again, after its bus was recovered
+    fn reinit(&mut self) {}
+}
+
+/// Imu whose mag is a separate magnetometer, the mag is read right after the imu sample
+pub struct WithMag<I, M> {
+    pub imu: I,
+    pub mag: M,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, defmt::Format)]
+pub enum WithMagError<I, M> {
+    Imu(I),
+    Mag(M),
+}
+
+impl<I: Imu, M: Magnetometer> Imu for WithMag<I, M> {
+    type Error = WithMagError<I::Error, M::Error>;
+
+    async fn read(&mut self, time_stamp: u32) -> Result<ImuData, Self::Error> {
+        let mut data = self.imu.read(time_stamp).await.map_err(WithMagError::Imu)?;
+        data.mag = self.mag.read().await.map_err(WithMagError::Mag)?;
+        Ok(data)
+    }
+
+    async fn set_output_rate(&mut self, period_us: u32) -> Result<u32, Self::Error> {
+        self.imu.set_output_rate(period_us).await.map_err(WithMagError::Imu)
+    }
+
+    async fn burst(&mut self, samples: &mut [[f32; 3]]) -> Result<u32, Self::Error> {
+        self.imu.burst(samples).await.map_err(WithMagError::Imu)
+    }
+
+    fn reinit(&mut self) {
+        self.imu.reinit();
+        self.mag.reinit();
+    }
+}
+
+/// Gps receiver driver, waits for the next fix
+pub trait Gps {
+    type Error: defmt::Format;
+
+    /// read the next fix, stamped with the given time stamp
+    fn read(&mut self, time_stamp: u32) -> impl Future<Output = Result<GpsData, Self::Error>>;
+
+    /// pass one whole ubx mga assistance message to the receiver
+    fn assist(&mut self, message: &[u8]) -> impl Future<Output = Result<(), Self::Error>>;
+}
+
+/// Supply voltage monitor on the battery rail
+pub trait Battery {
+    type Error: defmt::Format;
+
+    /// read the battery voltage, V
+    fn read(&mut self) -> impl Future<Output = Result<f32, Self::Error>>;
+}
+
+/// Solar array and charger monitor on the charger input
+pub trait Charger {
+    type Error: defmt::Format;
+
+    /// read the array voltage and current and the charger's status pins
+    fn read(&mut self) -> impl Future<Output = Result<ChargeInput, Self::Error>>;
+}
+
+/// Which picture a camera takes
+#[derive(Copy, Clone, PartialEq, Eq, Debug, defmt::Format)]
+pub enum ImageSize {
+    /// the camera's own still size, for the card
+    Full,
+    /// a small picture the radio can send as ssdv, width and height multiples of 16
+    Thumbnail,
+}
+
+/// Camera that takes jpeg stills, the picture is held on the camera and read out a piece at a time since a full
+/// size jpeg doesn't fit in ram
+pub trait Camera {
+    type Error: defmt::Format;
+
+    /// take a picture, returns its jpeg length in bytes
+    fn capture(&mut self, size: ImageSize) -> impl Future<Output = Result<u32, Self::Error>>;
+
+    /// the next bytes of the last picture into buf, returns how many, 0 once it has all been read
+    fn read(&mut self, buf: &mut [u8]) -> impl Future<Output = Result<usize, Self::Error>>;
+
+    /// forget the camera's setup so the next capture sets it up again, after its bus was recovered
+    fn reinit(&mut self) {}
+}
+
+/// Pyro and cutdown outputs, each with a continuity sense line and a current sense through its load
+pub trait Actuators {
+    type Error: defmt::Format;
+
+    /// read a channel's continuity sense voltage, V, low with a load across the channel
+    fn continuity(&mut self, channel: Channel) -> impl Future<Output = Result<f32, Self::Error>>;
+
+    /// switch a channel's output on to fire it, or off
+    fn set_output(&mut self, channel: Channel, on: bool) -> impl Future<Output = Result<(), Self::Error>>;
+
+    /// read the current through a channel's load, A
+    fn current(&mut self, channel: Channel) -> impl Future<Output = Result<f32, Self::Error>>;
+}
+
+/// Mock barometer, reads the same pressure and temperature give or take the last digit
+pub struct MockBarometer {
+    pub pressure: f32,
+    pub temperature: f32,
+    reads: u32,
+}
+
+impl Default for MockBarometer {
+    fn default() -> Self {
+        Self {
+            pressure: 1013.25,
+            temperature: 25.0,
+            reads: 0,
+        }
+    }
+}
+
+impl Barometer for MockBarometer {
+    type Error = ();
+
+    // the last digit toggles like a live part's noise, otherwise the samples are flagged frozen
+    async fn read(&mut self, time_stamp: u32) -> Result<BaroData, ()> {
+        self.reads = self.reads.wrapping_add(1);
+        Ok(BaroData {
+            pressure: self.pressure + (self.reads % 2) as f32 * 0.01,
+            temperature: self.temperature,
+            flags: SampleFlags::NONE,
+            time_stamp,
+        })
+    }
+}
+
+/// Mock imu, sitting still and level give or take the last digit
+pub struct MockImu {
+    pub acceleration: [f32; 3],
+    pub gyro: [f32; 3],
+    pub mag: [f32; 3],
+    pub temperature: f32,
+    reads: u32,
+}
+
+impl Default for MockImu {
+    fn default() -> Self {
+        Self {
+            acceleration: [0.0, 0.0, 9.81],
+            gyro: [0.0, 0.0, 0.0],
+            mag: [0.0, 0.0, 0.0],
+            temperature: 25.0,
+            reads: 0,
+        }
+    }
+}
+
+impl Imu for MockImu {
+    type Error = ();
+
+    // the last digit toggles like a live part's noise, otherwise the samples are flagged frozen
+    async fn read(&mut self, time_stamp: u32) -> Result<ImuData, ()> {
+        self.reads = self.reads.wrapping_add(1);
+        let mut acceleration = self.acceleration;
+        acceleration[2] += (self.reads % 2) as f32 * 0.001;
+        Ok(ImuData {
+            acceleration,
+            gyro: self.gyro,
+            mag: self.mag,
+            temperature: self.temperature,
+            flags: SampleFlags::NONE,
+            time_stamp,
+        })
+    }
+
+    // still at a 4 kHz rate, so the bursts go through the whole chain on the bench
+    async fn burst(&mut self, samples: &mut [[f32; 3]]) -> Result<u32, ()> {
+        samples.fill(self.acceleration);
+        Ok(250)
+    }
+}
+
+/// Mock battery, a fresh 2S pack
+pub struct MockBattery {
+    pub voltage: f32,
+}
+
+impl Default for MockBattery {
+    fn default() -> Self {
+        Self { voltage: 8.2 }
+    }
+}
+
+impl Battery for MockBattery {
+    type Error = ();
+
+    async fn read(&mut self) -> Result<f32, ()> {
+        Ok(self.voltage)
+    }
+}
+
+/// Mock solar charger, a lit array charging the battery
+pub struct MockCharger {
+    pub input: ChargeInput,
+}
+
+impl Default for MockCharger {
+    fn default() -> Self {
+        Self {
+            input: ChargeInput { voltage: 18.0, current: 0.3, state: ChargeState::Charging },
+        }
+    }
+}
+
+impl Charger for MockCharger {
+    type Error = ();
+
+    async fn read(&mut self) -> Result<ChargeInput, ()> {
+        Ok(self.input)
+    }
+}
+
+/// Mock gps, a stationary fix at the launch site
+pub struct MockGps {
+    pub latitude: f64,
+    pub longitude: f64,
+    pub altitude: f32,
+    pub satellites: u8,
+    pub hdop: f32,
+    pub utc: Option<u64>,
+}
+
+impl Default for MockGps {
+    fn default() -> Self {
+        Self {
+            latitude: 40.4237,
+            longitude: -86.9212,
+            altitude: 187.0,
+            satellites: 8,
+            hdop: 1.2,
+            utc: None,
+        }
+    }
+}
+
+impl Gps for MockGps {
+    type Error = ();
+
+    async fn read(&mut self, time_stamp: u32) -> Result<GpsData, ()> {
+        Ok(GpsData {
+            latitude: self.latitude,
+            longitude: self.longitude,
+            altitude: self.altitude,
+            velocity: [0.0, 0.0, 0.0],
+            satellites: self.satellites,
+            fix: FixType::ThreeD,
+            hdop: self.hdop,
+            quality: Default::default(),
+            flags: SampleFlags::NONE,
+            utc: self.utc,
+            time_stamp,
+        })
+    }
+
+    async fn assist(&mut self, _message: &[u8]) -> Result<(), ()> {
+        Ok(())
+    }
+}
+
+
+// a 16x16 mid grey baseline jpeg, one 2x2 MCU, huffman tables with only the symbols the scan uses
+const MOCK_JPEG: [u8; 184] = [
+    0xFF, 0xD8,
+    // quantisation table 0, all steps 1
+    0xFF, 0xDB, 0x00, 0x43, 0x00,
+    1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1,
+    1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1,
+    // 16x16, y 2x2, cb and cr 1x1
+    0xFF, 0xC0, 0x00, 0x11, 0x08, 0x00, 0x10, 0x00, 0x10, 0x03, 0x01, 0x22, 0x00, 0x02, 0x11, 0x00, 0x03, 0x11, 0x00,
+    // dc and ac tables 0 and 1, each one code of length 1 for symbol 0
+    0xFF, 0xC4, 0x00, 0x4A,
+    0x00, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x00,
+    0x10, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x00,
+    0x01, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x00,
+    0x11, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x00,
+    0xFF, 0xDA, 0x00, 0x0C, 0x03, 0x01, 0x00, 0x02, 0x11, 0x03, 0x11, 0x00, 0x3F, 0x00,
+    // every block dc difference 0 then end of block, padded with 1s
+    0x00, 0x0F,
+    0xFF, 0xD9,
+];
+
+/// Mock camera, every picture is the same small grey jpeg whatever the size asked for
+#[derive(Default)]
+pub struct MockCamera {
+    at: usize,
+}
+
+impl Camera for MockCamera {
+    type Error = ();
+
+    async fn capture(&mut self, _size: ImageSize) -> Result<u32, ()> {
+        self.at = 0;
+        Ok(MOCK_JPEG.len() as u32)
+    }
+
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, ()> {
+        let rest = &MOCK_JPEG[self.at..];
+        let len = rest.len().min(buf.len());
+        buf[..len].copy_from_slice(&rest[..len]);
+        self.at += len;
+        Ok(len)
+    }
+}
+
+/// Mock actuators, every channel reads its fixed sense voltage and draws its fixed current while on, all with a
+/// load fitted by default
+pub struct MockActuators {
+    pub volts: [f32; Channel::ALL.len()],
+    pub amps: [f32; Channel::ALL.len()],
+    on: ChannelFlags,
+}
+
+impl Default for MockActuators {
+    fn default() -> Self {
+        Self { volts: [0.1; Channel::ALL.len()], amps: [2.0; Channel::ALL.len()], on: ChannelFlags::NONE }
+    }
+}
+
+impl Actuators for MockActuators {
+    type Error = ();
+
+    async fn continuity(&mut self, channel: Channel) -> Result<f32, ()> {
+        Ok(self.volts[channel.id() as usize])
+    }
+
+    async fn set_output(&mut self, channel: Channel, on: bool) -> Result<(), ()> {
+        self.on.set(channel, on);
+        Ok(())
+    }
+
+    async fn current(&mut self, channel: Channel) -> Result<f32, ()> {
+        Ok(if self.on.contains(channel) { self.amps[channel.id() as usize] } else { 0.0 })
+    }
+}
@@ -0,0 +1,166 @@
+// mission sequence, a few condition to action rules set in config, so what changes from flight to flight, when the
+// camera comes on or the gnc experiment starts, is set on the pad instead of built into the firmware
+// each rule is three config words, rule<n>_when says what it waits for and rule<n>_at the value for it, and rule<n>_do
+// what it does, the action in the high byte and its rail or channel id in the low byte
+//   when: 0 off, 1 altitude above sea level reached (at in m), 2 flight state is (at the state id), 3 mission elapsed
+//   time reached (at in s), 4 float or landing confirmed by the duty cycle
+//   do: 0x01nn rail on, 0x02nn rail off, 0x03nn arm channel, 0x04nn disarm channel, 0x05nn fire channel
+// the control task checks the rules every loop, each fires once per boot the first time its condition holds, so a
+// rule for the pad state fires at boot, and a rule changed in config can fire again
+// a rail action holds the rail off or releases it back to its sequence, a channel action goes through the command
+// channel with the same checks as an uplinked command
+
+use crate::actuation::Channel;
+use crate::command::Command;
+use crate::config::Config;
+use crate::met::MetClock;
+use crate::mission::FlightState;
+use crate::rails::Rail;
+
+/// rules in config
+pub const MISSION_RULES: usize = 4;
+
+/// What a rule waits for
+#[derive(Copy, Clone, PartialEq, Debug, defmt::Format)]
+pub enum Trigger {
+    Never,
+    /// filtered baro altitude above sea level reached, m
+    Altitude(f32),
+    /// the flight state is this one
+    State(FlightState),
+    /// mission elapsed time reached, s
+    Met(u32),
+    /// the duty cycle confirmed a float or a landing
+    Float,
+}
+
+/// What a rule does
+#[derive(Copy, Clone, PartialEq, defmt::Format)]
+pub enum Action {
+    /// release a rail to its sequence, or hold it off
+    Rail { rail: Rail, on: bool },
+    /// an actuation channel command, carried out like an uplinked one
+    Command(Command),
+}
+
+/// One condition to action rule
+#[derive(Copy, Clone, PartialEq, defmt::Format)]
+pub struct Rule {
+    pub trigger: Trigger,
+    /// None for a rule that is off
+    pub action: Option<Action>,
+}
+
+impl Rule {
+    pub const OFF: Rule = Rule { trigger: Trigger::Never, action: None };
+
+    /// rule from its config words, an unknown code turns it off
+    pub fn from_words(when: u32, at: f32, action: u32) -> Self {
+        let trigger = match when {
+            1 => Trigger::Altitude(at),
+            2 => FlightState::from_id(at as u8).map_or(Trigger::Never, Trigger::State),
+            3 => Trigger::Met(at as u32),
+            4 => Trigger::Float,
+            _ => Trigger::Never,
+        };
+        let id = action as u8;
+        let action = match action >> 8 {
+            1 => Rail::from_id(id).map(|rail| Action::Rail { rail, on: true }),
+            2 => Rail::from_id(id).map(|rail| Action::Rail { rail, on: false }),
+            3 => Channel::from_id(id).map(|channel| Action::Command(Command::ArmChannel { channel, override_continuity: false })),
+            4 => Channel::from_id(id).map(|channel| Action::Command(Command::DisarmChannel(channel))),
+            5 => Channel::from_id(id).map(|channel| Action::Command(Command::Fire(channel))),
+            _ => None,
+        };
+        match (trigger, action) {
+            (Trigger::Never, _) | (_, None) => Self::OFF,
+            _ => Self { trigger, action },
+        }
+    }
+}
+
+/// the rules as set in config
+pub fn rules(config: &Config) -> [Rule; MISSION_RULES] {
+    [
+        Rule::from_words(config.rule1_when, config.rule1_at, config.rule1_do),
+        Rule::from_words(config.rule2_when, config.rule2_at, config.rule2_do),
+        Rule::from_words(config.rule3_when, config.rule3_at, config.rule3_do),
+        Rule::from_words(config.rule4_when, config.rule4_at, config.rule4_do),
+    ]
+}
+
+/// What the rules look at each pass
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct SequenceInput {
+    pub state: FlightState,
+    /// filtered baro altitude above sea level, m, None before the first baro sample
+    pub altitude: Option<f32>,
+    pub floating: bool,
+    /// uptime time stamp of launch detection, None before launch
+    pub launch: Option<u32>,
+    pub time_stamp: u32,
+}
+
+/// Checks the rules and keeps track of the ones that fired
+pub struct Sequence<const N: usize> {
+    rules: [Rule; N],
+    fired: [bool; N],
+    met: MetClock,
+}
+
+impl<const N: usize> Default for Sequence<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> Sequence<N> {
+    pub const fn new() -> Self {
+        Self {
+            rules: [Rule::OFF; N],
+            fired: [false; N],
+            met: MetClock::new(),
+        }
+    }
+
+    /// take the rules as configured, one that changed can fire again
+    pub fn load(&mut self, rules: [Rule; N]) {
+        for ((rule, fired), new) in self.rules.iter_mut().zip(self.fired.iter_mut()).zip(rules) {
+            if *rule != new {
+                *rule = new;
+                *fired = false;
+            }
+        }
+    }
+
+    /// the first rule whose condition holds that hasn't fired, with its index, it fires once done is called, so an
+    /// action that can't be carried out yet is tried again on the next pass
+    pub fn due(&mut self, input: &SequenceInput) -> Option<(usize, Action)> {
+        let met = self.met.update(input.launch, input.time_stamp);
+        self.rules.iter().zip(self.fired).enumerate().find_map(|(index, (rule, fired))| {
+            let holds = match rule.trigger {
+                Trigger::Never => false,
+                Trigger::Altitude(target) => input.altitude.is_some_and(|altitude| altitude >= target),
+                Trigger::State(state) => input.state == state,
+                Trigger::Met(seconds) => met.is_some_and(|met| met >= seconds as u64 * 1_000_000),
+                Trigger::Float => input.floating,
+            };
+            if holds && !fired { rule.action.map(|action| (index, action)) } else { None }
+        })
+    }
+
+    /// mark a rule fired
+    pub fn done(&mut self, index: usize) {
+        if let Some(fired) = self.fired.get_mut(index) {
+            *fired = true;
+        }
+    }
+
+    pub fn rules(&self) -> &[Rule; N] {
+        &self.rules
+    }
+
+    pub fn fired(&self, index: usize) -> bool {
+        self.fired.get(index).copied().unwrap_or(false)
+    }
+}
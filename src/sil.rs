@@ -0,0 +1,322 @@
+use crate::config::Config;
+use crate::estimator::AltitudeEstimator;
+use crate::faults::{Fault, FaultEvent, FaultLog};
+use crate::filters::AltitudeKalman;
+use crate::health::{Stream, StreamMonitor};
+use crate::mission::{FlightState, FreeFallDetector, Mission};
+
+/// Scripted flights for software in the loop runs of the mission logic
+/// every scenario is deterministic, the same run always produces the same actions at the same times
+#[derive(Copy, Clone, PartialEq, Eq, Debug, defmt::Format)]
+pub enum Scenario {
+    /// pad, 5 m/s ascent to burst at 30 km, free fall, 8 m/s descent under parachute, landing
+    Nominal,
+    /// nominal flight with the balloon bursting at 8 km
+    EarlyBurst,
+    /// nominal flight with no gps above 18 km (cocom limit)
+    GpsLoss,
+    /// nominal flight with the barometer dropping out for a minute during ascent
+    BaroFailure,
+}
+
+impl Scenario {
+    pub const ALL: [Scenario; 4] = [Scenario::Nominal, Scenario::EarlyBurst, Scenario::GpsLoss, Scenario::BaroFailure];
+
+    fn burst_altitude(self) -> f32 {
+        match self {
+            Scenario::EarlyBurst => 8_000.0,
+            _ => 30_000.0,
+        }
+    }
+
+    /// the actions a correct run must produce, in order
+    pub fn expected(self) -> &'static [Action] {
+        match self {
+            Scenario::Nominal | Scenario::EarlyBurst => &[
+                Action::Transition(FlightState::Ascent),
+                Action::HighRateLogging(true),
+                Action::Transition(FlightState::Descent),
+                Action::Transition(FlightState::Landed),
+                Action::HighRateLogging(false),
+            ],
+            Scenario::GpsLoss => &[
+                Action::Transition(FlightState::Ascent),
+                Action::Fault(Fault::Stale(Stream::Gps), true),
+                Action::HighRateLogging(true),
+                Action::Transition(FlightState::Descent),
+                Action::Fault(Fault::Stale(Stream::Gps), false),
+                Action::Transition(FlightState::Landed),
+                Action::HighRateLogging(false),
+            ],
+            Scenario::BaroFailure => &[
+                Action::Transition(FlightState::Ascent),
+                Action::Fault(Fault::Stale(Stream::Baro), true),
+                Action::Fault(Fault::Stale(Stream::Baro), false),
+                Action::HighRateLogging(true),
+                Action::Transition(FlightState::Descent),
+                Action::Transition(FlightState::Landed),
+                Action::HighRateLogging(false),
+            ],
+        }
+    }
+}
+
+/// What the flight software decided to do, the outputs a run is checked on
+#[derive(Copy, Clone, PartialEq, Eq, Debug, defmt::Format)]
+pub enum Action {
+    Transition(FlightState),
+    HighRateLogging(bool),
+    Fault(Fault, bool),
+}
+
+/// First difference between a run and the expected actions, None where a list ran out
+#[derive(Copy, Clone, PartialEq, Eq, Debug, defmt::Format)]
+pub struct Mismatch {
+    pub index: usize,
+    pub expected: Option<Action>,
+    pub actual: Option<Action>,
+}
+
+/// launch site altitude, m
+const GROUND_ALTITUDE: f32 = 190.0;
+
+/// time on the pad before launch, s
+const PAD_TIME: f32 = 60.0;
+
+/// time on the ground after landing before the run ends, s
+const LANDED_TIME: f32 = 120.0;
+
+const ASCENT_RATE: f32 = 5.0;
+const DESCENT_RATE: f32 = 8.0;
+
+/// free fall after burst before the parachute opens, s
+const FREE_FALL_TIME: f32 = 3.0;
+
+const GRAVITY: f32 = 9.81;
+
+/// gps receivers stop reporting above this altitude, m
+const GPS_CEILING: f32 = 18_000.0;
+
+/// baro dropout window in the BaroFailure scenario, s
+const BARO_DROPOUT: (f32, f32) = (600.0, 660.0);
+
+/// simulation step, the imu rate, baro runs every BARO_TICKS steps and gps every GPS_TICKS, us
+const TICK: u32 = 100_000;
+const BARO_TICKS: u32 = 5;
+const GPS_TICKS: u32 = 10;
+
+/// same stale timeouts as the flight build, us, in Stream::ALL order
+const STREAM_TIMEOUTS: [u32; Stream::ALL.len()] = [5_000_000, 2_000_000, 2_000_000, 5_000_000, 2_000_000];
+
+/// Truth for one simulation step
+struct Truth {
+    altitude: f32,
+    acceleration: [f32; 3],
+    landed_for: Option<f32>,
+}
+
+fn truth(scenario: Scenario, t: f32) -> Truth {
+    let burst = scenario.burst_altitude();
+    let burst_time = PAD_TIME + (burst - GROUND_ALTITUDE) / ASCENT_RATE;
+    let still = [0.0, 0.0, GRAVITY];
+
+    if t < PAD_TIME {
+        return Truth { altitude: GROUND_ALTITUDE, acceleration: still, landed_for: None };
+    }
+    if t < burst_time {
+        return Truth { altitude: GROUND_ALTITUDE + (t - PAD_TIME) * ASCENT_RATE, acceleration: still, landed_for: None };
+    }
+
+    // nothing pushes on the accelerometer while falling freely
+    let falling = t - burst_time;
+    if falling < FREE_FALL_TIME {
+        return Truth { altitude: burst - 0.5 * GRAVITY * falling * falling, acceleration: [0.0, 0.0, 0.3], landed_for: None };
+    }
+
+    let chute_altitude = burst - 0.5 * GRAVITY * FREE_FALL_TIME * FREE_FALL_TIME;
+    let altitude = chute_altitude - (falling - FREE_FALL_TIME) * DESCENT_RATE;
+    if altitude > GROUND_ALTITUDE {
+        return Truth { altitude, acceleration: still, landed_for: None };
+    }
+
+    let landing_time = burst_time + FREE_FALL_TIME + (chute_altitude - GROUND_ALTITUDE) / DESCENT_RATE;
+    Truth { altitude: GROUND_ALTITUDE, acceleration: still, landed_for: Some(t - landing_time) }
+}
+
+/// Small fixed seed generator for repeatable baro noise
+struct Noise(u32);
+
+impl Noise {
+    /// next sample in -1..1
+    fn next(&mut self) -> f32 {
+        self.0 = self.0.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+        (self.0 >> 8) as f32 / (1 << 23) as f32 - 1.0
+    }
+}
+
+/// Runs the mission logic over a scripted flight, calling back with every action and the time since the start of the
+/// run (us), which unlike the time stamps the logic is fed doesn't wrap, the nominal flight outlasts a wrap
+/// the same pieces the flight tasks use: altitude filter, flight state machine, free fall detector, stream monitor
+pub fn run(scenario: Scenario, mut on_action: impl FnMut(u64, Action)) {
+    let config = Config::DEFAULT;
+    let mut altitude = AltitudeKalman::new(config.altitude_accel_sigma, config.altitude_measurement_sigma);
+    let mut mission = Mission::new();
+    let mut free_fall = FreeFallDetector::new();
+    let mut monitor = StreamMonitor::new(STREAM_TIMEOUTS);
+    let mut faults = FaultLog::<8>::new();
+    let mut noise = Noise(1);
+    let mut high_rate = false;
+
+    let mut tick: u32 = 0;
+    loop {
+        let time_stamp = tick.wrapping_mul(TICK);
+        let elapsed = tick as u64 * TICK as u64;
+        let t = tick as f32 * (TICK as f32 / 1e6);
+        let truth = truth(scenario, t);
+        if truth.landed_for.is_some_and(|landed| landed > LANDED_TIME) {
+            return;
+        }
+
+        // imu, attitude, and nav always run in these scripts
+        monitor.record(Stream::Imu, time_stamp);
+        monitor.record(Stream::Attitude, time_stamp);
+        monitor.record(Stream::Nav, time_stamp);
+
+        if free_fall.update(truth.acceleration, time_stamp) && !high_rate {
+            high_rate = true;
+            on_action(elapsed, Action::HighRateLogging(true));
+        }
+
+        let baro_out = scenario == Scenario::BaroFailure && (BARO_DROPOUT.0..BARO_DROPOUT.1).contains(&t);
+        if tick.is_multiple_of(BARO_TICKS) && !baro_out {
+            monitor.record(Stream::Baro, time_stamp);
+            let state = AltitudeEstimator::update(&mut altitude, truth.altitude + noise.next(), time_stamp);
+
+            if let Some(state) = mission.update(&state) {
+                on_action(elapsed, Action::Transition(state));
+                if state == FlightState::Landed && high_rate {
+                    high_rate = false;
+                    on_action(elapsed, Action::HighRateLogging(false));
+                }
+            }
+        }
+
+        let gps_out = scenario == Scenario::GpsLoss && truth.altitude > GPS_CEILING;
+        if tick.is_multiple_of(GPS_TICKS) && !gps_out {
+            monitor.record(Stream::Gps, time_stamp);
+        }
+
+        let flags = monitor.check(time_stamp);
+        for stream in Stream::ALL {
+            let fault = Fault::Stale(stream);
+            let active = flags.contains(fault);
+            if faults.report(FaultEvent { fault, active, time_stamp }) {
+                on_action(elapsed, Action::Fault(fault, active));
+            }
+        }
+
+        tick += 1;
+    }
+}
+
+/// run a scenario and compare its actions against the expected list
+pub fn check(scenario: Scenario) -> Result<(), Mismatch> {
+    let expected = scenario.expected();
+    let mut index = 0;
+    let mut mismatch = None;
+
+    run(scenario, |_, action| {
+        if mismatch.is_none() && expected.get(index) != Some(&action) {
+            mismatch = Some(Mismatch { index, expected: expected.get(index).copied(), actual: Some(action) });
+        }
+        index += 1;
+    });
+
+    match mismatch {
+        Some(mismatch) => Err(mismatch),
+        None if index < expected.len() => Err(Mismatch { index, expected: Some(expected[index]), actual: None }),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn actions(scenario: Scenario) -> Vec<(u64, Action)> {
+        let mut actions = Vec::new();
+        run(scenario, |elapsed, action| actions.push((elapsed, action)));
+        actions
+    }
+
+    /// time (s) of the first action matching
+    fn first(actions: &[(u64, Action)], action: Action) -> f32 {
+        let (elapsed, _) = actions.iter().find(|(_, a)| *a == action).unwrap_or_else(|| panic!("no {action:?}"));
+        *elapsed as f32 / 1e6
+    }
+
+    #[test]
+    fn scenarios_produce_the_expected_actions() {
+        for scenario in Scenario::ALL {
+            if let Err(mismatch) = check(scenario) {
+                panic!("{scenario:?}: action {} was {:?}, expected {:?}", mismatch.index, mismatch.actual, mismatch.expected);
+            }
+        }
+    }
+
+    #[test]
+    fn runs_are_deterministic() {
+        for scenario in Scenario::ALL {
+            assert_eq!(actions(scenario), actions(scenario), "{scenario:?}");
+        }
+    }
+
+    #[test]
+    fn nominal_transitions_come_on_time() {
+        let actions = actions(Scenario::Nominal);
+        let burst_time = PAD_TIME + (30_000.0 - GROUND_ALTITUDE) / ASCENT_RATE;
+
+        // launch within a few baro samples of leaving the pad
+        let launch = first(&actions, Action::Transition(FlightState::Ascent));
+        assert!((PAD_TIME..PAD_TIME + 5.0).contains(&launch), "launch at {launch} s");
+
+        // free fall is the first sign of burst, the descent follows once the filter sees the sink rate
+        let free_fall = first(&actions, Action::HighRateLogging(true));
+        assert!((burst_time..burst_time + 1.0).contains(&free_fall), "free fall at {free_fall} s");
+        let descent = first(&actions, Action::Transition(FlightState::Descent));
+        assert!((free_fall..burst_time + 10.0).contains(&descent), "descent at {descent} s");
+
+        let landing_time = burst_time + FREE_FALL_TIME
+            + (30_000.0 - 0.5 * GRAVITY * FREE_FALL_TIME * FREE_FALL_TIME - GROUND_ALTITUDE) / DESCENT_RATE;
+        let landed = first(&actions, Action::Transition(FlightState::Landed));
+        assert!((landing_time..landing_time + 30.0).contains(&landed), "landed at {landed} s, touchdown at {landing_time} s");
+    }
+
+    #[test]
+    fn early_burst_descends_from_8_km() {
+        let actions = actions(Scenario::EarlyBurst);
+        let burst_time = PAD_TIME + (8_000.0 - GROUND_ALTITUDE) / ASCENT_RATE;
+        let descent = first(&actions, Action::Transition(FlightState::Descent));
+        assert!((burst_time..burst_time + 10.0).contains(&descent), "descent at {descent} s");
+    }
+
+    #[test]
+    fn gps_loss_is_flagged_at_the_ceiling() {
+        let actions = actions(Scenario::GpsLoss);
+        let ceiling_time = PAD_TIME + (GPS_CEILING - GROUND_ALTITUDE) / ASCENT_RATE;
+        let stale = first(&actions, Action::Fault(Fault::Stale(Stream::Gps), true));
+        assert!((ceiling_time..ceiling_time + 6.0).contains(&stale), "gps stale at {stale} s");
+    }
+
+    #[test]
+    fn baro_dropout_is_flagged_and_cleared() {
+        let actions = actions(Scenario::BaroFailure);
+        let stale = first(&actions, Action::Fault(Fault::Stale(Stream::Baro), true));
+        let back = first(&actions, Action::Fault(Fault::Stale(Stream::Baro), false));
+        assert!((BARO_DROPOUT.0..BARO_DROPOUT.0 + 6.0).contains(&stale), "baro stale at {stale} s");
+        assert!((BARO_DROPOUT.1..BARO_DROPOUT.1 + 1.0).contains(&back), "baro back at {back} s");
+        // the dropout is mid ascent, it mustn't look like a burst
+        let descent = first(&actions, Action::Transition(FlightState::Descent));
+        assert!(descent > BARO_DROPOUT.1 + 1000.0, "descent at {descent} s");
+    }
+}
@@ -0,0 +1,50 @@
+// firmware image slots and the update record, shared by the application and, on the nucleo, the bootloader
+// build.rs links each binary to its part of flash and has to agree with the layout here
+
+use avionics_sw_hapsis::update::UpdateRecord;
+
+// update record lives in backup sram after the panic record, on the f4 and f7 alike
+pub const UPDATE_RECORD: *mut UpdateRecord = 0x4002_4100 as *mut UpdateRecord;
+
+// boots a new image gets to prove itself before it is rolled back
+pub const UPDATE_TRIAL_BOOTS: u32 = 3;
+
+// the flight board's f407 has no room for an update slot, its 1M holds the running image, which is well past the
+// 256K a third of the flash outside config and calibration would give each slot, so it can only be updated over
+// swd or usb dfu and every transfer is refused, see update_step in main.rs
+#[cfg(not(feature = "nucleo-f767"))]
+pub const IMAGE_SLOT_SIZE: u32 = 0;
+
+// nucleo-f767zi, 2M in single bank mode, sectors 0-3 are 32K, sector 4 128K, and sectors 5-11 256K
+// the bootloader keeps sector 0 and the application never writes it, the application runs from sectors 1-5 (480K),
+// an update is received into sectors 6-7 and the running image is backed up to sectors 8-9 before one is installed
+// config and calibration keep sectors 10 and 11
+// the update and backup slots are larger than an image, a SlotTrailer past the image says a whole one is there,
+// and two words after it are programmed when the bootloader starts and finishes copying it over the application,
+// so a copy a reset or power loss cut short is finished at the next boot
+// each binary uses its own part of the layout
+#[cfg(feature = "nucleo-f767")]
+#[allow(dead_code)]
+mod nucleo {
+    pub const FLASH_BASE: u32 = 0x0800_0000;
+
+    // largest image, the application slot
+    pub const IMAGE_SLOT_SIZE: u32 = 0x7_8000;
+
+    // offsets and (first sector, sector count)
+    pub const APP_SLOT: (u32, u8, u8) = (0x8000, 1, 5);
+    pub const UPDATE_SLOT: (u32, u8, u8) = (0x8_0000, 6, 2);
+    pub const BACKUP_SLOT: (u32, u8, u8) = (0x10_0000, 8, 2);
+    pub const SLOT_SIZE: u32 = 0x8_0000;
+
+    // within the update and backup slots, the flags are each a word left erased until programmed to zero and sit
+    // a flash write past the trailer so writing the trailer never touches them
+    pub const TRAILER_OFFSET: u32 = IMAGE_SLOT_SIZE;
+    pub const STARTED_OFFSET: u32 = TRAILER_OFFSET + 0x20;
+    pub const DONE_OFFSET: u32 = TRAILER_OFFSET + 0x40;
+
+    const _: () = assert!(DONE_OFFSET + 4 <= SLOT_SIZE);
+}
+
+#[cfg(feature = "nucleo-f767")]
+pub use nucleo::*;
@@ -0,0 +1,795 @@
+// ssdv, the amateur balloon image format, a baseline jpeg cut into fixed size packets that each decode on their own,
+// so a lost packet costs a few MCUs of the picture instead of the rest of it
+// the scan is re-coded with the standard huffman tables and ssdv's quantisation tables for the quality level, and the
+// dc prediction starts over at the first MCU of every packet, which starts byte aligned at the offset in the header
+// the ground puts the sync byte back and rebuilds the jpeg with the ssdv tool, ssdv -d -l PACKET_LEN
+// packets are the no fec type, lora has its own crc and forward error correction
+
+use crate::crc::crc32;
+
+/// bytes per packet, sync byte included as the ssdv tool counts them, the downlink leaves the sync byte out
+pub const PACKET_LEN: usize = 128;
+/// quality level 0 to 7, 4 is the standard jpeg tables
+pub const DEFAULT_QUALITY: u8 = 4;
+
+const SYNC: u8 = 0x55;
+const TYPE_NOFEC: u8 = 0x67;
+// sync, type, callsign, image id, packet id, width, height, flags, mcu offset, mcu index
+const HEADER_LEN: usize = 15;
+const CRC_LEN: usize = 4;
+const PAYLOAD_LEN: usize = PACKET_LEN - HEADER_LEN - CRC_LEN;
+// no mcu starts in the packet
+const NO_MCU: (u8, u16) = (0xFF, 0xFFFF);
+
+// jpeg markers
+const SOI: u8 = 0xD8;
+const EOI: u8 = 0xD9;
+const SOF0: u8 = 0xC0;
+const SOF1: u8 = 0xC1;
+const DHT: u8 = 0xC4;
+const DQT: u8 = 0xDB;
+const DRI: u8 = 0xDD;
+const SOS: u8 = 0xDA;
+const RST0: u8 = 0xD0;
+const RST7: u8 = 0xD7;
+
+// ssdv sizes are in units of 16 px
+const SIZE_UNIT: u16 = 16;
+
+// standard quantisation tables, luminance and chrominance, in zigzag order, scaled by quality as libjpeg does
+const STANDARD_QUANTISATION: [[u8; 64]; 2] = [
+    [
+        16, 11, 12, 14, 12, 10, 16, 14, 13, 14, 18, 17, 16, 19, 24, 40, 26, 24, 22, 22, 24, 49, 35, 37, 29, 40, 58, 51, 61, 60, 57, 51,
+        56, 55, 64, 72, 92, 78, 64, 68, 87, 69, 55, 56, 80, 109, 81, 87, 95, 98, 103, 104, 103, 62, 77, 113, 121, 112, 100, 120, 92, 101, 103, 99,
+    ],
+    [
+        17, 18, 18, 24, 21, 24, 47, 26, 26, 47, 99, 66, 56, 66, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99,
+        99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99,
+    ],
+];
+// percent scale of the standard tables for each quality level, jpeg quality 1, 14, 29, 43, 50, 71, 86, 100
+const QUALITY_SCALE: [u32; 8] = [5000, 357, 172, 116, 100, 58, 28, 0];
+
+// standard huffman tables, code counts by length then symbols
+const DC_LUMINANCE_BITS: [u8; 16] = [0, 1, 5, 1, 1, 1, 1, 1, 1, 0, 0, 0, 0, 0, 0, 0];
+const DC_CHROMINANCE_BITS: [u8; 16] = [0, 3, 1, 1, 1, 1, 1, 1, 1, 1, 1, 0, 0, 0, 0, 0];
+const DC_VALUES: [u8; 12] = [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11];
+const AC_LUMINANCE_BITS: [u8; 16] = [0, 2, 1, 3, 3, 2, 4, 3, 5, 5, 4, 4, 0, 0, 1, 0x7D];
+const AC_LUMINANCE_VALUES: [u8; 162] = [
+    0x01, 0x02, 0x03, 0x00, 0x04, 0x11, 0x05, 0x12, 0x21, 0x31, 0x41, 0x06, 0x13, 0x51, 0x61, 0x07, 0x22, 0x71, 0x14, 0x32, 0x81, 0x91,
+    0xA1, 0x08, 0x23, 0x42, 0xB1, 0xC1, 0x15, 0x52, 0xD1, 0xF0, 0x24, 0x33, 0x62, 0x72, 0x82, 0x09, 0x0A, 0x16, 0x17, 0x18, 0x19, 0x1A,
+    0x25, 0x26, 0x27, 0x28, 0x29, 0x2A, 0x34, 0x35, 0x36, 0x37, 0x38, 0x39, 0x3A, 0x43, 0x44, 0x45, 0x46, 0x47, 0x48, 0x49, 0x4A, 0x53,
+    0x54, 0x55, 0x56, 0x57, 0x58, 0x59, 0x5A, 0x63, 0x64, 0x65, 0x66, 0x67, 0x68, 0x69, 0x6A, 0x73, 0x74, 0x75, 0x76, 0x77, 0x78, 0x79,
+    0x7A, 0x83, 0x84, 0x85, 0x86, 0x87, 0x88, 0x89, 0x8A, 0x92, 0x93, 0x94, 0x95, 0x96, 0x97, 0x98, 0x99, 0x9A, 0xA2, 0xA3, 0xA4, 0xA5,
+    0xA6, 0xA7, 0xA8, 0xA9, 0xAA, 0xB2, 0xB3, 0xB4, 0xB5, 0xB6, 0xB7, 0xB8, 0xB9, 0xBA, 0xC2, 0xC3, 0xC4, 0xC5, 0xC6, 0xC7, 0xC8, 0xC9,
+    0xCA, 0xD2, 0xD3, 0xD4, 0xD5, 0xD6, 0xD7, 0xD8, 0xD9, 0xDA, 0xE1, 0xE2, 0xE3, 0xE4, 0xE5, 0xE6, 0xE7, 0xE8, 0xE9, 0xEA, 0xF1, 0xF2,
+    0xF3, 0xF4, 0xF5, 0xF6, 0xF7, 0xF8, 0xF9, 0xFA,
+];
+const AC_CHROMINANCE_BITS: [u8; 16] = [0, 2, 1, 2, 4, 4, 3, 4, 7, 5, 4, 4, 0, 1, 2, 0x77];
+const AC_CHROMINANCE_VALUES: [u8; 162] = [
+    0x00, 0x01, 0x02, 0x03, 0x11, 0x04, 0x05, 0x21, 0x31, 0x06, 0x12, 0x41, 0x51, 0x07, 0x61, 0x71, 0x13, 0x22, 0x32, 0x81, 0x08, 0x14,
+    0x42, 0x91, 0xA1, 0xB1, 0xC1, 0x09, 0x23, 0x33, 0x52, 0xF0, 0x15, 0x62, 0x72, 0xD1, 0x0A, 0x16, 0x24, 0x34, 0xE1, 0x25, 0xF1, 0x17,
+    0x18, 0x19, 0x1A, 0x26, 0x27, 0x28, 0x29, 0x2A, 0x35, 0x36, 0x37, 0x38, 0x39, 0x3A, 0x43, 0x44, 0x45, 0x46, 0x47, 0x48, 0x49, 0x4A,
+    0x53, 0x54, 0x55, 0x56, 0x57, 0x58, 0x59, 0x5A, 0x63, 0x64, 0x65, 0x66, 0x67, 0x68, 0x69, 0x6A, 0x73, 0x74, 0x75, 0x76, 0x77, 0x78,
+    0x79, 0x7A, 0x82, 0x83, 0x84, 0x85, 0x86, 0x87, 0x88, 0x89, 0x8A, 0x92, 0x93, 0x94, 0x95, 0x96, 0x97, 0x98, 0x99, 0x9A, 0xA2, 0xA3,
+    0xA4, 0xA5, 0xA6, 0xA7, 0xA8, 0xA9, 0xAA, 0xB2, 0xB3, 0xB4, 0xB5, 0xB6, 0xB7, 0xB8, 0xB9, 0xBA, 0xC2, 0xC3, 0xC4, 0xC5, 0xC6, 0xC7,
+    0xC8, 0xC9, 0xCA, 0xD2, 0xD3, 0xD4, 0xD5, 0xD6, 0xD7, 0xD8, 0xD9, 0xDA, 0xE2, 0xE3, 0xE4, 0xE5, 0xE6, 0xE7, 0xE8, 0xE9, 0xEA, 0xF2,
+    0xF3, 0xF4, 0xF5, 0xF6, 0xF7, 0xF8, 0xF9, 0xFA,
+];
+
+// dc and ac codes, luminance then chrominance
+static DC_CODES: [HuffmanCodes; 2] = [HuffmanCodes::new(&DC_LUMINANCE_BITS, &DC_VALUES), HuffmanCodes::new(&DC_CHROMINANCE_BITS, &DC_VALUES)];
+static AC_CODES: [HuffmanCodes; 2] =
+    [HuffmanCodes::new(&AC_LUMINANCE_BITS, &AC_LUMINANCE_VALUES), HuffmanCodes::new(&AC_CHROMINANCE_BITS, &AC_CHROMINANCE_VALUES)];
+
+// coefficients are clamped so a dc difference fits category 11 and an ac value category 10
+const COEFFICIENT_LIMIT: i32 = 1023;
+
+/// Why a jpeg can't be sent as ssdv
+#[derive(Copy, Clone, PartialEq, Eq, Debug, defmt::Format)]
+pub enum SsdvError {
+    /// not a jpeg, or a marker segment or the scan runs past the end
+    Malformed,
+    /// not baseline 8 bit huffman coded YCbCr with chroma at one block per MCU
+    Unsupported,
+    /// width or height not a multiple of 16 px, or over 4080 px
+    Size,
+    /// the scan has a code its huffman table doesn't
+    BadCode,
+}
+
+// code and length of every symbol of one standard table, built at compile time
+struct HuffmanCodes {
+    code: [u16; 256],
+    size: [u8; 256],
+}
+
+impl HuffmanCodes {
+    const fn new(bits: &[u8; 16], values: &[u8]) -> Self {
+        let mut codes = Self { code: [0; 256], size: [0; 256] };
+        let mut code = 0u32;
+        let mut k = 0;
+        let mut len = 0;
+        while len < 16 {
+            let mut i = 0;
+            while i < bits[len] {
+                codes.code[values[k] as usize] = code as u16;
+                codes.size[values[k] as usize] = len as u8 + 1;
+                code += 1;
+                k += 1;
+                i += 1;
+            }
+            code <<= 1;
+            len += 1;
+        }
+        codes
+    }
+}
+
+// one huffman table of the source jpeg, its symbols are read from the jpeg where the table was
+#[derive(Copy, Clone)]
+struct HuffmanTable {
+    values: usize,
+    // by code length, largest code (-1 for none), first code, and index of the first code's symbol
+    max_code: [i32; 17],
+    first_code: [u16; 17],
+    first_value: [u16; 17],
+}
+
+impl HuffmanTable {
+    const EMPTY: Self = Self { values: 0, max_code: [-1; 17], first_code: [0; 17], first_value: [0; 17] };
+
+    fn new(bits: &[u8], values: usize) -> Self {
+        let mut table = Self { values, ..Self::EMPTY };
+        let mut code = 0u32;
+        let mut index = 0u16;
+        for len in 1..=16 {
+            let count = bits[len - 1] as u16;
+            if count > 0 {
+                table.first_code[len] = code as u16;
+                table.first_value[len] = index;
+                table.max_code[len] = (code + count as u32 - 1) as i32;
+            }
+            code = (code + count as u32) << 1;
+            index += count;
+        }
+        table
+    }
+}
+
+// where a component's tables are, by index
+#[derive(Copy, Clone, Default)]
+struct Component {
+    quantisation: usize,
+    dc: usize,
+    ac: usize,
+}
+
+// bits of the source scan, undoing the 0xff 0x00 stuffing
+#[derive(Copy, Clone, Default)]
+struct Reader {
+    at: usize,
+    byte: u8,
+    count: u8,
+}
+
+impl Reader {
+    fn bit(&mut self, jpeg: &[u8]) -> Result<u16, SsdvError> {
+        if self.count == 0 {
+            let byte = *jpeg.get(self.at).ok_or(SsdvError::Malformed)?;
+            self.byte = byte;
+            if byte == 0xFF {
+                match jpeg.get(self.at + 1) {
+                    Some(0) => self.at += 2,
+                    // a marker, the scan ended early, the rest reads as zeros
+                    Some(_) => self.byte = 0,
+                    None => return Err(SsdvError::Malformed),
+                }
+            } else {
+                self.at += 1;
+            }
+            self.count = 8;
+        }
+        self.count -= 1;
+        Ok((self.byte >> self.count) as u16 & 1)
+    }
+
+    fn bits(&mut self, jpeg: &[u8], count: u8) -> Result<u16, SsdvError> {
+        let mut value = 0;
+        for _ in 0..count {
+            value = (value << 1) | self.bit(jpeg)?;
+        }
+        Ok(value)
+    }
+
+    fn decode(&mut self, jpeg: &[u8], table: &HuffmanTable) -> Result<u8, SsdvError> {
+        let mut code = 0i32;
+        for len in 1..=16 {
+            code = (code << 1) | self.bit(jpeg)? as i32;
+            if code <= table.max_code[len] {
+                let index = table.first_value[len] as usize + (code - table.first_code[len] as i32) as usize;
+                return jpeg.get(table.values + index).copied().ok_or(SsdvError::Malformed);
+            }
+        }
+        Err(SsdvError::BadCode)
+    }
+
+    // a category's value bits, sign extended
+    fn value(&mut self, jpeg: &[u8], category: u8) -> Result<i32, SsdvError> {
+        if category == 0 {
+            return Ok(0);
+        }
+        let value = self.bits(jpeg, category)? as i32;
+        Ok(if value < 1 << (category - 1) { value - (1 << category) + 1 } else { value })
+    }
+
+    // skip the restart marker between restart intervals, a missing one is let go
+    fn restart(&mut self, jpeg: &[u8]) {
+        self.count = 0;
+        if let [0xFF, marker, ..] = jpeg[self.at.min(jpeg.len())..]
+            && (RST0..=RST7).contains(&marker)
+        {
+            self.at += 2;
+        }
+    }
+}
+
+// bits going into packets, whatever doesn't fit one waits for the next
+#[derive(Copy, Clone, Default)]
+struct Writer {
+    bits: u64,
+    count: u8,
+}
+
+impl Writer {
+    fn put(&mut self, bits: u16, count: u8) {
+        self.bits = (self.bits << count) | (bits as u64 & ((1 << count) - 1));
+        self.count += count;
+    }
+
+    // pad to a byte with ones, as jpeg does
+    fn align(&mut self) {
+        let pad = (8 - self.count % 8) % 8;
+        self.put(0xFF, pad);
+    }
+
+    // whole bytes into the payload from len while there's room, returns the new len
+    fn flush(&mut self, payload: &mut [u8], mut len: usize) -> usize {
+        while self.count >= 8 && len < payload.len() {
+            self.count -= 8;
+            payload[len] = (self.bits >> self.count) as u8;
+            len += 1;
+        }
+        len
+    }
+
+    // a category and its value bits, negative values as value - 1
+    fn value(&mut self, codes: &HuffmanCodes, symbol: u8, value: i32) {
+        let category = category(value);
+        let symbol = symbol | category;
+        self.put(codes.code[symbol as usize], codes.size[symbol as usize]);
+        if category > 0 {
+            let bits = if value < 0 { value - 1 } else { value };
+            self.put(bits as u16, category);
+        }
+    }
+}
+
+/// Cuts one jpeg into ssdv packets, one per next_packet call, the jpeg is passed to every call so it can stay where
+/// it was captured
+pub struct Encoder {
+    callsign: u32,
+    image_id: u8,
+    quality: u8,
+    width: u16,
+    height: u16,
+    mcu_mode: u8,
+    /// luminance blocks per MCU, one each of Cb and Cr follow
+    luminance_blocks: u8,
+    mcu_count: u16,
+    restart_interval: u16,
+    components: [Component; 3],
+    /// offset and 16 bit flag of each source quantisation table
+    quantisation: [Option<(usize, bool)>; 4],
+    dc_tables: [HuffmanTable; 2],
+    ac_tables: [HuffmanTable; 2],
+
+    reader: Reader,
+    writer: Writer,
+    packet_id: u16,
+    mcu: u16,
+    /// block of the MCU, luminance first
+    block: u8,
+    /// dc predictions of the source and of the packets, by component
+    source_dc: [i32; 3],
+    packet_dc: [i32; 3],
+    /// block being written, its coefficients in zigzag order and the next one to write, None between blocks
+    coefficients: [i16; 64],
+    next: Option<usize>,
+    done: bool,
+}
+
+impl Encoder {
+    /// read the jpeg's headers, callsign is up to 6 letters and digits, quality 0 to 7
+    pub fn new(jpeg: &[u8], callsign: &str, image_id: u8, quality: u8) -> Result<Self, SsdvError> {
+        let mut encoder = Self {
+            callsign: encode_callsign(callsign),
+            image_id,
+            quality: quality.min(7),
+            width: 0,
+            height: 0,
+            mcu_mode: 0,
+            luminance_blocks: 0,
+            mcu_count: 0,
+            restart_interval: 0,
+            components: [Component::default(); 3],
+            quantisation: [None; 4],
+            dc_tables: [HuffmanTable::EMPTY; 2],
+            ac_tables: [HuffmanTable::EMPTY; 2],
+            reader: Reader::default(),
+            writer: Writer::default(),
+            packet_id: 0,
+            mcu: 0,
+            block: 0,
+            source_dc: [0; 3],
+            packet_dc: [0; 3],
+            coefficients: [0; 64],
+            next: None,
+            done: false,
+        };
+        encoder.read_headers(jpeg)?;
+        Ok(encoder)
+    }
+
+    /// packets written so far
+    pub fn packets(&self) -> u16 {
+        self.packet_id
+    }
+
+    /// write the next packet, false with nothing written once every packet of the image has been
+    /// the jpeg must be the one the encoder was made with
+    pub fn next_packet(&mut self, jpeg: &[u8], packet: &mut [u8; PACKET_LEN]) -> Result<bool, SsdvError> {
+        if self.done {
+            return Ok(false);
+        }
+
+        let payload = &mut packet[HEADER_LEN..HEADER_LEN + PAYLOAD_LEN];
+        let mut len = 0;
+        let mut first_mcu = None;
+        let mut end = false;
+        loop {
+            len = self.writer.flush(payload, len);
+            if self.next.is_none() && self.mcu == self.mcu_count {
+                self.writer.align();
+                len = self.writer.flush(payload, len);
+                end = self.writer.count == 0;
+                break;
+            }
+            if len == PAYLOAD_LEN {
+                break;
+            }
+
+            if self.next.is_some() {
+                self.write_symbol();
+                continue;
+            }
+
+            if self.block == 0 {
+                if self.restart_interval > 0 && self.mcu > 0 && self.mcu.is_multiple_of(self.restart_interval) {
+                    self.reader.restart(jpeg);
+                    self.source_dc = [0; 3];
+                }
+                // the first MCU in a packet starts on a byte, with its dc values whole
+                if first_mcu.is_none() {
+                    self.writer.align();
+                    len = self.writer.flush(payload, len);
+                    if len == PAYLOAD_LEN {
+                        break;
+                    }
+                    first_mcu = Some((len as u8, self.mcu));
+                    self.packet_dc = [0; 3];
+                }
+            }
+            self.read_block(jpeg)?;
+        }
+        payload[len..].fill(0xFF);
+
+        let (offset, index) = first_mcu.unwrap_or(NO_MCU);
+        packet[0] = SYNC;
+        packet[1] = TYPE_NOFEC;
+        packet[2..6].copy_from_slice(&self.callsign.to_be_bytes());
+        packet[6] = self.image_id;
+        packet[7..9].copy_from_slice(&self.packet_id.to_be_bytes());
+        packet[9] = (self.width / SIZE_UNIT) as u8;
+        packet[10] = (self.height / SIZE_UNIT) as u8;
+        // quality is sent relative to 4 so a zero means the standard tables
+        packet[11] = ((self.quality ^ 4) << 3) | ((end as u8) << 2) | self.mcu_mode;
+        packet[12] = offset;
+        packet[13..15].copy_from_slice(&index.to_be_bytes());
+        let crc = crc32(&packet[1..HEADER_LEN + PAYLOAD_LEN]);
+        packet[HEADER_LEN + PAYLOAD_LEN..].copy_from_slice(&crc.to_be_bytes());
+
+        self.packet_id = self.packet_id.wrapping_add(1);
+        self.done = end;
+        Ok(true)
+    }
+
+    fn read_headers(&mut self, jpeg: &[u8]) -> Result<(), SsdvError> {
+        if jpeg.get(..2) != Some(&[0xFF, SOI]) {
+            return Err(SsdvError::Malformed);
+        }
+        let mut at = 2;
+        loop {
+            // markers may be preceded by fill bytes
+            while jpeg.get(at + 1) == Some(&0xFF) {
+                at += 1;
+            }
+            let (Some(0xFF), Some(&marker)) = (jpeg.get(at), jpeg.get(at + 1)) else {
+                return Err(SsdvError::Malformed);
+            };
+            let len = u16::from_be_bytes([*jpeg.get(at + 2).ok_or(SsdvError::Malformed)?, *jpeg.get(at + 3).ok_or(SsdvError::Malformed)?]) as usize;
+            let segment = jpeg.get(at + 4..at + 2 + len).ok_or(SsdvError::Malformed)?;
+            let start = at + 4;
+            match marker {
+                SOF0 | SOF1 => self.read_frame(segment)?,
+                // progressive, lossless, and arithmetic coded frames
+                0xC2 | 0xC3 | 0xC5..=0xC7 | 0xC9..=0xCB | 0xCD..=0xCF => return Err(SsdvError::Unsupported),
+                DHT => self.read_huffman_tables(segment, start)?,
+                DQT => self.read_quantisation_tables(segment, start)?,
+                DRI => {
+                    let [high, low, ..] = *segment else {
+                        return Err(SsdvError::Malformed);
+                    };
+                    self.restart_interval = u16::from_be_bytes([high, low]);
+                }
+                SOS => {
+                    // no frame header before the scan
+                    if self.mcu_count == 0 {
+                        return Err(SsdvError::Malformed);
+                    }
+                    self.read_scan(segment)?;
+                    self.reader.at = at + 2 + len;
+                    return Ok(());
+                }
+                EOI => return Err(SsdvError::Malformed),
+                _ => {}
+            }
+            at += 2 + len;
+        }
+    }
+
+    fn read_frame(&mut self, segment: &[u8]) -> Result<(), SsdvError> {
+        let [precision, height_high, height_low, width_high, width_low, 3, ref components @ ..] = *segment else {
+            return Err(SsdvError::Unsupported);
+        };
+        if precision != 8 || components.len() < 9 {
+            return Err(SsdvError::Unsupported);
+        }
+        self.height = u16::from_be_bytes([height_high, height_low]);
+        self.width = u16::from_be_bytes([width_high, width_low]);
+        // whole 16 px units, up to 255 of them
+        let fits = |size: u16| size > 0 && size.is_multiple_of(SIZE_UNIT) && size / SIZE_UNIT <= 255;
+        if !fits(self.width) || !fits(self.height) {
+            return Err(SsdvError::Size);
+        }
+
+        // luminance sampling sets the MCU, chroma must be one block per MCU
+        let (mcu_mode, horizontal, vertical) = match components[1] {
+            0x22 => (0, 2, 2),
+            0x12 => (1, 1, 2),
+            0x21 => (2, 2, 1),
+            0x11 => (3, 1, 1),
+            _ => return Err(SsdvError::Unsupported),
+        };
+        if components[4] != 0x11 || components[7] != 0x11 {
+            return Err(SsdvError::Unsupported);
+        }
+        for (component, spec) in self.components.iter_mut().zip(components.chunks_exact(3)) {
+            component.quantisation = (spec[2] & 0x03) as usize;
+        }
+        self.mcu_mode = mcu_mode;
+        self.luminance_blocks = horizontal * vertical;
+        self.mcu_count = (self.width / (8 * horizontal as u16)) * (self.height / (8 * vertical as u16));
+        Ok(())
+    }
+
+    fn read_huffman_tables(&mut self, segment: &[u8], start: usize) -> Result<(), SsdvError> {
+        let mut at = 0;
+        while at < segment.len() {
+            let class_id = segment[at];
+            let bits = segment.get(at + 1..at + 17).ok_or(SsdvError::Malformed)?;
+            let count: usize = bits.iter().map(|&count| count as usize).sum();
+            if at + 17 + count > segment.len() {
+                return Err(SsdvError::Malformed);
+            }
+            let table = HuffmanTable::new(bits, start + at + 17);
+            match (class_id >> 4, class_id & 0x0F) {
+                (0, id @ 0..=1) => self.dc_tables[id as usize] = table,
+                (1, id @ 0..=1) => self.ac_tables[id as usize] = table,
+                _ => return Err(SsdvError::Unsupported),
+            }
+            at += 17 + count;
+        }
+        Ok(())
+    }
+
+    fn read_quantisation_tables(&mut self, segment: &[u8], start: usize) -> Result<(), SsdvError> {
+        let mut at = 0;
+        while at < segment.len() {
+            let wide = segment[at] >> 4 != 0;
+            let id = (segment[at] & 0x0F) as usize;
+            let len = if wide { 128 } else { 64 };
+            if id > 3 || at + 1 + len > segment.len() {
+                return Err(SsdvError::Malformed);
+            }
+            self.quantisation[id] = Some((start + at + 1, wide));
+            at += 1 + len;
+        }
+        Ok(())
+    }
+
+    fn read_scan(&mut self, segment: &[u8]) -> Result<(), SsdvError> {
+        let [3, ref components @ ..] = *segment else {
+            return Err(SsdvError::Unsupported);
+        };
+        let tables = components.get(..6).ok_or(SsdvError::Malformed)?;
+        for (component, spec) in self.components.iter_mut().zip(tables.chunks_exact(2)) {
+            component.dc = (spec[1] >> 4) as usize;
+            component.ac = (spec[1] & 0x0F) as usize;
+            if component.dc > 1 || component.ac > 1 || self.quantisation[component.quantisation].is_none() {
+                return Err(SsdvError::Unsupported);
+            }
+        }
+        Ok(())
+    }
+
+    // luminance blocks then Cb then Cr
+    fn component(&self) -> usize {
+        (self.block + 1).saturating_sub(self.luminance_blocks) as usize
+    }
+
+    // a source quantisation step, k in zigzag order
+    fn source_step(&self, jpeg: &[u8], component: usize, k: usize) -> i32 {
+        let step = match self.quantisation[self.components[component].quantisation] {
+            Some((at, true)) => jpeg.get(at + 2 * k..at + 2 * k + 2).map(|step| u16::from_be_bytes([step[0], step[1]])),
+            Some((at, false)) => jpeg.get(at + k).map(|&step| step as u16),
+            None => None,
+        };
+        step.unwrap_or(1) as i32
+    }
+
+    // the packets' quantisation step, luminance or chrominance table at the quality level
+    fn packet_step(&self, component: usize, k: usize) -> i32 {
+        let table = &STANDARD_QUANTISATION[(component > 0) as usize];
+        ((table[k] as u32 * QUALITY_SCALE[self.quality as usize] + 50) / 100).clamp(1, 255) as i32
+    }
+
+    // decode the next block of the source and requantise it for the packets
+    fn read_block(&mut self, jpeg: &[u8]) -> Result<(), SsdvError> {
+        let component = self.component();
+        let Component { dc, ac, .. } = self.components[component];
+        let (dc_table, ac_table) = (self.dc_tables[dc], self.ac_tables[ac]);
+        self.coefficients = [0; 64];
+
+        let category = self.reader.decode(jpeg, &dc_table)?;
+        self.source_dc[component] += self.reader.value(jpeg, category)?;
+        let value = requantise(self.source_dc[component] * self.source_step(jpeg, component, 0), self.packet_step(component, 0));
+        self.coefficients[0] = (value - self.packet_dc[component]) as i16;
+        self.packet_dc[component] = value;
+
+        let mut k = 1;
+        while k < 64 {
+            let symbol = self.reader.decode(jpeg, &ac_table)?;
+            let (run, category) = ((symbol >> 4) as usize, symbol & 0x0F);
+            if category == 0 {
+                // end of block, or a run of 16 zeros
+                if run != 15 {
+                    break;
+                }
+                k += 16;
+                continue;
+            }
+            k += run;
+            if k > 63 {
+                return Err(SsdvError::BadCode);
+            }
+            let value = self.reader.value(jpeg, category)?;
+            self.coefficients[k] = requantise(value * self.source_step(jpeg, component, k), self.packet_step(component, k)) as i16;
+            k += 1;
+        }
+        self.next = Some(0);
+        Ok(())
+    }
+
+    // one huffman symbol of the block being written, the block and MCU move on after the last
+    fn write_symbol(&mut self) {
+        let Some(k) = self.next else {
+            return;
+        };
+        let table = (self.component() > 0) as usize;
+        if k == 0 {
+            self.writer.value(&DC_CODES[table], 0, self.coefficients[0] as i32);
+            self.next = Some(1);
+            return;
+        }
+
+        match self.coefficients[k..].iter().position(|&coefficient| coefficient != 0) {
+            // runs over 15 zeros go as 16 zero runs first
+            Some(run) if run > 15 => {
+                self.writer.value(&AC_CODES[table], 0xF0, 0);
+                self.next = Some(k + 16);
+            }
+            Some(run) => {
+                self.writer.value(&AC_CODES[table], (run as u8) << 4, self.coefficients[k + run] as i32);
+                self.next = Some(k + run + 1);
+            }
+            None => self.next = Some(64),
+        }
+        // end of block code unless the last coefficient ended it
+        if self.next == Some(64) {
+            if self.coefficients[63] == 0 {
+                self.writer.value(&AC_CODES[table], 0x00, 0);
+            }
+            self.next = None;
+            self.block += 1;
+            if self.block == self.luminance_blocks + 2 {
+                self.block = 0;
+                self.mcu += 1;
+            }
+        }
+    }
+}
+
+// rounded to the nearest step, clamped so every value has a baseline code
+fn requantise(value: i32, step: i32) -> i32 {
+    let rounded = if value < 0 { -((-value + step / 2) / step) } else { (value + step / 2) / step };
+    rounded.clamp(-COEFFICIENT_LIMIT, COEFFICIENT_LIMIT)
+}
+
+// bits needed for a value's magnitude
+fn category(value: i32) -> u8 {
+    (32 - value.unsigned_abs().leading_zeros()) as u8
+}
+
+// base 40, up to 6 characters, the last first
+fn encode_callsign(callsign: &str) -> u32 {
+    callsign.bytes().take(6).rev().fold(0, |code, byte| {
+        let digit = match byte {
+            b'0'..=b'9' => byte - b'0' + 1,
+            b'A'..=b'Z' => byte - b'A' + 14,
+            b'a'..=b'z' => byte - b'a' + 14,
+            _ => 0,
+        };
+        code * 40 + digit as u32
+    })
+}
+
+/// A received packet with its sync byte back, as the ssdv tool reads them
+#[cfg(feature = "std")]
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub struct ReceivedPacket(pub [u8; PACKET_LEN]);
+
+#[cfg(feature = "std")]
+impl ReceivedPacket {
+    /// a packet as the downlink sends it, without the sync byte, None if it is the wrong length
+    pub fn from_frame(frame: &[u8]) -> Option<Self> {
+        let mut packet = [SYNC; PACKET_LEN];
+        packet.get_mut(1..)?.copy_from_slice(frame.get(..PACKET_LEN - 1).filter(|_| frame.len() == PACKET_LEN - 1)?);
+        Some(Self(packet))
+    }
+
+    pub fn image_id(&self) -> u8 {
+        self.0[6]
+    }
+
+    pub fn packet_id(&self) -> u16 {
+        u16::from_be_bytes([self.0[7], self.0[8]])
+    }
+
+    /// the packet with the end of the image
+    pub fn last(&self) -> bool {
+        self.0[11] & 0x04 != 0
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::fmt::Debug for ReceivedPacket {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ReceivedPacket")
+            .field("image_id", &self.image_id())
+            .field("packet_id", &self.packet_id())
+            .field("last", &self.last())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a grey baseline jpeg 16 px high and an MCU per 16 px across, 2x2 luminance sampling, quantisation steps of 16
+    // and tables with only the codes it uses, a dc of 8 steps on the first block and zeros after
+    fn jpeg(mcus: u16) -> Vec<u8> {
+        let width = (16 * mcus).to_be_bytes();
+        let mut jpeg = vec![0xFF, SOI, 0xFF, DQT, 0x00, 0x43, 0x00];
+        jpeg.extend([16; 64]);
+        jpeg.extend([0xFF, SOF0, 0x00, 0x11, 8, 0x00, 0x10, width[0], width[1], 3, 1, 0x22, 0, 2, 0x11, 0, 3, 0x11, 0]);
+        // dc categories 0 (0) and 4 (10), ac end of block (0)
+        jpeg.extend([0xFF, DHT, 0x00, 0x27, 0x00, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x00, 0x04]);
+        jpeg.extend([0x10, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x00]);
+        jpeg.extend([0xFF, SOS, 0x00, 0x0C, 3, 1, 0x00, 2, 0x00, 3, 0x00, 0, 63, 0]);
+        // 1010000 for the first block, then 00 for each of the rest, padded with ones
+        let bits = 5 + 12 * mcus as usize;
+        let mut scan = vec![0; bits.div_ceil(8)];
+        scan[0] = 0xA0;
+        *scan.last_mut().unwrap() |= (1 << ((8 - bits % 8) % 8)) - 1;
+        jpeg.extend(scan);
+        jpeg.extend([0xFF, EOI]);
+        jpeg
+    }
+
+    fn packets(jpeg: &[u8]) -> Vec<[u8; PACKET_LEN]> {
+        let mut encoder = Encoder::new(jpeg, "HAPSIS", 7, DEFAULT_QUALITY).unwrap();
+        let mut packets = Vec::new();
+        let mut packet = [0; PACKET_LEN];
+        while encoder.next_packet(jpeg, &mut packet).unwrap() {
+            packets.push(packet);
+        }
+        assert_eq!(encoder.packets() as usize, packets.len());
+        packets
+    }
+
+    #[test]
+    fn small_image_is_one_packet() {
+        let packets = packets(&jpeg(2));
+        assert_eq!(packets.len(), 1);
+        let packet = packets[0];
+
+        // HAPSIS in base 40 is 0xC6CB5785, 2 by 1 units of 16 px, standard tables, the end of the image, 2x2 MCUs,
+        // the first MCU at the start of the payload
+        assert_eq!(packet[..HEADER_LEN], [0x55, 0x67, 0xC6, 0xCB, 0x57, 0x85, 7, 0, 0, 2, 1, 0x04, 0, 0, 0]);
+        // in the standard tables, first MCU: dc 101 1000, end of block 1010, then 00 1010 for the other luminance
+        // blocks and 00 00 for each chroma block, the second MCU the same but its first dc is predicted, padded to a
+        // byte with ones and the payload filled out
+        let payload = [0xB1, 0x45, 0x14, 0x50, 0x01, 0x45, 0x14, 0x50, 0x07];
+        assert_eq!(packet[HEADER_LEN..HEADER_LEN + payload.len()], payload);
+        assert!(packet[HEADER_LEN + payload.len()..HEADER_LEN + PAYLOAD_LEN].iter().all(|&byte| byte == 0xFF));
+        // crc32 of everything after the sync byte, no reed-solomon parity in the no fec type
+        assert_eq!(packet[HEADER_LEN + PAYLOAD_LEN..], 0x81B8_BE54u32.to_be_bytes());
+    }
+
+    #[test]
+    fn mcus_restart_byte_aligned_in_the_next_packet() {
+        // 37 bits for the first MCU and 32 for the rest, MCU 27 starts 3 bits before the end of the first packet
+        let packets = packets(&jpeg(30));
+        assert_eq!(packets.len(), 2);
+        for (id, packet) in packets.iter().enumerate() {
+            assert_eq!(packet[7..9], (id as u16).to_be_bytes());
+            assert_eq!(crc32(&packet[1..HEADER_LEN + PAYLOAD_LEN]).to_be_bytes(), packet[HEADER_LEN + PAYLOAD_LEN..]);
+        }
+        assert_eq!(packets[0][11..HEADER_LEN], [0x00, 0, 0, 0]);
+        // the rest of MCU 27 is 29 bits, so MCU 28 starts in the fifth byte with its dc whole
+        assert_eq!(packets[1][11..HEADER_LEN], [0x04, 4, 0, 28]);
+        assert_eq!(packets[1][HEADER_LEN + 3..HEADER_LEN + 6], [0x07, 0xB1, 0x45]);
+    }
+
+    #[test]
+    fn unsupported_images_are_refused() {
+        assert_eq!(Encoder::new(&[0xFF, SOI, 0xFF, EOI], "HAPSIS", 0, DEFAULT_QUALITY).err(), Some(SsdvError::Malformed));
+        let mut jpeg = jpeg(1);
+        // 20 px wide, the low byte of the frame header's width
+        jpeg[79] = 20;
+        assert_eq!(Encoder::new(&jpeg, "HAPSIS", 0, DEFAULT_QUALITY).err(), Some(SsdvError::Size));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn downlink_frame_gets_its_sync_byte_back() {
+        let packet = packets(&jpeg(2))[0];
+        let received = ReceivedPacket::from_frame(&packet[1..]).unwrap();
+        assert_eq!(received.0, packet);
+        assert_eq!((received.image_id(), received.packet_id(), received.last()), (7, 0, true));
+        assert_eq!(ReceivedPacket::from_frame(&packet), None);
+        assert_eq!(ReceivedPacket::from_frame(&packet[2..]), None);
+    }
+}
@@ -0,0 +1,436 @@
+// block storage the log is written to, an sd card over spi or sdmmc or the external nor flash depending on the board
+// the log task only sees LogStorage, so it runs the same against every backend and against RamStorage on the host
+// what a stream has written this boot can be read back, for sending logged records down on request
+// each boot gets its own log and image region on the device, so a reset mid flight doesn't overwrite what was logged
+// before it, and each log stream its own part of the log region
+// nor flash has to be erased before it is written and the writer erases each unit as it gets to it
+
+use core::future::Future;
+
+use crate::blockqueue::{BLOCK_SIZE, Consumer};
+
+/// Block device the log is written to, addressed in BLOCK_SIZE blocks
+pub trait LogStorage {
+    type Error: defmt::Format;
+
+    /// blocks on the device, known once it has been brought up
+    fn capacity(&self) -> u32;
+
+    /// blocks one erase clears, a block has to be erased before it is written again, 0 for devices that overwrite in
+    /// place
+    fn erase_size(&self) -> u32;
+
+    /// write one block at a block address
+    fn write_block(&mut self, address: u32, block: &[u8]) -> impl Future<Output = Result<(), Self::Error>>;
+
+    /// read one block at a block address back into block
+    fn read_block(&mut self, address: u32, block: &mut [u8]) -> impl Future<Output = Result<(), Self::Error>>;
+
+    /// clear count blocks from first, whole erase units on devices that have them
+    fn erase(&mut self, first: u32, count: u32) -> impl Future<Output = Result<(), Self::Error>>;
+
+    /// wait until everything written so far has reached the medium
+    fn sync(&mut self) -> impl Future<Output = Result<(), Self::Error>>;
+}
+
+/// Run of blocks on a device
+#[derive(Copy, Clone, PartialEq, Eq, Debug, defmt::Format)]
+pub struct Region {
+    pub first: u32,
+    pub blocks: u32,
+}
+
+impl Region {
+    /// block address of an offset into the region, None past its end
+    pub fn address(&self, offset: u32) -> Option<u32> {
+        (offset < self.blocks).then(|| self.first + offset)
+    }
+
+    /// part index of the region split by shares, in order, parts are whole erase units when the region is
+    pub fn part(&self, shares: &[u32], index: usize, erase_size: u32) -> Region {
+        let unit = erase_size.max(1) as u64;
+        let total = shares.iter().sum::<u32>().max(1) as u64;
+        let boundary = |index: usize| {
+            let before = shares[..index].iter().sum::<u32>() as u64;
+            (self.blocks as u64 * before / total / unit * unit) as u32
+        };
+        let (start, end) = (boundary(index), boundary(index + 1));
+        Region { first: self.first + start, blocks: end - start }
+    }
+}
+
+/// Where one boot writes on a device
+/// the log takes the first half of the device and camera images the second, each half split into `boots` regions of
+/// at most `max_blocks` used in turn by boot count, every region is whole erase units
+#[derive(Copy, Clone, PartialEq, Eq, Debug, defmt::Format)]
+pub struct BootLayout {
+    pub log: Region,
+    pub images: Region,
+}
+
+impl BootLayout {
+    pub fn new(capacity: u32, erase_size: u32, boots: u32, max_blocks: u32, boot_count: u32) -> Self {
+        let unit = erase_size.max(1);
+        let blocks = (capacity / 2 / boots).min(max_blocks) / unit * unit;
+        let first = boot_count % boots * blocks;
+        Self {
+            log: Region { first, blocks },
+            images: Region { first: boots * blocks + first, blocks },
+        }
+    }
+}
+
+/// Writes STREAMS log streams and camera images into a boot's regions of a storage device
+pub struct LogWriter<S, const STREAMS: usize> {
+    storage: S,
+    layout: BootLayout,
+    // each stream's part of the log region
+    streams: [Region; STREAMS],
+    // log blocks written to each stream so far
+    written: [u32; STREAMS],
+}
+
+impl<S: LogStorage, const STREAMS: usize> LogWriter<S, STREAMS> {
+    /// the log region is split between the streams by shares
+    pub fn new(storage: S, layout: BootLayout, shares: [u32; STREAMS]) -> Self {
+        let erase_size = storage.erase_size();
+        let streams = core::array::from_fn(|index| layout.log.part(&shares, index, erase_size));
+        Self { storage, layout, streams, written: [0; STREAMS] }
+    }
+
+    pub fn storage(&self) -> &S {
+        &self.storage
+    }
+
+    pub fn layout(&self) -> BootLayout {
+        self.layout
+    }
+
+    /// a stream's part of the log region
+    pub fn stream(&self, stream: usize) -> Region {
+        self.streams[stream]
+    }
+
+    /// blocks the stream has written this boot
+    pub fn written(&self, stream: usize) -> u32 {
+        self.written[stream]
+    }
+
+    /// block address the stream's next block goes to
+    pub fn address(&self, stream: usize) -> u32 {
+        self.streams[stream].first + self.written[stream]
+    }
+
+    /// append a block to a stream, returns false with the stream's part full and the block not written
+    /// a block that fails to write isn't counted, the next one goes in its place
+    pub async fn append(&mut self, stream: usize, block: &[u8]) -> Result<bool, S::Error> {
+        let Some(address) = self.streams[stream].address(self.written[stream]) else {
+            return Ok(false);
+        };
+        self.erase_ahead(address).await?;
+        self.storage.write_block(address, block).await?;
+        self.written[stream] += 1;
+        Ok(true)
+    }
+
+    /// read back a block the stream has written, at its offset into the stream's part, returns false past what has
+    /// been written this boot
+    pub async fn read(&mut self, stream: usize, offset: u32, block: &mut [u8]) -> Result<bool, S::Error> {
+        if offset >= self.written[stream] {
+            return Ok(false);
+        }
+        self.storage.read_block(self.streams[stream].first + offset, block).await?;
+        Ok(true)
+    }
+
+    /// write a camera image block at its offset into the image region, returns false past the end of the region
+    /// image blocks come in order, the erase unit is cleared when its first block arrives
+    pub async fn write_image(&mut self, offset: u32, block: &[u8]) -> Result<bool, S::Error> {
+        let Some(address) = self.layout.images.address(offset) else {
+            return Ok(false);
+        };
+        self.erase_ahead(address).await?;
+        self.storage.write_block(address, block).await?;
+        Ok(true)
+    }
+
+    pub async fn sync(&mut self) -> Result<(), S::Error> {
+        self.storage.sync().await
+    }
+
+    /// write what the streams' queues hold, a block at a time from the first stream with one ready so the streams
+    /// before it never wait behind a run of blocks from one after it, returns the blocks taken off the queues
+    /// a block that can't be written is dropped, holding it would stall the stream behind it, failed gets its address
+    /// and the error, and a stream whose part is full drops its blocks without writing them
+    pub async fn drain<const N: usize>(&mut self, queues: &mut [Consumer<N>; STREAMS], mut failed: impl FnMut(u32, S::Error)) -> u32 {
+        let mut taken = 0;
+        while let Some((stream, block)) = queues.iter_mut().enumerate().find_map(|(stream, queue)| Some((stream, queue.read()?))) {
+            let address = self.address(stream);
+            if let Err(e) = self.append(stream, &block).await {
+                failed(address, e);
+            }
+            block.release();
+            taken += 1;
+        }
+        taken
+    }
+
+    // clear the erase unit a block starts, regions are whole units so the rest of it is still unwritten
+    async fn erase_ahead(&mut self, address: u32) -> Result<(), S::Error> {
+        let unit = self.storage.erase_size();
+        if unit > 0 && address.is_multiple_of(unit) {
+            self.storage.erase(address, unit).await?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug, defmt::Format)]
+pub enum RamError {
+    /// block address past the end
+    OutOfRange,
+    /// erase not on whole erase units
+    Unaligned,
+    /// block written again without an erase in between
+    NotErased,
+}
+
+/// Mock storage in ram, for running the log writer on the host
+/// with an erase size it holds to the nor flash rules, a block written twice without an erase fails rather than
+/// ending up with the two writes anded together as on the flash
+pub struct RamStorage<const BLOCKS: usize> {
+    blocks: [[u8; BLOCK_SIZE]; BLOCKS],
+    erased: [bool; BLOCKS],
+    erase_size: u32,
+    syncs: u32,
+}
+
+impl<const BLOCKS: usize> RamStorage<BLOCKS> {
+    pub fn new(erase_size: u32) -> Self {
+        Self {
+            blocks: [[0xFF; BLOCK_SIZE]; BLOCKS],
+            erased: [true; BLOCKS],
+            erase_size,
+            syncs: 0,
+        }
+    }
+
+    pub fn block(&self, address: u32) -> Option<&[u8; BLOCK_SIZE]> {
+        self.blocks.get(address as usize)
+    }
+
+    /// times sync was called
+    pub fn syncs(&self) -> u32 {
+        self.syncs
+    }
+}
+
+impl<const BLOCKS: usize> LogStorage for RamStorage<BLOCKS> {
+    type Error = RamError;
+
+    fn capacity(&self) -> u32 {
+        BLOCKS as u32
+    }
+
+    fn erase_size(&self) -> u32 {
+        self.erase_size
+    }
+
+    async fn write_block(&mut self, address: u32, block: &[u8]) -> Result<(), RamError> {
+        let index = address as usize;
+        if index >= BLOCKS {
+            return Err(RamError::OutOfRange);
+        }
+        if self.erase_size > 0 && !self.erased[index] {
+            return Err(RamError::NotErased);
+        }
+        self.blocks[index].copy_from_slice(block);
+        self.erased[index] = false;
+        Ok(())
+    }
+
+    async fn read_block(&mut self, address: u32, block: &mut [u8]) -> Result<(), RamError> {
+        block.copy_from_slice(self.blocks.get(address as usize).ok_or(RamError::OutOfRange)?);
+        Ok(())
+    }
+
+    async fn erase(&mut self, first: u32, count: u32) -> Result<(), RamError> {
+        if self.erase_size > 0 && (!first.is_multiple_of(self.erase_size) || !count.is_multiple_of(self.erase_size)) {
+            return Err(RamError::Unaligned);
+        }
+        let (first, end) = (first as usize, first as usize + count as usize);
+        if end > BLOCKS {
+            return Err(RamError::OutOfRange);
+        }
+        self.blocks[first..end].fill([0xFF; BLOCK_SIZE]);
+        self.erased[first..end].fill(true);
+        Ok(())
+    }
+
+    async fn sync(&mut self) -> Result<(), RamError> {
+        self.syncs += 1;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embassy_futures::block_on;
+
+    use super::*;
+    use crate::blockqueue::{BlockQueue, Producer};
+
+    const QUEUE: usize = 4 * BLOCK_SIZE;
+
+    fn queue() -> (Producer<QUEUE>, Consumer<QUEUE>) {
+        Box::leak(Box::new(BlockQueue::<QUEUE>::new())).split().unwrap()
+    }
+
+    // a whole block of one byte value, so where it landed shows which it was
+    fn push(producer: &mut Producer<QUEUE>, value: u8) {
+        let mut grant = producer.grant(BLOCK_SIZE).unwrap();
+        grant.fill(value);
+        grant.commit(BLOCK_SIZE);
+    }
+
+    fn writer<const BLOCKS: usize>(erase_size: u32, boot_count: u32) -> LogWriter<RamStorage<BLOCKS>, 2> {
+        let storage = RamStorage::<BLOCKS>::new(erase_size);
+        let layout = BootLayout::new(storage.capacity(), erase_size, 4, 1024, boot_count);
+        LogWriter::new(storage, layout, [1, 3])
+    }
+
+    #[test]
+    fn boot_regions_take_turns_and_never_overlap() {
+        let layouts: Vec<_> = (0..5).map(|boot| BootLayout::new(256, 8, 4, 1024, boot)).collect();
+        for (boot, layout) in layouts.iter().enumerate().take(4) {
+            assert_eq!(layout.log, Region { first: boot as u32 * 32, blocks: 32 });
+            assert_eq!(layout.images, Region { first: 128 + boot as u32 * 32, blocks: 32 });
+        }
+        assert_eq!(layouts[4], layouts[0]);
+    }
+
+    #[test]
+    fn regions_are_whole_erase_units() {
+        let layout = BootLayout::new(1000, 16, 3, 1024, 1);
+        assert_eq!(layout.log.first % 16, 0);
+        assert_eq!(layout.log.blocks % 16, 0);
+        let parts: Vec<_> = (0..3).map(|index| layout.log.part(&[1, 1, 2], index, 16)).collect();
+        assert!(parts.iter().all(|part| part.first % 16 == 0 && part.blocks % 16 == 0));
+        assert!(parts.windows(2).all(|pair| pair[0].first + pair[0].blocks == pair[1].first));
+        assert!(parts[2].first + parts[2].blocks <= layout.log.first + layout.log.blocks);
+    }
+
+    #[test]
+    fn streams_fill_their_own_parts() {
+        let mut log = writer::<256>(8, 1);
+        assert_eq!(log.stream(0), Region { first: 32, blocks: 8 });
+        assert_eq!(log.stream(1), Region { first: 40, blocks: 24 });
+        block_on(async {
+            assert!(log.append(0, &[1; BLOCK_SIZE]).await.unwrap());
+            assert!(log.append(1, &[2; BLOCK_SIZE]).await.unwrap());
+            assert!(log.append(0, &[3; BLOCK_SIZE]).await.unwrap());
+        });
+        assert_eq!(log.storage().block(32).unwrap()[0], 1);
+        assert_eq!(log.storage().block(33).unwrap()[0], 3);
+        assert_eq!(log.storage().block(40).unwrap()[0], 2);
+        assert_eq!((log.written(0), log.written(1)), (2, 1));
+    }
+
+    #[test]
+    fn full_stream_stops_at_its_part() {
+        let mut log = writer::<256>(8, 0);
+        block_on(async {
+            for _ in 0..8 {
+                assert!(log.append(0, &[1; BLOCK_SIZE]).await.unwrap());
+            }
+            assert!(!log.append(0, &[9; BLOCK_SIZE]).await.unwrap());
+        });
+        // the next stream's first block is untouched
+        assert_eq!(log.storage().block(8).unwrap()[0], 0xFF);
+        assert_eq!(log.written(0), 8);
+    }
+
+    #[test]
+    fn flash_is_erased_ahead_of_each_unit() {
+        // ram storage refuses a second write to a block without an erase in between, as the nor flash would garble it
+        let mut storage = RamStorage::<256>::new(8);
+        block_on(async {
+            for address in 0..256 {
+                storage.write_block(address, &[0; BLOCK_SIZE]).await.unwrap();
+            }
+        });
+        let layout = BootLayout::new(256, 8, 4, 1024, 0);
+        let mut log = LogWriter::new(storage, layout, [1, 3]);
+        block_on(async {
+            for i in 0..8 {
+                assert!(log.append(0, &[i; BLOCK_SIZE]).await.unwrap());
+            }
+            assert!(log.write_image(0, &[7; BLOCK_SIZE]).await.unwrap());
+        });
+        assert_eq!(log.storage().block(7).unwrap()[0], 7);
+        assert_eq!(log.storage().block(128).unwrap()[0], 7);
+        // the rest of the image unit was erased with its first block
+        assert_eq!(log.storage().block(129).unwrap()[0], 0xFF);
+    }
+
+    #[test]
+    fn read_back_stops_at_what_was_written() {
+        let mut log = writer::<256>(0, 2);
+        let mut block = [0; BLOCK_SIZE];
+        block_on(async {
+            log.append(1, &[5; BLOCK_SIZE]).await.unwrap();
+            assert!(log.read(1, 0, &mut block).await.unwrap());
+            assert!(!log.read(1, 1, &mut block).await.unwrap());
+            assert!(!log.read(0, 0, &mut block).await.unwrap());
+        });
+        assert_eq!(block, [5; BLOCK_SIZE]);
+    }
+
+    #[test]
+    fn image_past_the_region_is_refused() {
+        let mut log = writer::<256>(8, 0);
+        block_on(async {
+            assert!(log.write_image(31, &[1; BLOCK_SIZE]).await.unwrap());
+            assert!(!log.write_image(32, &[1; BLOCK_SIZE]).await.unwrap());
+        });
+    }
+
+    #[test]
+    fn drain_takes_the_first_stream_first() {
+        let mut log = writer::<256>(8, 0);
+        let (mut events, events_out) = queue();
+        let (mut imu, imu_out) = queue();
+        let mut queues = [events_out, imu_out];
+        push(&mut imu, 1);
+        push(&mut imu, 2);
+        push(&mut events, 3);
+
+        let mut failures = 0;
+        let taken = block_on(log.drain(&mut queues, |_, _| failures += 1));
+        assert_eq!((taken, failures), (3, 0));
+        assert_eq!(log.storage().block(0).unwrap()[0], 3);
+        assert_eq!(log.storage().block(8).unwrap()[0], 1);
+        assert_eq!(log.storage().block(9).unwrap()[0], 2);
+        assert!(imu.is_empty() && events.is_empty());
+    }
+
+    #[test]
+    fn drain_drops_a_block_that_fails() {
+        // a layout past the end of the device, every write fails
+        let storage = RamStorage::<16>::new(0);
+        let layout = BootLayout { log: Region { first: 16, blocks: 8 }, images: Region { first: 24, blocks: 8 } };
+        let mut log = LogWriter::new(storage, layout, [1, 1]);
+        let (mut producer, consumer) = queue();
+        let mut queues = [consumer, queue().1];
+        push(&mut producer, 1);
+        push(&mut producer, 2);
+
+        let mut failed = Vec::new();
+        let taken = block_on(log.drain(&mut queues, |address, e| failed.push((address, e))));
+        assert_eq!(taken, 2);
+        // the queue doesn't stall on it, and a failed block doesn't count as written
+        assert!(producer.is_empty());
+        assert_eq!(failed, [(16, RamError::OutOfRange), (16, RamError::OutOfRange)]);
+        assert_eq!(log.written(0), 0);
+    }
+}
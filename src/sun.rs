@@ -0,0 +1,145 @@
+// solar position from utc and a place on the earth, the noaa general solar position equations behind its solar
+// calculator, good to about 0.01 deg between 1900 and 2100, plenty for pointing a payload at the sun
+// worked in f64, the julian century needs more digits than f32 has to place the sun to a hundredth of a degree
+// the elevation is geometric, no atmospheric refraction, which is gone at float altitude anyway
+
+use libm::{asin, atan2, cos, cosf, fmod, sin, sinf, tan};
+
+const MS_PER_DAY: u64 = 86_400_000;
+
+/// julian day of the unix epoch
+const UNIX_EPOCH_JD: f64 = 2_440_587.5;
+
+/// julian day of J2000.0
+const J2000_JD: f64 = 2_451_545.0;
+
+/// Where the sun is in the sky
+#[derive(Copy, Clone, PartialEq, Debug, defmt::Format)]
+pub struct SunPosition {
+    /// deg clockwise from true north
+    pub azimuth: f32,
+    /// deg above the horizon, negative below it
+    pub elevation: f32,
+}
+
+impl SunPosition {
+    /// the sun seen from latitude and longitude (deg, east positive) at utc (ms since the unix epoch)
+    pub fn at(utc: u64, latitude: f64, longitude: f64) -> Self {
+        let julian_century = ((utc as f64 / MS_PER_DAY as f64 + UNIX_EPOCH_JD) - J2000_JD) / 36_525.0;
+        let (declination, equation_of_time) = declination_and_equation_of_time(julian_century);
+
+        // true solar time in minutes, then the hour angle, zero at local solar noon
+        let minutes = (utc % MS_PER_DAY) as f64 / 60_000.0;
+        let solar_time = wrap(minutes + equation_of_time + 4.0 * longitude, 1440.0);
+        let hour_angle = (solar_time / 4.0 - 180.0).to_radians();
+
+        let latitude = latitude.to_radians();
+        let elevation = asin(sin(latitude) * sin(declination) + cos(latitude) * cos(declination) * cos(hour_angle));
+        // measured from south by the formula, turned to from north
+        let azimuth = atan2(sin(hour_angle), cos(hour_angle) * sin(latitude) - tan(declination) * cos(latitude));
+
+        Self {
+            azimuth: wrap(azimuth.to_degrees() + 180.0, 360.0) as f32,
+            elevation: elevation.to_degrees() as f32,
+        }
+    }
+
+    /// above the horizon
+    pub fn is_up(&self) -> bool {
+        self.elevation > 0.0
+    }
+
+    /// unit vector towards the sun in the earth frame (x north, y west, z up), the frame of the attitude estimate
+    pub fn direction(&self) -> [f32; 3] {
+        let (azimuth, elevation) = (self.azimuth.to_radians(), self.elevation.to_radians());
+        let horizontal = cosf(elevation);
+        [horizontal * cosf(azimuth), -horizontal * sinf(azimuth), sinf(elevation)]
+    }
+}
+
+// solar declination, rad, and the equation of time, minutes, for the julian century since J2000.0
+fn declination_and_equation_of_time(t: f64) -> (f64, f64) {
+    // geometric mean longitude and anomaly of the sun and the eccentricity of the earth's orbit
+    let mean_longitude = wrap(280.466_46 + t * (36_000.769_83 + t * 0.000_303_2), 360.0).to_radians();
+    let mean_anomaly = (357.529_11 + t * (35_999.050_29 - 0.000_153_7 * t)).to_radians();
+    let eccentricity = 0.016_708_634 - t * (0.000_042_037 + 0.000_000_126_7 * t);
+
+    let center = sin(mean_anomaly) * (1.914_602 - t * (0.004_817 + 0.000_014 * t))
+        + sin(2.0 * mean_anomaly) * (0.019_993 - 0.000_101 * t)
+        + sin(3.0 * mean_anomaly) * 0.000_289;
+    let omega = (125.04 - 1934.136 * t).to_radians();
+    let apparent_longitude = (mean_longitude.to_degrees() + center - 0.005_69 - 0.004_78 * sin(omega)).to_radians();
+
+    let mean_obliquity = 23.0 + (26.0 + (21.448 - t * (46.815 + t * (0.000_59 - t * 0.001_813))) / 60.0) / 60.0;
+    let obliquity = (mean_obliquity + 0.002_56 * cos(omega)).to_radians();
+
+    let declination = asin(sin(obliquity) * sin(apparent_longitude));
+
+    let y = tan(obliquity / 2.0) * tan(obliquity / 2.0);
+    let equation_of_time = y * sin(2.0 * mean_longitude) - 2.0 * eccentricity * sin(mean_anomaly)
+        + 4.0 * eccentricity * y * sin(mean_anomaly) * cos(2.0 * mean_longitude)
+        - 0.5 * y * y * sin(4.0 * mean_longitude)
+        - 1.25 * eccentricity * eccentricity * sin(2.0 * mean_anomaly);
+
+    (declination, 4.0 * equation_of_time.to_degrees())
+}
+
+// value brought into 0..modulus
+fn wrap(value: f64, modulus: f64) -> f64 {
+    let wrapped = fmod(value, modulus);
+    if wrapped < 0.0 { wrapped + modulus } else { wrapped }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // utc (ms since the unix epoch), latitude, longitude, azimuth, and elevation from noaa's solar calculation
+    // spreadsheet with refraction left out
+    const REFERENCE: [(u64, f64, f64, f32, f32); 6] = [
+        // purdue, 2024-06-21 17:00 and 2023-01-15 14:30
+        (1_718_989_200_000, 40.4237, -86.9212, 144.670, 70.058),
+        (1_673_793_000_000, 40.4237, -86.9212, 131.509, 11.898),
+        // sydney, 2024-12-21 02:00, the summer sun to the north
+        (1_734_746_400_000, -33.8688, 151.2093, 351.537, 79.466),
+        // fiji and chukotka, either side of the date line, 2025-03-20 21:45 and 2025-09-01 23:10
+        (1_742_507_100_000, -17.75, 179.95, 66.649, 50.638),
+        (1_756_768_200_000, 64.0, -179.9, 165.333, 33.219),
+        // nairobi, 2021-10-05 08:00
+        (1_633_420_800_000, -1.29, 36.82, 100.449, 69.440),
+    ];
+
+    #[test]
+    fn matches_the_noaa_calculator() {
+        for (utc, latitude, longitude, azimuth, elevation) in REFERENCE {
+            let sun = SunPosition::at(utc, latitude, longitude);
+            assert!((sun.azimuth - azimuth).abs() < 0.05, "{utc} at {latitude}, {longitude}: azimuth {}, expected {azimuth}",
+                sun.azimuth);
+            assert!((sun.elevation - elevation).abs() < 0.05,
+                "{utc} at {latitude}, {longitude}: elevation {}, expected {elevation}", sun.elevation);
+        }
+    }
+
+    #[test]
+    fn solar_noon_is_due_south() {
+        // greenwich, 2024-12-21 11:58:19, solar noon by the noaa calculator, 90 deg less the latitude and declination up
+        let sun = SunPosition::at(1_734_782_299_000, 51.4769, -0.0005);
+        assert!((sun.azimuth - 180.0).abs() < 0.05, "{}", sun.azimuth);
+        assert!((sun.elevation - 15.085).abs() < 0.05, "{}", sun.elevation);
+    }
+
+    #[test]
+    fn night_is_below_the_horizon() {
+        // purdue, 2024-06-21 05:00 utc, the middle of the night
+        let sun = SunPosition::at(1_718_946_000_000, 40.4237, -86.9212);
+        assert!(!sun.is_up(), "{}", sun.elevation);
+    }
+
+    #[test]
+    fn direction_points_at_the_sun() {
+        let sun = SunPosition { azimuth: 90.0, elevation: 30.0 };
+        let [x, y, z] = sun.direction();
+        // due east is -y in the north west up frame
+        assert!(x.abs() < 1e-6 && (y + 0.866_025).abs() < 1e-5 && (z - 0.5).abs() < 1e-6, "{x}, {y}, {z}");
+    }
+}
@@ -0,0 +1,262 @@
+use crate::watchdog::MAX_TASKS;
+
+/// Handle returned when a task loop registers for timing
+#[derive(Copy, Clone, PartialEq, Eq, defmt::Format)]
+pub struct LoopId(u8);
+
+/// Timing of one task loop over a measurement window, times in us
+#[derive(Copy, Clone, Default, defmt::Format)]
+pub struct LoopStats {
+    pub iterations: u32,
+    /// total time spent running the loop body
+    pub busy: u32,
+    pub max_execution: u32,
+    /// largest difference between the time between loop starts and the nominal period
+    pub max_jitter: u32,
+    /// iterations whose body ran longer than the period
+    pub overruns: u16,
+}
+
+#[derive(Copy, Clone)]
+struct Entry {
+    name: &'static str,
+    /// nominal loop period, None for loops that wait on a channel instead of a timer
+    period: Option<u32>,
+    started: Option<u32>,
+    /// start of the previous iteration, kept across windows for the jitter
+    last_start: Option<u32>,
+    stats: LoopStats,
+}
+
+/// Registry of task loop timings, gives execution time, jitter, and overruns per loop measured from loop start and
+/// end marks, and the cpu load over the same window from the busy time
+pub struct LoopTimings {
+    loops: [Option<Entry>; MAX_TASKS],
+    /// start of the current measurement window
+    window_start: Option<u32>,
+}
+
+impl Default for LoopTimings {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LoopTimings {
+    pub const fn new() -> Self {
+        Self {
+            loops: [None; MAX_TASKS],
+            window_start: None,
+        }
+    }
+
+    /// register a task loop with its nominal period (us), None if the registry is full
+    pub fn register(&mut self, name: &'static str, period: Option<u32>) -> Option<LoopId> {
+        let index = self.loops.iter().position(Option::is_none)?;
+        self.loops[index] = Some(Entry {
+            name,
+            period,
+            started: None,
+            last_start: None,
+            stats: LoopStats::default(),
+        });
+        Some(LoopId(index as u8))
+    }
+
+    /// change a loop's nominal period (us), the interval the change falls in isn't counted as jitter
+    pub fn set_period(&mut self, id: LoopId, period: Option<u32>) {
+        if let Some(entry) = self.loops[id.0 as usize].as_mut() {
+            entry.period = period;
+            entry.last_start = None;
+        }
+    }
+
+    /// mark the start of a loop iteration
+    pub fn start(&mut self, id: LoopId, now: u32) {
+        self.window_start.get_or_insert(now);
+        let Some(entry) = self.loops[id.0 as usize].as_mut() else {
+            return;
+        };
+
+        if let (Some(period), Some(last)) = (entry.period, entry.last_start) {
+            let jitter = now.wrapping_sub(last).abs_diff(period);
+            entry.stats.max_jitter = entry.stats.max_jitter.max(jitter);
+        }
+        entry.last_start = Some(now);
+        entry.started = Some(now);
+    }
+
+    /// mark the end of a loop iteration, returns the execution time (us) if it overran the period
+    pub fn end(&mut self, id: LoopId, now: u32) -> Option<u32> {
+        let entry = self.loops[id.0 as usize].as_mut()?;
+        let execution = now.wrapping_sub(entry.started.take()?);
+
+        let stats = &mut entry.stats;
+        stats.iterations += 1;
+        stats.busy = stats.busy.saturating_add(execution);
+        stats.max_execution = stats.max_execution.max(execution);
+
+        if entry.period.is_some_and(|period| execution > period) {
+            stats.overruns = stats.overruns.saturating_add(1);
+            return Some(execution);
+        }
+        None
+    }
+
+    /// whole periods gone by since the loop last started past the one it's running in, 0 for loops without a period
+    /// or that haven't started yet, a hung loop counts up from here while its stats show nothing at all
+    pub fn missed(&self, id: LoopId, now: u32) -> u32 {
+        let Some(Entry { period: Some(period), last_start: Some(last), .. }) = self.loops[id.0 as usize] else {
+            return 0;
+        };
+        (now.wrapping_sub(last) / period.max(1)).saturating_sub(1)
+    }
+
+    pub fn name(&self, id: LoopId) -> &'static str {
+        self.loops[id.0 as usize].map_or("", |e| e.name)
+    }
+
+    /// share of the window (percent) the cpu was busy, busy is the time (us) spent polling tasks over the window
+    /// the loop marks can't give this, a loop body's time includes whatever preempted it and every await in it
+    pub fn cpu_load(&self, busy: u64, now: u32) -> f32 {
+        let Some(start) = self.window_start else {
+            return 0.0;
+        };
+        let window = now.wrapping_sub(start);
+        if window == 0 {
+            return 0.0;
+        }
+        100.0 * busy as f32 / window as f32
+    }
+
+    /// stats of every registered loop for the current window
+    pub fn stats(&self) -> impl Iterator<Item = (&'static str, LoopStats)> + '_ {
+        self.loops.iter().flatten().map(|e| (e.name, e.stats))
+    }
+
+    /// total overruns across all loops in the current window
+    pub fn overruns(&self) -> u16 {
+        self.loops.iter().flatten().fold(0u16, |n, e| n.saturating_add(e.stats.overruns))
+    }
+
+    /// clear the stats and start a new window
+    pub fn reset(&mut self, now: u32) {
+        for entry in self.loops.iter_mut().flatten() {
+            entry.stats = LoopStats::default();
+        }
+        self.window_start = Some(now);
+    }
+}
+
+/// Cpu busy time from the executors' task poll hooks, in cpu cycles
+/// only the outermost poll is timed, a poll on the interrupt executor that preempts one on the thread executor is
+/// already inside it
+pub struct BusyTime {
+    /// polls running, more than one while preempted
+    depth: u32,
+    /// cycle count at the start of the outermost poll
+    started: u32,
+    busy: u64,
+}
+
+impl Default for BusyTime {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BusyTime {
+    pub const fn new() -> Self {
+        Self { depth: 0, started: 0, busy: 0 }
+    }
+
+    /// a task poll starts, now is the cycle count
+    pub fn begin(&mut self, now: u32) {
+        if self.depth == 0 {
+            self.started = now;
+        }
+        self.depth += 1;
+    }
+
+    /// a task poll is done
+    pub fn end(&mut self, now: u32) {
+        self.depth = self.depth.saturating_sub(1);
+        if self.depth == 0 {
+            self.busy += now.wrapping_sub(self.started) as u64;
+        }
+    }
+
+    /// busy cycles since the last take, a poll still running counts up to now and the rest of it goes in the next
+    /// the counter wraps in seconds, a poll has to be shorter than that
+    pub fn take(&mut self, now: u32) -> u64 {
+        if self.depth > 0 {
+            self.busy += now.wrapping_sub(self.started) as u64;
+            self.started = now;
+        }
+        core::mem::take(&mut self.busy)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn busy_time_adds_up_polls() {
+        let mut busy = BusyTime::new();
+        busy.begin(100);
+        busy.end(150);
+        busy.begin(400);
+        busy.end(410);
+        assert_eq!(busy.take(1000), 60);
+        assert_eq!(busy.take(2000), 0);
+    }
+
+    #[test]
+    fn preempting_poll_is_not_counted_twice() {
+        let mut busy = BusyTime::new();
+        busy.begin(0);
+        busy.begin(10);
+        busy.end(30);
+        busy.end(100);
+        assert_eq!(busy.take(200), 100);
+    }
+
+    #[test]
+    fn running_poll_is_split_across_takes() {
+        let mut busy = BusyTime::new();
+        busy.begin(u32::MAX - 9);
+        assert_eq!(busy.take(10), 20);
+        busy.end(30);
+        assert_eq!(busy.take(40), 20);
+    }
+
+    #[test]
+    fn load_is_busy_over_the_window() {
+        let mut timings = LoopTimings::new();
+        let id = timings.register("control", Some(10_000)).unwrap();
+        assert_eq!(timings.cpu_load(0, 0), 0.0);
+        timings.start(id, 0);
+        timings.end(id, 9_000);
+        // the loop took 9 ms between its marks, but only 2 of those were the cpu's
+        assert!((timings.cpu_load(2_000, 10_000) - 20.0).abs() < 1e-3);
+        timings.reset(10_000);
+        assert_eq!(timings.cpu_load(0, 20_000), 0.0);
+    }
+    #[test]
+    fn period_change_isnt_jitter() {
+        let mut timings = LoopTimings::new();
+        let id = timings.register("baro", Some(100_000)).unwrap();
+        timings.start(id, 0);
+        timings.end(id, 1_000);
+        timings.set_period(id, Some(20_000));
+        timings.start(id, 50_000);
+        timings.end(id, 51_000);
+        timings.start(id, 71_000);
+        // 25 ms is an overrun at the new period, it wasn't at the old
+        assert_eq!(timings.end(id, 96_000), Some(25_000));
+        let (_, stats) = timings.stats().next().unwrap();
+        assert_eq!(stats.max_jitter, 1_000);
+        assert_eq!(stats.overruns, 1);
+    }
+}
@@ -0,0 +1,469 @@
+use crate::crc::{crc32, crc32_update};
+
+/// image bytes carried by one uplink chunk
+pub const CHUNK_LEN: usize = 64;
+
+/// marks a written update record, "UPDT"
+const MAGIC: u32 = 0x5444_5055;
+
+/// marks a slot trailer, "SLOT"
+const TRAILER_MAGIC: u32 = 0x544F_4C53;
+
+/// Firmware image transfer steps from the uplink
+#[derive(Copy, Clone, PartialEq, defmt::Format)]
+pub enum UpdateCommand {
+    /// start a transfer of an image of size bytes with the given crc32, or resume the one in progress if it matches
+    Begin { size: u32, crc: u32 },
+    /// image bytes at offset, crc is the crc32 of data[..len], every chunk but the last must be full
+    Chunk {
+        offset: u32,
+        len: u8,
+        data: [u8; CHUNK_LEN],
+        crc: u32,
+    },
+    /// check the whole image and install it at the next reboot
+    Finish,
+    /// drop the transfer in progress
+    Abort,
+}
+
+/// Why a transfer step was rejected
+#[derive(Copy, Clone, PartialEq, Eq, Debug, defmt::Format)]
+pub enum UpdateError {
+    /// no transfer in progress
+    NotStarted,
+    /// empty image or doesn't fit the update slot
+    BadSize,
+    /// chunk data doesn't match its crc, resend it
+    ChunkCrc,
+    /// chunk is short without ending the image, or runs past the end
+    BadChunk,
+    /// chunk past the next expected offset, resend from next_offset
+    OutOfOrder,
+    /// finish before every byte arrived
+    Incomplete,
+    /// whole image crc doesn't match, the transfer has to start over
+    ImageCrc,
+    /// flash erase or write failed
+    Flash,
+    /// the board has no update slot
+    NoSlot,
+}
+
+/// Transfer progress, downlinked after every step so the ground knows where to resume
+#[derive(Copy, Clone, PartialEq, Eq, defmt::Format)]
+pub struct UpdateStatus {
+    pub size: u32,
+    pub next_offset: u32,
+    pub result: Result<(), UpdateError>,
+    pub time_stamp: u32,
+}
+
+#[derive(Copy, Clone)]
+struct Transfer {
+    size: u32,
+    crc: u32,
+    next_offset: u32,
+    /// crc32 of the bytes written so far, not yet inverted
+    running_crc: u32,
+}
+
+/// Tracks an image transfer into the update slot
+/// chunks are written in order, a transfer interrupted by a lost link resumes from next_offset
+/// as long as the ground begins it again with the same size and crc
+pub struct ImageReceiver {
+    /// update slot size, bytes
+    capacity: u32,
+    transfer: Option<Transfer>,
+}
+
+impl ImageReceiver {
+    pub const fn new(capacity: u32) -> Self {
+        Self { capacity, transfer: None }
+    }
+
+    /// image size and next expected offset, zeros when idle
+    pub fn progress(&self) -> (u32, u32) {
+        self.transfer.map_or((0, 0), |t| (t.size, t.next_offset))
+    }
+
+    /// start or resume a transfer, returns true when a new one starts and the slot has to be erased
+    pub fn begin(&mut self, size: u32, crc: u32) -> Result<bool, UpdateError> {
+        if size == 0 || size > self.capacity {
+            return Err(UpdateError::BadSize);
+        }
+        if self.transfer.is_some_and(|t| t.size == size && t.crc == crc) {
+            return Ok(false);
+        }
+
+        self.transfer = Some(Transfer {
+            size,
+            crc,
+            next_offset: 0,
+            running_crc: 0xFFFF_FFFF,
+        });
+        Ok(true)
+    }
+
+    /// check a chunk, returns true if it is the next one and should be written to the slot at its offset
+    /// false for a repeat of a chunk already written, the ack was lost and the ground sent it again
+    pub fn accept(&self, offset: u32, data: &[u8], crc: u32) -> Result<bool, UpdateError> {
+        let transfer = self.transfer.ok_or(UpdateError::NotStarted)?;
+        if crc32(data) != crc {
+            return Err(UpdateError::ChunkCrc);
+        }
+
+        let end = offset.checked_add(data.len() as u32).ok_or(UpdateError::BadChunk)?;
+        if data.is_empty() || end > transfer.size || (data.len() < CHUNK_LEN && end != transfer.size) {
+            return Err(UpdateError::BadChunk);
+        }
+
+        if offset < transfer.next_offset {
+            return Ok(false);
+        }
+        if offset > transfer.next_offset {
+            return Err(UpdateError::OutOfOrder);
+        }
+        Ok(true)
+    }
+
+    /// count an accepted chunk once it is in flash
+    pub fn advance(&mut self, data: &[u8]) {
+        if let Some(transfer) = self.transfer.as_mut() {
+            transfer.running_crc = crc32_update(transfer.running_crc, data);
+            transfer.next_offset += data.len() as u32;
+        }
+    }
+
+    /// end the transfer, returns the image size and crc when every byte arrived and the crc matches
+    /// a crc mismatch drops the transfer since the slot holds a bad image
+    pub fn finish(&mut self) -> Result<(u32, u32), UpdateError> {
+        let transfer = self.transfer.ok_or(UpdateError::NotStarted)?;
+        if transfer.next_offset != transfer.size {
+            return Err(UpdateError::Incomplete);
+        }
+
+        self.transfer = None;
+        if !transfer.running_crc != transfer.crc {
+            return Err(UpdateError::ImageCrc);
+        }
+        Ok((transfer.size, transfer.crc))
+    }
+
+    pub fn abort(&mut self) {
+        self.transfer = None;
+    }
+}
+
+/// Size and crc of the image in an update or backup slot, written past it once the whole image is there
+/// so the bootloader can tell a complete image from a partly written or erased slot
+#[derive(Copy, Clone, PartialEq, Eq, Debug, defmt::Format)]
+pub struct SlotTrailer {
+    pub size: u32,
+    pub crc: u32,
+}
+
+impl SlotTrailer {
+    pub const LEN: usize = 16;
+
+    pub fn to_bytes(&self) -> [u8; Self::LEN] {
+        let mut bytes = [0u8; Self::LEN];
+        bytes[0..4].copy_from_slice(&TRAILER_MAGIC.to_le_bytes());
+        bytes[4..8].copy_from_slice(&self.size.to_le_bytes());
+        bytes[8..12].copy_from_slice(&self.crc.to_le_bytes());
+        let check = crc32(&bytes[..12]);
+        bytes[12..16].copy_from_slice(&check.to_le_bytes());
+        bytes
+    }
+
+    /// None for an erased or partly written trailer
+    pub fn from_bytes(bytes: &[u8; Self::LEN]) -> Option<Self> {
+        let word = |i: usize| u32::from_le_bytes([bytes[i], bytes[i + 1], bytes[i + 2], bytes[i + 3]]);
+        if word(0) != TRAILER_MAGIC || word(12) != crc32(&bytes[..12]) {
+            return None;
+        }
+        Some(SlotTrailer { size: word(4), crc: word(8) })
+    }
+}
+
+/// Where a received image is in its life
+#[derive(Copy, Clone, PartialEq, Eq, Debug, defmt::Format)]
+pub enum UpdateState {
+    /// verified in the update slot, installed at the next boot
+    Pending,
+    /// installed, running on trial until it proves healthy
+    Trial,
+    /// proved healthy, the previous image is no longer needed
+    Confirmed,
+    /// failed its trial, the previous image was put back
+    RolledBack,
+}
+
+impl UpdateState {
+    fn from_u32(value: u32) -> Option<Self> {
+        match value {
+            0 => Some(UpdateState::Pending),
+            1 => Some(UpdateState::Trial),
+            2 => Some(UpdateState::Confirmed),
+            3 => Some(UpdateState::RolledBack),
+            _ => None,
+        }
+    }
+}
+
+/// What the boot code should do about an update
+#[derive(Copy, Clone, PartialEq, Eq, Debug, defmt::Format)]
+pub enum BootAction {
+    /// run the current image
+    Run,
+    /// back up the current image and copy the update over it
+    Install,
+    /// copy the backed up image back over a failed update
+    Rollback,
+}
+
+/// Update state kept in backup sram across resets, so a new image that keeps crashing before it is confirmed
+/// gets rolled back
+/// the state is stored as a plain u32 since the memory holds garbage after a power loss without vbat
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct UpdateRecord {
+    magic: u32,
+    state: u32,
+    pub size: u32,
+    pub crc: u32,
+    /// boots of the installed image while on trial
+    pub boots: u32,
+    check: u32,
+}
+
+impl UpdateRecord {
+    /// record that never validates
+    pub const EMPTY: UpdateRecord = UpdateRecord {
+        magic: 0,
+        state: 0,
+        size: 0,
+        crc: 0,
+        boots: 0,
+        check: 0,
+    };
+
+    pub fn new(state: UpdateState, size: u32, crc: u32) -> Self {
+        let mut record = UpdateRecord {
+            magic: MAGIC,
+            state: state as u32,
+            size,
+            crc,
+            boots: 0,
+            check: 0,
+        };
+        record.check = record.checksum();
+        record
+    }
+
+    /// None when no update has been received or the record is garbage
+    pub fn state(&self) -> Option<UpdateState> {
+        if self.magic != MAGIC || self.check != self.checksum() {
+            return None;
+        }
+        UpdateState::from_u32(self.state)
+    }
+
+    fn set_state(&mut self, state: UpdateState) {
+        self.state = state as u32;
+        self.check = self.checksum();
+    }
+
+    /// decide what to do at boot and advance the record, the caller writes it back before acting
+    /// a pending image moves to trial, and a trial image that has booted more than max_trial_boots times
+    /// without being confirmed is rolled back
+    pub fn boot(&mut self, max_trial_boots: u32) -> BootAction {
+        match self.state() {
+            Some(UpdateState::Pending) => {
+                self.boots = 0;
+                self.set_state(UpdateState::Trial);
+                BootAction::Install
+            }
+            Some(UpdateState::Trial) => {
+                self.boots += 1;
+                if self.boots > max_trial_boots {
+                    self.set_state(UpdateState::RolledBack);
+                    BootAction::Rollback
+                } else {
+                    self.set_state(UpdateState::Trial);
+                    BootAction::Run
+                }
+            }
+            _ => BootAction::Run,
+        }
+    }
+
+    /// the image on trial proved healthy, returns false if there was nothing on trial
+    pub fn confirm(&mut self) -> bool {
+        if self.state() != Some(UpdateState::Trial) {
+            return false;
+        }
+        self.set_state(UpdateState::Confirmed);
+        true
+    }
+
+    fn checksum(&self) -> u32 {
+        let mut buf = [0u8; 20];
+        for (chunk, word) in buf.chunks_exact_mut(4).zip([self.magic, self.state, self.size, self.crc, self.boots]) {
+            chunk.copy_from_slice(&word.to_le_bytes());
+        }
+        crc32(&buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // two full chunks and a short last one
+    fn image() -> [u8; 150] {
+        core::array::from_fn(|i| (i * 7) as u8)
+    }
+
+    // send the chunks from offset up to end, writing the ones accepted
+    fn send(receiver: &mut ImageReceiver, image: &[u8], from: usize, to: usize) {
+        for (index, chunk) in image[from..to].chunks(CHUNK_LEN).enumerate() {
+            let offset = (from + index * CHUNK_LEN) as u32;
+            if receiver.accept(offset, chunk, crc32(chunk)).unwrap() {
+                receiver.advance(chunk);
+            }
+        }
+    }
+
+    #[test]
+    fn whole_image_finishes() {
+        let image = image();
+        let mut receiver = ImageReceiver::new(1024);
+        assert_eq!(receiver.begin(150, crc32(&image)), Ok(true));
+        send(&mut receiver, &image, 0, 150);
+        assert_eq!(receiver.progress(), (150, 150));
+        assert_eq!(receiver.finish(), Ok((150, crc32(&image))));
+        assert_eq!(receiver.progress(), (0, 0));
+    }
+
+    #[test]
+    fn same_image_resumes_and_a_changed_one_restarts() {
+        let image = image();
+        let mut receiver = ImageReceiver::new(1024);
+        receiver.begin(150, crc32(&image)).unwrap();
+        send(&mut receiver, &image, 0, 64);
+
+        assert_eq!(receiver.begin(150, crc32(&image)), Ok(false));
+        assert_eq!(receiver.progress(), (150, 64));
+        send(&mut receiver, &image, 64, 150);
+        assert_eq!(receiver.finish(), Ok((150, crc32(&image))));
+
+        receiver.begin(150, crc32(&image)).unwrap();
+        send(&mut receiver, &image, 0, 64);
+        assert_eq!(receiver.begin(150, crc32(&image) ^ 1), Ok(true));
+        assert_eq!(receiver.progress(), (150, 0));
+    }
+
+    #[test]
+    fn size_past_the_slot_is_rejected() {
+        let mut receiver = ImageReceiver::new(128);
+        assert_eq!(receiver.begin(0, 0), Err(UpdateError::BadSize));
+        assert_eq!(receiver.begin(129, 0), Err(UpdateError::BadSize));
+        assert_eq!(receiver.accept(0, &[0; CHUNK_LEN], crc32(&[0; CHUNK_LEN])), Err(UpdateError::NotStarted));
+    }
+
+    #[test]
+    fn repeated_chunk_is_acked_without_writing() {
+        let image = image();
+        let mut receiver = ImageReceiver::new(1024);
+        receiver.begin(150, crc32(&image)).unwrap();
+        send(&mut receiver, &image, 0, 128);
+        let chunk = &image[64..128];
+        assert_eq!(receiver.accept(64, chunk, crc32(chunk)), Ok(false));
+        assert_eq!(receiver.progress(), (150, 128));
+    }
+
+    #[test]
+    fn out_of_order_and_bad_chunks_are_rejected() {
+        let image = image();
+        let mut receiver = ImageReceiver::new(1024);
+        receiver.begin(150, crc32(&image)).unwrap();
+        send(&mut receiver, &image, 0, 64);
+
+        let chunk = &image[128..150];
+        assert_eq!(receiver.accept(128, chunk, crc32(chunk)), Err(UpdateError::OutOfOrder));
+        // short without ending the image
+        let chunk = &image[64..96];
+        assert_eq!(receiver.accept(64, chunk, crc32(chunk)), Err(UpdateError::BadChunk));
+        // runs past the end
+        let chunk = &image[64..128];
+        assert_eq!(receiver.accept(128, chunk, crc32(chunk)), Err(UpdateError::BadChunk));
+        assert_eq!(receiver.accept(64, chunk, crc32(chunk) ^ 1), Err(UpdateError::ChunkCrc));
+        assert_eq!(receiver.progress(), (150, 64));
+    }
+
+    #[test]
+    fn finish_needs_every_byte_and_a_matching_crc() {
+        let image = image();
+        let mut receiver = ImageReceiver::new(1024);
+        receiver.begin(150, crc32(&image) ^ 1).unwrap();
+        send(&mut receiver, &image, 0, 128);
+        assert_eq!(receiver.finish(), Err(UpdateError::Incomplete));
+
+        send(&mut receiver, &image, 128, 150);
+        assert_eq!(receiver.finish(), Err(UpdateError::ImageCrc));
+        // the slot holds a bad image, so the transfer is dropped
+        assert_eq!(receiver.progress(), (0, 0));
+        assert_eq!(receiver.finish(), Err(UpdateError::NotStarted));
+    }
+
+    #[test]
+    fn slot_trailer_round_trips_and_rejects_erased_or_damaged_bytes() {
+        let trailer = SlotTrailer { size: 150, crc: 0x1234_5678 };
+        assert_eq!(SlotTrailer::from_bytes(&trailer.to_bytes()), Some(trailer));
+        assert_eq!(SlotTrailer::from_bytes(&[0xFF; SlotTrailer::LEN]), None);
+
+        let mut bytes = trailer.to_bytes();
+        bytes[5] ^= 1;
+        assert_eq!(SlotTrailer::from_bytes(&bytes), None);
+    }
+
+    #[test]
+    fn pending_image_installs_then_rolls_back_after_its_trial_boots() {
+        let mut record = UpdateRecord::new(UpdateState::Pending, 150, 0x1234_5678);
+        assert_eq!(record.boot(3), BootAction::Install);
+        assert_eq!(record.state(), Some(UpdateState::Trial));
+        for boots in 1..=3 {
+            assert_eq!(record.boot(3), BootAction::Run);
+            assert_eq!(record.boots, boots);
+        }
+        assert_eq!(record.boot(3), BootAction::Rollback);
+        assert_eq!(record.state(), Some(UpdateState::RolledBack));
+        assert_eq!(record.boot(3), BootAction::Run);
+        assert!(!record.confirm());
+    }
+
+    #[test]
+    fn confirmed_image_stays() {
+        let mut record = UpdateRecord::new(UpdateState::Pending, 150, 0x1234_5678);
+        record.boot(3);
+        assert!(record.confirm());
+        assert_eq!(record.state(), Some(UpdateState::Confirmed));
+        for _ in 0..5 {
+            assert_eq!(record.boot(3), BootAction::Run);
+        }
+        assert!(!record.confirm());
+    }
+
+    #[test]
+    fn garbage_record_runs_the_current_image() {
+        let mut record = UpdateRecord::EMPTY;
+        assert_eq!(record.state(), None);
+        assert_eq!(record.boot(3), BootAction::Run);
+
+        let mut record = UpdateRecord::new(UpdateState::Pending, 150, 0x1234_5678);
+        record.size += 1;
+        assert_eq!(record.state(), None);
+        assert_eq!(record.boot(3), BootAction::Run);
+    }
+}
@@ -0,0 +1,125 @@
+// sensor sample validation, runs on every sample before it can reach a filter
+// a sensor that has died on the bus usually still answers, with nans, with values no atmosphere or airframe can
+// produce, or with the same register contents over and over, none of which the filters can tell from real data
+// each check that fails sets its flag on the sample and marks it failed, a failed sample is kept out of the filters
+// the status bits say how far consumers further down can trust a sample that got through
+
+/// Sample status, how the sample came about and one bit per check it failed
+#[derive(Copy, Clone, PartialEq, Eq, Default, Debug, defmt::Format)]
+pub struct SampleFlags(pub u8);
+
+impl SampleFlags {
+    pub const NONE: SampleFlags = SampleFlags(0);
+    /// a value is nan or infinite
+    pub const NOT_FINITE: SampleFlags = SampleFlags(1 << 0);
+    /// a value is outside what the sensor can physically read
+    pub const OUT_OF_RANGE: SampleFlags = SampleFlags(1 << 1);
+    /// a value changed faster than the quantity can
+    pub const RATE: SampleFlags = SampleFlags(1 << 2);
+    /// every value repeated exactly for longer than a live sensor's noise allows
+    pub const FROZEN: SampleFlags = SampleFlags(1 << 3);
+    /// measured this cycle, not held over or derived
+    pub const FRESH: SampleFlags = SampleFlags(1 << 4);
+    /// usable but less trustworthy than normal, its redundant pair disagrees or has lost a unit, or a marginal gps fix
+    pub const DEGRADED: SampleFlags = SampleFlags(1 << 5);
+    /// derived from other data rather than measured
+    pub const ESTIMATED: SampleFlags = SampleFlags(1 << 6);
+    /// not to be used, a check failed or the source says the value is bad
+    pub const FAILED: SampleFlags = SampleFlags(1 << 7);
+
+    // flags that keep a sample out of the filters
+    const INVALID: SampleFlags =
+        SampleFlags(Self::NOT_FINITE.0 | Self::OUT_OF_RANGE.0 | Self::RATE.0 | Self::FROZEN.0 | Self::FAILED.0);
+
+    pub fn contains(self, flags: SampleFlags) -> bool {
+        self.0 & flags.0 == flags.0
+    }
+
+    pub fn insert(&mut self, flags: SampleFlags) {
+        self.0 |= flags.0;
+    }
+
+    /// not failed, the sample can go into the filters
+    pub fn valid(self) -> bool {
+        self.0 & Self::INVALID.0 == 0
+    }
+}
+
+/// What one value of a sample can physically be
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Limits {
+    pub min: f32,
+    pub max: f32,
+    /// fastest change per second, infinity to skip the rate check
+    pub max_rate: f32,
+}
+
+/// Checks the samples of one sensor, each sample is N values checked against their own limits and the sample before
+pub struct Validator<const N: usize> {
+    limits: [Limits; N],
+    /// identical samples in a row that count as frozen
+    frozen_samples: u16,
+    /// the last finite sample and its time stamp
+    last: Option<([f32; N], u32)>,
+    /// identical samples in a row so far
+    repeats: u16,
+}
+
+impl<const N: usize> Validator<N> {
+    pub const fn new(limits: [Limits; N], frozen_samples: u16) -> Self {
+        Self {
+            limits,
+            frozen_samples,
+            last: None,
+            repeats: 1,
+        }
+    }
+
+    /// check a freshly measured sample, returns the flags of the checks it failed and failed, or fresh if none did
+    /// a single spike fails the rate check twice, going out and coming back
+    pub fn check(&mut self, values: [f32; N], time_stamp: u32) -> SampleFlags {
+        let mut flags = self.checks(values, time_stamp);
+        flags.insert(if flags == SampleFlags::NONE { SampleFlags::FRESH } else { SampleFlags::FAILED });
+        flags
+    }
+
+    fn checks(&mut self, values: [f32; N], time_stamp: u32) -> SampleFlags {
+        let mut flags = SampleFlags::NONE;
+
+        let finite = values.iter().all(|value| value.is_finite());
+        if !finite {
+            flags.insert(SampleFlags::NOT_FINITE);
+        }
+
+        if values.iter().zip(&self.limits).any(|(value, limits)| value.is_finite() && !(limits.min..=limits.max).contains(value)) {
+            flags.insert(SampleFlags::OUT_OF_RANGE);
+        }
+
+        // a nan says nothing about how fast the next sample moved or whether it repeated, so it isn't kept
+        if !finite {
+            return flags;
+        }
+
+        if let Some((last, last_time_stamp)) = self.last {
+            let dt = time_stamp.wrapping_sub(last_time_stamp) as f32 * 1e-6;
+            let too_fast = values
+                .iter()
+                .zip(&last)
+                .zip(&self.limits)
+                .any(|((value, last), limits)| (value - last).abs() > limits.max_rate * dt);
+            if dt > 0.0 && too_fast {
+                flags.insert(SampleFlags::RATE);
+            }
+
+            // compared bit for bit, a live sensor's last digit doesn't sit still
+            let repeated = values.iter().zip(&last).all(|(value, last)| value.to_bits() == last.to_bits());
+            self.repeats = if repeated { self.repeats.saturating_add(1) } else { 1 };
+            if self.repeats >= self.frozen_samples {
+                flags.insert(SampleFlags::FROZEN);
+            }
+        }
+
+        self.last = Some((values, time_stamp));
+        flags
+    }
+}
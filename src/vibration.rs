@@ -0,0 +1,145 @@
+// vibration bursts, short runs of accelerometer samples at the imu's fastest rate taken around flight events for
+// structures analysis, reduced onboard to the rms and the energy in a few frequency bands on each axis
+// the raw samples are logged as well so the whole spectrum can be looked at on the ground
+
+use core::f32::consts::PI;
+
+use libm::{cosf, sinf, sqrtf};
+
+/// samples in a burst, a power of two for the fft
+pub const BURST_SAMPLES: usize = 256;
+
+/// samples per log record, the raw burst goes in as a run of records, a whole number of them
+pub const CHUNK_SAMPLES: usize = 32;
+
+/// number of frequency bands
+pub const BANDS: usize = 6;
+
+/// band edges, Hz, band i takes the bins above edge i up to and including edge i + 1
+/// octaves up to the nyquist frequency of a 4 kHz burst, with everything below 62.5 Hz in the lowest band
+pub const BAND_EDGES: [f32; BANDS + 1] = [0.0, 62.5, 125.0, 250.0, 500.0, 1000.0, 2000.0];
+
+/// m/s^2 per count of a logged sample, +-327 m/s^2 covers the +-16 g the imus run at
+pub const SAMPLE_SCALE: f32 = 0.01;
+
+/// Part of a burst's raw samples on its way to the log
+#[derive(Copy, Clone, PartialEq, Eq, Debug, defmt::Format)]
+pub struct SampleChunk {
+    /// uptime at the burst's first sample, us
+    pub time_stamp: u32,
+    /// us
+    pub sample_period: u32,
+    /// index of the chunk's first sample in the burst
+    pub first: u16,
+    /// acceleration in SAMPLE_SCALE counts
+    pub samples: [[i16; 3]; CHUNK_SAMPLES],
+}
+
+/// Reduced burst, what goes in the log next to the raw samples
+#[derive(Copy, Clone, PartialEq, Debug, defmt::Format)]
+pub struct VibrationSummary {
+    /// rms about the mean on each axis, m/s^2
+    pub rms: [f32; 3],
+    /// largest excursion from the mean on each axis, m/s^2
+    pub peak: [f32; 3],
+    /// mean square in each band on each axis, (m/s^2)^2, the bands add up to about the square of the rms
+    /// bins above the last edge only count toward the rms
+    pub bands: [[f32; BANDS]; 3],
+    /// us
+    pub sample_period: u32,
+    /// uptime at the first sample, us
+    pub time_stamp: u32,
+}
+
+impl VibrationSummary {
+    /// reduce a burst whose samples (m/s^2) are sample_period (us) apart, starting at time_stamp
+    pub fn new(samples: &[[f32; 3]; BURST_SAMPLES], sample_period: u32, time_stamp: u32) -> Self {
+        let mut summary = Self {
+            rms: [0.0; 3],
+            peak: [0.0; 3],
+            bands: [[0.0; BANDS]; 3],
+            sample_period,
+            time_stamp,
+        };
+        let bin_width = 1e6 / (sample_period.max(1) as f32 * BURST_SAMPLES as f32);
+        // hann window against leakage between bands, the band energies are scaled back up by its power
+        let window_power: f32 = (0..BURST_SAMPLES).map(|i| hann(i) * hann(i)).sum();
+
+        for axis in 0..3 {
+            let mean = samples.iter().map(|sample| sample[axis]).sum::<f32>() / BURST_SAMPLES as f32;
+            let mut re = [0.0; BURST_SAMPLES];
+            let mut im = [0.0; BURST_SAMPLES];
+            let mut square = 0.0;
+            for (i, sample) in samples.iter().enumerate() {
+                let value = sample[axis] - mean;
+                square += value * value;
+                summary.peak[axis] = summary.peak[axis].max(value.abs());
+                re[i] = value * hann(i);
+            }
+            summary.rms[axis] = sqrtf(square / BURST_SAMPLES as f32);
+
+            fft(&mut re, &mut im);
+            // one sided, every bin but nyquist stands for its mirror image as well
+            for k in 1..=BURST_SAMPLES / 2 {
+                let frequency = k as f32 * bin_width;
+                let Some(band) = (0..BANDS).find(|&band| frequency <= BAND_EDGES[band + 1]) else {
+                    break;
+                };
+                let sides = if k == BURST_SAMPLES / 2 { 1.0 } else { 2.0 };
+                summary.bands[axis][band] += sides * (re[k] * re[k] + im[k] * im[k]) / (BURST_SAMPLES as f32 * window_power);
+            }
+        }
+        summary
+    }
+}
+
+/// a sample in SAMPLE_SCALE counts, clamped to what an i16 holds
+pub fn to_counts(sample: [f32; 3]) -> [i16; 3] {
+    sample.map(|value| (value / SAMPLE_SCALE).clamp(i16::MIN as f32, i16::MAX as f32) as i16)
+}
+
+/// the samples of a logged chunk, three i16 counts each, as m/s^2
+pub fn from_counts(bytes: &[u8]) -> impl Iterator<Item = [f32; 3]> + '_ {
+    bytes.chunks_exact(6).map(|sample| {
+        let count = |i: usize| i16::from_le_bytes([sample[2 * i], sample[2 * i + 1]]) as f32 * SAMPLE_SCALE;
+        [count(0), count(1), count(2)]
+    })
+}
+
+fn hann(i: usize) -> f32 {
+    0.5 - 0.5 * cosf(2.0 * PI * i as f32 / BURST_SAMPLES as f32)
+}
+
+// in place radix 2 fft, bit reversed reorder then the butterflies a stage at a time
+fn fft(re: &mut [f32; BURST_SAMPLES], im: &mut [f32; BURST_SAMPLES]) {
+    let mut j = 0;
+    for i in 1..BURST_SAMPLES {
+        let mut bit = BURST_SAMPLES >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            re.swap(i, j);
+            im.swap(i, j);
+        }
+    }
+
+    let mut len = 2;
+    while len <= BURST_SAMPLES {
+        let angle = -2.0 * PI / len as f32;
+        for start in (0..BURST_SAMPLES).step_by(len) {
+            for k in 0..len / 2 {
+                let (sin, cos) = (sinf(angle * k as f32), cosf(angle * k as f32));
+                let (a, b) = (start + k, start + k + len / 2);
+                let (tr, ti) = (re[b] * cos - im[b] * sin, re[b] * sin + im[b] * cos);
+                re[b] = re[a] - tr;
+                im[b] = im[a] - ti;
+                re[a] += tr;
+                im[a] += ti;
+            }
+        }
+        len <<= 1;
+    }
+}
@@ -0,0 +1,57 @@
+/// most tasks that can register for watchdog check ins
+pub const MAX_TASKS: usize = 16;
+
+/// Handle returned when a task registers, used to check in
+#[derive(Copy, Clone, PartialEq, Eq, defmt::Format)]
+pub struct TaskId(u8);
+
+#[derive(Copy, Clone)]
+struct Entry {
+    name: &'static str,
+    /// longest allowed gap between check ins, us
+    deadline: u32,
+    /// last check in, or registration time before the first one, us
+    last: u32,
+}
+
+/// Registry of tasks that must check in periodically
+/// the watchdog is only fed while every registered task has checked in within its deadline,
+/// so a hung task resets the board even though the feeder task itself is still running
+pub struct CheckIns {
+    tasks: [Option<Entry>; MAX_TASKS],
+}
+
+impl Default for CheckIns {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CheckIns {
+    pub const fn new() -> Self {
+        Self { tasks: [None; MAX_TASKS] }
+    }
+
+    /// register a task with its check in deadline (us), None if the registry is full
+    pub fn register(&mut self, name: &'static str, deadline: u32, now: u32) -> Option<TaskId> {
+        let index = self.tasks.iter().position(Option::is_none)?;
+        self.tasks[index] = Some(Entry { name, deadline, last: now });
+        Some(TaskId(index as u8))
+    }
+
+    pub fn check_in(&mut self, id: TaskId, now: u32) {
+        if let Some(entry) = self.tasks.get_mut(id.0 as usize).and_then(Option::as_mut) {
+            entry.last = now;
+        }
+    }
+
+    /// name of the first task that missed its deadline, None if all are healthy
+    /// timestamps are microseconds and wrap, wrapping_sub gives the right interval across one wrap
+    pub fn overdue(&self, now: u32) -> Option<&'static str> {
+        self.tasks
+            .iter()
+            .flatten()
+            .find(|entry| now.wrapping_sub(entry.last) > entry.deadline)
+            .map(|entry| entry.name)
+    }
+}
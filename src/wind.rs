@@ -0,0 +1,96 @@
+use crate::{GpsData, WindProfile};
+
+/// thickness of each wind layer, m
+pub const LAYER_HEIGHT: f32 = 500.0;
+
+/// number of layers, covers the ground up to 40 km
+const MAX_LAYERS: usize = 80;
+
+#[derive(Copy, Clone)]
+struct Layer {
+    sum: [f32; 2],
+    count: u16,
+}
+
+/// Builds a wind profile from gps ground velocity during ascent
+/// a balloon drifts with the air mass, so its horizontal ground velocity is the wind at its altitude
+pub struct WindEstimator {
+    layers: [Layer; MAX_LAYERS],
+    current: Option<usize>,
+}
+
+impl Default for WindEstimator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl WindEstimator {
+    pub const fn new() -> Self {
+        Self {
+            layers: [Layer { sum: [0.0; 2], count: 0 }; MAX_LAYERS],
+            current: None,
+        }
+    }
+
+    /// add a gps fix taken during ascent, returns the finished layer once the balloon climbs out of it
+    pub fn add(&mut self, gps: &GpsData) -> Option<WindProfile> {
+        if !gps.quality.usable() {
+            return None;
+        }
+        let index = layer_index(gps.altitude)?;
+
+        let layer = &mut self.layers[index];
+        layer.sum[0] += gps.velocity[0];
+        layer.sum[1] += gps.velocity[1];
+        layer.count = layer.count.saturating_add(1);
+
+        match self.current.replace(index) {
+            Some(prev) if prev != index => self.profile(prev, gps.time_stamp),
+            _ => None,
+        }
+    }
+
+    /// averaged wind for a layer, None if no fixes landed in it
+    pub fn profile(&self, index: usize, time_stamp: u32) -> Option<WindProfile> {
+        let layer = self.layers.get(index)?;
+        if layer.count == 0 {
+            return None;
+        }
+        Some(WindProfile {
+            altitude: (index as f32 + 0.5) * LAYER_HEIGHT,
+            wind: [layer.sum[0] / layer.count as f32, layer.sum[1] / layer.count as f32],
+            samples: layer.count,
+            time_stamp,
+        })
+    }
+
+    /// wind (north, east) in m/s at an altitude, interpolated between the measured layers around it
+    /// outside the measured range the closest layer is used, None if nothing has been measured
+    pub fn wind_at(&self, altitude: f32) -> Option<[f32; 2]> {
+        let position = (altitude / LAYER_HEIGHT - 0.5).clamp(0.0, (MAX_LAYERS - 1) as f32);
+        let below = (0..=position as usize).rev().find_map(|i| self.profile(i, 0));
+        let above = (position as usize + 1..MAX_LAYERS).find_map(|i| self.profile(i, 0));
+
+        match (below, above) {
+            (Some(below), Some(above)) => {
+                let t = (altitude - below.altitude) / (above.altitude - below.altitude);
+                let t = t.clamp(0.0, 1.0);
+                Some([
+                    below.wind[0] + (above.wind[0] - below.wind[0]) * t,
+                    below.wind[1] + (above.wind[1] - below.wind[1]) * t,
+                ])
+            }
+            (Some(layer), None) | (None, Some(layer)) => Some(layer.wind),
+            (None, None) => None,
+        }
+    }
+}
+
+fn layer_index(altitude: f32) -> Option<usize> {
+    if altitude.is_nan() || altitude < 0.0 {
+        return None;
+    }
+    let index = (altitude / LAYER_HEIGHT) as usize;
+    (index < MAX_LAYERS).then_some(index)
+}
@@ -0,0 +1,31 @@
+// the tag for a bootloader challenge, worked out with the key the firmware was built with, for the console's
+// bootloader <tag> command after bootloader challenge has handed out the nonce
+//
+// BOOTLOADER_KEY=<64 hex digits> cargo bootloader-tag <nonce>
+
+use std::process::ExitCode;
+
+use avionics_sw_hapsis::auth;
+
+const USAGE: &str = "usage: BOOTLOADER_KEY=<64 hex digits> bootloader-tag <nonce>";
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let [nonce] = args.as_slice() else {
+        eprintln!("{USAGE}");
+        return ExitCode::FAILURE;
+    };
+    let Some(key) = std::env::var("BOOTLOADER_KEY").ok().and_then(|hex| auth::parse_key(&hex)) else {
+        eprintln!("BOOTLOADER_KEY isn't set to 64 hex digits\n{USAGE}");
+        return ExitCode::FAILURE;
+    };
+    // the console prints the nonce as 16 hex digits
+    let Ok(nonce) = u64::from_str_radix(nonce.trim_start_matches("0x"), 16) else {
+        eprintln!("nonce {nonce} isn't hex");
+        return ExitCode::FAILURE;
+    };
+
+    let tag: String = auth::tag(&key, nonce).iter().map(|byte| format!("{byte:02x}")).collect();
+    println!("bootloader {tag}");
+    ExitCode::SUCCESS
+}
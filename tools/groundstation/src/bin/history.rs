@@ -0,0 +1,43 @@
+// logged records the payload sent down on request, gathered out of a ground station telemetry log into stream dumps
+// named by flightlog::Stream::file_name, for summary and track to read as if they came off the card
+//
+// cargo history <telemetry log> <out dir>
+
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::process::ExitCode;
+
+use avionics_sw_hapsis::blockqueue::BLOCK_SIZE;
+use groundstation::history;
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let [telemetry, output] = args.as_slice() else {
+        eprintln!("usage: history <telemetry log> <out dir>");
+        return ExitCode::FAILURE;
+    };
+
+    match run(Path::new(telemetry), Path::new(output)) {
+        Ok(0) => {
+            println!("no logged records in {telemetry}");
+            ExitCode::SUCCESS
+        }
+        Ok(_) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(telemetry: &Path, output: &Path) -> io::Result<usize> {
+    let streams = history::from_telemetry_log(&fs::read_to_string(telemetry)?);
+    fs::create_dir_all(output)?;
+    for (stream, dump) in &streams {
+        let path = output.join(stream.file_name());
+        fs::write(&path, dump)?;
+        println!("{}: {} blocks written to {}", stream.name(), dump.len() / BLOCK_SIZE, path.display());
+    }
+    Ok(streams.len())
+}
@@ -0,0 +1,37 @@
+// sondehub amateur upload from a ground station telemetry log, the positions as the json array the api takes,
+// written to stdout for an upload such as
+// curl -X PUT -H "Content-Type: application/json" -d @upload.json https://api.v2.sondehub.org/amateur/telemetry
+// safe to run again during the flight, sondehub drops the positions it already has
+//
+// cargo sondehub <payload callsign> <uploader callsign> <telemetry log>
+
+use std::fs;
+use std::process::ExitCode;
+
+use groundstation::sondehub::{self, valid_callsign};
+
+const USAGE: &str = "usage: sondehub <payload callsign> <uploader callsign> <telemetry log>";
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let [callsign, uploader, log] = args.as_slice() else {
+        eprintln!("{USAGE}");
+        return ExitCode::FAILURE;
+    };
+    if let Some(bad) = [callsign, uploader].into_iter().find(|callsign| !valid_callsign(callsign)) {
+        eprintln!("bad callsign {bad:?}, only letters, digits, - and _");
+        return ExitCode::FAILURE;
+    }
+
+    let log = match fs::read_to_string(log) {
+        Ok(log) => log,
+        Err(e) => {
+            eprintln!("{log}: {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let positions = sondehub::from_telemetry_log(&log);
+    println!("{}", sondehub::upload(&positions, callsign, uploader));
+    eprintln!("{} positions", positions.len());
+    ExitCode::SUCCESS
+}
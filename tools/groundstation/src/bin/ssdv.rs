@@ -0,0 +1,49 @@
+// ssdv images from a ground station telemetry log, each image's packets written to its own file for the ssdv
+// decoder, ssdv -d -l 128 image-<n>-<id>.bin image.jpg, lost packets show as grey blocks in the picture
+//
+// cargo ssdv <telemetry log> [out dir]
+
+use std::fs;
+use std::path::Path;
+use std::process::ExitCode;
+
+use groundstation::image;
+
+const USAGE: &str = "usage: ssdv <telemetry log> [out dir]";
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let (log, out) = match args.as_slice() {
+        [log] => (log, "."),
+        [log, out] => (log, out.as_str()),
+        _ => {
+            eprintln!("{USAGE}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let log = match fs::read_to_string(log) {
+        Ok(log) => log,
+        Err(e) => {
+            eprintln!("{log}: {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let images = image::from_telemetry_log(&log);
+    for (n, image) in images.iter().enumerate() {
+        let path = Path::new(out).join(format!("image-{n}-{}.bin", image.image_id));
+        if let Err(e) = fs::write(&path, image.bytes()) {
+            eprintln!("{}: {e}", path.display());
+            return ExitCode::FAILURE;
+        }
+        let (missing, complete) = image.missing();
+        println!(
+            "{}: {} packets, {missing} missing{}",
+            path.display(),
+            image.packets.len(),
+            if complete { "" } else { ", end not received" }
+        );
+    }
+    eprintln!("{} images", images.len());
+    ExitCode::SUCCESS
+}
@@ -0,0 +1,47 @@
+// camera stills from an image region dumped from the card, each written out as its own jpeg
+// a boot's region starts 2 GB into the card, block 4194304 + boot % 64 * 65536, dump it with e.g.
+// dd if=/dev/sdX of=images.bin bs=512 skip=$((4194304 + boot % 64 * 65536)) count=65536
+// that is on a card of 4 GB or more, the firmware logs the regions it uses at boot on anything smaller
+//
+// cargo stills <image dump> [out dir]
+
+use std::fs;
+use std::path::Path;
+use std::process::ExitCode;
+
+use avionics_sw_hapsis::imagelog::Reader;
+
+const USAGE: &str = "usage: stills <image dump> [out dir]";
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let (dump, out) = match args.as_slice() {
+        [dump] => (dump, "."),
+        [dump, out] => (dump, out.as_str()),
+        _ => {
+            eprintln!("{USAGE}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let dump = match fs::read(dump) {
+        Ok(dump) => dump,
+        Err(e) => {
+            eprintln!("{dump}: {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let mut count = 0;
+    for (header, jpeg) in Reader::new(&dump) {
+        let path = Path::new(out).join(format!("still-{}.jpg", header.number));
+        if let Err(e) = fs::write(&path, jpeg) {
+            eprintln!("{}: {e}", path.display());
+            return ExitCode::FAILURE;
+        }
+        let short = if jpeg.len() < header.len as usize { ", cut short" } else { "" };
+        println!("{}: ts {} us, utc {:?}, {} bytes{short}", path.display(), header.time_stamp, header.utc, jpeg.len());
+        count += 1;
+    }
+    eprintln!("{count} stills");
+    ExitCode::SUCCESS
+}
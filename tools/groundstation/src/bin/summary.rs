@@ -0,0 +1,55 @@
+// post-flight summary report from a boot's flight log streams dumped from the card into a directory, each in the file
+// named by flightlog::Stream::file_name, with link statistics when the ground station's telemetry log of the same
+// flight is given, a stream's region is its share (Stream::SHARES) of the boot's log region
+//
+// cargo summary <log dir> [telemetry log]
+
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::process::ExitCode;
+
+use avionics_sw_hapsis::flightlog::Stream;
+use groundstation::summary::Summary;
+use groundstation::telemetry::LinkStats;
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let (log, telemetry) = match args.as_slice() {
+        [log] => (log, None),
+        [log, telemetry] => (log, Some(telemetry)),
+        _ => {
+            eprintln!("usage: summary <log dir> [telemetry log]");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    match run(log, telemetry) {
+        Ok(summary) => {
+            print!("{summary}");
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("{e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(log: &str, telemetry: Option<&String>) -> io::Result<Summary> {
+    // the debug stream is text for reading as it is, a stream that wasn't dumped is left out
+    let mut streams = Vec::new();
+    for stream in Stream::ALL.into_iter().filter(|&stream| stream != Stream::Debug) {
+        match fs::read(Path::new(log).join(stream.file_name())) {
+            Ok(dump) => streams.push(dump),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+    }
+    let streams: Vec<&[u8]> = streams.iter().map(Vec::as_slice).collect();
+    let mut summary = Summary::from_streams(&streams);
+    if let Some(telemetry) = telemetry {
+        summary.link = Some(LinkStats::from_log(&fs::read_to_string(telemetry)?));
+    }
+    Ok(summary)
+}
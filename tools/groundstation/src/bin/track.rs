@@ -0,0 +1,53 @@
+// track export, turns a flight log's gps stream dumped from the card or a ground station telemetry log into kml or gpx
+// the format follows the output's extension
+//
+// cargo track flight <gps.bin> <out.kml | out.gpx>
+// cargo track telemetry <telemetry log> <out.kml | out.gpx>
+
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::process::ExitCode;
+
+use groundstation::track::{self, TrackPoint};
+
+const USAGE: &str = "usage: track flight|telemetry <log> <out.kml | out.gpx>";
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let [source, input, output] = args.as_slice() else {
+        eprintln!("{USAGE}");
+        return ExitCode::FAILURE;
+    };
+
+    match run(source, Path::new(input), Path::new(output)) {
+        Ok(points) => {
+            println!("{points} points written to {output}");
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("{e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(source: &str, input: &Path, output: &Path) -> io::Result<usize> {
+    let points: Vec<TrackPoint> = match source {
+        "flight" => track::from_flight_log(&fs::read(input)?),
+        "telemetry" => track::from_telemetry_log(&fs::read_to_string(input)?),
+        _ => return Err(io::Error::new(io::ErrorKind::InvalidInput, USAGE)),
+    };
+    // named after the log so several flights can be loaded side by side
+    let name = input.file_stem().map_or_else(|| "flight".into(), |stem| stem.to_string_lossy());
+
+    let write: fn(&mut BufWriter<File>, &str, &[TrackPoint]) -> io::Result<()> = match output.extension().and_then(|extension| extension.to_str()) {
+        Some("kml") => track::write_kml,
+        Some("gpx") => track::write_gpx,
+        _ => return Err(io::Error::new(io::ErrorKind::InvalidInput, "output must end in .kml or .gpx")),
+    };
+    let mut out = BufWriter::new(File::create(output)?);
+    write(&mut out, &name, &points)?;
+    out.flush()?;
+    Ok(points.len())
+}
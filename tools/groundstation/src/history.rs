@@ -0,0 +1,45 @@
+// logged records sent down on request, gathered from a telemetry log back into stream dumps laid out like a stream
+// dumped from the card, so summary and track read what came down the same way as what is recovered from the card
+// a record never spans blocks, so the records are packed into blocks whole and each block padded out with PAD
+
+use std::collections::HashSet;
+
+use avionics_sw_hapsis::blockqueue::{BLOCK_SIZE, PAD};
+use avionics_sw_hapsis::compact::PositionDecoder;
+use avionics_sw_hapsis::flightlog::Stream;
+use avionics_sw_hapsis::packet::{self, Frame};
+
+use crate::telemetry;
+
+/// each stream's records in a telemetry log as a stream dump, in the order received, a record sent again for a
+/// repeated request is kept once, streams with nothing received are left out
+pub fn from_telemetry_log(log: &str) -> Vec<(Stream, Vec<u8>)> {
+    let mut positions = PositionDecoder::new();
+    let mut streams: Vec<(Stream, Vec<u8>)> = Vec::new();
+    let mut seen = HashSet::new();
+    for (_, bytes) in telemetry::frames(log) {
+        let Ok(Frame::History(record)) = packet::decode(&bytes, &mut positions) else {
+            continue;
+        };
+        if !seen.insert((record.stream.id(), record.bytes().to_vec())) {
+            continue;
+        }
+        let dump = match streams.iter_mut().find(|(stream, _)| *stream == record.stream) {
+            Some((_, dump)) => dump,
+            None => {
+                streams.push((record.stream, Vec::new()));
+                &mut streams.last_mut().unwrap().1
+            }
+        };
+        // a record that doesn't fit what is left of the block starts the next one
+        let used = dump.len() % BLOCK_SIZE;
+        if used > 0 && used + record.bytes().len() > BLOCK_SIZE {
+            dump.resize(dump.len() + BLOCK_SIZE - used, PAD);
+        }
+        dump.extend_from_slice(record.bytes());
+    }
+    for (_, dump) in &mut streams {
+        dump.resize(dump.len().next_multiple_of(BLOCK_SIZE), PAD);
+    }
+    streams
+}
@@ -0,0 +1,47 @@
+// ssdv images from the downlink, the image packets of a telemetry log gathered per image for the ssdv decoder
+// image ids wrap, so a new image starts whenever the id changes rather than once per id
+
+use avionics_sw_hapsis::compact::PositionDecoder;
+use avionics_sw_hapsis::packet::{self, Frame};
+use avionics_sw_hapsis::ssdv::ReceivedPacket;
+
+use crate::telemetry;
+
+/// One image's packets as received, in packet order without repeats
+pub struct Image {
+    pub image_id: u8,
+    pub packets: Vec<ReceivedPacket>,
+}
+
+impl Image {
+    /// the packets back to back, what ssdv -d -l 128 reads
+    pub fn bytes(&self) -> Vec<u8> {
+        self.packets.iter().flat_map(|packet| packet.0).collect()
+    }
+
+    /// packets lost before the last one received, and whether the end of the image came through
+    pub fn missing(&self) -> (usize, bool) {
+        let received = self.packets.last().map_or(0, |last| last.packet_id() as usize + 1);
+        (received - self.packets.len(), self.packets.last().is_some_and(ReceivedPacket::last))
+    }
+}
+
+/// every image in a telemetry log, in the order they started
+pub fn from_telemetry_log(log: &str) -> Vec<Image> {
+    let mut positions = PositionDecoder::new();
+    let mut images: Vec<Image> = Vec::new();
+    for (_, bytes) in telemetry::frames(log) {
+        let Ok(Frame::Image(packet)) = packet::decode(&bytes, &mut positions) else {
+            continue;
+        };
+        match images.last_mut() {
+            Some(image) if image.image_id == packet.image_id() => image.packets.push(packet),
+            _ => images.push(Image { image_id: packet.image_id(), packets: vec![packet] }),
+        }
+    }
+    for image in &mut images {
+        image.packets.sort_by_key(ReceivedPacket::packet_id);
+        image.packets.dedup_by_key(|packet| packet.packet_id());
+    }
+    images
+}
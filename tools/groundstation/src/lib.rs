@@ -0,0 +1,7 @@
+// shared by the ground tools
+pub mod history;
+pub mod image;
+pub mod sondehub;
+pub mod summary;
+pub mod telemetry;
+pub mod track;
@@ -0,0 +1,205 @@
+// ground station, reads downlink frames from a serial attached receiver, decodes them with the flight packet
+// definitions, prints each one as it arrives, and logs them for comparison with the flight log after recovery
+// the receiver passes every radio packet on as one cobs encoded frame ending in a zero byte
+// with a payload callsign every position is also printed as its ukhas sentence for amateur tracking software
+//
+// cargo groundstation <port> [baud] [log file] [callsign]
+
+use std::fs::File;
+use std::io::{self, BufWriter, ErrorKind, Read, Write};
+use std::process::ExitCode;
+use std::time::{Duration, Instant};
+
+use avionics_sw_hapsis::compact::{CompactPosition, PositionDecoder};
+use avionics_sw_hapsis::packet::{self, Frame, MAX_PACKET_LEN};
+use avionics_sw_hapsis::ssdv;
+use groundstation::sondehub::{SondehubPosition, valid_callsign};
+use groundstation::telemetry::{ERROR, hex, unix_ms};
+
+const DEFAULT_BAUD: u32 = 115_200;
+
+// longest downlink frame, an image packet when images are on
+const MAX_DOWNLINK_LEN: usize = if ssdv::PACKET_LEN > MAX_PACKET_LEN { ssdv::PACKET_LEN } else { MAX_PACKET_LEN };
+
+// longest cobs frame, the packet plus one code byte per 254 bytes
+const MAX_FRAME_LEN: usize = MAX_DOWNLINK_LEN + MAX_DOWNLINK_LEN / 254 + 1;
+
+// how long a read waits before the log is flushed, so a crash or ctrl-c loses at most this much
+const READ_TIMEOUT: Duration = Duration::from_secs(1);
+
+fn main() -> ExitCode {
+    let mut args = std::env::args().skip(1);
+    let Some(port) = args.next() else {
+        eprintln!("usage: groundstation <port> [baud] [log file] [callsign]");
+        return ExitCode::FAILURE;
+    };
+    let baud = match args.next().map(|baud| baud.parse()) {
+        None => DEFAULT_BAUD,
+        Some(Ok(baud)) => baud,
+        Some(Err(e)) => {
+            eprintln!("bad baud rate: {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let log_path = args.next().unwrap_or_else(|| format!("telemetry-{}.log", unix_ms() / 1000));
+    let callsign = args.next();
+    if let Some(callsign) = callsign.as_ref().filter(|callsign| !valid_callsign(callsign)) {
+        eprintln!("bad callsign {callsign:?}, only letters, digits, - and _");
+        return ExitCode::FAILURE;
+    }
+
+    match run(&port, baud, &log_path, callsign) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{port}: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(port: &str, baud: u32, log_path: &str, callsign: Option<String>) -> io::Result<()> {
+    let mut port = serialport::new(port, baud).timeout(READ_TIMEOUT).open()?;
+    let mut log = BufWriter::new(File::create(log_path)?);
+    println!("logging to {log_path}");
+
+    let mut station = Station::new(callsign);
+    let mut frame = Vec::with_capacity(MAX_FRAME_LEN);
+    // more bytes than any frame, set until the next delimiter
+    let mut overlong = false;
+    let mut buffer = [0u8; 256];
+    loop {
+        let len = match port.read(&mut buffer) {
+            Ok(len) => len,
+            Err(e) if e.kind() == ErrorKind::TimedOut => {
+                log.flush()?;
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
+
+        for &byte in &buffer[..len] {
+            if byte != 0 {
+                if frame.len() < MAX_FRAME_LEN {
+                    frame.push(byte);
+                } else {
+                    overlong = true;
+                }
+                continue;
+            }
+
+            if overlong {
+                station.garbage(&frame, &mut log)?;
+            } else if !frame.is_empty() {
+                station.receive(&frame, &mut log)?;
+            }
+            frame.clear();
+            overlong = false;
+        }
+    }
+}
+
+// decoder state and link counts for one session
+struct Station {
+    positions: PositionDecoder,
+    start: Instant,
+    frames: u32,
+    errors: u32,
+    /// payload callsign for the ukhas sentences and positions received so far, their frame count
+    callsign: Option<String>,
+    positions_received: u32,
+}
+
+impl Station {
+    fn new(callsign: Option<String>) -> Self {
+        Self {
+            positions: PositionDecoder::new(),
+            start: Instant::now(),
+            frames: 0,
+            errors: 0,
+            callsign,
+            positions_received: 0,
+        }
+    }
+
+    // one frame from the receiver, cobs encoded, delimiter stripped
+    fn receive(&mut self, encoded: &[u8], log: &mut impl Write) -> io::Result<()> {
+        let Some(bytes) = cobs_decode(encoded) else {
+            return self.garbage(encoded, log);
+        };
+
+        let time = unix_ms();
+        match packet::decode(&bytes, &mut self.positions) {
+            Ok(frame) => {
+                self.frames += 1;
+                println!("{:>8.1} s {} [{} frames, {} errors]", self.elapsed(), describe(&frame), self.frames, self.errors);
+                if let Some(callsign) = &self.callsign
+                    && let Some(position) = SondehubPosition::from_frame(&frame, self.positions_received + 1, time)
+                {
+                    self.positions_received += 1;
+                    println!("{}", position.sentence(callsign));
+                }
+                writeln!(log, "{time} {} {frame:?}", hex(&bytes))
+            }
+            Err(e) => {
+                self.errors += 1;
+                println!("{:>8.1} s {e} [{} frames, {} errors]", self.elapsed(), self.frames, self.errors);
+                writeln!(log, "{time} {} {ERROR} {e}", hex(&bytes))
+            }
+        }
+    }
+
+    // bytes that aren't a frame, line noise or a frame cut short by the receiver
+    fn garbage(&mut self, bytes: &[u8], log: &mut impl Write) -> io::Result<()> {
+        self.errors += 1;
+        println!("{:>8.1} s bad frame, {} bytes [{} frames, {} errors]", self.elapsed(), bytes.len(), self.frames, self.errors);
+        writeln!(log, "{} {} {ERROR} bad frame", unix_ms(), hex(bytes))
+    }
+
+    fn elapsed(&self) -> f32 {
+        self.start.elapsed().as_secs_f32()
+    }
+}
+
+// one line for the live view, positions are the most frequent frame so they get a fixed layout
+fn describe(frame: &Frame) -> String {
+    match frame {
+        Frame::Position(position) => describe_position(position),
+        Frame::Image(image) => format!("image {} packet {}{}", image.image_id(), image.packet_id(), if image.last() { ", last" } else { "" }),
+        Frame::History(history) => match history.record().and_then(|record| record.time_stamp()) {
+            Some(time_stamp) => format!("history {} record {:#04x} at {time_stamp} us", history.stream.name(), history.bytes()[0]),
+            None => format!("history {} record {:#04x}", history.stream.name(), history.bytes()[0]),
+        },
+        frame => format!("{frame:?}"),
+    }
+}
+
+fn describe_position(position: &CompactPosition) -> String {
+    let flags = [
+        (position.dead_reckoned, " dead reckoned"),
+        (position.degraded, " degraded"),
+        (position.low_voltage, " low voltage"),
+        (position.fault, " fault"),
+    ];
+    let flags: String = flags.iter().filter(|(set, _)| *set).map(|(_, name)| *name).collect();
+    format!(
+        "{:?} ({:.5}, {:.5}) {:.0} m {:+.1} m/s {:.2} V{flags}",
+        position.state, position.latitude, position.longitude, position.altitude, position.climb_rate, position.voltage
+    )
+}
+
+// undo the receiver's cobs encoding, None if a block runs past the end of the frame
+fn cobs_decode(encoded: &[u8]) -> Option<Vec<u8>> {
+    let mut decoded = Vec::with_capacity(encoded.len());
+    let mut rest = encoded;
+    while let Some((&code, tail)) = rest.split_first() {
+        // never zero, zero is the delimiter
+        let len = code as usize - 1;
+        decoded.extend_from_slice(tail.get(..len)?);
+        rest = &tail[len..];
+        // a full block carries no zero after it
+        if code != 0xFF && !rest.is_empty() {
+            decoded.push(0);
+        }
+    }
+    Some(decoded)
+}
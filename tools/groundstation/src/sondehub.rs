@@ -0,0 +1,193 @@
+// sondehub amateur tracking, maps received positions to the ukhas telemetry sentence amateur trackers decode and to
+// the json the sondehub amateur api takes, so stations running this tool put the flight on the public map
+// the downlink carries no payload time or frame counter, the receive time cut to the second stands in for the payload
+// time, so stations hearing the same frame agree on it and sondehub merges their reports, and the frame count is this
+// station's count of positions received
+
+use avionics_sw_hapsis::compact::PositionDecoder;
+use avionics_sw_hapsis::packet::{self, Frame};
+use chrono::DateTime;
+
+use crate::telemetry;
+
+const SOFTWARE_NAME: &str = "groundstation";
+
+/// One position as sondehub takes it
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct SondehubPosition {
+    pub frame: u32,
+    /// ms since the unix epoch, whole seconds
+    pub utc: u64,
+    pub latitude: f64,
+    pub longitude: f64,
+    /// above sea level, m
+    pub altitude: f32,
+    /// m/s, None from a beacon
+    pub climb_rate: Option<f32>,
+    /// V, None when the payload didn't know
+    pub battery: Option<f32>,
+    /// when this station received it, ms since the unix epoch
+    pub received: u64,
+}
+
+impl SondehubPosition {
+    /// a decoded frame as a position, None for frames without one
+    pub fn from_frame(frame: &Frame, count: u32, received: u64) -> Option<Self> {
+        let (latitude, longitude, altitude, climb_rate, voltage) = match frame {
+            Frame::Position(position) => (position.latitude, position.longitude, position.altitude, Some(position.climb_rate), position.voltage),
+            Frame::BeaconPacket(beacon) => (beacon.latitude, beacon.longitude, beacon.altitude, None, beacon.voltage.0),
+            _ => return None,
+        };
+        Some(Self {
+            frame: count,
+            utc: received / 1000 * 1000,
+            latitude,
+            longitude,
+            altitude,
+            climb_rate,
+            // the compact position sends 0 for unknown
+            battery: Some(voltage).filter(|&voltage| voltage > 0.0),
+            received,
+        })
+    }
+
+    /// ukhas sentence, $$callsign,frame,time,latitude,longitude,altitude,climb rate,battery*crc
+    /// unknown fields are left empty
+    pub fn sentence(&self, callsign: &str) -> String {
+        let optional = |value: Option<f32>, precision: usize| value.map(|value| format!("{value:.precision$}")).unwrap_or_default();
+        let body = format!(
+            "{callsign},{},{},{:.6},{:.6},{:.0},{},{}",
+            self.frame,
+            time(self.utc, "%H:%M:%S"),
+            self.latitude,
+            self.longitude,
+            self.altitude,
+            optional(self.climb_rate, 1),
+            optional(self.battery, 2),
+        );
+        format!("$${body}*{:04X}", crc16(body.as_bytes()))
+    }
+
+    /// one telemetry object for the sondehub amateur api, callsigns must pass valid_callsign
+    pub fn json(&self, callsign: &str, uploader: &str) -> String {
+        let mut json = format!(
+            r#"{{"software_name":"{SOFTWARE_NAME}","software_version":"{}","modulation":"LoRa","uploader_callsign":"{uploader}","time_received":"{}","payload_callsign":"{callsign}","datetime":"{}","frame":{},"lat":{:.6},"lon":{:.6},"alt":{:.0}"#,
+            env!("CARGO_PKG_VERSION"),
+            time(self.received, "%Y-%m-%dT%H:%M:%S%.3fZ"),
+            time(self.utc, "%Y-%m-%dT%H:%M:%SZ"),
+            self.frame,
+            self.latitude,
+            self.longitude,
+            self.altitude,
+        );
+        if let Some(climb_rate) = self.climb_rate {
+            json += &format!(r#","vel_v":{climb_rate:.1}"#);
+        }
+        if let Some(battery) = self.battery {
+            json += &format!(r#","batt":{battery:.2}"#);
+        }
+        json += &format!(r#","raw":"{}"}}"#, self.sentence(callsign));
+        json
+    }
+}
+
+/// positions and beacons from a telemetry log, numbered in the order received
+pub fn from_telemetry_log(log: &str) -> Vec<SondehubPosition> {
+    let mut positions = PositionDecoder::new();
+    let mut count = 0;
+    telemetry::frames(log)
+        .filter_map(|(time, bytes)| {
+            let frame = packet::decode(&bytes, &mut positions).ok()?;
+            let position = SondehubPosition::from_frame(&frame, count + 1, time)?;
+            count += 1;
+            Some(position)
+        })
+        .collect()
+}
+
+/// the sondehub amateur api body, a json array of telemetry objects
+pub fn upload(positions: &[SondehubPosition], callsign: &str, uploader: &str) -> String {
+    let objects: Vec<String> = positions.iter().map(|position| position.json(callsign, uploader)).collect();
+    format!("[{}]", objects.join(","))
+}
+
+/// letters, digits, - and _, what ukhas decoders accept, and nothing that needs escaping in json
+pub fn valid_callsign(callsign: &str) -> bool {
+    !callsign.is_empty() && callsign.bytes().all(|byte| byte.is_ascii_alphanumeric() || byte == b'-' || byte == b'_')
+}
+
+/// crc-16/ccitt-false, the ukhas sentence checksum over everything between $$ and *
+pub fn crc16(data: &[u8]) -> u16 {
+    let mut crc = 0xFFFFu16;
+    for &byte in data {
+        crc ^= (byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 { (crc << 1) ^ 0x1021 } else { crc << 1 };
+        }
+    }
+    crc
+}
+
+fn time(utc: u64, format: &str) -> String {
+    DateTime::from_timestamp_millis(utc as i64).map_or_else(String::new, |time| time.format(format).to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use avionics_sw_hapsis::compact::CompactPosition;
+    use avionics_sw_hapsis::mission::FlightState;
+    use avionics_sw_hapsis::packet::{BeaconPacket, Voltage};
+
+    // 2026-06-14 13:45:07 utc
+    const RECEIVED: u64 = 1_781_444_707_480;
+
+    #[test]
+    fn crc16_is_ccitt_false() {
+        // the catalogue check value
+        assert_eq!(crc16(b"123456789"), 0x29B1);
+        assert_eq!(crc16(b""), 0xFFFF);
+    }
+
+    #[test]
+    fn position_sentence_has_every_field() {
+        let frame = Frame::Position(CompactPosition {
+            latitude: 40.4237,
+            longitude: -86.9212,
+            altitude: 21_350.0,
+            climb_rate: 5.5,
+            state: FlightState::Ascent,
+            dead_reckoned: false,
+            degraded: false,
+            low_voltage: false,
+            fault: false,
+            voltage: 11.1,
+        });
+        let position = SondehubPosition::from_frame(&frame, 42, RECEIVED).unwrap();
+        assert_eq!(position.utc, 1_781_444_707_000);
+        assert_eq!(position.sentence("HAPSIS"), "$$HAPSIS,42,13:45:07,40.423700,-86.921200,21350,5.5,11.10*C97E");
+        assert!(position.json("HAPSIS", "KD9ABC").contains(r#""raw":"$$HAPSIS,42,13:45:07,40.423700,-86.921200,21350,5.5,11.10*C97E"}"#));
+    }
+
+    #[test]
+    fn beacon_sentence_leaves_unknown_fields_empty() {
+        let frame = Frame::BeaconPacket(BeaconPacket {
+            latitude: 40.4237,
+            longitude: -86.9212,
+            altitude: 21_355.0,
+            voltage: Voltage(f32::NAN),
+            time_stamp: 0,
+        });
+        let position = SondehubPosition::from_frame(&frame, 43, RECEIVED + 1_000).unwrap();
+        assert_eq!((position.climb_rate, position.battery), (None, None));
+        assert_eq!(position.sentence("HAPSIS"), "$$HAPSIS,43,13:45:08,40.423700,-86.921200,21355,,*1EA9");
+    }
+
+    #[test]
+    fn callsigns_are_letters_digits_dashes_and_underscores() {
+        assert!(valid_callsign("HAPSIS-2_a"));
+        assert!(!valid_callsign(""));
+        assert!(!valid_callsign("HAP SIS"));
+        assert!(!valid_callsign("HAPSIS\""));
+    }
+}
@@ -0,0 +1,552 @@
+// post-flight engineering summary of one boot's flight log streams, the same sections in the same order for every flight so
+// reports can be compared side by side, link statistics come from the ground station's telemetry log when given
+
+use std::fmt;
+
+use avionics_sw_hapsis::DropCounts;
+use avionics_sw_hapsis::actuation::FireReport;
+use avionics_sw_hapsis::atmosphere::pressure_to_altitude;
+use avionics_sw_hapsis::calibration::CalibrationChange;
+use avionics_sw_hapsis::command::Command;
+use avionics_sw_hapsis::faults::FaultFlags;
+use avionics_sw_hapsis::flightlog::{self, Record};
+use avionics_sw_hapsis::heater::HeaterReport;
+use avionics_sw_hapsis::power::ChargeState;
+use avionics_sw_hapsis::reset::ResetReason;
+use avionics_sw_hapsis::vibration::{BAND_EDGES, BANDS, VibrationSummary};
+use avionics_sw_hapsis::FlightEvent;
+use chrono::DateTime;
+
+use crate::telemetry::LinkStats;
+
+// time stamps are u32 us of uptime and wrap every 71.6 minutes, records are close enough to log order that
+// the shortest step between neighbours is the real one
+#[derive(Default)]
+struct Clock {
+    last: Option<(u32, i64)>,
+}
+
+impl Clock {
+    // us since boot
+    fn at(&mut self, time_stamp: u32) -> i64 {
+        let time = match self.last {
+            Some((last, time)) => time + time_stamp.wrapping_sub(last) as i32 as i64,
+            None => time_stamp as i64,
+        };
+        self.last = Some((time_stamp, time));
+        time
+    }
+}
+
+/// Lowest and highest of a reading and when they were seen, us since boot
+#[derive(Copy, Clone)]
+pub struct Extremes {
+    pub min: (f32, i64),
+    pub max: (f32, i64),
+}
+
+impl Extremes {
+    fn add(extremes: &mut Option<Extremes>, value: f32, time: i64) {
+        if value.is_nan() {
+            return;
+        }
+        let extremes = extremes.get_or_insert(Extremes { min: (value, time), max: (value, time) });
+        if value < extremes.min.0 {
+            extremes.min = (value, time);
+        }
+        if value > extremes.max.0 {
+            extremes.max = (value, time);
+        }
+    }
+}
+
+/// What one flight log says about the flight, times are us since boot
+#[derive(Default)]
+pub struct Summary {
+    pub firmware: Option<String>,
+    pub reset_reason: Option<ResetReason>,
+    pub boot_count: Option<u32>,
+    pub ground_altitude: Option<f32>,
+    pub records: usize,
+    /// blocks cut short by a bad record
+    pub skipped: u32,
+    pub first: Option<i64>,
+    pub last: Option<i64>,
+    pub launch: Option<i64>,
+    pub burst: Option<i64>,
+    pub landed: Option<i64>,
+    /// gps altitude, usable fixes only
+    pub gps_altitude: Option<Extremes>,
+    /// baro altitude in the standard atmosphere, still there when the gps locks out
+    pub baro_altitude: Option<Extremes>,
+    /// gps vertical speed, usable fixes only
+    pub vertical_speed: Option<Extremes>,
+    pub baro_temperature: Option<Extremes>,
+    pub imu_temperature: Option<Extremes>,
+    /// last battery heater report and how many said the heater was held off
+    pub heater: Option<HeaterReport>,
+    pub heater_held: usize,
+    pub battery_voltage: Option<Extremes>,
+    /// power from the solar array into the charger, W
+    pub solar_power: Option<Extremes>,
+    /// power reports, and how many of them had the charger charging or done
+    pub power_reports: usize,
+    pub charging_reports: usize,
+    /// vibration burst summaries in log order
+    pub vibration: Vec<(i64, VibrationSummary)>,
+    /// actuation channel firings in log order
+    pub firings: Vec<(i64, FireReport)>,
+    /// state changes, commands, events, faults, and calibration changes in log order
+    pub timeline: Vec<(i64, String)>,
+    /// drop counts at the end of the log
+    pub drops: Option<DropCounts>,
+    pub link: Option<LinkStats>,
+    // (us since boot, utc ms) from the time sync records
+    syncs: Vec<(i64, u64)>,
+    // (us since boot, baro altitude) for the rates between events
+    baro: Vec<(i64, f32)>,
+}
+
+impl Summary {
+    /// summary of the binary streams of one boot's log, each dumped from its part of the boot's region, in any order
+    pub fn from_streams(streams: &[&[u8]]) -> Self {
+        let mut summary = Summary::default();
+        for stream in streams {
+            summary.add_stream(stream);
+        }
+        // each stream comes in time order, the time syncs every stream carries are merged into one
+        summary.syncs.sort_by_key(|&(time, _)| time);
+        summary.syncs.dedup();
+        summary.timeline.sort_by_key(|&(time, _)| time);
+        summary
+    }
+
+    fn add_stream(&mut self, log: &[u8]) {
+        // every stream starts just after boot, so each unwraps its time stamps from there on its own
+        let mut clock = Clock::default();
+        let mut reader = flightlog::Reader::new(log);
+
+        for record in reader.by_ref() {
+            self.records += 1;
+            let time = match record {
+                Record::Firmware { dirty, version, git_hash, profile, features } => {
+                    let dirty = if dirty { " dirty" } else { "" };
+                    self.firmware = Some(format!("{version} {git_hash}{dirty} {profile} [{features}]"));
+                    continue;
+                }
+                Record::Session { ground_altitude, reset_reason, boot_count, time_stamp, .. } => {
+                    self.ground_altitude = Some(ground_altitude);
+                    self.reset_reason = ResetReason::ALL.get(reset_reason as usize).copied();
+                    self.boot_count = Some(boot_count);
+                    clock.at(time_stamp)
+                }
+                Record::Baro(baro) => {
+                    let time = clock.at(baro.time_stamp);
+                    let altitude = pressure_to_altitude(baro.pressure);
+                    Extremes::add(&mut self.baro_altitude, altitude, time);
+                    Extremes::add(&mut self.baro_temperature, baro.temperature, time);
+                    self.baro.push((time, altitude));
+                    time
+                }
+                Record::Imu(imu) => {
+                    let time = clock.at(imu.time_stamp);
+                    Extremes::add(&mut self.imu_temperature, imu.temperature, time);
+                    time
+                }
+                Record::Gps(gps) => {
+                    let time = clock.at(gps.time_stamp);
+                    if gps.quality.usable() {
+                        Extremes::add(&mut self.gps_altitude, gps.altitude, time);
+                        Extremes::add(&mut self.vertical_speed, gps.velocity[2], time);
+                    }
+                    time
+                }
+                Record::Event { code, time_stamp } => {
+                    let time = clock.at(time_stamp);
+                    let event = FlightEvent::from_code(code);
+                    match event {
+                        Some(FlightEvent::Launch) => self.launch = Some(time),
+                        Some(FlightEvent::Burst) => self.burst = Some(time),
+                        Some(FlightEvent::Landed) => self.landed = Some(time),
+                        _ => {}
+                    }
+                    let name = event.map_or_else(|| format!("unknown event {code:#04x}"), |event| format!("{event:?}"));
+                    self.timeline.push((time, name));
+                    time
+                }
+                Record::Fault { fault, active, time_stamp } => {
+                    let time = clock.at(time_stamp);
+                    let change = if active { "raised" } else { "cleared" };
+                    self.timeline.push((time, format!("fault {} {change}", fault_names(fault))));
+                    time
+                }
+                Record::TimeSync(sync) => {
+                    let time = clock.at(sync.time_stamp);
+                    self.syncs.push((time, sync.utc));
+                    time
+                }
+                Record::DropCounts(drops, time_stamp) => {
+                    self.drops = Some(drops);
+                    clock.at(time_stamp)
+                }
+                Record::ConfigChange { time_stamp, .. } | Record::MetSync { time_stamp, .. } => clock.at(time_stamp),
+                Record::StackUsage(usage) => clock.at(usage.time_stamp),
+                Record::Attitude(attitude) => clock.at(attitude.time_stamp),
+                Record::Wind(wind) => clock.at(wind.time_stamp),
+                Record::StateVector(state) => clock.at(state.time_stamp),
+                Record::VibrationSamples { time_stamp, .. } => clock.at(time_stamp),
+                Record::Vibration(vibration) => {
+                    let time = clock.at(vibration.time_stamp);
+                    self.vibration.push((time, vibration));
+                    time
+                }
+                Record::Firing(firing) => {
+                    let time = clock.at(firing.time_stamp);
+                    self.firings.push((time, firing));
+                    time
+                }
+                Record::BusRecovery(recovery) => {
+                    let time = clock.at(recovery.time_stamp);
+                    let outcome = if recovery.released { "free" } else { "still stuck" };
+                    self.timeline.push((time, format!(
+                        "sensor bus cleared after {} failed transfers, {} clocks, {outcome}",
+                        recovery.failures, recovery.clocks
+                    )));
+                    time
+                }
+                Record::Power(power) => {
+                    let time = clock.at(power.time_stamp);
+                    Extremes::add(&mut self.battery_voltage, power.battery_voltage, time);
+                    Extremes::add(&mut self.solar_power, power.solar_voltage * power.solar_current, time);
+                    self.power_reports += 1;
+                    self.charging_reports += matches!(power.charge, ChargeState::Charging | ChargeState::Done) as usize;
+                    time
+                }
+                Record::Heater(heater) => {
+                    self.heater = Some(heater);
+                    self.heater_held += heater.held as usize;
+                    clock.at(heater.time_stamp)
+                }
+                Record::State { from, to, time_stamp } => {
+                    let time = clock.at(time_stamp);
+                    self.timeline.push((time, format!("state {from:?} -> {to:?}")));
+                    time
+                }
+                Record::Command { code, accepted, time_stamp } => {
+                    let time = clock.at(time_stamp);
+                    let outcome = if accepted { "carried out" } else { "refused" };
+                    let name = match Command::describe(code) {
+                        Some((name, Some(channel))) => format!("{name} {}", channel.name()),
+                        Some((name, None)) => name.to_string(),
+                        None => format!("{code:#04x}"),
+                    };
+                    self.timeline.push((time, format!("command {name} {outcome}")));
+                    time
+                }
+                Record::Calibration { code, time_stamp } => {
+                    let time = clock.at(time_stamp);
+                    let change = CalibrationChange::from_code(code)
+                        .map_or_else(|| format!("unknown change {code:#04x}"), |change| format!("{change:?}"));
+                    self.timeline.push((time, format!("calibration {change} updated")));
+                    time
+                }
+            };
+            self.first = Some(self.first.map_or(time, |first| first.min(time)));
+            self.last = Some(self.last.map_or(time, |last| last.max(time)));
+        }
+
+        self.skipped += reader.skipped();
+    }
+
+    /// mean rate of climb from launch to burst, m/s, from the baro altitude
+    pub fn ascent_rate(&self) -> Option<f32> {
+        self.rate(self.launch?, self.burst?)
+    }
+
+    /// mean rate of descent from burst to landing, m/s, positive down
+    pub fn descent_rate(&self) -> Option<f32> {
+        self.rate(self.burst?, self.landed?).map(|rate| -rate)
+    }
+
+    fn rate(&self, from: i64, to: i64) -> Option<f32> {
+        let seconds = (to - from) as f32 / 1e6;
+        if seconds <= 0.0 {
+            return None;
+        }
+        Some((self.baro_altitude_at(to)? - self.baro_altitude_at(from)?) / seconds)
+    }
+
+    // first baro sample at or after time
+    fn baro_altitude_at(&self, time: i64) -> Option<f32> {
+        self.baro.iter().find(|&&(sample, _)| sample >= time).map(|&(_, altitude)| altitude)
+    }
+
+    /// utc ms at a time since boot, from the nearest time sync before it, or the first one after it on the pad
+    pub fn utc_at(&self, time: i64) -> Option<u64> {
+        let &(sync, utc) = self.syncs.iter().rev().find(|&&(sync, _)| sync <= time).or(self.syncs.first())?;
+        Some((utc as i64 + (time - sync) / 1000) as u64)
+    }
+
+    /// "T+h:mm:ss" from launch, or from the start of the log before launch is known
+    fn met(&self, time: i64) -> String {
+        let Some(zero) = self.launch.or(self.first) else {
+            return String::new();
+        };
+        let seconds = (time - zero) / 1_000_000;
+        let sign = if seconds < 0 { '-' } else { '+' };
+        let seconds = seconds.abs();
+        format!("T{sign}{}:{:02}:{:02}", seconds / 3600, seconds / 60 % 60, seconds % 60)
+    }
+
+    // met and utc of a moment for the report
+    fn when(&self, time: i64) -> String {
+        match self.utc_at(time).and_then(|utc| DateTime::from_timestamp_millis(utc as i64)) {
+            Some(utc) => format!("{} ({})", self.met(time), utc.format("%Y-%m-%d %H:%M:%S UTC")),
+            None => self.met(time),
+        }
+    }
+
+    fn write_extremes(&self, f: &mut fmt::Formatter<'_>, name: &str, unit: &str, extremes: Option<Extremes>) -> fmt::Result {
+        match extremes {
+            Some(Extremes { min, max }) => writeln!(
+                f,
+                "  {name}: min {:.1} {unit} at {}, max {:.1} {unit} at {}",
+                min.0,
+                self.met(min.1),
+                max.0,
+                self.met(max.1)
+            ),
+            None => writeln!(f, "  {name}: no data"),
+        }
+    }
+}
+
+// names of the faults in a set, a fault record carries one
+fn fault_names(flags: FaultFlags) -> String {
+    let names: Vec<String> = flags.iter().map(|fault| format!("{fault:?}")).collect();
+    if names.is_empty() { format!("{:#06x}", flags.0) } else { names.join(", ") }
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "flight summary")?;
+        writeln!(f, "  firmware: {}", self.firmware.as_deref().unwrap_or("unknown"))?;
+        if let Some(boot_count) = self.boot_count {
+            writeln!(f, "  boot: {boot_count}, reset: {:?}", self.reset_reason.unwrap_or(ResetReason::Unknown))?;
+        }
+        writeln!(f, "  records: {}, blocks cut short: {}", self.records, self.skipped)?;
+        if let (Some(first), Some(last)) = (self.first, self.last) {
+            writeln!(f, "  logged: {:.1} min", (last - first) as f32 / 60e6)?;
+        }
+
+        writeln!(f, "\nphases")?;
+        for (name, time) in [("launch", self.launch), ("burst", self.burst), ("landed", self.landed)] {
+            match time {
+                Some(time) => writeln!(f, "  {name}: {}", self.when(time))?,
+                None => writeln!(f, "  {name}: not detected")?,
+            }
+        }
+        if let (Some(launch), Some(burst)) = (self.launch, self.burst) {
+            writeln!(f, "  ascent: {:.1} min", (burst - launch) as f32 / 60e6)?;
+        }
+        if let (Some(burst), Some(landed)) = (self.burst, self.landed) {
+            writeln!(f, "  descent: {:.1} min", (landed - burst) as f32 / 60e6)?;
+        }
+
+        writeln!(f, "\naltitude")?;
+        if let Some(Extremes { max, .. }) = self.gps_altitude {
+            writeln!(f, "  max gps: {:.0} m at {}", max.0, self.when(max.1))?;
+        }
+        if let Some(Extremes { max, .. }) = self.baro_altitude {
+            writeln!(f, "  max baro: {:.0} m at {}", max.0, self.when(max.1))?;
+        }
+        if let (Some(ground), Some(Extremes { max, .. })) = (self.ground_altitude, self.gps_altitude.or(self.baro_altitude)) {
+            writeln!(f, "  max above launch site: {:.0} m", max.0 - ground)?;
+        }
+        match self.ascent_rate() {
+            Some(rate) => writeln!(f, "  mean ascent rate: {rate:.2} m/s")?,
+            None => writeln!(f, "  mean ascent rate: needs launch and burst")?,
+        }
+        match self.descent_rate() {
+            Some(rate) => writeln!(f, "  mean descent rate: {rate:.2} m/s")?,
+            None => writeln!(f, "  mean descent rate: needs burst and landing")?,
+        }
+        if let Some(Extremes { min, max }) = self.vertical_speed {
+            writeln!(f, "  peak climb: {:.1} m/s at {}, peak descent: {:.1} m/s at {}", max.0, self.met(max.1), -min.0, self.met(min.1))?;
+        }
+
+        writeln!(f, "\ntemperature")?;
+        self.write_extremes(f, "baro", "C", self.baro_temperature)?;
+        self.write_extremes(f, "imu", "C", self.imu_temperature)?;
+        match self.heater {
+            Some(heater) => writeln!(
+                f,
+                "  battery heater: {:.2} Wh used, held off in {} of its reports",
+                heater.total_energy / 3600.0,
+                self.heater_held
+            )?,
+            None => writeln!(f, "  battery heater: no reports")?,
+        }
+
+        writeln!(f, "\npower")?;
+        self.write_extremes(f, "battery", "V", self.battery_voltage)?;
+        self.write_extremes(f, "solar", "W", self.solar_power)?;
+        if self.power_reports > 0 {
+            writeln!(f, "  charging in {} of {} reports", self.charging_reports, self.power_reports)?;
+        }
+
+        // the band with the most energy over all three axes, where a resonance would show
+        writeln!(f, "\nvibration")?;
+        if self.vibration.is_empty() {
+            writeln!(f, "  no bursts")?;
+        }
+        for (time, vibration) in &self.vibration {
+            let energy = |band: usize| vibration.bands.iter().map(|axis| axis[band]).sum::<f32>();
+            let band = (0..BANDS).max_by(|&a, &b| energy(a).total_cmp(&energy(b))).unwrap_or(0);
+            writeln!(
+                f,
+                "  {} rms {:.2} {:.2} {:.2} m/s^2, most in {:.0}-{:.0} Hz",
+                self.met(*time),
+                vibration.rms[0],
+                vibration.rms[1],
+                vibration.rms[2],
+                BAND_EDGES[band],
+                BAND_EDGES[band + 1]
+            )?;
+        }
+
+        writeln!(f, "\nactuation")?;
+        if self.firings.is_empty() {
+            writeln!(f, "  nothing fired")?;
+        }
+        for (time, firing) in &self.firings {
+            let outcome = if firing.confirmed { "fired and confirmed" } else { "fired, no current detected" };
+            let opened = if firing.opened { ", load burned open" } else { "" };
+            writeln!(
+                f,
+                "  {} {} {outcome}, peak {:.2} A, {:.1} ms conducting of {:.1} ms{opened}",
+                self.met(*time),
+                firing.channel.name(),
+                firing.peak_current,
+                firing.conducted as f32 / 1e3,
+                firing.dwell as f32 / 1e3
+            )?;
+        }
+
+        writeln!(f, "\ntimeline")?;
+        if self.timeline.is_empty() {
+            writeln!(f, "  no events")?;
+        }
+        for (time, name) in &self.timeline {
+            writeln!(f, "  {} {name}", self.met(*time))?;
+        }
+
+        writeln!(f, "\nlink")?;
+        if let Some(drops) = self.drops {
+            writeln!(
+                f,
+                "  dropped on board: telemetry {}, log {}, baro {}, imu {}, gps {}",
+                drops.telemetry, drops.log, drops.baro, drops.imu, drops.gps
+            )?;
+        }
+        match &self.link {
+            Some(link) => write!(f, "{link}"),
+            None => writeln!(f, "  no telemetry log given"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use avionics_sw_hapsis::blockqueue::{BLOCK_SIZE, PAD};
+    use avionics_sw_hapsis::flightlog::{LOG_BARO, LOG_EVENT, LOG_TIME_SYNC};
+
+    const MINUTE: u64 = 60_000_000;
+    const LAUNCH: u64 = 1_000_000;
+    // 2026-06-14 12:00:00 utc, at boot
+    const BOOT_UTC: u64 = 1_781_438_400_000;
+
+    // records in one block, time stamps as logged, wrapped to u32
+    fn block(records: &[(u8, Vec<u8>, u64)]) -> Vec<u8> {
+        let mut block = Vec::new();
+        for (tag, fields, time) in records {
+            block.push(*tag);
+            block.extend(fields);
+            block.extend((*time as u32).to_le_bytes());
+        }
+        block.resize(BLOCK_SIZE, PAD);
+        block
+    }
+
+    fn event(event: FlightEvent, time: u64) -> (u8, Vec<u8>, u64) {
+        (LOG_EVENT, vec![event.code()], time)
+    }
+
+    fn sync(time: u64) -> (u8, Vec<u8>, u64) {
+        (LOG_TIME_SYNC, (BOOT_UTC + time / 1000).to_le_bytes().to_vec(), time)
+    }
+
+    fn baro(pressure: f32, time: u64) -> (u8, Vec<u8>, u64) {
+        (LOG_BARO, [pressure.to_le_bytes(), 0.0f32.to_le_bytes()].concat(), time)
+    }
+
+    // an hour up and half an hour down, the time stamps wrap between burst and landing, each stream has a record at
+    // least every 20 min for its clock to follow
+    fn flight() -> (Vec<u8>, Vec<u8>) {
+        let burst = LAUNCH + 60 * MINUTE;
+        let landed = burst + 30 * MINUTE;
+        let events = block(&[
+            sync(0),
+            event(FlightEvent::Launch, LAUNCH),
+            sync(LAUNCH + 20 * MINUTE),
+            sync(LAUNCH + 40 * MINUTE),
+            event(FlightEvent::Burst, burst),
+            sync(burst + 15 * MINUTE),
+            event(FlightEvent::Landed, landed),
+        ]);
+        let baro = block(&[
+            baro(1000.0, 0),
+            baro(1000.0, LAUNCH),
+            baro(500.0, LAUNCH + 20 * MINUTE),
+            baro(100.0, LAUNCH + 40 * MINUTE),
+            baro(10.0, burst),
+            baro(300.0, burst + 15 * MINUTE),
+            baro(1000.0, landed),
+        ]);
+        (events, baro)
+    }
+
+    #[test]
+    fn phases_are_found_across_streams_and_a_time_stamp_wrap() {
+        let (events, baro) = flight();
+        let summary = Summary::from_streams(&[&baro, &events]);
+        assert_eq!(summary.records, 14);
+        assert_eq!(summary.launch, Some(LAUNCH as i64));
+        assert_eq!(summary.burst, Some((LAUNCH + 60 * MINUTE) as i64));
+        // past the 71.6 min the u32 time stamps cover
+        assert_eq!(summary.landed, Some((LAUNCH + 90 * MINUTE) as i64));
+        assert_eq!(summary.utc_at(LAUNCH as i64), Some(BOOT_UTC + 1_000));
+
+        let climb = pressure_to_altitude(10.0) - pressure_to_altitude(1000.0);
+        assert!((summary.ascent_rate().unwrap() - climb / 3600.0).abs() < 1e-3);
+        assert!((summary.descent_rate().unwrap() - climb / 1800.0).abs() < 1e-3);
+
+        let report = summary.to_string();
+        assert!(report.contains("  launch: T+0:00:00 (2026-06-14 12:00:01 UTC)\n"));
+        assert!(report.contains("  burst: T+1:00:00 (2026-06-14 13:00:01 UTC)\n"));
+        assert!(report.contains("  landed: T+1:30:00 (2026-06-14 13:30:01 UTC)\n"));
+        assert!(report.contains("  ascent: 60.0 min\n  descent: 30.0 min\n"));
+    }
+
+    #[test]
+    fn missing_phases_are_reported_as_such() {
+        let events = block(&[sync(0), event(FlightEvent::Launch, LAUNCH)]);
+        let summary = Summary::from_streams(&[&events]);
+        assert_eq!((summary.launch, summary.burst, summary.landed), (Some(LAUNCH as i64), None, None));
+        assert_eq!(summary.ascent_rate(), None);
+        let report = summary.to_string();
+        assert!(report.contains("  burst: not detected\n  landed: not detected\n"));
+        assert!(report.contains("  mean ascent rate: needs launch and burst\n"));
+    }
+}
@@ -0,0 +1,190 @@
+// received telemetry log, written by the ground station, one line per frame from the receiver
+// <receive time, ms since the unix epoch> <frame hex> <decoded frame, or ERROR and why it wasn't decoded>
+// the hex is the frame as received so a log can be decoded again by newer tools
+
+use std::fmt;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use avionics_sw_hapsis::FlightEvent;
+use avionics_sw_hapsis::antenna::Antenna;
+use avionics_sw_hapsis::packet::{EventPacket, HealthPacket, Packet};
+use chrono::DateTime;
+
+/// marks a line whose frame wasn't decoded
+pub const ERROR: &str = "error:";
+
+pub fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+pub fn unhex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len()).step_by(2).map(|at| u8::from_str_radix(text.get(at..at + 2)?, 16).ok()).collect()
+}
+
+/// receive time for the log, to line frames up with the utc times in the flight log
+pub fn unix_ms() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_millis() as u64)
+}
+
+/// receive time and bytes of every frame that was decoded, in the order received
+/// decode them again in order, positions are deltas against earlier frames
+pub fn frames(log: &str) -> impl Iterator<Item = (u64, Vec<u8>)> + '_ {
+    log.lines().filter(|line| !line.contains(ERROR)).filter_map(|line| {
+        let mut fields = line.split_whitespace();
+        let time = fields.next()?.parse().ok()?;
+        let bytes = unhex(fields.next()?)?;
+        Some((time, bytes))
+    })
+}
+
+/// How well the downlink got through, from a telemetry log, times are ms since the unix epoch
+#[derive(Copy, Clone, Default)]
+pub struct LinkStats {
+    pub frames: u32,
+    /// frames the receiver passed on that didn't decode
+    pub errors: u32,
+    pub first: Option<u64>,
+    pub last: Option<u64>,
+    /// longest time between decoded frames, ms, and when it began
+    pub longest_gap: Option<(u64, u64)>,
+    /// antenna the payload last said it selected, from health reports and antenna switch events
+    pub antenna: Option<Antenna>,
+    /// decoded frames that came through each antenna, in Antenna::ALL order, counted once the selection is known
+    pub by_antenna: [u32; Antenna::ALL.len()],
+}
+
+impl LinkStats {
+    pub fn from_log(log: &str) -> Self {
+        let mut stats = LinkStats::default();
+        for line in log.lines() {
+            let Some(time) = line.split_whitespace().next().and_then(|time| time.parse::<u64>().ok()) else {
+                continue;
+            };
+            if line.contains(ERROR) {
+                stats.errors += 1;
+                continue;
+            }
+
+            stats.frames += 1;
+            if let Some(antenna) = line.split_whitespace().nth(1).and_then(unhex).and_then(|bytes| selected_antenna(&bytes)) {
+                stats.antenna = Some(antenna);
+            }
+            if let Some(antenna) = stats.antenna {
+                stats.by_antenna[antenna.id() as usize] += 1;
+            }
+            if let Some(last) = stats.last {
+                let gap = time.saturating_sub(last);
+                if stats.longest_gap.is_none_or(|(longest, _)| gap > longest) {
+                    stats.longest_gap = Some((gap, last));
+                }
+            }
+            stats.first.get_or_insert(time);
+            stats.last = Some(time);
+        }
+        stats
+    }
+}
+
+impl fmt::Display for LinkStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let received = self.frames + self.errors;
+        let error_rate = if received > 0 { 100.0 * self.errors as f32 / received as f32 } else { 0.0 };
+        writeln!(f, "  received: {} frames, {} bad ({error_rate:.1}%)", self.frames, self.errors)?;
+        if let (Some(first), Some(last)) = (self.first, self.last) {
+            writeln!(f, "  heard from {} to {}", utc(first), utc(last))?;
+        }
+        if let Some((gap, from)) = self.longest_gap {
+            writeln!(f, "  longest silence: {:.1} s from {}", gap as f32 / 1000.0, utc(from))?;
+        }
+        if let Some(antenna) = self.antenna {
+            let through: Vec<String> = Antenna::ALL
+                .iter()
+                .map(|antenna| format!("{} {}", antenna.name(), self.by_antenna[antenna.id() as usize]))
+                .collect();
+            writeln!(f, "  antenna: {} selected, frames through {}", antenna.name(), through.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+// the antenna a health report or antenna switch event says the payload selected
+fn selected_antenna(frame: &[u8]) -> Option<Antenna> {
+    match *frame.first()? {
+        HealthPacket::ID => Antenna::from_id(HealthPacket::decode(frame).ok()?.antenna),
+        EventPacket::ID => match FlightEvent::from_code(EventPacket::decode(frame).ok()?.event)? {
+            FlightEvent::AntennaSwitched(antenna) => Some(antenna),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn utc(time: u64) -> String {
+    DateTime::from_timestamp_millis(time as i64).map_or_else(String::new, |time| time.format("%H:%M:%S UTC").to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use avionics_sw_hapsis::compact::PositionDecoder;
+    use avionics_sw_hapsis::mission::FlightState;
+    use avionics_sw_hapsis::packet::{self, Frame};
+
+    // a key frame, a frame the receiver passed on that didn't decode, an antenna switch to the upper antenna, and a
+    // delta frame
+    const LOG: &str = "\
+1781444707000 40d27d397a30c266530b01dc position 40.423701 -86.921210 21350 m
+1781444709000 ff01 error: unknown packet id 0xff
+1781444712000 83b100000015cd5b07 event AntennaSwitched(Upper)
+1781444722000 005d00a3ff84530b01dc position 40.424699 -86.923206 21380 m
+";
+
+    #[test]
+    fn hex_round_trips() {
+        assert_eq!(hex(&[0x00, 0x5d, 0xff]), "005dff");
+        assert_eq!(unhex("005dFF"), Some(vec![0x00, 0x5d, 0xff]));
+        assert_eq!(unhex("005"), None);
+        assert_eq!(unhex("0g"), None);
+    }
+
+    #[test]
+    fn captured_frames_decode() {
+        let frames: Vec<(u64, Vec<u8>)> = frames(LOG).collect();
+        assert_eq!(frames.iter().map(|&(time, _)| time).collect::<Vec<_>>(), [1781444707000, 1781444712000, 1781444722000]);
+
+        let mut positions = PositionDecoder::new();
+        let Ok(Frame::Position(key)) = packet::decode(&frames[0].1, &mut positions) else {
+            panic!("not a position");
+        };
+        assert!((key.latitude - 40.4237).abs() < 2e-5 && (key.longitude + 86.9212).abs() < 2e-5);
+        assert_eq!((key.altitude, key.climb_rate, key.state, key.voltage), (21_350.0, 5.5, FlightState::Ascent, 11.0));
+        let Ok(Frame::EventPacket(event)) = packet::decode(&frames[1].1, &mut positions) else {
+            panic!("not an event");
+        };
+        assert_eq!(FlightEvent::from_code(event.event), Some(FlightEvent::AntennaSwitched(Antenna::Upper)));
+        assert_eq!(event.time_stamp, 123_456_789);
+        let Ok(Frame::Position(delta)) = packet::decode(&frames[2].1, &mut positions) else {
+            panic!("not a position");
+        };
+        assert!((delta.latitude - 40.4247).abs() < 2e-5 && (delta.longitude + 86.9232).abs() < 2e-5);
+        assert_eq!(delta.altitude, 21_380.0);
+    }
+
+    #[test]
+    fn link_stats_count_errors_gaps_and_antennas() {
+        let stats = LinkStats::from_log(LOG);
+        assert_eq!((stats.frames, stats.errors), (3, 1));
+        assert_eq!((stats.first, stats.last), (Some(1781444707000), Some(1781444722000)));
+        assert_eq!(stats.longest_gap, Some((10_000, 1781444712000)));
+        assert_eq!(stats.antenna, Some(Antenna::Upper));
+        // the key frame came in before the payload said which antenna
+        assert_eq!(stats.by_antenna, [0, 2]);
+        let report = stats.to_string();
+        assert!(report.contains("received: 3 frames, 1 bad (25.0%)"));
+        assert!(report.contains("longest silence: 10.0 s from 13:45:12 UTC"));
+        assert!(report.contains("antenna: upper selected, frames through lower 0, upper 2"));
+    }
+}
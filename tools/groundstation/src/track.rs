@@ -0,0 +1,208 @@
+// flight track for mapping software, from the gps records in a flight log or the positions in a telemetry log
+// kml for google earth, gpx for everything else, both end on a marker at the last position for recovery
+
+use std::io::{self, Write};
+
+use avionics_sw_hapsis::compact::PositionDecoder;
+use avionics_sw_hapsis::flightlog::{self, Record};
+use avionics_sw_hapsis::packet::{self, Frame};
+use chrono::DateTime;
+
+use crate::telemetry;
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct TrackPoint {
+    pub latitude: f64,
+    pub longitude: f64,
+    /// above sea level, m
+    pub altitude: f32,
+    /// ms since the unix epoch, None before the gps had time
+    pub utc: Option<u64>,
+}
+
+/// usable fixes from a flight log, the gps task's own grading, so lockout and junk fixes stay out of the track
+pub fn from_flight_log(log: &[u8]) -> Vec<TrackPoint> {
+    flightlog::Reader::new(log)
+        .filter_map(|record| match record {
+            Record::Gps(gps) if gps.quality.usable() => Some(TrackPoint {
+                latitude: gps.latitude,
+                longitude: gps.longitude,
+                altitude: gps.altitude,
+                utc: gps.utc,
+            }),
+            _ => None,
+        })
+        .collect()
+}
+
+/// positions and beacons from a telemetry log, timed by when they were received
+pub fn from_telemetry_log(log: &str) -> Vec<TrackPoint> {
+    let mut positions = PositionDecoder::new();
+    telemetry::frames(log)
+        .filter_map(|(time, bytes)| {
+            let (latitude, longitude, altitude) = match packet::decode(&bytes, &mut positions).ok()? {
+                Frame::Position(position) => (position.latitude, position.longitude, position.altitude),
+                Frame::BeaconPacket(beacon) => (beacon.latitude, beacon.longitude, beacon.altitude),
+                _ => return None,
+            };
+            Some(TrackPoint {
+                latitude,
+                longitude,
+                altitude,
+                utc: Some(time),
+            })
+        })
+        .collect()
+}
+
+pub fn write_kml(out: &mut impl Write, name: &str, points: &[TrackPoint]) -> io::Result<()> {
+    writeln!(out, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(out, r#"<kml xmlns="http://www.opengis.net/kml/2.2">"#)?;
+    writeln!(out, "<Document>")?;
+    writeln!(out, "<name>{}</name>", escape(name))?;
+    writeln!(out, "<Placemark>")?;
+    writeln!(out, "<name>track</name>")?;
+    // absolute so the track is drawn at the altitudes flown instead of clamped to the ground
+    writeln!(out, "<LineString>")?;
+    writeln!(out, "<altitudeMode>absolute</altitudeMode>")?;
+    writeln!(out, "<coordinates>")?;
+    for point in points {
+        writeln!(out, "{:.7},{:.7},{:.1}", point.longitude, point.latitude, point.altitude)?;
+    }
+    writeln!(out, "</coordinates>")?;
+    writeln!(out, "</LineString>")?;
+    writeln!(out, "</Placemark>")?;
+    if let Some(last) = points.last() {
+        writeln!(out, "<Placemark>")?;
+        writeln!(out, "<name>last position{}</name>", last.utc.map(|utc| format!(" {}", time(utc))).unwrap_or_default())?;
+        writeln!(out, "<Point>")?;
+        writeln!(out, "<coordinates>{:.7},{:.7},{:.1}</coordinates>", last.longitude, last.latitude, last.altitude)?;
+        writeln!(out, "</Point>")?;
+        writeln!(out, "</Placemark>")?;
+    }
+    writeln!(out, "</Document>")?;
+    writeln!(out, "</kml>")
+}
+
+pub fn write_gpx(out: &mut impl Write, name: &str, points: &[TrackPoint]) -> io::Result<()> {
+    writeln!(out, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(out, r#"<gpx version="1.1" creator="groundstation" xmlns="http://www.topografix.com/GPX/1/1">"#)?;
+    // gpx wants waypoints ahead of tracks
+    if let Some(last) = points.last() {
+        writeln!(out, r#"<wpt lat="{:.7}" lon="{:.7}">"#, last.latitude, last.longitude)?;
+        write_point_body(out, last)?;
+        writeln!(out, "<name>last position</name>")?;
+        writeln!(out, "</wpt>")?;
+    }
+    writeln!(out, "<trk>")?;
+    writeln!(out, "<name>{}</name>", escape(name))?;
+    writeln!(out, "<trkseg>")?;
+    for point in points {
+        writeln!(out, r#"<trkpt lat="{:.7}" lon="{:.7}">"#, point.latitude, point.longitude)?;
+        write_point_body(out, point)?;
+        writeln!(out, "</trkpt>")?;
+    }
+    writeln!(out, "</trkseg>")?;
+    writeln!(out, "</trk>")?;
+    writeln!(out, "</gpx>")
+}
+
+// elevation and time, in the order the schema wants them
+fn write_point_body(out: &mut impl Write, point: &TrackPoint) -> io::Result<()> {
+    writeln!(out, "<ele>{:.1}</ele>", point.altitude)?;
+    if let Some(utc) = point.utc {
+        writeln!(out, "<time>{}</time>", time(utc))?;
+    }
+    Ok(())
+}
+
+// iso 8601 utc, as both formats want it
+fn time(utc: u64) -> String {
+    DateTime::from_timestamp_millis(utc as i64).map_or_else(String::new, |time| time.format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string())
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POINTS: [TrackPoint; 2] = [
+        TrackPoint { latitude: 40.4237, longitude: -86.9212, altitude: 21_350.0, utc: None },
+        // 2026-06-14 13:45:07 utc
+        TrackPoint { latitude: 40.5, longitude: -86.5, altitude: 30_000.5, utc: Some(1_781_444_707_000) },
+    ];
+
+    fn output(write: fn(&mut Vec<u8>, &str, &[TrackPoint]) -> io::Result<()>) -> String {
+        let mut out = Vec::new();
+        write(&mut out, "flight 3 & 4", &POINTS).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn kml_is_a_line_and_the_last_position() {
+        assert_eq!(output(write_kml), r#"<?xml version="1.0" encoding="UTF-8"?>
+<kml xmlns="http://www.opengis.net/kml/2.2">
+<Document>
+<name>flight 3 &amp; 4</name>
+<Placemark>
+<name>track</name>
+<LineString>
+<altitudeMode>absolute</altitudeMode>
+<coordinates>
+-86.9212000,40.4237000,21350.0
+-86.5000000,40.5000000,30000.5
+</coordinates>
+</LineString>
+</Placemark>
+<Placemark>
+<name>last position 2026-06-14T13:45:07.000Z</name>
+<Point>
+<coordinates>-86.5000000,40.5000000,30000.5</coordinates>
+</Point>
+</Placemark>
+</Document>
+</kml>
+"#);
+    }
+
+    #[test]
+    fn gpx_has_the_last_position_ahead_of_the_track() {
+        assert_eq!(output(write_gpx), r#"<?xml version="1.0" encoding="UTF-8"?>
+<gpx version="1.1" creator="groundstation" xmlns="http://www.topografix.com/GPX/1/1">
+<wpt lat="40.5000000" lon="-86.5000000">
+<ele>30000.5</ele>
+<time>2026-06-14T13:45:07.000Z</time>
+<name>last position</name>
+</wpt>
+<trk>
+<name>flight 3 &amp; 4</name>
+<trkseg>
+<trkpt lat="40.4237000" lon="-86.9212000">
+<ele>21350.0</ele>
+</trkpt>
+<trkpt lat="40.5000000" lon="-86.5000000">
+<ele>30000.5</ele>
+<time>2026-06-14T13:45:07.000Z</time>
+</trkpt>
+</trkseg>
+</trk>
+</gpx>
+"#);
+    }
+
+    #[test]
+    fn telemetry_track_is_the_positions_at_their_receive_times() {
+        // a key frame, an antenna switch event, and a delta frame
+        let log = "\
+1781444707000 40d27d397a30c266530b01dc
+1781444712000 83b100000015cd5b07
+1781444722000 005d00a3ff84530b01dc
+";
+        let points = from_telemetry_log(log);
+        assert_eq!(points.iter().map(|point| point.utc).collect::<Vec<_>>(), [Some(1781444707000), Some(1781444722000)]);
+        assert_eq!(points[1].altitude, 21_380.0);
+    }
+}